@@ -0,0 +1,72 @@
+//! Time one simulation step across particle counts, neighbour search variants and the plain
+//! and tiled kernels.
+//!
+//! Runs on a headless device: `cargo bench --bench simulation`, optionally filtered, e.g.
+//! `cargo bench --bench simulation -- circle-tiled/100000`. The quadratic search makes the 1M cases
+//! take seconds per step on most GPUs.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nannou::rand::{rngs::StdRng, SeedableRng};
+use nannou::wgpu;
+use std::time::Duration;
+
+use particle_nannou::resources::GpuResources;
+use particle_nannou::sim_variant::{Neighborhood, SimVariant};
+use particle_nannou::simulation::Simulation;
+use particle_nannou::{headless, Particle};
+
+const PARTICLE_COUNTS: [u32; 4] = [10_000, 100_000, 500_000, 1_000_000];
+
+fn simulation_step(c: &mut Criterion) {
+    let Some((device, queue)) = headless::device() else {
+        eprintln!("No GPU adapter available, skipping simulation benchmarks");
+        return;
+    };
+
+    let mut group = c.benchmark_group("simulation_step");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+    for count in PARTICLE_COUNTS {
+        let mut rng = StdRng::seed_from_u64(0);
+        let particles = (0..count)
+            .map(|_| Particle::random(&mut rng))
+            .collect::<Vec<_>>();
+        let mut resources = GpuResources::new(&device, &particles);
+        group.throughput(Throughput::Elements(count as u64));
+
+        let kernels = [Neighborhood::Circle, Neighborhood::Square]
+            .into_iter()
+            .flat_map(|neighborhood| [(neighborhood, false), (neighborhood, true)]);
+        for (neighborhood, tiled) in kernels {
+            let variant = SimVariant {
+                neighborhood,
+                tiled,
+                ..SimVariant::default()
+            };
+            let mut simulation = Simulation::new(&device, &mut resources, variant);
+            simulation.write_params(&queue, &resources, Some(count), 1, 0.0);
+
+            let name = format!(
+                "{}{}",
+                format!("{:?}", neighborhood).to_lowercase(),
+                if tiled { "-tiled" } else { "" }
+            );
+            group.bench_function(BenchmarkId::new(name, count), |b| {
+                b.iter(|| {
+                    let mut encoder =
+                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Bench Encoder"),
+                        });
+                    simulation.encode(&device, &mut encoder, &mut resources, variant, count, 1);
+                    queue.submit(Some(encoder.finish()));
+                    // Wait for the GPU so the time covers the dispatch, not just encoding
+                    device.poll(wgpu::Maintain::Wait);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, simulation_step);
+criterion_main!(benches);
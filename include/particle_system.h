@@ -0,0 +1,77 @@
+/*
+ * C interface to the particle simulation, see src/ffi.rs. Build the shared library with
+ * `cargo build --release --lib`, which leaves libparticle_nannou.so, .dylib or
+ * particle_nannou.dll in target/release.
+ *
+ *     ParticleSystem *system = particle_system_create(10000, 42);
+ *     if (!system) {
+ *         // No GPU adapter
+ *     }
+ *     ParticleSystemParams params;
+ *     particle_system_get_params(system, &params);
+ *     params.cohesion = 2.0f;
+ *     particle_system_set_params(system, &params);
+ *
+ *     particle_system_step(system, 60);
+ *     uint32_t count = particle_system_count(system);
+ *     ParticleSystemParticle *particles = malloc(count * sizeof *particles);
+ *     size_t read = particle_system_read(system, particles, count);
+ *     free(particles);
+ *     particle_system_destroy(system);
+ *
+ * Positions are in the simulation domain, -1 to 1 on both axes, and velocities in domain
+ * units per frame. A system is used from one thread at a time.
+ */
+
+#ifndef PARTICLE_SYSTEM_H
+#define PARTICLE_SYSTEM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ParticleSystem ParticleSystem;
+
+/* `Particle` on the Rust side */
+typedef struct {
+    float position[2];
+    float velocity[2];
+} ParticleSystemParticle;
+
+/* Rule weights, multiples of each boids rule's usual strength, and soft speed limits */
+typedef struct {
+    float alignment;
+    float cohesion;
+    float separation;
+    float max_speed;
+    float min_speed;
+    /* Fraction of the speed outside the limits lost each frame, 1 for hard limits */
+    float speed_decay;
+} ParticleSystemParams;
+
+/* NULL when there is no GPU adapter */
+ParticleSystem *particle_system_create(uint32_t particle_count, uint64_t seed);
+void particle_system_destroy(ParticleSystem *system);
+
+/* Queues the frames on the GPU without waiting for them */
+void particle_system_step(ParticleSystem *system, uint32_t frames);
+uint32_t particle_system_count(const ParticleSystem *system);
+/* Waits for the steps so far, returns how many particles were copied */
+size_t particle_system_read(const ParticleSystem *system, ParticleSystemParticle *out, size_t capacity);
+
+void particle_system_get_params(const ParticleSystem *system, ParticleSystemParams *params);
+/* Takes effect from the next step */
+void particle_system_set_params(ParticleSystem *system, const ParticleSystemParams *params);
+
+/* Kicks the particles within radius of (x, y) outwards once at the start of the next step,
+ * by up to strength in domain units per frame at the centre, inwards when negative */
+void particle_system_impulse(ParticleSystem *system, float x, float y, float strength, float radius);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
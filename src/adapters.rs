@@ -0,0 +1,97 @@
+use nannou::prelude::*;
+use nannou::wgpu;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// The adapters nannou can pick from on `backends`, in the order `--adapter` indices refer
+/// to.
+fn available(backends: wgpu::Backends) -> Vec<wgpu::AdapterInfo> {
+    let instance = wgpu::Instance::new(wgpu_upstream::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    instance
+        .enumerate_adapters(backends)
+        .map(|adapter| adapter.get_info())
+        .collect()
+}
+
+fn describe(info: &wgpu::AdapterInfo) -> String {
+    format!("{} ({:?}, {:?})", info.name, info.device_type, info.backend)
+}
+
+/// Print the adapters wgpu reports, so `--adapter` indices can be looked up.
+pub fn print_adapters(backends: wgpu::Backends) {
+    for (index, info) in available(backends).iter().enumerate() {
+        println!("{}: {}", index, describe(info));
+    }
+}
+
+/// Find the adapter `selector` names, either by its index in `--list-adapters` or by part
+/// of its name, ignoring case.
+pub fn select(selector: &str, backends: wgpu::Backends) -> Result<wgpu::AdapterInfo, String> {
+    let adapters = available(backends);
+    let found = match selector.parse::<usize>() {
+        Ok(index) => adapters.get(index),
+        Err(_) => {
+            let selector = selector.to_lowercase();
+            adapters
+                .iter()
+                .find(|info| info.name.to_lowercase().contains(&selector))
+        }
+    };
+    found.cloned().ok_or_else(|| {
+        let list = adapters
+            .iter()
+            .enumerate()
+            .map(|(index, info)| format!("\n  {}: {}", index, describe(info)))
+            .collect::<String>();
+        format!("No adapter matches `{selector}`, available adapters:{list}")
+    })
+}
+
+/// nannou requests adapters by power preference rather than by name, so the chosen one is
+/// reached by only offering its backend and asking for its kind of GPU.
+pub fn backends(info: &wgpu::AdapterInfo) -> wgpu::Backends {
+    info.backend.into()
+}
+
+pub fn power_preference(info: &wgpu::AdapterInfo) -> wgpu::PowerPreference {
+    match info.device_type {
+        wgpu::DeviceType::DiscreteGpu => wgpu::PowerPreference::HighPerformance,
+        _ => wgpu::PowerPreference::LowPower,
+    }
+}
+
+pub fn force_fallback(info: &wgpu::AdapterInfo) -> bool {
+    info.device_type == wgpu::DeviceType::Cpu
+}
+
+/// The adapter nannou puts the windows on when asked for `wanted`, the default if `None`.
+pub fn request(app: &App, wanted: Option<&wgpu::AdapterInfo>) -> Option<Arc<wgpu::ActiveAdapter>> {
+    let power_preference = wanted.map_or(wgpu::DEFAULT_POWER_PREFERENCE, power_preference);
+    // Windows requested with the same preference share this adapter
+    let options = wgpu::RequestAdapterOptions {
+        power_preference,
+        compatible_surface: None,
+        force_fallback_adapter: wanted.is_some_and(force_fallback),
+    };
+    app.wgpu_adapters().get_or_request(options, app.instance())
+}
+
+/// Print the adapter the windows ended up on, warning if it isn't the one asked for, e.g.
+/// with two GPUs of the same kind on one backend.
+pub fn report(app: &App, wanted: Option<&wgpu::AdapterInfo>) {
+    let Some(adapter) = request(app, wanted) else {
+        return;
+    };
+    let info = adapter.get_info();
+    info!("Using adapter {}", info.name);
+    info!("Using the {:?} backend", info.backend);
+    if let Some(wanted) = wanted.filter(|wanted| wanted.name != info.name) {
+        warn!(
+            "Couldn't get nannou to pick {}, see --list-adapters",
+            describe(wanted)
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use nannou::color::Srgb;
+use nannou::wgpu::{self, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::error;
+
+use crate::bindings::Bindings;
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::settings::hex_color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackgroundKind {
+    Solid,
+    Gradient,
+    Image,
+    Noise,
+}
+
+impl BackgroundKind {
+    fn mode(self) -> u32 {
+        match self {
+            BackgroundKind::Solid => 0,
+            BackgroundKind::Gradient => 1,
+            BackgroundKind::Image => 2,
+            BackgroundKind::Noise => 3,
+        }
+    }
+}
+
+// Must match `BackgroundUniforms` in background_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniforms {
+    color_top: [f32; 4],
+    color_bottom: [f32; 4],
+    mode: u32,
+    time: f32,
+    aspect: f32,
+    period: f32,
+    // Offset and scale of the visible part in uv, less than the whole for tiled rendering
+    view: [f32; 4],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundConfig {
+    pub kind: BackgroundKind,
+    // sRGB colours, top is also used for solid backgrounds
+    #[serde(with = "hex_color")]
+    pub color_top: [u8; 3],
+    #[serde(with = "hex_color")]
+    pub color_bottom: [u8; 3],
+    pub image: Option<PathBuf>,
+}
+
+pub struct Background {
+    pub kind: BackgroundKind,
+    color_top: [f32; 4],
+    color_bottom: [f32; 4],
+    // Width / height of the loaded image, `None` if no image is available
+    image_aspect: Option<f32>,
+    // Seconds after which animated backgrounds repeat exactly, for perfect loops
+    pub period: Option<f32>,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Background {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &mut GpuResources,
+        config: &BackgroundConfig,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "background_shader",
+            include_str!("./shaders/background_shader.wgsl"),
+        );
+
+        let texture_usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        let image_texture = config.image.as_ref().and_then(|path| {
+            match wgpu::Texture::load_from_path(device, queue, texture_usage, path) {
+                Ok(texture) => {
+                    resources.track("Background Image", texture.size_bytes() as u64);
+                    Some(texture)
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to load background image {}: {}",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        });
+        let image_aspect = image_texture.as_ref().map(|texture| {
+            let [width, height] = texture.size();
+            width as f32 / height as f32
+        });
+        // The bind group always needs a texture, so fall back to a 1x1 placeholder
+        let texture = image_texture.unwrap_or_else(|| {
+            resources.texture(
+                device,
+                "Background Placeholder",
+                [1, 1],
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                texture_usage,
+            )
+        });
+        let texture_view = texture.view().build();
+        let sampler = wgpu::SamplerBuilder::new()
+            .address_mode(wgpu::AddressMode::ClampToEdge)
+            .label(Some("Background Sampler"))
+            .build(device);
+
+        let uniform_buffer =
+            resources.uniform::<BackgroundUniforms>(device, "Background Uniform Buffer");
+
+        let bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .uniform(0)
+            .texture(1)
+            .sampler(2)
+            .build(device, "Background");
+        let bind_group = bindings.bind_group(
+            device,
+            &[
+                uniform_buffer.as_entire_binding(),
+                wgpu::BindingResource::TextureView(&texture_view),
+                wgpu::BindingResource::Sampler(&sampler),
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Background Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = diagnostics::checked(device, "Background Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Background Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        Background {
+            kind: config.kind,
+            color_top: to_linear(config.color_top),
+            color_bottom: to_linear(config.color_bottom),
+            image_aspect,
+            period: None,
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Whether an image was loaded for the `Image` kind.
+    pub fn has_image(&self) -> bool {
+        self.image_aspect.is_some()
+    }
+
+    /// Switch to the next background kind, skipping `Image` if no image was loaded.
+    pub fn cycle(&mut self) {
+        self.kind = match self.kind {
+            BackgroundKind::Solid => BackgroundKind::Gradient,
+            BackgroundKind::Gradient if self.image_aspect.is_some() => BackgroundKind::Image,
+            BackgroundKind::Gradient | BackgroundKind::Image => BackgroundKind::Noise,
+            BackgroundKind::Noise => BackgroundKind::Solid,
+        };
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, time: f32, screen_size: [u32; 2]) {
+        self.update_view(queue, time, screen_size, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    /// Like `update`, but only drawing the `[x, y, width, height]` part of the background in
+    /// uv space, for rendering an image of `screen_size` in tiles.
+    pub fn update_view(
+        &self,
+        queue: &wgpu::Queue,
+        time: f32,
+        screen_size: [u32; 2],
+        view: [f32; 4],
+    ) {
+        let screen_aspect = screen_size[0] as f32 / screen_size[1].max(1) as f32;
+        let uniforms = BackgroundUniforms {
+            color_top: self.color_top,
+            color_bottom: self.color_bottom,
+            mode: self.kind.mode(),
+            time,
+            aspect: screen_aspect / self.image_aspect.unwrap_or(screen_aspect),
+            period: self.period.unwrap_or(0.0),
+            view,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Colour to clear the frame with. Solid backgrounds are drawn entirely by the clear.
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = match self.kind {
+            BackgroundKind::Solid => self.color_top,
+            _ => [0.0, 0.0, 0.0, 1.0],
+        };
+        wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: a as f64,
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.kind == BackgroundKind::Solid {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// The frame texture is linear, so colours given in sRGB need converting first
+fn to_linear([r, g, b]: [u8; 3]) -> [f32; 4] {
+    let linear = Srgb::new(r, g, b).into_format::<f32>().into_linear();
+    [linear.red, linear.green, linear.blue, 1.0]
+}
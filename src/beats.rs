@@ -0,0 +1,150 @@
+use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::{error, info};
+
+use crate::impulses::{Impulse, ImpulseConfig};
+
+// Samples in each block whose energy is compared with the ones before
+const BLOCK: usize = 1024;
+// Blocks the average runs over, about a second at 44.1 kHz
+const HISTORY: usize = 43;
+// Blocks after a beat before the next can be heard, so one hit isn't heard twice
+const REFRACTORY: u32 = 8;
+// Mean square below which a block is silence, however quiet the blocks before
+const SILENCE: f32 = 1e-5;
+
+/// Impulses set off on the beats of live audio, like the one I sets off. The audio is raw
+/// 16-bit signed little-endian mono samples from a file or pipe, e.g. from
+/// `arecord -f S16_LE -c 1 -r 44100 -t raw`, read directly rather than through an audio
+/// library.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BeatConfig {
+    // Where the samples come from, `-` for standard input
+    pub source: PathBuf,
+    // How many times louder than the last second a block has to be to be a beat, lower hears
+    // more of them
+    pub sensitivity: f32,
+}
+
+impl Default for BeatConfig {
+    fn default() -> Self {
+        BeatConfig {
+            source: PathBuf::from("-"),
+            sensitivity: 1.5,
+        }
+    }
+}
+
+/// Energy onsets: blocks much louder than the average of the ones just before.
+struct Onsets {
+    sensitivity: f32,
+    energies: VecDeque<f32>,
+    // Blocks until the next beat can be heard
+    cooldown: u32,
+}
+
+impl Onsets {
+    fn new(sensitivity: f32) -> Self {
+        Onsets {
+            sensitivity,
+            energies: VecDeque::with_capacity(HISTORY),
+            cooldown: 0,
+        }
+    }
+
+    /// Whether the block with this mean square is a beat.
+    fn push(&mut self, energy: f32) -> bool {
+        let average = self.energies.iter().sum::<f32>() / self.energies.len().max(1) as f32;
+        let beat = self.energies.len() == HISTORY
+            && self.cooldown == 0
+            && energy > SILENCE
+            && energy > average * self.sensitivity;
+        if self.energies.len() == HISTORY {
+            self.energies.pop_front();
+        }
+        self.energies.push_back(energy);
+        self.cooldown = if beat {
+            REFRACTORY
+        } else {
+            self.cooldown.saturating_sub(1)
+        };
+        beat
+    }
+}
+
+/// Listens for beats on a thread of its own, handing them over once a frame.
+pub struct BeatDetector {
+    // Set off somewhere in the middle of the domain on each beat
+    impulse: ImpulseConfig,
+    // Heard so far by the listening thread
+    heard: Arc<AtomicU32>,
+    // Handed over so far, and seeds where the next goes off
+    fired: u32,
+}
+
+impl BeatDetector {
+    pub fn open(config: BeatConfig, impulse: ImpulseConfig) -> io::Result<Self> {
+        let mut source = open_source(&config.source)?;
+        info!("Listening for beats on {}", config.source.display());
+        let heard = Arc::new(AtomicU32::new(0));
+        let counter = heard.clone();
+        let mut onsets = Onsets::new(config.sensitivity);
+        let path = config.source.clone();
+        thread::spawn(move || {
+            let mut block = [0u8; BLOCK * 2];
+            loop {
+                if let Err(err) = source.read_exact(&mut block) {
+                    error!("Stopped hearing audio from {}: {}", path.display(), err);
+                    return;
+                }
+                let energy = block
+                    .chunks_exact(2)
+                    .map(|sample| {
+                        let sample = i16::from_le_bytes([sample[0], sample[1]]) as f32;
+                        (sample / i16::MAX as f32).powi(2)
+                    })
+                    .sum::<f32>()
+                    / BLOCK as f32;
+                if onsets.push(energy) {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        Ok(BeatDetector {
+            impulse,
+            heard,
+            fired: 0,
+        })
+    }
+
+    /// An impulse for each beat heard since the last call.
+    pub fn update(&mut self) -> Vec<Impulse> {
+        let heard = self.heard.load(Ordering::Relaxed);
+        let impulses = (self.fired..heard)
+            .map(|beat| {
+                // Somewhere in the middle, the same for the same beat every run
+                let mut rng = StdRng::seed_from_u64(beat as u64);
+                let position = [rng.gen_range(-0.6..0.6), rng.gen_range(-0.6..0.6)];
+                self.impulse.at(position)
+            })
+            .collect();
+        self.fired = heard;
+        impulses
+    }
+}
+
+fn open_source(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    if path == Path::new("-") {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+use crate::cli::BenchArgs;
+use crate::particle_system::ParticleSystem;
+use crate::sim_variant::{Neighborhood, SimVariant};
+
+/// Time the plain boids step headlessly for each particle count and neighbour search, with
+/// the plain and tiled kernels, and print a table of milliseconds per step. A quick check
+/// of a machine without the benches' dev-dependencies, see benches/simulation.rs.
+pub fn run(args: &BenchArgs) -> Result<(), String> {
+    let first = args.particles.first().copied().unwrap_or(1);
+    let mut system = ParticleSystem::new(first, 0).ok_or("no GPU adapter available")?;
+    println!(
+        "Timing {} steps after {} to warm up",
+        args.frames, args.warmup
+    );
+    println!(
+        "{:>10}  {:<14}{:>10}{:>16}",
+        "particles", "kernel", "ms/step", "particles/s"
+    );
+    for &count in &args.particles {
+        system.reset(count, 0);
+        let kernels = [Neighborhood::Circle, Neighborhood::Square]
+            .into_iter()
+            .flat_map(|neighborhood| [(neighborhood, false), (neighborhood, true)]);
+        for (neighborhood, tiled) in kernels {
+            let mut params = system.params();
+            params.variant = SimVariant {
+                neighborhood,
+                tiled,
+                ..SimVariant::default()
+            };
+            system.set_params(params);
+            system.step(args.warmup);
+            system.wait();
+
+            let start = Instant::now();
+            // One submission a step, as the app makes them, waiting so the time covers the
+            // dispatches and not just encoding
+            for _ in 0..args.frames {
+                system.step(1);
+            }
+            system.wait();
+            let seconds = start.elapsed().as_secs_f64() / args.frames as f64;
+
+            let name = format!(
+                "{}{}",
+                format!("{:?}", neighborhood).to_lowercase(),
+                if tiled { "-tiled" } else { "" }
+            );
+            println!(
+                "{:>10}  {:<14}{:>10.3}{:>16.0}",
+                count,
+                name,
+                seconds * 1000.0,
+                count as f64 / seconds
+            );
+        }
+    }
+    Ok(())
+}
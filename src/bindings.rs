@@ -0,0 +1,152 @@
+use nannou::wgpu::{self, ShaderStages};
+
+use crate::diagnostics;
+
+/// Describes a bind group layout one binding at a time.
+///
+/// ```ignore
+/// let layout = Bindings::new(ShaderStages::COMPUTE)
+///     .storage_rw(0)
+///     .uniform(1)
+///     .build(device, "Simulate");
+/// let bind_group = layout.bind_group(device, &[particles.as_entire_binding(), ...]);
+/// ```
+pub struct Bindings {
+    visibility: ShaderStages,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+/// A built bind group layout that makes bind groups matching its bindings.
+pub struct BindingLayout {
+    label: String,
+    layout: wgpu::BindGroupLayout,
+    bindings: Vec<u32>,
+}
+
+impl Bindings {
+    /// Bindings visible to the given shader stages.
+    pub fn new(visibility: ShaderStages) -> Self {
+        Bindings {
+            visibility,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn storage_ro(self, binding: u32) -> Self {
+        self.buffer(
+            binding,
+            wgpu::BufferBindingType::Storage { read_only: true },
+        )
+    }
+
+    pub fn storage_rw(self, binding: u32) -> Self {
+        self.buffer(
+            binding,
+            wgpu::BufferBindingType::Storage { read_only: false },
+        )
+    }
+
+    pub fn uniform(self, binding: u32) -> Self {
+        self.buffer(binding, wgpu::BufferBindingType::Uniform)
+    }
+
+    /// A filterable 2D float texture.
+    pub fn texture(self, binding: u32) -> Self {
+        self.entry(
+            binding,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+        )
+    }
+
+    /// A filtering sampler.
+    pub fn sampler(self, binding: u32) -> Self {
+        self.entry(
+            binding,
+            wgpu::BindingType::Sampler(wgpu_upstream::SamplerBindingType::Filtering),
+        )
+    }
+
+    fn buffer(self, binding: u32, ty: wgpu::BufferBindingType) -> Self {
+        self.entry(
+            binding,
+            wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        )
+    }
+
+    fn entry(mut self, binding: u32, ty: wgpu::BindingType) -> Self {
+        assert!(
+            self.entries.iter().all(|entry| entry.binding != binding),
+            "binding {binding} declared twice"
+        );
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: self.visibility,
+            ty,
+            count: None,
+        });
+        self
+    }
+
+    /// Create the layout. `label` names both the layout and bind groups made from it.
+    pub fn build(self, device: &wgpu::Device, label: &str) -> BindingLayout {
+        let layout_label = format!("{label} Bind Group Layout");
+        let layout = diagnostics::checked(device, &layout_label, || {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(&layout_label),
+                entries: &self.entries,
+            })
+        });
+        BindingLayout {
+            label: label.to_owned(),
+            layout,
+            bindings: self.entries.iter().map(|entry| entry.binding).collect(),
+        }
+    }
+}
+
+impl BindingLayout {
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Create a bind group, with resources given in the order the bindings were declared.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        resources: &[wgpu::BindingResource],
+    ) -> wgpu::BindGroup {
+        assert_eq!(
+            resources.len(),
+            self.bindings.len(),
+            "{} bind group expects {} resources",
+            self.label,
+            self.bindings.len()
+        );
+        let entries: Vec<_> = self
+            .bindings
+            .iter()
+            .zip(resources)
+            .map(|(&binding, resource)| wgpu::BindGroupEntry {
+                binding,
+                resource: resource.clone(),
+            })
+            .collect();
+        let label = format!("{} Bind Group", self.label);
+        let what = format!("{} (bindings {:?})", label, self.bindings);
+        diagnostics::checked(device, &what, || {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&label),
+                layout: &self.layout,
+                entries: &entries,
+            })
+        })
+    }
+}
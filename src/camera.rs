@@ -0,0 +1,367 @@
+use clap::ValueEnum;
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::f32::consts::FRAC_PI_4;
+
+const MIN_ZOOM: f32 = 0.25;
+// Distance of the perspective camera from the plane, in domain units at zoom 1
+const EYE_DISTANCE: f32 = 2.0;
+// Tilts from straight down the perspective camera can take, in radians
+const MIN_TILT: f32 = 0.0;
+const MAX_TILT: f32 = 1.3;
+const MAX_ZOOM: f32 = 256.0;
+// Below this zoom boids are only a pixel or two across, so the density field reads better
+const LOD_ZOOM: f32 = 0.5;
+
+/// How particles get drawn at the current zoom level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    /// Every particle as a boid sprite
+    Sprites,
+    /// Only the on-screen particles as sprites, see `Culler`
+    Culled,
+    /// A splatted density field, see `DensitySplat`
+    Density,
+}
+
+/// What the colour of each particle shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorMode {
+    /// Heading and speed
+    Velocity,
+    /// Neighbours counted in the last boids step, from dark when alone to bright when
+    /// crowded, to show density waves
+    Crowding,
+    /// The colour of the emitter that dyed it, or the mix of those around it, see `Emitters`,
+    /// whether it's caught the `Contagion`, or its home base, see `Territory`
+    Emitter,
+}
+
+impl ColorMode {
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Velocity => ColorMode::Crowding,
+            ColorMode::Crowding => ColorMode::Emitter,
+            ColorMode::Emitter => ColorMode::Velocity,
+        }
+    }
+
+    // Must match `particle_color` in vertex_shader.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            ColorMode::Velocity => 0,
+            ColorMode::Crowding => 1,
+            ColorMode::Emitter => 2,
+        }
+    }
+}
+
+/// How the domain's plane is put on screen, after panning and zooming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Projection {
+    /// Straight down, orthographic
+    #[default]
+    TopDown,
+    /// Turned 45° and squashed to half its height, like an isometric game
+    Isometric,
+    /// Seen through a perspective camera tilted back from straight down, by PageUp and
+    /// PageDown
+    Perspective,
+}
+
+/// 2D pan/zoom over the simulation domain, which spans -1..1 on both axes, seen through a
+/// `Projection`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub center: Vec2,
+    pub zoom: f32,
+    pub projection: Projection,
+    // Radians back from straight down, for `Projection::Perspective`
+    pub tilt: f32,
+    // Part of the picture shown, as its centre in clip space and how much it's enlarged, for
+    // rendering it in tiles
+    pub crop: (Vec2, f32),
+}
+
+/// What of a `Camera` is saved, with presets and in recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+// Recordings from before projections have only the centre and zoom
+#[serde(default)]
+pub struct CameraState {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    pub projection: Projection,
+    pub tilt: f32,
+}
+
+impl Default for CameraState {
+    fn default() -> Self {
+        Camera::default().state()
+    }
+}
+
+impl From<CameraState> for Camera {
+    fn from(state: CameraState) -> Self {
+        Camera {
+            center: Vec2::from(state.center),
+            zoom: state.zoom,
+            projection: state.projection,
+            tilt: state.tilt,
+            ..Default::default()
+        }
+    }
+}
+
+// Must match `Camera` in vertex_shader.wgsl, cull_shader.wgsl, splat_shader.wgsl,
+// reaction_view_shader.wgsl and trail_view_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniforms {
+    center: [f32; 2],
+    zoom: f32,
+    _pad: f32,
+    // Columns of `Camera::view` and its inverse, padded to vec4s like WGSL's mat3x3
+    view: [[f32; 4]; 3],
+    inverse: [[f32; 4]; 3],
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            center: Vec2::ZERO,
+            zoom: 1.0,
+            projection: Projection::TopDown,
+            tilt: FRAC_PI_4,
+            crop: (Vec2::ZERO, 1.0),
+        }
+    }
+}
+
+impl Camera {
+    /// The default view through `projection`, for switching presets, zoomed in to show
+    /// `1 / world_size` of the domain across.
+    pub fn preset(projection: Projection, world_size: f32) -> Self {
+        Camera {
+            projection,
+            zoom: world_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            center: self.center.to_array(),
+            zoom: self.zoom,
+            projection: self.projection,
+            tilt: self.tilt,
+        }
+    }
+
+    /// Points on the domain's plane, as (x, y, 1), to clip space with the perspective divide
+    /// in the last component.
+    pub fn view(&self) -> Mat3 {
+        let projection = match self.projection {
+            Projection::TopDown => Mat3::IDENTITY,
+            Projection::Isometric => {
+                Mat3::from_cols(vec3(0.5, 0.25, 0.0), vec3(-0.5, 0.25, 0.0), Vec3::Z)
+            }
+            // The plane turned back about the x axis, seen from `EYE_DISTANCE` above its
+            // centre, so the centre stays put
+            Projection::Perspective => Mat3::from_cols(
+                vec3(EYE_DISTANCE, 0.0, 0.0),
+                vec3(0.0, EYE_DISTANCE * self.tilt.cos(), self.tilt.sin()),
+                vec3(0.0, 0.0, EYE_DISTANCE),
+            ),
+        };
+        let (crop_center, crop_scale) = self.crop;
+        Mat3::from_scale(Vec2::splat(crop_scale))
+            * Mat3::from_translation(-crop_center)
+            * projection
+            * Mat3::from_scale(Vec2::splat(self.zoom))
+            * Mat3::from_translation(-self.center)
+    }
+
+    pub fn uniforms(&self) -> CameraUniforms {
+        let view = self.view();
+        let columns = |matrix: Mat3| {
+            [matrix.x_axis, matrix.y_axis, matrix.z_axis].map(|column| column.extend(0.0).into())
+        };
+        CameraUniforms {
+            center: self.center.to_array(),
+            zoom: self.zoom,
+            _pad: 0.0,
+            view: columns(view),
+            inverse: columns(view.inverse()),
+        }
+    }
+
+    /// Whether the domain is seen straight down without cropping, as culling and the density
+    /// field assume.
+    pub fn is_top_down(&self) -> bool {
+        self.projection == Projection::TopDown && self.crop == (Vec2::ZERO, 1.0)
+    }
+
+    /// Convert a point in world space to clip space.
+    pub fn world_to_clip(&self, point: Vec2) -> Vec2 {
+        let clip = self.view() * point.extend(1.0);
+        clip.truncate() / clip.z
+    }
+
+    /// Convert a point in window coordinates (nannou's centred, y-up points) to world space.
+    /// Points above a perspective camera's horizon come out behind it.
+    pub fn window_to_world(&self, point: Vec2, window: Rect) -> Vec2 {
+        let clip = point / (window.wh() * 0.5);
+        let world = self.view().inverse() * clip.extend(1.0);
+        world.truncate() / world.z
+    }
+
+    /// Convert a point in world space to window coordinates, the inverse of `window_to_world`.
+    pub fn world_to_window(&self, point: Vec2, window: Rect) -> Vec2 {
+        self.world_to_clip(point) * window.wh() * 0.5
+    }
+
+    /// World units a logical point in the window covers at its centre, along whichever axis
+    /// it covers more of, for sizing things in points whatever the display's scale factor.
+    pub fn world_per_point(&self, window: Rect) -> f32 {
+        let origin = self.window_to_world(Vec2::ZERO, window);
+        [Vec2::X, Vec2::Y]
+            .map(|axis| self.window_to_world(axis, window).distance(origin))
+            .into_iter()
+            .fold(0.0, f32::max)
+    }
+
+    /// Tilt a perspective camera back from straight down by `angle` radians.
+    pub fn tilt_by(&mut self, angle: f32) {
+        self.tilt = (self.tilt + angle).clamp(MIN_TILT, MAX_TILT);
+    }
+
+    /// Zoom by `factor`, keeping the world point under `cursor` fixed on screen.
+    pub fn zoom_at(&mut self, cursor: Vec2, window: Rect, factor: f32) {
+        let before = self.window_to_world(cursor, window);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let after = self.window_to_world(cursor, window);
+        self.center += before - after;
+    }
+
+    /// Pan by a mouse movement given in window coordinates, measured at the window's centre.
+    pub fn pan(&mut self, delta: Vec2, window: Rect) {
+        let moved = self.window_to_world(delta, window) - self.window_to_world(Vec2::ZERO, window);
+        self.center -= moved;
+    }
+
+    pub fn render_path(&self) -> RenderPath {
+        // Culling and the density field only work looking straight down
+        if !self.is_top_down() {
+            RenderPath::Sprites
+        } else if self.zoom < LOD_ZOOM {
+            RenderPath::Density
+        } else if self.zoom > 1.0 {
+            // Some of the domain is off-screen, so culling is worth doing
+            RenderPath::Culled
+        } else {
+            RenderPath::Sprites
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window() -> Rect {
+        Rect::from_w_h(1280.0, 720.0)
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{a} isn't {b}");
+    }
+
+    #[test]
+    fn camera_uniforms_are_laid_out_like_wgsl() {
+        // vec2, f32, f32 then two mat3x3s of three vec4 columns each
+        assert_eq!(std::mem::size_of::<CameraUniforms>(), 16 + 2 * 48);
+        assert_eq!(std::mem::offset_of!(CameraUniforms, view), 16);
+        assert_eq!(std::mem::offset_of!(CameraUniforms, inverse), 64);
+    }
+
+    #[test]
+    fn window_and_world_are_inverses() {
+        for projection in [
+            Projection::TopDown,
+            Projection::Isometric,
+            Projection::Perspective,
+        ] {
+            let camera = Camera {
+                center: vec2(0.3, -0.2),
+                zoom: 2.5,
+                projection,
+                ..Default::default()
+            };
+            for point in [vec2(0.0, 0.0), vec2(-400.0, 100.0), vec2(600.0, -300.0)] {
+                let world = camera.window_to_world(point, window());
+                assert_near(camera.world_to_window(world, window()), point);
+            }
+        }
+    }
+
+    #[test]
+    fn default_camera_fits_the_domain_to_the_window_height() {
+        let camera = Camera::default();
+        assert_near(camera.world_to_window(Vec2::ZERO, window()), Vec2::ZERO);
+        assert_near(
+            camera.world_to_window(vec2(1.0, 1.0), window()),
+            vec2(640.0, 360.0),
+        );
+    }
+
+    #[test]
+    fn points_cover_more_of_the_world_along_the_shorter_side() {
+        let mut camera = Camera::default();
+        assert!((camera.world_per_point(window()) - 2.0 / 720.0).abs() < 1e-6);
+        camera.zoom = 2.0;
+        assert!((camera.world_per_point(window()) - 1.0 / 720.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut camera = Camera::default();
+        let cursor = vec2(200.0, -150.0);
+        let before = camera.window_to_world(cursor, window());
+        camera.zoom_at(cursor, window(), 3.0);
+        assert_eq!(camera.zoom, 3.0);
+        assert_near(camera.window_to_world(cursor, window()), before);
+    }
+
+    #[test]
+    fn zoom_and_tilt_are_clamped() {
+        let mut camera = Camera::default();
+        camera.zoom_at(Vec2::ZERO, window(), 1e6);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        camera.zoom_at(Vec2::ZERO, window(), 1e-9);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        camera.tilt_by(10.0);
+        assert_eq!(camera.tilt, MAX_TILT);
+        camera.tilt_by(-10.0);
+        assert_eq!(camera.tilt, MIN_TILT);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let camera = Camera {
+            center: vec2(0.5, 0.25),
+            zoom: 4.0,
+            projection: Projection::Isometric,
+            tilt: 0.5,
+            crop: (Vec2::ZERO, 1.0),
+        };
+        let json = serde_json::to_string(&camera.state()).unwrap();
+        let state: CameraState = serde_json::from_str(&json).unwrap();
+        assert_eq!(Camera::from(state), camera);
+        // Recordings from before projections have only the centre and zoom
+        let state: CameraState = serde_json::from_str(r#"{"center":[0.5,0.25],"zoom":4}"#).unwrap();
+        assert_eq!(state.projection, Projection::TopDown);
+    }
+}
@@ -0,0 +1,332 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+// Red, green, blue and count sums per cell, see `deposits` in canvas_shader.wgsl
+const DEPOSIT_SIZE: wgpu::BufferAddress = 16;
+// Colour and coverage per cell, see `paint` in canvas_shader.wgsl
+const PAINT_SIZE: wgpu::BufferAddress = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CanvasConfig {
+    // Fraction of a cell each particle passing through it covers with its colour each frame
+    pub opacity: f32,
+    // Cells along each side of the canvas, which covers the domain
+    pub size: u32,
+}
+
+impl Default for CanvasConfig {
+    fn default() -> Self {
+        CanvasConfig {
+            opacity: 0.02,
+            size: 1024,
+        }
+    }
+}
+
+/// Parse `opacity[,size]`, e.g. `0.02,1024`.
+pub fn parse_canvas(s: &str) -> Result<CanvasConfig, String> {
+    let (opacity, size) = match s.split_once(',') {
+        Some((opacity, size)) => (opacity, Some(size)),
+        None => (s, None),
+    };
+    let opacity = opacity
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid canvas opacity `{opacity}`"))?;
+    let size = match size {
+        Some(size) => size
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid canvas size `{size}`"))?,
+        None => CanvasConfig::default().size,
+    };
+    if !(0.0..=1.0).contains(&opacity) || !(16..=4096).contains(&size) {
+        return Err(format!(
+            "expected an opacity of 0 to 1 and a size of 16 to 4096 cells, got `{s}`"
+        ));
+    }
+    Ok(CanvasConfig { opacity, size })
+}
+
+wgsl_struct! {
+    // Must match `CanvasParams` in canvas_shader.wgsl
+    struct CanvasParams {
+        particle_count: u32,
+        opacity: f32,
+        size: u32,
+    }
+}
+
+/// A canvas over the domain that the particles paint as they go, each in the colour its
+/// velocity gives it, and that never fades, so their paths build up into a painting. Unlike
+/// the trail it's only ever drawn, never felt by the particles.
+///
+/// Particles' colours are summed per cell with atomics in fixed point, then laid over what's
+/// already there, covering it a little more with each particle. Kept through resizing.
+pub struct Canvas {
+    pub config: CanvasConfig,
+    deposit: wgpu::ComputePipeline,
+    blend: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<CanvasParams>,
+    deposits: wgpu::Buffer,
+    paint: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the paint has been wiped
+    needs_clear: bool,
+}
+
+impl Canvas {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: CanvasConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "canvas_shader",
+            include_str!("./shaders/canvas_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .build(device, "Canvas");
+        let params_buffer = UniformBuffer::new(device, resources, "Canvas Params Buffer");
+        let cells = cell_count(config.size);
+        let deposits = resources.buffer(
+            device,
+            "Canvas Deposits Buffer",
+            cells * DEPOSIT_SIZE,
+            BufferUsages::STORAGE,
+        );
+        let paint = resources.buffer(
+            device,
+            "Canvas Paint Buffer",
+            cells * PAINT_SIZE,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        );
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &deposits,
+            &paint,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Canvas Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Canvas Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Canvas Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+
+        Canvas {
+            config,
+            deposit: pipeline("deposit"),
+            blend: pipeline("blend"),
+            bindings,
+            params_buffer,
+            deposits,
+            paint,
+            bind_groups,
+            needs_clear: false,
+        }
+    }
+
+    /// Each cell's colour and how much of it is covered, linear RGBA row by row from the
+    /// bottom of the domain, for drawing and saving it.
+    pub fn paint_buffer(&self) -> &wgpu::Buffer {
+        &self.paint
+    }
+
+    /// Cells along each side, as the buffers were made with.
+    pub fn size(&self) -> u32 {
+        self.config.size.clamp(16, 4096)
+    }
+
+    /// Wipe the canvas on the next frame.
+    pub fn clear(&mut self) {
+        self.needs_clear = true;
+    }
+}
+
+impl Stage for Canvas {
+    fn kind(&self) -> StageKind {
+        StageKind::Canvas
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if std::mem::take(&mut self.needs_clear) {
+            encoder.clear_buffer(&self.paint, 0, None);
+        }
+        let params = CanvasParams {
+            particle_count: frame.particle_count,
+            opacity: self.config.opacity.clamp(0.0, 1.0),
+            size: self.size(),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Canvas Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.set_pipeline(&self.deposit);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        compute_pass.set_pipeline(&self.blend);
+        let cells = params.size * params.size;
+        compute_pass.dispatch_workgroups(cells.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.deposits,
+            &self.paint,
+        );
+    }
+}
+
+fn cell_count(size: u32) -> wgpu::BufferAddress {
+    let size = size.clamp(16, 4096) as wgpu::BufferAddress;
+    size * size
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<CanvasParams>,
+    deposits: &wgpu::Buffer,
+    paint: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                deposits.as_entire_binding(),
+                paint.as_entire_binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_variant::SimVariant;
+    use crate::{gpu, headless, Particle, SimParams};
+
+    #[test]
+    fn paints_the_cells_particles_pass_through() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        let particle = |position: [f32; 2], velocity: [f32; 2]| Particle { position, velocity };
+        let particles = [
+            // Two in the bottom left cell, heading right at half the speed that's full blue
+            particle([-0.99, -0.99], [0.005, 0.0]),
+            particle([-0.95, -0.95], [0.005, 0.0]),
+            // One in the top right cell, heading up
+            particle([0.99, 0.99], [0.0, 0.005]),
+            // Outside the canvas, on its far edges and beyond, so painting nothing
+            particle([1.0, 0.0], [0.005, 0.0]),
+            particle([0.0, -1.5], [0.005, 0.0]),
+        ];
+        let mut resources = GpuResources::new(&device, &particles);
+        let config = CanvasConfig {
+            opacity: 0.5,
+            size: 16,
+        };
+        let mut canvas = Canvas::new(&device, &mut resources, config);
+        let params = SimParams {
+            particle_count: 5,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        queue.write_buffer(&resources.params, 0, bytemuck::bytes_of(&params));
+        let frame = FrameContext {
+            device: &device,
+            queue: &queue,
+            variant: SimVariant::default(),
+            particle_count: 5,
+            substeps: 1,
+            time: 0.0,
+            frame: 0,
+        };
+        let mut paint = |canvas: &mut Canvas| {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Canvas Test Encoder"),
+            });
+            canvas.encode(&frame, &mut encoder, &mut resources);
+            queue.submit(Some(encoder.finish()));
+            gpu::read_buffer::<[f32; 4]>(&device, &queue, canvas.paint_buffer(), 16 * 16)
+        };
+        let close = |a: [f32; 4], b: [f32; 4]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3);
+
+        let cells = paint(&mut canvas);
+        let [bottom_left, top_right] = [0, 16 * 16 - 1];
+        assert!(
+            close(cells[bottom_left], [1.0, 0.0, 0.5, 0.75]),
+            "{:?}",
+            cells[0]
+        );
+        assert!(
+            close(cells[top_right], [0.0, 1.0, 0.5, 0.5]),
+            "{:?}",
+            cells[255]
+        );
+        let painted = cells.iter().filter(|cell| cell[3] > 0.0).count();
+        assert_eq!(painted, 2);
+
+        // Never fading, only covered more
+        let cells = paint(&mut canvas);
+        assert!(
+            close(cells[bottom_left], [1.0, 0.0, 0.5, 0.9375]),
+            "{:?}",
+            cells[0]
+        );
+        assert!(
+            close(cells[top_right], [0.0, 1.0, 0.5, 0.75]),
+            "{:?}",
+            cells[255]
+        );
+
+        // Wiped, then painted again from bare
+        canvas.clear();
+        let cells = paint(&mut canvas);
+        assert!(
+            close(cells[bottom_left], [1.0, 0.0, 0.5, 0.75]),
+            "{:?}",
+            cells[0]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use nannou::image::RgbaImage;
+use nannou::wgpu::{self, ShaderStages};
+use std::path::Path;
+
+use crate::bindings::Bindings;
+use crate::canvas::Canvas;
+use crate::diagnostics;
+use crate::resources::GpuResources;
+
+/// Draws a `Canvas` over the background through the camera, the paint as opaque as it's
+/// been laid on thick.
+pub struct CanvasView {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CanvasView {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &GpuResources,
+        canvas: &Canvas,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "canvas_view_shader",
+            include_str!("./shaders/canvas_view_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .storage_ro(0)
+            .uniform(1)
+            .build(device, "Canvas View");
+        let bind_group = bindings.bind_group(
+            device,
+            &[
+                canvas.paint_buffer().as_entire_binding(),
+                resources.camera.as_entire_binding(),
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Canvas View Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Canvas View Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Canvas View Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        CanvasView {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    /// Shade the paint over the whole frame.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Save a canvas's paint, read back as `size` rows of `size` cells, laid over the solid
+/// sRGB `background` as a PNG, top row first.
+pub fn export(
+    path: &Path,
+    paint: &[[f32; 4]],
+    size: u32,
+    background: [u8; 3],
+) -> nannou::image::ImageResult<()> {
+    image(paint, size, background).save(path)
+}
+
+fn image(paint: &[[f32; 4]], size: u32, background: [u8; 3]) -> RgbaImage {
+    let background = background.map(|c| to_linear(c as f32 / 255.0));
+    RgbaImage::from_fn(size, size, |x, y| {
+        // Cells run from the bottom of the domain, images from the top
+        let [r, g, b, a] = paint[((size - 1 - y) * size + x) as usize];
+        let a = a.clamp(0.0, 1.0);
+        let mut pixel = [255; 4];
+        for (channel, (paint, background)) in [r, g, b].into_iter().zip(background).enumerate() {
+            let linear = paint.clamp(0.0, 1.0) * a + background * (1.0 - a);
+            pixel[channel] = (to_srgb(linear) * 255.0).round() as u8;
+        }
+        pixel.into()
+    })
+}
+
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_paint_over_the_background_top_row_first() {
+        // The bottom row painted red, the top left untouched
+        let paint = [
+            [1.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.5],
+        ];
+        let image = image(&paint, 2, [0xf4, 0xef, 0xe4]);
+        assert_eq!(image.get_pixel(0, 1).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 0).0, [0xf4, 0xef, 0xe4, 255]);
+        let [r, _, b, _] = image.get_pixel(1, 0).0;
+        assert!(r < 0xf4 && b > r);
+    }
+}
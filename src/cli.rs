@@ -0,0 +1,1595 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use nannou::wgpu;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::background::BackgroundKind;
+use crate::beats::BeatConfig;
+use crate::camera::{CameraState, ColorMode, Projection};
+use crate::canvas::{self, CanvasConfig};
+use crate::compaction::CompactionConfig;
+use crate::constraints::{NetworkConfig, NetworkShape};
+use crate::contagion::{self, ContagionConfig};
+use crate::drag::{self, DragConfig};
+use crate::emitters::EmittersConfig;
+use crate::forces::{self, Attractor, NoiseConfig, WanderConfig};
+use crate::freeze::{self, FreezeConfig, Region};
+use crate::gamepad::GamepadConfig;
+use crate::goal::{self, GoalConfig};
+use crate::impulses::{self, ImpulseConfig};
+use crate::inflow::{self, InflowConfig};
+use crate::installation::{self, InstallationConfig};
+use crate::lennard_jones::LennardJonesConfig;
+use crate::lifetime::{Easing, LifetimeConfig};
+use crate::mass::{self, MassConfig, MassDistribution};
+use crate::modulation::{self, Modulator};
+use crate::orientation::OrientationConfig;
+use crate::parameters::{self, Parameter};
+use crate::particle_sort::{SortConfig, SortKey};
+use crate::pbd::PbdConfig;
+use crate::pen::PenPressure;
+use crate::post_fx::{self, Effect};
+use crate::settings::Settings;
+use crate::sim_variant::{self, Neighborhood};
+use crate::simulation::{self, LeaderConfig, RegionOfInterest};
+use crate::sleep::{self, SleepConfig};
+use crate::species::{self, SpeciesConfig};
+use crate::stamina::{self, StaminaConfig};
+use crate::stir::{self, StirConfig};
+use crate::svg_export::SvgStyle;
+use crate::sweep::{self, SweepAxis};
+use crate::territory::TerritoryConfig;
+use crate::text_targets::TextConfig;
+use crate::theme::{Cue, Hues, Theme};
+use crate::thermostat::ThermostatConfig;
+use crate::trail::TrailConfig;
+use crate::vector_field::{ImageFollow, VectorFieldConfig};
+use crate::vorticity::{self, VorticityConfig};
+
+/// Options left unset fall back to the values saved from the last run, then to the defaults.
+#[derive(Debug, Parser)]
+#[command(about = "GPU boids particle simulation")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Settings file to restore from and save to on exit
+    #[arg(long)]
+    pub settings: Option<PathBuf>,
+
+    /// Ignore the saved settings and start from the defaults
+    #[arg(long)]
+    pub reset_settings: bool,
+
+    /// Scene file to run in place of the saved settings: a complete setup, modes, walls and
+    /// all, to share. Other options still override it, and nothing is saved on exit
+    #[arg(long, conflicts_with = "reset_settings")]
+    pub scene: Option<PathBuf>,
+
+    /// Folder of scene files to list with F8 and switch between, saving the current setup
+    /// there as a new one. A scene starts over with the same options, except the recording,
+    /// playback, the stats and logs written out and sync, which end with the old scene.
+    /// Telemetry and shared frames carry on
+    #[arg(long)]
+    pub scenes: Option<PathBuf>,
+
+    /// Seconds switching scenes takes, crossfading from the old one to the new one with both
+    /// running, 0 for a hard cut
+    #[arg(long, default_value_t = 1.0)]
+    pub scene_fade: f32,
+
+    /// Colours to draw in: bright particles on black, or inked ones on white or off-white for
+    /// figures, with overlays to match. Sets the background colours, which the flags below
+    /// override [default: dark]
+    #[arg(long, value_enum)]
+    pub theme: Option<Theme>,
+
+    /// Colours picked for what the particles' colours show, safe ones or greys so they stay
+    /// readable with colour blindness [default: wheel]
+    #[arg(long, value_enum)]
+    pub hues: Option<Hues>,
+
+    /// Show speed and species by brightness or shape as well as colour, for audiences who
+    /// can't tell the hues apart [default: none]
+    #[arg(long, value_enum)]
+    pub cue: Option<Cue>,
+
+    /// Language of the overlays and messages, for classrooms and museums: en, es, or the path
+    /// of a JSON locale file translating any of them [default: en]
+    #[arg(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
+    /// Background drawn behind the particles [default: solid]
+    #[arg(long, value_enum)]
+    pub background: Option<BackgroundKind>,
+
+    /// Primary background colour (solid colour, top of gradient/noise), as #rrggbb
+    #[arg(long, value_parser = parse_hex_color)]
+    pub background_color: Option<[u8; 3]>,
+
+    /// Secondary background colour (bottom of gradient/noise), as #rrggbb
+    #[arg(long, value_parser = parse_hex_color)]
+    pub background_color2: Option<[u8; 3]>,
+
+    /// Image file used by the `image` background
+    #[arg(long)]
+    pub background_image: Option<PathBuf>,
+
+    /// How particles are drawn in SVG snapshots, saved with S [default: strokes]
+    #[arg(long, value_enum)]
+    pub svg_style: Option<SvgStyle>,
+
+    /// Line width in pixels for SVG snapshots [default: 1.5]
+    #[arg(long)]
+    pub svg_stroke_width: Option<f32>,
+
+    /// Render a single image of this size, e.g. 4096x4096, independent of the window size.
+    /// Simulates for --render-frames first, then saves to --render-output and quits
+    #[arg(long, value_parser = crate::offline::parse_size)]
+    pub render: Option<[u32; 2]>,
+
+    /// Frames to simulate before rendering with --render
+    #[arg(long, default_value_t = 600, requires = "render")]
+    pub render_frames: u64,
+
+    /// Image file saved by --render, the format follows the extension
+    #[arg(long, default_value = "render.png", requires = "render")]
+    pub render_output: PathBuf,
+
+    /// Frames in a row saved by --render, numbered before the extension when more than one,
+    /// for a sequence to bring into a video editor
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), requires = "render")]
+    pub render_count: u64,
+
+    /// Render only the particles, on a transparent background instead of the --background,
+    /// and save them with their alpha for compositing. Needs an --render-output format with
+    /// alpha, such as PNG
+    #[arg(long, requires = "render")]
+    pub render_transparent: bool,
+
+    /// Capture --gif-frames frames after --gif-warmup and save them as an animated GIF, then quit
+    #[arg(long)]
+    pub gif: Option<PathBuf>,
+
+    /// Frames in the GIF, at 60fps
+    #[arg(long, default_value_t = 180, value_parser = clap::value_parser!(u64).range(1..), requires = "gif")]
+    pub gif_frames: u64,
+
+    /// Frames to simulate before capturing the GIF
+    #[arg(long, default_value_t = 300, requires = "gif")]
+    pub gif_warmup: u64,
+
+    /// Width of the GIF in pixels, the height follows the window
+    #[arg(long, default_value_t = 480, value_parser = clap::value_parser!(u32).range(1..), requires = "gif")]
+    pub gif_width: u32,
+
+    /// Crossfade this many frames at the end into the start so the GIF loops smoothly.
+    /// They're taken out of --gif-frames
+    #[arg(long, default_value_t = 0, requires = "gif")]
+    pub gif_blend: u64,
+
+    /// Make seamlessly looping output with this period in frames: the noise background repeats
+    /// exactly, and --gif captures two periods and crossfades the second into the first
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["gif_frames", "gif_blend"])]
+    pub loop_frames: Option<u64>,
+
+    /// Log the mean speed, polarization (how aligned the headings are, 0 to 1), mean nearest
+    /// neighbour distance and how many separate clusters the particles form to this CSV file as
+    /// the run goes
+    #[arg(long)]
+    pub stats: Option<PathBuf>,
+
+    /// Frames between rows of --stats. Each row reads the particles back, which stalls the
+    /// GPU for a moment
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), requires = "stats")]
+    pub stats_interval: u64,
+
+    /// Serve telemetry and remote control over WebSocket on this address, e.g. 0.0.0.0:9001.
+    /// Clients get the frame rate, particle count, enabled stages and flock statistics as
+    /// JSON, and can send actions as JSON, e.g. "toggle-alignment" or {"set-particles": 20000}
+    #[arg(long)]
+    pub telemetry: Option<String>,
+
+    /// Frames between --telemetry messages. Each reads the particles back
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..), requires = "telemetry")]
+    pub telemetry_interval: u64,
+
+    /// Lead a synced simulation on this address, e.g. 0.0.0.0:9100, for walls of screens driven
+    /// by several computers: instances started with --sync-follow reproduce it frame for frame.
+    /// It's only identical on the same GPU and driver, and files the settings name, like the
+    /// level, have to be at the same paths on every machine
+    #[arg(long, conflicts_with_all = ["play", "sync_follow"])]
+    pub sync_serve: Option<String>,
+
+    /// Followers to wait for before the first frame. Later ones are turned away
+    #[arg(long, default_value_t = 1, requires = "sync_serve")]
+    pub sync_followers: usize,
+
+    /// Follow the --sync-serve authority on this address, e.g. 192.168.1.10:9100, waiting for
+    /// it to come up. Its starting state and actions replace this instance's, the camera and
+    /// window stay local
+    #[arg(long, conflicts_with = "play")]
+    pub sync_follow: Option<String>,
+
+    /// Open a second, overlay-free output window sharing the simulation (e.g. for a projector).
+    /// Presentation mode then applies to the output window.
+    #[arg(long)]
+    pub output_window: bool,
+
+    /// Stream raw RGBA8 frames of the output to this file or named pipe, so tools like ffmpeg
+    /// can forward them to a virtual camera or a recording
+    #[arg(long)]
+    pub share_pipe: Option<PathBuf>,
+
+    /// Share the output as an NDI source of this name on the network, for OBS, Resolume and
+    /// the like. Needs a build with the ndi feature and the NDI runtime installed
+    #[arg(long, conflicts_with = "share_pipe")]
+    pub share_ndi: Option<String>,
+
+    /// Number of particles, also changed at runtime with [ and ] [default: 50000]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=crate::MAX_PARTICLES as i64))]
+    pub particles: Option<u32>,
+
+    /// Replay a session saved with the record command, reproducing it frame for frame. A
+    /// .replay file brings the settings it was recorded with too, the options given override
+    /// them
+    #[arg(long)]
+    pub play: Option<PathBuf>,
+
+    /// Random seed for placing the particles, random if not given
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Seconds of particle history kept for rewinding by holding Backspace, 0 to disable.
+    /// Unavailable while recording or playing back [default: 10]
+    #[arg(long)]
+    pub rewind_seconds: Option<f32>,
+
+    /// Simulation substeps per frame, more is smoother but costs more [default: 1]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub substeps: Option<u32>,
+
+    /// Compute shader workgroup size: 32, 64, 128 or 256. The fastest depends on the GPU
+    /// [default: 256]
+    #[arg(long, value_parser = sim_variant::parse_workgroup_size)]
+    pub workgroup_size: Option<u32>,
+
+    /// Load neighbours into workgroup shared memory a tile at a time, which is usually much
+    /// faster for large counts. Off runs the plain loop, for comparison. Toggled with T
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub tiled: Option<bool>,
+
+    /// Store particles as half floats, halving the memory traffic for multi-million particle
+    /// runs at the cost of precision. Works on any GPU, since the shaders pack and unpack the
+    /// halves themselves. Only for the plain boids step, ignored with a warning alongside any
+    /// other stage, e.g. --attractor, --noise or --lifetime, and always draws every particle
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub half_precision: Option<bool>,
+
+    /// Store all the positions, then all the velocities, rather than interleaving them, so
+    /// the neighbour loop reads only what it needs. Only for the plain boids step, ignored with
+    /// a warning alongside any other stage, e.g. --attractor, --noise or --lifetime, and always
+    /// draws every particle
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub separate_arrays: Option<bool>,
+
+    /// Shape of the area boids look for neighbours in, toggled with N [default: circle]
+    #[arg(long, value_enum)]
+    pub neighborhood: Option<Neighborhood>,
+
+    /// Top boid speed in domain units per frame, a soft limit, see --speed-decay
+    /// [default: 0.005]
+    #[arg(long)]
+    pub max_speed: Option<f32>,
+
+    /// Cruising speed slower boids speed back up to [default: 0.001]
+    #[arg(long)]
+    pub min_speed: Option<f32>,
+
+    /// Fraction of a boid's speed over --max-speed or under --min-speed lost each frame, 1
+    /// for hard limits [default: 0.5]
+    #[arg(long)]
+    pub speed_decay: Option<f32>,
+
+    /// How strongly alignment steers, as a multiple of its usual strength [default: 1]
+    #[arg(long)]
+    pub alignment_weight: Option<f32>,
+
+    /// How strongly cohesion steers, as a multiple of its usual strength [default: 1]
+    #[arg(long)]
+    pub cohesion_weight: Option<f32>,
+
+    /// How strongly separation steers, as a multiple of its usual strength [default: 1]
+    #[arg(long)]
+    pub separation_weight: Option<f32>,
+
+    /// How near neighbours have to be for alignment to count them, in domain units
+    /// [default: 0.09]
+    #[arg(long, value_parser = parse_radius)]
+    pub alignment_radius: Option<f32>,
+
+    /// How near neighbours have to be for cohesion to count them, in domain units
+    /// [default: 0.09]
+    #[arg(long, value_parser = parse_radius)]
+    pub cohesion_radius: Option<f32>,
+
+    /// How near neighbours have to be for separation to count them, in domain units. Classic
+    /// boids keeps this shorter than the others [default: 0.09]
+    #[arg(long, value_parser = parse_radius)]
+    pub separation_radius: Option<f32>,
+
+    /// Let boids see neighbours only up to this many degrees either side of their heading,
+    /// not behind them. 180 or more sees all the way round [default: 180]
+    #[arg(long, value_parser = parse_view_angle)]
+    pub view_angle: Option<f32>,
+
+    /// Set a boids parameter by name, as NAME=VALUE, e.g. cohesion=0.5, after the flags
+    /// above. Repeat for more. The names are the ones --param sweeps and remote clients send
+    /// set-parameter with
+    #[arg(long = "set", value_parser = parameters::parse_assignment)]
+    pub set: Vec<(&'static Parameter, f32)>,
+
+    /// Pressure demo: close the box, move its right wall with the arrow keys and plot
+    /// pressure against volume. Pairs well with --thermostat
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub piston: Option<bool>,
+
+    /// Hold the particles' RMS speed (their temperature) at this value by rescaling
+    /// velocities each frame, e.g. 0.003. 0 turns a saved thermostat off
+    #[arg(long)]
+    pub thermostat: Option<f32>,
+
+    /// Fraction of the difference from the target speed the thermostat corrects each frame,
+    /// 0 to 1 [default: 0.1]
+    #[arg(long)]
+    pub thermostat_rate: Option<f32>,
+
+    /// Replace the boids with Lennard-Jones molecular dynamics in a periodic box, at this
+    /// reduced density (particles per diameter squared), e.g. 0.8. Cool with the down arrow
+    /// to crystallize, heat with up to melt. 0 turns it off
+    #[arg(long)]
+    pub lennard_jones: Option<f32>,
+
+    /// Replace the boids with physarum slime mould: agents that follow and lay down a trail,
+    /// drawn under them, growing into networks of veins. The trail is set up by the settings
+    /// file's [trail] table when it has one
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub physarum: Option<bool>,
+
+    /// Replace the boids with a fireworks display: sparks that burst out, fall, fade and
+    /// leave trails, in colours cycling round a palette. Click to set one off
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub fireworks: Option<bool>,
+
+    /// Simulated seconds between --fireworks going off on their own, 0 for only clicks
+    /// [default: 0.8]
+    #[arg(long)]
+    pub burst_interval: Option<f32>,
+
+    /// Starting Lennard-Jones temperature in reduced units [default: 0.5]
+    #[arg(long)]
+    pub temperature: Option<f32>,
+
+    /// Replace the particles with a hanging cloth this many particles across and down, held
+    /// together by springs, e.g. 64x48. Try it with --substeps 4
+    #[arg(long, value_parser = crate::offline::parse_size, conflicts_with = "rope")]
+    pub cloth: Option<[u32; 2]>,
+
+    /// Replace the particles with a rope this many particles long. 0 turns a saved cloth or
+    /// rope off
+    #[arg(long)]
+    pub rope: Option<u32>,
+
+    /// Spring force per unit stretch for --cloth and --rope [default: 0.3]
+    #[arg(long)]
+    pub stiffness: Option<f32>,
+
+    /// Correct positions after each step with this many position-based dynamics iterations,
+    /// so particles collide instead of overlapping and cloth and rope don't stretch. 0 turns
+    /// it off
+    #[arg(long)]
+    pub pbd: Option<u32>,
+
+    /// Collision radius for --pbd in domain units [default: 0.005]
+    #[arg(long)]
+    pub particle_radius: Option<f32>,
+
+    /// Push the particles around with a drifting curl noise field of this strength, e.g.
+    /// 0.0001, toggled with 4. 0 turns it off
+    #[arg(long)]
+    pub noise: Option<f32>,
+
+    /// Kick every particle in a random direction each frame, the standard deviation of the
+    /// change in velocity, e.g. 0.0002. Goes with --noise, without one adds a field of
+    /// strength 0. Drawn on the GPU, so the same each run
+    #[arg(long)]
+    pub jitter: Option<f32>,
+
+    /// Let each particle's heading wander this many radians a frame at most, drifting at
+    /// `frequency` swings a second [default: 1], as strength[,frequency], e.g. 0.05,1. Lively
+    /// for sparse flocks. 0 turns it off
+    #[arg(long, value_parser = forces::parse_wander)]
+    pub wander: Option<WanderConfig>,
+
+    /// Add a point attractor as x,y,strength, e.g. 0,0,0.00005, negative repels. Repeat for
+    /// more, up to 16. Replaces the saved attractors, toggled with 5
+    #[arg(long, value_parser = forces::parse_attractor, allow_hyphen_values = true)]
+    pub attractor: Vec<Attractor>,
+
+    /// Slow every particle with linear and quadratic drag, as fractions of the velocity lost
+    /// each frame, the second per unit of speed, e.g. 0.01,1, so steady forces give a
+    /// terminal velocity. Toggled with 9, scaled with - and =. 0,0 turns it off
+    #[arg(long, value_parser = drag::parse_drag)]
+    pub drag: Option<DragConfig>,
+
+    /// Spin up the swirls in the flow with vorticity confinement, binning the velocities
+    /// into a grid and pushing each particle round the eddy it's in, as strength[,grid],
+    /// e.g. 0.2,32 [default grid: 32 cells a side]. 0 turns it off
+    #[arg(long, value_parser = vorticity::parse_vorticity)]
+    pub vorticity: Option<VorticityConfig>,
+
+    /// Give each particle a mass between min and max, e.g. 0.5,2, dividing the steering,
+    /// noise, attractor and Lennard-Jones forces on it so heavy particles lag and light ones
+    /// dart. 1,1 turns saved masses off
+    #[arg(long, value_parser = mass::parse_range)]
+    pub mass: Option<[f32; 2]>,
+
+    /// How --mass spreads the masses between min and max [default: uniform]
+    #[arg(long, value_enum)]
+    pub mass_distribution: Option<MassDistribution>,
+
+    /// Simulate the boids in this rectangle, x0,y0,x1,y1, in finer substeps than the rest of
+    /// the domain, e.g. around an attractor, so the rest can run coarsely
+    #[arg(long, value_parser = simulation::parse_rect, allow_hyphen_values = true)]
+    pub roi: Option<[[f32; 2]; 2]>,
+
+    /// Substeps inside --roi for each one outside, 1 turns a saved region off [default: 4]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub roi_substeps: Option<u32>,
+
+    /// Carry the particles along a velocity field, from a .npy array shaped (rows, columns,
+    /// 2) or a .csv with a row of u,v pairs per line, bottom row first, or from an image's
+    /// brightness, see --field-follow. Stretched over the whole domain, toggled with 6. A .npy
+    /// shaped (frames, rows, columns, 2), or a directory of files taken in name order, plays
+    /// as a sequence, see --field-seconds
+    #[arg(long)]
+    pub vector_field: Option<PathBuf>,
+
+    /// Which way a --vector-field image carries the particles: along its edges, tracing the
+    /// shapes in it, or up or down its brightness, gathering them in the light or dark parts
+    /// [default: contours]
+    #[arg(long, value_enum)]
+    pub field_follow: Option<ImageFollow>,
+
+    /// Domain units per frame for each unit of --vector-field's vectors [default: 0.001]
+    #[arg(long)]
+    pub field_scale: Option<f32>,
+
+    /// Fraction of the difference from --vector-field's velocity closed each frame, 1 to
+    /// move exactly with it [default: 0.1]
+    #[arg(long)]
+    pub field_coupling: Option<f32>,
+
+    /// Simulated seconds between frames of a --vector-field sequence, blended between and
+    /// looped [default: 1]
+    #[arg(long)]
+    pub field_seconds: Option<f32>,
+
+    /// Have the particles leave a spreading, fading trail and steer up its slope with this
+    /// strength, e.g. 0.000001, for ant-like trail following. Negative avoids the trail, 0
+    /// turns it off. Toggled with 7
+    #[arg(long, allow_hyphen_values = true)]
+    pub trail: Option<f32>,
+
+    /// Grow Gray-Scott reaction-diffusion patterns from where the particles pass, drawn as
+    /// the background. Toggled with 8
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub reaction_diffusion: Option<bool>,
+
+    /// Steer the particles up the slope of the reaction-diffusion patterns with this
+    /// strength, e.g. 0.00005, negative to avoid them
+    #[arg(long, allow_hyphen_values = true)]
+    pub reaction_follow: Option<f32>,
+
+    /// Remove particles that get further than this from the centre, or blow up, after each
+    /// frame, keeping the rest packed at the front of the buffer, e.g. 2. 0 turns it off.
+    /// Ignored for --cloth and --rope, whose springs refer to particles by index
+    #[arg(long)]
+    pub compact: Option<f32>,
+
+    /// Give each particle an angle of its own, turned towards its velocity by a torque of
+    /// this strength, so triangles and meshes can spin, e.g. 0.05. 0 turns it off. Drawn
+    /// without culling, and turns off --compact, which would reorder the particles
+    #[arg(long)]
+    pub orient: Option<f32>,
+
+    /// Give each particle energy it spends going fast and regains going slow, its top speed
+    /// shrinking as it tires down to `rest` of it, where a spent particle stays until it's
+    /// rested, as drain[,recovery[,rest]] per frame, e.g. 0.004,0.01,0.2 [default recovery:
+    /// 0.01, rest: 0.2]. 0 turns it off, and it turns off --compact too
+    #[arg(long, value_parser = stamina::parse_stamina)]
+    pub stamina: Option<StaminaConfig>,
+
+    /// Stream particles through the domain like a wind tunnel, in along one edge and out at
+    /// the opposite one, where they come straight back in, turning `drive` of each one's
+    /// velocity to the stream's each frame, as edge[,speed[,drive]], e.g. left,0.004,0.02
+    /// [default speed: 0.004, drive: 0.02]. Draw obstacles for it to stream past with
+    /// --level or in a scene
+    #[arg(long, value_parser = inflow::parse_inflow)]
+    pub inflow: Option<InflowConfig>,
+
+    /// Stop particles that stay under `speed` of the top speed for `frames` frames in a row
+    /// and skip them in the boids step until something moves them, for settled granular
+    /// scenes, as speed[,frames], e.g. 0.02,60 [default frames: 60]. 0 turns it off, and it
+    /// turns off --compact too
+    #[arg(long, value_parser = sleep::parse_sleep)]
+    pub sleep: Option<SleepConfig>,
+
+    /// Have the particles paint their paths onto a canvas over the domain that never fades,
+    /// each in its velocity colour, covering `opacity` of the cell it's in each frame, as
+    /// opacity[,size], e.g. 0.02,1024 [default size: 1024 cells a side]. P saves it as a PNG
+    /// and Shift+P wipes it. 0 turns it off
+    #[arg(long, value_parser = canvas::parse_canvas)]
+    pub canvas: Option<CanvasConfig>,
+
+    /// Respawn each particle somewhere random after about this many simulated seconds, e.g.
+    /// 4, drawing it fading and shrinking as it ages. 0 turns it off. Drawn without culling,
+    /// and turns off --compact, which would reorder the particles
+    #[arg(long)]
+    pub lifetime: Option<f32>,
+
+    /// How --lifetime fades particles out over their life [default: smooth]
+    #[arg(long, value_enum)]
+    pub fade: Option<Easing>,
+
+    /// How --lifetime shrinks particles over their life [default: linear]
+    #[arg(long, value_enum)]
+    pub shrink: Option<Easing>,
+
+    /// Have the flock steer towards a goal travelling these waypoints over time, as
+    /// x,y;x,y;... in domain units, e.g. "-0.5,-0.5;0.5,-0.5;0,0.5". Loops back to the start
+    #[arg(long, value_parser = goal::parse_waypoints, allow_hyphen_values = true)]
+    pub goal: Option<Vec<[f32; 2]>>,
+
+    /// Like --goal, along the first path in this SVG file, fitted to the domain
+    #[arg(long)]
+    pub goal_path: Option<PathBuf>,
+
+    /// Simulated seconds for the goal to travel its whole route [default: 20]
+    #[arg(long)]
+    pub goal_seconds: Option<f32>,
+
+    /// Velocity change per frame towards the goal [default: 0.00005]
+    #[arg(long)]
+    pub goal_strength: Option<f32>,
+
+    /// Make this fraction of the boids leaders, following the --goal or without one a
+    /// meandering route, which the rest weigh `weight` times as much in cohesion, as
+    /// fraction[,weight], e.g. 0.05,10 [default weight: 10]. 0 turns them off
+    #[arg(long, value_parser = simulation::parse_leaders)]
+    pub leaders: Option<LeaderConfig>,
+
+    /// Have the particles assemble into this text, e.g. "HELLO". \n starts a new line. Strong
+    /// enough to beat the flocking, so turn the rules off for crisp letters
+    #[arg(long)]
+    pub text: Option<String>,
+
+    /// TrueType or OpenType font for --text [default: Noto Sans]
+    #[arg(long)]
+    pub font: Option<PathBuf>,
+
+    /// Velocity change per frame towards each particle's spot in the --text [default: 0.0002]
+    #[arg(long)]
+    pub text_strength: Option<f32>,
+
+    /// Dye the particles passing near these points, each in its own colour, to watch the
+    /// flock mix them, e.g. "-0.5,0;0.5,0". Colours the particles by emitter
+    #[arg(long, value_parser = goal::parse_waypoints, allow_hyphen_values = true)]
+    pub emitters: Option<Vec<[f32; 2]>>,
+
+    /// Fraction of each particle's dye blended with its neighbours' each frame, so the
+    /// --emitters' colours run together [default: 0]
+    #[arg(long)]
+    pub dye_mixing: Option<f32>,
+
+    /// Spread an outbreak through the flock: susceptible particles catch it from infected ones
+    /// in range by chance each frame and recover for good after a while, as
+    /// probability[,radius[,recovery[,initial]]], the chance per infected neighbour per frame,
+    /// the range in domain units, seconds until recovery and the fraction infected at the start,
+    /// e.g. 0.05,0.02,10,0.01. Colours the particles blue, red and green by state. 0 turns it
+    /// off, and it turns off --compact too
+    #[arg(long, value_parser = contagion::parse_contagion, conflicts_with_all = ["emitters", "bases"])]
+    pub contagion: Option<ContagionConfig>,
+
+    /// Log how many particles are susceptible, infected and recovered to this CSV file as the
+    /// --contagion runs
+    #[arg(long)]
+    pub contagion_log: Option<PathBuf>,
+
+    /// Divide the particles between home bases at these points, each heading back to its own
+    /// for part of every --base-period and roaming the rest, the bases taking turns, e.g.
+    /// "-0.5,0;0.5,0". Colours the particles by base
+    #[arg(long, value_parser = goal::parse_waypoints, allow_hyphen_values = true, conflicts_with = "emitters")]
+    pub bases: Option<Vec<[f32; 2]>>,
+
+    /// Simulated seconds between each of the --bases calling its particles home [default: 20]
+    #[arg(long)]
+    pub base_period: Option<f32>,
+
+    /// Log how many of each base's particles are nearer it than any other base, and how many
+    /// visitors from others are, to this CSV file as the --bases run
+    #[arg(long)]
+    pub territory_log: Option<PathBuf>,
+
+    /// Automatically drop substeps, post-fx and particles when frames go over budget, and
+    /// restore them when there is headroom again
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub adaptive_quality: Option<bool>,
+
+    /// Frame time budget for --adaptive-quality, defaults to the --fps cap or 60fps
+    #[arg(long, value_parser = parse_frame_budget)]
+    pub frame_budget_ms: Option<f32>,
+
+    /// How frames are presented: fifo is vsync, immediate runs uncapped (may tear) [default: fifo]
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+
+    /// Cap the frame rate, e.g. 30 to run installations power-efficiently
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: Option<u32>,
+
+    /// Simulate this many frames a second whatever the display runs at, catching up with
+    /// several a frame when it's slow and drawing between them when it's fast
+    #[arg(long, value_parser = parse_tick_rate)]
+    pub tick_rate: Option<f32>,
+
+    /// Run unattended, for gallery machines: stop simulating and drawing while the window is
+    /// minimised or hidden, carry on once it's back or focused, and restart from
+    /// --restart-preset once frames have stayed slower than a watchdog threshold for a while,
+    /// as ms[,seconds], e.g. 100,30 [default seconds: 30]. 0 turns it off
+    #[arg(long, value_parser = installation::parse_installation)]
+    pub installation: Option<InstallationConfig>,
+
+    /// Settings file loaded as a preset when --installation restarts, the settings launched
+    /// with when not given
+    #[arg(long)]
+    pub restart_preset: Option<PathBuf>,
+
+    /// Start in presentation mode (borderless fullscreen, toggled with F11)
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub present: Option<bool>,
+
+    /// Show a graph of recent frame times in the corner, toggled with F3
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub frame_graph: Option<bool>,
+
+    /// Time each pass of the frame on the GPU, listed under the frame graph (F3)
+    #[arg(long)]
+    pub gpu_profile: bool,
+
+    /// Write the GPU timings of the first 600 frames to this file as a Chrome trace, for
+    /// chrome://tracing or Perfetto
+    #[arg(long, requires = "gpu_profile")]
+    pub gpu_trace: Option<PathBuf>,
+
+    /// Where the domain wraps around (--lennard-jones, --physarum), draw what's near its edges
+    /// again beyond the opposite ones, so flocks crossing over stay in one piece
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub periodic_copies: Option<bool>,
+
+    /// Show the whole domain in the corner, brighter where the particles are, with the part
+    /// the window sees outlined. Toggled with M
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub minimap: Option<bool>,
+
+    /// Show how fast the particles are going as a histogram in the corner, with the speed
+    /// limit marked and the 2D Maxwell-Boltzmann curve of the same temperature over it.
+    /// Toggled with H
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub speed_histogram: Option<bool>,
+
+    /// Make the domain this many windows across, with the camera starting (and reset with
+    /// Home) zoomed in on the middle of it. Pairs with --minimap [default: 1]
+    #[arg(long, value_parser = parse_world_size)]
+    pub world_size: Option<f32>,
+
+    /// Shade the zoomed out density field as a lit liquid surface, toggled with F. Hold L and
+    /// move the mouse to move the light
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub fluid_shading: Option<bool>,
+
+    /// Expose the zoomed out density field from how the counts on screen are spread rather
+    /// than a fixed scale, easing as the flock packs together or spreads out so it stays
+    /// neither blown out nor dim. Toggled with E
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub auto_exposure: Option<bool>,
+
+    /// Reorder the particle buffer every so often, by cell to keep neighbours close in
+    /// memory or by age for particles that age. Species stay in their own ranges
+    #[arg(long, value_enum)]
+    pub sort: Option<SortKey>,
+
+    /// Frames between sorts with --sort, 0 to stop sorting [default: 60]
+    #[arg(long)]
+    pub sort_interval: Option<u32>,
+
+    /// Hold particles still in these regions from the start, as `rect:x0,y0,x1,y1` or
+    /// `circle:x,y,radius` separated by ";", to build static structures the flock moves
+    /// around. Drag with Z held to freeze more, in a rectangle or with Shift a circle, and
+    /// with X held to thaw them. "" only turns the tool on
+    #[arg(long, value_parser = parse_regions_or_none, allow_hyphen_values = true)]
+    pub freeze: Option<Vec<Region>>,
+
+    /// Draw the particles as species, each its share of them with its own shape, blend mode
+    /// and size range, as `shape[,blend[,size[,share]]];...`, e.g.
+    /// "triangle;dot,additive,0.5-2,0.25". Shapes are triangle, square and dot, blend modes
+    /// opaque, alpha and additive. "" draws every particle alike
+    #[arg(long, value_parser = parse_species_or_none)]
+    pub species: Option<Vec<SpeciesConfig>>,
+
+    /// Effects run over the rendered frame in order, separated by ";". `kaleidoscope:6,0.1`
+    /// folds it into 6 mirrored wedges about the centre for mandala-like symmetry, turning
+    /// at 0.1 radians a second, still when left out. `feedback:0.95,1.01,0.002,0.01` draws
+    /// the frame over the one before it, dimmed to 0.95, zoomed by 1.01, turned by 0.002
+    /// radians and hue-shifted by 0.01 radians each frame for music-visualizer trails,
+    /// neither zoomed, turned nor shifted when left out. `aberration:0.005` splits red and
+    /// blue apart towards the edges by 0.005 of the frame, and `vignette:0.5` darkens the
+    /// corners by half, both as strong as that when left out. "" turns them off
+    #[arg(long, value_parser = parse_effects_or_none)]
+    pub post_fx: Option<Vec<Effect>>,
+
+    /// Swing parameters around their values over time, separated by ";", each
+    /// `target:wave[,rate[,depth[,phase]]]`. Targets are alignment, cohesion, separation,
+    /// max-speed, min-speed and zoom, waves sine, triangle, noise and envelope, at `rate`
+    /// cycles per simulated second [default: 0.1] swinging by a fraction `depth` either way
+    /// [default: 0.5], `phase` cycles in [default: 0].
+    /// E.g. `cohesion:sine,0.05;zoom:noise,0.2,0.1`. Saved with presets, at most 8. "" turns
+    /// them off
+    #[arg(long, value_parser = parse_modulators_or_none)]
+    pub modulate: Option<Vec<Modulator>>,
+
+    /// What the particles' colours show: their velocity, how many neighbours the boids step
+    /// counted around them, to make crowding visible, or the emitter that dyed them. Cycled
+    /// with K [default: velocity]
+    #[arg(long, value_enum)]
+    pub color_mode: Option<ColorMode>,
+
+    /// How the domain is put on screen: straight down, isometric, or through a perspective
+    /// camera tilted back with PageUp and PageDown. Switched with F5, F6 and F7
+    #[arg(long, value_enum)]
+    pub projection: Option<Projection>,
+
+    /// Pull of each finger on a touchscreen at a firm press, harder presses pull harder where
+    /// the screen senses pressure. Negative pushes the particles away [default: 0.00005]
+    #[arg(long, allow_hyphen_values = true)]
+    pub touch_strength: Option<f32>,
+
+    /// What a graphics tablet's pen pressure scales when stirring and setting off fireworks,
+    /// a normal press leaving them as with the mouse and a full one doubling them [default:
+    /// both]
+    #[arg(long, value_enum)]
+    pub pen_pressure: Option<PenPressure>,
+
+    /// Velocity kick and radius of the impulses I and the middle mouse button set off at the
+    /// cursor, pushing the particles outwards, e.g. 0.01,0.3. Negative pulls them in
+    /// [default: 0.01,0.3]
+    #[arg(long, value_parser = impulses::parse_impulse, allow_hyphen_values = true)]
+    pub impulse: Option<ImpulseConfig>,
+
+    /// How dragging with Shift held stirs the particles, as the fraction of the cursor's
+    /// movement handed to those under it and the radius it fades out over, e.g. 0.2,0.15
+    /// [default: 0.2,0.15]. 0 stirs nothing
+    #[arg(long, value_parser = stir::parse_stir, allow_hyphen_values = true)]
+    pub stir: Option<StirConfig>,
+
+    /// Steer an attractor around with a gamepad's left stick, pulling with this strength,
+    /// e.g. 0.0001. Negative makes it a predator the flock flees, 0 turns it off. Either
+    /// trigger sets off a burst pushing the particles away
+    #[arg(long, allow_hyphen_values = true)]
+    pub gamepad: Option<f32>,
+
+    /// Linux joystick device of the gamepad [default: /dev/input/js0]
+    #[arg(long)]
+    pub gamepad_device: Option<PathBuf>,
+
+    /// Set off an impulse (see --impulse) on each beat of raw 16-bit little-endian mono audio
+    /// read from this file or pipe, `-` for standard input, e.g. piped from
+    /// `arecord -f S16_LE -c 1 -r 44100 -t raw`. Empty turns it off
+    #[arg(long)]
+    pub audio: Option<PathBuf>,
+
+    /// How many times louder than the last second a moment has to be to count as a beat,
+    /// lower hears more of them [default: 1.5]
+    #[arg(long)]
+    pub beat_sensitivity: Option<f32>,
+
+    /// Draw each particle as this OBJ mesh, facing along +x, instead of a triangle. Its
+    /// z axis points out of the screen
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+
+    /// Draw each particle this many logical points across whatever the zoom, window and
+    /// display's scale factor, so it looks the same on a hi-DPI laptop and a projector, e.g.
+    /// 6. 0 goes back to a fixed share of the domain, which grows with the window
+    #[arg(long, value_parser = parse_particle_size)]
+    pub particle_size: Option<f32>,
+
+    /// Load walls for the particles to flow around from an image, where dark pixels are
+    /// walls, or a .txt file, where `#` is. Stretched over the whole domain
+    #[arg(long)]
+    pub level: Option<PathBuf>,
+
+    /// Simulate with this copy of compute_shader.wgsl and reload it whenever it's saved, for
+    /// live coding. A copy that doesn't compile is shown in the window and the last one that
+    /// did keeps running
+    #[arg(long)]
+    pub watch_shader: Option<PathBuf>,
+
+    /// Folder of fragment shaders to draw the particles with instead of the built-in one,
+    /// cycled through with U. Each .wgsl file defines `fn shade(input: ShadeInput) ->
+    /// vec4<f32>`, see src/shaders/user_fragment.wgsl for what it's given
+    #[arg(long, default_value = "user-shaders")]
+    pub user_shaders: PathBuf,
+
+    /// Folder of compute kernels to run over the particles each frame, after the forces
+    /// unless they say otherwise. Each .wgsl file defines a `main` entry point, see
+    /// src/shaders/kernel_interface.wgsl for its bindings
+    #[arg(long, default_value = "kernels")]
+    pub kernels: PathBuf,
+
+    /// Monitor index to present on, see --list-monitors
+    #[arg(long)]
+    pub monitor: Option<usize>,
+
+    /// Stretch the presentation window across all monitors
+    #[arg(long, conflicts_with = "monitor")]
+    pub span_monitors: bool,
+
+    /// Print the available monitors and exit
+    #[arg(long)]
+    pub list_monitors: bool,
+
+    /// GPU to run on, by index from --list-adapters or part of its name, e.g. to force the
+    /// discrete GPU on a laptop
+    #[arg(long)]
+    pub adapter: Option<String>,
+
+    /// Graphics API to run on, e.g. to rule out a driver problem with the default one. Also
+    /// limits what --adapter and --list-adapters see
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Print the available GPU adapters and exit
+    #[arg(long)]
+    pub list_adapters: bool,
+
+    /// Log more: -v adds how long setup and each pipeline take, -vv every frame's phases too
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Also write the log to this file
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+}
+
+/// What to do, running the simulation in a window when not given. Options for the window go
+/// before the command, e.g. `--particles 1000 record session.json`.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the simulation in a window, the same as giving no command
+    Run,
+    /// Run the simulation in a window and record the session (starting state, key actions,
+    /// camera moves) for --play
+    Record(RecordArgs),
+    /// Time simulation steps headlessly, without a window, across particle counts and kernels
+    Bench(BenchArgs),
+    /// Run headless simulations over a grid of parameter values, without a window, and
+    /// write a table of statistics for each run
+    Sweep(SweepArgs),
+    /// Check settings, scene, recording and replay files for errors and keys the app would ignore,
+    /// without running anything
+    Validate(ValidateArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RecordArgs {
+    /// File to save the recording to, written on exit. A .replay file bundles the settings
+    /// and version with it, compressed, to share for --play on someone else's machine
+    pub output: PathBuf,
+
+    /// Also write the log next to the recording, with a .log extension in place of its own
+    #[arg(long, conflicts_with = "log_file")]
+    pub log: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BenchArgs {
+    /// Particle counts to time, comma separated
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "10000,100000",
+        value_parser = clap::value_parser!(u32).range(1..=crate::MAX_PARTICLES as i64)
+    )]
+    pub particles: Vec<u32>,
+
+    /// Steps run first for each case, untimed, so pipelines are built and caches warm
+    #[arg(long, default_value_t = 10)]
+    pub warmup: u32,
+
+    /// Steps timed for each case
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: u32,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ValidateArgs {
+    /// Files to check: settings or scenes as .toml, recordings as .json, replays as .replay
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SweepArgs {
+    /// A parameter to vary and its values, as NAME=A,B,C or NAME=START:END:STEPS, e.g.
+    /// cohesion=0:2:5. Repeat for a grid over several. Names: alignment, cohesion and
+    /// separation weights, alignment-radius, cohesion-radius, separation-radius, max-speed,
+    /// min-speed, speed-decay, view-angle
+    #[arg(long = "param", required = true, value_parser = sweep::parse_axis)]
+    pub params: Vec<SweepAxis>,
+
+    /// Particles in each run
+    #[arg(long, default_value_t = 2000, value_parser = clap::value_parser!(u32).range(2..=crate::MAX_PARTICLES as i64))]
+    pub particles: u32,
+
+    /// Frames to settle for before measuring
+    #[arg(long, default_value_t = 300)]
+    pub warmup: u64,
+
+    /// Frames measured after the warmup
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    pub frames: u64,
+
+    /// Frames between measurements, each of which reads the particles back
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub sample_interval: u64,
+
+    /// Runs at each point, seeded 0, 1, 2 and so on
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    pub seeds: u64,
+
+    /// CSV file for the results table
+    #[arg(long, default_value = "sweep.csv")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+    /// Vsync, falling back to whatever the platform supports
+    AutoVsync,
+    /// No vsync, falling back to whatever the platform supports
+    AutoNoVsync,
+}
+
+impl PresentMode {
+    pub fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    /// OpenGL, or WebGL on the web
+    Gl,
+}
+
+impl Backend {
+    pub fn to_wgpu(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Dx11 => wgpu::Backends::DX11,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+impl Args {
+    /// Where the log is written to as well as the terminal, if anywhere.
+    pub fn log_path(&self) -> Option<PathBuf> {
+        match &self.command {
+            Some(Command::Record(record)) if record.log => {
+                Some(record.output.with_extension("log"))
+            }
+            _ => self.log_file.clone(),
+        }
+    }
+
+    /// Where the session is recorded to, with the record command.
+    pub fn record_path(&self) -> Option<&PathBuf> {
+        match &self.command {
+            Some(Command::Record(record)) => Some(&record.output),
+            _ => None,
+        }
+    }
+
+    /// The backends nannou may choose from, before narrowing down to an --adapter.
+    pub fn backends(&self) -> wgpu::Backends {
+        self.backend
+            .map_or(wgpu::DEFAULT_BACKENDS, Backend::to_wgpu)
+    }
+
+    pub fn settings_path(&self) -> PathBuf {
+        self.settings.clone().unwrap_or_else(Settings::default_path)
+    }
+
+    /// Where the settings are saved on exit. Nowhere when running a --scene or playing a
+    /// .replay, as those bring someone else's settings, not worth keeping over the user's own.
+    pub fn saved_settings_path(&self) -> Option<PathBuf> {
+        let replaying = self.play.as_deref().is_some_and(crate::replay::is_replay);
+        (self.scene.is_none() && !replaying).then(|| self.settings_path())
+    }
+
+    /// Override the saved settings with any options given on the command line.
+    pub fn apply_to(&self, settings: &mut Settings) {
+        let background = &mut settings.background;
+        if let Some(theme) = self.theme {
+            settings.theme = theme;
+            (background.color_top, background.color_bottom) = theme.background();
+        }
+        if let Some(hues) = self.hues {
+            settings.hues = hues;
+        }
+        if let Some(cue) = self.cue {
+            settings.cue = cue;
+        }
+        if let Some(locale) = &self.locale {
+            settings.locale = locale.clone();
+        }
+        if let Some(kind) = self.background {
+            background.kind = kind;
+        }
+        if let Some(color) = self.background_color {
+            background.color_top = color;
+        }
+        if let Some(color) = self.background_color2 {
+            background.color_bottom = color;
+        }
+        if let Some(image) = &self.background_image {
+            background.image = Some(image.clone());
+        }
+        if let Some(style) = self.svg_style {
+            settings.svg.style = style;
+        }
+        if let Some(stroke_width) = self.svg_stroke_width {
+            settings.svg.stroke_width = stroke_width;
+        }
+        if let Some(particles) = self.particles {
+            settings.particles = particles;
+        }
+        if let Some(rewind_seconds) = self.rewind_seconds {
+            settings.rewind_seconds = rewind_seconds;
+        }
+        if let Some(substeps) = self.substeps {
+            settings.substeps = substeps;
+        }
+        if let Some(workgroup_size) = self.workgroup_size {
+            settings.simulation.workgroup_size = workgroup_size;
+        }
+        if let Some(tiled) = self.tiled {
+            settings.simulation.tiled = tiled;
+        }
+        if let Some(half_precision) = self.half_precision {
+            settings.simulation.half_precision = half_precision;
+        }
+        if let Some(separate_arrays) = self.separate_arrays {
+            settings.simulation.separate_arrays = separate_arrays;
+        }
+        if let Some(neighborhood) = self.neighborhood {
+            settings.simulation.neighborhood = neighborhood;
+        }
+        if let Some(max) = self.max_speed {
+            settings.speed_limits.max = max;
+        }
+        if let Some(min) = self.min_speed {
+            settings.speed_limits.min = min;
+        }
+        if let Some(decay) = self.speed_decay {
+            settings.speed_limits.decay = decay;
+        }
+        if let Some(alignment) = self.alignment_weight {
+            settings.rule_weights.alignment = alignment;
+        }
+        if let Some(cohesion) = self.cohesion_weight {
+            settings.rule_weights.cohesion = cohesion;
+        }
+        if let Some(separation) = self.separation_weight {
+            settings.rule_weights.separation = separation;
+        }
+        if let Some(alignment) = self.alignment_radius {
+            settings.rule_radii.alignment = alignment;
+        }
+        if let Some(cohesion) = self.cohesion_radius {
+            settings.rule_radii.cohesion = cohesion;
+        }
+        if let Some(separation) = self.separation_radius {
+            settings.rule_radii.separation = separation;
+        }
+        if let Some(view_angle) = self.view_angle {
+            settings.view_angle = (view_angle < 180.0).then_some(view_angle);
+        }
+        if !self.set.is_empty() {
+            let mut params = settings.boids_params();
+            for (parameter, value) in &self.set {
+                parameter.set(&mut params, *value);
+            }
+            settings.set_boids_params(&params);
+        }
+        if let Some(piston) = self.piston {
+            settings.simulation.piston = piston;
+        }
+        if let Some(target_speed) = self.thermostat {
+            settings.thermostat = (target_speed > 0.0).then(|| ThermostatConfig {
+                target_speed,
+                ..settings
+                    .thermostat
+                    .unwrap_or_else(|| ThermostatConfig::new(target_speed))
+            });
+        }
+        if let (Some(rate), Some(thermostat)) = (self.thermostat_rate, &mut settings.thermostat) {
+            thermostat.rate = rate;
+        }
+        if let Some(physarum) = self.physarum {
+            settings.physarum = physarum.then(|| settings.physarum.unwrap_or_default());
+        }
+        if let Some(fireworks) = self.fireworks {
+            settings.fireworks = fireworks.then(|| settings.fireworks.unwrap_or_default());
+        }
+        if let (Some(interval), Some(fireworks)) = (self.burst_interval, &mut settings.fireworks) {
+            fireworks.interval = interval;
+        }
+        if let Some(density) = self.lennard_jones {
+            settings.lennard_jones = (density > 0.0).then(|| LennardJonesConfig {
+                density,
+                ..settings.lennard_jones.unwrap_or_default()
+            });
+        }
+        let network = settings.network.unwrap_or_default();
+        if let Some(size) = self.cloth {
+            settings.network = Some(NetworkConfig {
+                shape: NetworkShape::Cloth,
+                size,
+                ..network
+            });
+        }
+        if let Some(length) = self.rope {
+            settings.network = (length > 0).then_some(NetworkConfig {
+                shape: NetworkShape::Rope,
+                size: [length, 1],
+                ..network
+            });
+        }
+        if let (Some(stiffness), Some(network)) = (self.stiffness, &mut settings.network) {
+            network.stiffness = stiffness;
+        }
+        if let Some(iterations) = self.pbd {
+            settings.pbd = (iterations > 0).then(|| PbdConfig {
+                iterations,
+                ..settings.pbd.unwrap_or_default()
+            });
+        }
+        if let (Some(radius), Some(pbd)) = (self.particle_radius, &mut settings.pbd) {
+            pbd.radius = radius;
+        }
+        if let Some(strength) = self.noise {
+            settings.noise = (strength > 0.0).then(|| NoiseConfig {
+                strength,
+                ..settings.noise.unwrap_or_default()
+            });
+        }
+        if let Some(jitter) = self.jitter {
+            let noise = settings.noise.get_or_insert(NoiseConfig {
+                strength: 0.0,
+                ..Default::default()
+            });
+            noise.jitter = jitter;
+        }
+        if let Some(wander) = self.wander {
+            settings.wander = (wander.strength > 0.0).then_some(wander);
+        }
+        if !self.attractor.is_empty() {
+            settings.attractors = self.attractor.clone();
+        }
+        if let Some(drag) = self.drag {
+            settings.drag = (drag.linear > 0.0 || drag.quadratic > 0.0).then_some(drag);
+        }
+        if let Some(vorticity) = self.vorticity {
+            settings.vorticity = (vorticity.strength > 0.0).then_some(vorticity);
+        }
+        if let Some([min, max]) = self.mass {
+            settings.mass = (min != 1.0 || max != 1.0).then(|| MassConfig {
+                min,
+                max,
+                ..settings.mass.unwrap_or_default()
+            });
+        }
+        if let (Some(distribution), Some(mass)) = (self.mass_distribution, &mut settings.mass) {
+            mass.distribution = distribution;
+        }
+        if let Some([min, max]) = self.roi {
+            settings.roi = Some(RegionOfInterest {
+                min,
+                max,
+                ..settings.roi.unwrap_or_default()
+            });
+        }
+        if let (Some(substeps), Some(roi)) = (self.roi_substeps, &mut settings.roi) {
+            roi.substeps = substeps;
+        }
+        settings.roi = settings.roi.filter(|roi| roi.substeps > 1);
+        if let Some(path) = &self.vector_field {
+            let field = settings.vector_field.take();
+            settings.vector_field = Some(VectorFieldConfig {
+                path: path.clone(),
+                ..field.unwrap_or_else(|| VectorFieldConfig::new(path.clone()))
+            });
+        }
+        if let (Some(scale), Some(field)) = (self.field_scale, &mut settings.vector_field) {
+            field.scale = scale;
+        }
+        if let (Some(coupling), Some(field)) = (self.field_coupling, &mut settings.vector_field) {
+            field.coupling = coupling;
+        }
+        if let (Some(seconds), Some(field)) = (self.field_seconds, &mut settings.vector_field) {
+            field.frame_seconds = seconds;
+        }
+        if let (Some(follow), Some(field)) = (self.field_follow, &mut settings.vector_field) {
+            field.follow = follow;
+        }
+        if let Some(enabled) = self.reaction_diffusion {
+            settings.reaction_diffusion =
+                enabled.then(|| settings.reaction_diffusion.unwrap_or_default());
+        }
+        if let (Some(follow), Some(reaction_diffusion)) =
+            (self.reaction_follow, &mut settings.reaction_diffusion)
+        {
+            reaction_diffusion.follow = follow;
+        }
+        if let Some(follow) = self.trail {
+            settings.trail = (follow != 0.0).then(|| TrailConfig {
+                follow,
+                ..settings.trail.unwrap_or_default()
+            });
+        }
+        if let Some(stamina) = self.stamina {
+            settings.stamina = (stamina.drain > 0.0).then_some(stamina);
+        }
+        if let Some(inflow) = self.inflow {
+            settings.inflow = Some(inflow);
+        }
+        if let Some(sleep) = self.sleep {
+            settings.sleep = (sleep.speed > 0.0).then_some(sleep);
+        }
+        if let Some(canvas) = self.canvas {
+            settings.canvas = (canvas.opacity > 0.0).then_some(canvas);
+        }
+        if let Some(align) = self.orient {
+            settings.orientation = (align > 0.0).then(|| OrientationConfig {
+                align,
+                ..settings.orientation.unwrap_or_default()
+            });
+        }
+        if let Some(seconds) = self.lifetime {
+            settings.lifetime = (seconds > 0.0).then(|| LifetimeConfig {
+                seconds,
+                ..settings
+                    .lifetime
+                    .unwrap_or_else(|| LifetimeConfig::new(seconds))
+            });
+        }
+        if let (Some(fade), Some(lifetime)) = (self.fade, &mut settings.lifetime) {
+            lifetime.fade = fade;
+        }
+        if let (Some(shrink), Some(lifetime)) = (self.shrink, &mut settings.lifetime) {
+            lifetime.shrink = shrink;
+        }
+        if let Some(waypoints) = &self.goal {
+            settings.goal = Some(GoalConfig {
+                waypoints: waypoints.clone(),
+                path: None,
+                ..settings.goal.take().unwrap_or_default()
+            });
+        }
+        if let Some(path) = &self.goal_path {
+            settings.goal = Some(GoalConfig {
+                path: Some(path.clone()),
+                ..settings.goal.take().unwrap_or_default()
+            });
+        }
+        if let (Some(seconds), Some(goal)) = (self.goal_seconds, &mut settings.goal) {
+            goal.seconds = seconds;
+        }
+        if let (Some(strength), Some(goal)) = (self.goal_strength, &mut settings.goal) {
+            goal.strength = strength;
+        }
+        if let Some(leaders) = self.leaders {
+            settings.leaders = (leaders.fraction > 0.0).then_some(leaders);
+        }
+        if let Some(text) = &self.text {
+            settings.text = (!text.is_empty()).then(|| TextConfig {
+                text: text.replace("\\n", "\n"),
+                ..settings.text.take().unwrap_or_default()
+            });
+        }
+        if let (Some(font), Some(text)) = (&self.font, &mut settings.text) {
+            text.font = Some(font.clone());
+        }
+        if let (Some(strength), Some(text)) = (self.text_strength, &mut settings.text) {
+            text.strength = strength;
+        }
+        if let Some(positions) = &self.emitters {
+            settings.emitters = Some(EmittersConfig {
+                positions: positions.clone(),
+                ..settings.emitters.take().unwrap_or_default()
+            });
+            settings.color_mode = ColorMode::Emitter;
+        }
+        if let (Some(mixing), Some(emitters)) = (self.dye_mixing, &mut settings.emitters) {
+            emitters.mixing = mixing;
+        }
+        if let Some(bases) = &self.bases {
+            settings.territory = (!bases.is_empty()).then(|| TerritoryConfig {
+                bases: bases.clone(),
+                ..settings.territory.take().unwrap_or_default()
+            });
+            settings.color_mode = ColorMode::Emitter;
+        }
+        if let (Some(period), Some(territory)) = (self.base_period, &mut settings.territory) {
+            territory.period = period;
+        }
+        if let Some(contagion) = self.contagion {
+            settings.contagion = (contagion.probability > 0.0).then_some(contagion);
+            if settings.contagion.is_some() {
+                settings.color_mode = ColorMode::Emitter;
+            }
+        }
+        if let Some(bounds) = self.compact {
+            settings.compaction = (bounds > 0.0).then_some(CompactionConfig { bounds });
+        }
+        if let (Some(temperature), Some(lennard_jones)) =
+            (self.temperature, &mut settings.lennard_jones)
+        {
+            lennard_jones.temperature = temperature;
+        }
+        if let Some(adaptive_quality) = self.adaptive_quality {
+            settings.adaptive_quality = adaptive_quality;
+        }
+        if let Some(frame_budget_ms) = self.frame_budget_ms {
+            settings.frame_budget_ms = Some(frame_budget_ms);
+        }
+        if let Some(present_mode) = self.present_mode {
+            settings.present_mode = present_mode;
+        }
+        if let Some(fps) = self.fps {
+            settings.fps = Some(fps);
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            settings.tick_rate = Some(tick_rate);
+        }
+        if let Some(installation) = &self.installation {
+            settings.installation = (installation.watchdog_ms > 0.0).then(|| InstallationConfig {
+                preset: settings
+                    .installation
+                    .as_ref()
+                    .and_then(|installation| installation.preset.clone()),
+                ..installation.clone()
+            });
+        }
+        if let (Some(preset), Some(installation)) =
+            (&self.restart_preset, &mut settings.installation)
+        {
+            installation.preset = Some(preset.clone());
+        }
+        if let Some(present) = self.present {
+            settings.present = present;
+        }
+        if let Some(periodic_copies) = self.periodic_copies {
+            settings.periodic_copies = periodic_copies;
+        }
+        if let Some(minimap) = self.minimap {
+            settings.minimap = minimap;
+        }
+        if let Some(speed_histogram) = self.speed_histogram {
+            settings.speed_histogram = speed_histogram;
+        }
+        if let Some(world_size) = self.world_size {
+            settings.world_size = world_size;
+        }
+        if let Some(frame_graph) = self.frame_graph {
+            settings.frame_graph = frame_graph;
+        }
+        if let Some(fluid_shading) = self.fluid_shading {
+            settings.fluid_shading = fluid_shading;
+        }
+        if let Some(auto_exposure) = self.auto_exposure {
+            settings.auto_exposure = auto_exposure;
+        }
+        if let Some(key) = self.sort {
+            settings.sort = Some(SortConfig {
+                key,
+                ..settings.sort.take().unwrap_or_default()
+            });
+        }
+        if let (Some(interval), Some(sort)) = (self.sort_interval, &mut settings.sort) {
+            sort.interval = interval;
+        }
+        settings.sort = settings.sort.filter(|sort| sort.interval > 0);
+        if let Some(regions) = &self.freeze {
+            settings.freeze = Some(FreezeConfig {
+                regions: regions.clone(),
+            });
+        }
+        if let Some(species) = &self.species {
+            settings.species = species.clone();
+        }
+        if let Some(effects) = &self.post_fx {
+            settings.post_fx = effects.clone();
+        }
+        if let Some(modulators) = &self.modulate {
+            settings.modulators = modulators.clone();
+        }
+        if let Some(color_mode) = self.color_mode {
+            settings.color_mode = color_mode;
+        }
+        if let Some(projection) = self.projection {
+            settings.camera = Some(CameraState {
+                projection,
+                ..settings.camera.unwrap_or(CameraState {
+                    zoom: settings.world_size,
+                    ..Default::default()
+                })
+            });
+        }
+        if let Some(strength) = self.touch_strength {
+            settings.touch_strength = strength;
+        }
+        if let Some(pen_pressure) = self.pen_pressure {
+            settings.pen_pressure = pen_pressure;
+        }
+        if let Some(impulse) = self.impulse {
+            settings.impulse = impulse;
+        }
+        if let Some(stir) = self.stir {
+            settings.stir = stir;
+        }
+        if let Some(strength) = self.gamepad {
+            settings.gamepad = (strength != 0.0).then(|| GamepadConfig {
+                strength,
+                ..settings.gamepad.clone().unwrap_or_default()
+            });
+        }
+        if let Some(source) = &self.audio {
+            settings.beats = (!source.as_os_str().is_empty()).then(|| BeatConfig {
+                source: source.clone(),
+                ..settings.beats.clone().unwrap_or_default()
+            });
+        }
+        if let (Some(sensitivity), Some(beats)) = (self.beat_sensitivity, &mut settings.beats) {
+            beats.sensitivity = sensitivity;
+        }
+        if let (Some(device), Some(gamepad)) = (&self.gamepad_device, &mut settings.gamepad) {
+            gamepad.device = device.clone();
+        }
+        if let Some(particle_size) = self.particle_size {
+            settings.particle_size = (particle_size > 0.0).then_some(particle_size);
+        }
+        if let Some(mesh) = &self.mesh {
+            settings.mesh = Some(mesh.clone());
+        }
+        if let Some(level) = &self.level {
+            settings.level = Some(level.clone());
+        }
+    }
+}
+
+pub fn parse_hex_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("expected a colour like #rrggbb, got `{s}`"));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid hex colour `{s}`"))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn parse_radius(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(radius) if radius.is_finite() && radius >= 0.0 => Ok(radius),
+        _ => Err(format!("expected a radius of 0 or more, got `{s}`")),
+    }
+}
+
+fn parse_view_angle(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(angle) if angle.is_finite() && angle > 0.0 => Ok(angle),
+        _ => Err(format!("expected a positive angle in degrees, got `{s}`")),
+    }
+}
+
+fn parse_particle_size(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(size) if size.is_finite() && size >= 0.0 => Ok(size),
+        _ => Err(format!("expected a size of 0 or more points, got `{s}`")),
+    }
+}
+
+fn parse_tick_rate(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!(
+            "expected a positive number of ticks a second, got `{s}`"
+        )),
+    }
+}
+
+fn parse_frame_budget(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(ms) if ms.is_finite() && ms > 0.0 => Ok(ms),
+        _ => Err(format!(
+            "expected a positive number of milliseconds, got `{s}`"
+        )),
+    }
+}
+
+fn parse_world_size(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err(format!("expected a positive number of windows, got `{s}`")),
+    }
+}
+
+// Empty for none, so saved species can be turned off again
+fn parse_species_or_none(s: &str) -> Result<Vec<SpeciesConfig>, String> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    species::parse_species(s)
+}
+
+fn parse_effects_or_none(s: &str) -> Result<Vec<Effect>, String> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    post_fx::parse_effects(s)
+}
+
+fn parse_modulators_or_none(s: &str) -> Result<Vec<Modulator>, String> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    modulation::parse_modulators(s)
+}
+
+// An empty list turns the freeze tool on with nothing frozen
+fn parse_regions_or_none(s: &str) -> Result<Vec<Region>, String> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    freeze::parse_regions(s)
+}
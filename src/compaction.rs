@@ -0,0 +1,359 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::gpu::PrefixScan;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Byte offsets into the counts buffer, must match `Counts` in compaction_shader.wgsl
+const DISPATCH_ARGS_OFFSET: wgpu::BufferAddress = 16;
+const ALIVE_OFFSET: wgpu::BufferAddress = 28;
+const COUNTS_SIZE: wgpu::BufferAddress = 32;
+// Where `particle_count` sits in `SimParams`
+const PARTICLE_COUNT_OFFSET: wgpu::BufferAddress = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompactionConfig {
+    // Particles further than this from the centre on either axis are removed. The domain
+    // spans -1..1, so the default only catches ones that escaped
+    pub bounds: f32,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig { bounds: 2.0 }
+    }
+}
+
+wgsl_struct! {
+    // Must match `CompactionParams` in compaction_shader.wgsl
+    struct CompactionParams {
+        bounds: f32,
+    }
+}
+
+/// Removes particles that left the bounds or turned into NaN, moving the survivors to the
+/// front of the buffer in order so neighbour loops stay dense.
+///
+/// Flags are scanned into each survivor's new index, then every particle is scattered into
+/// the other ping-pong buffer. The surviving count is written into the draw and dispatch
+/// arguments and into `SimParams` on the GPU, so later passes in the same frame only see
+/// survivors, and is read back asynchronously for the CPU's count a frame or two later.
+pub struct Compactor {
+    pub config: CompactionConfig,
+    flag: wgpu::ComputePipeline,
+    scatter: wgpu::ComputePipeline,
+    scan: PrefixScan,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<CompactionParams>,
+    offsets_buffer: wgpu::Buffer,
+    counts_buffer: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    // Indexed by the particle buffer read from, see `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Whether the count was copied out this frame, then the count it was compacted from
+    // while it's being mapped
+    copied: bool,
+    pending: Option<u32>,
+    mapped: Arc<AtomicBool>,
+    // The count last written into `SimParams`, or caught up to, see `count_to_write`
+    written: Option<u32>,
+}
+
+impl Compactor {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: CompactionConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "compaction_shader",
+            include_str!("./shaders/compaction_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .uniform(5)
+            .build(device, "Compaction");
+        let params_buffer = UniformBuffer::new(device, resources, "Compaction Params Buffer");
+        let counts_buffer = resources.buffer(
+            device,
+            "Compaction Counts Buffer",
+            COUNTS_SIZE,
+            BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_SRC,
+        );
+        let staging = resources.buffer(
+            device,
+            "Compaction Readback Buffer",
+            std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        );
+        let (offsets_buffer, bind_groups) =
+            bind(device, resources, &bindings, &params_buffer, &counts_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compaction Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Compaction Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Compaction Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Compactor {
+            config,
+            flag: pipeline("flag"),
+            scatter: pipeline("scatter"),
+            scan: PrefixScan::new(device),
+            bindings,
+            params_buffer,
+            offsets_buffer,
+            counts_buffer,
+            staging,
+            bind_groups,
+            copied: false,
+            pending: None,
+            mapped: Arc::default(),
+            written: None,
+        }
+    }
+
+    /// Draw the survivors of the last compaction, 3 vertices each.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.draw_indirect(&self.counts_buffer, 0);
+    }
+
+    /// Dispatch one `WORKGROUP_SIZE` invocation per survivor of the last compaction.
+    pub fn dispatch<'a>(&'a self, compute_pass: &mut wgpu::ComputePass<'a>) {
+        compute_pass.dispatch_workgroups_indirect(&self.counts_buffer, DISPATCH_ARGS_OFFSET);
+    }
+
+    /// Start mapping the count copied out this frame. Call after submitting.
+    pub fn map(&mut self) {
+        if !std::mem::take(&mut self.copied) {
+            return;
+        }
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("Failed to map the compacted count: {}", err),
+            });
+    }
+
+    /// How many survived a compaction, for the CPU's count to catch up to, once a reading has
+    /// arrived, unless `particle_count` changed while it was in flight.
+    pub fn poll(&mut self, particle_count: u32) -> Option<u32> {
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        let alive = {
+            let data = self.staging.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned::<u32>(&data)
+        };
+        self.staging.unmap();
+        let compacted = self.pending.take()?;
+        (compacted == particle_count).then(|| {
+            // Only catching up, the GPU's count may have moved on since
+            self.written = Some(alive);
+            alive
+        })
+    }
+
+    /// The count to write into `SimParams` for `particle_count`, the CPU's count, none while
+    /// it's unchanged, as the GPU's compacted count is newer.
+    pub fn count_to_write(&mut self, particle_count: u32) -> Option<u32> {
+        (self.written != Some(particle_count)).then(|| {
+            self.written = Some(particle_count);
+            particle_count
+        })
+    }
+}
+
+impl Stage for Compactor {
+    fn kind(&self) -> StageKind {
+        StageKind::Compaction
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if frame.particle_count == 0 {
+            return;
+        }
+        let params = CompactionParams {
+            bounds: self.config.bounds,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        let bind_group = &self.bind_groups[resources.current()];
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Compaction Flag Pass"),
+            });
+            compute_pass.set_pipeline(&self.flag);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        self.scan.encode(
+            frame.device,
+            encoder,
+            &self.offsets_buffer,
+            frame.particle_count,
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Compaction Scatter Pass"),
+            });
+            compute_pass.set_pipeline(&self.scatter);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        resources.swap();
+
+        encoder.copy_buffer_to_buffer(
+            &self.counts_buffer,
+            ALIVE_OFFSET,
+            &resources.params,
+            PARTICLE_COUNT_OFFSET,
+            4,
+        );
+        if self.pending.is_none() {
+            encoder.copy_buffer_to_buffer(&self.counts_buffer, ALIVE_OFFSET, &self.staging, 0, 4);
+            self.pending = Some(frame.particle_count);
+            self.copied = true;
+        }
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.offsets_buffer, self.bind_groups) = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.counts_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<CompactionParams>,
+    counts_buffer: &wgpu::Buffer,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let offsets_buffer = resources.buffer(
+        device,
+        "Compaction Offsets Buffer",
+        resources.capacity() as wgpu::BufferAddress
+            * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE,
+    );
+    let [a, b] = resources.particle_buffers();
+    let bind_groups = [(a, b), (b, a)].map(|(src, dst)| {
+        bindings.bind_group(
+            device,
+            &[
+                src.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                offsets_buffer.as_entire_binding(),
+                dst.as_entire_binding(),
+                counts_buffer.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    });
+    (offsets_buffer, bind_groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_variant::SimVariant;
+    use crate::{gpu, headless, Particle, SimParams};
+
+    #[test]
+    fn keeps_the_compacted_count_until_the_cpu_changes_it() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // Every third particle escaped the bounds
+        let particles = (0..600)
+            .map(|i| Particle {
+                position: [if i % 3 == 0 { 5.0 } else { 0.5 }, i as f32 / 1000.0],
+                velocity: [0.0; 2],
+            })
+            .collect::<Vec<_>>();
+        let mut resources = GpuResources::new(&device, &particles);
+        let mut compactor = Compactor::new(&device, &mut resources, CompactionConfig::default());
+        let count = particles.len() as u32;
+        assert_eq!(compactor.count_to_write(count), Some(count));
+        let params = SimParams {
+            particle_count: count,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        queue.write_buffer(&resources.params, 0, bytemuck::bytes_of(&params));
+
+        let frame = FrameContext {
+            device: &device,
+            queue: &queue,
+            variant: SimVariant::default(),
+            particle_count: count,
+            substeps: 1,
+            time: 0.0,
+            frame: 0,
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Compaction Test Encoder"),
+        });
+        compactor.encode(&frame, &mut encoder, &mut resources);
+        queue.submit(Some(encoder.finish()));
+        compactor.map();
+        device.poll(wgpu::Maintain::Wait);
+
+        let alive = compactor.poll(count);
+        assert_eq!(alive, Some(400));
+        let survivors = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 400);
+        assert!(survivors.iter().all(|particle| particle.position[0] == 0.5));
+        // In order, so neighbour loops stay dense and particles keep their places
+        assert!(survivors
+            .windows(2)
+            .all(|pair| pair[0].position[1] < pair[1].position[1]));
+
+        // Caught up, the GPU's count stands until the CPU's changes
+        assert_eq!(compactor.count_to_write(400), None);
+        assert_eq!(compactor.count_to_write(500), Some(500));
+        assert_eq!(compactor.count_to_write(500), None);
+    }
+}
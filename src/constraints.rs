@@ -0,0 +1,361 @@
+use clap::ValueEnum;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::Particle;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkShape {
+    /// A grid pinned at a few points along its top edge
+    Cloth,
+    /// A chain pinned at its left end
+    Rope,
+}
+
+/// A spring network built in place of the random particles.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub shape: NetworkShape,
+    // Particles across and down, a rope only uses the first
+    pub size: [u32; 2],
+    // Spring force per unit stretch, per frame squared. Above about 0.5 needs more substeps.
+    pub stiffness: f32,
+    // Fraction of the relative velocity along each spring removed per frame
+    pub damping: f32,
+    // Downward acceleration in domain units per frame squared
+    pub gravity: f32,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            shape: NetworkShape::Cloth,
+            size: [64, 48],
+            stiffness: 0.3,
+            damping: 0.05,
+            gravity: 0.00001,
+        }
+    }
+}
+
+/// A distance constraint between two particles.
+// Must match `Constraint` in spring_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Constraint {
+    pub a: u32,
+    pub b: u32,
+    pub rest_length: f32,
+}
+
+/// Starting particles and the constraints between them. Pinned particles have an inverse
+/// mass of 0 and never move.
+pub struct ConstraintNetwork {
+    pub particles: Vec<Particle>,
+    pub inverse_mass: Vec<f32>,
+    pub constraints: Vec<Constraint>,
+}
+
+impl ConstraintNetwork {
+    pub fn new(config: &NetworkConfig) -> Self {
+        match config.shape {
+            NetworkShape::Cloth => Self::cloth(config.size[0].max(2), config.size[1].max(2)),
+            NetworkShape::Rope => Self::rope(config.size[0].max(2)),
+        }
+    }
+
+    fn cloth(columns: u32, rows: u32) -> Self {
+        let spacing = 1.4 / (columns - 1) as f32;
+        let index = |column: u32, row: u32| row * columns + column;
+        let particles = (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| Particle {
+                    position: [-0.7 + column as f32 * spacing, 0.9 - row as f32 * spacing],
+                    velocity: [0.0, 0.0],
+                })
+            })
+            .collect::<Vec<_>>();
+        // Pin five points along the top edge so the cloth hangs in folds
+        let mut inverse_mass = vec![1.0; particles.len()];
+        for pin in 0..5 {
+            inverse_mass[(pin * (columns - 1) / 4) as usize] = 0.0;
+        }
+
+        // Springs along the grid, plus both diagonals so the cells resist shearing
+        let diagonal = spacing * std::f32::consts::SQRT_2;
+        let mut constraints = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let mut link = |other: u32, rest_length: f32| {
+                    constraints.push(Constraint {
+                        a: index(column, row),
+                        b: other,
+                        rest_length,
+                    })
+                };
+                if column + 1 < columns {
+                    link(index(column + 1, row), spacing);
+                }
+                if row + 1 < rows {
+                    link(index(column, row + 1), spacing);
+                }
+                if column + 1 < columns && row + 1 < rows {
+                    link(index(column + 1, row + 1), diagonal);
+                }
+                if column > 0 && row + 1 < rows {
+                    link(index(column - 1, row + 1), diagonal);
+                }
+            }
+        }
+        ConstraintNetwork {
+            particles,
+            inverse_mass,
+            constraints,
+        }
+    }
+
+    fn rope(length: u32) -> Self {
+        let spacing = 1.6 / (length - 1) as f32;
+        let particles = (0..length)
+            .map(|i| Particle {
+                position: [-0.8 + i as f32 * spacing, 0.8],
+                velocity: [0.0, 0.0],
+            })
+            .collect::<Vec<_>>();
+        let mut inverse_mass = vec![1.0; particles.len()];
+        inverse_mass[0] = 0.0;
+        let constraints = (1..length)
+            .map(|i| Constraint {
+                a: i - 1,
+                b: i,
+                rest_length: spacing,
+            })
+            .collect();
+        ConstraintNetwork {
+            particles,
+            inverse_mass,
+            constraints,
+        }
+    }
+}
+
+/// The network's constraints and masses on the GPU, for the solvers.
+pub struct ConstraintBuffers {
+    pub constraints: wgpu::Buffer,
+    pub inverse_mass: wgpu::Buffer,
+    pub constraint_count: u32,
+}
+
+impl ConstraintBuffers {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        network: &ConstraintNetwork,
+    ) -> Self {
+        ConstraintBuffers {
+            constraints: resources.buffer_init(
+                device,
+                "Constraint Buffer",
+                bytemuck::cast_slice(&network.constraints),
+                BufferUsages::STORAGE,
+            ),
+            inverse_mass: resources.buffer_init(
+                device,
+                "Inverse Mass Buffer",
+                bytemuck::cast_slice(&network.inverse_mass),
+                BufferUsages::STORAGE,
+            ),
+            constraint_count: network.constraints.len() as u32,
+        }
+    }
+}
+
+// Must match `SpringParams` in spring_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpringParams {
+    constraint_count: u32,
+    stiffness: f32,
+    damping: f32,
+    gravity: f32,
+}
+
+/// Moves a spring network in place of the boids step, for cloth and rope demos.
+///
+/// Each substep one pass adds every spring's force to both its particles through fixed-point
+/// atomics, then a second integrates the particles and clears the forces. The particles are
+/// updated in place, so there's no ping-pong.
+pub struct SpringSolver {
+    pub config: NetworkConfig,
+    buffers: ConstraintBuffers,
+    springs: wgpu::ComputePipeline,
+    integrate: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl SpringSolver {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: NetworkConfig,
+        network: &ConstraintNetwork,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "spring_shader",
+            include_str!("./shaders/spring_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_ro(3)
+            .storage_ro(4)
+            .storage_rw(5)
+            .build(device, "Springs");
+        let buffers = ConstraintBuffers::new(device, resources, network);
+        let params_buffer = resources.uniform::<SpringParams>(device, "Spring Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer, &buffers);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Spring Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Spring Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Spring Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+
+        SpringSolver {
+            config,
+            buffers,
+            springs: pipeline("springs"),
+            integrate: pipeline("integrate"),
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.buffers,
+        );
+    }
+
+    /// The network on the GPU, for a `PbdSolver` to hold its distances too.
+    pub fn buffers(&self) -> &ConstraintBuffers {
+        &self.buffers
+    }
+
+    /// Encode `substeps` steps for the first `particle_count` particles. Uses the `SimParams`
+    /// written by `Simulation::write_params`.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        particle_count: u32,
+        substeps: u32,
+    ) {
+        let params = SpringParams {
+            constraint_count: self.buffers.constraint_count,
+            stiffness: self.config.stiffness,
+            damping: self.config.damping,
+            gravity: self.config.gravity,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Spring Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        for _ in 0..substeps {
+            compute_pass.set_pipeline(&self.springs);
+            compute_pass.dispatch_workgroups(
+                self.buffers.constraint_count.div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+            compute_pass.set_pipeline(&self.integrate);
+            compute_pass.dispatch_workgroups(particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+}
+
+impl Stage for SpringSolver {
+    fn kind(&self) -> StageKind {
+        StageKind::Springs
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        SpringSolver::encode(
+            self,
+            frame.queue,
+            encoder,
+            resources,
+            frame.particle_count,
+            frame.substeps,
+        );
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        SpringSolver::rebind(self, device, resources);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &wgpu::Buffer,
+    buffers: &ConstraintBuffers,
+) -> [wgpu::BindGroup; 2] {
+    // An x and y per particle
+    let forces = resources.buffer(
+        device,
+        "Spring Force Buffer",
+        resources.capacity() as wgpu::BufferAddress * 2 * std::mem::size_of::<i32>() as u64,
+        BufferUsages::STORAGE,
+    );
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.as_entire_binding(),
+                buffers.constraints.as_entire_binding(),
+                buffers.inverse_mass.as_entire_binding(),
+                forces.as_entire_binding(),
+            ],
+        )
+    })
+}
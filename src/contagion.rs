@@ -0,0 +1,270 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match `Health` in contagion_shader.wgsl
+const HEALTH_SIZE: wgpu::BufferAddress = 8;
+
+/// An outbreak spreading through the flock: susceptible particles near infected ones catch it
+/// by chance each frame, and recover after a while for good.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContagionConfig {
+    // Chance each frame of catching it from each infected particle in range
+    pub probability: f32,
+    // Distance it spreads over, in domain units
+    pub radius: f32,
+    // Simulated seconds until an infected particle recovers
+    pub recovery: f32,
+    // Fraction of the particles infected at the start
+    pub initial: f32,
+}
+
+impl Default for ContagionConfig {
+    fn default() -> Self {
+        ContagionConfig {
+            probability: 0.05,
+            radius: 0.02,
+            recovery: 10.0,
+            initial: 0.01,
+        }
+    }
+}
+
+/// Parse `probability[,radius[,recovery[,initial]]]`, e.g. `0.05,0.02,10,0.01`.
+pub fn parse_contagion(s: &str) -> Result<ContagionConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid contagion `{s}`"))?;
+    if values.is_empty() || values.len() > 4 || values.iter().any(|&value| value < 0.0) {
+        return Err(format!(
+            "expected a contagion like 0.05,0.02,10,0.01, none negative, got `{s}`"
+        ));
+    }
+    let default = ContagionConfig::default();
+    let value = |i: usize, default: f32| values.get(i).copied().unwrap_or(default);
+    Ok(ContagionConfig {
+        probability: value(0, default.probability).min(1.0),
+        radius: value(1, default.radius),
+        recovery: value(2, default.recovery),
+        initial: value(3, default.initial).min(1.0),
+    })
+}
+
+/// Particles susceptible, infected and recovered, as the counts read back from
+/// `Contagion::counts_buffer`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outbreak {
+    pub susceptible: u32,
+    pub infected: u32,
+    pub recovered: u32,
+}
+
+impl Outbreak {
+    /// From the counts buffer's contents.
+    pub fn from_counts(counts: [u32; 3]) -> Self {
+        let [susceptible, infected, recovered] = counts;
+        Outbreak {
+            susceptible,
+            infected,
+            recovered,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `ContagionParams` in contagion_shader.wgsl
+    struct ContagionParams {
+        particle_count: u32,
+        frame: u32,
+        probability: f32,
+        radius: f32,
+        recovery_frames: u32,
+        initial: f32,
+    }
+}
+
+/// Spreads a `ContagionConfig` outbreak, each particle's health kept in a buffer of its own
+/// indexed like the particles, and shown through `GpuResources::dyes` with the emitter colour
+/// mode. Like the boids step it looks at every other particle, so it costs about as much.
+///
+/// Health is stepped from one buffer into the other and copied back, so every particle
+/// catches it from where things stood at the start of the frame. The frame's counts are
+/// added up into `counts_buffer` for logging. Compaction is left out with it, and the
+/// outbreak starts over when the buffers are resized.
+pub struct Contagion {
+    pub config: ContagionConfig,
+    init: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ContagionParams>,
+    // Read from and written to, indexed like the particles
+    health: wgpu::Buffer,
+    next_health: wgpu::Buffer,
+    counts: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the first particles have been infected
+    needs_init: bool,
+}
+
+impl Contagion {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: ContagionConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "contagion_shader",
+            include_str!("./shaders/contagion_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .storage_rw(5)
+            .build(device, "Contagion");
+        let params_buffer = UniformBuffer::new(device, resources, "Contagion Params Buffer");
+        let counts = resources.buffer(
+            device,
+            "Contagion Counts Buffer",
+            3 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        );
+        let (health, next_health, bind_groups) =
+            bind(device, resources, &bindings, &params_buffer, &counts);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Contagion Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Contagion Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Contagion Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Contagion {
+            config,
+            init: pipeline("init"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            health,
+            next_health,
+            counts,
+            bind_groups,
+            needs_init: true,
+        }
+    }
+
+    /// Each particle's health, for moving it along with the particles.
+    pub fn health_buffer(&self) -> &wgpu::Buffer {
+        &self.health
+    }
+
+    /// The last frame's `Outbreak`, as three `u32`s, for copying out.
+    pub fn counts_buffer(&self) -> &wgpu::Buffer {
+        &self.counts
+    }
+}
+
+impl Stage for Contagion {
+    fn kind(&self) -> StageKind {
+        StageKind::Contagion
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = ContagionParams {
+            particle_count: frame.particle_count,
+            frame: frame.frame,
+            probability: self.config.probability.clamp(0.0, 1.0),
+            radius: self.config.radius.max(0.0),
+            recovery_frames: (self.config.recovery.max(0.0) * 60.0).round() as u32,
+            initial: self.config.initial.clamp(0.0, 1.0),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        encoder.clear_buffer(&self.counts, 0, None);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Contagion Pass"),
+            });
+            compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+            if std::mem::take(&mut self.needs_init) {
+                compute_pass.set_pipeline(&self.init);
+                let workgroups = resources.capacity().div_ceil(WORKGROUP_SIZE);
+                compute_pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+            compute_pass.set_pipeline(&self.step);
+            compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        let used = frame.particle_count.max(1) as wgpu::BufferAddress * HEALTH_SIZE;
+        encoder.copy_buffer_to_buffer(&self.next_health, 0, &self.health, 0, used);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.health, self.next_health, self.bind_groups) = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.counts,
+        );
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ContagionParams>,
+    counts: &wgpu::Buffer,
+) -> (wgpu::Buffer, wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let size = resources.capacity().max(1) as wgpu::BufferAddress * HEALTH_SIZE;
+    let usages = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let health = resources.buffer(device, "Contagion Health Buffer", size, usages);
+    let next_health = resources.buffer(device, "Contagion Next Health Buffer", size, usages);
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                health.as_entire_binding(),
+                next_health.as_entire_binding(),
+                resources.dyes.as_entire_binding(),
+                counts.as_entire_binding(),
+            ],
+        )
+    });
+    (health, next_health, bind_groups)
+}
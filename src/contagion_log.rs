@@ -0,0 +1,113 @@
+use nannou::wgpu::{self, BufferUsages};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::contagion::{Contagion, Outbreak};
+use crate::resources::GpuResources;
+
+// Frames between rows
+const INTERVAL: u64 = 10;
+
+/// Writes the `Contagion`'s counts to a CSV file as the outbreak runs, a row every
+/// `INTERVAL` frames with the frame and simulated time.
+///
+/// The counts are copied out and mapped asynchronously like the pressure gauge's, so rows
+/// arrive a frame or two late but never stall rendering.
+pub struct ContagionLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    staging: wgpu::Buffer,
+    // Frame of the counts in flight
+    pending: Option<u64>,
+    mapped: Arc<AtomicBool>,
+}
+
+impl ContagionLog {
+    pub fn create(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        path: &Path,
+    ) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "frame,time,susceptible,infected,recovered")?;
+        info!(
+            "Logging the outbreak to {} every {} frames",
+            path.display(),
+            INTERVAL
+        );
+        let staging = resources.buffer(
+            device,
+            "Contagion Counts Readback Buffer",
+            3 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        );
+        Ok(ContagionLog {
+            path: path.to_owned(),
+            writer,
+            staging,
+            pending: None,
+            mapped: Arc::default(),
+        })
+    }
+
+    /// Copy out the counts of the frame just encoded when it's due a row. Returns whether a
+    /// copy was encoded, in which case call `map` after submitting.
+    pub fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        contagion: &Contagion,
+        frame: u64,
+    ) -> bool {
+        if !frame.is_multiple_of(INTERVAL) || self.pending.is_some() {
+            return false;
+        }
+        encoder.copy_buffer_to_buffer(contagion.counts_buffer(), 0, &self.staging, 0, 12);
+        self.pending = Some(frame);
+        true
+    }
+
+    pub fn map(&self) {
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("Failed to map the outbreak's counts: {}", err),
+            });
+    }
+
+    /// Write the row in flight if its counts have arrived.
+    pub fn poll(&mut self) {
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let Some(frame) = self.pending.take() else {
+            return;
+        };
+        let outbreak = {
+            let data = self.staging.slice(..).get_mapped_range();
+            Outbreak::from_counts(bytemuck::pod_read_unaligned(&data))
+        };
+        self.staging.unmap();
+
+        let time = frame as f32 / 60.0;
+        let row = format!(
+            "{frame},{time},{},{},{}",
+            outbreak.susceptible, outbreak.infected, outbreak.recovered
+        );
+        if let Err(err) = writeln!(self.writer, "{row}") {
+            error!("Failed to write to {}: {}", self.path.display(), err);
+        }
+    }
+
+    pub fn finish(mut self) {
+        match self.writer.flush() {
+            Ok(()) => info!("Saved the outbreak to {}", self.path.display()),
+            Err(err) => error!("Failed to save {}: {}", self.path.display(), err),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, ShaderStages};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+wgsl_struct! {
+    // Must match `CrossfadeParams` in crossfade_shader.wgsl
+    struct CrossfadeParams {
+        opacity: f32,
+    }
+}
+
+// What a window's outgoing scene is drawn into, sized to it
+struct Targets {
+    size: [u32; 2],
+    // The scene is drawn into this when multisampled, resolved into `view`
+    multisampled: Option<wgpu::TextureView>,
+    view: wgpu::TextureView,
+    // Sampling `view`
+    source: wgpu::BindGroup,
+}
+
+/// Blends between two scenes when switching, so performances move between setups without a
+/// hard cut. Both keep running while it's under way: the scene switched away from is drawn
+/// into a texture of its own and over the one taking over, fading out as it goes.
+pub struct Crossfade {
+    // When it started and how long it takes, while it's under way
+    started: Option<(Instant, Duration)>,
+    sample_count: u32,
+    sampler: wgpu::Sampler,
+    bindings: BindingLayout,
+    pipeline: wgpu::RenderPipeline,
+    params_buffer: UniformBuffer<CrossfadeParams>,
+    // Made on a window's first crossfade and whenever its size changes, indexed by
+    // `is_output`. Not tracked, as they're made while drawing
+    targets: [RefCell<Option<Targets>>; 2],
+}
+
+impl Crossfade {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, sample_count: u32) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "crossfade_shader",
+            include_str!("./shaders/crossfade_shader.wgsl"),
+        );
+        let sampler = wgpu::SamplerBuilder::new()
+            .address_mode(wgpu::AddressMode::ClampToEdge)
+            .label(Some("Crossfade Sampler"))
+            .build(device);
+        let bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .texture(0)
+            .sampler(1)
+            .uniform(2)
+            .build(device, "Crossfade");
+        let params_buffer = UniformBuffer::new(device, resources, "Crossfade Params Buffer");
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Crossfade Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Crossfade Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Crossfade Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: Frame::TEXTURE_FORMAT,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        Crossfade {
+            started: None,
+            sample_count,
+            sampler,
+            bindings,
+            pipeline,
+            params_buffer,
+            targets: Default::default(),
+        }
+    }
+
+    /// Start blending away from the outgoing scene, gone after `seconds`.
+    pub fn start(&mut self, seconds: f32) {
+        self.started = Some((Instant::now(), Duration::from_secs_f32(seconds.max(0.0))));
+    }
+
+    /// Whether the outgoing scene has faded out completely.
+    pub fn done(&self) -> bool {
+        self.opacity() <= 0.0
+    }
+
+    // How much of the outgoing scene shows, eased so it doesn't start or stop abruptly
+    fn opacity(&self) -> f32 {
+        let Some((started, duration)) = self.started else {
+            return 0.0;
+        };
+        let progress = if duration.is_zero() {
+            1.0
+        } else {
+            (started.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0)
+        };
+        1.0 - progress * progress * (3.0 - 2.0 * progress)
+    }
+
+    /// Draw the outgoing scene over `view`, the frame the scene taking over was drawn into.
+    /// `draw` encodes the outgoing scene into the given attachment, cleared to `clear`. Each
+    /// window keeps targets of its own, told apart by `is_output` as in `render_scene`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: [u32; 2],
+        is_output: bool,
+        clear: wgpu::Color,
+        draw: impl FnOnce(&mut wgpu::CommandEncoder, wgpu::RenderPassColorAttachment),
+    ) {
+        let opacity = self.opacity();
+        if opacity <= 0.0 {
+            return;
+        }
+        let mut targets = self.targets[is_output as usize].borrow_mut();
+        if !matches!(&*targets, Some(targets) if targets.size == size) {
+            *targets = Some(self.targets(device, size));
+        }
+        let targets = targets.as_ref().unwrap();
+
+        let (target, resolve_target) = match &targets.multisampled {
+            Some(multisampled) => (multisampled, Some(&*targets.view)),
+            None => (&targets.view, None),
+        };
+        draw(
+            encoder,
+            wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: true,
+                },
+            },
+        );
+
+        self.params_buffer
+            .write(queue, &CrossfadeParams { opacity });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Crossfade Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &targets.source, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn targets(&self, device: &wgpu::Device, size: [u32; 2]) -> Targets {
+        let target = |samples: u32, usage: wgpu::TextureUsages| {
+            wgpu::TextureBuilder::new()
+                .size(size)
+                .format(Frame::TEXTURE_FORMAT)
+                .sample_count(samples)
+                .usage(usage)
+                .build(device)
+        };
+        let multisampled = (self.sample_count > 1).then(|| {
+            target(self.sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT)
+                .view()
+                .build()
+        });
+        let view = target(
+            1,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        )
+        .view()
+        .build();
+        let source = self.bindings.bind_group(
+            device,
+            &[
+                wgpu::BindingResource::TextureView(&view),
+                wgpu::BindingResource::Sampler(&self.sampler),
+                self.params_buffer.binding(),
+            ],
+        );
+        Targets {
+            size,
+            multisampled,
+            view,
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use particle_nannou::{gpu, headless};
+
+    // Pixels across the square frame, 256 bytes a row of half float RGBA so it reads back
+    // unpadded
+    const SIZE: u32 = 32;
+
+    fn half(bits: u16) -> f32 {
+        let mantissa = (bits & 0x3ff) as f32;
+        match (bits >> 10) & 0x1f {
+            0 => mantissa * 2f32.powi(-24),
+            exponent => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent as i32 - 15),
+        }
+    }
+
+    // A red frame with a blue outgoing scene crossfaded over it, as the frame's first pixel
+    fn crossfaded(crossfade: &Crossfade, device: &wgpu::Device, queue: &wgpu::Queue) -> [f32; 4] {
+        let texture = wgpu::TextureBuilder::new()
+            .size([SIZE, SIZE])
+            .format(Frame::TEXTURE_FORMAT)
+            .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC)
+            .build(device);
+        let view = texture.view().build();
+        let bytes = (SIZE * SIZE * 8) as u64;
+        let pixels = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Crossfade Test Pixels"),
+            size: bytes,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Crossfade Test Encoder"),
+        });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Crossfade Test Frame Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let blue = wgpu::Color::BLUE;
+        crossfade.encode(
+            device,
+            queue,
+            &mut encoder,
+            &view,
+            [SIZE, SIZE],
+            false,
+            blue,
+            |encoder, attachment| {
+                // Just the clear, as an empty scene
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Crossfade Test Outgoing Pass"),
+                    color_attachments: &[Some(attachment)],
+                    depth_stencil_attachment: None,
+                });
+            },
+        );
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &pixels,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 8),
+                    rows_per_image: None,
+                },
+            },
+            texture.extent(),
+        );
+        queue.submit(Some(encoder.finish()));
+        let pixels = gpu::read_buffer::<u16>(device, queue, &pixels, 4);
+        [0, 1, 2, 3].map(|channel| half(pixels[channel]))
+    }
+
+    #[test]
+    fn blends_the_outgoing_scene_over_the_frame() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        let mut resources = GpuResources::new(&device, &[]);
+        let mut crossfade = Crossfade::new(&device, &mut resources, 1);
+        let close = |a: [f32; 4], b: [f32; 4]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.01);
+
+        // Not started, so only the frame shows
+        assert!(crossfade.done());
+        let pixel = crossfaded(&crossfade, &device, &queue);
+        assert!(close(pixel, [1.0, 0.0, 0.0, 1.0]), "{pixel:?}");
+
+        // Half way through, eased or not
+        let duration = Duration::from_secs(1000);
+        crossfade.started = Some((Instant::now() - duration / 2, duration));
+        assert!(!crossfade.done());
+        let pixel = crossfaded(&crossfade, &device, &queue);
+        assert!(close(pixel, [0.5, 0.0, 0.5, 1.0]), "{pixel:?}");
+
+        // Just started, all the outgoing scene
+        crossfade.start(1000.0);
+        let pixel = crossfaded(&crossfade, &device, &queue);
+        assert!(close(pixel, [0.0, 0.0, 1.0, 1.0]), "{pixel:?}");
+
+        crossfade.start(0.0);
+        assert!(crossfade.done());
+    }
+}
@@ -0,0 +1,136 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use particle_nannou::compaction::Compactor;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+
+// Vertices per boid triangle
+const VERTEX_COUNT: u32 = 3;
+
+/// Compacts on-screen particles into a separate buffer drawn with `draw_indirect`, so
+/// rendering cost scales with what's visible rather than the total particle count.
+///
+/// Whole particles are copied rather than indices, so the compacted buffer feeds the same
+/// instanced vertex layout as the main particle buffer.
+pub struct Culler {
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    // One per particle buffer, indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    visible_buffer: wgpu::Buffer,
+    draw_args_buffer: wgpu::Buffer,
+}
+
+impl Culler {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "cull_shader",
+            include_str!("./shaders/cull_shader.wgsl"),
+        );
+
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_rw(1)
+            .storage_rw(2)
+            .uniform(3)
+            .uniform(4)
+            .storage_ro(5)
+            .build(device, "Cull");
+        let (visible_buffer, draw_args_buffer, bind_groups) = bind(device, resources, &bindings);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = diagnostics::checked(device, "Cull Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Cull Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "cull",
+            })
+        });
+
+        Culler {
+            pipeline,
+            bindings,
+            bind_groups,
+            visible_buffer,
+            draw_args_buffer,
+        }
+    }
+
+    /// Resize for and rebind to a new particle buffer.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.visible_buffer, self.draw_args_buffer, self.bind_groups) =
+            bind(device, resources, &self.bindings);
+    }
+
+    /// Reset the visible count and encode the culling pass. Run after the simulation step,
+    /// with the compactor if there is one so only its survivors are looked at.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        count: u32,
+        compactor: Option<&Compactor>,
+    ) {
+        let draw_args: [u32; 4] = [VERTEX_COUNT, 0, 0, 0];
+        queue.write_buffer(&self.draw_args_buffer, 0, bytemuck::cast_slice(&draw_args));
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Cull Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        match compactor {
+            Some(compactor) => compactor.dispatch(&mut compute_pass),
+            None => compute_pass.dispatch_workgroups((count as f32 / 256.0).ceil() as u32, 1, 1),
+        }
+    }
+
+    /// Draw the particles that survived the last culling pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.visible_buffer.slice(..));
+        render_pass.draw_indirect(&self.draw_args_buffer, 0);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+) -> (wgpu::Buffer, wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let visible_buffer = resources.buffer(
+        device,
+        "Visible Particle Buffer",
+        resources.particles().size(),
+        BufferUsages::STORAGE | BufferUsages::VERTEX,
+    );
+    let draw_args_buffer = resources.buffer(
+        device,
+        "Cull Draw Args Buffer",
+        4 * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                visible_buffer.as_entire_binding(),
+                draw_args_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                resources.camera.as_entire_binding(),
+                resources.flags.as_entire_binding(),
+            ],
+        )
+    });
+    (visible_buffer, draw_args_buffer, bind_groups)
+}
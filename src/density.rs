@@ -0,0 +1,302 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+// Cells per side of the screen-space density grid. Must match the shaders.
+const GRID_SIZE: u32 = 512;
+// Height of the light above the field, in the same units as the screen's -1..1
+const LIGHT_HEIGHT: f32 = 0.6;
+// Bins of the cells' log2(1 + count) auto exposure equalizes. Must match the shaders.
+const EXPOSURE_BINS: usize = 64;
+// Octaves of count the bins span, up to 65535 particles in a cell. Must match the shaders.
+const LOG_RANGE: f32 = 16.0;
+// Where the fixed exposure puts full brightness, which auto exposure starts from. Must match
+// FULL_DENSITY in density_shader.wgsl
+const FULL_DENSITY: f32 = 64.0;
+
+wgsl_struct! {
+    // Must match `ShadingParams` in density_shader.wgsl
+    struct ShadingParams {
+        light: [f32; 3],
+        fluid: u32,
+        auto_exposure: u32,
+    }
+}
+
+/// Renders particles as a density field instead of individual boids, for when they're too
+/// small on screen to make out.
+///
+/// A compute pass counts the particles in each cell of a screen-space grid, then a full-screen
+/// pass shades the counts. The cost of drawing no longer depends on the particle count.
+///
+/// With `fluid` set, the counts are shaded as the height of a liquid surface instead, lit
+/// from `light`.
+///
+/// With `auto_exposure` set, the occupied cells are binned by count each frame and the
+/// brightness each count is shaded with eases towards the histogram equalized, so the field
+/// stays well exposed whether the flock is spread thin or packed thousands to a cell.
+pub struct DensitySplat {
+    pub fluid: bool,
+    pub auto_exposure: bool,
+    // Where the light sits over the screen, -1..1 on each axis
+    pub light: Vec2,
+    splat_pipeline: wgpu::ComputePipeline,
+    splat_bindings: BindingLayout,
+    // One per particle buffer, indexed like `GpuResources::particle_buffers`
+    splat_bind_groups: [wgpu::BindGroup; 2],
+    render_pipeline: wgpu::RenderPipeline,
+    render_bind_group: wgpu::BindGroup,
+    grid_buffer: wgpu::Buffer,
+    shading_buffer: UniformBuffer<ShadingParams>,
+    count_pipeline: wgpu::ComputePipeline,
+    adapt_pipeline: wgpu::ComputePipeline,
+    exposure_bind_group: wgpu::BindGroup,
+}
+
+impl DensitySplat {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let splat_shader = diagnostics::shader(
+            device,
+            "splat_shader",
+            include_str!("./shaders/splat_shader.wgsl"),
+        );
+        let density_shader = diagnostics::shader(
+            device,
+            "density_shader",
+            include_str!("./shaders/density_shader.wgsl"),
+        );
+
+        let grid_buffer = resources.buffer(
+            device,
+            "Density Grid Buffer",
+            (GRID_SIZE * GRID_SIZE) as wgpu::BufferAddress
+                * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        );
+
+        let splat_bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_rw(1)
+            .uniform(2)
+            .uniform(3)
+            .build(device, "Splat");
+        let splat_bind_groups = bind_splat(device, resources, &splat_bindings, &grid_buffer);
+
+        let splat_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Splat Pipeline Layout"),
+                bind_group_layouts: &[splat_bindings.layout()],
+                push_constant_ranges: &[],
+            });
+
+        let splat_pipeline = diagnostics::checked(device, "Splat Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Splat Pipeline"),
+                layout: Some(&splat_pipeline_layout),
+                module: &splat_shader,
+                entry_point: "splat",
+            })
+        });
+
+        let exposure_shader = diagnostics::shader(
+            device,
+            "exposure_shader",
+            include_str!("./shaders/exposure_shader.wgsl"),
+        );
+        let bins_buffer = resources.buffer(
+            device,
+            "Exposure Bins Buffer",
+            (EXPOSURE_BINS * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            BufferUsages::STORAGE,
+        );
+        let curve_buffer = resources.buffer_init(
+            device,
+            "Exposure Curve Buffer",
+            bytemuck::cast_slice(&fixed_curve()),
+            BufferUsages::STORAGE,
+        );
+        let exposure_bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_rw(1)
+            .storage_rw(2)
+            .build(device, "Exposure");
+        let exposure_bind_group = exposure_bindings.bind_group(
+            device,
+            &[
+                grid_buffer.as_entire_binding(),
+                bins_buffer.as_entire_binding(),
+                curve_buffer.as_entire_binding(),
+            ],
+        );
+        let exposure_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Exposure Pipeline Layout"),
+                bind_group_layouts: &[exposure_bindings.layout()],
+                push_constant_ranges: &[],
+            });
+        let exposure_pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Exposure Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Exposure Pipeline"),
+                        layout: Some(&exposure_pipeline_layout),
+                        module: &exposure_shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        let shading_buffer = UniformBuffer::new(device, resources, "Shading Params Buffer");
+        let render_bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_ro(2)
+            .build(device, "Density");
+        let render_bind_group = render_bindings.bind_group(
+            device,
+            &[
+                grid_buffer.as_entire_binding(),
+                shading_buffer.binding(),
+                curve_buffer.as_entire_binding(),
+            ],
+        );
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Density Pipeline Layout"),
+                bind_group_layouts: &[render_bindings.layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = diagnostics::checked(device, "Density Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Density Pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &density_shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &density_shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        DensitySplat {
+            fluid: false,
+            auto_exposure: false,
+            light: vec2(-0.5, 0.5),
+            splat_pipeline,
+            splat_bindings,
+            splat_bind_groups,
+            render_pipeline,
+            render_bind_group,
+            grid_buffer,
+            shading_buffer,
+            count_pipeline: exposure_pipeline("count"),
+            adapt_pipeline: exposure_pipeline("adapt"),
+            exposure_bind_group,
+        }
+    }
+
+    /// Rebind to a new particle buffer.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &GpuResources) {
+        self.splat_bind_groups =
+            bind_splat(device, resources, &self.splat_bindings, &self.grid_buffer);
+    }
+
+    /// Clear the grid and encode the splat pass. Run after the simulation step.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        count: u32,
+    ) {
+        let shading = ShadingParams {
+            light: self.light.extend(LIGHT_HEIGHT).to_array(),
+            fluid: self.fluid as u32,
+            auto_exposure: self.auto_exposure as u32,
+        };
+        self.shading_buffer.write(queue, &shading);
+        encoder.clear_buffer(&self.grid_buffer, 0, None);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Splat Pass"),
+        });
+        compute_pass.set_pipeline(&self.splat_pipeline);
+        compute_pass.set_bind_group(0, &self.splat_bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups((count as f32 / 256.0).ceil() as u32, 1, 1);
+
+        // Adapts only while shown, from wherever it had got to
+        if self.auto_exposure {
+            compute_pass.set_bind_group(0, &self.exposure_bind_group, &[]);
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch_workgroups((GRID_SIZE * GRID_SIZE).div_ceil(256), 1, 1);
+            compute_pass.set_pipeline(&self.adapt_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+    }
+
+    /// Shade the density grid from the last splat pass over the whole frame.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// The fixed exposure's brightness at the bottom of each bin, for auto exposure to start from
+fn fixed_curve() -> [f32; EXPOSURE_BINS] {
+    std::array::from_fn(|bin| {
+        let octaves = bin as f32 / EXPOSURE_BINS as f32 * LOG_RANGE;
+        (octaves / (1.0 + FULL_DENSITY).log2()).min(1.0)
+    })
+}
+
+fn bind_splat(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    grid_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                grid_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                resources.camera.as_entire_binding(),
+            ],
+        )
+    })
+}
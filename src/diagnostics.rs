@@ -0,0 +1,113 @@
+use nannou::wgpu;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{error, info};
+
+use crate::wgsl;
+
+/// Set to a directory to dump every compiled shader, the device limits and any GPU
+/// validation errors there, for attaching to bug reports.
+const DUMP_ENV: &str = "PARTICLES_GPU_DUMP";
+
+fn dump_dir() -> Option<&'static Path> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = PathBuf::from(std::env::var_os(DUMP_ENV)?);
+        match fs::create_dir_all(&dir) {
+            Ok(()) => {
+                info!("Dumping GPU diagnostics to {}", dir.display());
+                Some(dir)
+            }
+            Err(err) => {
+                error!("Failed to create {} {}: {}", DUMP_ENV, dir.display(), err);
+                None
+            }
+        }
+    })
+    .as_deref()
+}
+
+fn dump(file_name: &str, contents: &str, append: bool) {
+    let Some(dir) = dump_dir() else {
+        return;
+    };
+    let path = dir.join(file_name);
+    let result = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{contents}"));
+    if let Err(err) = result {
+        error!("Failed to write {}: {}", path.display(), err);
+    }
+}
+
+/// Record the device limits and features, if dumping is enabled.
+pub fn dump_device(device: &wgpu::Device) {
+    if dump_dir().is_some() {
+        let info = format!(
+            "Features: {:?}\n\nLimits: {:#?}",
+            device.features(),
+            device.limits()
+        );
+        dump("device.txt", &info, false);
+    }
+}
+
+/// Run `f` inside a validation error scope, panicking with a message that says what was
+/// being created if wgpu rejects it.
+pub fn checked<T>(device: &wgpu::Device, what: &str, f: impl FnOnce() -> T) -> T {
+    try_checked(device, what, f).unwrap_or_else(|message| panic!("{message}"))
+}
+
+/// Like `checked`, but returns the message rather than panicking, for what the app can
+/// carry on without. What `f` made is invalid then and mustn't be used.
+pub fn try_checked<T>(
+    device: &wgpu::Device,
+    what: &str,
+    f: impl FnOnce() -> T,
+) -> Result<T, String> {
+    let _span = tracing::debug_span!("create", what).entered();
+    device.push_error_scope(wgpu_upstream::ErrorFilter::Validation);
+    let value = f();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(err) => {
+            let message = format!("GPU validation failed creating {what}: {err}");
+            dump("errors.log", &message, true);
+            Err(message)
+        }
+        None => Ok(value),
+    }
+}
+
+/// Compile a WGSL shader, resolving its includes with `wgsl::compose`. `name` identifies it
+/// in error messages and names its dump file.
+pub fn shader(device: &wgpu::Device, name: &str, source: &str) -> wgpu::ShaderModule {
+    try_shader(device, name, source).unwrap_or_else(|message| panic!("{message}"))
+}
+
+/// Like `shader`, but returns the error, naga's for WGSL that doesn't compile, rather than
+/// panicking.
+pub fn try_shader(
+    device: &wgpu::Device,
+    name: &str,
+    source: &str,
+) -> Result<wgpu::ShaderModule, String> {
+    let source = wgsl::compose(source).map_err(|err| {
+        let message = format!("Failed to compose shader {name}: {err}");
+        dump("errors.log", &message, true);
+        message
+    })?;
+    let source = source.as_str();
+    dump(&format!("{name}.wgsl"), source, false);
+    try_checked(device, &format!("shader {name}"), || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
+    })
+}
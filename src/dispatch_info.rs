@@ -0,0 +1,82 @@
+use nannou::prelude::*;
+use nannou::wgpu;
+use tracing::info;
+
+use crate::resources::GpuResources;
+use crate::sim_variant::SimVariant;
+use crate::theme::Hud;
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// How the simulation is dispatched and what's allocated, printed on startup and shown in the
+/// debug view (F4) so perf reports can include it.
+pub fn lines(
+    device: &wgpu::Device,
+    variant: &SimVariant,
+    particle_count: u32,
+    substeps: u32,
+    resources: &GpuResources,
+) -> Vec<String> {
+    let limits = device.limits();
+    let workgroups = variant.workgroups(particle_count);
+    // Invocations past the particle count return straight away
+    let occupancy = particle_count as f64 / (workgroups * variant.workgroup_size) as f64;
+    let mut lines = vec![
+        format!("Simulation variant: {}", variant.name()),
+        format!(
+            "Dispatch: {} workgroups of {} x {} substeps for {} particles ({:.1}% of invocations used)",
+            workgroups,
+            variant.workgroup_size,
+            substeps,
+            particle_count,
+            occupancy * 100.0
+        ),
+        format!(
+            "GPU memory: {:.1} MiB",
+            resources.total_bytes() as f64 / MIB
+        ),
+    ];
+    lines.extend(
+        resources
+            .allocations()
+            .map(|(label, bytes)| format!("  {}: {:.2} MiB", label, bytes as f64 / MIB)),
+    );
+    lines.extend([
+        format!(
+            "Limits: {} invocations per workgroup, {} workgroups per dimension",
+            limits.max_compute_invocations_per_workgroup,
+            limits.max_compute_workgroups_per_dimension
+        ),
+        format!(
+            "Limits: {:.0} MiB storage binding, {:.0} MiB buffer, {} texture size",
+            limits.max_storage_buffer_binding_size as f64 / MIB,
+            limits.max_buffer_size as f64 / MIB,
+            limits.max_texture_dimension_2d
+        ),
+    ]);
+    lines
+}
+
+pub fn print(lines: &[String]) {
+    for line in lines {
+        info!("{}", line);
+    }
+}
+
+/// Draw the lines in the top right corner of the window.
+pub fn draw(draw: &Draw, window: Rect, hud: Hud, lines: &[String]) {
+    const LINE_HEIGHT: f32 = 14.0;
+    const WIDTH: f32 = 520.0;
+    let area = Rect::from_w_h(WIDTH, LINE_HEIGHT * lines.len() as f32 + 8.0)
+        .top_right_of(window.pad(10.0));
+    draw.rect().xy(area.xy()).wh(area.wh()).color(hud.panel);
+    let text = area.pad(4.0);
+    draw.text(&lines.join("\n"))
+        .xy(text.xy())
+        .wh(text.wh())
+        .left_justify()
+        .align_text_top()
+        .line_spacing(2.0)
+        .font_size(11)
+        .color(hud.text);
+}
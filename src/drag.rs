@@ -0,0 +1,153 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Air resistance: a linear term for slow, viscous motion and a quadratic one, growing with
+/// speed, for fast motion. Together they give anything pushed by a steady force a terminal
+/// velocity, where the drag matches the force.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DragConfig {
+    // Fraction of the velocity lost each frame
+    pub linear: f32,
+    // Fraction of the velocity lost each frame per domain unit per frame of speed
+    pub quadratic: f32,
+}
+
+impl Default for DragConfig {
+    fn default() -> Self {
+        DragConfig {
+            linear: 0.01,
+            quadratic: 1.0,
+        }
+    }
+}
+
+/// Parse `linear,quadratic`, e.g. `0.01,1`.
+pub fn parse_drag(s: &str) -> Result<DragConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid drag `{s}`"))?;
+    match values[..] {
+        [linear, quadratic] if linear >= 0.0 && quadratic >= 0.0 => {
+            Ok(DragConfig { linear, quadratic })
+        }
+        _ => Err(format!("expected drag like 0.01,1, got `{s}`")),
+    }
+}
+
+wgsl_struct! {
+    // Must match `DragParams` in drag_shader.wgsl
+    struct DragParams {
+        linear: f32,
+        quadratic: f32,
+    }
+}
+
+/// Slows every particle in place after the integrating stage and the forces, heavier ones
+/// less, so modes without the boids' speed cap don't speed up forever.
+pub struct Drag {
+    pub config: DragConfig,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<DragParams>,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Drag {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: DragConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "drag_shader",
+            include_str!("./shaders/drag_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .build(device, "Drag");
+        let params_buffer = UniformBuffer::new(device, resources, "Drag Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Drag Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Drag Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Drag Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "step",
+            })
+        });
+
+        Drag {
+            config,
+            pipeline,
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for Drag {
+    fn kind(&self) -> StageKind {
+        StageKind::Drag
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = DragParams {
+            linear: self.config.linear.max(0.0),
+            quadratic: self.config.quadratic.max(0.0),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Drag Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<DragParams>,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,239 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+const CELL_WORKGROUP_SIZE: u32 = 16;
+
+/// Most emitters there can be, the size of the array in emitters_shader.wgsl.
+pub const MAX_EMITTERS: usize = 8;
+
+// Red, green, blue and count sums per cell, see `deposit` in emitters_shader.wgsl
+const CELL_SIZE: wgpu::BufferAddress = 16;
+
+/// Sources that dye the particles passing through them, each in its own colour, so the way
+/// the flock stirs them together shows like dye in a fluid. Drawn with
+/// `ColorMode::Emitter`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmittersConfig {
+    // Where each emitter is, in domain units. Particles start dyed by the nearest
+    pub positions: Vec<[f32; 2]>,
+    // Distance from an emitter inside which particles take its colour
+    pub radius: f32,
+    // Fraction of each particle's colour swapped for the average of those around it each
+    // frame, 0 to keep the colour of the emitter it last passed
+    pub mixing: f32,
+    // Cells along each side of the grid colours are averaged over
+    pub grid: u32,
+}
+
+impl Default for EmittersConfig {
+    fn default() -> Self {
+        EmittersConfig {
+            positions: Vec::new(),
+            radius: 0.1,
+            mixing: 0.0,
+            grid: 64,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `EmittersParams` in emitters_shader.wgsl
+    struct EmittersParams {
+        particle_count: u32,
+        emitters: u32,
+        radius: f32,
+        mixing: f32,
+        grid: u32,
+    }
+}
+
+/// Tags each particle with the emitter that last dyed it, and its colour, in
+/// `GpuResources::dyes`, indexed like the particles, so compaction is left out with it.
+/// Particles all start dyed, by the nearest emitter, and again when the buffers are resized.
+pub struct Emitters {
+    pub config: EmittersConfig,
+    init: wgpu::ComputePipeline,
+    deposit: wgpu::ComputePipeline,
+    blend: wgpu::ComputePipeline,
+    clear: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<EmittersParams>,
+    // x, y and hue of each emitter, the last component is unused
+    emitter_buffer: wgpu::Buffer,
+    cells: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the dyes have been drawn
+    needs_init: bool,
+}
+
+impl Emitters {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: EmittersConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "emitters_shader",
+            include_str!("./shaders/emitters_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .build(device, "Emitters");
+        let params_buffer = UniformBuffer::new(device, resources, "Emitters Params Buffer");
+        let emitter_buffer =
+            resources.uniform::<[[f32; 4]; MAX_EMITTERS]>(device, "Emitters Positions Buffer");
+        let grid = config.grid.max(1) as wgpu::BufferAddress;
+        let cells = resources.buffer(
+            device,
+            "Emitters Cells Buffer",
+            grid * grid * CELL_SIZE,
+            BufferUsages::STORAGE,
+        );
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &emitter_buffer,
+            &cells,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Emitters Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Emitters Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Emitters Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Emitters {
+            config,
+            init: pipeline("init"),
+            deposit: pipeline("deposit"),
+            blend: pipeline("blend"),
+            clear: pipeline("clear"),
+            bindings,
+            params_buffer,
+            emitter_buffer,
+            cells,
+            bind_groups,
+            needs_init: true,
+        }
+    }
+}
+
+impl Stage for Emitters {
+    fn kind(&self) -> StageKind {
+        StageKind::Emitters
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let positions = &self.config.positions[..self.config.positions.len().min(MAX_EMITTERS)];
+        // Evenly round the palette, so however many there are they're told apart
+        let mut emitters = [[0.0; 4]; MAX_EMITTERS];
+        for (i, position) in positions.iter().enumerate() {
+            let hue = i as f32 / positions.len() as f32;
+            emitters[i] = [position[0], position[1], hue, 0.0];
+        }
+        frame
+            .queue
+            .write_buffer(&self.emitter_buffer, 0, bytemuck::cast_slice(&emitters));
+        let grid = self.config.grid.max(1);
+        let params = EmittersParams {
+            particle_count: frame.particle_count,
+            emitters: positions.len() as u32,
+            radius: self.config.radius.max(0.0),
+            mixing: self.config.mixing.clamp(0.0, 1.0),
+            grid,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Emitters Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        let particle_workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        if std::mem::take(&mut self.needs_init) {
+            compute_pass.set_pipeline(&self.init);
+            compute_pass.dispatch_workgroups(resources.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        // Without mixing there's nothing to average
+        if params.mixing > 0.0 {
+            compute_pass.set_pipeline(&self.deposit);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+        }
+        compute_pass.set_pipeline(&self.blend);
+        compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+        if params.mixing > 0.0 {
+            let cell_workgroups = grid.div_ceil(CELL_WORKGROUP_SIZE);
+            compute_pass.set_pipeline(&self.clear);
+            compute_pass.dispatch_workgroups(cell_workgroups, cell_workgroups, 1);
+        }
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.emitter_buffer,
+            &self.cells,
+        );
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<EmittersParams>,
+    emitter_buffer: &wgpu::Buffer,
+    cells: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                emitter_buffer.as_entire_binding(),
+                resources.dyes.as_entire_binding(),
+                cells.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,159 @@
+//! A C interface to `ParticleSystem`, for embedding the simulation in hosts that can load a
+//! shared library but not link Rust, e.g. a Unity native plugin or a Max external. The
+//! functions and types match include/particle_system.h.
+//!
+//! Every function taking a system pointer expects one returned by `particle_system_create`
+//! and not yet passed to `particle_system_destroy`, used from one thread at a time.
+
+use std::ptr;
+
+use crate::impulses::Impulse;
+use crate::particle_system::ParticleSystem;
+use crate::Particle;
+
+/// `ParticleSystemParams` in particle_system.h: the rule weights and speed limits, see
+/// `RuleWeights` and `SpeedLimits`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ParticleSystemParams {
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+    pub max_speed: f32,
+    pub min_speed: f32,
+    pub speed_decay: f32,
+}
+
+/// A new system of `particle_count` particles scattered from `seed`, or null when there's no
+/// GPU adapter. Free it with `particle_system_destroy`.
+#[no_mangle]
+pub extern "C" fn particle_system_create(particle_count: u32, seed: u64) -> *mut ParticleSystem {
+    match ParticleSystem::new(particle_count, seed) {
+        Some(system) => Box::into_raw(Box::new(system)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `system` is null or a live system, which isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_destroy(system: *mut ParticleSystem) {
+    if !system.is_null() {
+        drop(Box::from_raw(system));
+    }
+}
+
+/// Advance `frames` frames on the GPU, without waiting for them.
+///
+/// # Safety
+///
+/// `system` is a live system.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_step(system: *mut ParticleSystem, frames: u32) {
+    if let Some(system) = system.as_mut() {
+        system.step(frames);
+    }
+}
+
+/// # Safety
+///
+/// `system` is a live system.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_count(system: *const ParticleSystem) -> u32 {
+    system.as_ref().map_or(0, ParticleSystem::particle_count)
+}
+
+/// Copy up to `capacity` particles into `out`, waiting for the steps so far, and return how
+/// many were copied.
+///
+/// # Safety
+///
+/// `system` is a live system and `out` has room for `capacity` particles.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_read(
+    system: *const ParticleSystem,
+    out: *mut Particle,
+    capacity: usize,
+) -> usize {
+    let Some(system) = system.as_ref() else {
+        return 0;
+    };
+    if out.is_null() || capacity == 0 {
+        return 0;
+    }
+    let particles = system.particles();
+    let count = particles.len().min(capacity);
+    ptr::copy_nonoverlapping(particles.as_ptr(), out, count);
+    count
+}
+
+/// Write the current parameters into `params`.
+///
+/// # Safety
+///
+/// `system` is a live system and `params` points to a `ParticleSystemParams`.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_get_params(
+    system: *const ParticleSystem,
+    params: *mut ParticleSystemParams,
+) {
+    let (Some(system), Some(params)) = (system.as_ref(), params.as_mut()) else {
+        return;
+    };
+    let current = system.params();
+    *params = ParticleSystemParams {
+        alignment: current.weights.alignment,
+        cohesion: current.weights.cohesion,
+        separation: current.weights.separation,
+        max_speed: current.speed_limits.max,
+        min_speed: current.speed_limits.min,
+        speed_decay: current.speed_limits.decay,
+    };
+}
+
+/// Change the parameters from the next step.
+///
+/// # Safety
+///
+/// `system` is a live system and `params` points to a `ParticleSystemParams`.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_set_params(
+    system: *mut ParticleSystem,
+    params: *const ParticleSystemParams,
+) {
+    let (Some(system), Some(params)) = (system.as_mut(), params.as_ref()) else {
+        return;
+    };
+    let mut next = system.params();
+    next.weights.alignment = params.alignment;
+    next.weights.cohesion = params.cohesion;
+    next.weights.separation = params.separation;
+    next.speed_limits.max = params.max_speed;
+    next.speed_limits.min = params.min_speed;
+    next.speed_limits.decay = params.speed_decay;
+    system.set_params(next);
+}
+
+/// Kick the particles within `radius` of (`x`, `y`) outwards once at the start of the next
+/// step, by up to `strength` at the centre, or inwards with a negative strength.
+///
+/// # Safety
+///
+/// `system` is a live system.
+#[no_mangle]
+pub unsafe extern "C" fn particle_system_impulse(
+    system: *mut ParticleSystem,
+    x: f32,
+    y: f32,
+    strength: f32,
+    radius: f32,
+) {
+    if let Some(system) = system.as_mut() {
+        system.impulse(Impulse {
+            position: [x, y],
+            strength,
+            radius,
+        });
+    }
+}
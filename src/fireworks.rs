@@ -0,0 +1,313 @@
+use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::lifetime::{Easing, Lifetime, LifetimeConfig};
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::trail::{Trail, TrailConfig};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most bursts that can go off in one frame, the size of the array in fireworks_shader.wgsl.
+/// Any more wait for the next frame.
+pub const MAX_BURSTS: usize = 16;
+
+/// Shells that burst into a ring of sparks, fall, slow down and fade, leaving trails.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FireworksConfig {
+    // Simulated seconds between bursts somewhere random, 0 for only clicks
+    pub interval: f32,
+    // Sparks in each burst, taken from the oldest
+    pub sparks: u32,
+    // Fastest spark, in domain units per frame
+    pub speed: f32,
+    // Taken off each spark's vertical velocity each frame
+    pub gravity: f32,
+    // Fraction of each spark's velocity lost each frame
+    pub drag: f32,
+}
+
+impl Default for FireworksConfig {
+    fn default() -> Self {
+        FireworksConfig {
+            interval: 0.8,
+            sparks: 4000,
+            speed: 0.012,
+            gravity: 0.00008,
+            drag: 0.02,
+        }
+    }
+}
+
+impl FireworksConfig {
+    /// Sparks that burn bright and go out quickly, and don't come back until a burst needs
+    /// them.
+    pub fn lifetime(&self) -> LifetimeConfig {
+        LifetimeConfig {
+            seconds: 2.0,
+            spread: 0.4,
+            fade: Easing::EaseIn,
+            shrink: Easing::EaseIn,
+            respawn: false,
+        }
+    }
+
+    /// Trails that fade fast and hardly spread, and that nothing follows.
+    pub fn trail(&self) -> TrailConfig {
+        TrailConfig {
+            size: 512,
+            deposit: 0.5,
+            diffusion: 0.1,
+            decay: 0.15,
+            follow: 0.0,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `FireworksParams` in fireworks_shader.wgsl
+    struct FireworksParams {
+        particle_count: u32,
+        frame: u32,
+        gravity: f32,
+        drag: f32,
+        speed: f32,
+        sparks: u32,
+        // Bursts this frame, and the first particle the first of them takes
+        bursts: u32,
+        next: u32,
+        // From the `LifetimeConfig`, for the new sparks' lifetimes
+        lifetime: f32,
+        spread: f32,
+    }
+}
+
+/// A fireworks display in place of the boids. Each burst brings the next `sparks` particles
+/// back to life at one point, flying outwards in a colour further round the palette than
+/// the last, then gravity and drag act on them as they age.
+///
+/// Owns the `Lifetime` that ages the sparks, which leaves dead ones alone, and a `Trail`
+/// they leave. Dead sparks are parked outside the domain so they leave no trail.
+pub struct Fireworks {
+    pub config: FireworksConfig,
+    lifetime: Lifetime,
+    trail: Trail,
+    step: wgpu::ComputePipeline,
+    burst: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<FireworksParams>,
+    // x, y, hue and spark speed scale of each burst this frame
+    burst_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Waiting to go off, in domain units, and how fast their sparks fly, as multiples of
+    // the top speed
+    pending: Vec<([f32; 2], f32)>,
+    // Bursts so far, for cycling the palette and seeding where the next goes off
+    count: u32,
+    // First particle the next burst takes
+    next: u32,
+    // `FrameContext::time` the next timed burst goes off at
+    next_time: f32,
+}
+
+impl Fireworks {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: FireworksConfig,
+    ) -> Self {
+        let lifetime = Lifetime::new(device, resources, config.lifetime());
+        let trail = Trail::new(device, resources, config.trail());
+        let shader = diagnostics::shader(
+            device,
+            "fireworks_shader",
+            include_str!("./shaders/fireworks_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Fireworks");
+        let params_buffer = UniformBuffer::new(device, resources, "Fireworks Params Buffer");
+        let burst_buffer =
+            resources.uniform::<[[f32; 4]; MAX_BURSTS]>(device, "Fireworks Burst Buffer");
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &burst_buffer,
+            lifetime.lives_buffer(),
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fireworks Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Fireworks Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Fireworks Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Fireworks {
+            config,
+            lifetime,
+            trail,
+            step: pipeline("step"),
+            burst: pipeline("burst"),
+            bindings,
+            params_buffer,
+            burst_buffer,
+            bind_groups,
+            pending: Vec::new(),
+            count: 0,
+            next: 0,
+            next_time: 0.0,
+        }
+    }
+
+    /// Set off a burst at `position` in domain units next frame, its sparks flying `scale`
+    /// times as fast as usual.
+    pub fn burst(&mut self, position: [f32; 2], scale: f32) {
+        self.pending.push((position, scale.max(0.0)));
+    }
+
+    pub fn lifetime(&self) -> &Lifetime {
+        &self.lifetime
+    }
+
+    pub fn trail(&self) -> &Trail {
+        &self.trail
+    }
+
+    // Somewhere in the upper part of the domain, the same for the same burst every run
+    fn timed_position(&self) -> [f32; 2] {
+        let mut rng = StdRng::seed_from_u64(self.count as u64);
+        [rng.gen_range(-0.7..0.7), rng.gen_range(-0.1..0.7)]
+    }
+}
+
+impl Stage for Fireworks {
+    fn kind(&self) -> StageKind {
+        StageKind::Fireworks
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        // Starts over when rewinding moves time back
+        if self.config.interval > 0.0 {
+            if self.next_time > frame.time + self.config.interval {
+                self.next_time = frame.time;
+            }
+            if frame.time >= self.next_time {
+                let position = self.timed_position();
+                self.pending.push((position, 1.0));
+                self.next_time = frame.time + self.config.interval;
+            }
+        }
+
+        let bursts = self.pending.len().min(MAX_BURSTS);
+        let mut burst_data = [[0.0f32; 4]; MAX_BURSTS];
+        for (data, ([x, y], scale)) in burst_data.iter_mut().zip(self.pending.drain(..bursts)) {
+            // Golden ratio steps round the hues spread them evenly however many go off
+            let hue = (self.count as f32 * 0.618034).fract();
+            *data = [x, y, hue, scale];
+            self.count += 1;
+        }
+        let particle_count = frame.particle_count.max(1);
+        let sparks = self.config.sparks.min(particle_count);
+        let next = self.next % particle_count;
+        self.next = (next as u64 + bursts as u64 * sparks as u64) as u32 % particle_count;
+
+        let lifetime = self.lifetime.config;
+        let params = FireworksParams {
+            particle_count: frame.particle_count,
+            frame: frame.frame,
+            gravity: self.config.gravity,
+            drag: self.config.drag.clamp(0.0, 1.0),
+            speed: self.config.speed,
+            sparks,
+            bursts: bursts as u32,
+            next,
+            lifetime: lifetime.seconds.max(f32::EPSILON),
+            spread: lifetime.spread.clamp(0.0, 1.0),
+        };
+        self.params_buffer.write(frame.queue, &params);
+        frame
+            .queue
+            .write_buffer(&self.burst_buffer, 0, bytemuck::cast_slice(&burst_data));
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Fireworks Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.set_pipeline(&self.step);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        if bursts > 0 {
+            compute_pass.set_pipeline(&self.burst);
+            let threads = bursts as u32 * sparks;
+            compute_pass.dispatch_workgroups(threads.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        drop(compute_pass);
+        self.lifetime.encode(frame, encoder, resources);
+        self.trail.encode(frame, encoder, resources);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.lifetime.rebind(device, resources);
+        self.trail.rebind(device, resources);
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.burst_buffer,
+            self.lifetime.lives_buffer(),
+        );
+        self.next = 0;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<FireworksParams>,
+    burst_buffer: &wgpu::Buffer,
+    lives_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                lives_buffer.as_entire_binding(),
+                burst_buffer.as_entire_binding(),
+            ],
+        )
+    })
+}
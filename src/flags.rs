@@ -0,0 +1,43 @@
+//! Bits of each particle's flags in `GpuResources::flags`, so features marking particles
+//! share one `u32` per particle rather than each adding to the particle layout. The same
+//! constants reach WGSL through `#include "flags.wgsl"`. `ParticleSort` moves the flags
+//! along with the particles; compaction doesn't, so it's left out wherever they're set.
+
+/// Held still by `Freeze`.
+pub const FROZEN: u32 = 1 << 0;
+/// Picked out by the user, drawn highlighted.
+pub const SELECTED: u32 = 1 << 1;
+/// Left out of drawing, culled or not.
+pub const HIDDEN: u32 = 1 << 2;
+/// Put to sleep by `Sleep`, skipped by the boids step.
+pub const ASLEEP: u32 = 1 << 3;
+
+/// Lowest of the bits left for a species index, for stages that treat species differently.
+/// They're 0 until such a stage sets them.
+pub const SPECIES_SHIFT: u32 = 8;
+pub const SPECIES_MASK: u32 = 0xff << SPECIES_SHIFT;
+
+/// `flags` with its species bits set to `species`.
+pub fn with_species(flags: u32, species: u32) -> u32 {
+    (flags & !SPECIES_MASK) | ((species << SPECIES_SHIFT) & SPECIES_MASK)
+}
+
+/// The species bits of `flags`.
+pub fn species(flags: u32) -> u32 {
+    (flags & SPECIES_MASK) >> SPECIES_SHIFT
+}
+
+/// The constants as WGSL, for the `flags.wgsl` module.
+pub fn wgsl() -> String {
+    [
+        ("FLAG_FROZEN", FROZEN),
+        ("FLAG_SELECTED", SELECTED),
+        ("FLAG_HIDDEN", HIDDEN),
+        ("FLAG_ASLEEP", ASLEEP),
+        ("FLAG_SPECIES_SHIFT", SPECIES_SHIFT),
+        ("FLAG_SPECIES_MASK", SPECIES_MASK),
+    ]
+    .iter()
+    .map(|(name, value)| format!("const {name}: u32 = {value}u;\n"))
+    .collect()
+}
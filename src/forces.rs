@@ -0,0 +1,282 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match the attractor array length in force_shader.wgsl
+pub const MAX_ATTRACTORS: usize = 16;
+
+/// A drifting curl noise field nudging the particles along, with optional random kicks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    // Velocity added per frame at the field's strongest, in domain units
+    pub strength: f32,
+    // Noise cells across the domain
+    pub scale: f32,
+    // Standard deviation of a random velocity kick per frame, for Brownian motion
+    pub jitter: f32,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig {
+            strength: 0.0001,
+            scale: 3.0,
+            jitter: 0.0,
+        }
+    }
+}
+
+/// Turns each particle's heading a little this way and that, drifting smoothly over time and
+/// differently for each, so sparse flocks meander instead of gliding in straight lines.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WanderConfig {
+    // Most the heading turns per frame, in radians
+    pub strength: f32,
+    // How quickly the turning changes, in swings per second or so
+    pub frequency: f32,
+}
+
+impl Default for WanderConfig {
+    fn default() -> Self {
+        WanderConfig {
+            strength: 0.05,
+            frequency: 1.0,
+        }
+    }
+}
+
+/// Parse `strength[,frequency]`, e.g. `0.05,1`.
+pub fn parse_wander(s: &str) -> Result<WanderConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid wander `{s}`"))?;
+    let frequency = match values[..] {
+        [_] => WanderConfig::default().frequency,
+        [_, frequency] if frequency >= 0.0 => frequency,
+        _ => return Err(format!("expected a wander like 0.05,1, got `{s}`")),
+    };
+    Ok(WanderConfig {
+        strength: values[0],
+        frequency,
+    })
+}
+
+/// A point pulling every particle towards it, or pushing them away with a negative strength.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Attractor {
+    pub position: [f32; 2],
+    pub strength: f32,
+}
+
+/// Parse `x,y,strength`, e.g. `0,0,0.00005`.
+pub fn parse_attractor(s: &str) -> Result<Attractor, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid attractor `{s}`"))?;
+    match values[..] {
+        [x, y, strength] => Ok(Attractor {
+            position: [x, y],
+            strength,
+        }),
+        _ => Err(format!("expected an attractor like 0,0,0.00005, got `{s}`")),
+    }
+}
+
+/// Which force a `ForceStage` applies.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Force {
+    Noise(NoiseConfig),
+    Wander(WanderConfig),
+    Attractors(Vec<Attractor>),
+    // Fingers on a touchscreen and the gamepad's attractor, skipped when there are none
+    Touches(Vec<Attractor>),
+}
+
+wgsl_struct! {
+    // Must match `ForceParams` in force_shader.wgsl
+    struct ForceParams {
+        particle_count: u32,
+        attractor_count: u32,
+        time: f32,
+        noise_strength: f32,
+        noise_scale: f32,
+        noise_jitter: f32,
+        frame: u32,
+        wander_strength: f32,
+        wander_frequency: f32,
+    }
+}
+
+/// Adds a velocity change to every particle in place, after the integrating stage.
+pub struct ForceStage {
+    pub force: Force,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ForceParams>,
+    attractor_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl ForceStage {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, force: Force) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "force_shader",
+            include_str!("./shaders/force_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .uniform(3)
+            .build(device, "Force");
+        // Labelled per force, since both stages allocate the same buffers
+        let (name, entry_point) = match force {
+            Force::Noise(_) => ("Noise", "noise"),
+            Force::Wander(_) => ("Wander", "wander"),
+            Force::Attractors(_) => ("Attractor", "attract"),
+            Force::Touches(_) => ("Touch", "attract"),
+        };
+        let params_buffer = UniformBuffer::new(device, resources, &format!("{name} Params Buffer"));
+        let attractor_buffer = resources
+            .uniform::<[[f32; 4]; MAX_ATTRACTORS]>(device, &format!("{name} Attractor Buffer"));
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &attractor_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Force Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            diagnostics::checked(device, &format!("Force Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Force Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            });
+
+        ForceStage {
+            force,
+            pipeline,
+            bindings,
+            params_buffer,
+            attractor_buffer,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for ForceStage {
+    fn kind(&self) -> StageKind {
+        match self.force {
+            Force::Noise(_) => StageKind::Noise,
+            Force::Wander(_) => StageKind::Wander,
+            Force::Attractors(_) => StageKind::Attractors,
+            Force::Touches(_) => StageKind::Touches,
+        }
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if matches!(&self.force, Force::Touches(touches) if touches.is_empty()) {
+            return;
+        }
+        let mut params = ForceParams {
+            particle_count: frame.particle_count,
+            attractor_count: 0,
+            time: frame.time,
+            noise_strength: 0.0,
+            noise_scale: 0.0,
+            noise_jitter: 0.0,
+            frame: frame.frame,
+            wander_strength: 0.0,
+            wander_frequency: 0.0,
+        };
+        match &self.force {
+            Force::Noise(noise) => {
+                params.noise_strength = noise.strength;
+                params.noise_scale = noise.scale;
+                params.noise_jitter = noise.jitter;
+            }
+            Force::Wander(wander) => {
+                params.wander_strength = wander.strength;
+                params.wander_frequency = wander.frequency;
+            }
+            Force::Attractors(attractors) | Force::Touches(attractors) => {
+                let mut packed = [[0.0f32; 4]; MAX_ATTRACTORS];
+                for (slot, attractor) in packed.iter_mut().zip(attractors) {
+                    let [x, y] = attractor.position;
+                    *slot = [x, y, attractor.strength, 0.0];
+                }
+                params.attractor_count = attractors.len().min(MAX_ATTRACTORS) as u32;
+                frame
+                    .queue
+                    .write_buffer(&self.attractor_buffer, 0, bytemuck::cast_slice(&packed));
+            }
+        }
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Force Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.attractor_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ForceParams>,
+    attractor_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                attractor_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+            ],
+        )
+    })
+}
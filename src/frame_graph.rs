@@ -0,0 +1,100 @@
+use nannou::prelude::*;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::theme::Hud;
+
+// Frames shown, one pixel each
+const HISTORY: usize = 240;
+const HEIGHT: f32 = 100.0;
+const MARGIN: f32 = 10.0;
+// Top of the graph, twice the 60fps budget
+const MAX_MS: f32 = 33.3;
+const BUDGET_MS: f32 = 1000.0 / 60.0;
+
+// `None` in the theme's text colour
+const SERIES: [(&str, Option<Srgb<u8>>); 3] = [
+    ("compute", Some(DEEPSKYBLUE)),
+    ("render", Some(LIMEGREEN)),
+    ("total", None),
+];
+
+/// Scrolling graph of recent frame times in the corner of the control window, toggled with F3.
+///
+/// Compute and render are the CPU time spent in `update` and the views, so GPU-bound work
+/// mostly shows up in the total.
+pub struct FrameGraph {
+    pub visible: bool,
+    // Milliseconds for each series in `SERIES`, oldest first
+    samples: VecDeque<[f32; 3]>,
+    compute: Duration,
+    // Accumulated over every view since the last update
+    render: Cell<Duration>,
+}
+
+impl FrameGraph {
+    pub fn new(visible: bool) -> Self {
+        FrameGraph {
+            visible,
+            samples: VecDeque::with_capacity(HISTORY),
+            compute: Duration::ZERO,
+            render: Cell::new(Duration::ZERO),
+        }
+    }
+
+    /// Finish the previous frame's sample, given the time since the frame before it.
+    pub fn push(&mut self, total: Duration) {
+        if self.samples.len() == HISTORY {
+            self.samples.pop_front();
+        }
+        let render = self.render.replace(Duration::ZERO);
+        self.samples
+            .push_back([self.compute, render, total].map(|d| d.as_secs_f32() * 1000.0));
+    }
+
+    pub fn set_compute(&mut self, compute: Duration) {
+        self.compute = compute;
+    }
+
+    pub fn add_render(&self, render: Duration) {
+        self.render.set(self.render.get() + render);
+    }
+
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud) {
+        let area = Rect::from_w_h(HISTORY as f32, HEIGHT).top_left_of(window.pad(MARGIN));
+        let y = |ms: f32| area.bottom() + (ms / MAX_MS).min(1.0) * area.h();
+        let x = |i: usize| area.left() + i as f32;
+
+        draw.rect().xy(area.xy()).wh(area.wh()).color(hud.panel);
+        draw.line()
+            .start(pt2(area.left(), y(BUDGET_MS)))
+            .end(pt2(area.right(), y(BUDGET_MS)))
+            .weight(1.0)
+            .color(RED);
+        let color = |shade: Option<Srgb<u8>>| shade.map_or(hud.text, |c| c.into_format().into());
+        for (series, (_, shade)) in SERIES.iter().enumerate() {
+            draw.polyline().weight(1.0).color(color(*shade)).points(
+                self.samples
+                    .iter()
+                    .enumerate()
+                    .map(|(i, sample)| pt2(x(i), y(sample[series]))),
+            );
+        }
+
+        let Some(latest) = self.samples.back() else {
+            return;
+        };
+        for (series, (name, shade)) in SERIES.iter().enumerate() {
+            let label = Rect::from_w_h(area.w(), 14.0)
+                .below(area)
+                .shift_y(-14.0 * series as f32);
+            draw.text(&format!("{} {:.1} ms", name, latest[series]))
+                .xy(label.xy())
+                .wh(label.wh())
+                .left_justify()
+                .font_size(12)
+                .color(color(*shade));
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Caps the frame rate by sleeping in `update` until the next frame is due.
+///
+/// nannou's `LoopMode::Rate` doesn't throttle the event loop, so this is done by hand.
+pub struct FrameLimiter {
+    interval: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(fps: u32) -> Self {
+        FrameLimiter {
+            interval: Duration::from_secs_f64(1.0 / fps as f64),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+            self.next_frame += self.interval;
+        } else {
+            // Running behind, don't try to catch up with a burst of frames
+            self.next_frame = now + self.interval;
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+// Most ticks run in one update, so a stall drops time rather than snowballing into ever
+// longer frames spent catching up
+const MAX_TICKS: u32 = 4;
+
+/// Runs the simulation at a fixed tick rate whatever the display's, for --tick-rate: each
+/// update runs the ticks the real time since the last one calls for, several when the display
+/// is slow and none when it's fast, and the drawing in between is interpolated from how far
+/// it is to the next tick. Each tick is one frame of the simulation, so physics steps the same
+/// on any display.
+pub struct FramePacer {
+    interval: Duration,
+    // Real time not yet simulated
+    owed: Duration,
+}
+
+impl FramePacer {
+    pub fn new(rate: f32) -> Self {
+        FramePacer {
+            interval: Duration::from_secs_f32(1.0 / rate),
+            owed: Duration::ZERO,
+        }
+    }
+
+    /// Ticks due now `elapsed` more real time has gone by.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.owed += elapsed;
+        let due = (self.owed.as_secs_f64() / self.interval.as_secs_f64()) as u32;
+        let ticks = due.min(MAX_TICKS);
+        self.owed = if due > MAX_TICKS {
+            Duration::ZERO
+        } else {
+            self.owed - self.interval * ticks
+        };
+        ticks
+    }
+
+    /// Ticks the latest state is ahead of what should be shown, 0 to 1, for drawing the
+    /// particles that far back along their velocities.
+    pub fn lag(&self) -> f32 {
+        1.0 - (self.owed.as_secs_f32() / self.interval.as_secs_f32()).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_and_waits_for_the_next_tick() {
+        // 50 ticks a second, 20ms each
+        let mut pacer = FramePacer::new(50.0);
+        assert_eq!(pacer.advance(Duration::from_millis(10)), 0);
+        assert!((pacer.lag() - 0.5).abs() < 1e-3);
+        assert_eq!(pacer.advance(Duration::from_millis(35)), 2);
+        assert!((pacer.lag() - 0.75).abs() < 1e-3);
+        // A stall is dropped after the most ticks an update runs
+        assert_eq!(pacer.advance(Duration::from_secs(1)), MAX_TICKS);
+        assert_eq!(pacer.advance(Duration::ZERO), 0);
+    }
+}
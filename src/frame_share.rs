@@ -0,0 +1,152 @@
+use nannou::image::RgbaImage;
+use nannou::prelude::*;
+use nannou::wgpu;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use tracing::{error, info, warn};
+
+// Frames waiting to be written before new ones get dropped
+const QUEUED_FRAMES: usize = 2;
+
+/// Where shared frames go.
+#[derive(Debug, Clone)]
+pub enum Destination {
+    /// Raw RGBA8 frames to a file or named pipe
+    Pipe(PathBuf),
+    /// An NDI source of this name, with the `ndi` feature
+    Ndi(String),
+}
+
+/// Streams the rendered output to other software without screen capture.
+///
+/// A pipe is the platform-neutral way: e.g. `ffmpeg -f rawvideo -pix_fmt rgba -s WxH -i
+/// <pipe> ...` can forward the stream to a v4l2loopback virtual camera for OBS, or a file.
+/// With the `ndi` feature, frames can go straight out as an NDI source instead, for OBS,
+/// Resolume, TouchDesigner and the like anywhere on the network.
+pub struct FrameShare {
+    capturer: wgpu::TextureCapturer,
+    // A pipe has no framing, so its size is locked to the first frame
+    size: Option<Mutex<Option<[u32; 2]>>>,
+    warned_resize: AtomicBool,
+    sender: SyncSender<RgbaImage>,
+}
+
+impl FrameShare {
+    pub fn new(destination: Destination) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<RgbaImage>(QUEUED_FRAMES);
+        let size = matches!(destination, Destination::Pipe(_)).then(|| Mutex::new(None));
+        // Opening a FIFO blocks until a reader connects, and sending NDI compresses the
+        // frame, so keep both off the render thread
+        thread::spawn(move || match destination {
+            Destination::Pipe(path) => write_pipe(&path, receiver),
+            Destination::Ndi(name) => send_ndi(&name, receiver),
+        });
+
+        FrameShare {
+            // A single worker keeps frames in order
+            capturer: wgpu::TextureCapturer::new(Some(1), None),
+            size,
+            warned_resize: AtomicBool::new(false),
+            sender,
+        }
+    }
+
+    /// Copy the frame into a readback buffer. Must be encoded before the frame is submitted.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
+    ) -> Option<wgpu::TextureSnapshot> {
+        let frame_size = frame.texture_size();
+        let Some(size) = &self.size else {
+            return Some(self.capturer.capture(device, encoder, frame.texture()));
+        };
+        let mut size = size.lock().unwrap();
+        let size = *size.get_or_insert_with(|| {
+            info!(
+                "Sharing {}x{} rgba frames, e.g. ffmpeg -f rawvideo -pix_fmt rgba -s {}x{} -i <pipe>",
+                frame_size[0], frame_size[1], frame_size[0], frame_size[1]
+            );
+            frame_size
+        });
+        if size != frame_size {
+            if !self.warned_resize.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Window resized, frame sharing paused until it is {}x{} again",
+                    size[0], size[1]
+                );
+            }
+            return None;
+        }
+        Some(self.capturer.capture(device, encoder, frame.texture()))
+    }
+
+    /// Queue a captured frame for writing once the GPU is done with it.
+    pub fn send(&self, snapshot: wgpu::TextureSnapshot) {
+        let sender = self.sender.clone();
+        let result = snapshot.read(move |result| match result {
+            Ok(image) => {
+                // Drop the frame rather than stall rendering if the reader is slow
+                let _ = sender.try_send(image.to_owned());
+            }
+            Err(err) => error!("Failed to read back shared frame: {:?}", err),
+        });
+        if result.is_err() {
+            error!("Timed out waiting to read back shared frame");
+        }
+    }
+}
+
+fn write_pipe(path: &Path, frames: Receiver<RgbaImage>) {
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) => {
+            error!(
+                "Failed to open frame share output {}: {}",
+                path.display(),
+                err
+            );
+            return;
+        }
+    };
+    for frame in frames {
+        if let Err(err) = file.write_all(frame.as_raw()) {
+            error!("Frame share output closed: {}", err);
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "ndi")]
+fn send_ndi(name: &str, frames: Receiver<RgbaImage>) {
+    let mut sender = match crate::ndi::NdiSender::new(name) {
+        Ok(sender) => sender,
+        Err(err) => {
+            error!("Failed to share frames over NDI: {}", err);
+            return;
+        }
+    };
+    info!("Sharing frames as the NDI source {}", name);
+    for frame in frames {
+        sender.send(&frame);
+    }
+}
+
+#[cfg(not(feature = "ndi"))]
+fn send_ndi(name: &str, _frames: Receiver<RgbaImage>) {
+    error!(
+        "Can't share frames as the NDI source {}, built without the ndi feature",
+        name
+    );
+}
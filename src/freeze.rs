@@ -0,0 +1,256 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most regions frozen or thawed in one frame, half the size of the array in
+/// freeze_shader.wgsl. Any more wait for the next frame.
+pub const MAX_EDITS: usize = 8;
+
+// Position each particle is held at while frozen, see `holds` in freeze_shader.wgsl
+const HOLD_SIZE: wgpu::BufferAddress = 8;
+
+/// Part of the domain frozen or thawed at once, in domain units.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+// Tagged inside, as TOML has no enum variants with fields
+#[serde(tag = "shape", rename_all = "kebab-case")]
+pub enum Region {
+    Rect { min: [f32; 2], max: [f32; 2] },
+    Circle { center: [f32; 2], radius: f32 },
+}
+
+impl Region {
+    /// The rectangle with corners `a` and `b`, either way round.
+    pub fn rect(a: [f32; 2], b: [f32; 2]) -> Self {
+        Region::Rect {
+            min: [a[0].min(b[0]), a[1].min(b[1])],
+            max: [a[0].max(b[0]), a[1].max(b[1])],
+        }
+    }
+
+    // Two of the shader's edits, see `edits` in freeze_shader.wgsl
+    fn edit(self, frozen: bool) -> [[f32; 4]; 2] {
+        let (bounds, shape) = match self {
+            Region::Rect { min, max } => ([min[0], min[1], max[0], max[1]], 0.0),
+            Region::Circle { center, radius } => ([center[0], center[1], radius, 0.0], 1.0),
+        };
+        [bounds, [shape, if frozen { 1.0 } else { 0.0 }, 0.0, 0.0]]
+    }
+}
+
+/// Parse regions like `rect:x0,y0,x1,y1` or `circle:x,y,radius`, separated by `;`, e.g.
+/// `rect:-1,-1,1,-0.8;circle:0,0,0.2`.
+pub fn parse_regions(s: &str) -> Result<Vec<Region>, String> {
+    s.split(';')
+        .map(|region| {
+            let (shape, values) = region
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("expected a region like circle:0,0,0.2, got `{region}`"))?;
+            let values = values
+                .split(',')
+                .map(|n| n.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid region `{region}`"))?;
+            match (shape.trim(), &values[..]) {
+                ("rect", &[x0, y0, x1, y1]) => Ok(Region::rect([x0, y0], [x1, y1])),
+                ("circle", &[x, y, radius]) if radius >= 0.0 => Ok(Region::Circle {
+                    center: [x, y],
+                    radius,
+                }),
+                _ => Err(format!(
+                    "expected a region like rect:x0,y0,x1,y1 or circle:x,y,radius, got `{region}`"
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Particles held still where they are, for building static structures out of the flock
+/// that the rest still flock around. More regions are frozen and thawed with the mouse.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FreezeConfig {
+    // Frozen from the start, and again when the buffers are resized
+    pub regions: Vec<Region>,
+}
+
+wgsl_struct! {
+    // Must match `FreezeParams` in freeze_shader.wgsl
+    struct FreezeParams {
+        particle_count: u32,
+        edits: u32,
+    }
+}
+
+/// Sets `flags::FROZEN` on the particles in the regions and keeps where they were frozen,
+/// indexed like the particles, so compaction is left out with it. Runs after everything
+/// that moves the particles and puts the frozen ones back, still.
+pub struct Freeze {
+    pub config: FreezeConfig,
+    mark: wgpu::ComputePipeline,
+    hold: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<FreezeParams>,
+    edit_buffer: wgpu::Buffer,
+    holds: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Waiting to be frozen, or thawed when false
+    pending: Vec<(Region, bool)>,
+}
+
+impl Freeze {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: FreezeConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "freeze_shader",
+            include_str!("./shaders/freeze_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .build(device, "Freeze");
+        let params_buffer = UniformBuffer::new(device, resources, "Freeze Params Buffer");
+        let edit_buffer =
+            resources.uniform::<[[[f32; 4]; 2]; MAX_EDITS]>(device, "Freeze Edits Buffer");
+        let (holds, bind_groups) = bind(device, resources, &bindings, &params_buffer, &edit_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Freeze Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Freeze Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Freeze Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+        let pending = config
+            .regions
+            .iter()
+            .map(|&region| (region, true))
+            .collect();
+
+        Freeze {
+            config,
+            mark: pipeline("mark"),
+            hold: pipeline("hold"),
+            bindings,
+            params_buffer,
+            edit_buffer,
+            holds,
+            bind_groups,
+            pending,
+        }
+    }
+
+    /// Freeze the particles in `region` where they are, or thaw them when not `frozen`.
+    pub fn edit(&mut self, region: Region, frozen: bool) {
+        self.pending.push((region, frozen));
+    }
+
+    /// Where each particle is held while frozen, for moving them along with the particles.
+    pub fn holds_buffer(&self) -> &wgpu::Buffer {
+        &self.holds
+    }
+}
+
+impl Stage for Freeze {
+    fn kind(&self) -> StageKind {
+        StageKind::Freeze
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let edits = self.pending.len().min(MAX_EDITS);
+        let mut edit_data = [[[0.0f32; 4]; 2]; MAX_EDITS];
+        for (data, (region, frozen)) in edit_data.iter_mut().zip(self.pending.drain(..edits)) {
+            *data = region.edit(frozen);
+        }
+        frame
+            .queue
+            .write_buffer(&self.edit_buffer, 0, bytemuck::cast_slice(&edit_data));
+        let params = FreezeParams {
+            particle_count: frame.particle_count,
+            edits: edits as u32,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Freeze Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        let workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        if edits > 0 {
+            compute_pass.set_pipeline(&self.mark);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        compute_pass.set_pipeline(&self.hold);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.holds, self.bind_groups) = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.edit_buffer,
+        );
+        // What was frozen with the mouse is lost with the old buffers
+        self.pending = self
+            .config
+            .regions
+            .iter()
+            .map(|&region| (region, true))
+            .collect();
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<FreezeParams>,
+    edit_buffer: &wgpu::Buffer,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let holds = resources.buffer(
+        device,
+        "Freeze Holds Buffer",
+        resources.capacity() as wgpu::BufferAddress * HOLD_SIZE,
+        BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                edit_buffer.as_entire_binding(),
+                holds.as_entire_binding(),
+                resources.flags.as_entire_binding(),
+            ],
+        )
+    });
+    (holds, bind_groups)
+}
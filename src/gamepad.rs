@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{error, info};
+
+use crate::forces::Attractor;
+
+// Stick deflection ignored around the centre, as a fraction of the way to the edge
+const DEAD_ZONE: f32 = 0.15;
+// Trigger travel that sets off a burst
+const TRIGGER_THRESHOLD: f32 = 0.75;
+// Frames a burst pushes for
+const BURST_FRAMES: u32 = 15;
+
+/// An attractor steered around the domain with a gamepad's left stick, or a predator the
+/// flock flees with a negative strength. Pulling either trigger sets off a burst pushing the
+/// particles away.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    // Joystick device of the gamepad, read directly rather than through a gamepad library
+    pub device: PathBuf,
+    // Like an attractor's strength, negative to push particles away
+    pub strength: f32,
+    // Distance moved per frame with the stick all the way over, in domain units
+    pub speed: f32,
+    // Push away from the attractor during a burst, like a negative strength
+    pub burst: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        GamepadConfig {
+            device: PathBuf::from("/dev/input/js0"),
+            strength: 0.0001,
+            speed: 0.01,
+            burst: 0.002,
+        }
+    }
+}
+
+// Axes of an Xbox layout pad as the Linux joystick driver numbers them
+const LEFT_X: usize = 0;
+const LEFT_Y: usize = 1;
+const LEFT_TRIGGER: usize = 2;
+const RIGHT_TRIGGER: usize = 5;
+const AXES: usize = 8;
+
+// Type bits of a joystick event, see linux/joystick.h
+const EVENT_AXIS: u8 = 0x02;
+// Set on the events reporting each axis's state when the device is opened
+const EVENT_INIT: u8 = 0x80;
+
+/// The attractor steered by the gamepad, moved once a frame from the latest stick position.
+pub struct GamepadAttractor {
+    config: GamepadConfig,
+    // Raw axis values, kept up to date by a thread reading the device
+    axes: Arc<Mutex<[i16; AXES]>>,
+    position: [f32; 2],
+    // Frames of the burst left to push for
+    burst: u32,
+    trigger_held: bool,
+    last: Option<Attractor>,
+}
+
+impl GamepadAttractor {
+    pub fn open(config: GamepadConfig) -> io::Result<Self> {
+        let mut device = File::open(&config.device)?;
+        info!("Gamepad on {}", config.device.display());
+        let axes = Arc::new(Mutex::new([0; AXES]));
+        let read = axes.clone();
+        let path = config.device.clone();
+        thread::spawn(move || {
+            let mut event = [0u8; 8];
+            // Each event is a timestamp, a value, a type and the axis or button number
+            loop {
+                if let Err(err) = device.read_exact(&mut event) {
+                    error!("Lost the gamepad on {}: {}", path.display(), err);
+                    // Centred, rather than stuck wherever the stick was
+                    *read.lock().unwrap() = [0; AXES];
+                    return;
+                }
+                let value = i16::from_ne_bytes([event[4], event[5]]);
+                let kind = event[6] & !EVENT_INIT;
+                let number = event[7] as usize;
+                if kind == EVENT_AXIS && number < AXES {
+                    read.lock().unwrap()[number] = value;
+                }
+            }
+        });
+        Ok(GamepadAttractor {
+            config,
+            axes,
+            position: [0.0; 2],
+            burst: 0,
+            trigger_held: false,
+            last: None,
+        })
+    }
+
+    /// Move for this frame, returning the attractor if it's changed since the last frame.
+    pub fn update(&mut self) -> Option<Attractor> {
+        let axes = *self.axes.lock().unwrap();
+        let axis = |number: usize| (axes[number] as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+        // Up is negative on the device
+        let stick = [axis(LEFT_X), -axis(LEFT_Y)];
+        let deflection = stick[0].hypot(stick[1]);
+        if deflection > DEAD_ZONE {
+            let scale =
+                self.config.speed * (deflection - DEAD_ZONE) / (1.0 - DEAD_ZONE) / deflection;
+            for (position, stick) in self.position.iter_mut().zip(stick) {
+                *position = (*position + stick * scale).clamp(-1.0, 1.0);
+            }
+        }
+
+        // Triggers rest at the bottom of the axis
+        let trigger = [LEFT_TRIGGER, RIGHT_TRIGGER]
+            .map(|number| (axis(number) + 1.0) / 2.0)
+            .into_iter()
+            .fold(0.0, f32::max);
+        let held = trigger > TRIGGER_THRESHOLD;
+        if held && !self.trigger_held {
+            self.burst = BURST_FRAMES;
+        }
+        self.trigger_held = held;
+        let strength = if self.burst > 0 {
+            self.burst -= 1;
+            -self.config.burst
+        } else {
+            self.config.strength
+        };
+
+        let attractor = Attractor {
+            position: self.position,
+            strength,
+        };
+        (self.last != Some(attractor)).then(|| {
+            self.last = Some(attractor);
+            attractor
+        })
+    }
+}
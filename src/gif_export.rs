@@ -0,0 +1,138 @@
+use nannou::image::codecs::gif::{GifEncoder, Repeat};
+use nannou::image::{imageops, Delay, Frame as GifFrame, RgbaImage};
+use nannou::prelude::*;
+use nannou::wgpu;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+
+// Quantization effort, 1 is best and slowest, 30 fastest
+const ENCODE_SPEED: i32 = 10;
+
+/// `--gif`: capture a run of frames from the output and save them as an animated GIF.
+pub struct GifCapture {
+    path: PathBuf,
+    // Frames simulated before capturing starts
+    warmup: u64,
+    frames: u64,
+    width: u32,
+    // Frames at the end crossfaded into the start, so the animation loops without a jump
+    blend: u64,
+    capturer: wgpu::TextureCapturer,
+    // The GIF has one size, locked to the first frame scaled to `width`
+    size: Arc<Mutex<Option<[u32; 2]>>>,
+    // Keyed by frame number, since view can run more than once per update
+    captured: Arc<Mutex<BTreeMap<u64, RgbaImage>>>,
+}
+
+impl GifCapture {
+    pub fn new(path: PathBuf, warmup: u64, frames: u64, width: u32, blend: u64) -> Self {
+        info!(
+            "Capturing {} frames to {} after {} frames",
+            frames,
+            path.display(),
+            warmup
+        );
+        GifCapture {
+            path,
+            warmup,
+            frames,
+            width,
+            blend: blend.min(frames / 2),
+            capturer: wgpu::TextureCapturer::new(Some(1), None),
+            size: Arc::default(),
+            captured: Arc::default(),
+        }
+    }
+
+    fn wants(&self, frame: u64) -> bool {
+        (self.warmup..self.warmup + self.frames).contains(&frame)
+    }
+
+    /// Whether every frame has been captured, after which `finish` should be called.
+    pub fn done(&self, frame: u64) -> bool {
+        frame >= self.warmup + self.frames
+    }
+
+    /// Copy the frame for the GIF if it's in the captured range. `simulated` is the number
+    /// of frames simulated so far.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
+        simulated: u64,
+    ) {
+        if !self.wants(simulated) || self.captured.lock().unwrap().contains_key(&simulated) {
+            return;
+        }
+        let snapshot = self.capturer.capture(device, encoder, frame.texture());
+        let [frame_width, frame_height] = frame.texture_size();
+        let size = *self.size.lock().unwrap().get_or_insert_with(|| {
+            let width = self.width.min(frame_width);
+            [width, (frame_height * width / frame_width).max(1)]
+        });
+        let captured = self.captured.clone();
+        let result = snapshot.read(move |result| match result {
+            Ok(image) => {
+                let image = imageops::thumbnail(&image.to_owned(), size[0], size[1]);
+                captured.lock().unwrap().insert(simulated, image);
+            }
+            Err(err) => error!("Failed to read back GIF frame: {:?}", err),
+        });
+        if result.is_err() {
+            error!("Timed out waiting to read back GIF frame");
+        }
+    }
+
+    /// Wait for the last frames, then blend the loop and encode the GIF.
+    pub fn finish(self, device: &wgpu::Device) {
+        if self.capturer.await_active_snapshots(device).is_err() {
+            error!("Timed out waiting for the last GIF frames");
+        }
+        let mut frames = std::mem::take(&mut *self.captured.lock().unwrap())
+            .into_values()
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            warn!("No frames captured for {}", self.path.display());
+            return;
+        }
+
+        // Fade the tail into the head and drop it, so the last frame leads into the first
+        let blend = self.blend.min(frames.len() as u64 / 2) as usize;
+        let kept = frames.len() - blend;
+        let tail = frames.split_off(kept);
+        for (i, end) in tail.iter().enumerate() {
+            let t = i as f32 / blend as f32;
+            for (start, end) in frames[i].pixels_mut().zip(end.pixels()) {
+                for (a, b) in start.0.iter_mut().zip(end.0) {
+                    *a = (b as f32 + (*a as f32 - b as f32) * t).round() as u8;
+                }
+            }
+        }
+
+        let result = File::create(&self.path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), ENCODE_SPEED);
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(|err| err.to_string())?;
+                let delay = Delay::from_numer_denom_ms(1000, 60);
+                encoder
+                    .encode_frames(
+                        frames
+                            .into_iter()
+                            .map(|image| GifFrame::from_parts(image, 0, 0, delay)),
+                    )
+                    .map_err(|err| err.to_string())
+            });
+        match result {
+            Ok(()) => info!("Saved {} frames to {}", kept, self.path.display()),
+            Err(err) => error!("Failed to save {}: {}", self.path.display(), err),
+        }
+    }
+}
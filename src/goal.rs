@@ -0,0 +1,628 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Straight pieces each curve in an SVG path is split into
+const CURVE_SEGMENTS: usize = 16;
+// Fraction of the domain an SVG path is fitted into, leaving a margin
+const SVG_FIT: f32 = 0.9;
+// Waypoints along `GoalConfig::meander`
+const MEANDER_POINTS: usize = 64;
+
+/// A goal point the whole flock steers towards, travelling a route over time so the flock
+/// can be choreographed along it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GoalConfig {
+    // The route in domain units, in order. A single point holds the goal still
+    pub waypoints: Vec<[f32; 2]>,
+    // SVG file whose first path is the route, fitted to the domain, in place of the waypoints
+    pub path: Option<PathBuf>,
+    // Simulated seconds to travel the whole route, at an even speed
+    pub seconds: f32,
+    // Back round to the start after the end, otherwise the goal stops there
+    pub looped: bool,
+    // Velocity change per frame towards the goal, in domain units
+    pub strength: f32,
+    // Distance from the goal inside which the pull eases off, so the flock doesn't overshoot
+    pub arrival: f32,
+}
+
+impl Default for GoalConfig {
+    fn default() -> Self {
+        GoalConfig {
+            waypoints: vec![[0.0, 0.0]],
+            path: None,
+            seconds: 20.0,
+            looped: true,
+            strength: 0.00005,
+            arrival: 0.1,
+        }
+    }
+}
+
+impl GoalConfig {
+    /// A looping route wandering over most of the domain, for leaders to follow when no goal
+    /// is set, see `LeaderConfig`.
+    pub fn meander() -> Self {
+        // Waves a whole number of times round, so the route joins up
+        let points = (0..MEANDER_POINTS)
+            .map(|i| {
+                let t = i as f32 / MEANDER_POINTS as f32 * std::f32::consts::TAU;
+                [
+                    0.55 * t.sin() + 0.2 * (3.0 * t + 1.0).sin(),
+                    0.55 * (2.0 * t + 0.5).sin() + 0.2 * (5.0 * t).cos(),
+                ]
+            })
+            .collect();
+        GoalConfig {
+            waypoints: points,
+            seconds: 60.0,
+            strength: 0.0001,
+            ..GoalConfig::default()
+        }
+    }
+}
+
+/// Parse waypoints like `x,y;x,y;...`, e.g. `-0.5,0;0,0.5;0.5,0`.
+pub fn parse_waypoints(s: &str) -> Result<Vec<[f32; 2]>, String> {
+    s.split(';')
+        .map(|point| {
+            let values = point
+                .split(',')
+                .map(|n| n.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid waypoint `{point}`"))?;
+            match values[..] {
+                [x, y] => Ok([x, y]),
+                _ => Err(format!("expected a waypoint like 0.5,0, got `{point}`")),
+            }
+        })
+        .collect()
+}
+
+/// A polyline walked at an even speed, by fraction of its length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    points: Vec<[f32; 2]>,
+    // Distance along the route to each point
+    distances: Vec<f32>,
+}
+
+impl Route {
+    /// The route `config` describes, read from its SVG file if it has one.
+    pub fn load(config: &GoalConfig) -> Result<Self, String> {
+        let mut points = match &config.path {
+            Some(path) => {
+                let svg = fs::read_to_string(path)
+                    .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+                svg_path(&svg).map_err(|err| format!("Bad route {}: {}", path.display(), err))?
+            }
+            None => config.waypoints.clone(),
+        };
+        if points.is_empty() {
+            return Err("The goal's route has no waypoints".to_owned());
+        }
+        if config.looped {
+            points.push(points[0]);
+        }
+        Ok(Route::new(points))
+    }
+
+    fn new(points: Vec<[f32; 2]>) -> Self {
+        let mut distances = vec![0.0];
+        for pair in points.windows(2) {
+            let step = (pair[1][0] - pair[0][0]).hypot(pair[1][1] - pair[0][1]);
+            distances.push(distances.last().unwrap() + step);
+        }
+        Route { points, distances }
+    }
+
+    /// The point `fraction` of the way along, 0 at the start and 1 at the end.
+    pub fn at(&self, fraction: f32) -> [f32; 2] {
+        if self.points.len() < 2 {
+            return self.points[0];
+        }
+        let distance = fraction.clamp(0.0, 1.0) * self.distances.last().unwrap();
+        let next = self
+            .distances
+            .partition_point(|&d| d < distance)
+            .clamp(1, self.points.len() - 1);
+        let (a, b) = (self.points[next - 1], self.points[next]);
+        let span = self.distances[next] - self.distances[next - 1];
+        let t = if span > 0.0 {
+            (distance - self.distances[next - 1]) / span
+        } else {
+            0.0
+        };
+        [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+    }
+}
+
+// The first path in an SVG document, as points fitted into the domain with y up
+fn svg_path(svg: &str) -> Result<Vec<[f32; 2]>, String> {
+    let element = svg.find("<path").ok_or("no <path> element")?;
+    let rest = &svg[element..];
+    let rest = &rest[..rest.find('>').unwrap_or(rest.len())];
+    let data = [" d=\"", " d='", "\nd=\"", "\td=\""]
+        .iter()
+        .find_map(|attribute| {
+            let start = rest.find(attribute)? + attribute.len();
+            let quote = attribute.chars().last().unwrap();
+            let end = rest[start..].find(quote)?;
+            Some(&rest[start..start + end])
+        })
+        .ok_or("the path has no d attribute")?;
+    fit(&flatten(data)?)
+}
+
+// Commands and their numbers, e.g. `M 0 0 L 10,5` as ('M', [0, 0]) and ('L', [10, 5])
+fn tokenize(data: &str) -> Result<Vec<(char, Vec<f32>)>, String> {
+    let mut commands: Vec<(char, Vec<f32>)> = Vec::new();
+    let mut number = String::new();
+    let flush = |number: &mut String, commands: &mut Vec<(char, Vec<f32>)>| {
+        if number.is_empty() {
+            return Ok(());
+        }
+        let value = number
+            .parse::<f32>()
+            .map_err(|_| format!("invalid number `{number}`"))?;
+        number.clear();
+        match commands.last_mut() {
+            Some((_, numbers)) => numbers.push(value),
+            None => return Err("path data doesn't start with a command".to_owned()),
+        }
+        Ok(())
+    };
+    for c in data.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' if c != 'e' && c != 'E' => {
+                flush(&mut number, &mut commands)?;
+                commands.push((c, Vec::new()));
+            }
+            // A sign starts a new number unless it's an exponent's
+            '-' | '+' if !number.is_empty() && !number.ends_with(['e', 'E']) => {
+                flush(&mut number, &mut commands)?;
+                number.push(c);
+            }
+            // A second point starts one too, e.g. `0.5.5`
+            '.' if number.contains('.') => {
+                flush(&mut number, &mut commands)?;
+                number.push(c);
+            }
+            '0'..='9' | '.' | '-' | '+' | 'e' | 'E' => number.push(c),
+            _ => flush(&mut number, &mut commands)?,
+        }
+    }
+    flush(&mut number, &mut commands)?;
+    Ok(commands)
+}
+
+// The path's outline as points, curves split into straight pieces. Arcs go straight to
+// their end point.
+fn flatten(data: &str) -> Result<Vec<[f32; 2]>, String> {
+    let mut points = Vec::new();
+    let mut current = [0.0f32; 2];
+    let mut start = current;
+    // The last curve's control point, for the smooth curve commands to reflect
+    let mut control: Option<(Curve, [f32; 2])> = None;
+    let curve = |points: &mut Vec<[f32; 2]>, controls: &[[f32; 2]]| {
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            // De Casteljau's construction
+            let mut level = controls.to_vec();
+            while level.len() > 1 {
+                level = level
+                    .windows(2)
+                    .map(|pair| {
+                        [
+                            pair[0][0] + (pair[1][0] - pair[0][0]) * t,
+                            pair[0][1] + (pair[1][1] - pair[0][1]) * t,
+                        ]
+                    })
+                    .collect();
+            }
+            points.push(level[0]);
+        }
+    };
+
+    for (command, numbers) in tokenize(data)? {
+        let relative = command.is_ascii_lowercase();
+        let point = |current: [f32; 2], x: f32, y: f32| {
+            if relative {
+                [current[0] + x, current[1] + y]
+            } else {
+                [x, y]
+            }
+        };
+        let arity = match command.to_ascii_uppercase() {
+            'M' | 'L' | 'T' => 2,
+            'H' | 'V' => 1,
+            'C' => 6,
+            'S' | 'Q' => 4,
+            'A' => 7,
+            'Z' => 0,
+            other => return Err(format!("unknown path command `{other}`")),
+        };
+        if arity == 0 {
+            current = start;
+            points.push(current);
+            control = None;
+            continue;
+        }
+        if numbers.is_empty() || numbers.len() % arity != 0 {
+            return Err(format!("wrong number of values for `{command}`"));
+        }
+        for (i, values) in numbers.chunks(arity).enumerate() {
+            let mut next_control = None;
+            match command.to_ascii_uppercase() {
+                // Pairs after the first of a move are lines
+                'M' => {
+                    current = point(current, values[0], values[1]);
+                    if i == 0 {
+                        start = current;
+                    }
+                    points.push(current);
+                }
+                'L' => {
+                    current = point(current, values[0], values[1]);
+                    points.push(current);
+                }
+                'H' => {
+                    current[0] = if relative {
+                        current[0] + values[0]
+                    } else {
+                        values[0]
+                    };
+                    points.push(current);
+                }
+                'V' => {
+                    current[1] = if relative {
+                        current[1] + values[0]
+                    } else {
+                        values[0]
+                    };
+                    points.push(current);
+                }
+                'C' => {
+                    let first = point(current, values[0], values[1]);
+                    let second = point(current, values[2], values[3]);
+                    let end = point(current, values[4], values[5]);
+                    curve(&mut points, &[current, first, second, end]);
+                    next_control = Some((Curve::Cubic, second));
+                    current = end;
+                }
+                'S' => {
+                    let first = reflect(control, Curve::Cubic, current);
+                    let second = point(current, values[0], values[1]);
+                    let end = point(current, values[2], values[3]);
+                    curve(&mut points, &[current, first, second, end]);
+                    next_control = Some((Curve::Cubic, second));
+                    current = end;
+                }
+                'Q' => {
+                    let middle = point(current, values[0], values[1]);
+                    let end = point(current, values[2], values[3]);
+                    curve(&mut points, &[current, middle, end]);
+                    next_control = Some((Curve::Quadratic, middle));
+                    current = end;
+                }
+                'T' => {
+                    let middle = reflect(control, Curve::Quadratic, current);
+                    let end = point(current, values[0], values[1]);
+                    curve(&mut points, &[current, middle, end]);
+                    next_control = Some((Curve::Quadratic, middle));
+                    current = end;
+                }
+                _ => {
+                    current = point(current, values[5], values[6]);
+                    points.push(current);
+                }
+            }
+            control = next_control;
+        }
+    }
+    Ok(points)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Curve {
+    Cubic,
+    Quadratic,
+}
+
+// The control point mirrored through `current`, or `current` itself when the last command
+// wasn't a curve of the same kind
+fn reflect(control: Option<(Curve, [f32; 2])>, kind: Curve, current: [f32; 2]) -> [f32; 2] {
+    match control {
+        Some((last, [x, y])) if last == kind => [2.0 * current[0] - x, 2.0 * current[1] - y],
+        _ => current,
+    }
+}
+
+// Scaled evenly into the domain, centred, with SVG's downward y flipped
+fn fit(points: &[[f32; 2]]) -> Result<Vec<[f32; 2]>, String> {
+    if points.is_empty() {
+        return Err("the path is empty".to_owned());
+    }
+    let mut min = [f32::INFINITY; 2];
+    let mut max = [f32::NEG_INFINITY; 2];
+    for point in points {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    let extent = (max[0] - min[0]).max(max[1] - min[1]);
+    let scale = if extent > 0.0 {
+        2.0 * SVG_FIT / extent
+    } else {
+        0.0
+    };
+    let centre = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    Ok(points
+        .iter()
+        .map(|&[x, y]| [(x - centre[0]) * scale, (centre[1] - y) * scale])
+        .collect())
+}
+
+wgsl_struct! {
+    // Must match `GoalParams` in goal_shader.wgsl
+    struct GoalParams {
+        goal: [f32; 2],
+        strength: f32,
+        arrival: f32,
+    }
+}
+
+/// Steers every particle towards the goal in place, after the integrating stage, the goal
+/// moving along its route with the simulated time so playback matches.
+pub struct Goal {
+    pub config: GoalConfig,
+    route: Route,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<GoalParams>,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Goal {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: GoalConfig,
+        route: Route,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "goal_shader",
+            include_str!("./shaders/goal_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .build(device, "Goal");
+        let params_buffer = UniformBuffer::new(device, resources, "Goal Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Goal Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Goal Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Goal Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "seek",
+            })
+        });
+
+        Goal {
+            config,
+            route,
+            pipeline,
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+
+    /// Where the goal is `time` simulated seconds in.
+    pub fn position(&self, time: f32) -> [f32; 2] {
+        let laps = time / self.config.seconds.max(f32::EPSILON);
+        let fraction = if self.config.looped {
+            laps.fract()
+        } else {
+            laps.min(1.0)
+        };
+        self.route.at(fraction)
+    }
+}
+
+impl Stage for Goal {
+    fn kind(&self) -> StageKind {
+        StageKind::Goal
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = GoalParams {
+            goal: self.position(frame.time),
+            strength: self.config.strength,
+            arrival: self.config.arrival.max(f32::EPSILON),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Goal Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<GoalParams>,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_points(actual: &[[f32; 2]], expected: &[[f32; 2]]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a[0] - e[0]).abs() < 1e-4 && (a[1] - e[1]).abs() < 1e-4,
+                "{actual:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tokenizes_packed_numbers() {
+        assert_eq!(
+            tokenize("M0,0L-1-2.5.5e1 1e-1").unwrap(),
+            [('M', vec![0.0, 0.0]), ('L', vec![-1.0, -2.5, 5.0, 0.1])]
+        );
+        assert!(tokenize("0 0").is_err());
+        assert!(tokenize("M 1..").is_err());
+    }
+
+    #[test]
+    fn repeats_commands_for_extra_values() {
+        // Pairs after the first of a move are lines
+        assert_points(
+            &flatten("M 0 0 10 0 L 10 10 0 10").unwrap(),
+            &[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+        );
+        assert_points(
+            &flatten("m 1 1 2 0 0 2").unwrap(),
+            &[[1.0, 1.0], [3.0, 1.0], [3.0, 3.0]],
+        );
+    }
+
+    #[test]
+    fn follows_relative_and_axis_commands() {
+        assert_points(
+            &flatten("M 10 10 l 5 0 v 5 h -5 V 10 H 20").unwrap(),
+            &[
+                [10.0, 10.0],
+                [15.0, 10.0],
+                [15.0, 15.0],
+                [10.0, 15.0],
+                [10.0, 10.0],
+                [20.0, 10.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn closes_back_to_the_start_of_the_subpath() {
+        // Relative commands after a close start from the subpath's start
+        assert_points(
+            &flatten("M 0 0 L 5 0 M 1 1 L 5 1 Z l 0 2").unwrap(),
+            &[
+                [0.0, 0.0],
+                [5.0, 0.0],
+                [1.0, 1.0],
+                [5.0, 1.0],
+                [1.0, 1.0],
+                [1.0, 3.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn reflects_only_controls_of_the_same_kind_of_curve() {
+        // Without a cubic before it, S's first control is the current point, keeping the
+        // curve on the line
+        let points = flatten("M 0 0 Q 10 10 20 0 S 40 0 40 0").unwrap();
+        assert!(points[1 + CURVE_SEGMENTS..]
+            .iter()
+            .all(|p| p[1].abs() < 1e-5));
+        let points = flatten("M 0 0 C 0 10 20 10 20 0 T 40 0").unwrap();
+        assert!(points[1 + CURVE_SEGMENTS..]
+            .iter()
+            .all(|p| p[1].abs() < 1e-5));
+        // After one of the same kind the control is mirrored through the current point
+        let points = flatten("M 0 0 Q 10 10 20 0 T 40 0").unwrap();
+        assert_points(
+            &points[CURVE_SEGMENTS + CURVE_SEGMENTS / 2..][..1],
+            &[[30.0, -5.0]],
+        );
+        let points = flatten("M 0 0 C 0 10 20 10 20 0 S 40 -10 40 0").unwrap();
+        assert_points(
+            &points[CURVE_SEGMENTS + CURVE_SEGMENTS / 2..][..1],
+            &[[30.0, -7.5]],
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        assert!(flatten("M 0 0 L 1").is_err());
+        assert!(flatten("M 0 0 X 1 1").is_err());
+        assert!(flatten("L").is_err());
+    }
+
+    #[test]
+    fn fits_paths_into_the_domain_with_y_up() {
+        assert_points(
+            &fit(&[[0.0, 0.0], [10.0, 0.0], [10.0, 20.0]]).unwrap(),
+            &[[-0.45, 0.9], [0.45, 0.9], [0.45, -0.9]],
+        );
+        assert_points(&fit(&[[3.0, 3.0]]).unwrap(), &[[0.0, 0.0]]);
+        assert!(fit(&[]).is_err());
+    }
+
+    #[test]
+    fn reads_the_first_paths_data() {
+        let svg = "<svg><path id='a'\n  d=\"M 0 0 H 2\"/><path d=\"M 5 5\"/></svg>";
+        assert_points(&svg_path(svg).unwrap(), &[[-0.9, 0.0], [0.9, 0.0]]);
+        assert!(svg_path("<svg/>").is_err());
+    }
+
+    #[test]
+    fn travels_the_route_at_an_even_speed() {
+        let route = Route::new(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 3.0]]);
+        assert_points(&[route.at(0.0)], &[[0.0, 0.0]]);
+        assert_points(&[route.at(0.5)], &[[1.0, 1.0]]);
+        assert_points(&[route.at(2.0)], &[[1.0, 3.0]]);
+    }
+}
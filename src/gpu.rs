@@ -0,0 +1,53 @@
+//! Reusable compute building blocks that work on plain storage buffers.
+
+use nannou::wgpu::{self, BufferUsages};
+use tracing::error;
+
+use crate::diagnostics;
+
+mod gather;
+mod scan;
+mod sort;
+
+pub use gather::{gather, Gather};
+pub use scan::{scan, PrefixScan};
+pub use sort::{sort, RadixSort};
+
+/// Copy the first `len` elements of a buffer back from the GPU, blocking until they arrive.
+/// The buffer needs `COPY_SRC`.
+pub fn read_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    len: usize,
+) -> Vec<T> {
+    let size = (len * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+    if size == 0 {
+        return Vec::new();
+    }
+    // Temporary, so not tracked
+    let staging = diagnostics::checked(device, "Readback Buffer", || {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+    queue.submit(Some(encoder.finish()));
+
+    let slice = staging.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| {
+        if let Err(err) = result {
+            error!("Failed to map readback buffer: {}", err);
+        }
+    });
+    device.poll(wgpu::Maintain::Wait);
+    let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging.unmap();
+    data
+}
@@ -0,0 +1,182 @@
+use nannou::wgpu::{self, util::DeviceExt, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+
+// Must match the shader
+const WORKGROUP_SIZE: u32 = 256;
+
+// Must match `GatherParams` in gather_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GatherParams {
+    count: u32,
+    words: u32,
+    _pad: [u32; 2],
+}
+
+/// Reorder the elements of a buffer by a list of indices, e.g. the values a `RadixSort`
+/// leaves behind, so per-particle data follows the particles to their sorted places.
+///
+/// Elements are any whole number of `u32` words, so one pipeline moves particles and
+/// anything indexed like them.
+pub struct Gather {
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+}
+
+impl Gather {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "gather_shader",
+            include_str!("../shaders/gather_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_ro(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Gather");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gather Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Gather Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Gather Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "gather",
+            })
+        });
+        Gather { pipeline, bindings }
+    }
+
+    /// Encode writing element `order[i]` of `source` to element `i` of `destination`, for
+    /// the first `count`, each element `words` `u32`s long. The buffers need `STORAGE` and
+    /// can't be the same buffer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        order: &wgpu::Buffer,
+        destination: &wgpu::Buffer,
+        count: u32,
+        words: u32,
+    ) {
+        if count == 0 || words == 0 {
+            return;
+        }
+        let params = GatherParams {
+            count,
+            words,
+            _pad: [0; 2],
+        };
+        // Dropped once the GPU is done with it, so not tracked
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gather Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = self.bindings.bind_group(
+            device,
+            &[
+                source.as_entire_binding(),
+                order.as_entire_binding(),
+                destination.as_entire_binding(),
+                params_buffer.as_entire_binding(),
+            ],
+        );
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Gather Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups((count * words).div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+/// Gather the first `count` elements of `words` words each, building the pipeline for this
+/// one call. Keep a `Gather` around to gather every frame.
+pub fn gather(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    source: &wgpu::Buffer,
+    order: &wgpu::Buffer,
+    destination: &wgpu::Buffer,
+    count: u32,
+    words: u32,
+) {
+    Gather::new(device).encode(device, encoder, source, order, destination, count, words);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gpu, headless};
+    use nannou::rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    fn check(values: Vec<u32>, order: Vec<u32>, words: u32) {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        let buffer = |label: &str, contents: &[u32]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(contents),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            })
+        };
+        let source = buffer("Source", &values);
+        let order_buffer = buffer("Order", &order);
+        let destination = buffer("Destination", &vec![0; values.len()]);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Gather Test Encoder"),
+        });
+        let count = order.len() as u32;
+        gather(
+            &device,
+            &mut encoder,
+            &source,
+            &order_buffer,
+            &destination,
+            count,
+            words,
+        );
+        queue.submit(Some(encoder.finish()));
+        let gathered = gpu::read_buffer::<u32>(&device, &queue, &destination, values.len());
+
+        let words = words as usize;
+        let mut expected = vec![0; values.len()];
+        for (i, &from) in order.iter().enumerate() {
+            let from = from as usize * words;
+            expected[i * words..(i + 1) * words].copy_from_slice(&values[from..from + words]);
+        }
+        assert_eq!(gathered, expected);
+    }
+
+    #[test]
+    fn gathers_single_words() {
+        check(vec![10, 11, 12, 13], vec![2, 0, 3, 1], 1);
+    }
+
+    #[test]
+    fn gathers_whole_elements() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut order = (0..10_000).collect::<Vec<u32>>();
+        order.shuffle(&mut rng);
+        check((0..80_000).collect(), order, 8);
+    }
+
+    #[test]
+    fn leaves_elements_past_the_count() {
+        // Only the first two are gathered, the rest of the destination stays zeroed
+        check(vec![1, 2, 3, 4, 5, 6], vec![2, 1], 2);
+    }
+}
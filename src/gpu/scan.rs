@@ -0,0 +1,231 @@
+use nannou::wgpu::{self, util::DeviceExt, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+
+// Must match the shader
+const BLOCK_SIZE: u32 = 512;
+
+// Must match `ScanParams` in scan_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScanParams {
+    count: u32,
+    _pad: [u32; 3],
+}
+
+/// In-place exclusive prefix sum of a `u32` buffer, e.g. turning per-cell counts into the
+/// offset of each cell's first particle, or keep flags into output indices.
+///
+/// Blocks of `BLOCK_SIZE` are scanned in shared memory, then the block totals are scanned
+/// recursively and added back, so any count up to `BLOCK_SIZE` cubed takes three levels at
+/// most.
+pub struct PrefixScan {
+    scan_blocks: wgpu::ComputePipeline,
+    add_block_offsets: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+}
+
+impl PrefixScan {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "scan_shader",
+            include_str!("../shaders/scan_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .storage_rw(1)
+            .uniform(2)
+            .build(device, "Scan");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Scan Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Scan Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Scan Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+        PrefixScan {
+            scan_blocks: pipeline("scan_blocks"),
+            add_block_offsets: pipeline("add_block_offsets"),
+            bindings,
+        }
+    }
+
+    /// Encode replacing the first `count` elements of `data` with the sum of the elements
+    /// before each. The buffer needs `STORAGE`.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        data: &wgpu::Buffer,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+        // Each level's block totals are the next level's data, until one block covers it all
+        let mut levels = Vec::new();
+        let mut level_count = count;
+        loop {
+            let block_count = level_count.div_ceil(BLOCK_SIZE);
+            // Scratch, dropped once the GPU is done with them, so not tracked
+            let block_sums = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Scan Block Sums Buffer"),
+                size: (block_count as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            levels.push((level_count, block_count, block_sums));
+            if block_count == 1 {
+                break;
+            }
+            level_count = block_count;
+        }
+
+        let bind_groups = levels
+            .iter()
+            .enumerate()
+            .map(|(level, (level_count, _, block_sums))| {
+                let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Scan Params Buffer"),
+                    contents: bytemuck::bytes_of(&ScanParams {
+                        count: *level_count,
+                        _pad: [0; 3],
+                    }),
+                    usage: BufferUsages::UNIFORM,
+                });
+                let level_data = if level == 0 {
+                    data
+                } else {
+                    &levels[level - 1].2
+                };
+                self.bindings.bind_group(
+                    device,
+                    &[
+                        level_data.as_entire_binding(),
+                        block_sums.as_entire_binding(),
+                        params_buffer.as_entire_binding(),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Scan Pass"),
+        });
+        compute_pass.set_pipeline(&self.scan_blocks);
+        for ((_, block_count, _), bind_group) in levels.iter().zip(&bind_groups) {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(*block_count, 1, 1);
+        }
+        // Back up from the coarsest level, whose single block needs no offset
+        compute_pass.set_pipeline(&self.add_block_offsets);
+        for ((_, block_count, _), bind_group) in levels.iter().zip(&bind_groups).rev().skip(1) {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(*block_count, 1, 1);
+        }
+    }
+}
+
+/// Exclusive prefix sum of the first `count` elements in place, building the pipelines for
+/// this one call. Keep a `PrefixScan` around to scan every frame.
+pub fn scan(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    data: &wgpu::Buffer,
+    count: u32,
+) {
+    PrefixScan::new(device).encode(device, encoder, data, count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gpu, headless};
+    use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn check(values: Vec<u32>) {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Scan Test Buffer"),
+            contents: bytemuck::cast_slice(&values),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Scan Test Encoder"),
+        });
+        scan(&device, &mut encoder, &buffer, values.len() as u32);
+        queue.submit(Some(encoder.finish()));
+        let scanned = gpu::read_buffer::<u32>(&device, &queue, &buffer, values.len());
+
+        let expected = values
+            .iter()
+            .scan(0u32, |sum, &value| {
+                let before = *sum;
+                *sum += value;
+                Some(before)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scanned, expected);
+    }
+
+    #[test]
+    fn scans_within_a_block() {
+        check(vec![3, 1, 4, 1, 5, 9, 2, 6]);
+        check(vec![1; BLOCK_SIZE as usize]);
+    }
+
+    #[test]
+    fn scans_across_blocks() {
+        let mut rng = StdRng::seed_from_u64(1);
+        check(
+            (0..BLOCK_SIZE * 3 + 17)
+                .map(|_| rng.gen_range(0..100))
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn scans_three_levels() {
+        // More blocks than fit in one block, so the block totals are scanned recursively
+        let mut rng = StdRng::seed_from_u64(2);
+        check(
+            (0..BLOCK_SIZE * BLOCK_SIZE + 1000)
+                .map(|_| rng.gen_range(0..4))
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn leaves_elements_past_the_count() {
+        let Some((device, queue)) = headless::device() else {
+            return;
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Scan Test Buffer"),
+            contents: bytemuck::cast_slice(&[1u32, 2, 3, 99]),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Scan Test Encoder"),
+        });
+        scan(&device, &mut encoder, &buffer, 3);
+        queue.submit(Some(encoder.finish()));
+        assert_eq!(
+            gpu::read_buffer::<u32>(&device, &queue, &buffer, 4),
+            [0, 1, 3, 99]
+        );
+    }
+}
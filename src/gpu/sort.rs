@@ -0,0 +1,277 @@
+use nannou::wgpu::{self, util::DeviceExt, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+
+use super::PrefixScan;
+
+// Must match the shader
+const BLOCK_SIZE: u32 = 256;
+const RADIX: u32 = 256;
+const DIGIT_BITS: u32 = 8;
+
+// Must match `SortParams` in radix_sort_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortParams {
+    count: u32,
+    shift: u32,
+    block_count: u32,
+    _pad: u32,
+}
+
+/// Stable LSD radix sort of `u32` keys with a `u32` value each, e.g. particle indices sorted
+/// by cell or depth.
+///
+/// Each of the four 8 bit passes counts digits per block of `BLOCK_SIZE`, scans the counts
+/// into output offsets with a `PrefixScan`, then scatters. The pipelines are built once,
+/// scratch buffers per call, unless the caller keeps them for `encode_in`.
+pub struct RadixSort {
+    histogram: wgpu::ComputePipeline,
+    scan: PrefixScan,
+    scatter: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+}
+
+impl RadixSort {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "radix_sort_shader",
+            include_str!("../shaders/radix_sort_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_ro(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .uniform(5)
+            .build(device, "Radix Sort");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Radix Sort Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Radix Sort Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Radix Sort Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+        RadixSort {
+            histogram: pipeline("histogram"),
+            scan: PrefixScan::new(device),
+            scatter: pipeline("scatter"),
+            bindings,
+        }
+    }
+
+    /// Encode sorting the first `count` keys, moving the values along with them. Both
+    /// buffers need `STORAGE`, and hold the sorted result once the encoder is submitted.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        keys: &wgpu::Buffer,
+        values: &wgpu::Buffer,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+        // Scratch, dropped once the GPU is done with them, so not tracked
+        let [keys_size, values_size, histograms_size] = RadixSort::scratch_sizes(count);
+        let scratch = |label: &str, size: wgpu::BufferAddress| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let scratch_keys = scratch("Radix Sort Keys Buffer", keys_size);
+        let scratch_values = scratch("Radix Sort Values Buffer", values_size);
+        let histograms = scratch("Radix Sort Histogram Buffer", histograms_size);
+        self.encode_in(
+            device,
+            encoder,
+            keys,
+            values,
+            count,
+            [&scratch_keys, &scratch_values, &histograms],
+        );
+    }
+
+    /// Bytes of each of the scratch buffers sorting `count` keys works in: the keys, the values
+    /// and the per block digit counts.
+    pub fn scratch_sizes(count: u32) -> [wgpu::BufferAddress; 3] {
+        let u32_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let block_count = count.div_ceil(BLOCK_SIZE) as wgpu::BufferAddress;
+        [
+            count as u64 * u32_size,
+            count as u64 * u32_size,
+            RADIX as u64 * block_count * u32_size,
+        ]
+    }
+
+    /// Like `encode`, working in `scratch` buffers the caller keeps between sorts, at least
+    /// `scratch_sizes` long and with `STORAGE`.
+    pub fn encode_in(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        keys: &wgpu::Buffer,
+        values: &wgpu::Buffer,
+        count: u32,
+        [scratch_keys, scratch_values, histograms]: [&wgpu::Buffer; 3],
+    ) {
+        if count == 0 {
+            return;
+        }
+        let block_count = count.div_ceil(BLOCK_SIZE);
+
+        // Even passes go from the caller's buffers to scratch and odd ones back, so the
+        // result ends up where it started
+        let passes = u32::BITS / DIGIT_BITS;
+        let bind_groups = (0..passes)
+            .map(|pass| {
+                let params = SortParams {
+                    count,
+                    shift: pass * DIGIT_BITS,
+                    block_count,
+                    _pad: 0,
+                };
+                let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Radix Sort Params Buffer"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: BufferUsages::UNIFORM,
+                });
+                let (src, dst) = if pass % 2 == 0 {
+                    ((keys, values), (scratch_keys, scratch_values))
+                } else {
+                    ((scratch_keys, scratch_values), (keys, values))
+                };
+                self.bindings.bind_group(
+                    device,
+                    &[
+                        src.0.as_entire_binding(),
+                        src.1.as_entire_binding(),
+                        dst.0.as_entire_binding(),
+                        dst.1.as_entire_binding(),
+                        histograms.as_entire_binding(),
+                        params_buffer.as_entire_binding(),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for bind_group in &bind_groups {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Radix Sort Histogram Pass"),
+            });
+            compute_pass.set_pipeline(&self.histogram);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(block_count, 1, 1);
+            drop(compute_pass);
+
+            self.scan
+                .encode(device, encoder, histograms, RADIX * block_count);
+
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Radix Sort Scatter Pass"),
+            });
+            compute_pass.set_pipeline(&self.scatter);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(block_count, 1, 1);
+        }
+    }
+}
+
+/// Sort the first `count` keys with their values, building the pipelines for this one call.
+/// Keep a `RadixSort` around to sort every frame.
+pub fn sort(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    keys: &wgpu::Buffer,
+    values: &wgpu::Buffer,
+    count: u32,
+) {
+    RadixSort::new(device).encode(device, encoder, keys, values, count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gpu, headless};
+    use nannou::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn sort_on_gpu(keys: &[u32]) -> Option<(Vec<u32>, Vec<u32>)> {
+        let (device, queue) = headless::device()?;
+        let buffer = |label: &str, contents: &[u32]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(contents),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            })
+        };
+        let indices = (0..keys.len() as u32).collect::<Vec<_>>();
+        let key_buffer = buffer("Keys", keys);
+        let value_buffer = buffer("Values", &indices);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Sort Test Encoder"),
+        });
+        sort(
+            &device,
+            &mut encoder,
+            &key_buffer,
+            &value_buffer,
+            keys.len() as u32,
+        );
+        queue.submit(Some(encoder.finish()));
+        Some((
+            gpu::read_buffer(&device, &queue, &key_buffer, keys.len()),
+            gpu::read_buffer(&device, &queue, &value_buffer, keys.len()),
+        ))
+    }
+
+    fn check(keys: Vec<u32>) {
+        let Some((sorted_keys, sorted_values)) = sort_on_gpu(&keys) else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // The stable CPU sort moves the original indices the same way
+        let mut expected = keys.iter().copied().zip(0u32..).collect::<Vec<_>>();
+        expected.sort_by_key(|&(key, _)| key);
+        let (expected_keys, expected_values): (Vec<_>, Vec<_>) = expected.into_iter().unzip();
+        assert_eq!(sorted_keys, expected_keys);
+        assert_eq!(sorted_values, expected_values);
+    }
+
+    #[test]
+    fn sorts_random_keys() {
+        let mut rng = StdRng::seed_from_u64(1);
+        check((0..100_000).map(|_| rng.gen()).collect());
+    }
+
+    #[test]
+    fn keeps_equal_keys_in_order() {
+        let mut rng = StdRng::seed_from_u64(2);
+        check((0..50_000).map(|_| rng.gen_range(0..100)).collect());
+    }
+
+    #[test]
+    fn sorts_partial_blocks() {
+        check(vec![5, 3, u32::MAX, 0, 3]);
+        check((0..BLOCK_SIZE + 1).rev().collect());
+    }
+}
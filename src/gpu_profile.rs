@@ -0,0 +1,165 @@
+use nannou::prelude::*;
+use nannou::wgpu;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use tracing::{debug, error, info, warn};
+use wgpu_profiler::{GpuProfiler, GpuTimerScopeResult};
+
+use crate::locale::text;
+use crate::theme::Hud;
+
+const LINE_HEIGHT: f32 = 14.0;
+const WIDTH: f32 = 240.0;
+const MARGIN: f32 = 10.0;
+// Frames kept for the trace, ten seconds at 60fps, after which it's written
+const TRACE_FRAMES: usize = 600;
+// Frames whose timings may still be on their way back
+const PENDING_FRAMES: usize = 4;
+
+/// GPU time spent in each pass of the frame, timed with timestamp queries, for
+/// --gpu-profile. Listed under the frame graph (F3), which only has CPU times, and with
+/// --gpu-trace written out as a Chrome trace, for chrome://tracing or Perfetto.
+///
+/// Timings come back a few frames late. Adapters without timestamp queries make none.
+pub struct GpuProfile {
+    // Borrowed from the views too, and only ever around a scope's start or end
+    profiler: RefCell<GpuProfiler>,
+    // Whether the device has timestamp queries to time with
+    timed: bool,
+    // Nesting depth, label and milliseconds of each scope in the latest frame timed
+    latest: Vec<(usize, String, f64)>,
+    trace: Option<(PathBuf, Vec<GpuTimerScopeResult>)>,
+    traced_frames: usize,
+}
+
+/// The timestamp query features the profiler needs that `adapter` has, for the device to be
+/// requested with.
+pub fn features(adapter: &wgpu::Adapter) -> wgpu::Features {
+    let features = adapter.features() & GpuProfiler::ALL_WGPU_TIMER_FEATURES;
+    if !features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+        warn!("The adapter can't time GPU work, --gpu-profile won't show anything");
+    }
+    features
+}
+
+/// Encode `f` inside a scope named `label`, if profiling, and plainly otherwise.
+pub fn scope<R>(
+    profile: Option<&GpuProfile>,
+    label: &str,
+    encoder: &mut wgpu::CommandEncoder,
+    device: &wgpu::Device,
+    f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+) -> R {
+    let Some(profile) = profile else {
+        return f(encoder);
+    };
+    profile
+        .profiler
+        .borrow_mut()
+        .begin_scope(label, encoder, device);
+    let result = f(encoder);
+    profile.profiler.borrow_mut().end_scope(encoder);
+    result
+}
+
+impl GpuProfile {
+    pub fn new(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        trace: Option<PathBuf>,
+    ) -> Self {
+        GpuProfile {
+            profiler: RefCell::new(GpuProfiler::new(adapter, device, queue, PENDING_FRAMES)),
+            timed: device.features().contains(wgpu::Features::TIMESTAMP_QUERY),
+            latest: Vec::new(),
+            trace: trace.map(|path| (path, Vec::new())),
+            traced_frames: 0,
+        }
+    }
+
+    /// Copy the timings of the scopes in `encoder` out, last thing before it's finished.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.profiler.borrow_mut().resolve_queries(encoder);
+    }
+
+    /// Close the frame, once everything in it is submitted, and pick up the timings of the
+    /// oldest one that's come back.
+    pub fn end_frame(&mut self) {
+        let profiler = self.profiler.get_mut();
+        if profiler.end_frame().is_err() {
+            error!("GPU profiler frame ended with scopes open or unresolved");
+        }
+        let Some(results) = profiler.process_finished_frame() else {
+            return;
+        };
+        self.latest.clear();
+        flatten(&results, 0, &mut self.latest);
+        for (depth, label, ms) in &self.latest {
+            debug!("GPU {:indent$}{label}: {ms:.3} ms", "", indent = depth * 2);
+        }
+
+        if let Some((_, frames)) = &mut self.trace {
+            frames.extend(results);
+            self.traced_frames += 1;
+            if self.traced_frames == TRACE_FRAMES {
+                self.write_trace();
+            }
+        }
+    }
+
+    /// Write the frames traced so far, and stop tracing.
+    pub fn write_trace(&mut self) {
+        let Some((path, frames)) = self.trace.take() else {
+            return;
+        };
+        match wgpu_profiler::chrometrace::write_chrometrace(&path, &frames) {
+            Ok(()) => info!(
+                "Saved a GPU trace of {} frames to {}",
+                self.traced_frames,
+                path.display()
+            ),
+            Err(err) => error!("Failed to save {}: {}", path.display(), err),
+        }
+    }
+
+    /// List the latest timings in the bottom left corner.
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud) {
+        let rows = self.latest.len().max(1);
+        let panel = Rect::from_w_h(WIDTH, rows as f32 * LINE_HEIGHT + 2.0 * MARGIN)
+            .bottom_left_of(window.pad(MARGIN));
+        draw.rect().xy(panel.xy()).wh(panel.wh()).color(hud.panel);
+        let text_area = panel.pad(MARGIN);
+        let lines = match (self.latest.is_empty(), self.timed) {
+            (true, false) => vec![text().gpu_untimed.clone()],
+            (true, true) => vec![text().gpu_waiting.clone()],
+            (false, _) => self
+                .latest
+                .iter()
+                .map(|(depth, label, ms)| {
+                    format!("{:indent$}{label} {ms:.2} ms", "", indent = depth * 2)
+                })
+                .collect(),
+        };
+        for (row, line) in lines.iter().enumerate() {
+            let rect = Rect::from_w_h(text_area.w(), LINE_HEIGHT)
+                .top_left_of(text_area)
+                .shift_y(-LINE_HEIGHT * row as f32);
+            draw.text(line)
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .font_size(12)
+                .left_justify()
+                .no_line_wrap()
+                .color(hud.text);
+        }
+    }
+}
+
+fn flatten(results: &[GpuTimerScopeResult], depth: usize, lines: &mut Vec<(usize, String, f64)>) {
+    for result in results {
+        let ms = (result.time.end - result.time.start) * 1000.0;
+        lines.push((depth, result.label.clone(), ms));
+        flatten(&result.nested_scopes, depth + 1, lines);
+    }
+}
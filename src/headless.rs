@@ -0,0 +1,17 @@
+use nannou::wgpu;
+
+/// A device with no window attached, for benchmarks and tests. `None` if no adapter is
+/// available, so callers can skip instead of failing on machines without a GPU.
+pub fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))?;
+    let descriptor = wgpu::DeviceDescriptor {
+        label: Some("Headless Device"),
+        features: wgpu::Features::empty(),
+        limits: adapter.limits(),
+    };
+    pollster::block_on(adapter.request_device(&descriptor, None)).ok()
+}
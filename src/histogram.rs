@@ -0,0 +1,292 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::locale::{fill, text};
+use crate::resources::GpuResources;
+use crate::theme::Hud;
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+// Must match BINS in histogram_shader.wgsl
+const BINS: usize = 64;
+const WORKGROUP_SIZE: u32 = 256;
+// Frames between readings
+const INTERVAL: u32 = 10;
+// Where the range starts, about the default speed limit
+const START_TOP: f32 = 0.01;
+const PLOT_WIDTH: f32 = 200.0;
+const PLOT_HEIGHT: f32 = 100.0;
+const MARGIN: f32 = 10.0;
+
+wgsl_struct! {
+    // Must match `HistogramParams` in histogram_shader.wgsl
+    struct HistogramParams {
+        top: f32,
+    }
+}
+
+/// How the particles' speeds are spread, counted into bins on the GPU and drawn in the bottom
+/// right corner with the speed limit marked and the 2D Maxwell-Boltzmann distribution of the
+/// same mean energy over it, for checking velocity clamping or showing a gas settle. Toggled
+/// with H.
+///
+/// Like `PressureGauge` the bins are mapped asynchronously, so readings arrive a frame or two
+/// late. The range follows the speeds, doubling when too many land in the last bin and
+/// halving when the top of it is empty.
+pub struct SpeedHistogram {
+    pub visible: bool,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<HistogramParams>,
+    bins_buffer: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Frames since the last reading was taken
+    frames: u32,
+    // The range of the reading in flight
+    pending: Option<f32>,
+    mapped: Arc<AtomicBool>,
+    // The last reading and the speed at the top of its range
+    counts: Vec<u32>,
+    shown_top: f32,
+    top: f32,
+}
+
+impl SpeedHistogram {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, visible: bool) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "histogram_shader",
+            include_str!("./shaders/histogram_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_rw(1)
+            .uniform(2)
+            .uniform(3)
+            .build(device, "Histogram");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Histogram Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Histogram Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Histogram Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "count",
+            })
+        });
+
+        let size = (BINS * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+        let bins_buffer = resources.buffer(
+            device,
+            "Speed Histogram Buffer",
+            size,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        );
+        let staging = resources.buffer(
+            device,
+            "Speed Histogram Readback Buffer",
+            size,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        );
+        let params_buffer = UniformBuffer::new(device, resources, "Histogram Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &bins_buffer, &params_buffer);
+
+        SpeedHistogram {
+            visible,
+            pipeline,
+            bindings,
+            params_buffer,
+            bins_buffer,
+            staging,
+            bind_groups,
+            frames: 0,
+            pending: None,
+            mapped: Arc::default(),
+            counts: vec![0; BINS],
+            shown_top: START_TOP,
+            top: START_TOP,
+        }
+    }
+
+    /// Rebind to a new particle buffer.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.bins_buffer,
+            &self.params_buffer,
+        );
+    }
+
+    /// Count a simulated frame, and every `INTERVAL` frames while shown bin the speeds and copy
+    /// them out. Returns whether a copy was encoded, in which case call `map` after submitting.
+    pub fn encode(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        particle_count: u32,
+    ) -> bool {
+        self.frames += 1;
+        if !self.visible || self.frames < INTERVAL || self.pending.is_some() {
+            return false;
+        }
+        self.params_buffer
+            .write(queue, &HistogramParams { top: self.top });
+        encoder.clear_buffer(&self.bins_buffer, 0, None);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Histogram Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+            compute_pass.dispatch_workgroups(particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.bins_buffer, 0, &self.staging, 0, self.staging.size());
+        self.pending = Some(self.top);
+        self.frames = 0;
+        true
+    }
+
+    pub fn map(&self) {
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("Failed to map the speed histogram: {}", err),
+            });
+    }
+
+    /// Pick up the reading in flight if it has arrived, and fit the next one's range to it.
+    pub fn poll(&mut self) {
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let Some(top) = self.pending.take() else {
+            return;
+        };
+        {
+            let data = self.staging.slice(..).get_mapped_range();
+            self.counts.copy_from_slice(bytemuck::cast_slice(&data));
+        }
+        self.staging.unmap();
+        self.shown_top = top;
+
+        let total = self.counts.iter().sum::<u32>();
+        let overflow = self.counts[BINS - 1];
+        let highest = self.counts.iter().rposition(|&count| count != 0);
+        if overflow as f32 > total as f32 * 0.01 {
+            self.top *= 2.0;
+        } else if highest.is_some_and(|bin| bin < BINS / 4) {
+            self.top = (self.top * 0.5).max(f32::EPSILON);
+        }
+    }
+
+    /// Bars in the bottom right corner, the speed limit in orange and the 2D Maxwell-Boltzmann
+    /// distribution of the same mean squared speed in the text colour.
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud, max_speed: f32) {
+        let total = self.counts.iter().sum::<u32>();
+        if total == 0 {
+            return;
+        }
+        let area = Rect::from_w_h(PLOT_WIDTH, PLOT_HEIGHT).bottom_right_of(window.pad(MARGIN));
+        draw.rect().xy(area.xy()).wh(area.wh()).color(hud.panel);
+
+        let bin_width = self.shown_top / BINS as f32;
+        // Fractions of the particles per bin, the distribution's scale too
+        let fractions = self
+            .counts
+            .iter()
+            .map(|&count| count as f32 / total as f32)
+            .collect::<Vec<_>>();
+        // In 2D the speeds of a gas at rest follow f(v) = v / s exp(-v^2 / 2s), s = <v^2> / 2
+        let mean_square = fractions
+            .iter()
+            .enumerate()
+            .map(|(bin, fraction)| fraction * ((bin as f32 + 0.5) * bin_width).powi(2))
+            .sum::<f32>();
+        let spread = (mean_square * 0.5).max(f32::EPSILON);
+        let expected = |speed: f32| speed / spread * (-speed * speed / (2.0 * spread)).exp();
+        let peak = (spread.sqrt()).min(self.shown_top);
+        let max_fraction = fractions
+            .iter()
+            .copied()
+            .fold(expected(peak) * bin_width, f32::max);
+
+        let bar_width = area.w() / BINS as f32;
+        for (bin, fraction) in fractions.iter().enumerate() {
+            let height = fraction / max_fraction * area.h();
+            draw.rect()
+                .x_y(
+                    area.left() + (bin as f32 + 0.5) * bar_width,
+                    area.bottom() + height * 0.5,
+                )
+                .w_h(bar_width, height)
+                .color(rgba(0.4, 0.8, 1.0, 0.8));
+        }
+
+        let curve = (0..=BINS * 2).map(|step| {
+            let speed = step as f32 / (BINS * 2) as f32 * self.shown_top;
+            pt2(
+                area.left() + speed / self.shown_top * area.w(),
+                area.bottom() + expected(speed) * bin_width / max_fraction * area.h(),
+            )
+        });
+        draw.polyline().weight(1.5).color(hud.text).points(curve);
+
+        if max_speed < self.shown_top {
+            let x = area.left() + max_speed / self.shown_top * area.w();
+            draw.line()
+                .start(pt2(x, area.bottom()))
+                .end(pt2(x, area.top()))
+                .weight(1.5)
+                .color(ORANGE);
+        }
+
+        let label = Rect::from_w_h(area.w(), 14.0).above(area);
+        draw.text(&fill(
+            &text().speed_range,
+            &[
+                ("max", &format!("{:.2e}", self.shown_top)),
+                ("rms", &format!("{:.2e}", mean_square.sqrt())),
+            ],
+        ))
+        .xy(label.xy())
+        .wh(label.wh())
+        .left_justify()
+        .font_size(12)
+        .color(hud.text);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    bins_buffer: &wgpu::Buffer,
+    params_buffer: &UniformBuffer<HistogramParams>,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                bins_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    })
+}
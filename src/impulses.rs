@@ -0,0 +1,213 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most impulses that can go off in one frame, the size of the array in impulse_shader.wgsl.
+/// Any more wait for the next frame.
+pub const MAX_IMPULSES: usize = 16;
+
+/// A one-shot kick outwards from a point, hardest at the centre and fading to nothing at
+/// `radius`, or inwards with a negative strength.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Impulse {
+    pub position: [f32; 2],
+    // Velocity added at the centre, in domain units per frame
+    pub strength: f32,
+    pub radius: f32,
+}
+
+/// The impulses I and the middle mouse button set off at the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpulseConfig {
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl Default for ImpulseConfig {
+    fn default() -> Self {
+        ImpulseConfig {
+            strength: 0.01,
+            radius: 0.3,
+        }
+    }
+}
+
+impl ImpulseConfig {
+    pub fn at(&self, position: [f32; 2]) -> Impulse {
+        Impulse {
+            position,
+            strength: self.strength,
+            radius: self.radius,
+        }
+    }
+}
+
+/// Parse `strength[,radius]`, e.g. `0.01,0.3`.
+pub fn parse_impulse(s: &str) -> Result<ImpulseConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid impulse `{s}`"))?;
+    let radius = match values[..] {
+        [_] => ImpulseConfig::default().radius,
+        [_, radius] if radius > 0.0 => radius,
+        _ => return Err(format!("expected an impulse like 0.01,0.3, got `{s}`")),
+    };
+    Ok(ImpulseConfig {
+        strength: values[0],
+        radius,
+    })
+}
+
+wgsl_struct! {
+    // Must match `ImpulseParams` in impulse_shader.wgsl
+    struct ImpulseParams {
+        particle_count: u32,
+        impulse_count: u32,
+    }
+}
+
+/// Radial kicks queued with `push`, each applied to the particles' velocities once on the
+/// next frame, e.g. for bursts in time with music. Skipped on frames without any.
+pub struct Impulses {
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ImpulseParams>,
+    // x, y, strength and radius of each impulse this frame
+    impulse_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    pending: Vec<Impulse>,
+}
+
+impl Impulses {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "impulse_shader",
+            include_str!("./shaders/impulse_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .uniform(3)
+            .build(device, "Impulse");
+        let params_buffer = UniformBuffer::new(device, resources, "Impulse Params Buffer");
+        let impulse_buffer =
+            resources.uniform::<[[f32; 4]; MAX_IMPULSES]>(device, "Impulse Buffer");
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &impulse_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Impulse Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Impulse Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Impulse Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "main",
+            })
+        });
+
+        Impulses {
+            pipeline,
+            bindings,
+            params_buffer,
+            impulse_buffer,
+            bind_groups,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Apply `impulse` on the next frame.
+    pub fn push(&mut self, impulse: Impulse) {
+        self.pending.push(impulse);
+    }
+}
+
+impl Stage for Impulses {
+    fn kind(&self) -> StageKind {
+        StageKind::Impulses
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let count = self.pending.len().min(MAX_IMPULSES);
+        let mut packed = [[0.0f32; 4]; MAX_IMPULSES];
+        for (slot, impulse) in packed.iter_mut().zip(self.pending.drain(..count)) {
+            let [x, y] = impulse.position;
+            *slot = [x, y, impulse.strength, impulse.radius];
+        }
+        let params = ImpulseParams {
+            particle_count: frame.particle_count,
+            impulse_count: count as u32,
+        };
+        self.params_buffer.write(frame.queue, &params);
+        frame
+            .queue
+            .write_buffer(&self.impulse_buffer, 0, bytemuck::cast_slice(&packed));
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Impulse Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.impulse_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ImpulseParams>,
+    impulse_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                impulse_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+            ],
+        )
+    })
+}
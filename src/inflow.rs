@@ -0,0 +1,279 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// An edge of the domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Edge {
+    #[default]
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl Edge {
+    /// The direction pointing into the domain from this edge.
+    pub fn inward(self) -> [f32; 2] {
+        match self {
+            Edge::Left => [1.0, 0.0],
+            Edge::Right => [-1.0, 0.0],
+            Edge::Bottom => [0.0, 1.0],
+            Edge::Top => [0.0, -1.0],
+        }
+    }
+}
+
+/// A stream through the domain, in at one edge and out at the opposite one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InflowConfig {
+    // Where particles come in, leaving by the opposite edge
+    pub edge: Edge,
+    // How fast they come in, in domain units per step like `SpeedLimits`
+    pub speed: f32,
+    // Fraction of each particle's velocity turned to the stream's each frame, so the stream
+    // carries the flock along, 0 to leave it to the boids once in
+    pub drive: f32,
+}
+
+impl Default for InflowConfig {
+    fn default() -> Self {
+        InflowConfig {
+            edge: Edge::Left,
+            speed: 0.004,
+            drive: 0.02,
+        }
+    }
+}
+
+/// Parse `edge[,speed[,drive]]`, e.g. `left,0.004,0.02`.
+pub fn parse_inflow(s: &str) -> Result<InflowConfig, String> {
+    let mut parts = s.split(',').map(str::trim);
+    let edge = match parts.next() {
+        Some("left") => Edge::Left,
+        Some("right") => Edge::Right,
+        Some("bottom") => Edge::Bottom,
+        Some("top") => Edge::Top,
+        _ => {
+            return Err(format!(
+                "expected left, right, bottom or top to come in at, got `{s}`"
+            ))
+        }
+    };
+    let values = parts
+        .map(|n| n.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid inflow `{s}`"))?;
+    let default = InflowConfig::default();
+    let (speed, drive) = match values[..] {
+        [] => (default.speed, default.drive),
+        [speed] => (speed, default.drive),
+        [speed, drive] => (speed, drive),
+        _ => {
+            return Err(format!(
+                "expected an inflow like left,0.004,0.02, got `{s}`"
+            ))
+        }
+    };
+    if speed <= 0.0 || !(0.0..=1.0).contains(&drive) {
+        return Err(format!(
+            "expected a speed over 0 and a drive of 0 to 1, got `{s}`"
+        ));
+    }
+    Ok(InflowConfig { edge, speed, drive })
+}
+
+wgsl_struct! {
+    // Must match `InflowParams` in inflow_shader.wgsl
+    struct InflowParams {
+        inward: [f32; 2],
+        speed: f32,
+        drive: f32,
+        frame: u32,
+    }
+}
+
+/// A wind tunnel: particles reaching the outflow edge leave and come straight back in at a
+/// random point along the inflow edge opposite, moving into the domain at the stream's
+/// speed, so the same particles stream past the obstacles over and over. With a drive the
+/// stream also keeps steering everything its way, as wind would.
+///
+/// Particles are only moved, never added or removed, so this works with compaction and
+/// needs nothing indexed like the particles.
+pub struct Inflow {
+    pub config: InflowConfig,
+    pipeline: wgpu::ComputePipeline,
+    params_buffer: UniformBuffer<InflowParams>,
+    bindings: BindingLayout,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Inflow {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: InflowConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "inflow_shader",
+            include_str!("./shaders/inflow_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .build(device, "Inflow");
+        let params_buffer = UniformBuffer::new(device, resources, "Inflow Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Inflow Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Inflow Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Inflow Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "stream",
+            })
+        });
+
+        Inflow {
+            config,
+            pipeline,
+            params_buffer,
+            bindings,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for Inflow {
+    fn kind(&self) -> StageKind {
+        StageKind::Inflow
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = InflowParams {
+            inward: self.config.edge.inward(),
+            speed: self.config.speed.max(0.0),
+            drive: self.config.drive.clamp(0.0, 1.0),
+            frame: frame.frame,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Inflow Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<InflowParams>,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_variant::SimVariant;
+    use crate::{gpu, headless, Particle, SimParams};
+
+    #[test]
+    fn brings_particles_leaving_back_in_at_the_opposite_edge() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // Coming in on the right, the first half at the outflow edge on the left, the rest
+        // midstream and still
+        let particles = (0..200)
+            .map(|i| Particle {
+                position: [if i < 100 { -0.999 } else { 0.0 }, i as f32 / 200.0 - 0.5],
+                velocity: [0.0; 2],
+            })
+            .collect::<Vec<_>>();
+        let mut resources = GpuResources::new(&device, &particles);
+        let config = InflowConfig {
+            edge: Edge::Right,
+            speed: 0.004,
+            drive: 0.25,
+        };
+        let mut inflow = Inflow::new(&device, &mut resources, config);
+        let params = SimParams {
+            particle_count: 200,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        queue.write_buffer(&resources.params, 0, bytemuck::bytes_of(&params));
+        let frame = FrameContext {
+            device: &device,
+            queue: &queue,
+            variant: SimVariant::default(),
+            particle_count: 200,
+            substeps: 1,
+            time: 0.0,
+            frame: 0,
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Inflow Test Encoder"),
+        });
+        inflow.encode(&frame, &mut encoder, &mut resources);
+        queue.submit(Some(encoder.finish()));
+        let streamed = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 200);
+
+        // Every one that left came back in along the right edge, spread across it and heading
+        // left at the stream's speed
+        let (entered, midstream) = streamed.split_at(100);
+        for p in entered {
+            assert!((p.position[0] - 0.995).abs() < 1e-6, "{p:?}");
+            assert!(p.position[1].abs() <= 1.0, "{p:?}");
+            assert_eq!(p.velocity[0], -0.004);
+            assert!(p.velocity[1].abs() <= 0.0004 + 1e-9, "{p:?}");
+        }
+        let ys = entered.iter().map(|p| p.position[1]);
+        let spread = ys.clone().fold(f32::MIN, f32::max) - ys.fold(f32::MAX, f32::min);
+        assert!(spread > 1.0, "{spread}");
+
+        // The rest stay where they are, steered a quarter of the way to the stream
+        for (p, before) in midstream.iter().zip(&particles[100..]) {
+            assert_eq!(p.position, before.position);
+            assert!((p.velocity[0] + 0.001).abs() < 1e-9, "{p:?}");
+            assert_eq!(p.velocity[1], 0.0);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use nannou::winit::event::WindowEvent;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::settings::Settings;
+
+// How long each update sleeps while suspended, before checking the window again
+pub const SUSPENDED_POLL: Duration = Duration::from_millis(100);
+
+/// Unattended running, for gallery machines, see `Installation`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstallationConfig {
+    // Frames slower than this, in milliseconds, count towards a restart
+    pub watchdog_ms: f32,
+    // Seconds frames have to stay that slow in a row before restarting
+    pub watchdog_seconds: f32,
+    // Settings file loaded as a preset on restarting, the settings launched with when unset
+    pub preset: Option<PathBuf>,
+}
+
+impl Default for InstallationConfig {
+    fn default() -> Self {
+        InstallationConfig {
+            watchdog_ms: 100.0,
+            watchdog_seconds: 30.0,
+            preset: None,
+        }
+    }
+}
+
+/// Parse `ms[,seconds]`, e.g. `100,30`.
+pub fn parse_installation(s: &str) -> Result<InstallationConfig, String> {
+    let (watchdog_ms, seconds) = match s.split_once(',') {
+        Some((watchdog_ms, seconds)) => (watchdog_ms, Some(seconds)),
+        None => (s, None),
+    };
+    let watchdog_ms = watchdog_ms
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid watchdog frame time `{watchdog_ms}`"))?;
+    let watchdog_seconds = match seconds {
+        Some(seconds) => seconds
+            .trim()
+            .parse::<f32>()
+            .map_err(|_| format!("invalid watchdog time `{seconds}`"))?,
+        None => InstallationConfig::default().watchdog_seconds,
+    };
+    if !(watchdog_ms >= 0.0 && watchdog_seconds > 0.0) {
+        return Err(format!(
+            "expected a frame time of 0ms or more and a positive number of seconds, got `{s}`"
+        ));
+    }
+    Ok(InstallationConfig {
+        watchdog_ms,
+        watchdog_seconds,
+        preset: None,
+    })
+}
+
+/// Keeps an installation running unattended, for --installation: nothing is simulated or
+/// drawn while the window is minimised or hidden behind others, until it's back on show or
+/// focused, and once frames have stayed slower than the watchdog's threshold for long enough,
+/// the particles are scattered again and the preset loaded, as something has gone wrong.
+pub struct Installation {
+    pub config: InstallationConfig,
+    // What a restart loads without a preset of its own, or when it can't be read
+    pub launched: Settings,
+    minimized: bool,
+    occluded: bool,
+    // Set on coming back, as the first frame after spans the whole time suspended
+    resumed: bool,
+    // Real time frames have been over the threshold in a row
+    slow: Duration,
+}
+
+impl Installation {
+    pub fn new(config: InstallationConfig, launched: Settings) -> Self {
+        Installation {
+            config,
+            launched,
+            minimized: false,
+            occluded: false,
+            resumed: false,
+            slow: Duration::ZERO,
+        }
+    }
+
+    pub fn suspended(&self) -> bool {
+        self.minimized || self.occluded
+    }
+
+    /// Follow the window being minimised, hidden and shown again.
+    pub fn event(&mut self, event: &WindowEvent) {
+        let suspended = self.suspended();
+        match event {
+            // Minimising resizes to nothing on some platforms
+            WindowEvent::Resized(size) => self.minimized = size.width == 0 || size.height == 0,
+            WindowEvent::Occluded(occluded) => self.occluded = *occluded,
+            // Whatever the platform said last, a focused window is on show
+            WindowEvent::Focused(true) => {
+                self.minimized = false;
+                self.occluded = false;
+            }
+            _ => return,
+        }
+        if suspended && !self.suspended() {
+            self.resumed = true;
+            self.slow = Duration::ZERO;
+        }
+    }
+
+    /// Feed in the last frame time, while not suspended, returns whether to restart.
+    pub fn watch(&mut self, frame_time: Duration) -> bool {
+        // A threshold of 0 only suspends
+        if std::mem::take(&mut self.resumed) || self.config.watchdog_ms <= 0.0 {
+            return false;
+        }
+        if frame_time.as_secs_f32() * 1000.0 <= self.config.watchdog_ms {
+            self.slow = Duration::ZERO;
+            return false;
+        }
+        self.slow += frame_time;
+        if self.slow.as_secs_f32() < self.config.watchdog_seconds {
+            return false;
+        }
+        self.slow = Duration::ZERO;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::winit::dpi::PhysicalSize;
+
+    #[test]
+    fn suspends_while_hidden_and_restarts_when_frames_stay_slow() {
+        let config = parse_installation("100,1").unwrap();
+        assert_eq!(config.watchdog_seconds, 1.0);
+        assert!(parse_installation("100,0").is_err());
+        let mut installation = Installation::new(config, Settings::default());
+
+        installation.event(&WindowEvent::Resized(PhysicalSize::new(0, 0)));
+        assert!(installation.suspended());
+        installation.event(&WindowEvent::Focused(true));
+        assert!(!installation.suspended());
+        // The frame spanning the time suspended doesn't count
+        assert!(!installation.watch(Duration::from_secs(5)));
+
+        let slow = Duration::from_millis(400);
+        assert!(!installation.watch(slow));
+        assert!(!installation.watch(slow));
+        // A fast frame starts the count again
+        assert!(!installation.watch(Duration::from_millis(16)));
+        assert!(!installation.watch(slow));
+        assert!(!installation.watch(slow));
+        assert!(installation.watch(slow));
+        assert!(!installation.watch(slow));
+    }
+}
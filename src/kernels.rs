@@ -0,0 +1,182 @@
+use clap::ValueEnum;
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+// What each kernel is appended to, documenting the bindings it gets
+const INTERFACE: &str = include_str!("./shaders/kernel_interface.wgsl");
+// Starts the line naming the stage a kernel runs after
+const AFTER: &str = "// after:";
+
+wgsl_struct! {
+    // Must match `KernelParams` in kernel_interface.wgsl
+    struct KernelParams {
+        particle_count: u32,
+        frame: u32,
+        time: f32,
+    }
+}
+
+/// A user compute kernel from the --kernels folder, changing the particles in place without
+/// touching the Rust code. See shaders/kernel_interface.wgsl for its bindings.
+pub struct Kernel {
+    // Its file name
+    pub name: String,
+    // The stage it asked to run after
+    pub after: Option<StageKind>,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<KernelParams>,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Kernel {
+    /// Compile a kernel, returning the error when it doesn't compile or names no stage to run
+    /// after.
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        name: &str,
+        source: &str,
+    ) -> Result<Self, String> {
+        let after = source
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(AFTER))
+            .map(|stage| {
+                StageKind::from_str(stage.trim(), true)
+                    .map_err(|_| format!("no stage named `{}` to run after", stage.trim()))
+            })
+            .transpose()?;
+        let shader = diagnostics::try_shader(
+            device,
+            &format!("kernel_{name}"),
+            &format!("{INTERFACE}\n{source}"),
+        )?;
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Kernel");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Kernel Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            diagnostics::try_checked(device, &format!("Kernel Pipeline ({name})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Kernel Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: "main",
+                })
+            })?;
+        let params_buffer =
+            UniformBuffer::new(device, resources, &format!("Kernel {name} Params Buffer"));
+        let bind_groups = bind(device, resources, &bindings, &params_buffer);
+
+        Ok(Kernel {
+            name: name.to_owned(),
+            after,
+            pipeline,
+            bindings,
+            params_buffer,
+            bind_groups,
+        })
+    }
+}
+
+/// The kernels in the `.wgsl` files in `dir`, in order of name. Those that don't compile are
+/// left out with a message, and a missing folder has none.
+pub fn load(device: &wgpu::Device, resources: &mut GpuResources, dir: &Path) -> Vec<Kernel> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wgsl")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let kernel = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|source| Kernel::new(device, resources, &name, &source));
+            match kernel {
+                Ok(kernel) => Some(kernel),
+                Err(err) => {
+                    warn!("Leaving out kernel {}: {}", path.display(), err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl Stage for Kernel {
+    fn kind(&self) -> StageKind {
+        StageKind::Kernel
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = KernelParams {
+            particle_count: frame.particle_count,
+            frame: frame.frame,
+            time: frame.time,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Kernel Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<KernelParams>,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                resources.flags.as_entire_binding(),
+                resources.params.as_entire_binding(),
+            ],
+        )
+    })
+}
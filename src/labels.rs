@@ -0,0 +1,69 @@
+use nannou::prelude::*;
+
+use crate::camera::Camera;
+use crate::theme::Hud;
+use crate::Particle;
+
+// Zoom from which the debug view labels particles
+const MIN_ZOOM: f32 = 8.0;
+// Most labels drawn, so a crowd in view doesn't bury it in text
+const MAX_LABELS: usize = 300;
+const FONT_SIZE: u32 = 10;
+// Up and to the right of the particle, so the label doesn't cover it
+const OFFSET: [f32; 2] = [10.0, 8.0];
+
+/// Each particle's index next to it, for tracing one across frames while debugging, drawn in
+/// the debug view (F4) once zoomed in far enough. Only particles in view are labelled, the
+/// lowest indices first.
+///
+/// The particles are read back for them every frame, which stalls the GPU for a moment, so
+/// they're only read while labels would be drawn.
+pub struct ParticleLabels {
+    // Index and position in world space of each labelled particle
+    labels: Vec<(u32, Vec2)>,
+}
+
+impl ParticleLabels {
+    pub fn new() -> Self {
+        ParticleLabels { labels: Vec::new() }
+    }
+
+    /// Whether to read the particles back for `label`.
+    pub fn due(&self, debug_view: bool, camera: &Camera) -> bool {
+        debug_view && camera.zoom >= MIN_ZOOM
+    }
+
+    pub fn label(&mut self, particles: &[Particle], camera: &Camera, window: Rect) {
+        self.labels.clear();
+        let view = camera.view();
+        for (index, particle) in particles.iter().enumerate() {
+            let position = Vec2::from(particle.position);
+            // Particles behind a perspective camera would come out mirrored in front of it
+            let behind = (view * position.extend(1.0)).z <= 0.0;
+            if behind || !window.contains(camera.world_to_window(position, window)) {
+                continue;
+            }
+            self.labels.push((index as u32, position));
+            if self.labels.len() == MAX_LABELS {
+                break;
+            }
+        }
+    }
+
+    /// Drop the labels once they're no longer due, so stale ones aren't drawn later.
+    pub fn clear(&mut self) {
+        self.labels.clear();
+    }
+
+    pub fn draw(&self, draw: &Draw, camera: &Camera, window: Rect, hud: Hud) {
+        for &(index, position) in &self.labels {
+            let point = camera.world_to_window(position, window) + Vec2::from(OFFSET);
+            draw.text(&index.to_string())
+                .xy(point)
+                .wh(vec2(60.0, 14.0))
+                .font_size(FONT_SIZE)
+                .left_justify()
+                .color(hud.text);
+        }
+    }
+}
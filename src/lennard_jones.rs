@@ -0,0 +1,250 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match the shader
+const CELL_CAPACITY: u32 = 32;
+const CUTOFF: f32 = 2.5;
+// Keeps the grid buffers to a few tens of MiB at a million particles
+const MAX_GRID_SIZE: u32 = 512;
+// Area of the -1..1 periodic domain
+const DOMAIN_AREA: f32 = 4.0;
+
+/// Lennard-Jones parameters, in reduced units: lengths in particle diameters (sigma), energies
+/// in the well depth (epsilon).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LennardJonesConfig {
+    // Particles per sigma squared. Around 0.8 crystallizes below a temperature of about 0.4
+    pub density: f32,
+    // Held by the thermostat, changed with the up and down arrows
+    pub temperature: f32,
+    // Reduced time units advanced per frame
+    pub time_step: f32,
+}
+
+impl Default for LennardJonesConfig {
+    fn default() -> Self {
+        LennardJonesConfig {
+            density: 0.8,
+            temperature: 0.5,
+            time_step: 0.005,
+        }
+    }
+}
+
+impl LennardJonesConfig {
+    /// Diameter in domain units that gives `density` for this many particles.
+    pub fn sigma(&self, particle_count: u32) -> f32 {
+        (self.density * DOMAIN_AREA / particle_count.max(1) as f32).sqrt()
+    }
+
+    /// RMS speed in domain units per frame at `temperature`, for the thermostat. In 2D the
+    /// mean squared speed is twice the temperature.
+    pub fn target_speed(&self, particle_count: u32) -> f32 {
+        self.sigma(particle_count) * self.time_step * (2.0 * self.temperature).sqrt()
+    }
+
+    fn grid_size(&self, particle_count: u32) -> u32 {
+        // Cells at least a cutoff wide, so the 3x3 block around a particle covers its range
+        ((2.0 / (CUTOFF * self.sigma(particle_count))) as u32).clamp(3, MAX_GRID_SIZE)
+    }
+}
+
+wgsl_struct! {
+    // Must match `LennardJonesParams` in lennard_jones_shader.wgsl
+    struct LennardJonesParams {
+        grid_size: u32,
+        sigma: f32,
+        time_step: f32,
+    }
+}
+
+/// Molecular dynamics in place of the boids step: a Lennard-Jones pair force with a cutoff in
+/// a periodic box, for crystallization and melting demos.
+///
+/// Each substep bins the particles into a uniform grid with fixed-size cells, then sums the
+/// forces from the 3x3 cells around each particle, so the cost is linear in the count.
+pub struct LennardJones {
+    pub config: LennardJonesConfig,
+    clear: wgpu::ComputePipeline,
+    bin: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<LennardJonesParams>,
+    // Indexed by the particle buffer read from, see `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl LennardJones {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: LennardJonesConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "lennard_jones_shader",
+            include_str!("./shaders/lennard_jones_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .storage_rw(4)
+            .storage_rw(5)
+            .build(device, "Lennard-Jones");
+        let params_buffer = UniformBuffer::new(device, resources, "Lennard-Jones Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer, &config);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lennard-Jones Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Lennard-Jones Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Lennard-Jones Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        LennardJones {
+            config,
+            clear: pipeline("clear"),
+            bin: pipeline("bin"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+
+    /// Resize the grid for and rebind to a new particle buffer.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.config,
+        );
+    }
+
+    /// Encode `substeps` steps for the first `particle_count` particles, leaving the result
+    /// in `resources.particles()`. Uses the `SimParams` written by `Simulation::write_params`.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+        particle_count: u32,
+        substeps: u32,
+    ) {
+        // Fewer particles than the capacity spread out, so sigma and the grid follow the count
+        let grid_size = self.config.grid_size(particle_count);
+        let params = LennardJonesParams {
+            grid_size,
+            sigma: self.config.sigma(particle_count),
+            time_step: self.config.time_step,
+        };
+        self.params_buffer.write(queue, &params);
+
+        let cell_workgroups = (grid_size * grid_size).div_ceil(WORKGROUP_SIZE);
+        let particle_workgroups = particle_count.div_ceil(WORKGROUP_SIZE);
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Lennard-Jones Pass"),
+        });
+        for _ in 0..substeps {
+            compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+            compute_pass.set_pipeline(&self.clear);
+            compute_pass.dispatch_workgroups(cell_workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.bin);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.step);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+            resources.swap();
+        }
+    }
+}
+
+impl Stage for LennardJones {
+    fn kind(&self) -> StageKind {
+        StageKind::LennardJones
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        LennardJones::encode(
+            self,
+            frame.queue,
+            encoder,
+            resources,
+            frame.particle_count,
+            frame.substeps,
+        );
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        LennardJones::rebind(self, device, resources);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<LennardJonesParams>,
+    config: &LennardJonesConfig,
+) -> [wgpu::BindGroup; 2] {
+    // The full buffer has the smallest sigma and so the finest grid
+    let grid_size = config.grid_size(resources.capacity());
+    let cells = (grid_size * grid_size) as wgpu::BufferAddress;
+    let u32_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+    let cell_counts = resources.buffer(
+        device,
+        "Lennard-Jones Cell Counts Buffer",
+        cells * u32_size,
+        BufferUsages::STORAGE,
+    );
+    let cell_particles = resources.buffer(
+        device,
+        "Lennard-Jones Cell Particles Buffer",
+        cells * CELL_CAPACITY as wgpu::BufferAddress * u32_size,
+        BufferUsages::STORAGE,
+    );
+    let [a, b] = resources.particle_buffers();
+    [(a, b), (b, a)].map(|(src, dst)| {
+        bindings.bind_group(
+            device,
+            &[
+                src.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                dst.as_entire_binding(),
+                params_buffer.binding(),
+                cell_counts.as_entire_binding(),
+                cell_particles.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,269 @@
+//! The GPU simulation core, shared by the app and the benchmarks: particle buffers,
+//! simulation pipelines and the helpers for building them.
+
+use nannou::rand::Rng;
+
+pub mod bindings;
+pub mod camera;
+pub mod canvas;
+pub mod compaction;
+pub mod constraints;
+pub mod contagion;
+pub mod diagnostics;
+pub mod drag;
+pub mod emitters;
+pub mod ffi;
+pub mod fireworks;
+pub mod flags;
+pub mod forces;
+pub mod freeze;
+pub mod goal;
+pub mod gpu;
+pub mod headless;
+pub mod impulses;
+pub mod inflow;
+pub mod kernels;
+pub mod lennard_jones;
+pub mod level;
+pub mod lifetime;
+pub mod mass;
+pub mod modulation;
+pub mod obstacles;
+pub mod orientation;
+pub mod parameters;
+pub mod particle_layout;
+pub mod particle_sort;
+pub mod particle_system;
+pub mod pbd;
+pub mod physarum;
+pub mod reaction_diffusion;
+pub mod resources;
+pub mod sim_variant;
+pub mod simulation;
+pub mod sleep;
+pub mod species;
+pub mod stages;
+pub mod stamina;
+pub mod stats;
+pub mod stir;
+pub mod territory;
+pub mod text_targets;
+pub mod thermostat;
+pub mod trail;
+pub mod uniform;
+pub mod vector_field;
+pub mod vorticity;
+pub mod wgsl;
+
+// Storage buffer bindings are only guaranteed up to 128 MiB, and the quadratic neighbour
+// search is far too slow well before then anyway
+pub const MAX_PARTICLES: u32 = 1 << 20;
+
+wgsl::wgsl_struct! {
+    // Also the WGSL `Particle`, see common.wgsl in `wgsl::compose`
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    pub struct Particle {
+        pub position: [f32; 2],
+        pub velocity: [f32; 2],
+    }
+}
+
+impl Particle {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Particle {
+            position: [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)],
+            velocity: [rng.gen_range(-0.001..0.001), rng.gen_range(-0.001..0.001)],
+        }
+    }
+}
+
+/// A `Particle` at half precision, each pair of components packed into a `u32` the way WGSL's
+/// `pack2x16float` does, low half first. Half the bandwidth, at about three significant
+/// digits.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HalfParticle {
+    pub position: u32,
+    pub velocity: u32,
+}
+
+impl From<Particle> for HalfParticle {
+    fn from(particle: Particle) -> Self {
+        HalfParticle {
+            position: pack_halves(particle.position),
+            velocity: pack_halves(particle.velocity),
+        }
+    }
+}
+
+impl From<HalfParticle> for Particle {
+    fn from(particle: HalfParticle) -> Self {
+        Particle {
+            position: unpack_halves(particle.position),
+            velocity: unpack_halves(particle.velocity),
+        }
+    }
+}
+
+// Like WGSL's `pack2x16float` and `unpack2x16float`
+pub(crate) fn pack_halves([x, y]: [f32; 2]) -> u32 {
+    to_f16_bits(x) as u32 | (to_f16_bits(y) as u32) << 16
+}
+
+pub(crate) fn unpack_halves(packed: u32) -> [f32; 2] {
+    [
+        from_f16_bits(packed as u16),
+        from_f16_bits((packed >> 16) as u16),
+    ]
+}
+
+// Round to nearest even, like the GPU's conversions
+fn to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity, or a quiet NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // Keeps the top 10 mantissa bits, or fewer for subnormals, rounding the rest away
+    let (kept, shift) = if exponent > 0 {
+        ((exponent as u32) << 23 | mantissa, 13)
+    } else if exponent >= -10 {
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    } else {
+        return sign;
+    };
+    let truncated = kept >> shift;
+    let rest = kept & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let round_up = rest > halfway || (rest == halfway && truncated & 1 == 1);
+    // Rounding up can carry into the exponent, all the way to infinity
+    sign | (truncated + round_up as u32) as u16
+}
+
+fn from_f16_bits(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    let bits = match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            return if sign != 0 { -magnitude } else { magnitude };
+        }
+        0x1f => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | (exponent + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+wgsl::wgsl_struct! {
+    // Also the WGSL `SimParams`, see common.wgsl in `wgsl::compose`
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    pub struct SimParams {
+        // Only the first `particle_count` particles are simulated
+        pub particle_count: u32,
+        // Fraction of a frame advanced per substep
+        pub dt: f32,
+        // x of the right wall when PISTON is on
+        pub piston: f32,
+        // Particles each buffer holds, where the velocities start with separate arrays
+        pub capacity: u32,
+        // See `mass::shader_distribution` and mass.wgsl
+        pub mass_distribution: u32,
+        pub mass_min: f32,
+        pub mass_max: f32,
+        // See `SpeedLimits`
+        pub max_speed: f32,
+        pub min_speed: f32,
+        pub speed_decay: f32,
+        // See `RuleWeights`
+        pub alignment_weight: f32,
+        pub cohesion_weight: f32,
+        pub separation_weight: f32,
+        // See `RegionOfInterest`: 1 when there is one, whether this is one of the extra
+        // substeps only it takes, and what they advance by
+        pub roi: u32,
+        pub roi_only: u32,
+        pub roi_dt: f32,
+        pub roi_min: [f32; 2],
+        pub roi_max: [f32; 2],
+        // Cosine of how far either side of its heading a boid sees, -1 all the way round, see
+        // `Simulation::view_angle`
+        pub view_cos: f32,
+        // See `RuleRadii`
+        pub alignment_radius: f32,
+        pub cohesion_radius: f32,
+        pub separation_radius: f32,
+        // See `LeaderConfig`, a fraction of 0 without leaders
+        pub leader_fraction: f32,
+        pub leader_weight: f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgsl::WgslType;
+
+    #[test]
+    fn particle_is_laid_out_like_wgsl() {
+        assert_eq!(std::mem::size_of::<Particle>(), 16);
+        assert_eq!(std::mem::align_of::<Particle>(), 4);
+        assert_eq!(std::mem::offset_of!(Particle, position), 0);
+        assert_eq!(std::mem::offset_of!(Particle, velocity), 8);
+        assert_eq!(
+            <Particle as WgslType>::SIZE,
+            std::mem::size_of::<Particle>()
+        );
+        assert_eq!(std::mem::size_of::<HalfParticle>(), 8);
+    }
+
+    #[test]
+    fn halves_round_trip_exactly_representable_values() {
+        for pair in [
+            [0.0, 1.0],
+            [-2.5, 0.125],
+            [65504.0, -65504.0],
+            [6.1035156e-5, 0.5],
+        ] {
+            assert_eq!(unpack_halves(pack_halves(pair)), pair);
+        }
+        // Low half first, like pack2x16float
+        assert_eq!(pack_halves([1.0, 0.0]), 0x3c00);
+        assert_eq!(pack_halves([0.0, 1.0]), 0x3c00 << 16);
+    }
+
+    #[test]
+    fn halves_round_to_nearest_even() {
+        // 1 + 2^-11 is halfway between 1 and the next half up, so goes down to the even one
+        assert_eq!(to_f16_bits(1.0 + 2f32.powi(-11)), 0x3c00);
+        // 1 + 3 * 2^-11 is halfway again, this time up to the even one
+        assert_eq!(to_f16_bits(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        // Just over halfway goes up
+        assert_eq!(to_f16_bits(1.0 + 2f32.powi(-11) + 2f32.powi(-20)), 0x3c01);
+        // Rounding up carries into the exponent
+        assert_eq!(to_f16_bits(2.0 - 2f32.powi(-12)), 0x4000);
+    }
+
+    #[test]
+    fn halves_keep_the_edge_cases() {
+        assert_eq!(to_f16_bits(1e6), 0x7c00);
+        assert_eq!(to_f16_bits(-1e6), 0xfc00);
+        assert_eq!(to_f16_bits(f32::INFINITY), 0x7c00);
+        assert!(from_f16_bits(to_f16_bits(f32::NAN)).is_nan());
+        assert_eq!(to_f16_bits(-0.0), 0x8000);
+        // The smallest subnormal, and below half of it to zero
+        assert_eq!(to_f16_bits(2f32.powi(-24)), 0x0001);
+        assert_eq!(from_f16_bits(0x0001), 2f32.powi(-24));
+        assert_eq!(to_f16_bits(2f32.powi(-26)), 0x0000);
+        assert_eq!(from_f16_bits(0x8001), -(2f32.powi(-24)));
+    }
+}
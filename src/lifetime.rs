@@ -0,0 +1,281 @@
+use clap::ValueEnum;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+// The alpha and size after the age and lifetime, then the tint, then the age and lifetime
+// themselves, see `Life` in lifetime_shader.wgsl
+const LOOK_ATTRIBUTES: [wgpu::VertexAttribute; 3] = [
+    wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Float32x2,
+        offset: 8,
+        shader_location: 5,
+    },
+    wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Float32x4,
+        offset: 16,
+        shader_location: 6,
+    },
+    wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Float32x2,
+        offset: 0,
+        shader_location: 7,
+    },
+];
+
+// Bytes per particle in the lives buffer
+const LIFE_SIZE: wgpu::BufferAddress = 32;
+
+/// How far a particle has faded or shrunk, from the fraction of its life it has lived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    /// Not at all, until it dies
+    Constant,
+    Linear,
+    /// Slowly at first, then all at once
+    EaseIn,
+    /// Quickly at first, then lingering
+    EaseOut,
+    /// Smoothstep, slowly at both ends
+    Smooth,
+}
+
+impl Easing {
+    // Must match `ease` in lifetime_shader.wgsl
+    fn shader_index(self) -> u32 {
+        match self {
+            Easing::Constant => 0,
+            Easing::Linear => 1,
+            Easing::EaseIn => 2,
+            Easing::EaseOut => 3,
+            Easing::Smooth => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeConfig {
+    // Average simulated seconds a particle lives before it's respawned
+    pub seconds: f32,
+    // Each lifetime is up to this fraction longer or shorter than `seconds`
+    pub spread: f32,
+    // Fading out to transparent
+    pub fade: Easing,
+    // Shrinking to nothing
+    pub shrink: Easing,
+    // Whether particles come back somewhere random once they die, or stay dead, hidden,
+    // until something like `Fireworks` brings them back
+    pub respawn: bool,
+}
+
+impl LifetimeConfig {
+    pub fn new(seconds: f32) -> Self {
+        LifetimeConfig {
+            seconds,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for LifetimeConfig {
+    fn default() -> Self {
+        LifetimeConfig {
+            seconds: 4.0,
+            spread: 0.5,
+            fade: Easing::Smooth,
+            shrink: Easing::Linear,
+            respawn: true,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `LifetimeParams` in lifetime_shader.wgsl
+    struct LifetimeParams {
+        seconds: f32,
+        spread: f32,
+        fade: u32,
+        shrink: u32,
+        // Simulated seconds since the last frame
+        dt: f32,
+        frame: u32,
+        respawn: u32,
+    }
+}
+
+/// An age and lifetime per particle. Particles that outlive theirs are respawned somewhere
+/// random with a new one, unless `respawn` is off, and are drawn fading and shrinking
+/// towards it along the configured easing curves.
+///
+/// Kept in a buffer of its own, indexed like the particles, like `Orientation`'s spins, so
+/// compaction is left out with it. Ages start over, spread out over a lifetime, when the
+/// buffers are resized.
+pub struct Lifetime {
+    pub config: LifetimeConfig,
+    init: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<LifetimeParams>,
+    lives: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the lives have been drawn
+    needs_init: bool,
+    // `FrameContext::time` last frame, to age by
+    last_time: Option<f32>,
+}
+
+impl Lifetime {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: LifetimeConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "lifetime_shader",
+            include_str!("./shaders/lifetime_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Lifetime");
+        let params_buffer = UniformBuffer::new(device, resources, "Lifetime Params Buffer");
+        let (lives, bind_groups) = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lifetime Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Lifetime Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Lifetime Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Lifetime {
+            config,
+            init: pipeline("init"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            lives,
+            bind_groups,
+            needs_init: true,
+            last_time: None,
+        }
+    }
+
+    /// The instance vertex buffer `set_vertex_buffer` binds, with the alpha and size, each
+    /// 0 to 1, at location 5 and a colour to draw in at 6, unless its alpha is 0.
+    pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: LIFE_SIZE,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &LOOK_ATTRIBUTES,
+        }
+    }
+
+    /// Each particle's `Life` in lifetime_shader.wgsl, for stages that bring particles back
+    /// to life themselves.
+    pub fn lives_buffer(&self) -> &wgpu::Buffer {
+        &self.lives
+    }
+
+    /// Bind the lives as the instance vertex buffer in `slot`.
+    pub fn set_vertex_buffer<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, slot: u32) {
+        render_pass.set_vertex_buffer(slot, self.lives.slice(..));
+    }
+}
+
+impl Stage for Lifetime {
+    fn kind(&self) -> StageKind {
+        StageKind::Lifetime
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        // Nothing ages on the first frame, or when time jumps back on rewind
+        let dt = self.last_time.map_or(0.0, |last| frame.time - last);
+        self.last_time = Some(frame.time);
+        let params = LifetimeParams {
+            seconds: self.config.seconds.max(f32::EPSILON),
+            spread: self.config.spread.clamp(0.0, 1.0),
+            fade: self.config.fade.shader_index(),
+            shrink: self.config.shrink.shader_index(),
+            dt: dt.max(0.0),
+            frame: frame.frame,
+            respawn: self.config.respawn as u32,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Lifetime Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        if std::mem::take(&mut self.needs_init) {
+            compute_pass.set_pipeline(&self.init);
+            compute_pass.dispatch_workgroups(resources.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        compute_pass.set_pipeline(&self.step);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.lives, self.bind_groups) =
+            bind(device, resources, &self.bindings, &self.params_buffer);
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<LifetimeParams>,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let lives = resources.buffer(
+        device,
+        "Lifetime Lives Buffer",
+        resources.capacity() as wgpu::BufferAddress * LIFE_SIZE,
+        BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                lives.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    });
+    (lives, bind_groups)
+}
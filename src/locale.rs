@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+// Languages built in, by the name --locale takes. English is the default of `Messages`
+const SHIPPED: [(&str, &str); 1] = [("es", include_str!("./locales/es.json"))];
+
+/// Everything shown in the overlays and messages, for --locale, with `{name}` where a value
+/// goes in, see `fill`. A locale file is this as JSON, any message left out stays in English,
+/// so a translation can be started with the overlays people see most.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Messages {
+    pub recording_to: String,
+    pub canvas_wiped: String,
+    pub camera: String,
+    pub no_scenes_to_switch: String,
+    pub ignoring_input: String,
+    pub no_user_shaders: String,
+    pub drawing_with: String,
+    pub switch_failed: String,
+    pub saved_scene: String,
+    pub compiled_editor_shader: String,
+    pub too_many_walls: String,
+    pub saved_svg: String,
+    pub save_failed: String,
+    pub saved_canvas: String,
+    pub ignoring_presets: String,
+    pub loaded_preset: String,
+    pub preset_failed: String,
+    pub background_set: String,
+    pub image_failed: String,
+    pub drop_hint: String,
+    pub reloaded: String,
+    pub shader_broken: String,
+    pub playback_finished: String,
+    pub sync_lost: String,
+    pub ignoring_remote_input: String,
+    // The scene browser, F8
+    pub no_scenes_in: String,
+    pub scene_browser_help: String,
+    // The shader editor, F2
+    pub editor_broken: String,
+    pub editor_edited: String,
+    pub editor_help: String,
+    // The GPU timings, --gpu-profile
+    pub gpu_untimed: String,
+    pub gpu_waiting: String,
+    // The speed histogram, H
+    pub speed_range: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        let english = |text: &str| text.to_owned();
+        Messages {
+            recording_to: english("Recording to {path}"),
+            canvas_wiped: english("Canvas wiped"),
+            camera: english("Camera: {projection}"),
+            no_scenes_to_switch: english("No scenes to switch between, see --scenes"),
+            ignoring_input: english(
+                "Ignoring input while following a recording or the sync authority",
+            ),
+            no_user_shaders: english("No user shaders to draw with, see --user-shaders"),
+            drawing_with: english("Drawing with {name} ({number}/{count})"),
+            switch_failed: english("Failed to switch to {path}: {error}"),
+            saved_scene: english("Saved scene {path}"),
+            compiled_editor_shader: english("Compiled the shader from the editor"),
+            too_many_walls: english("Can't draw more than {count} walls"),
+            saved_svg: english("Saved {count} particles to {path}"),
+            save_failed: english("Failed to save {path}: {error}"),
+            saved_canvas: english("Saved the canvas to {path}"),
+            ignoring_presets: english(
+                "Ignoring presets while following a recording or the sync authority",
+            ),
+            loaded_preset: english("Loaded preset {name}"),
+            preset_failed: english("Failed to load preset {name}: {error}"),
+            background_set: english("Background set to {name}"),
+            image_failed: english("Failed to load image {name}"),
+            drop_hint: english("Drop a .toml preset or a .png image, not {name}"),
+            reloaded: english("Reloaded {path}"),
+            shader_broken: english("{path} doesn't compile, still running the last copy that did"),
+            playback_finished: english("Playback finished after {frames} frames"),
+            sync_lost: english(
+                "Lost the sync authority after {frames} frames, carrying on alone: {error}",
+            ),
+            ignoring_remote_input: english(
+                "Ignoring remote input while following a recording or the sync authority",
+            ),
+            no_scenes_in: english("No scenes in {path}"),
+            scene_browser_help: english(
+                "Up/Down to pick, Enter to switch, S to save this setup, F8 to close",
+            ),
+            editor_broken: english("Doesn't compile, the last shader that did keeps running"),
+            editor_edited: english("Edited, Ctrl+Enter to compile"),
+            editor_help: english("Ctrl+Enter to compile, F2 to close"),
+            gpu_untimed: english("The adapter can't time GPU work"),
+            gpu_waiting: english("No GPU timings yet"),
+            speed_range: english("speed 0..{max}  rms {rms}"),
+        }
+    }
+}
+
+/// Messages for `locale`, a language built in, `en` and `es` so far, or the path of a
+/// locale file.
+pub fn load(locale: &str) -> Result<Messages, String> {
+    if locale == "en" {
+        return Ok(Messages::default());
+    }
+    if let Some((_, json)) = SHIPPED.iter().find(|(name, _)| *name == locale) {
+        return serde_json::from_str(json).map_err(|err| format!("Broken locale {locale}: {err}"));
+    }
+    let path = Path::new(locale);
+    let json = fs::read_to_string(path)
+        .map_err(|err| format!("No locale {locale}, expected en, es or a locale file: {err}"))?;
+    serde_json::from_str(&json)
+        .map_err(|err| format!("Failed to load locale {}: {}", path.display(), err))
+}
+
+static MESSAGES: OnceLock<Messages> = OnceLock::new();
+
+/// Show everything in `locale` from here on, English if it can't be loaded. Only the first
+/// call counts, at launch, before anything is shown.
+pub fn set(locale: &str) {
+    let messages = load(locale).unwrap_or_else(|err| {
+        warn!("{}, staying in English", err);
+        Messages::default()
+    });
+    if locale != "en" {
+        info!("Showing messages in {}", locale);
+    }
+    let _ = MESSAGES.set(messages);
+}
+
+/// The messages of the locale set at launch.
+pub fn text() -> &'static Messages {
+    MESSAGES.get_or_init(Messages::default)
+}
+
+/// `template` with each `{name}` in `values` replaced by its value.
+pub fn fill(template: &str, values: &[(&str, &dyn Display)]) -> String {
+    values
+        .iter()
+        .fold(template.to_owned(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), &value.to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn shipped_locales_translate_every_message_with_its_placeholders() {
+        let english = serde_json::to_value(Messages::default()).unwrap();
+        let placeholders = |text: &str| {
+            let mut names = text
+                .split('{')
+                .skip(1)
+                .filter_map(|part| part.split_once('}'))
+                .map(|(name, _)| name.to_owned())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        for (name, json) in SHIPPED {
+            let given: Value = serde_json::from_str(json).unwrap();
+            let given = given.as_object().unwrap();
+            for (key, text) in english.as_object().unwrap() {
+                let translated = given
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .unwrap_or_else(|| panic!("{name} leaves out {key}"));
+                assert_eq!(
+                    placeholders(translated),
+                    placeholders(text.as_str().unwrap()),
+                    "{name} {key}"
+                );
+            }
+            assert_eq!(given.len(), english.as_object().unwrap().len(), "{name}");
+            load(name).unwrap();
+        }
+
+        assert_eq!(
+            fill(
+                &Messages::default().saved_svg,
+                &[("count", &3), ("path", &"out.svg")]
+            ),
+            "Saved 3 particles to out.svg"
+        );
+    }
+}
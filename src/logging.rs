@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+
+/// Log to stderr, and to `file` too if given, at a level set by how many times -v was given:
+/// what the app prints plainly by default, then setup and pipeline creation with their
+/// timings, then each frame's phases. Spans are logged as they close, with how long they
+/// took. Other crates, wgpu's validation included, only log warnings.
+pub fn init(verbosity: u8, file: Option<&Path>) -> Result<(), String> {
+    let level = match verbosity {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let filter = Targets::new()
+        .with_default(LevelFilter::WARN)
+        .with_target(env!("CARGO_CRATE_NAME"), level);
+    let file = file
+        .map(|path| {
+            File::create(path)
+                .map_err(|err| format!("Failed to create {}: {}", path.display(), err))
+        })
+        .transpose()?;
+
+    let terminal = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_span_events(FmtSpan::CLOSE);
+    let file = file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+    });
+    tracing_subscriber::registry()
+        .with(terminal)
+        .with(file)
+        .with(filter)
+        .try_init()
+        .map_err(|err| format!("Failed to set up logging: {}", err))
+}
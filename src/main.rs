@@ -1,217 +1,3552 @@
+use clap::{CommandFactory, Parser, ValueEnum};
 use nannou::prelude::*;
-use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
-use std::mem;
+use nannou::rand::{rngs::StdRng, SeedableRng};
+use nannou::wgpu::{self, ShaderStages};
+use nannou::window::SurfaceConfigurationBuilder;
+use nannou::winit::event::WindowEvent as RawWindowEvent;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+use tracing::{error, info, warn};
 
-const PARTICLE_COUNT: u32 = 5_0000;
+use particle_nannou::{
+    bindings, camera, canvas, compaction, constraints, contagion, diagnostics, drag, emitters,
+    fireworks, forces, freeze, goal, gpu, impulses, inflow, kernels, lennard_jones, level,
+    lifetime, mass, modulation, obstacles, orientation, parameters, particle_layout, particle_sort,
+    particle_system, pbd, physarum, reaction_diffusion, resources, sim_variant, simulation, sleep,
+    species, stages, stamina, stats, stir, territory, text_targets, thermostat, trail, uniform,
+    vector_field, vorticity, wgsl, Particle, MAX_PARTICLES,
+};
 
-struct Model {
-    simulate_pipeline: wgpu::ComputePipeline,
-    render_pipeline: wgpu::RenderPipeline,
-    particle_buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
+mod adapters;
+mod background;
+mod beats;
+mod bench;
+mod canvas_view;
+mod cli;
+mod contagion_log;
+mod crossfade;
+mod cull;
+mod density;
+mod dispatch_info;
+mod frame_graph;
+mod frame_limiter;
+mod frame_pacer;
+mod frame_share;
+mod gamepad;
+mod gif_export;
+mod gpu_profile;
+mod histogram;
+mod installation;
+mod labels;
+mod locale;
+mod logging;
+mod mesh;
+mod minimap;
+#[cfg(feature = "ndi")]
+mod ndi;
+mod offline;
+mod pen;
+mod post_fx;
+mod presentation;
+mod pressure;
+mod quality;
+mod reaction_view;
+mod recording;
+mod replay;
+mod rewind;
+mod scene;
+mod settings;
+mod shader_editor;
+mod shader_watch;
+mod stats_log;
+mod svg_export;
+mod sweep;
+mod sync;
+mod telemetry;
+mod territory_log;
+mod theme;
+mod toast;
+mod trail_view;
+mod user_shader;
+mod validate;
+
+use background::{Background, BackgroundConfig, BackgroundKind};
+use beats::BeatDetector;
+use bindings::{BindingLayout, Bindings};
+use camera::{Camera, ColorMode, Projection, RenderPath};
+use canvas::Canvas;
+use canvas_view::CanvasView;
+use cli::{Args, Command};
+use compaction::Compactor;
+use constraints::{ConstraintNetwork, SpringSolver};
+use contagion::Contagion;
+use contagion_log::ContagionLog;
+use crossfade::Crossfade;
+use cull::Culler;
+use density::DensitySplat;
+use drag::{Drag, DragConfig};
+use emitters::Emitters;
+use fireworks::Fireworks;
+use forces::{Attractor, Force, ForceStage};
+use frame_graph::FrameGraph;
+use frame_limiter::FrameLimiter;
+use frame_pacer::FramePacer;
+use frame_share::{Destination, FrameShare};
+use freeze::{Freeze, Region};
+use gamepad::GamepadAttractor;
+use gif_export::GifCapture;
+use goal::{Goal, GoalConfig, Route};
+use gpu_profile::GpuProfile;
+use histogram::SpeedHistogram;
+use impulses::Impulses;
+use inflow::Inflow;
+use installation::Installation;
+use labels::ParticleLabels;
+use lennard_jones::LennardJones;
+use level::Level;
+use lifetime::Lifetime;
+use locale::{fill, text};
+use mesh::{Mesh, MeshRenderer};
+use minimap::Minimap;
+use modulation::Target;
+use obstacles::Obstacles;
+use offline::OfflineRender;
+use orientation::Orientation;
+use particle_layout::ParticleLayout;
+use particle_sort::{ParticleSort, SortKey};
+use pbd::PbdSolver;
+use physarum::Physarum;
+use post_fx::PostFx;
+use presentation::Presentation;
+use pressure::PressureGauge;
+use quality::{Quality, QualityGovernor};
+use reaction_diffusion::ReactionDiffusion;
+use reaction_view::ReactionView;
+use recording::{Action, Player, Recorder, Recording};
+use replay::Replay;
+use resources::GpuResources;
+use rewind::History;
+use scene::{Scene, SceneBrowser};
+use settings::Settings;
+use shader_editor::ShaderEditor;
+use shader_watch::ShaderWatch;
+use sim_variant::{Neighborhood, SimVariant};
+use simulation::Simulation;
+use sleep::Sleep;
+use species::{SpeciesBlend, SpeciesConfig, SpeciesLook};
+use stages::{FrameContext, StageKind, Stages};
+use stamina::Stamina;
+use stats_log::StatsLog;
+use stir::Stirring;
+use sync::{SyncAuthority, SyncFollower};
+use telemetry::{Telemetry, TelemetryServer};
+use territory::Territory;
+use territory_log::TerritoryLog;
+use text_targets::TextTargets;
+use theme::{Cue, Theme};
+use thermostat::{Thermostat, ThermostatConfig};
+use toast::Toasts;
+use trail::Trail;
+use trail_view::TrailView;
+use uniform::UniformBuffer;
+use vector_field::{VectorField, VectorFieldStage};
+use vorticity::Vorticity;
+use wgsl::wgsl_struct;
+
+// Adds each particle's colour, weighted by its alpha, to what's there
+const ADDITIVE_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent::OVER,
+};
+
+// World size of a particle, unless given in points with --particle-size
+const BOID_SIZE: f32 = 0.009;
+
+wgsl_struct! {
+    // Must match `RenderParams` in vertex_shader.wgsl
+    struct RenderParams {
+        color_mode: u32,
+        // Neighbour count drawn brightest when colouring by crowding
+        crowded: f32,
+        // 1 when instances are indexed like the particles, so their flags can be read
+        flagged: u32,
+        // See `background_time`, for user fragment shaders
+        time: f32,
+        // 9 to draw the copies of a periodic domain around its edges too, see `copies`
+        copies: u32,
+        palette: u32,
+        // See `FramePacer::lag`
+        lag: f32,
+        // See `particle_size`
+        size: f32,
+        hues: u32,
+        cue: u32,
+    }
+}
+
+// A species drawn its own way, see `SpeciesConfig`
+struct SpeciesDraw {
+    config: SpeciesConfig,
+    // Over its `SpeciesLook`
+    look: wgpu::BindGroup,
+}
+
+// Staging buffers filled by a frame's commands, to map once they're submitted
+#[derive(Debug, Clone, Copy, Default)]
+struct Readbacks {
+    pressure: bool,
+    histogram: bool,
+    outbreak: bool,
+    mixing: bool,
 }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-struct Particle {
-    position: [f32; 2],
-    velocity: [f32; 2],
+// The pipelines drawing the particles with one fragment shader
+struct ParticlePipelines {
+    name: String,
+    render: wgpu::RenderPipeline,
+    // Indexed like `Model::species`
+    species: Vec<wgpu::RenderPipeline>,
+}
+
+struct Model {
+    // Clean output window, when running with --output-window
+    output_window: Option<window::Id>,
+    // Boids or another integrator, then forces and corrections, see `model`
+    stages: Stages,
+    sim_variant: SimVariant,
+    // With the built-in fragment shader, then each user shader, see `user_shader`
+    pipelines: Vec<ParticlePipelines>,
+    // Index of those drawn with, cycled with U
+    fragment: usize,
+    // Over the `SpeciesLook` of particles drawn alike
+    default_look: wgpu::BindGroup,
+    // Drawn in place of the render pipeline when set, each its share of the particles
+    species: Vec<SpeciesDraw>,
+    // Drawn in place of the triangles when a mesh was loaded
+    mesh: Option<MeshRenderer>,
+    resources: GpuResources,
+    // Particles simulated and drawn, at most the particle buffer capacity
+    particle_count: u32,
+    substeps: u32,
+    // Only with the piston variant
+    pressure: Option<PressureGauge>,
+    quality: Option<QualityGovernor>,
+    camera: Camera,
+    // Camera as of the last recorded camera action
+    recorded_camera: Camera,
+    render_bindings: BindingLayout,
+    render_params: UniformBuffer<RenderParams>,
+    // Over the camera, render params, neighbour counts and dyes, rebuilt with the particle buffers
+    render_bind_group: wgpu::BindGroup,
+    color_mode: ColorMode,
+    theme: Theme,
+    culler: Culler,
+    // Reorders the particles after the stages every so often, when set
+    sorter: Option<ParticleSort>,
+    density: DensitySplat,
+    // Reaction-diffusion patterns, drawn over the background
+    reaction_view: Option<ReactionView>,
+    // Physarum's trail map, drawn under the agents
+    trail_view: Option<TrailView>,
+    // The particles' painting, drawn over the trail
+    canvas_view: Option<CanvasView>,
+    post_fx: PostFx,
+    // Dropped by the adaptive quality governor, drawing the scene straight to the frame
+    post_fx_dropped: bool,
+    // Picked from the zoom level each update
+    render_path: RenderPath,
+    // Draw the periodic domain's copies around its edges, see `copies`
+    periodic_copies: bool,
+    // Last cursor position, for panning with the right mouse button
+    last_mouse: Vec2,
+    // Latest pressure from a tablet's pen, 0 to 1, while it's pressed down
+    pen: Option<f32>,
+    // Where the wall being drawn with the left mouse button has got to, in domain units
+    drawing: Option<Vec2>,
+    // Where the region being frozen, or thawed when false, was started, in domain units
+    selecting: Option<(Vec2, bool)>,
+    // Fingers on the touchscreen by id, in the order they went down
+    touches: Vec<(u64, Attractor)>,
+    gamepad: Option<GamepadAttractor>,
+    gamepad_attractor: Option<Attractor>,
+    beats: Option<BeatDetector>,
+    background: Background,
+    presentation: Presentation,
+    frame_share: Option<FrameShare>,
+    frame_limiter: Option<FrameLimiter>,
+    // Steps the simulation at --tick-rate whatever the frame rate
+    pacer: Option<FramePacer>,
+    installation: Option<Installation>,
+    frame_graph: FrameGraph,
+    gpu_profile: Option<GpuProfile>,
+    // The frame's simulation, left for the first view to add its drawing to and submit, so
+    // each frame is one submission. wgpu has the one queue, so drawing waits on the
+    // simulation whichever way they're submitted
+    pending_encoder: RefCell<Option<wgpu::CommandEncoder>>,
+    // Filled by the pending encoder's commands
+    pending_reads: Readbacks,
+    minimap: Minimap,
+    speed_histogram: SpeedHistogram,
+    // Windows across the domain, for the camera to start and reset at
+    world_size: f32,
+    // Dispatch and allocation details, and particle labels zoomed in, toggled with F4
+    debug_view: bool,
+    labels: ParticleLabels,
+    toasts: Toasts,
+    // The boids shader from --watch-shader
+    shader_watch: Option<ShaderWatch>,
+    // Toggled with F2, takes the keyboard while open
+    shader_editor: ShaderEditor,
+    settings: Settings,
+    // Where the settings are saved on exit, see `Args::saved_settings_path`
+    settings_path: Option<PathBuf>,
+    // The --scene running
+    scene: Option<PathBuf>,
+    // Toggled with F8 when there's a --scenes folder, takes the keyboard while open
+    scene_browser: Option<SceneBrowser>,
+    // Seconds to crossfade from one scene into the next, see `--scene-fade`
+    scene_fade: f32,
+    crossfade: Crossfade,
+    // The scene switched away from, kept running while it fades out
+    outgoing: Option<Box<Model>>,
+    // Frames simulated so far, the clock for recordings
+    frame: u64,
+    // Seeded so recordings can reproduce particle placement
+    rng: StdRng,
+    recorder: Option<Recorder>,
+    player: Option<Player>,
+    // Recent particle states, scrubbed back through by holding Backspace
+    history: Option<History>,
+    rewinding: bool,
+    offline: Option<OfflineRender>,
+    gif: Option<GifCapture>,
+    stats_log: Option<StatsLog>,
+    contagion_log: Option<ContagionLog>,
+    territory_log: Option<TerritoryLog>,
+    telemetry: Option<TelemetryServer>,
+    authority: Option<SyncAuthority>,
+    follower: Option<SyncFollower>,
 }
 
 fn model(app: &App) -> Model {
-    let window_id = app.new_window().size(1024, 768).view(view).build().unwrap();
+    let _setup = tracing::debug_span!("setup").entered();
+    let args = Args::parse();
+    if args.list_monitors {
+        presentation::print_monitors(app);
+        std::process::exit(0);
+    }
+    if args.list_adapters {
+        adapters::print_adapters(args.backends());
+        std::process::exit(0);
+    }
+    let adapter = chosen_adapter(&args);
+    let power_preference = adapter
+        .as_ref()
+        .map_or(wgpu::DEFAULT_POWER_PREFERENCE, adapters::power_preference);
+    let force_fallback = adapter.as_ref().is_some_and(adapters::force_fallback);
+
+    let launch = launch(&args);
+
+    let surface_conf =
+        SurfaceConfigurationBuilder::new().present_mode(launch.settings.present_mode.to_wgpu());
+    // Timestamp queries are only asked for when profiling, as not every adapter has them
+    let profiled_adapter = args
+        .gpu_profile
+        .then(|| adapters::request(app, adapter.as_ref()))
+        .flatten();
+    let device_descriptor = wgpu::DeviceDescriptor {
+        features: profiled_adapter
+            .as_deref()
+            .map_or(wgpu::Features::empty(), |adapter| {
+                gpu_profile::features(adapter)
+            }),
+        ..wgpu::default_device_descriptor()
+    };
+
+    let [width, height] = launch.settings.window_size;
+    let window_id = app
+        .new_window()
+        .size(width, height)
+        .surface_conf_builder(surface_conf.clone())
+        .power_preference(power_preference)
+        .force_fallback_adapter(force_fallback)
+        .device_descriptor(device_descriptor.clone())
+        .view(view)
+        .key_pressed(key_pressed)
+        .received_character(received_character)
+        .mouse_moved(mouse_moved)
+        .mouse_pressed(mouse_pressed)
+        .mouse_released(mouse_released)
+        .mouse_wheel(mouse_wheel)
+        .dropped_file(dropped_file)
+        .raw_event(raw_window_event)
+        .build()
+        .unwrap();
+    // Windows on the same adapter share a device, so the output window can draw
+    // straight from the same particle buffer with the same pipelines
+    let output_window = args.output_window.then(|| {
+        app.new_window()
+            .size(1024, 768)
+            .title("Particles Output")
+            .surface_conf_builder(surface_conf)
+            .power_preference(power_preference)
+            .force_fallback_adapter(force_fallback)
+            .device_descriptor(device_descriptor)
+            .view(output_view)
+            .key_pressed(key_pressed)
+            .build()
+            .unwrap()
+    });
+
+    adapters::report(app, adapter.as_ref());
+
+    let window = app.window(window_id).unwrap();
+    let device = window.device();
+    diagnostics::dump_device(device);
+
+    let mut model = build(app, &args, launch, window_id, output_window);
+    model.gpu_profile = profiled_adapter
+        .map(|adapter| GpuProfile::new(&adapter, device, window.queue(), args.gpu_trace.clone()));
+    if model.settings.present {
+        model
+            .presentation
+            .enter(app, &presentation_window(app, output_window));
+    }
+    if let Some(authority) = &model.authority {
+        authority.wait_for(args.sync_followers);
+    }
+    dispatch_info::print(&dispatch_lines(device, &model));
+    model
+}
+
+// The settings a run starts with, and the recording it starts, plays back or follows
+struct Launch {
+    settings: Settings,
+    // Where the settings are saved on exit, see `Args::saved_settings_path`
+    settings_path: Option<PathBuf>,
+    recording: Recording,
+    playback: Option<Recording>,
+    follower: Option<SyncFollower>,
+}
+
+// What `args` start from, exiting if any of it can't be loaded
+fn launch(args: &Args) -> Launch {
+    let settings_path = args.settings_path();
+    let saved_settings_path = args.saved_settings_path();
+    let scene = args.scene.as_ref().map(|path| {
+        Scene::load(path).unwrap_or_else(|err| {
+            warn!("{}", err);
+            std::process::exit(1);
+        })
+    });
+    // A replay brings the settings it was recorded with, as well as the recording
+    let replay = args
+        .play
+        .as_deref()
+        .filter(|path| replay::is_replay(path))
+        .map(|path| {
+            Replay::load(path).unwrap_or_else(|err| {
+                warn!("{}", err);
+                std::process::exit(1);
+            })
+        });
+    let mut settings = match (&replay, &scene) {
+        (Some(replay), _) => replay.settings.clone(),
+        (None, Some(scene)) => scene.settings.clone(),
+        (None, None) if args.reset_settings => Settings::default(),
+        (None, None) => Settings::load(&settings_path),
+    };
+    args.apply_to(&mut settings);
+    // Before anything is shown
+    locale::set(&settings.locale);
+
+    // Playback starts from the recorded state rather than the saved settings
+    let playback = match replay {
+        Some(replay) => {
+            if replay.version != env!("CARGO_PKG_VERSION") {
+                warn!(
+                    "Replay made by version {}, playing on {}, which should match",
+                    replay.version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            Some(replay.recording)
+        }
+        None => args.play.as_ref().map(|path| {
+            Recording::load(path).unwrap_or_else(|err| {
+                warn!("{}", err);
+                std::process::exit(1);
+            })
+        }),
+    };
+    // So does following an authority
+    let (follower, followed) = args
+        .sync_follow
+        .as_deref()
+        .map(|address| {
+            SyncFollower::connect(address).unwrap_or_else(|err| {
+                error!("Failed to follow {}: {}", address, err);
+                std::process::exit(1);
+            })
+        })
+        .unzip();
+    let mut recording = playback.clone().or(followed).unwrap_or_else(|| {
+        let seed = args.seed.unwrap_or_else(random);
+        let mut recording = Recording::new(seed, &settings);
+        recording.walls = scene.map(|scene| scene.walls).unwrap_or_default();
+        recording
+    });
+    // Other stages and the culled and splatted rendering read the default layout
+    if recording.simulation.layout() != ParticleLayout::default() && !recording.boids_only() {
+        warn!(
+            "Half precision and separate arrays only cover the plain boids step, using the default \
+             layout alongside {}",
+            recording.other_stages().join(", ")
+        );
+        recording.simulation.half_precision = false;
+        recording.simulation.separate_arrays = false;
+    }
+    Launch {
+        settings,
+        settings_path: saved_settings_path,
+        recording,
+        playback,
+        follower,
+    }
+}
+
+// Everything the scene `args` sets up runs with, drawn in the windows already made. Switching
+// scenes builds another alongside, see `switch_scene`
+fn build(
+    app: &App,
+    args: &Args,
+    launch: Launch,
+    window_id: window::Id,
+    output_window: Option<window::Id>,
+) -> Model {
+    let Launch {
+        settings,
+        settings_path,
+        recording,
+        playback,
+        follower,
+    } = launch;
+    // Either way the actions come from elsewhere
+    let replaying = playback.is_some() || follower.is_some();
+    let default_layout = recording.simulation.layout() == ParticleLayout::default();
+    let mut rng = StdRng::seed_from_u64(recording.seed);
+    let authority = args.sync_serve.as_deref().map(|address| {
+        SyncAuthority::start(address, recording.clone()).unwrap_or_else(|err| {
+            error!("Failed to lead sync on {}: {}", address, err);
+            std::process::exit(1);
+        })
+    });
+
     let window = app.window(window_id).unwrap();
     let device = window.device();
 
     // Load shaders
-    let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Compute Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/compute_shader.wgsl").into()),
+    let vertex_shader = diagnostics::shader(
+        device,
+        "vertex_shader",
+        include_str!("./shaders/vertex_shader.wgsl"),
+    );
+
+    let fragment_shader = diagnostics::shader(
+        device,
+        "fragment_shader",
+        include_str!("./shaders/fragment_shader.wgsl"),
+    );
+
+    let network = recording
+        .network
+        .map(|config| ConstraintNetwork::new(&config));
+    let particles = match &network {
+        Some(network) => network.particles.clone(),
+        None => (0..recording.particles)
+            .map(|_| Particle::random(&mut rng))
+            .collect::<Vec<_>>(),
+    };
+    let mut resources =
+        GpuResources::with_layout(device, &particles, recording.simulation.layout());
+
+    let mut simulation = Simulation::new(device, &mut resources, recording.simulation);
+    simulation.mass = recording.mass;
+    simulation.roi = recording.roi;
+    simulation.modulators = recording.modulators.clone();
+    simulation.speed_limits = recording.speed_limits;
+    simulation.weights = recording.rule_weights;
+    simulation.radii = recording.rule_radii;
+    simulation.leaders = recording.leaders;
+    simulation.view_angle = recording.view_angle;
+    let pressure = recording
+        .simulation
+        .piston
+        .then(|| PressureGauge::new(device, &mut resources));
+    let springs = recording
+        .network
+        .zip(network)
+        .map(|(config, network)| SpringSolver::new(device, &mut resources, config, &network));
+    let pbd = recording.pbd.map(|config| {
+        let network = springs.as_ref().map(SpringSolver::buffers);
+        PbdSolver::new(device, &mut resources, config, network)
     });
-    let vertex_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Vertex Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/vertex_shader.wgsl").into()),
+    let lennard_jones = recording
+        .lennard_jones
+        .map(|config| LennardJones::new(device, &mut resources, config));
+    let physarum = recording.physarum.map(|config| {
+        let trail = recording.trail.unwrap_or_else(|| config.trail());
+        Physarum::new(device, &mut resources, config, trail)
+    });
+    let fireworks = recording
+        .fireworks
+        .map(|config| Fireworks::new(device, &mut resources, config));
+    // Molecular dynamics needs a thermostat to hold its temperature, see `update`
+    let thermostat = recording
+        .thermostat
+        .or_else(|| {
+            let config = lennard_jones.as_ref()?.config;
+            Some(ThermostatConfig::new(
+                config.target_speed(recording.particles),
+            ))
+        })
+        .map(|config| Thermostat::new(device, &mut resources, config));
+
+    // Integrators first, then the forces and corrections applied to what they produced. The
+    // boids stage is always there, since it writes the parameters every stage reads.
+    let mut stages = Stages::default();
+    stages.push(
+        simulation,
+        lennard_jones.is_none() && springs.is_none() && physarum.is_none() && fireworks.is_none(),
+    );
+    if let Some(lennard_jones) = lennard_jones {
+        stages.push(lennard_jones, true);
+    }
+    if let Some(physarum) = physarum {
+        stages.push(physarum, true);
+    }
+    if let Some(fireworks) = fireworks {
+        stages.push(fireworks, true);
+    }
+    if let Some(springs) = springs {
+        stages.push(springs, true);
+    }
+    // Off until toggled on unless configured, and left out for layouts they can't read
+    if default_layout {
+        let noise = Force::Noise(recording.noise.unwrap_or_default());
+        stages.push(
+            ForceStage::new(device, &mut resources, noise),
+            recording.noise.is_some(),
+        );
+        if let Some(wander) = recording.wander {
+            stages.push(
+                ForceStage::new(device, &mut resources, Force::Wander(wander)),
+                true,
+            );
+        }
+        let attractors = Force::Attractors(recording.attractors.clone());
+        stages.push(
+            ForceStage::new(device, &mut resources, attractors),
+            !recording.attractors.is_empty(),
+        );
+        let touches = Force::Touches(Vec::new());
+        stages.push(ForceStage::new(device, &mut resources, touches), true);
+        stages.push(Impulses::new(device, &mut resources), true);
+        stages.push(Stirring::new(device, &mut resources), true);
+        // Left out if the route can't be loaded. Leaders without a goal meander
+        let goal = recording
+            .goal
+            .clone()
+            .or_else(|| recording.leaders.map(|_| GoalConfig::meander()));
+        let goal = goal.and_then(|config| {
+            Route::load(&config)
+                .map(|route| (config, route))
+                .map_err(|err| warn!("{}", err))
+                .ok()
+        });
+        if let Some((config, route)) = goal {
+            stages.push(Goal::new(device, &mut resources, config, route), true);
+        }
+        // Left out with the emitters, which dye the particles too
+        if let Some(config) = recording.territory.clone() {
+            match recording.emitters.is_some() {
+                true => warn!("The emitters already dye the particles, leaving out the bases"),
+                false => stages.push(Territory::new(device, &mut resources, config), true),
+            }
+        }
+        // Left out if the font can't be loaded
+        let text = recording.text.as_ref().and_then(|config| {
+            text_targets::rasterize(config)
+                .map(|(spots, cell)| (config.clone(), spots, cell))
+                .map_err(|err| warn!("{}", err))
+                .ok()
+        });
+        if let Some((config, spots, cell)) = text {
+            stages.push(
+                TextTargets::new(device, &mut resources, config, spots, cell),
+                true,
+            );
+        }
+        // Left out if the field can't be loaded
+        let field = recording.vector_field.as_ref().and_then(|config| {
+            match VectorField::load(&config.path, config.follow) {
+                Ok(field) => Some((config.clone(), field)),
+                Err(err) => {
+                    warn!("{}", err);
+                    None
+                }
+            }
+        });
+        if let Some((config, field)) = field {
+            stages.push(
+                VectorFieldStage::new(device, &mut resources, config, field),
+                true,
+            );
+        }
+        // Before the drag, which takes back some of what it spins up
+        if let Some(config) = recording.vorticity {
+            stages.push(Vorticity::new(device, &mut resources, config), true);
+        }
+        let drag = recording.drag.unwrap_or_default();
+        stages.push(
+            Drag::new(device, &mut resources, drag),
+            recording.drag.is_some(),
+        );
+        // Physarum and fireworks lay down trails of their own
+        let own_trail = recording.physarum.is_some() || recording.fireworks.is_some();
+        if let Some(config) = recording.trail.filter(|_| !own_trail) {
+            stages.push(Trail::new(device, &mut resources, config), true);
+        }
+        if let Some(config) = recording.reaction_diffusion {
+            stages.push(ReactionDiffusion::new(device, &mut resources, config), true);
+        }
+        // Fireworks age their sparks themselves
+        if let Some(config) = recording.lifetime.filter(|_| recording.fireworks.is_none()) {
+            stages.push(Lifetime::new(device, &mut resources, config), true);
+        }
+        // Before the walls, so they turn back whatever the stream drives into them
+        if let Some(config) = recording.inflow {
+            stages.push(Inflow::new(device, &mut resources, config), true);
+        }
+        // Without walls if the level can't be loaded
+        let level = recording
+            .level
+            .as_ref()
+            .and_then(|path| match Level::load(path) {
+                Ok(level) => Some(level),
+                Err(err) => {
+                    warn!("{}", err);
+                    None
+                }
+            });
+        let mut obstacles = Obstacles::new(device, &mut resources, level.as_ref());
+        for &[ax, ay, bx, by] in &recording.walls {
+            obstacles.push([ax, ay], [bx, by]);
+        }
+        stages.push(obstacles, true);
+    }
+    if let Some(pbd) = pbd {
+        stages.push(pbd, true);
+    }
+    if let Some(thermostat) = thermostat {
+        stages.push(thermostat, true);
+    }
+    // After the forces, so they can't push tired particles past what they have left
+    let tiring = recording.stamina.is_some() && default_layout;
+    if let Some(config) = recording.stamina.filter(|_| default_layout) {
+        stages.push(Stamina::new(device, &mut resources, config), true);
+    }
+    // After everything that moves the particles, so the frozen ones are put back
+    let frozen = recording.freeze.is_some();
+    if let Some(config) = recording.freeze.clone() {
+        stages.push(Freeze::new(device, &mut resources, config), true);
+    }
+    // After the freeze too, so whatever moved a sleeper this frame wakes it
+    let sleeping = recording.sleep.is_some() && default_layout;
+    if let Some(config) = recording.sleep.filter(|_| default_layout) {
+        stages.push(Sleep::new(device, &mut resources, config), true);
+    }
+    // After everything that changes the velocities it turns towards
+    let orientation = recording
+        .orientation
+        .map(|config| Orientation::new(device, &mut resources, config));
+    let oriented = orientation.is_some();
+    if let Some(orientation) = orientation {
+        stages.push(orientation, true);
+    }
+    // Where the particles end up, after everything that moves them, is where they paint
+    if let Some(config) = recording.canvas {
+        stages.push(Canvas::new(device, &mut resources, config), true);
+    }
+    let homed = stages.get::<Territory>().is_some();
+    // Where the particles end up is where they're dyed
+    let dyed = recording.emitters.is_some();
+    if let Some(config) = recording.emitters.clone() {
+        stages.push(Emitters::new(device, &mut resources, config), true);
+    }
+    // Brute force over the default layout, and with its own colours in place of the dyes
+    let contagion = recording
+        .contagion
+        .filter(|_| default_layout)
+        .filter(|_| match (dyed, homed) {
+            (true, _) => {
+                warn!("The emitters already dye the particles, leaving out the contagion");
+                false
+            }
+            (_, true) => {
+                warn!("The bases already dye the particles, leaving out the contagion");
+                false
+            }
+            _ => true,
+        })
+        .map(|config| Contagion::new(device, &mut resources, config));
+    let infectious = contagion.is_some();
+    if let Some(contagion) = contagion {
+        stages.push(contagion, true);
+    }
+    let aged = lifetime(&stages).is_some();
+    // Last, so rendering only sees survivors. Springs, spins, energies, stills, ages, dyes,
+    // health and holds refer to particles by index, so networks and oriented, tiring,
+    // sleeping, aging, dyed, homed, infectious or frozen particles are never compacted
+    let compaction = recording.compaction.filter(|_| {
+        let conflicts = [
+            ("--cloth or --rope", recording.network.is_some()),
+            ("--orient", oriented),
+            ("--stamina", tiring),
+            ("--sleep", sleeping),
+            ("--lifetime", aged),
+            ("--emitters", dyed),
+            ("--bases", homed),
+            ("--contagion", infectious),
+            ("--freeze", frozen),
+        ]
+        .into_iter()
+        .filter_map(|(option, on)| on.then_some(option))
+        .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            warn!(
+                "Leaving out --compact, which moves particles, alongside {}",
+                conflicts.join(", ")
+            );
+        }
+        conflicts.is_empty()
     });
+    if let Some(config) = compaction {
+        stages.push(Compactor::new(device, &mut resources, config), true);
+    }
+    // User kernels, read the default layout like the forces. In reverse, so those after the
+    // same stage run in order of name
+    let kernels = match default_layout {
+        true => kernels::load(device, &mut resources, &args.kernels),
+        false => Vec::new(),
+    };
+    for kernel in kernels.into_iter().rev() {
+        let after = kernel.after.unwrap_or(StageKind::Touches);
+        let name = kernel.name.clone();
+        if let Err(kernel) = stages.insert_after(after, kernel, true) {
+            warn!("No {after:?} stage for kernel {name} to run after, running it after the forces");
+            if stages
+                .insert_after(StageKind::Touches, kernel, true)
+                .is_err()
+            {
+                warn!("Leaving out kernel {name}");
+            }
+        }
+    }
+    // Springs and text spots refer to particles by index, and compaction moves particles
+    // itself, so none of them are sorted
+    let sorter = recording
+        .sort
+        .filter(|_| recording.network.is_none() && recording.text.is_none() && compaction.is_none())
+        .map(|config| {
+            if config.key == SortKey::Age && !aged {
+                warn!("Only particles that age can be sorted by age, sorting by cell");
+            }
+            ParticleSort::new(
+                device,
+                config,
+                settings.species.clone(),
+                dyed || homed || infectious,
+            )
+        });
 
-    let fragment_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Fragment Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/fragment_shader.wgsl").into()),
+    let culler = Culler::new(device, &mut resources);
+    let speed_histogram = SpeedHistogram::new(device, &mut resources, settings.speed_histogram);
+    let contagion_log = args.contagion_log.as_deref().and_then(|path| {
+        if !infectious {
+            warn!("No contagion to log to {}", path.display());
+            return None;
+        }
+        let log = ContagionLog::create(device, &mut resources, path).unwrap_or_else(|err| {
+            error!("Failed to create {}: {}", path.display(), err);
+            std::process::exit(1);
+        });
+        Some(log)
+    });
+    let territory_log = args.territory_log.as_deref().and_then(|path| {
+        if !homed {
+            warn!("No bases to log the mixing of to {}", path.display());
+            return None;
+        }
+        let log = TerritoryLog::create(device, &mut resources, path).unwrap_or_else(|err| {
+            error!("Failed to create {}: {}", path.display(), err);
+            std::process::exit(1);
+        });
+        Some(log)
     });
 
-    // Create buffer for particles
-    let particles = (0..PARTICLE_COUNT)
-        .map(|_| Particle {
-            position: [random_range(-1.0, 1.0), random_range(-1.0, 1.0)],
-            velocity: [random_range(-0.001, 0.001), random_range(-0.001, 0.001)],
+    // Render pipeline
+    let render_bindings = render_bindings(device);
+    let render_params = UniformBuffer::new(device, &mut resources, "Render Params Buffer");
+    let render_bind_group = render_bind_group(device, &render_bindings, &resources, &render_params);
+
+    let look_bindings = look_bindings(device);
+    let look_bind_group = |resources: &mut GpuResources, look: &SpeciesLook, label: &str| {
+        let buffer = UniformBuffer::new(device, resources, label);
+        buffer.write(window.queue(), look);
+        look_bindings.bind_group(device, &[buffer.binding()])
+    };
+    let default_look = look_bind_group(
+        &mut resources,
+        &SpeciesLook::default(),
+        "Default Look Buffer",
+    );
+
+    let render_pipeline_layout = render_pipeline_layout(device, &render_bindings, &look_bindings);
+
+    // Whatever the storage layout, the vertex shader gets the position and velocity as f32s,
+    // then the spins when oriented, then the alpha and size when aging
+    let mut vertex_buffer_layouts = resources.layout().vertex_buffer_layouts();
+    if oriented {
+        vertex_buffer_layouts.push(Orientation::vertex_buffer_layout());
+    }
+    if aged {
+        vertex_buffer_layouts.push(Lifetime::vertex_buffer_layout());
+    }
+
+    // Only aging particles are ever transparent, and sparks glow where they overlap
+    let default_blend = if stages.get::<Fireworks>().is_some() {
+        SpeciesBlend::Additive
+    } else if aged {
+        SpeciesBlend::Alpha
+    } else {
+        SpeciesBlend::Opaque
+    };
+    let color_targets = |blend| color_targets(Frame::TEXTURE_FORMAT, blend);
+    let targets = color_targets(default_blend);
+    let fragment = |module| wgpu::FragmentState {
+        module,
+        entry_point: "fs_main",
+        targets: &targets,
+    };
+    let multisample = wgpu::MultisampleState {
+        count: window.msaa_samples(),
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+    };
+    let vertex = wgpu::VertexState {
+        module: &vertex_shader,
+        entry_point: vertex_entry_point(oriented, aged),
+        buffers: &vertex_buffer_layouts,
+    };
+    let create_render_pipeline = |fragment: wgpu::FragmentState| {
+        render_pipeline(
+            device,
+            &render_pipeline_layout,
+            vertex.clone(),
+            fragment,
+            multisample,
+        )
+    };
+    // The built-in fragment shader, then the user shaders that fit
+    let mut fragments = vec![fragment(&fragment_shader)];
+    let mut names = vec!["the built-in shader".to_owned()];
+    let mut render_pipelines =
+        vec![create_render_pipeline(fragments[0].clone()).unwrap_or_else(|err| panic!("{err}"))];
+    let user_shaders = user_shader::load(device, &args.user_shaders);
+    for shader in &user_shaders {
+        match create_render_pipeline(fragment(&shader.module)) {
+            Ok(pipeline) => {
+                fragments.push(fragment(&shader.module));
+                names.push(shader.name.clone());
+                render_pipelines.push(pipeline);
+            }
+            Err(err) => warn!("Leaving out user shader {}: {}", shader.name, err),
+        }
+    }
+    // Species ranges follow the buffer order, which compaction changes
+    let mut species_config = match stages.get::<Compactor>() {
+        Some(_) if !settings.species.is_empty() => {
+            warn!("Species are drawn alike while compacting");
+            Vec::new()
+        }
+        _ => settings.species.clone(),
+    };
+    if settings.cue == Cue::Shape {
+        species::distinct_shapes(&mut species_config);
+    }
+    let species = species_config
+        .iter()
+        .enumerate()
+        .map(|(i, config)| {
+            let mut look = config.look();
+            if settings.cue == Cue::Brightness {
+                look.shade = species::shade(i, species_config.len());
+            }
+            SpeciesDraw {
+                config: *config,
+                look: look_bind_group(&mut resources, &look, &format!("Species {i} Look Buffer")),
+            }
+        })
+        .collect::<Vec<_>>();
+    let pipelines = fragments
+        .iter()
+        .zip(names)
+        .zip(render_pipelines)
+        .map(|((fragment, name), render)| ParticlePipelines {
+            name,
+            render,
+            species: species_config
+                .iter()
+                .map(|config| {
+                    let targets = color_targets(config.blend.unwrap_or(default_blend));
+                    create_render_pipeline(wgpu::FragmentState {
+                        targets: &targets,
+                        ..fragment.clone()
+                    })
+                    .unwrap_or_else(|err| panic!("{err}"))
+                })
+                .collect(),
         })
         .collect::<Vec<_>>();
 
-    let particle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Particle Buffer"),
-        contents: bytemuck::cast_slice(&particles),
-        usage: BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_DST,
-    });
-
-    // Create bind group
-    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Bind Group Layout"),
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: false },
-                has_dynamic_offset: false,
-                min_binding_size: None,
+    // Falls back to the triangles if the mesh can't be loaded
+    let mesh = settings
+        .mesh
+        .as_ref()
+        .and_then(|path| match Mesh::load_obj(path) {
+            Ok(mesh) => Some(mesh),
+            Err(err) => {
+                warn!("{}", err);
+                None
+            }
+        });
+    let mesh = mesh.map(|mesh| {
+        MeshRenderer::new(
+            device,
+            &mut resources,
+            &mesh,
+            &render_pipeline_layout,
+            wgpu::VertexState {
+                entry_point: match (oriented, aged) {
+                    (false, false) => "vs_mesh",
+                    (true, false) => "vs_mesh_oriented",
+                    (false, true) => "vs_mesh_aged",
+                    (true, true) => "vs_mesh_oriented_aged",
+                },
+                ..vertex
             },
-            count: None,
-        }],
+            &fragments,
+            multisample,
+        )
     });
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("Bind Group"),
-        layout: &bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: particle_buffer.as_entire_binding(),
-        }],
-    });
+    let mut background = Background::new(
+        device,
+        window.queue(),
+        &mut resources,
+        &settings.background,
+        Frame::TEXTURE_FORMAT,
+        window.msaa_samples(),
+    );
 
-    // Compute pipeline
-    let simulate_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Simulate Pipeline Layout"),
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
+    background.period = args.loop_frames.map(|frames| frames as f32 / 60.0);
+
+    let mut density = DensitySplat::new(
+        device,
+        &mut resources,
+        Frame::TEXTURE_FORMAT,
+        window.msaa_samples(),
+    );
+    density.fluid = settings.fluid_shading;
+    density.auto_exposure = settings.auto_exposure;
+    let reaction_view = stages.get::<ReactionDiffusion>().map(|reaction_diffusion| {
+        ReactionView::new(
+            device,
+            &resources,
+            reaction_diffusion,
+            Frame::TEXTURE_FORMAT,
+            window.msaa_samples(),
+        )
     });
+    let trail = stages
+        .get::<Physarum>()
+        .map(Physarum::trail)
+        .or_else(|| stages.get::<Fireworks>().map(Fireworks::trail));
+    let trail_view = trail.map(|trail| {
+        TrailView::new(
+            device,
+            &resources,
+            trail,
+            Frame::TEXTURE_FORMAT,
+            window.msaa_samples(),
+        )
+    });
+    let canvas_view = stages.get::<Canvas>().map(|canvas| {
+        CanvasView::new(
+            device,
+            &resources,
+            canvas,
+            Frame::TEXTURE_FORMAT,
+            window.msaa_samples(),
+        )
+    });
+    let post_fx = PostFx::new(
+        device,
+        &mut resources,
+        settings.post_fx.clone(),
+        window.msaa_samples(),
+    );
+    let crossfade = Crossfade::new(device, &mut resources, window.msaa_samples());
+    let camera = settings.camera.map_or_else(
+        || Camera::preset(Projection::TopDown, settings.world_size),
+        Camera::from,
+    );
+
+    let record = args.record_path().cloned();
+    // Rewinding changes the simulation outside the recorded actions, so it's off for recordings
+    // and sync
+    let history =
+        (settings.rewind_seconds > 0.0 && record.is_none() && !replaying && authority.is_none())
+            .then(|| History::new(device, &mut resources, settings.rewind_seconds));
 
-    let simulate_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("Simulate Pipeline"),
-        layout: Some(&simulate_pipeline_layout),
-        module: &compute_shader,
-        entry_point: "simulate_boids",
+    let gamepad = settings.gamepad.clone().and_then(|config| {
+        let device = config.device.clone();
+        GamepadAttractor::open(config)
+            .map_err(|err| warn!("No gamepad on {}: {}", device.display(), err))
+            .ok()
+    });
+    let beats = settings.beats.clone().and_then(|config| {
+        let source = config.source.clone();
+        BeatDetector::open(config, settings.impulse)
+            .map_err(|err| warn!("No audio from {}: {}", source.display(), err))
+            .ok()
     });
 
-    // Render pipeline
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    let mut toasts = Toasts::default();
+    if let Some(path) = &record {
+        toasts.info(fill(&text().recording_to, &[("path", &path.display())]));
+    }
+    let recorder = record.map(|path| Recorder::new(path, recording.clone(), settings.clone()));
+    Model {
+        output_window,
+        stages,
+        sim_variant: recording.simulation,
+        pipelines,
+        fragment: 0,
+        default_look,
+        species,
+        mesh,
+        particle_count: resources.capacity(),
+        resources,
+        substeps: recording.substeps,
+        pressure,
+        // Quality changes are part of the recording, so playback leaves them to it
+        quality: (settings.adaptive_quality && !replaying).then(|| {
+            QualityGovernor::new(
+                settings.frame_budget(),
+                recording.substeps,
+                !post_fx.is_empty(),
+            )
+        }),
+        camera,
+        recorded_camera: camera,
+        render_bindings,
+        render_params,
+        render_bind_group,
+        color_mode: settings.color_mode,
+        theme: settings.theme,
+        culler,
+        sorter,
+        density,
+        reaction_view,
+        trail_view,
+        canvas_view,
+        post_fx,
+        post_fx_dropped: false,
+        render_path: RenderPath::Sprites,
+        periodic_copies: settings.periodic_copies,
+        last_mouse: Vec2::ZERO,
+        pen: None,
+        drawing: None,
+        selecting: None,
+        touches: Vec::new(),
+        gamepad,
+        gamepad_attractor: None,
+        beats,
+        background,
+        presentation: Presentation::new(args.monitor, args.span_monitors),
+        frame_share: args
+            .share_pipe
+            .clone()
+            .map(Destination::Pipe)
+            .or(args.share_ndi.clone().map(Destination::Ndi))
+            .map(FrameShare::new),
+        frame_limiter: settings.fps.map(FrameLimiter::new),
+        pacer: settings.tick_rate.map(FramePacer::new),
+        installation: settings
+            .installation
+            .clone()
+            .map(|config| Installation::new(config, settings.clone())),
+        frame_graph: FrameGraph::new(settings.frame_graph),
+        pending_encoder: RefCell::new(None),
+        pending_reads: Readbacks::default(),
+        gpu_profile: None,
+        minimap: Minimap::new(settings.minimap),
+        speed_histogram,
+        world_size: settings.world_size,
+        debug_view: false,
+        labels: ParticleLabels::new(),
+        toasts,
+        shader_watch: args.watch_shader.clone().map(ShaderWatch::new),
+        shader_editor: ShaderEditor::default(),
+        settings,
+        settings_path,
+        scene: args.scene.clone(),
+        scene_browser: args.scenes.clone().map(SceneBrowser::new),
+        scene_fade: args.scene_fade,
+        crossfade,
+        outgoing: None,
+        frame: 0,
+        rng,
+        recorder,
+        player: playback.map(Player::new),
+        history,
+        rewinding: false,
+        offline: args.render.map(|size| OfflineRender {
+            size,
+            frames: args.render_frames,
+            count: args.render_count,
+            path: args.render_output.clone(),
+            transparent: args.render_transparent,
+        }),
+        gif: args.gif.as_ref().map(|path| {
+            // A perfect loop crossfades the second period into the first, see GifCapture::finish
+            let (frames, blend) = match args.loop_frames {
+                Some(period) => (period * 2, period),
+                None => (args.gif_frames, args.gif_blend),
+            };
+            GifCapture::new(path.clone(), args.gif_warmup, frames, args.gif_width, blend)
+        }),
+        stats_log: args.stats.as_ref().map(|path| {
+            StatsLog::create(path, args.stats_interval).unwrap_or_else(|err| {
+                error!("Failed to create {}: {}", path.display(), err);
+                std::process::exit(1);
+            })
+        }),
+        contagion_log,
+        territory_log,
+        telemetry: args.telemetry.as_deref().map(|address| {
+            TelemetryServer::start(address, args.telemetry_interval).unwrap_or_else(|err| {
+                error!("Failed to serve telemetry on {}: {}", address, err);
+                std::process::exit(1);
+            })
+        }),
+        authority,
+        follower,
+    }
+}
+
+fn dispatch_lines(device: &wgpu::Device, model: &Model) -> Vec<String> {
+    let mut lines = dispatch_info::lines(
+        device,
+        &model.sim_variant,
+        model.particle_count,
+        model.substeps,
+        &model.resources,
+    );
+    let stages = model
+        .stages
+        .list()
+        .map(|(kind, enabled)| format!("{:?}{}", kind, if enabled { "" } else { " (off)" }))
+        .collect::<Vec<_>>();
+    lines.push(format!("Stages: {}", stages.join(", ")));
+    lines
+}
+
+fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if model.shader_editor.open {
+        edit_shader(app, model, key);
+        return;
+    }
+    if model
+        .scene_browser
+        .as_ref()
+        .is_some_and(|browser| browser.open)
+    {
+        browse_scenes(app, model, key);
+        return;
+    }
+    let capacity = model.resources.capacity();
+    let action = match key {
+        Key::B => Action::CycleBackground,
+        Key::I => impulse_at_cursor(app, model),
+        Key::Key1 => Action::ToggleAlignment,
+        Key::Key2 => Action::ToggleCohesion,
+        Key::Key3 => Action::ToggleSeparation,
+        Key::Key4 => Action::ToggleStage(StageKind::Noise),
+        Key::Key5 => Action::ToggleStage(StageKind::Attractors),
+        Key::Key6 => Action::ToggleStage(StageKind::VectorField),
+        Key::Key7 => Action::ToggleStage(StageKind::Trail),
+        Key::Key8 => Action::ToggleStage(StageKind::ReactionDiffusion),
+        Key::Key9 => Action::ToggleStage(StageKind::Drag),
+        Key::Minus | Key::Equals if model.stages.enabled(StageKind::Drag) => {
+            let scale = if key == Key::Minus { 0.8 } else { 1.25 };
+            let drag = model.stages.get::<Drag>().unwrap().config;
+            Action::SetDrag {
+                linear: drag.linear * scale,
+                quadratic: drag.quadratic * scale,
+            }
+        }
+        Key::N => Action::CycleNeighborhood,
+        Key::T => Action::ToggleTiled,
+        Key::F => Action::ToggleFluidShading,
+        Key::E => Action::ToggleAutoExposure,
+        Key::C => Action::ClearObstacles,
+        Key::K => Action::CycleColorMode,
+        Key::Left | Key::Right if model.pressure.is_some() => {
+            let step = if key == Key::Left { -0.05 } else { 0.05 };
+            let piston = simulation(&mut model.stages).piston + step;
+            Action::SetPiston(piston.clamp(pressure::MIN_PISTON, pressure::MAX_PISTON))
+        }
+        Key::Up | Key::Down if model.stages.get::<LennardJones>().is_some() => {
+            let step = if key == Key::Down { -0.05 } else { 0.05 };
+            let lennard_jones = model.stages.get::<LennardJones>().unwrap();
+            Action::SetTemperature((lennard_jones.config.temperature + step).max(0.05))
+        }
+        // The network has a fixed size
+        Key::RBracket | Key::LBracket if model.stages.get::<SpringSolver>().is_some() => return,
+        Key::RBracket => Action::SetParticles((capacity * 2).min(MAX_PARTICLES)),
+        Key::LBracket => Action::SetParticles((capacity / 2).max(1)),
+        Key::S => {
+            export_svg(app, model);
+            return;
+        }
+        Key::P if model.stages.get::<Canvas>().is_some() => {
+            if app.keys.mods.shift() {
+                model.stages.get_mut::<Canvas>().unwrap().clear();
+                model.toasts.info(&text().canvas_wiped);
+            } else {
+                export_canvas(app, model);
+            }
+            return;
+        }
+        Key::Home => {
+            model.camera = Camera::preset(model.camera.projection, model.world_size);
+            return;
+        }
+        Key::F5 | Key::F6 | Key::F7 => {
+            let projection = match key {
+                Key::F5 => Projection::TopDown,
+                Key::F6 => Projection::Isometric,
+                _ => Projection::Perspective,
+            };
+            model.camera = Camera::preset(projection, model.world_size);
+            model.toasts.info(fill(
+                &text().camera,
+                &[("projection", &format!("{:?}", projection))],
+            ));
+            return;
+        }
+        Key::PageUp | Key::PageDown if model.camera.projection == Projection::Perspective => {
+            let step = if key == Key::PageUp { 0.05 } else { -0.05 };
+            model.camera.tilt_by(step);
+            return;
+        }
+        Key::U => {
+            cycle_fragment_shader(model);
+            return;
+        }
+        Key::F2 => {
+            let source = simulation(&mut model.stages).shader_source().to_owned();
+            model.shader_editor.show(&source);
+            return;
+        }
+        Key::F3 => {
+            model.frame_graph.visible = !model.frame_graph.visible;
+            return;
+        }
+        Key::M => {
+            model.minimap.visible = !model.minimap.visible;
+            return;
+        }
+        Key::H => {
+            model.speed_histogram.visible = !model.speed_histogram.visible;
+            return;
+        }
+        Key::F4 => {
+            model.debug_view = !model.debug_view;
+            if model.debug_view {
+                dispatch_info::print(&dispatch_lines(app.main_window().device(), model));
+            }
+            return;
+        }
+        Key::F8 => {
+            match &mut model.scene_browser {
+                Some(browser) => {
+                    browser.refresh();
+                    browser.open = true;
+                }
+                None => model.toasts.info(&text().no_scenes_to_switch),
+            }
+            return;
+        }
+        Key::F11 => {
+            model
+                .presentation
+                .toggle(app, &presentation_window(app, model.output_window));
+            return;
+        }
+        _ => return,
+    };
+    if replaying(model) {
+        model.toasts.info(&text().ignoring_input);
+        return;
+    }
+    perform(app, model, action);
+}
+
+fn cycle_fragment_shader(model: &mut Model) {
+    if model.pipelines.len() == 1 {
+        model.toasts.info(&text().no_user_shaders);
+        return;
+    }
+    model.fragment = (model.fragment + 1) % model.pipelines.len();
+    let name = &model.pipelines[model.fragment].name;
+    let message = fill(
+        &text().drawing_with,
+        &[
+            ("name", name),
+            ("number", &(model.fragment + 1)),
+            ("count", &model.pipelines.len()),
+        ],
+    );
+    model.toasts.info(message);
+}
+
+// Keys for the scene browser while it's open
+fn browse_scenes(app: &App, model: &mut Model, key: Key) {
+    let Some(browser) = &mut model.scene_browser else {
+        return;
+    };
+    match key {
+        Key::Up => browser.select(-1),
+        Key::Down => browser.select(1),
+        Key::F8 => browser.open = false,
+        Key::S => save_scene(app, model),
+        Key::Return => {
+            let Some(path) = browser.selected().map(Path::to_path_buf) else {
+                return;
+            };
+            browser.open = false;
+            switch_scene(app, model, &path);
+        }
+        _ => {}
+    }
+}
+
+// Build the scene at `path` with the same options and hand over to it, crossfading from this
+// one over --scene-fade seconds while both run. The recording, logs and sync end with this
+// one, see `scene::scene_args`
+fn switch_scene(app: &App, model: &mut Model, path: &Path) {
+    // Loaded first, as a scene that can't be would end the launch
+    if let Err(err) = Scene::load(path) {
+        model.toasts.error(fill(
+            &text().switch_failed,
+            &[("path", &path.display()), ("error", &err)],
+        ));
+        return;
+    }
+    let (given, left_out) = scene::scene_args(std::env::args_os().skip(1), path);
+    let program = std::env::args_os().next().unwrap_or_default();
+    let args = match Args::try_parse_from(std::iter::once(program).chain(given)) {
+        Ok(args) => args,
+        Err(err) => {
+            model.toasts.error(fill(
+                &text().switch_failed,
+                &[("path", &path.display()), ("error", &err)],
+            ));
+            return;
+        }
+    };
+    if !left_out.is_empty() {
+        warn!("Switching scenes without {}", left_out.join(", "));
+    }
+    let window_id = app.main_window().id();
+    let incoming = build(app, &args, launch(&args), window_id, model.output_window);
+    dispatch_info::print(&dispatch_lines(app.main_window().device(), &incoming));
+
+    let mut outgoing = std::mem::replace(model, incoming);
+    // Saved now, while it still has the windows' state, rather than once it's faded out
+    if let Some(path) = outgoing.settings_path.take() {
+        current_settings(app, &outgoing).save(&path);
+    }
+    hand_over(&mut outgoing, model);
+    // Switching again before the last crossfade is done drops the scene still fading out
+    if let Some(older) = outgoing.outgoing.take() {
+        exit(app, *older);
+    }
+    model.crossfade.start(model.scene_fade);
+    model.outgoing = Some(Box::new(outgoing));
+}
+
+// Move what outlives a scene from the one switched away from to the one taking over: the
+// windows' state, what's shared or served, and what's on show over the scene. What only
+// the window on show should drive is dropped from the outgoing one.
+fn hand_over(outgoing: &mut Model, incoming: &mut Model) {
+    std::mem::swap(&mut outgoing.presentation, &mut incoming.presentation);
+    std::mem::swap(&mut outgoing.toasts, &mut incoming.toasts);
+    std::mem::swap(&mut outgoing.frame_graph, &mut incoming.frame_graph);
+    incoming.frame_share = outgoing.frame_share.take();
+    incoming.telemetry = outgoing.telemetry.take();
+    incoming.gpu_profile = outgoing.gpu_profile.take();
+    incoming.scene_browser = outgoing.scene_browser.take();
+    outgoing.frame_limiter = None;
+    outgoing.history = None;
+    outgoing.installation = None;
+    outgoing.offline = None;
+    outgoing.gif = None;
+}
+
+// Run the scene switched away from alongside until it has faded out, then end it
+fn update_outgoing(app: &App, model: &mut Model, update: Update) {
+    if model.crossfade.done() {
+        if let Some(outgoing) = model.outgoing.take() {
+            exit(app, *outgoing);
+        }
+    } else if let Some(outgoing) = &mut model.outgoing {
+        self::update(app, outgoing, update);
+        // Its view isn't drawn, so nothing else would
+        submit_pending(app, outgoing);
+    }
+}
+
+// The running setup, its view and drawn walls, as a new scene in the --scenes folder
+fn save_scene(app: &App, model: &mut Model) {
+    let Some(browser) = &model.scene_browser else {
+        return;
+    };
+    let mut settings = current_settings(app, model);
+    settings.camera = Some(model.camera.state());
+    let walls = model
+        .stages
+        .get::<Obstacles>()
+        .map_or_else(Vec::new, |obstacles| obstacles.segments().to_vec());
+    let scene = Scene {
+        // Listed by file name until it's given one
+        name: String::new(),
+        walls,
+        settings,
+    };
+    let path = browser.new_path();
+    let saved = std::fs::create_dir_all(&browser.dir)
+        .map_err(|err| format!("Failed to create {}: {}", browser.dir.display(), err))
+        .and_then(|()| scene.save(&path));
+    match saved {
+        Ok(()) => {
+            model
+                .toasts
+                .info(fill(&text().saved_scene, &[("path", &path.display())]));
+            if let Some(browser) = &mut model.scene_browser {
+                browser.refresh();
+            }
+        }
+        Err(err) => model.toasts.error(err),
+    }
+}
+
+// Keys for the shader editor while it's open
+fn edit_shader(app: &App, model: &mut Model, key: Key) {
+    match key {
+        Key::F2 => model.shader_editor.open = false,
+        Key::Return if app.keys.mods.ctrl() => {
+            let source = model.shader_editor.source();
+            let window = app.main_window();
+            let variant = model.sim_variant;
+            let result =
+                simulation(&mut model.stages).reload_shader(window.device(), variant, source);
+            match &result {
+                Ok(()) => model.toasts.info(&text().compiled_editor_shader),
+                Err(err) => warn!("{}", err),
+            }
+            model.shader_editor.set_result(result);
+        }
+        _ => model.shader_editor.key(key),
+    }
+}
+
+fn received_character(app: &App, model: &mut Model, c: char) {
+    if model.shader_editor.open && !app.keys.mods.ctrl() {
+        model.shader_editor.type_char(c);
+    }
+}
+
+// Whether to keep to --tick-rate, as playback and captures keep to one tick a frame, as
+// recorded or as written out
+fn paced(model: &Model) -> bool {
+    !replaying(model) && model.offline.is_none() && model.gif.is_none()
+}
+
+// Playing back or following the sync authority, where the actions come from instead
+fn replaying(model: &Model) -> bool {
+    model.player.is_some() || model.follower.is_some()
+}
+
+// Apply an action, recording it for playback when recording
+fn perform(app: &App, model: &mut Model, action: Action) {
+    if let Some(recorder) = &mut model.recorder {
+        recorder.record(model.frame, action);
+    }
+    if let Some(authority) = &mut model.authority {
+        authority.push(action);
+    }
+    match action {
+        Action::ToggleAlignment => toggle_rule("Alignment", &mut model.sim_variant.alignment),
+        Action::ToggleCohesion => toggle_rule("Cohesion", &mut model.sim_variant.cohesion),
+        Action::ToggleSeparation => toggle_rule("Separation", &mut model.sim_variant.separation),
+        Action::CycleNeighborhood => {
+            let variant = &mut model.sim_variant;
+            variant.neighborhood = match variant.neighborhood {
+                Neighborhood::Circle => Neighborhood::Square,
+                Neighborhood::Square => Neighborhood::Circle,
+            };
+            info!("Neighbourhood: {:?}", variant.neighborhood);
+        }
+        Action::ToggleTiled => toggle_rule("Tiled kernel", &mut model.sim_variant.tiled),
+        Action::CycleBackground => model.background.cycle(),
+        Action::ToggleFluidShading => toggle_rule("Fluid shading", &mut model.density.fluid),
+        Action::ToggleAutoExposure => {
+            toggle_rule("Auto exposure", &mut model.density.auto_exposure)
+        }
+        Action::CycleColorMode => {
+            model.color_mode = model.color_mode.next();
+            info!("Colour: {:?}", model.color_mode);
+            if model.color_mode == ColorMode::Crowding && !model.stages.enabled(StageKind::Boids) {
+                info!("Only the boids step counts neighbours");
+            }
+            let dyed = model.stages.get::<Emitters>().is_some()
+                || model.stages.get::<Territory>().is_some()
+                || model.stages.get::<Contagion>().is_some();
+            if model.color_mode == ColorMode::Emitter && !dyed {
+                info!("No emitters, bases or contagion to dye the particles");
+            }
+        }
+        Action::SetParticles(capacity) => {
+            resize_particles(app, model, capacity);
+            // Keep the adaptive quality level; as its own action so playback needs no governor
+            if let Some(quality) = model.quality.as_ref().map(QualityGovernor::quality) {
+                apply_quality(app, model, quality);
+            }
+        }
+        Action::SetQuality {
+            particle_count,
+            substeps,
+            drop_post_fx,
+        } => {
+            model.particle_count = particle_count.min(model.resources.capacity());
+            model.substeps = substeps;
+            model.post_fx_dropped = drop_post_fx;
+        }
+        Action::SetPiston(piston) => simulation(&mut model.stages).piston = piston,
+        Action::SetTemperature(temperature) => {
+            if let Some(lennard_jones) = model.stages.get_mut::<LennardJones>() {
+                lennard_jones.config.temperature = temperature;
+                info!("Temperature: {:.2}", temperature);
+            }
+        }
+        Action::ToggleStage(kind) => {
+            if let Some(enabled) = model.stages.toggle(kind) {
+                info!("{:?}: {}", kind, if enabled { "on" } else { "off" });
+            }
+        }
+        Action::SetDrag { linear, quadratic } => {
+            if let Some(drag) = model.stages.get_mut::<Drag>() {
+                drag.config = DragConfig { linear, quadratic };
+                info!("Drag: {:.4} linear, {:.3} quadratic", linear, quadratic);
+            }
+        }
+        Action::SetRuleWeights(weights) => {
+            simulation(&mut model.stages).weights = weights;
+            info!(
+                "Rule weights: {} alignment, {} cohesion, {} separation",
+                weights.alignment, weights.cohesion, weights.separation
+            );
+        }
+        Action::SetRuleRadii(radii) => {
+            simulation(&mut model.stages).radii = radii;
+            info!(
+                "Rule radii: {} alignment, {} cohesion, {} separation",
+                radii.alignment, radii.cohesion, radii.separation
+            );
+        }
+        Action::SetParameter { parameter, value } => {
+            let simulation = simulation(&mut model.stages);
+            let mut params = simulation.boids_params(model.sim_variant);
+            parameter.set(&mut params, value);
+            simulation.set_boids_params(&params);
+            info!("{}: {}", parameter.name, parameter.value(&params));
+        }
+        Action::SetSpeedLimits(limits) => {
+            simulation(&mut model.stages).speed_limits = limits;
+            info!(
+                "Speed limits: {} to {}, {} decay",
+                limits.min, limits.max, limits.decay
+            );
+        }
+        Action::AddObstacle { a, b } => {
+            if let Some(obstacles) = model.stages.get_mut::<Obstacles>() {
+                if !obstacles.push(a, b) {
+                    let message = fill(
+                        &text().too_many_walls,
+                        &[("count", &obstacles::MAX_SEGMENTS)],
+                    );
+                    model.toasts.info(message);
+                }
+            }
+        }
+        Action::Burst { position, scale } => {
+            if let Some(fireworks) = model.stages.get_mut::<Fireworks>() {
+                fireworks.burst(position, scale.unwrap_or(1.0));
+            }
+        }
+        Action::ClearModulators => simulation(&mut model.stages).modulators.clear(),
+        Action::AddModulator(modulator) => {
+            let modulators = &mut simulation(&mut model.stages).modulators;
+            if modulators.len() < modulation::MAX_MODULATORS {
+                modulators.push(modulator);
+            }
+        }
+        Action::Impulse(impulse) => {
+            if let Some(impulses) = model.stages.get_mut::<Impulses>() {
+                impulses.push(impulse);
+            }
+        }
+        Action::Stir(stir) => {
+            if let Some(stirring) = model.stages.get_mut::<Stirring>() {
+                stirring.push(stir);
+            }
+        }
+        Action::Freeze { region, frozen } => {
+            if let Some(freeze) = model.stages.get_mut::<Freeze>() {
+                freeze.edit(region, frozen);
+            }
+        }
+        Action::ClearObstacles => {
+            if let Some(obstacles) = model.stages.get_mut::<Obstacles>() {
+                obstacles.clear();
+            }
+        }
+        Action::Touch {
+            id,
+            position,
+            strength,
+        } => {
+            let attractor = Attractor { position, strength };
+            match model.touches.iter_mut().find(|(touch, _)| *touch == id) {
+                Some((_, held)) => *held = attractor,
+                None => model.touches.push((id, attractor)),
+            }
+            update_touches(model);
+        }
+        Action::EndTouch { id } => {
+            model.touches.retain(|(touch, _)| *touch != id);
+            update_touches(model);
+        }
+        Action::SetGamepadAttractor(attractor) => {
+            model.gamepad_attractor = Some(attractor);
+            update_touches(model);
+        }
+        Action::Camera(state) => {
+            model.camera = Camera::from(state);
+            model.recorded_camera = model.camera;
+        }
+    }
+}
+
+fn update_touches(model: &mut Model) {
+    let touches = model
+        .touches
+        .iter()
+        .map(|(_, attractor)| *attractor)
+        .chain(model.gamepad_attractor)
+        .collect();
+    if let Some(stage) = model.stages.get_kind_mut::<ForceStage>(StageKind::Touches) {
+        stage.force = Force::Touches(touches);
+    }
+}
+
+fn resize_particles(app: &App, model: &mut Model, capacity: u32) {
+    let window = app.main_window();
+    let device = window.device();
+    let resources = &mut model.resources;
+    resources.set_capacity(
+        device,
+        window.queue(),
+        capacity.clamp(1, MAX_PARTICLES),
+        &mut model.rng,
+    );
+
+    model.stages.rebind(device, resources);
+    model.culler.rebind(device, resources);
+    model.speed_histogram.rebind(device, resources);
+    model.render_bind_group = render_bind_group(
+        device,
+        &model.render_bindings,
+        resources,
+        &model.render_params,
+    );
+    model.density.rebind(device, resources);
+    // Old snapshots are the wrong size
+    if model.history.is_some() {
+        model.history = Some(History::new(
+            device,
+            resources,
+            model.settings.rewind_seconds,
+        ));
+    }
+
+    model.particle_count = resources.capacity();
+    model.settings.particles = resources.capacity();
+    info!(
+        "Particles: {} ({:.1} MiB of GPU buffers)",
+        resources.capacity(),
+        resources.total_bytes() as f64 / (1024.0 * 1024.0)
+    );
+}
+
+fn export_svg(app: &App, model: &mut Model) {
+    let window = app.main_window();
+    let particles =
+        model
+            .resources
+            .read_particles(window.device(), window.queue(), model.particle_count);
+    let path = PathBuf::from(format!("particles-{:06}.svg", model.frame));
+    // In points, so stroke widths look the same on any display
+    let (width, height) = window.inner_size_points();
+    let size = particle_size(model.settings.particle_size, &model.camera, window.rect());
+    let exported = svg_export::export(
+        &path,
+        &particles,
+        &model.camera,
+        [width.round() as u32, height.round() as u32],
+        size,
+        model.background.kind,
+        &model.settings.background,
+        model.theme.palette(),
+        model.settings.hues,
+        model.settings.cue,
+        &model.settings.svg,
+    );
+    match exported {
+        Ok(drawn) => model.toasts.info(fill(
+            &text().saved_svg,
+            &[("count", &drawn), ("path", &path.display())],
+        )),
+        Err(err) => model.toasts.error(fill(
+            &text().save_failed,
+            &[("path", &path.display()), ("error", &err)],
+        )),
+    }
+}
+
+fn export_canvas(app: &App, model: &mut Model) {
+    let Some(canvas) = model.stages.get::<Canvas>() else {
+        return;
+    };
+    let window = app.main_window();
+    let size = canvas.size();
+    let paint = gpu::read_buffer::<[f32; 4]>(
+        window.device(),
+        window.queue(),
+        canvas.paint_buffer(),
+        (size * size) as usize,
+    );
+    let path = PathBuf::from(format!("canvas-{:06}.png", model.frame));
+    match canvas_view::export(&path, &paint, size, model.theme.background().0) {
+        Ok(()) => model
+            .toasts
+            .info(fill(&text().saved_canvas, &[("path", &path.display())])),
+        Err(err) => model.toasts.error(fill(
+            &text().save_failed,
+            &[("path", &path.display()), ("error", &err)],
+        )),
+    }
+}
+
+// The boids stage is always in the list, see `model`
+fn simulation(stages: &mut Stages) -> &mut Simulation {
+    stages.get_mut().expect("the boids stage is always present")
+}
+
+// Over the camera, render params, neighbour counts, dyes and flags, see `render_bind_group`
+fn render_bindings(device: &wgpu::Device) -> BindingLayout {
+    Bindings::new(ShaderStages::VERTEX)
+        .uniform(0)
+        .uniform(1)
+        .storage_ro(2)
+        .storage_ro(3)
+        .storage_ro(4)
+        .build(device, "Render")
+}
+
+// Which species is drawn, as a group of its own so changing it needs no rebinding
+fn look_bindings(device: &wgpu::Device) -> BindingLayout {
+    Bindings::new(ShaderStages::VERTEX)
+        .uniform(0)
+        .build(device, "Species")
+}
+
+fn render_pipeline_layout(
+    device: &wgpu::Device,
+    render_bindings: &BindingLayout,
+    look_bindings: &BindingLayout,
+) -> wgpu::PipelineLayout {
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[],
+        bind_group_layouts: &[render_bindings.layout(), look_bindings.layout()],
         push_constant_ranges: &[],
-    });
+    })
+}
 
-    // Make sure your vertex buffer layout correctly maps the struct fields
-    let vertex_buffer_layout = wgpu::VertexBufferLayout {
-        array_stride: mem::size_of::<Particle>() as wgpu::BufferAddress,
-        step_mode: wgpu::VertexStepMode::Instance,
-        attributes: &[
-            // Position
-            wgpu::VertexAttribute {
-                offset: 0,
-                shader_location: 0,
-                format: wgpu::VertexFormat::Float32x2,
-            },
-            // Velocity
-            wgpu::VertexAttribute {
-                offset: 8, // 2 * 4 bytes for the position
-                shader_location: 1,
-                format: wgpu::VertexFormat::Float32x2,
+// Where a species is drawn to, blended the way its config says
+fn color_targets(
+    format: wgpu::TextureFormat,
+    blend: SpeciesBlend,
+) -> [Option<wgpu::ColorTargetState>; 1] {
+    [Some(wgpu::ColorTargetState {
+        format,
+        blend: Some(match blend {
+            SpeciesBlend::Opaque => wgpu::BlendState::REPLACE,
+            SpeciesBlend::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            SpeciesBlend::Additive => ADDITIVE_BLENDING,
+        }),
+        write_mask: wgpu::ColorWrites::ALL,
+    })]
+}
+
+// The vertex shader entry point reading the spins and ages the particles have
+fn vertex_entry_point(oriented: bool, aged: bool) -> &'static str {
+    match (oriented, aged) {
+        (false, false) => "vs_main",
+        (true, false) => "vs_oriented",
+        (false, true) => "vs_aged",
+        (true, true) => "vs_oriented_aged",
+    }
+}
+
+// A pipeline drawing each particle as a triangle, an error if `fragment` doesn't fit `vertex`
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex: wgpu::VertexState,
+    fragment: wgpu::FragmentState,
+    multisample: wgpu::MultisampleState,
+) -> Result<wgpu::RenderPipeline, String> {
+    diagnostics::try_checked(device, "Render Pipeline", || {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(layout),
+            vertex,
+            fragment: Some(fragment),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
             },
+            depth_stencil: None,
+            multisample,
+            multiview: None,
+        })
+    })
+}
+
+fn render_bind_group(
+    device: &wgpu::Device,
+    bindings: &BindingLayout,
+    resources: &GpuResources,
+    render_params: &UniformBuffer<RenderParams>,
+) -> wgpu::BindGroup {
+    bindings.bind_group(
+        device,
+        &[
+            resources.camera.as_entire_binding(),
+            render_params.binding(),
+            resources.neighbor_counts.as_entire_binding(),
+            resources.dyes.as_entire_binding(),
+            resources.flags.as_entire_binding(),
         ],
+    )
+}
+
+// Ages to draw with, whether particles age on their own or as fireworks
+fn lifetime(stages: &Stages) -> Option<&Lifetime> {
+    stages
+        .get::<Lifetime>()
+        .or_else(|| stages.get::<Fireworks>().map(Fireworks::lifetime))
+}
+
+fn toggle_rule(name: &str, enabled: &mut bool) {
+    *enabled = !*enabled;
+    info!("{}: {}", name, if *enabled { "on" } else { "off" });
+}
+
+// Shorter strokes are left to grow, so slow drags don't make thousands of tiny walls
+const MIN_WALL_LENGTH: f32 = 0.02;
+
+fn mouse_moved(app: &App, model: &mut Model, position: Point2) {
+    if app.mouse.buttons.right().is_down() {
+        model
+            .camera
+            .pan(position - model.last_mouse, app.window_rect());
+    }
+    // Dragging with the left button and Shift held stirs the particles along with the cursor
+    let dragging = app.mouse.buttons.left().is_down() && !replaying(model);
+    let selecting = model.selecting.is_some();
+    let stirring = dragging && app.keys.mods.shift() && !selecting;
+    if stirring && model.settings.stir.strength != 0.0 {
+        let rect = app.window_rect();
+        let point = model.camera.window_to_world(position, rect);
+        let motion = point - model.camera.window_to_world(model.last_mouse, rect);
+        let (strength, radius) = model.settings.pen_pressure.scales(model.pen);
+        let stir = model
+            .settings
+            .stir
+            .at(point.to_array(), motion.to_array())
+            .scaled(strength, radius);
+        perform(app, model, Action::Stir(stir));
+    }
+    // Otherwise it draws walls, a segment every so often along the way, except when it sets
+    // off fireworks or marks a region to freeze
+    let fireworks = model.stages.get::<Fireworks>().is_some();
+    if dragging && !stirring && !fireworks && !selecting {
+        let point = model.camera.window_to_world(position, app.window_rect());
+        match model.drawing {
+            Some(last) if last.distance(point) >= MIN_WALL_LENGTH => {
+                perform(
+                    app,
+                    model,
+                    Action::AddObstacle {
+                        a: last.to_array(),
+                        b: point.to_array(),
+                    },
+                );
+                model.drawing = Some(point);
+            }
+            Some(_) => {}
+            None => model.drawing = Some(point),
+        }
+    } else {
+        model.drawing = None;
+    }
+    // Holding L drags the fluid shading's light around
+    if app.keys.down.contains(&Key::L) {
+        let half_size = app.window_rect().wh() / 2.0;
+        model.density.light = position / half_size;
+    }
+    model.last_mouse = position;
+}
+
+// Each finger on a touchscreen pulls the particles towards it, harder the harder it's pressed.
+// nannou's touch events leave out the pressure, so these are winit's. A tablet's pen pressure
+// comes as a touchpad's, and is kept for stirring and fireworks with `PenPressure`
+fn raw_window_event(app: &App, model: &mut Model, event: &RawWindowEvent) {
+    if let Some(installation) = &mut model.installation {
+        installation.event(event);
+    }
+    if let RawWindowEvent::TouchpadPressure { pressure, .. } = event {
+        model.pen = (*pressure > 0.0).then_some(pressure.clamp(0.0, 1.0));
+        return;
+    }
+    let RawWindowEvent::Touch(touch) = event else {
+        return;
     };
+    if replaying(model) {
+        return;
+    }
+    let action = match touch.phase {
+        TouchPhase::Started | TouchPhase::Moved => {
+            let window = app.main_window();
+            let rect = window.rect();
+            let location = touch
+                .location
+                .to_logical::<f32>(window.scale_factor() as f64);
+            let point = pt2(location.x + rect.left(), rect.top() - location.y);
+            // About 1 at a firm press, up to 2 pressed hard, 1 where the screen can't tell
+            let pressure = touch
+                .force
+                .map_or(1.0, |force| (force.normalized() * 2.0) as f32);
+            Action::Touch {
+                id: touch.id,
+                position: model.camera.window_to_world(point, rect).to_array(),
+                strength: model.settings.touch_strength * pressure,
+            }
+        }
+        TouchPhase::Ended | TouchPhase::Cancelled => Action::EndTouch { id: touch.id },
+    };
+    perform(app, model, action);
+}
 
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &vertex_shader,
-            entry_point: "vs_main",
-            buffers: &[vertex_buffer_layout],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &fragment_shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba16Float,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: None,
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
+fn impulse_at_cursor(app: &App, model: &Model) -> Action {
+    let position = model
+        .camera
+        .window_to_world(app.mouse.position(), app.window_rect());
+    Action::Impulse(model.settings.impulse.at(position.to_array()))
+}
+
+// Clicking sets off a firework under the cursor. With Z held it starts a region to freeze
+// instead, with X one to thaw, and with Shift held it starts stirring. The middle button
+// sets off an impulse
+fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    if button == MouseButton::Middle && !replaying(model) {
+        let action = impulse_at_cursor(app, model);
+        perform(app, model, action);
+        return;
+    }
+    if button != MouseButton::Left || replaying(model) {
+        return;
+    }
+    let position = model
+        .camera
+        .window_to_world(app.mouse.position(), app.window_rect());
+    let freezing = app.keys.down.contains(&Key::Z);
+    if (freezing || app.keys.down.contains(&Key::X)) && model.stages.get::<Freeze>().is_some() {
+        model.selecting = Some((position, freezing));
+        return;
+    }
+    if app.keys.mods.shift() || model.stages.get::<Fireworks>().is_none() {
+        return;
+    }
+    // Pressed harder, the sparks fly further
+    let (_, radius) = model.settings.pen_pressure.scales(model.pen);
+    perform(
+        app,
+        model,
+        Action::Burst {
+            position: position.to_array(),
+            scale: model.pen.map(|_| radius),
         },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 4,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
+    );
+}
+
+// Letting go freezes or thaws the rectangle dragged out, or with Shift held the circle
+// around where the drag started
+fn mouse_released(app: &App, model: &mut Model, button: MouseButton) {
+    if button != MouseButton::Left {
+        return;
+    }
+    model.pen = None;
+    let Some((start, frozen)) = model.selecting.take() else {
+        return;
+    };
+    let end = model
+        .camera
+        .window_to_world(app.mouse.position(), app.window_rect());
+    let region = if app.keys.mods.shift() {
+        Region::Circle {
+            center: start.to_array(),
+            radius: start.distance(end),
+        }
+    } else {
+        Region::rect(start.to_array(), end.to_array())
+    };
+    perform(app, model, Action::Freeze { region, frozen });
+}
+
+fn mouse_wheel(app: &App, model: &mut Model, delta: MouseScrollDelta, _phase: TouchPhase) {
+    let lines = match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+    };
+    model
+        .camera
+        .zoom_at(app.mouse.position(), app.window_rect(), 1.1f32.powf(lines));
+}
+
+// Dropping a settings file applies what can change while running as a preset, dropping an
+// image shows it as the background
+fn dropped_file(app: &App, model: &mut Model, path: PathBuf) {
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into(),
+    );
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("toml") if replaying(model) => model.toasts.info(&text().ignoring_presets),
+        Some("toml") => match nannou::io::load_from_toml::<_, Settings>(&path) {
+            Ok(preset) => {
+                load_preset(app, model, &preset);
+                model
+                    .toasts
+                    .info(fill(&text().loaded_preset, &[("name", &name)]));
+            }
+            Err(err) => model.toasts.error(fill(
+                &text().preset_failed,
+                &[("name", &name), ("error", &err)],
+            )),
         },
-        multiview: None,
-    });
+        Some("png") => {
+            let config = BackgroundConfig {
+                kind: BackgroundKind::Image,
+                image: Some(path.clone()),
+                ..model.settings.background.clone()
+            };
+            if set_background(app, model, config) {
+                model
+                    .toasts
+                    .info(fill(&text().background_set, &[("name", &name)]));
+            } else {
+                model
+                    .toasts
+                    .error(fill(&text().image_failed, &[("name", &name)]));
+            }
+        }
+        _ => model
+            .toasts
+            .error(fill(&text().drop_hint, &[("name", &name)])),
+    }
+}
 
-    Model {
-        simulate_pipeline,
-        render_pipeline,
-        particle_buffer,
-        bind_group,
+// Replace the background, keeping the old one if `config`'s image can't be loaded
+fn set_background(app: &App, model: &mut Model, config: BackgroundConfig) -> bool {
+    let window = app.main_window();
+    let mut background = Background::new(
+        window.device(),
+        window.queue(),
+        &mut model.resources,
+        &config,
+        Frame::TEXTURE_FORMAT,
+        window.msaa_samples(),
+    );
+    if config.image.is_some() && !background.has_image() {
+        return false;
+    }
+    background.period = model.background.period;
+    model.background = background;
+    model.settings.background = config;
+    true
+}
+
+// The rule weights and radii, speed limits, drag, boids rules, colours, theme, background and
+// camera of a settings file, the simulation through actions so recordings replay it. Particle
+// counts and modes only apply at launch
+fn load_preset(app: &App, model: &mut Model, preset: &Settings) {
+    perform(app, model, Action::SetRuleWeights(preset.rule_weights));
+    perform(app, model, Action::SetRuleRadii(preset.rule_radii));
+    perform(app, model, Action::SetSpeedLimits(preset.speed_limits));
+    if let (Some(drag), true) = (preset.drag, model.stages.get::<Drag>().is_some()) {
+        perform(
+            app,
+            model,
+            Action::SetDrag {
+                linear: drag.linear,
+                quadratic: drag.quadratic,
+            },
+        );
+    }
+    let (current, wanted) = (model.sim_variant, preset.simulation);
+    let toggles = [
+        (
+            current.alignment != wanted.alignment,
+            Action::ToggleAlignment,
+        ),
+        (current.cohesion != wanted.cohesion, Action::ToggleCohesion),
+        (
+            current.separation != wanted.separation,
+            Action::ToggleSeparation,
+        ),
+        (
+            current.neighborhood != wanted.neighborhood,
+            Action::CycleNeighborhood,
+        ),
+        (current.tiled != wanted.tiled, Action::ToggleTiled),
+    ];
+    for (differs, action) in toggles {
+        if differs {
+            perform(app, model, action);
+        }
     }
+    for _ in ColorMode::value_variants() {
+        if model.color_mode == preset.color_mode {
+            break;
+        }
+        perform(app, model, Action::CycleColorMode);
+    }
+    perform(app, model, Action::ClearModulators);
+    for &modulator in &preset.modulators {
+        perform(app, model, Action::AddModulator(modulator));
+    }
+    model.settings.rule_weights = preset.rule_weights;
+    model.settings.rule_radii = preset.rule_radii;
+    model.settings.speed_limits = preset.speed_limits;
+    model.settings.modulators = preset.modulators.clone();
+    model.theme = preset.theme;
+    model.settings.theme = preset.theme;
+    // Species keep the shapes they launched with
+    model.settings.hues = preset.hues;
+    model.settings.cue = preset.cue;
+    // An image that has gone missing leaves the background as it was
+    set_background(app, model, preset.background.clone());
+    if let Some(camera) = preset.camera {
+        model.camera = Camera::from(camera);
+    }
+}
+
+// Presentation targets the output window when there is one, leaving the controls alone
+fn presentation_window(app: &App, output_window: Option<window::Id>) -> std::cell::Ref<'_, Window> {
+    output_window
+        .and_then(|id| app.window(id))
+        .unwrap_or_else(|| app.main_window())
+}
+
+// Recompile the watched shader once it's been saved, keeping the last copy that compiled
+fn reload_shader(app: &App, model: &mut Model) {
+    let Some(watch) = &mut model.shader_watch else {
+        return;
+    };
+    let Some(source) = watch.poll() else {
+        return;
+    };
+    let window = app.main_window();
+    let variant = model.sim_variant;
+    let result = simulation(&mut model.stages).reload_shader(window.device(), variant, source);
+    match &result {
+        Ok(()) => model
+            .toasts
+            .info(fill(&text().reloaded, &[("path", &watch.path.display())])),
+        Err(err) => {
+            warn!("{}", err);
+            model.toasts.error(fill(
+                &text().shader_broken,
+                &[("path", &watch.path.display())],
+            ));
+        }
+    }
+    watch.set_result(result);
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
+fn update(app: &App, model: &mut Model, update: Update) {
+    let _update = tracing::trace_span!("update", frame = model.frame).entered();
+    if let Some(limiter) = &mut model.frame_limiter {
+        limiter.wait();
+    }
+    let started = Instant::now();
+    model.frame_graph.push(update.since_last);
+    submit_pending(app, model);
+    if let Some(profile) = &mut model.gpu_profile {
+        profile.end_frame();
+    }
+    if let Some(installation) = &mut model.installation {
+        // Nothing's on show, so nothing is simulated or drawn until it is again
+        if installation.suspended() {
+            thread::sleep(installation::SUSPENDED_POLL);
+            return;
+        }
+        if installation.watch(update.since_last) && !replaying(model) {
+            restart(app, model);
+        }
+    }
+    model.toasts.prune();
+    reload_shader(app, model);
+    if let Some(pressure) = &mut model.pressure {
+        pressure.poll();
+    }
+    model.speed_histogram.poll();
+    if let Some(log) = &mut model.contagion_log {
+        log.poll();
+    }
+    if let Some(log) = &mut model.territory_log {
+        log.poll();
+    }
+    if let Some(alive) = model
+        .stages
+        .get_mut::<Compactor>()
+        .and_then(|compactor| compactor.poll(model.particle_count))
+    {
+        model.particle_count = alive;
+    }
+
+    if let Some(player) = &mut model.player {
+        let actions = player.due(model.frame);
+        if player.finished(model.frame) {
+            let frames = model.frame;
+            model
+                .toasts
+                .info(fill(&text().playback_finished, &[("frames", &frames)]));
+            model.player = None;
+        }
+        for action in actions {
+            perform(app, model, action);
+        }
+    }
+    // A follower simulates each frame once the authority has, with the same actions
+    let mut waiting = false;
+    match model
+        .follower
+        .as_ref()
+        .map(|follower| follower.due(model.frame))
+    {
+        Some(Ok(Some(actions))) => {
+            for action in actions {
+                perform(app, model, action);
+            }
+        }
+        Some(Ok(None)) => waiting = true,
+        Some(Err(err)) => {
+            model.toasts.error(fill(
+                &text().sync_lost,
+                &[("frames", &model.frame), ("error", &err)],
+            ));
+            model.follower = None;
+        }
+        None => {}
+    }
+    let remote = model
+        .telemetry
+        .as_ref()
+        .map(TelemetryServer::poll)
+        .unwrap_or_default();
+    for action in remote {
+        if replaying(model) {
+            model.toasts.info(&text().ignoring_remote_input);
+            break;
+        }
+        perform(app, model, action);
+    }
+
+    if let Some(attractor) = model.gamepad.as_mut().and_then(GamepadAttractor::update) {
+        if !replaying(model) {
+            perform(app, model, Action::SetGamepadAttractor(attractor));
+        }
+    }
+    // Playback has the beats' impulses recorded already
+    let impulses = model.beats.as_mut().map(BeatDetector::update);
+    if !replaying(model) {
+        for impulse in impulses.into_iter().flatten() {
+            perform(app, model, Action::Impulse(impulse));
+        }
+    }
+
+    if let Some(quality) = model
+        .quality
+        .as_mut()
+        .and_then(|governor| governor.update(update.since_last))
+    {
+        apply_quality(app, model, quality);
+    }
+
+    if model.recorder.is_some() && model.camera != model.recorded_camera {
+        let state = model.camera.state();
+        perform(app, model, Action::Camera(state));
+    }
+
+    let paced = paced(model);
+    let ticks = match model.pacer.as_mut().filter(|_| paced) {
+        Some(pacer) => pacer.advance(update.since_last),
+        None => 1,
+    };
+
     let window = app.main_window();
     let queue = window.queue();
 
-    let mut encoder = window
-        .device()
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Compute Encoder"),
-        });
+    let simulated_time = model.frame as f32 / 60.0;
+    let boids = simulation(&mut model.stages);
+    // Only what's drawn zooms, the cursor keeps to the camera's own zoom
+    let zoom = modulation::factor(&boids.modulators, Target::Zoom, simulated_time);
+    let camera = Camera {
+        zoom: model.camera.zoom * zoom,
+        ..model.camera
+    };
+    queue.write_buffer(
+        &model.resources.camera,
+        0,
+        bytemuck::bytes_of(&camera.uniforms()),
+    );
+
+    let device = window.device();
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Frame Encoder"),
+    });
+
+    let rewinding = app.keys.down.contains(&Key::Back);
+    if let Some(history) = model.history.as_mut().filter(|_| rewinding) {
+        if !model.rewinding {
+            info!("Rewinding through {:.1}s of history", history.seconds());
+        }
+        history.rewind(&mut encoder, &model.resources);
+    }
+    model.rewinding = rewinding && model.history.is_some();
+    let time = background_time(app, model);
+    let size = particle_size(
+        model.settings.particle_size,
+        &camera,
+        presentation_window(app, model.output_window).rect(),
+    );
+    let mut reads = Readbacks::default();
+    let mut simulated = false;
+    // Hold still while rewinding, including once the history runs out, and while the sync
+    // authority is behind
+    if !model.rewinding && !waiting {
+        for tick in 0..ticks {
+            // Stages write their params through the queue, which lands ahead of the whole
+            // submission, so each tick after the first needs one of its own
+            if tick > 0 {
+                if let Some(profile) = &model.gpu_profile {
+                    profile.resolve(&mut encoder);
+                }
+                let next = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Frame Encoder"),
+                });
+                queue.submit(Some(std::mem::replace(&mut encoder, next).finish()));
+            }
+            encode_tick(model, device, queue, &mut encoder, &mut reads);
+            simulated = true;
+        }
+    }
+
+    encode_render_inputs(model, device, queue, &mut encoder, time, size);
+    // All read the particles back, so share one reading when they coincide
+    let frame = model.frame;
+    let log_due = simulated && model.stats_log.as_ref().is_some_and(|log| log.due(frame));
+    let telemetry_due = simulated && model.telemetry.as_ref().is_some_and(|t| t.due(frame));
+    let minimap_due = simulated && model.minimap.due(frame);
+    // Even when paused, as panning changes which are in view
+    let labels_due = model.labels.due(model.debug_view, &model.camera);
+    let offline_due = model
+        .offline
+        .as_ref()
+        .is_some_and(|offline| model.frame >= offline.frames);
+    // Reading back straight away needs the simulation submitted first, otherwise the view
+    // submits it with the drawing
+    if log_due || telemetry_due || minimap_due || labels_due || offline_due {
+        if let Some(profile) = &model.gpu_profile {
+            profile.resolve(&mut encoder);
+        }
+        queue.submit(Some(encoder.finish()));
+        map_readbacks(model, reads);
+    } else {
+        *model.pending_encoder.get_mut() = Some(encoder);
+        model.pending_reads = reads;
+    }
+    if !labels_due {
+        model.labels.clear();
+    }
+    if log_due || telemetry_due || minimap_due || labels_due {
+        let particles = model
+            .resources
+            .read_particles(device, queue, model.particle_count);
+        if minimap_due {
+            model.minimap.count(&particles);
+        }
+        if labels_due {
+            let window = app.main_window().rect();
+            model.labels.label(&particles, &model.camera, window);
+        }
+        // Counting clusters takes a while with many particles, so only when they're wanted
+        if log_due || telemetry_due {
+            let stats = stats::measure(&particles);
+            if let Some(log) = model.stats_log.as_mut().filter(|_| log_due) {
+                log.log(frame, model.particle_count, &stats);
+            }
+            if let Some(server) = model.telemetry.as_ref().filter(|_| telemetry_due) {
+                server.broadcast(&Telemetry {
+                    frame,
+                    fps: 1.0 / update.since_last.as_secs_f32().max(f32::EPSILON),
+                    particles: model.particle_count,
+                    substeps: model.substeps,
+                    simulation: model.sim_variant,
+                    stages: Telemetry::stages(model.stages.list()),
+                    parameters: Telemetry::parameters(
+                        &simulation(&mut model.stages).boids_params(model.sim_variant),
+                    ),
+                    stats,
+                });
+            }
+        }
+    }
+    model.frame_graph.set_compute(started.elapsed());
+
+    if offline_due {
+        render_offline(app, model);
+        if model.offline.is_none() {
+            app.quit();
+        }
+    }
+    update_outgoing(app, model, update);
+    if model.gif.as_ref().is_some_and(|gif| gif.done(model.frame)) {
+        if let Some(gif) = model.gif.take() {
+            gif.finish(device);
+        }
+        app.quit();
+    }
+}
+
+// One frame of the simulation, and everything that goes with each: sorting, the readings
+// and the rewind history
+fn encode_tick(
+    model: &mut Model,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    reads: &mut Readbacks,
+) {
+    // Once compacted the GPU's count is ahead of the CPU's, which only overwrites it on changing
+    let particle_count = match model.stages.get_mut::<Compactor>() {
+        Some(compactor) => compactor.count_to_write(model.particle_count),
+        None => Some(model.particle_count),
+    };
+    simulation(&mut model.stages).write_params(
+        queue,
+        &model.resources,
+        particle_count,
+        model.substeps,
+        model.frame as f32 / 60.0,
+    );
+    // Molecular dynamics holds its temperature through the thermostat
+    let target_speed = model
+        .stages
+        .get::<LennardJones>()
+        .map(|lennard_jones| lennard_jones.config.target_speed(model.particle_count));
+    if let (Some(target_speed), Some(thermostat)) =
+        (target_speed, model.stages.get_mut::<Thermostat>())
+    {
+        thermostat.config.target_speed = target_speed;
+    }
+    let frame = FrameContext {
+        device,
+        queue,
+        variant: model.sim_variant,
+        particle_count: model.particle_count,
+        substeps: model.substeps,
+        time: model.frame as f32 / 60.0,
+        frame: model.frame as u32,
+    };
+    let _simulate = tracing::trace_span!("simulate", frame = model.frame).entered();
+    let profile = model.gpu_profile.as_ref();
+    gpu_profile::scope(profile, "simulate", encoder, device, |encoder| {
+        model.stages.encode_each(
+            &frame,
+            encoder,
+            &mut model.resources,
+            |kind, encoder, encode| {
+                gpu_profile::scope(profile, &format!("{kind:?}"), encoder, device, encode)
+            },
+        )
+    });
+
+    if let Some(sorter) = model
+        .sorter
+        .as_mut()
+        .filter(|sorter| sorter.due(model.frame))
     {
-        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-            label: Some("Compute Pass"),
+        let lives = lifetime(&model.stages).map(Lifetime::lives_buffer);
+        let mut indexed = Vec::new();
+        if let Some(orientation) = model.stages.get::<Orientation>() {
+            indexed.push(orientation.spins_buffer());
+        }
+        if let Some(freeze) = model.stages.get::<Freeze>() {
+            indexed.push(freeze.holds_buffer());
+        }
+        if let Some(stamina) = model.stages.get::<Stamina>() {
+            indexed.push(stamina.energies_buffer());
+        }
+        if let Some(sleep) = model.stages.get::<Sleep>() {
+            indexed.push(sleep.stills_buffer());
+        }
+        if let Some(contagion) = model.stages.get::<Contagion>() {
+            indexed.push(contagion.health_buffer());
+        }
+        gpu_profile::scope(profile, "sort", encoder, device, |encoder| {
+            sorter.encode(
+                device,
+                encoder,
+                &mut model.resources,
+                model.particle_count,
+                lives,
+                &indexed,
+            )
         });
-        compute_pass.set_pipeline(&model.simulate_pipeline);
-        compute_pass.set_bind_group(0, &model.bind_group, &[]);
-        let workgroups_x = (PARTICLE_COUNT as f32 / 256.0).ceil() as u32; // e.g., 63
-                                                                          //
-        compute_pass.dispatch_workgroups(workgroups_x, 1, 1);
     }
-    queue.submit(Some(encoder.finish()));
+    if let Some(pressure) = &mut model.pressure {
+        let piston = simulation(&mut model.stages).piston;
+        reads.pressure = pressure.encode(encoder, &model.resources, piston);
+    }
+    // Binned from the default layout's full-precision velocities
+    if model.resources.layout() == ParticleLayout::default() {
+        reads.histogram =
+            model
+                .speed_histogram
+                .encode(queue, encoder, &model.resources, model.particle_count);
+    }
+    if let Some(authority) = &mut model.authority {
+        authority.send_frame(model.frame);
+    }
+    if let (Some(log), Some(contagion)) =
+        (&mut model.contagion_log, model.stages.get::<Contagion>())
+    {
+        reads.outbreak = log.encode(encoder, contagion, model.frame);
+    }
+    if let (Some(log), Some(territory)) =
+        (&mut model.territory_log, model.stages.get::<Territory>())
+    {
+        reads.mixing = log.encode(encoder, territory, model.frame);
+    }
+    model.frame += 1;
+
+    if let Some(history) = &mut model.history {
+        history.capture(encoder, &model.resources);
+    }
+}
+
+// Submit what the last frame left for its view, if no view took it, e.g. with the window
+// minimised, and map what its commands read back
+fn submit_pending(app: &App, model: &mut Model) {
+    if let Some(mut encoder) = model.pending_encoder.get_mut().take() {
+        if let Some(profile) = &model.gpu_profile {
+            profile.resolve(&mut encoder);
+        }
+        app.main_window().queue().submit(Some(encoder.finish()));
+    }
+    let reads = std::mem::take(&mut model.pending_reads);
+    map_readbacks(model, reads);
+}
+
+// Start mapping the staging buffers `reads` says were filled, once their commands are
+// submitted
+fn map_readbacks(model: &mut Model, reads: Readbacks) {
+    if let Some(pressure) = model.pressure.as_ref().filter(|_| reads.pressure) {
+        pressure.map();
+    }
+    if reads.histogram {
+        model.speed_histogram.map();
+    }
+    if let Some(log) = model.contagion_log.as_ref().filter(|_| reads.outbreak) {
+        log.map();
+    }
+    if let Some(log) = model.territory_log.as_ref().filter(|_| reads.mixing) {
+        log.map();
+    }
+    if let Some(compactor) = model.stages.get_mut::<Compactor>() {
+        compactor.map();
+    }
+}
+
+// Everything the render pass reads besides the camera, from the latest particle state
+fn encode_render_inputs(
+    model: &mut Model,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    time: f32,
+    size: f32,
+) {
+    // Culling and splatting read the default layout, and culling only draws full size, opaque
+    // triangles facing along the velocity, reordered so they no longer line up with their
+    // neighbour counts, dyes or species
+    let oriented = model.stages.get::<Orientation>().is_some();
+    let aged = lifetime(&model.stages).is_some();
+    let indexed = model.color_mode != ColorMode::Velocity || !model.species.is_empty();
+    // Culling would leave out the copies of particles outside the view
+    let tiled = copies(model) > 1;
+    model.render_path = match model.camera.render_path() {
+        _ if model.resources.layout() != ParticleLayout::default() => RenderPath::Sprites,
+        RenderPath::Culled if model.mesh.is_some() || oriented || aged || indexed || tiled => {
+            RenderPath::Sprites
+        }
+        path => path,
+    };
+    let reach = simulation(&mut model.stages).radii.reach();
+    let render_params = RenderParams {
+        color_mode: model.color_mode.shader_index(),
+        // A few times as crowded as an even spread
+        crowded: (simulation::even_neighbors(model.particle_count, reach) * 4.0).max(1.0),
+        flagged: (model.render_path != RenderPath::Culled) as u32,
+        time,
+        copies: copies(model),
+        palette: model.theme.palette().shader_index(),
+        lag: match &model.pacer {
+            Some(pacer) if paced(model) => pacer.lag(),
+            _ => 0.0,
+        },
+        size,
+        hues: model.settings.hues.shader_index(),
+        cue: model.settings.cue.shader_index(),
+    };
+    model.render_params.write(queue, &render_params);
+    let profile = model.gpu_profile.as_ref();
+    gpu_profile::scope(
+        profile,
+        "render inputs",
+        encoder,
+        device,
+        |encoder| match model.render_path {
+            RenderPath::Sprites => {}
+            RenderPath::Culled => model.culler.encode(
+                queue,
+                encoder,
+                &model.resources,
+                model.particle_count,
+                model.stages.get::<Compactor>(),
+            ),
+            RenderPath::Density => {
+                model
+                    .density
+                    .encode(queue, encoder, &model.resources, model.particle_count)
+            }
+        },
+    );
+}
+
+// How many copies of the domain each particle is drawn in: the 3x3 around it when it wraps
+// around and they're asked for, so what's crossing an edge shows on both sides. Meshes and the
+// compacted count's draw arguments only cover the one
+fn copies(model: &Model) -> u32 {
+    let periodic =
+        model.stages.get::<LennardJones>().is_some() || model.stages.get::<Physarum>().is_some();
+    let compacted = model.stages.get::<Compactor>().is_some();
+    if model.periodic_copies && periodic && model.mesh.is_none() && !compacted {
+        9
+    } else {
+        1
+    }
+}
+
+fn render_offline(app: &App, model: &mut Model) {
+    let Some(offline) = model.offline.take() else {
+        return;
+    };
+    let window = app.main_window();
+    let queue = window.queue();
+    let image = offline.render(
+        window.device(),
+        queue,
+        window.msaa_samples(),
+        &model.camera,
+        if offline.transparent {
+            wgpu::Color::TRANSPARENT
+        } else {
+            model.background.clear_color()
+        },
+        |encoder, attachment, tile| {
+            queue.write_buffer(
+                &model.resources.camera,
+                0,
+                bytemuck::bytes_of(&tile.camera.uniforms()),
+            );
+            model.background.update_view(
+                queue,
+                background_time(app, model),
+                offline.size,
+                tile.view,
+            );
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Offline Render Pass"),
+                color_attachments: &[Some(attachment)],
+                depth_stencil_attachment: None,
+            });
+            if !offline.transparent {
+                draw_backdrop(model, &mut render_pass);
+            }
+            // Culling and the density field depend on the camera, so draw everything
+            draw_particles(model, &mut render_pass, RenderPath::Sprites);
+        },
+    );
+    let index = model.frame - offline.frames;
+    offline.save(&image, index);
+    // The rest of a run are rendered on the frames after
+    if index + 1 < offline.count {
+        model.offline = Some(offline);
+    }
+}
+
+// World size of a particle, `BOID_SIZE`, or with --particle-size that many logical points of
+// `window` through `camera`, the same on any display whatever its scale factor
+fn particle_size(points: Option<f32>, camera: &Camera, window: Rect) -> f32 {
+    points.map_or(BOID_SIZE, |points| points * camera.world_per_point(window))
+}
+
+// Whether --installation has stopped everything while the window is hidden
+fn suspended(model: &Model) -> bool {
+    model
+        .installation
+        .as_ref()
+        .is_some_and(Installation::suspended)
+}
+
+// Start over once the --installation watchdog has seen frames stay slow, with the particles
+// scattered again and the preset, or the settings launched with, loaded
+fn restart(app: &App, model: &mut Model) {
+    let Some(installation) = &model.installation else {
+        return;
+    };
+    let config = &installation.config;
+    warn!(
+        "Frames stayed over {}ms for {}s, restarting",
+        config.watchdog_ms, config.watchdog_seconds
+    );
+    let preset = match &config.preset {
+        Some(path) => match nannou::io::load_from_toml::<_, Settings>(path) {
+            Ok(preset) => preset,
+            Err(err) => {
+                error!(
+                    "Failed to load restart preset {}, using the settings launched with: {}",
+                    path.display(),
+                    err
+                );
+                installation.launched.clone()
+            }
+        },
+        None => installation.launched.clone(),
+    };
+    perform(app, model, Action::SetParticles(preset.particles));
+    load_preset(app, model, &preset);
+    model
+        .resources
+        .scatter(app.main_window().queue(), &mut model.rng);
+}
+
+fn apply_quality(app: &App, model: &mut Model, quality: Quality) {
+    let capacity = model.resources.capacity();
+    let particle_count = ((capacity as f32 * quality.particle_fraction) as u32).max(1);
+    info!(
+        "Adaptive quality: {} particles, {} substeps, post-fx {}",
+        particle_count,
+        quality.substeps,
+        if quality.post_fx { "on" } else { "off" }
+    );
+    perform(
+        app,
+        model,
+        Action::SetQuality {
+            particle_count,
+            substeps: quality.substeps,
+            drop_post_fx: !quality.post_fx,
+        },
+    );
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
+    if suspended(model) {
+        return;
+    }
+    render_scene(app, model, &frame, model.output_window.is_none());
+    // Drawn after the scene is submitted, so it stays out of shared and captured frames
+    let obstacles = model
+        .stages
+        .get::<Obstacles>()
+        .filter(|obstacles| !obstacles.is_empty());
+    // The editor shows its own errors
+    let shader_error = model
+        .shader_watch
+        .as_ref()
+        .filter(|watch| watch.failed() && !model.shader_editor.open);
+    if model.frame_graph.visible
+        || model.minimap.visible
+        || model.speed_histogram.visible
+        || model.debug_view
+        || model.pressure.is_some()
+        || obstacles.is_some()
+        || !model.toasts.is_empty()
+        || shader_error.is_some()
+        || model.shader_editor.open
+        || model
+            .scene_browser
+            .as_ref()
+            .is_some_and(|browser| browser.open)
+    {
+        let _overlay = tracing::trace_span!("overlay").entered();
+        let draw = app.draw();
+        let hud = model.theme.hud();
+        if let Some(obstacles) = obstacles {
+            obstacles.draw(&draw, &model.camera, frame.rect());
+        }
+        if let Some(pressure) = &model.pressure {
+            pressure.draw(&draw, frame.rect(), hud);
+        }
+        if model.frame_graph.visible {
+            model.frame_graph.draw(&draw, frame.rect(), hud);
+            if let Some(profile) = &model.gpu_profile {
+                profile.draw(&draw, frame.rect(), hud);
+            }
+        }
+        if model.minimap.visible {
+            model.minimap.draw(&draw, &model.camera, frame.rect(), hud);
+        }
+        if model.speed_histogram.visible {
+            let max_speed = model
+                .stages
+                .get::<Simulation>()
+                .map_or(f32::INFINITY, |simulation| simulation.speed_limits.max);
+            model
+                .speed_histogram
+                .draw(&draw, frame.rect(), hud, max_speed);
+        }
+        if model.debug_view {
+            model.labels.draw(&draw, &model.camera, frame.rect(), hud);
+            let lines = dispatch_lines(frame.device_queue_pair().device(), model);
+            dispatch_info::draw(&draw, frame.rect(), hud, &lines);
+        }
+        if let Some(watch) = shader_error {
+            watch.draw(&draw, frame.rect());
+        }
+        if model.shader_editor.open {
+            model.shader_editor.draw(&draw, frame.rect());
+        }
+        if let Some(browser) = model.scene_browser.as_ref().filter(|browser| browser.open) {
+            browser.draw(&draw, frame.rect(), hud, model.scene.as_deref());
+        }
+        model.toasts.draw(&draw, frame.rect(), hud);
+        if let Err(err) = draw.to_frame(app, &frame) {
+            error!("Failed to draw overlay: {:?}", err);
+        }
+    }
+}
+
+fn output_view(app: &App, model: &Model, frame: Frame) {
+    if suspended(model) {
+        return;
+    }
+    render_scene(app, model, &frame, true);
+}
+
+// `is_output` marks the window whose frames are shared with --share-pipe
+fn render_scene(app: &App, model: &Model, frame: &Frame, is_output: bool) {
+    let _render = tracing::trace_span!("render", frame = model.frame, is_output).entered();
+    let started = Instant::now();
     let device = frame.device_queue_pair().device();
     let queue = frame.device_queue_pair().queue();
 
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("Render Encoder"),
+    model
+        .background
+        .update(queue, background_time(app, model), frame.texture_size());
+
+    // The first view adds to the frame's simulation, any other has its own
+    let pending = model.pending_encoder.borrow_mut().take();
+    let mut encoder = pending.unwrap_or_else(|| {
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        })
     });
 
+    let size = frame.texture_size();
+    let profile = model.gpu_profile.as_ref();
+    let attachment = wgpu::RenderPassColorAttachment {
+        view: frame.texture_view(),
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(model.background.clear_color()),
+            store: true,
+        },
+    };
+    encode_composited(
+        app,
+        model,
+        profile,
+        &mut encoder,
+        size,
+        attachment,
+        is_output,
+    );
+    if let Some(outgoing) = &model.outgoing {
+        gpu_profile::scope(profile, "crossfade", &mut encoder, device, |encoder| {
+            outgoing
+                .background
+                .update(queue, background_time(app, outgoing), size);
+            model.crossfade.encode(
+                device,
+                queue,
+                encoder,
+                frame.texture_view(),
+                size,
+                is_output,
+                outgoing.background.clear_color(),
+                |encoder, attachment| {
+                    encode_composited(app, outgoing, None, encoder, size, attachment, is_output)
+                },
+            )
+        });
+    }
+
+    let snapshot = model
+        .frame_share
+        .as_ref()
+        .filter(|_| is_output)
+        .and_then(|share| share.capture(device, &mut encoder, frame));
+    if let Some(gif) = model.gif.as_ref().filter(|_| is_output) {
+        gif.capture(device, &mut encoder, frame, model.frame);
+    }
+    if let Some(profile) = profile {
+        profile.resolve(&mut encoder);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    if let (Some(share), Some(snapshot)) = (&model.frame_share, snapshot) {
+        share.send(snapshot);
+    }
+    model.frame_graph.add_render(started.elapsed());
+}
+
+// The scene through its effects into `attachment`, `size` pixels across, which the effects'
+// own targets are cleared to the background colour for
+fn encode_composited(
+    app: &App,
+    model: &Model,
+    profile: Option<&GpuProfile>,
+    encoder: &mut wgpu::CommandEncoder,
+    size: [u32; 2],
+    attachment: wgpu::RenderPassColorAttachment,
+    is_output: bool,
+) {
+    let window = app.main_window();
+    let device = window.device();
+    if model.post_fx.is_empty() || model.post_fx_dropped {
+        gpu_profile::scope(profile, "scene", encoder, device, |encoder| {
+            encode_scene(model, encoder, attachment)
+        });
+    } else {
+        gpu_profile::scope(profile, "post", encoder, device, |encoder| {
+            model.post_fx.render(
+                device,
+                window.queue(),
+                encoder,
+                size,
+                attachment,
+                is_output,
+                background_time(app, model),
+                model.background.clear_color(),
+                |encoder, scene| {
+                    gpu_profile::scope(profile, "scene", encoder, device, |encoder| {
+                        encode_scene(model, encoder, scene)
+                    })
+                },
+            )
+        });
+    }
+}
+
+// Perfect loops run the background on the simulation clock, so captures line up with it.
+// Captured frames play back at 60fps.
+fn background_time(app: &App, model: &Model) -> f32 {
+    match model.background.period {
+        Some(_) => model.frame as f32 / 60.0,
+        None => app.time,
+    }
+}
+
+fn encode_scene(
+    model: &Model,
+    encoder: &mut wgpu::CommandEncoder,
+    attachment: wgpu::RenderPassColorAttachment,
+) {
     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
         label: Some("Render Pass"),
-        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-            view: frame.texture_view(),
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                store: true,
-            },
-        })],
+        color_attachments: &[Some(attachment)],
         depth_stencil_attachment: None,
     });
+    draw_scene(model, &mut render_pass, model.render_path);
+}
 
-    // In the view function, change the draw call to:
-    render_pass.set_pipeline(&model.render_pipeline);
-    render_pass.set_vertex_buffer(0, model.particle_buffer.slice(..));
-    render_pass.draw(0..3, 0..PARTICLE_COUNT); // Draw 3 vertices per instance, PARTICLE_COUNT instances
+fn draw_scene<'a>(model: &'a Model, render_pass: &mut wgpu::RenderPass<'a>, path: RenderPath) {
+    draw_backdrop(model, render_pass);
+    draw_particles(model, render_pass, path);
+}
 
-    drop(render_pass);
-    queue.submit(Some(encoder.finish()));
+// The background and the fields drawn over it, behind the particles
+fn draw_backdrop<'a>(model: &'a Model, render_pass: &mut wgpu::RenderPass<'a>) {
+    model.background.draw(render_pass);
+    if let Some(reaction_view) = &model.reaction_view {
+        reaction_view.draw(render_pass);
+    }
+    if let Some(trail_view) = &model.trail_view {
+        trail_view.draw(render_pass);
+    }
+    if let Some(canvas_view) = &model.canvas_view {
+        canvas_view.draw(render_pass);
+    }
+}
+
+fn draw_particles<'a>(model: &'a Model, render_pass: &mut wgpu::RenderPass<'a>, path: RenderPath) {
+    if path == RenderPath::Density {
+        model.density.draw(render_pass);
+    } else {
+        let pipelines = &model.pipelines[model.fragment];
+        render_pass.set_pipeline(&pipelines.render);
+        render_pass.set_bind_group(0, &model.render_bind_group, &[]);
+        render_pass.set_bind_group(1, &model.default_look, &[]);
+        if path == RenderPath::Culled {
+            model.culler.draw(render_pass);
+        } else {
+            model.resources.set_vertex_buffers(render_pass);
+            let mut slot = model.resources.layout().vertex_buffer_count();
+            if let Some(orientation) = model.stages.get::<Orientation>() {
+                orientation.set_vertex_buffer(render_pass, slot);
+                slot += 1;
+            }
+            if let Some(lifetime) = lifetime(&model.stages) {
+                lifetime.set_vertex_buffer(render_pass, slot);
+            }
+            // Indexed, so it can't use the compacted draw arguments, and the CPU's count
+            // only includes a few removed particles from the last frames
+            if let Some(mesh) = &model.mesh {
+                mesh.draw(render_pass, model.fragment, model.particle_count);
+                return;
+            }
+            if !model.species.is_empty() {
+                let configs = model.species.iter().map(|species| species.config);
+                let ranges = species::ranges(&configs.collect::<Vec<_>>(), model.particle_count);
+                for ((species, pipeline), range) in
+                    model.species.iter().zip(&pipelines.species).zip(ranges)
+                {
+                    render_pass.set_pipeline(pipeline);
+                    render_pass.set_bind_group(1, &species.look, &[]);
+                    let vertices = species.config.shape.vertices() * copies(model);
+                    render_pass.draw(0..vertices, range);
+                }
+                return;
+            }
+            match model.stages.get::<Compactor>() {
+                // The CPU's count lags behind the compacted one
+                Some(compactor) => compactor.draw(render_pass),
+                // 3 vertices per instance and copy, particle_count instances
+                None => render_pass.draw(0..3 * copies(model), 0..model.particle_count),
+            }
+        }
+    }
+}
+
+// The settings with the window size and runtime toggles as they are now
+fn current_settings(app: &App, model: &Model) -> Settings {
+    let mut settings = model.settings.clone();
+    // The fullscreen size isn't worth keeping; the window size from launch still applies
+    if !model.presentation.active {
+        let (width, height) = app.main_window().inner_size_points();
+        settings.window_size = [width.round() as u32, height.round() as u32];
+    }
+    settings.present = model.presentation.active;
+    settings.frame_graph = model.frame_graph.visible;
+    settings.minimap = model.minimap.visible;
+    settings.speed_histogram = model.speed_histogram.visible;
+    settings.fluid_shading = model.density.fluid;
+    settings.auto_exposure = model.density.auto_exposure;
+    settings.color_mode = model.color_mode;
+    settings.simulation = model.sim_variant;
+    settings.background.kind = model.background.kind;
+    settings
+}
+
+// Remember the window size and runtime toggles for next time
+fn exit(app: &App, mut model: Model) {
+    if let Some(path) = &model.settings_path {
+        current_settings(app, &model).save(path);
+    }
+
+    if let Some(recorder) = model.recorder.take() {
+        recorder.finish(model.frame);
+    }
+    if let Some(log) = model.contagion_log.take() {
+        log.finish();
+    }
+    if let Some(log) = model.territory_log.take() {
+        log.finish();
+    }
+    if let Some(log) = model.stats_log.take() {
+        log.finish();
+    }
+    if let Some(profile) = &mut model.gpu_profile {
+        profile.write_trace();
+    }
+}
+
+// The adapter picked with --adapter, exiting if nothing matches
+fn chosen_adapter(args: &Args) -> Option<wgpu::AdapterInfo> {
+    let selector = args.adapter.as_deref()?;
+    Some(
+        adapters::select(selector, args.backends()).unwrap_or_else(|err| {
+            warn!("{}", err);
+            std::process::exit(1);
+        }),
+    )
 }
 
 fn main() {
-    nannou::app(model).update(update).run();
+    // Backends are fixed when the app starts, before `model` gets to read the arguments
+    let args = Args::parse();
+    if let Err(err) = logging::init(args.verbose, args.log_path().as_deref()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let headless = match &args.command {
+        None | Some(Command::Run) => None,
+        Some(Command::Record(_)) => {
+            if args.play.is_some() {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "--play can't be used with the record command",
+                    )
+                    .exit();
+            }
+            None
+        }
+        Some(Command::Bench(bench)) => Some(("Bench", bench::run(bench))),
+        Some(Command::Sweep(sweep)) => Some(("Sweep", sweep::run(sweep))),
+        Some(Command::Validate(validate)) => Some(("Validation", validate::run(validate))),
+    };
+    if let Some((name, result)) = headless {
+        if let Err(err) = result {
+            error!("{} failed: {}", name, err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let backends =
+        chosen_adapter(&args).map_or(args.backends(), |adapter| adapters::backends(&adapter));
+    nannou::app(model)
+        .backends(backends)
+        .update(update)
+        .exit(exit)
+        .run();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::image::{self, RgbaImage};
+    use particle_nannou::{gpu, headless};
+
+    // Pixels across the square snapshot, 256 bytes a row so it reads back unpadded
+    const SNAPSHOT_SIZE: u32 = 64;
+    const SNAPSHOT: &str = "src/snapshots/particles.png";
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    // Most any channel may be off by before a pixel counts as changed, for rounding
+    const TOLERANCE: u8 = 8;
+    // Changed pixels allowed, for where GPUs rasterize triangle edges differently
+    const MAX_CHANGED: usize = 16;
+
+    // A ring of boids heading round it, each direction its own colour, and one at the centre
+    fn particles() -> Vec<Particle> {
+        let ring = (0..12).map(|i| {
+            let angle = i as f32 / 12.0 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            Particle {
+                position: [0.06 * cos, 0.06 * sin],
+                velocity: [-0.004 * sin, 0.004 * cos],
+            }
+        });
+        let centre = Particle {
+            position: [0.0, 0.0],
+            velocity: [0.004, 0.0],
+        };
+        ring.chain([centre]).collect()
+    }
+
+    // The particles drawn the way `encode_scene` draws sprites, through the pipeline `model`
+    // builds for plain boids, coloured by velocity onto black
+    fn render(device: &wgpu::Device, queue: &wgpu::Queue) -> RgbaImage {
+        let particles = particles();
+        let mut resources = GpuResources::new(device, &particles);
+        // Close enough in that the ring fills the view and each boid covers a few pixels
+        let camera = Camera {
+            zoom: 12.0,
+            ..Default::default()
+        };
+        queue.write_buffer(&resources.camera, 0, bytemuck::bytes_of(&camera.uniforms()));
+        let render_params = UniformBuffer::new(device, &mut resources, "Render Params Buffer");
+        render_params.write(
+            queue,
+            &RenderParams {
+                color_mode: ColorMode::Velocity.shader_index(),
+                crowded: 1.0,
+                flagged: 1,
+                time: 0.0,
+                copies: 1,
+                palette: 0,
+                lag: 0.0,
+                size: BOID_SIZE,
+                hues: 0,
+                cue: 0,
+            },
+        );
+        let render_bindings = render_bindings(device);
+        let render_bind_group =
+            render_bind_group(device, &render_bindings, &resources, &render_params);
+        let look_bindings = look_bindings(device);
+        let look = UniformBuffer::new(device, &mut resources, "Default Look Buffer");
+        look.write(queue, &SpeciesLook::default());
+        let look_bind_group = look_bindings.bind_group(device, &[look.binding()]);
+
+        let vertex_shader = diagnostics::shader(
+            device,
+            "vertex_shader",
+            include_str!("./shaders/vertex_shader.wgsl"),
+        );
+        let fragment_shader = diagnostics::shader(
+            device,
+            "fragment_shader",
+            include_str!("./shaders/fragment_shader.wgsl"),
+        );
+        let layout = render_pipeline_layout(device, &render_bindings, &look_bindings);
+        let pipeline = render_pipeline(
+            device,
+            &layout,
+            wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: vertex_entry_point(false, false),
+                buffers: &resources.layout().vertex_buffer_layouts(),
+            },
+            wgpu::FragmentState {
+                module: &fragment_shader,
+                entry_point: "fs_main",
+                targets: &color_targets(FORMAT, SpeciesBlend::Opaque),
+            },
+            wgpu::MultisampleState::default(),
+        )
+        .unwrap();
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Snapshot Texture"),
+            size: wgpu::Extent3d {
+                width: SNAPSHOT_SIZE,
+                height: SNAPSHOT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bytes = (SNAPSHOT_SIZE * SNAPSHOT_SIZE * 4) as wgpu::BufferAddress;
+        let pixels = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Snapshot Pixels Buffer"),
+            size: bytes,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Snapshot Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Snapshot Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &render_bind_group, &[]);
+            render_pass.set_bind_group(1, &look_bind_group, &[]);
+            resources.set_vertex_buffers(&mut render_pass);
+            render_pass.draw(0..3, 0..particles.len() as u32);
+        }
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &pixels,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SNAPSHOT_SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+        let pixels = gpu::read_buffer::<u8>(device, queue, &pixels, bytes as usize);
+        RgbaImage::from_raw(SNAPSHOT_SIZE, SNAPSHOT_SIZE, pixels).unwrap()
+    }
+
+    // Run with UPDATE_SNAPSHOTS=1 to write the reference again after changing how particles
+    // are drawn on purpose
+    #[test]
+    fn renders_like_the_snapshot() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        let rendered = render(&device, &queue);
+        let lit = rendered
+            .pixels()
+            .filter(|pixel| pixel.0[..3] != [0; 3])
+            .count();
+        assert!(lit > 0, "No particles were drawn");
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            rendered.save(&path).unwrap();
+            eprintln!("Wrote the snapshot {}", path.display());
+            return;
+        }
+        assert!(
+            path.exists(),
+            "No snapshot {}, write it with UPDATE_SNAPSHOTS=1",
+            path.display()
+        );
+        let reference = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(reference.dimensions(), rendered.dimensions());
+
+        let changed = reference
+            .pixels()
+            .zip(rendered.pixels())
+            .filter(|(a, b)| a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > TOLERANCE))
+            .count();
+        if changed > MAX_CHANGED {
+            let actual = path.with_file_name("particles.actual.png");
+            rendered.save(&actual).unwrap();
+            panic!(
+                "{changed} pixels differ from {}, see {}",
+                path.display(),
+                actual.display()
+            );
+        }
+    }
+
+    #[test]
+    fn saves_settings_only_when_they_are_the_users_own() {
+        let saved = |given: &[&str]| {
+            let args = Args::parse_from(
+                ["particles", "--settings", "viewer.toml"]
+                    .iter()
+                    .chain(given),
+            );
+            args.saved_settings_path()
+        };
+        let viewer = Some(PathBuf::from("viewer.toml"));
+        assert_eq!(saved(&[]), viewer);
+        // A recording replays actions over the viewer's own settings
+        assert_eq!(saved(&["--play", "actions.json"]), viewer);
+        assert_eq!(saved(&["--play", "x.replay"]), None);
+        assert_eq!(saved(&["--scene", "stage.toml"]), None);
+    }
 }
@@ -0,0 +1,58 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How masses are spread between `MassConfig::min` and `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MassDistribution {
+    Uniform,
+    /// Evenly spread in log space, so light particles outnumber heavy ones
+    LogUniform,
+}
+
+/// A mass per particle, dividing the forces on it so heavy particles turn and speed up
+/// slowly while light ones dart about.
+///
+/// Masses aren't stored anywhere: each is hashed from the particle's index whenever it's
+/// needed, see mass.wgsl, so they survive resizing for free. Anything that reorders the
+/// particles, like compaction, reshuffles them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MassConfig {
+    pub min: f32,
+    pub max: f32,
+    pub distribution: MassDistribution,
+}
+
+impl Default for MassConfig {
+    fn default() -> Self {
+        MassConfig {
+            min: 0.5,
+            max: 2.0,
+            distribution: MassDistribution::Uniform,
+        }
+    }
+}
+
+/// The `SimParams::mass_distribution` mass.wgsl reads, 0 for every mass being 1.
+pub fn shader_distribution(config: Option<&MassConfig>) -> u32 {
+    match config.map(|config| config.distribution) {
+        None => 0,
+        Some(MassDistribution::Uniform) => 1,
+        Some(MassDistribution::LogUniform) => 2,
+    }
+}
+
+/// Parse `min,max`, e.g. `0.5,2`.
+pub fn parse_range(s: &str) -> Result<[f32; 2], String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid mass range `{s}`"))?;
+    match values[..] {
+        [min, max] if 0.0 < min && min <= max => Ok([min, max]),
+        [_, _] => Err(format!("expected 0 < min <= max, got `{s}`")),
+        _ => Err(format!("expected a mass range like 0.5,2, got `{s}`")),
+    }
+}
@@ -0,0 +1,229 @@
+use nannou::wgpu;
+use std::fs;
+use std::path::Path;
+
+use crate::diagnostics;
+use crate::resources::GpuResources;
+
+// Must match `MeshVertex` in vertex_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+/// A triangle mesh drawn in place of each boid's triangle, facing +x like the triangle's tip.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Load the vertices and faces of a Wavefront OBJ file, ignoring materials, texture
+    /// coordinates and its own normals. Polygons are split into fans of triangles.
+    pub fn load_obj(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read mesh {}: {}", path.display(), err))?;
+        Mesh::parse_obj(&text).map_err(|err| format!("Invalid mesh {}: {}", path.display(), err))
+    }
+
+    fn parse_obj(text: &str) -> Result<Self, String> {
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let coords = words
+                        .take(3)
+                        .map(str::parse::<f32>)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| format!("line {}: {}", number + 1, err))?;
+                    let [x, y, z] = coords[..] else {
+                        return Err(format!("line {}: expected 3 coordinates", number + 1));
+                    };
+                    positions.push([x, y, z]);
+                }
+                Some("f") => {
+                    // Each corner is v, v/vt, v//vn or v/vt/vn, counted from 1 or from the end
+                    let corners = words
+                        .map(|corner| {
+                            let index = corner.split('/').next().unwrap_or_default();
+                            let index = index.parse::<i64>().map_err(|err| err.to_string())?;
+                            let resolved = if index < 0 {
+                                positions.len() as i64 + index
+                            } else {
+                                index - 1
+                            };
+                            if (0..positions.len() as i64).contains(&resolved) {
+                                Ok(resolved as u32)
+                            } else {
+                                Err(format!("no vertex {index}"))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| format!("line {}: {}", number + 1, err))?;
+                    for pair in corners.get(1..).unwrap_or_default().windows(2) {
+                        indices.extend([corners[0], pair[0], pair[1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if indices.is_empty() {
+            return Err("no faces".to_string());
+        }
+        Ok(Mesh::from_triangles(positions, indices))
+    }
+
+    /// Centre and scale the positions into -1..1, with smooth normals from the faces around
+    /// each vertex.
+    fn from_triangles(positions: Vec<[f32; 3]>, indices: Vec<u32>) -> Self {
+        let (min, max) =
+            positions
+                .iter()
+                .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), position| {
+                    (
+                        [0, 1, 2].map(|axis| min[axis].min(position[axis])),
+                        [0, 1, 2].map(|axis| max[axis].max(position[axis])),
+                    )
+                });
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let extent = (0..3)
+            .map(|axis| (max[axis] - min[axis]) / 2.0)
+            .fold(f32::EPSILON, f32::max);
+        let positions = positions
+            .iter()
+            .map(|position| [0, 1, 2].map(|axis| (position[axis] - center[axis]) / extent))
+            .collect::<Vec<_>>();
+
+        // Weighted by area, since the cross product's length is twice the triangle's
+        let mut normals = vec![[0.0f32; 3]; positions.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+            let ab = [0, 1, 2].map(|axis| b[axis] - a[axis]);
+            let ac = [0, 1, 2].map(|axis| c[axis] - a[axis]);
+            let normal = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            for &index in triangle {
+                let sum = &mut normals[index as usize];
+                *sum = [0, 1, 2].map(|axis| sum[axis] + normal[axis]);
+            }
+        }
+        let vertices = positions
+            .into_iter()
+            .zip(normals)
+            .map(|(position, normal)| {
+                let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+                MeshVertex {
+                    position,
+                    normal: if length > 0.0 {
+                        normal.map(|n| n / length)
+                    } else {
+                        [0.0, 0.0, 1.0]
+                    },
+                }
+            })
+            .collect();
+        Mesh { vertices, indices }
+    }
+}
+
+/// Draws every particle as an instance of a `Mesh`, rotated to face along its velocity.
+///
+/// Built from a vertex state with one of the mesh entry points and the triangle pipeline's
+/// instance vertex buffers, with the mesh's vertices in the slot after them, and a pipeline
+/// for each fragment state it might be drawn with.
+pub struct MeshRenderer {
+    pipelines: Vec<wgpu::RenderPipeline>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    // Slot the mesh's vertices are bound to, after the particle buffers
+    slot: u32,
+}
+
+impl MeshRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        mesh: &Mesh,
+        pipeline_layout: &wgpu::PipelineLayout,
+        vertex: wgpu::VertexState,
+        fragments: &[wgpu::FragmentState],
+        multisample: wgpu::MultisampleState,
+    ) -> Self {
+        let vertex_buffer = resources.buffer_init(
+            device,
+            "Mesh Vertex Buffer",
+            bytemuck::cast_slice(&mesh.vertices),
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buffer = resources.buffer_init(
+            device,
+            "Mesh Index Buffer",
+            bytemuck::cast_slice(&mesh.indices),
+            wgpu::BufferUsages::INDEX,
+        );
+
+        let slot = vertex.buffers.len() as u32;
+        let attributes = wgpu::vertex_attr_array![2 => Float32x3, 3 => Float32x3];
+        let mut buffers = vertex.buffers.to_vec();
+        buffers.push(wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &attributes,
+        });
+        let pipeline = |fragment: &wgpu::FragmentState| {
+            diagnostics::checked(device, "Mesh Pipeline", || {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Mesh Pipeline"),
+                    layout: Some(pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        buffers: &buffers,
+                        ..vertex.clone()
+                    },
+                    fragment: Some(fragment.clone()),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        front_face: wgpu::FrontFace::Ccw,
+                        // Without a depth buffer, hiding the far side keeps it from drawing
+                        // over the near side
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample,
+                    multiview: None,
+                })
+            })
+        };
+
+        MeshRenderer {
+            pipelines: fragments.iter().map(pipeline).collect(),
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+            slot,
+        }
+    }
+
+    /// Draw `instances` particles from the instance buffers already bound, with the
+    /// `fragment`th of the fragment states it was built with.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        fragment: usize,
+        instances: u32,
+    ) {
+        render_pass.set_pipeline(&self.pipelines[fragment]);
+        render_pass.set_vertex_buffer(self.slot, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..instances);
+    }
+}
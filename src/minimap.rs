@@ -0,0 +1,88 @@
+use nannou::prelude::*;
+
+use crate::camera::Camera;
+use crate::theme::Hud;
+use crate::Particle;
+
+// Cells per side of the grid the particles are counted in
+const CELLS: usize = 48;
+const SIZE: f32 = 160.0;
+const MARGIN: f32 = 10.0;
+// Frames between readbacks, as each one waits for the particles
+const INTERVAL: u64 = 15;
+
+/// The whole domain in the corner of the control window, brighter where more particles are,
+/// with what the camera sees outlined, for finding the flock while zoomed in on part of a
+/// larger world. Toggled with M.
+pub struct Minimap {
+    pub visible: bool,
+    // Particles in each cell, row by row from the bottom of the domain
+    counts: Vec<u32>,
+}
+
+impl Minimap {
+    pub fn new(visible: bool) -> Self {
+        Minimap {
+            visible,
+            counts: vec![0; CELLS * CELLS],
+        }
+    }
+
+    /// Whether to read the particles back for `count` on this frame.
+    pub fn due(&self, frame: u64) -> bool {
+        self.visible && frame.is_multiple_of(INTERVAL)
+    }
+
+    pub fn count(&mut self, particles: &[Particle]) {
+        self.counts.fill(0);
+        for particle in particles {
+            let [x, y] = particle.position;
+            // Escaped particles and NaNs stay off the map
+            if !(x.abs() <= 1.0 && y.abs() <= 1.0) {
+                continue;
+            }
+            let cell = |v: f32| (((v + 1.0) * 0.5 * CELLS as f32) as usize).min(CELLS - 1);
+            self.counts[cell(y) * CELLS + cell(x)] += 1;
+        }
+    }
+
+    pub fn draw(&self, draw: &Draw, camera: &Camera, window: Rect, hud: Hud) {
+        let area = Rect::from_w_h(SIZE, SIZE).top_right_of(window.pad(MARGIN));
+        draw.rect().xy(area.xy()).wh(area.wh()).color(hud.panel);
+
+        // Log scale, so stragglers show next to the thick of the flock
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        let scale = 1.0 / (max as f32).ln_1p().max(f32::EPSILON);
+        let cell_size = SIZE / CELLS as f32;
+        for (index, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let (row, column) = (index / CELLS, index % CELLS);
+            let brightness = (count as f32).ln_1p() * scale;
+            draw.rect()
+                .x_y(
+                    area.left() + (column as f32 + 0.5) * cell_size,
+                    area.bottom() + (row as f32 + 0.5) * cell_size,
+                )
+                .w_h(cell_size, cell_size)
+                .color(rgba(0.4, 0.8, 1.0, 0.2 + 0.8 * brightness));
+        }
+
+        // The window's corners on the domain, kept to the map
+        let corners = [
+            window.top_left(),
+            window.top_right(),
+            window.bottom_right(),
+            window.bottom_left(),
+            window.top_left(),
+        ]
+        .map(|corner| {
+            let world = camera
+                .window_to_world(corner, window)
+                .clamp(-Vec2::ONE, Vec2::ONE);
+            area.xy() + world * SIZE * 0.5
+        });
+        draw.polyline().weight(1.5).color(hud.text).points(corners);
+    }
+}
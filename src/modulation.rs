@@ -0,0 +1,125 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+/// Most modulators at once, so each fits a slot of `Action::SetModulator`.
+pub const MAX_MODULATORS: usize = 8;
+
+/// What a `Modulator` swings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Target {
+    Alignment,
+    Cohesion,
+    Separation,
+    MaxSpeed,
+    MinSpeed,
+    /// Only what's drawn, on top of the camera's own zoom
+    Zoom,
+}
+
+/// The shape a `Modulator` swings in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Wave {
+    Sine,
+    Triangle,
+    /// Drifts smoothly between random levels, a new one each cycle
+    Noise,
+    /// Jumps up at the start of each cycle and dies away, only ever raising the parameter
+    Envelope,
+}
+
+/// A parameter swung up and down around the value it's set to, as the simulated time goes by,
+/// so the visuals keep changing without anyone touching them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Modulator {
+    pub target: Target,
+    pub wave: Wave,
+    // Cycles per simulated second
+    pub rate: f32,
+    // Fraction of the parameter's value it swings by to either side
+    pub depth: f32,
+    // Fraction of a cycle in it starts
+    #[serde(default)]
+    pub phase: f32,
+}
+
+impl Modulator {
+    /// Where the wave is at `time` simulated seconds, from -1 to 1, or 0 to 1 for an envelope.
+    pub fn value(&self, time: f32) -> f32 {
+        let cycles = time * self.rate + self.phase;
+        let t = cycles.rem_euclid(1.0);
+        match self.wave {
+            Wave::Sine => (t * TAU).sin(),
+            // In step with the sine, peaking a quarter of the way through
+            Wave::Triangle => 1.0 - 4.0 * ((t + 0.25).rem_euclid(1.0) - 0.5).abs(),
+            Wave::Noise => {
+                let cycle = cycles.floor();
+                let eased = t * t * (3.0 - 2.0 * t);
+                level(cycle) + (level(cycle + 1.0) - level(cycle)) * eased
+            }
+            // Down to a hundredth by the end of the cycle
+            Wave::Envelope => (-t * 4.6).exp(),
+        }
+    }
+}
+
+// A random level from -1 to 1 for each cycle, the same every run
+fn level(cycle: f32) -> f32 {
+    let hash = (cycle as i32 as u32).wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    let hash = (hash ^ (hash >> 15)).wrapping_mul(0x2c1b_3c6d);
+    (hash ^ (hash >> 12)) as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// What `modulators` multiply `target` by at `time`, never below 0.
+pub fn factor(modulators: &[Modulator], target: Target, time: f32) -> f32 {
+    modulators
+        .iter()
+        .filter(|modulator| modulator.target == target)
+        .map(|modulator| (1.0 + modulator.depth * modulator.value(time)).max(0.0))
+        .product()
+}
+
+/// Parse modulators separated by `;`, each `target:wave[,rate[,depth[,phase]]]`, e.g.
+/// `cohesion:sine,0.1,0.5;zoom:envelope,2,0.1`.
+pub fn parse_modulators(s: &str) -> Result<Vec<Modulator>, String> {
+    let modulators = s
+        .split(';')
+        .map(|modulator| {
+            let usage =
+                || format!("expected target:wave[,rate[,depth[,phase]]], got `{modulator}`");
+            let (target, rest) = modulator.trim().split_once(':').ok_or_else(usage)?;
+            let mut fields = rest.split(',').map(str::trim);
+            let target = Target::from_str(target.trim(), true)
+                .map_err(|_| format!("no parameter named `{}` to modulate", target.trim()))?;
+            let wave = fields.next().unwrap_or_default();
+            let wave = Wave::from_str(wave, true).map_err(|_| format!("no wave named `{wave}`"))?;
+            let values = fields
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| usage())?;
+            let (rate, depth, phase) = match values[..] {
+                [] => (0.1, 0.5, 0.0),
+                [rate] => (rate, 0.5, 0.0),
+                [rate, depth] => (rate, depth, 0.0),
+                [rate, depth, phase] => (rate, depth, phase),
+                _ => return Err(usage()),
+            };
+            Ok(Modulator {
+                target,
+                wave,
+                rate,
+                depth,
+                phase,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if modulators.len() > MAX_MODULATORS {
+        return Err(format!(
+            "at most {MAX_MODULATORS} modulators, got {}",
+            modulators.len()
+        ));
+    }
+    Ok(modulators)
+}
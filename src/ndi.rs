@@ -0,0 +1,165 @@
+//! Sending frames as an NDI source, through the NDI runtime loaded when sharing starts, so
+//! the app builds and runs without the SDK installed. Only the handful of sender functions
+//! and types used are declared, matching Processing.NDI.Lib.h.
+
+use libloading::Library;
+use nannou::image::RgbaImage;
+use std::ffi::{c_char, c_void, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+// The runtime library, newest first, looked for in the folder the runtime installer names
+// in these variables, then on the usual library path
+#[cfg(target_os = "windows")]
+const LIBRARIES: [&str; 1] = ["Processing.NDI.Lib.x64.dll"];
+#[cfg(target_os = "macos")]
+const LIBRARIES: [&str; 1] = ["libndi.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LIBRARIES: [&str; 3] = ["libndi.so.6", "libndi.so.5", "libndi.so"];
+const RUNTIME_DIRS: [&str; 2] = ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"];
+
+// NDI_LIB_FOURCC('R', 'G', 'B', 'A')
+const FOURCC_RGBA: u32 = u32::from_le_bytes(*b"RGBA");
+// NDIlib_frame_format_type_progressive
+const PROGRESSIVE: i32 = 1;
+// NDIlib_send_timecode_synthesize, leaving the timecode to the runtime
+const SYNTHESIZE_TIMECODE: i64 = i64::MAX;
+
+// NDIlib_send_create_t
+#[repr(C)]
+struct SendCreate {
+    p_ndi_name: *const c_char,
+    p_groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+// NDIlib_video_frame_v2_t, with `line_stride_in_bytes` standing for its union
+#[repr(C)]
+struct VideoFrame {
+    xres: i32,
+    yres: i32,
+    fourcc: u32,
+    frame_rate_n: i32,
+    frame_rate_d: i32,
+    picture_aspect_ratio: f32,
+    frame_format_type: i32,
+    timecode: i64,
+    p_data: *const u8,
+    line_stride_in_bytes: i32,
+    p_metadata: *const c_char,
+    timestamp: i64,
+}
+
+type Initialize = unsafe extern "C" fn() -> bool;
+type SendCreateFn = unsafe extern "C" fn(*const SendCreate) -> *mut c_void;
+type SendVideo = unsafe extern "C" fn(*mut c_void, *const VideoFrame);
+type SendDestroy = unsafe extern "C" fn(*mut c_void);
+
+/// An NDI source on the network, sending each frame as it's given. Created and used on the
+/// one thread, as the runtime's sender instance isn't shared between threads.
+pub struct NdiSender {
+    instance: *mut c_void,
+    send_video: SendVideo,
+    destroy: SendDestroy,
+    // Only dropped after the instance is destroyed, as the functions live in it
+    _library: Library,
+}
+
+impl NdiSender {
+    /// A source other machines see as `name`, an error if the runtime isn't installed.
+    pub fn new(name: &str) -> Result<Self, String> {
+        let library = load()?;
+        let name = CString::new(name).map_err(|_| format!("NDI name {name:?} holds a NUL"))?;
+        // SAFETY: the symbols are declared with the signatures Processing.NDI.Lib.h gives
+        // them, and copied out while `library` stays loaded for as long as they're used
+        unsafe {
+            let initialize: Initialize = symbol(&library, "NDIlib_initialize")?;
+            let send_create: SendCreateFn = symbol(&library, "NDIlib_send_create")?;
+            let send_video: SendVideo = symbol(&library, "NDIlib_send_send_video_v2")?;
+            let destroy: SendDestroy = symbol(&library, "NDIlib_send_destroy")?;
+            if !initialize() {
+                return Err("The NDI runtime doesn't support this CPU".to_owned());
+            }
+            // Frames go out as they're rendered, the app paces itself
+            let instance = send_create(&SendCreate {
+                p_ndi_name: name.as_ptr(),
+                p_groups: ptr::null(),
+                clock_video: false,
+                clock_audio: false,
+            });
+            if instance.is_null() {
+                return Err("The NDI runtime couldn't create a sender".to_owned());
+            }
+            Ok(NdiSender {
+                instance,
+                send_video,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+
+    /// Send `image`, returning once the runtime has copied or sent it.
+    pub fn send(&mut self, image: &RgbaImage) {
+        let frame = VideoFrame {
+            xres: image.width() as i32,
+            yres: image.height() as i32,
+            fourcc: FOURCC_RGBA,
+            frame_rate_n: 60,
+            frame_rate_d: 1,
+            // 0 for the frame's own shape
+            picture_aspect_ratio: 0.0,
+            frame_format_type: PROGRESSIVE,
+            timecode: SYNTHESIZE_TIMECODE,
+            p_data: image.as_raw().as_ptr(),
+            line_stride_in_bytes: image.width() as i32 * 4,
+            p_metadata: ptr::null(),
+            timestamp: 0,
+        };
+        // SAFETY: `instance` is live until dropped, and the call without clocking is
+        // synchronous, so `image` outlives the runtime's use of it
+        unsafe { (self.send_video)(self.instance, &frame) }
+    }
+}
+
+impl Drop for NdiSender {
+    fn drop(&mut self) {
+        // SAFETY: `instance` came from NDIlib_send_create and isn't used after this
+        unsafe { (self.destroy)(self.instance) }
+    }
+}
+
+// # Safety
+//
+// `T` is the function's type as Processing.NDI.Lib.h declares it, and the copy isn't called
+// once `library` is dropped.
+unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T, String> {
+    library
+        .get::<T>(name.as_bytes())
+        .map(|symbol| *symbol)
+        .map_err(|err| format!("The NDI runtime has no {name}: {err}"))
+}
+
+fn load() -> Result<Library, String> {
+    let folders = RUNTIME_DIRS
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from);
+    let paths = folders
+        .flat_map(|folder| LIBRARIES.map(|library| folder.join(library)))
+        .chain(LIBRARIES.map(PathBuf::from));
+    let mut errors = Vec::new();
+    for path in paths {
+        // SAFETY: the NDI runtime does nothing on load beyond its own setup
+        match unsafe { Library::new(&path) } {
+            Ok(library) => return Ok(library),
+            Err(err) => errors.push(err.to_string()),
+        }
+    }
+    Err(format!(
+        "No NDI runtime found, install it from ndi.video or set {}: {}",
+        RUNTIME_DIRS[0],
+        errors.join(", ")
+    ))
+}
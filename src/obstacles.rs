@@ -0,0 +1,243 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::camera::Camera;
+use crate::diagnostics;
+use crate::level::Level;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Every particle checks every wall, so this keeps the loop short
+pub const MAX_SEGMENTS: usize = 4096;
+// Half a wall's thickness, in domain units. More than the boids' top speed, so they can't
+// step through a wall in one frame
+pub const RADIUS: f32 = 0.01;
+const BOUNCE: f32 = 0.5;
+
+wgsl_struct! {
+    // Must match `ObstacleParams` in obstacle_shader.wgsl
+    struct ObstacleParams {
+        particle_count: u32,
+        segment_count: u32,
+        radius: f32,
+        bounce: f32,
+        level_width: u32,
+        level_height: u32,
+    }
+}
+
+/// Walls made of line segments, and optionally a `Level`, which particles bounce off after
+/// the rest of the step.
+///
+/// A sandbox for drawing in: segments are appended as they're drawn and uploaded the next
+/// frame, up to `MAX_SEGMENTS`. The level is fixed once created and kept by clearing.
+pub struct Obstacles {
+    // End points as [ax, ay, bx, by]
+    segments: Vec<[f32; 4]>,
+    // The level's walls as [left, bottom, right, top], for drawing
+    level_runs: Vec<[f32; 4]>,
+    // Zero without a level
+    level_size: [u32; 2],
+    // Segments already in `segment_buffer`
+    uploaded: usize,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ObstacleParams>,
+    segment_buffer: wgpu::Buffer,
+    // The level's signed distance field, or a single unused value without one
+    level_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Obstacles {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, level: Option<&Level>) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "obstacle_shader",
+            include_str!("./shaders/obstacle_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_ro(2)
+            .storage_ro(3)
+            .build(device, "Obstacle");
+        let params_buffer = UniformBuffer::new(device, resources, "Obstacle Params Buffer");
+        let segment_buffer = resources.buffer(
+            device,
+            "Obstacle Segment Buffer",
+            (MAX_SEGMENTS * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        );
+        let distances = level.map_or(vec![0.0], Level::signed_distance);
+        let level_buffer = resources.buffer_init(
+            device,
+            "Obstacle Level Buffer",
+            bytemuck::cast_slice(&distances),
+            BufferUsages::STORAGE,
+        );
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &segment_buffer,
+            &level_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Obstacle Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Obstacle Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Obstacle Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "collide",
+            })
+        });
+
+        Obstacles {
+            segments: Vec::new(),
+            level_runs: level.map_or_else(Vec::new, Level::runs),
+            level_size: level.map_or([0, 0], |level| [level.width, level.height]),
+            uploaded: 0,
+            pipeline,
+            bindings,
+            params_buffer,
+            segment_buffer,
+            level_buffer,
+            bind_groups,
+        }
+    }
+
+    /// Add a wall from `a` to `b`, in domain units. Returns false once there are
+    /// `MAX_SEGMENTS`.
+    pub fn push(&mut self, a: [f32; 2], b: [f32; 2]) -> bool {
+        if self.segments.len() >= MAX_SEGMENTS {
+            return false;
+        }
+        self.segments.push([a[0], a[1], b[0], b[1]]);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.uploaded = 0;
+    }
+
+    /// How many walls have been drawn, not counting the level.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The drawn walls as [ax, ay, bx, by], not counting the level.
+    pub fn segments(&self) -> &[[f32; 4]] {
+        &self.segments
+    }
+
+    /// Whether there's nothing to bounce off, neither drawn walls nor a level.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty() && self.level_size == [0, 0]
+    }
+
+    /// Draw the level's walls, then the drawn ones as lines as thick as they are, seen
+    /// through `camera`.
+    pub fn draw(&self, draw: &Draw, camera: &Camera, window: Rect) {
+        for &[left, bottom, right, top] in &self.level_runs {
+            let min = camera.world_to_window(vec2(left, bottom), window);
+            let max = camera.world_to_window(vec2(right, top), window);
+            draw.rect().xy((min + max) / 2.0).wh(max - min).color(GRAY);
+        }
+        let weight = (RADIUS * 2.0 * camera.zoom * window.w() / 2.0).max(1.0);
+        for &[ax, ay, bx, by] in &self.segments {
+            draw.line()
+                .start(camera.world_to_window(vec2(ax, ay), window))
+                .end(camera.world_to_window(vec2(bx, by), window))
+                .weight(weight)
+                .caps_round()
+                .color(GRAY);
+        }
+    }
+}
+
+impl Stage for Obstacles {
+    fn kind(&self) -> StageKind {
+        StageKind::Obstacles
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        if self.uploaded < self.segments.len() {
+            let offset = self.uploaded * std::mem::size_of::<[f32; 4]>();
+            frame.queue.write_buffer(
+                &self.segment_buffer,
+                offset as wgpu::BufferAddress,
+                bytemuck::cast_slice(&self.segments[self.uploaded..]),
+            );
+            self.uploaded = self.segments.len();
+        }
+        let params = ObstacleParams {
+            particle_count: frame.particle_count,
+            segment_count: self.segments.len() as u32,
+            radius: RADIUS,
+            bounce: BOUNCE,
+            level_width: self.level_size[0],
+            level_height: self.level_size[1],
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Obstacle Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.segment_buffer,
+            &self.level_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ObstacleParams>,
+    segment_buffer: &wgpu::Buffer,
+    level_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                segment_buffer.as_entire_binding(),
+                level_buffer.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,185 @@
+use nannou::image::{imageops, RgbaImage};
+use nannou::prelude::*;
+use nannou::wgpu;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use tracing::{error, info};
+
+use crate::camera::Camera;
+
+// Largest tile rendered at once, well under most texture limits. Multisampled float
+// targets this size already take 128 MiB.
+const MAX_TILE: u32 = 2048;
+
+/// `--render`: simulate for a while, then save one frame, or a run of them, at a resolution
+/// independent of the window and quit.
+pub struct OfflineRender {
+    pub size: [u32; 2],
+    // Frames simulated before rendering
+    pub frames: u64,
+    // Consecutive frames saved, numbered when there's more than one
+    pub count: u64,
+    pub path: PathBuf,
+    // Only the particles, on a transparent background, for compositing
+    pub transparent: bool,
+}
+
+/// The part of the full image being rendered.
+pub struct Tile {
+    // Camera that puts just this tile on screen
+    pub camera: Camera,
+    // Offset and scale of the tile in full-image uv coordinates (y up), for the background
+    pub view: [f32; 4],
+}
+
+pub fn parse_size(s: &str) -> Result<[u32; 2], String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected a size like 4096x4096, got `{s}`"))?;
+    let parse = |n: &str| match n.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid size `{s}`")),
+    };
+    Ok([parse(width)?, parse(height)?])
+}
+
+impl OfflineRender {
+    /// Render the image as an n x n grid of tiles, each no bigger than the texture limit.
+    /// `draw` encodes the scene for one tile into the given render pass attachment.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sample_count: u32,
+        camera: &Camera,
+        clear: wgpu::Color,
+        mut draw: impl FnMut(&mut wgpu::CommandEncoder, wgpu::RenderPassColorAttachment, &Tile),
+    ) -> RgbaImage {
+        let [width, height] = self.size;
+        let max_tile = MAX_TILE.min(device.limits().max_texture_dimension_2d);
+        // Same count on both axes so every tile keeps the full image's aspect ratio
+        let tiles = width.max(height).div_ceil(max_tile);
+        let tile_size = [width.div_ceil(tiles), height.div_ceil(tiles)];
+        info!(
+            "Rendering {}x{} in {} tiles of {}x{}",
+            width,
+            height,
+            tiles * tiles,
+            tile_size[0],
+            tile_size[1]
+        );
+
+        let target = |samples: u32, usage: wgpu::TextureUsages| {
+            wgpu::TextureBuilder::new()
+                .size(tile_size)
+                .format(Frame::TEXTURE_FORMAT)
+                .sample_count(samples)
+                .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | usage)
+                .build(device)
+        };
+        let resolved = target(
+            1,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        );
+        let multisampled =
+            (sample_count > 1).then(|| target(sample_count, wgpu::TextureUsages::empty()));
+        let resolved_view = resolved.view().build();
+        let multisampled_view = multisampled.as_ref().map(|texture| texture.view().build());
+        let capturer = wgpu::TextureCapturer::new(Some(1), None);
+
+        let mut image = RgbaImage::new(width, height);
+        for row in 0..tiles {
+            for column in 0..tiles {
+                // Tile centre in the full image's clip space
+                let offset = vec2(
+                    (2 * column + 1) as f32 / tiles as f32 - 1.0,
+                    1.0 - (2 * row + 1) as f32 / tiles as f32,
+                );
+                let tile = Tile {
+                    camera: Camera {
+                        crop: (offset, tiles as f32),
+                        ..*camera
+                    },
+                    view: [
+                        column as f32 / tiles as f32,
+                        (tiles - 1 - row) as f32 / tiles as f32,
+                        1.0 / tiles as f32,
+                        1.0 / tiles as f32,
+                    ],
+                };
+
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Offline Render Encoder"),
+                });
+                let attachment = wgpu::RenderPassColorAttachment {
+                    view: multisampled_view.as_ref().unwrap_or(&resolved_view),
+                    resolve_target: multisampled_view.as_ref().map(|_| &*resolved_view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear),
+                        store: true,
+                    },
+                };
+                draw(&mut encoder, attachment, &tile);
+                let snapshot = capturer.capture(device, &mut encoder, &resolved);
+                queue.submit(Some(encoder.finish()));
+
+                let (sender, receiver) = mpsc::channel();
+                let result = snapshot.read(move |result| match result {
+                    Ok(tile) => {
+                        let _ = sender.send(tile.to_owned());
+                    }
+                    Err(err) => error!("Failed to read back render tile: {:?}", err),
+                });
+                if result.is_err() || capturer.await_active_snapshots(device).is_err() {
+                    error!("Timed out reading back render tile");
+                }
+                // Tiles past the right and bottom edges get cropped
+                if let Ok(tile) = receiver.recv() {
+                    let x = column * tile_size[0];
+                    let y = row * tile_size[1];
+                    imageops::replace(&mut image, &tile, x, y);
+                }
+            }
+        }
+        if self.transparent {
+            unpremultiply(&mut image);
+        }
+        image
+    }
+
+    /// Save the `index`th frame of the run.
+    pub fn save(&self, image: &RgbaImage, index: u64) {
+        let path = self.path(index);
+        match image.save(&path) {
+            Ok(()) => info!("Saved render to {}", path.display()),
+            Err(err) => error!("Failed to save {}: {}", path.display(), err),
+        }
+    }
+
+    // `--render-output` as given for a single frame, with the index before the extension for
+    // a run of them
+    fn path(&self, index: u64) -> PathBuf {
+        if self.count == 1 {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{stem}-{index:04}.{}", extension.to_string_lossy()),
+            None => format!("{stem}-{index:04}"),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+// Blending onto a transparent clear leaves colours multiplied by their coverage, where PNGs
+// expect them as they are
+fn unpremultiply(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if a == 0 || a == 255 {
+            continue;
+        }
+        let scale = |c: u8| (c as u32 * 255 / a as u32).min(255) as u8;
+        pixel.0 = [scale(r), scale(g), scale(b), a];
+    }
+}
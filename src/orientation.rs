@@ -0,0 +1,211 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+const SPIN_ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x2];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrientationConfig {
+    // Torque per radian between where a particle faces and where it's heading
+    pub align: f32,
+    // Fraction of the angular velocity lost each frame
+    pub damping: f32,
+    // Largest starting angular velocity, in radians per frame
+    pub spin: f32,
+}
+
+impl Default for OrientationConfig {
+    fn default() -> Self {
+        OrientationConfig {
+            align: 0.05,
+            damping: 0.2,
+            spin: 0.2,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `OrientationParams` in orientation_shader.wgsl
+    struct OrientationParams {
+        align: f32,
+        damping: f32,
+        spin: f32,
+    }
+}
+
+/// An angle and angular velocity per particle, so particles can spin and be drawn facing
+/// somewhere other than along their velocity.
+///
+/// Kept in a buffer of its own, indexed like the particles, and torqued towards each
+/// particle's heading after the rest of the step. Anything that reorders the particles
+/// without it would leave it behind, so it doesn't go with compaction or culling. Spins start over
+/// when the buffers are resized.
+pub struct Orientation {
+    pub config: OrientationConfig,
+    init: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<OrientationParams>,
+    spins: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the spins have been started from the particles' headings
+    needs_init: bool,
+}
+
+impl Orientation {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: OrientationConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "orientation_shader",
+            include_str!("./shaders/orientation_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Orientation");
+        let params_buffer = UniformBuffer::new(device, resources, "Orientation Params Buffer");
+        let (spins, bind_groups) = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Orientation Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Orientation Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Orientation Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Orientation {
+            config,
+            init: pipeline("init"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            spins,
+            bind_groups,
+            needs_init: true,
+        }
+    }
+
+    /// The instance vertex buffer `draw` binds, with the angle and angular velocity at
+    /// location 4.
+    pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &SPIN_ATTRIBUTES,
+        }
+    }
+
+    /// Each particle's angle and angular velocity, for moving them along with the particles.
+    pub fn spins_buffer(&self) -> &wgpu::Buffer {
+        &self.spins
+    }
+
+    /// Bind the spins as the instance vertex buffer in `slot`.
+    pub fn set_vertex_buffer<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, slot: u32) {
+        render_pass.set_vertex_buffer(slot, self.spins.slice(..));
+    }
+
+    /// Encode turning every particle towards its heading. Run after the rest of the step.
+    pub fn encode(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        count: u32,
+    ) {
+        let params = OrientationParams {
+            align: self.config.align,
+            damping: self.config.damping.clamp(0.0, 1.0),
+            spin: self.config.spin,
+        };
+        self.params_buffer.write(queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Orientation Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        if std::mem::take(&mut self.needs_init) {
+            compute_pass.set_pipeline(&self.init);
+            compute_pass.dispatch_workgroups(resources.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        compute_pass.set_pipeline(&self.step);
+        compute_pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+impl Stage for Orientation {
+    fn kind(&self) -> StageKind {
+        StageKind::Orientation
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        Orientation::encode(self, frame.queue, encoder, resources, frame.particle_count);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.spins, self.bind_groups) =
+            bind(device, resources, &self.bindings, &self.params_buffer);
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<OrientationParams>,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let spins = resources.buffer(
+        device,
+        "Orientation Spins Buffer",
+        resources.capacity() as wgpu::BufferAddress
+            * std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE | BufferUsages::VERTEX | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                spins.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    });
+    (spins, bind_groups)
+}
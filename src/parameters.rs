@@ -0,0 +1,191 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::particle_system::BoidsParams;
+
+/// A boids parameter by name, with the range it's taken in, so everything that sets one from
+/// outside, sweeps, `--set` and remote clients, shares one list of names and limits rather
+/// than each keeping its own. A new parameter goes in `PARAMETERS` and reaches all of them.
+#[derive(Clone, Copy)]
+pub struct Parameter {
+    // Kebab-case, as typed on the command line and sent by clients
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    get: fn(&BoidsParams) -> f32,
+    set: fn(&mut BoidsParams, f32),
+}
+
+pub const PARAMETERS: [Parameter; 10] = [
+    Parameter {
+        name: "alignment",
+        min: 0.0,
+        max: 10.0,
+        get: |params| params.weights.alignment,
+        set: |params, value| params.weights.alignment = value,
+    },
+    Parameter {
+        name: "cohesion",
+        min: 0.0,
+        max: 10.0,
+        get: |params| params.weights.cohesion,
+        set: |params, value| params.weights.cohesion = value,
+    },
+    Parameter {
+        name: "separation",
+        min: 0.0,
+        max: 10.0,
+        get: |params| params.weights.separation,
+        set: |params, value| params.weights.separation = value,
+    },
+    Parameter {
+        name: "alignment-radius",
+        min: 0.0,
+        max: 1.0,
+        get: |params| params.radii.alignment,
+        set: |params, value| params.radii.alignment = value,
+    },
+    Parameter {
+        name: "cohesion-radius",
+        min: 0.0,
+        max: 1.0,
+        get: |params| params.radii.cohesion,
+        set: |params, value| params.radii.cohesion = value,
+    },
+    Parameter {
+        name: "separation-radius",
+        min: 0.0,
+        max: 1.0,
+        get: |params| params.radii.separation,
+        set: |params, value| params.radii.separation = value,
+    },
+    Parameter {
+        name: "max-speed",
+        min: 0.0,
+        max: 0.1,
+        get: |params| params.speed_limits.max,
+        set: |params, value| params.speed_limits.max = value,
+    },
+    Parameter {
+        name: "min-speed",
+        min: 0.0,
+        max: 0.1,
+        get: |params| params.speed_limits.min,
+        set: |params, value| params.speed_limits.min = value,
+    },
+    Parameter {
+        name: "speed-decay",
+        min: 0.0,
+        max: 1.0,
+        get: |params| params.speed_limits.decay,
+        set: |params, value| params.speed_limits.decay = value,
+    },
+    // Degrees, 180 all round
+    Parameter {
+        name: "view-angle",
+        min: 1.0,
+        max: 180.0,
+        get: |params| params.view_angle.unwrap_or(180.0),
+        set: |params, value| params.view_angle = (value < 180.0).then_some(value),
+    },
+];
+
+impl Parameter {
+    pub fn value(&self, params: &BoidsParams) -> f32 {
+        (self.get)(params)
+    }
+
+    /// Set to `value`, kept to the range.
+    pub fn set(&self, params: &mut BoidsParams, value: f32) {
+        (self.set)(params, value.clamp(self.min, self.max));
+    }
+
+    /// What it is unless set.
+    pub fn default_value(&self) -> f32 {
+        self.value(&BoidsParams::default())
+    }
+}
+
+impl fmt::Debug for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+// The accessors are fixed by the name
+impl PartialEq for Parameter {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+// By name, so recordings and clients name what they set
+impl Serialize for Parameter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for &'static Parameter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        find(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn find(name: &str) -> Result<&'static Parameter, String> {
+    PARAMETERS
+        .iter()
+        .find(|parameter| parameter.name == name.trim())
+        .ok_or_else(|| {
+            let names = PARAMETERS.map(|parameter| parameter.name).join(", ");
+            format!("unknown parameter `{name}`, expected one of {names}")
+        })
+}
+
+/// Parse `NAME=VALUE`, e.g. `cohesion=0.5`, refusing values outside the parameter's range.
+pub fn parse_assignment(s: &str) -> Result<(&'static Parameter, f32), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got `{s}`"))?;
+    let parameter = find(name)?;
+    let value = value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid number `{value}`"))?;
+    if !(parameter.min..=parameter.max).contains(&value) {
+        return Err(format!(
+            "expected {} from {} to {}, got {value}",
+            parameter.name, parameter.min, parameter.max
+        ));
+    }
+    Ok((parameter, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_parameters_by_name() {
+        let mut params = BoidsParams::default();
+        let (cohesion, value) = parse_assignment("cohesion=0.5").unwrap();
+        cohesion.set(&mut params, value);
+        assert_eq!(params.weights.cohesion, 0.5);
+        assert_eq!(cohesion.default_value(), 1.0);
+
+        let view_angle = find("view-angle").unwrap();
+        view_angle.set(&mut params, 90.0);
+        assert_eq!(params.view_angle, Some(90.0));
+        view_angle.set(&mut params, 360.0);
+        assert_eq!(params.view_angle, None);
+
+        assert!(parse_assignment("cohesion=-1").is_err());
+        assert!(parse_assignment("cohesian=1").is_err());
+
+        let json = serde_json::to_value(cohesion).unwrap();
+        assert_eq!(json, "cohesion");
+        let parameter: &Parameter = serde_json::from_value(json).unwrap();
+        assert_eq!(parameter, cohesion);
+    }
+}
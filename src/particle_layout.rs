@@ -0,0 +1,311 @@
+use nannou::wgpu::{self, VertexAttribute};
+use std::mem::offset_of;
+
+use crate::{pack_halves, unpack_halves, HalfParticle, Particle};
+
+/// How particles are stored in the particle buffers. Anything other than the default is only
+/// read by the boids step and the plain sprite rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParticleLayout {
+    // Each position and velocity as a pair of half floats, see `HalfParticle`
+    pub half_precision: bool,
+    // Every position, then every velocity, rather than interleaved. Neighbour loops only read
+    // positions, so this wastes less of each memory transaction
+    pub separate_arrays: bool,
+}
+
+// Offsets from the structs themselves, so they follow any change to the fields
+const INTERLEAVED_FULL: [VertexAttribute; 2] = [
+    attribute(
+        0,
+        wgpu::VertexFormat::Float32x2,
+        offset_of!(Particle, position),
+    ),
+    attribute(
+        1,
+        wgpu::VertexFormat::Float32x2,
+        offset_of!(Particle, velocity),
+    ),
+];
+const INTERLEAVED_HALF: [VertexAttribute; 2] = [
+    attribute(
+        0,
+        wgpu::VertexFormat::Float16x2,
+        offset_of!(HalfParticle, position),
+    ),
+    attribute(
+        1,
+        wgpu::VertexFormat::Float16x2,
+        offset_of!(HalfParticle, velocity),
+    ),
+];
+const POSITIONS_FULL: [VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x2];
+const VELOCITIES_FULL: [VertexAttribute; 1] = wgpu::vertex_attr_array![1 => Float32x2];
+const POSITIONS_HALF: [VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float16x2];
+const VELOCITIES_HALF: [VertexAttribute; 1] = wgpu::vertex_attr_array![1 => Float16x2];
+
+const fn attribute(
+    shader_location: u32,
+    format: wgpu::VertexFormat,
+    offset: usize,
+) -> VertexAttribute {
+    VertexAttribute {
+        format,
+        offset: offset as wgpu::BufferAddress,
+        shader_location,
+    }
+}
+
+impl ParticleLayout {
+    /// Bytes per position or velocity.
+    pub fn component_size(self) -> wgpu::BufferAddress {
+        if self.half_precision {
+            4
+        } else {
+            8
+        }
+    }
+
+    /// Bytes per particle.
+    pub fn stride(self) -> wgpu::BufferAddress {
+        let size = if self.half_precision {
+            std::mem::size_of::<HalfParticle>()
+        } else {
+            std::mem::size_of::<Particle>()
+        };
+        size as wgpu::BufferAddress
+    }
+
+    /// How many vertex buffers `GpuResources::set_vertex_buffers` binds, from slot 0.
+    pub fn vertex_buffer_count(self) -> u32 {
+        if self.separate_arrays {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Instance vertex buffers with the position at location 0 and the velocity at 1, widened
+    /// to f32 either way. Matches `GpuResources::set_vertex_buffers`.
+    pub fn vertex_buffer_layouts(self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        let layout = |attributes, array_stride| wgpu::VertexBufferLayout {
+            array_stride,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes,
+        };
+        let size = self.component_size();
+        match (self.separate_arrays, self.half_precision) {
+            (false, false) => vec![layout(&INTERLEAVED_FULL, self.stride())],
+            (false, true) => vec![layout(&INTERLEAVED_HALF, self.stride())],
+            (true, false) => vec![
+                layout(&POSITIONS_FULL, size),
+                layout(&VELOCITIES_FULL, size),
+            ],
+            (true, true) => vec![
+                layout(&POSITIONS_HALF, size),
+                layout(&VELOCITIES_HALF, size),
+            ],
+        }
+    }
+
+    /// Buffer contents holding exactly `particles`.
+    pub fn encode(self, particles: &[Particle]) -> Vec<u8> {
+        let components: Vec<[f32; 2]> = if self.separate_arrays {
+            let positions = particles.iter().map(|particle| particle.position);
+            positions
+                .chain(particles.iter().map(|particle| particle.velocity))
+                .collect()
+        } else {
+            particles
+                .iter()
+                .flat_map(|particle| [particle.position, particle.velocity])
+                .collect()
+        };
+        if self.half_precision {
+            let packed = components.into_iter().map(pack_halves).collect::<Vec<_>>();
+            bytemuck::cast_slice(&packed).to_vec()
+        } else {
+            bytemuck::cast_slice(&components).to_vec()
+        }
+    }
+
+    /// The first `count` particles of a buffer holding `capacity`, from at least the bytes
+    /// `bytes_needed` asks for.
+    pub fn decode(self, bytes: &[u8], capacity: u32, count: u32) -> Vec<Particle> {
+        let size = self.component_size() as usize;
+        let component = |index: usize| {
+            let bytes = &bytes[index * size..(index + 1) * size];
+            if self.half_precision {
+                unpack_halves(bytemuck::pod_read_unaligned(bytes))
+            } else {
+                bytemuck::pod_read_unaligned(bytes)
+            }
+        };
+        (0..count as usize)
+            .map(|index| {
+                let (position, velocity) = if self.separate_arrays {
+                    (index, capacity as usize + index)
+                } else {
+                    (2 * index, 2 * index + 1)
+                };
+                Particle {
+                    position: component(position),
+                    velocity: component(velocity),
+                }
+            })
+            .collect()
+    }
+
+    /// How many bytes from the start of a buffer holding `capacity` cover the first `count`
+    /// particles.
+    pub fn bytes_needed(self, capacity: u32, count: u32) -> wgpu::BufferAddress {
+        if self.separate_arrays {
+            (capacity + count) as wgpu::BufferAddress * self.component_size()
+        } else {
+            count as wgpu::BufferAddress * self.stride()
+        }
+    }
+
+    /// Byte ranges holding the first `count` particles' data, as (offset in a buffer holding
+    /// `from`, offset in one holding `to`, length), for keeping them when resizing.
+    pub fn kept_ranges(
+        self,
+        from: u32,
+        to: u32,
+        count: u32,
+    ) -> Vec<(
+        wgpu::BufferAddress,
+        wgpu::BufferAddress,
+        wgpu::BufferAddress,
+    )> {
+        let size = self.component_size();
+        let count = count as wgpu::BufferAddress;
+        if self.separate_arrays {
+            vec![
+                (0, 0, count * size),
+                (
+                    from as wgpu::BufferAddress * size,
+                    to as wgpu::BufferAddress * size,
+                    count * size,
+                ),
+            ]
+        } else {
+            vec![(0, 0, count * self.stride())]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [ParticleLayout; 4] = [
+        ParticleLayout {
+            half_precision: false,
+            separate_arrays: false,
+        },
+        ParticleLayout {
+            half_precision: true,
+            separate_arrays: false,
+        },
+        ParticleLayout {
+            half_precision: false,
+            separate_arrays: true,
+        },
+        ParticleLayout {
+            half_precision: true,
+            separate_arrays: true,
+        },
+    ];
+
+    fn particles(count: usize) -> Vec<Particle> {
+        (0..count)
+            .map(|i| {
+                let i = i as f32;
+                Particle {
+                    position: [i * 0.25 - 1.0, 0.5 - i * 0.125],
+                    velocity: [i * 0.0625, -i * 0.03125],
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_layouts_match_the_structs() {
+        for layout in LAYOUTS {
+            let buffers = layout.vertex_buffer_layouts();
+            assert_eq!(buffers.len() as u32, layout.vertex_buffer_count());
+            let attributes = buffers
+                .iter()
+                .flat_map(|buffer| buffer.attributes)
+                .collect::<Vec<_>>();
+            let locations = attributes
+                .iter()
+                .map(|attribute| attribute.shader_location)
+                .collect::<Vec<_>>();
+            assert_eq!(locations, [0, 1]);
+            for (buffer, attribute) in buffers.iter().zip(&attributes) {
+                assert_eq!(attribute.format.size(), layout.component_size());
+                assert!(attribute.offset + attribute.format.size() <= buffer.array_stride);
+            }
+            if !layout.separate_arrays {
+                assert_eq!(buffers[0].array_stride, layout.stride());
+                assert_eq!(attributes[0].offset, 0);
+                assert_eq!(attributes[1].offset, layout.component_size());
+            }
+        }
+    }
+
+    #[test]
+    fn strides_match_the_structs() {
+        assert_eq!(
+            LAYOUTS[0].stride() as usize,
+            std::mem::size_of::<Particle>()
+        );
+        assert_eq!(
+            LAYOUTS[1].stride() as usize,
+            std::mem::size_of::<HalfParticle>()
+        );
+        for layout in LAYOUTS {
+            assert_eq!(layout.stride(), 2 * layout.component_size());
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        // Quarters and eighths, which half floats hold exactly
+        let particles = particles(9);
+        let count = particles.len() as u32;
+        for layout in LAYOUTS {
+            let bytes = layout.encode(&particles);
+            assert_eq!(
+                bytes.len() as wgpu::BufferAddress,
+                layout.bytes_needed(count, count)
+            );
+            let decoded = layout.decode(&bytes, count, count);
+            for (decoded, particle) in decoded.iter().zip(&particles) {
+                assert_eq!(decoded.position, particle.position, "{layout:?}");
+                assert_eq!(decoded.velocity, particle.velocity, "{layout:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_the_first_of_a_larger_buffer() {
+        let particles = particles(6);
+        for layout in LAYOUTS {
+            let bytes = layout.encode(&particles);
+            let decoded = layout.decode(&bytes, 6, 2);
+            assert_eq!(decoded.len(), 2);
+            assert_eq!(decoded[1].velocity, particles[1].velocity, "{layout:?}");
+            assert!(layout.bytes_needed(6, 2) <= bytes.len() as wgpu::BufferAddress);
+        }
+    }
+
+    #[test]
+    fn keeps_both_arrays_when_resizing() {
+        let layout = LAYOUTS[2];
+        assert_eq!(layout.kept_ranges(10, 20, 4), [(0, 0, 32), (80, 160, 32)]);
+        assert_eq!(LAYOUTS[0].kept_ranges(10, 20, 4), [(0, 0, 64)]);
+    }
+}
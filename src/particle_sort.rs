@@ -0,0 +1,405 @@
+use clap::ValueEnum;
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::gpu::{Gather, RadixSort};
+use crate::resources::GpuResources;
+use crate::species::{self, SpeciesConfig};
+use crate::wgsl::{wgsl_struct, WgslType};
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// What the particles are put in order of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    /// Where they are, along a Z-order curve, so neighbours sit near each other in memory
+    Cell,
+    /// Youngest first, the dead last, for particles that age
+    Age,
+}
+
+impl SortKey {
+    // Must match `keys` in sort_keys_shader.wgsl
+    fn shader_index(self) -> u32 {
+        match self {
+            SortKey::Cell => 0,
+            SortKey::Age => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SortConfig {
+    pub key: SortKey,
+    // Frames between sorts. Particles don't move far in a frame, so there's no need to sort
+    // every one
+    pub interval: u32,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig {
+            key: SortKey::Cell,
+            interval: 60,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `SortKeyParams` in sort_keys_shader.wgsl
+    struct SortKeyParams {
+        particle_count: u32,
+        key: u32,
+        species: u32,
+    }
+}
+
+/// Reorders the particle buffer every so often with a `RadixSort`, each species within its
+/// own share of the buffer so it's still drawn as one range. Whatever is indexed like the
+/// particles, e.g. ages, flags and dyes, is moved along with them.
+///
+/// Sorting by cell keeps the particles a neighbour loop reads close together in memory.
+/// Springs refer to particles by index, so networks are never sorted.
+pub struct ParticleSort {
+    pub config: SortConfig,
+    // Kept apart, as they're drawn
+    species: Vec<SpeciesConfig>,
+    // Whether `GpuResources::dyes` are moved too
+    dyed: bool,
+    keys: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    radix_sort: RadixSort,
+    gather: Gather,
+    // Made on the first sort and again whenever the particle count changes
+    scratch: Option<Scratch>,
+}
+
+// What a sort of `count` particles works in, tracked in `GpuResources`
+struct Scratch {
+    count: u32,
+    key: SortKey,
+    sort_keys: wgpu::Buffer,
+    order: wgpu::Buffer,
+    // Where each species ends
+    ends: wgpu::Buffer,
+    params: wgpu::Buffer,
+    // Bound in place of the lives when there aren't any
+    lives_placeholder: wgpu::Buffer,
+    // The `RadixSort`'s
+    radix: [wgpu::Buffer; 3],
+    // Each moved buffer is gathered into this in turn, then copied back. Grown to fit the
+    // largest
+    moved: wgpu::Buffer,
+}
+
+impl ParticleSort {
+    pub fn new(
+        device: &wgpu::Device,
+        config: SortConfig,
+        species: Vec<SpeciesConfig>,
+        dyed: bool,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "sort_keys_shader",
+            include_str!("./shaders/sort_keys_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .storage_ro(1)
+            .storage_ro(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .uniform(5)
+            .build(device, "Sort Keys");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sort Keys Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let keys = diagnostics::checked(device, "Sort Keys Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Sort Keys Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "keys",
+            })
+        });
+
+        ParticleSort {
+            config,
+            species,
+            dyed,
+            keys,
+            bindings,
+            radix_sort: RadixSort::new(device),
+            gather: Gather::new(device),
+            scratch: None,
+        }
+    }
+
+    /// Whether the particles are due a sort before `frame` is drawn.
+    pub fn due(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.config.interval.max(1) as u64)
+    }
+
+    /// Encode sorting the first `count` particles. `lives` are the particles' `Life`s to sort
+    /// by age, and are moved too, like each of `indexed`, whole elements per particle, the
+    /// flags and the dyes when dyed. Those buffers need `COPY_DST`.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+        count: u32,
+        lives: Option<&wgpu::Buffer>,
+        indexed: &[&wgpu::Buffer],
+    ) {
+        if count < 2 {
+            return;
+        }
+        let key = match lives {
+            Some(_) => self.config.key,
+            None => SortKey::Cell,
+        };
+        let u32_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let moved_size = self
+            .moved(resources, lives, indexed)
+            .map(|(_, words)| count as u64 * words as u64 * u32_size)
+            .max()
+            .unwrap_or(0);
+        let stale = match &self.scratch {
+            Some(scratch) => {
+                scratch.count != count || scratch.key != key || scratch.moved.size() < moved_size
+            }
+            None => true,
+        };
+        if stale {
+            self.scratch = Some(self.scratch(device, resources, count, key, moved_size));
+        }
+        let scratch = self.scratch.as_ref().unwrap();
+
+        // Read only for the age key, a lone placeholder otherwise
+        let lives_binding = lives.unwrap_or(&scratch.lives_placeholder);
+        let bind_group = self.bindings.bind_group(
+            device,
+            &[
+                resources.particles().as_entire_binding(),
+                lives_binding.as_entire_binding(),
+                scratch.ends.as_entire_binding(),
+                scratch.sort_keys.as_entire_binding(),
+                scratch.order.as_entire_binding(),
+                scratch.params.as_entire_binding(),
+            ],
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Sort Keys Pass"),
+            });
+            compute_pass.set_pipeline(&self.keys);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        let [radix_keys, radix_values, histograms] = &scratch.radix;
+        self.radix_sort.encode_in(
+            device,
+            encoder,
+            &scratch.sort_keys,
+            &scratch.order,
+            count,
+            [radix_keys, radix_values, histograms],
+        );
+
+        // Into the other ping-pong buffer, which becomes the latest
+        let particle_words = (resources.layout().stride() / u32_size) as u32;
+        let [first, second] = resources.particle_buffers();
+        let (source, destination) = match resources.current() {
+            0 => (first, second),
+            _ => (second, first),
+        };
+        self.gather.encode(
+            device,
+            encoder,
+            source,
+            &scratch.order,
+            destination,
+            count,
+            particle_words,
+        );
+
+        // The rest by way of the scratch copy
+        for (buffer, words) in self.moved(resources, lives, indexed) {
+            let size = count as u64 * words as u64 * u32_size;
+            self.gather.encode(
+                device,
+                encoder,
+                buffer,
+                &scratch.order,
+                &scratch.moved,
+                count,
+                words,
+            );
+            encoder.copy_buffer_to_buffer(&scratch.moved, 0, buffer, 0, size);
+        }
+        resources.swap();
+    }
+
+    // What's moved along with the particles, each with its words per particle
+    fn moved<'a>(
+        &self,
+        resources: &'a GpuResources,
+        lives: Option<&'a wgpu::Buffer>,
+        indexed: &'a [&'a wgpu::Buffer],
+    ) -> impl Iterator<Item = (&'a wgpu::Buffer, u32)> {
+        let u32_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let capacity = resources.capacity() as wgpu::BufferAddress;
+        let dyes = Some(&resources.dyes).filter(|_| self.dyed);
+        lives
+            .into_iter()
+            .chain(indexed.iter().copied())
+            .chain([&resources.flags])
+            .chain(dyes)
+            .map(move |buffer| (buffer, (buffer.size() / capacity / u32_size) as u32))
+    }
+
+    fn scratch(
+        &self,
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        count: u32,
+        key: SortKey,
+        moved_size: wgpu::BufferAddress,
+    ) -> Scratch {
+        let u32_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let mut ends = species::ranges(&self.species, count)
+            .into_iter()
+            .map(|range| range.end)
+            .collect::<Vec<_>>();
+        // Without species, all of them sorted as one
+        if ends.is_empty() {
+            ends.push(count);
+        }
+        let params = SortKeyParams {
+            particle_count: count,
+            key: key.shader_index(),
+            species: ends.len() as u32,
+        };
+        let mut params_bytes = Vec::new();
+        params.write(&mut params_bytes);
+        let [radix_keys, radix_values, histograms] = RadixSort::scratch_sizes(count);
+        Scratch {
+            count,
+            key,
+            sort_keys: resources.buffer(
+                device,
+                "Particle Sort Keys Buffer",
+                count as u64 * u32_size,
+                BufferUsages::STORAGE,
+            ),
+            order: resources.buffer(
+                device,
+                "Particle Sort Order Buffer",
+                count as u64 * u32_size,
+                BufferUsages::STORAGE,
+            ),
+            ends: resources.buffer_init(
+                device,
+                "Particle Sort Species Buffer",
+                bytemuck::cast_slice(&ends),
+                BufferUsages::STORAGE,
+            ),
+            params: resources.buffer_init(
+                device,
+                "Particle Sort Params Buffer",
+                &params_bytes,
+                BufferUsages::UNIFORM,
+            ),
+            lives_placeholder: resources.buffer(
+                device,
+                "Particle Sort Lives Placeholder",
+                32,
+                BufferUsages::STORAGE,
+            ),
+            radix: [
+                ("Radix Sort Keys Buffer", radix_keys),
+                ("Radix Sort Values Buffer", radix_values),
+                ("Radix Sort Histogram Buffer", histograms),
+            ]
+            .map(|(label, size)| resources.buffer(device, label, size, BufferUsages::STORAGE)),
+            moved: resources.buffer(
+                device,
+                "Particle Sort Scratch Buffer",
+                moved_size.max(u32_size),
+                BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gpu, headless, Particle};
+
+    fn sort(
+        sorter: &mut ParticleSort,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &mut GpuResources,
+        count: u32,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Particle Sort Test Encoder"),
+        });
+        sorter.encode(device, &mut encoder, resources, count, None, &[]);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    #[test]
+    fn keeps_its_scratch_buffers_until_the_count_changes() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // Down the diagonal, so sorting by cell turns them around
+        let particles = (0..500)
+            .map(|i| {
+                let along = 0.9 - i as f32 * 0.0035;
+                Particle {
+                    position: [along, along],
+                    velocity: [i as f32, 0.0],
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut resources = GpuResources::new(&device, &particles);
+        let mut sorter = ParticleSort::new(&device, SortConfig::default(), Vec::new(), false);
+        let before = resources.total_bytes();
+
+        sort(&mut sorter, &device, &queue, &mut resources, 500);
+        let sorted = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 500);
+        let velocities = sorted.iter().map(|p| p.velocity[0]).collect::<Vec<_>>();
+        assert_eq!(
+            velocities,
+            (0..500).rev().map(|i| i as f32).collect::<Vec<_>>()
+        );
+        let allocated = resources.total_bytes();
+        assert!(allocated > before);
+        assert!(resources
+            .allocations()
+            .any(|(label, _)| label == "Particle Sort Order Buffer"));
+
+        // Sorted again as they are, and in place
+        sort(&mut sorter, &device, &queue, &mut resources, 500);
+        assert_eq!(resources.total_bytes(), allocated);
+        let again = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 500);
+        assert!(again.iter().map(|p| p.velocity[0]).eq(velocities));
+
+        // Fewer of them, in smaller buffers
+        sort(&mut sorter, &device, &queue, &mut resources, 250);
+        assert!(resources.total_bytes() < allocated);
+    }
+}
@@ -0,0 +1,166 @@
+use nannou::rand::{rngs::StdRng, SeedableRng};
+use nannou::wgpu;
+
+use crate::headless;
+use crate::impulses::{Impulse, Impulses};
+use crate::resources::GpuResources;
+use crate::sim_variant::SimVariant;
+use crate::simulation::{RuleRadii, RuleWeights, Simulation, SpeedLimits};
+use crate::stages::{FrameContext, Stage};
+use crate::stats::{self, FlockStats};
+use crate::Particle;
+
+/// The boids parameters `ParticleSystem::set_params` can change between steps.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoidsParams {
+    pub weights: RuleWeights,
+    pub radii: RuleRadii,
+    pub speed_limits: SpeedLimits,
+    // Degrees either side of its heading a boid sees, all round when `None`
+    pub view_angle: Option<f32>,
+    // Which rules run and how neighbours are found, see `SimVariant`
+    pub variant: SimVariant,
+}
+
+/// The plain boids simulation on a headless device, without a window: built, stepped and
+/// read back from a script rather than driven by the app. Meant as the one surface for
+/// scripting the simulation, e.g. from bindings for other languages, so everything it takes
+/// and returns is plain data.
+///
+/// Stepping stays on the GPU. Reading the particles back waits for the steps before it.
+pub struct ParticleSystem {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    resources: GpuResources,
+    simulation: Simulation,
+    impulses: Impulses,
+    variant: SimVariant,
+    particle_count: u32,
+    frame: u64,
+}
+
+impl ParticleSystem {
+    /// `particle_count` particles scattered like `Particle::random`, the same for the same
+    /// seed. `None` when there's no GPU adapter.
+    pub fn new(particle_count: u32, seed: u64) -> Option<Self> {
+        let (device, queue) = headless::device()?;
+        let particle_count = particle_count.clamp(1, crate::MAX_PARTICLES);
+        let mut resources = GpuResources::new(&device, &scatter(particle_count, seed));
+        let variant = SimVariant::default();
+        let simulation = Simulation::new(&device, &mut resources, variant);
+        let impulses = Impulses::new(&device, &mut resources);
+        Some(ParticleSystem {
+            device,
+            queue,
+            resources,
+            simulation,
+            impulses,
+            variant,
+            particle_count,
+            frame: 0,
+        })
+    }
+
+    /// Start over from `particle_count` newly scattered particles, keeping the parameters
+    /// and the device.
+    pub fn reset(&mut self, particle_count: u32, seed: u64) {
+        self.particle_count = particle_count.clamp(1, crate::MAX_PARTICLES);
+        self.resources = GpuResources::new(&self.device, &scatter(self.particle_count, seed));
+        self.simulation.rebind(&self.device, &self.resources);
+        self.impulses.rebind(&self.device, &mut self.resources);
+        self.frame = 0;
+    }
+
+    pub fn params(&self) -> BoidsParams {
+        self.simulation.boids_params(self.variant)
+    }
+
+    /// Takes effect from the next step. A new variant builds its pipeline then.
+    pub fn set_params(&mut self, params: BoidsParams) {
+        self.simulation.set_boids_params(&params);
+        self.variant = params.variant;
+    }
+
+    /// Kick the particles around `impulse` once, at the start of the next step.
+    pub fn impulse(&mut self, impulse: Impulse) {
+        self.impulses.push(impulse);
+    }
+
+    /// Advance `frames` frames, all in one submission.
+    pub fn step(&mut self, frames: u32) {
+        let time = self.frame as f32 / 60.0;
+        self.simulation.write_params(
+            &self.queue,
+            &self.resources,
+            Some(self.particle_count),
+            1,
+            time,
+        );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Particle System Encoder"),
+            });
+        let frame = FrameContext {
+            device: &self.device,
+            queue: &self.queue,
+            variant: self.variant,
+            particle_count: self.particle_count,
+            substeps: 1,
+            time,
+            frame: self.frame as u32,
+        };
+        self.impulses
+            .encode(&frame, &mut encoder, &mut self.resources);
+        for _ in 0..frames {
+            self.simulation.encode(
+                &self.device,
+                &mut encoder,
+                &mut self.resources,
+                self.variant,
+                self.particle_count,
+                1,
+            );
+        }
+        self.queue.submit(Some(encoder.finish()));
+        self.frame += frames as u64;
+    }
+
+    /// Block until every step so far has run on the GPU, e.g. to time them.
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Frames stepped so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn particle_count(&self) -> u32 {
+        self.particle_count
+    }
+
+    pub fn particles(&self) -> Vec<Particle> {
+        self.resources
+            .read_particles(&self.device, &self.queue, self.particle_count)
+    }
+
+    pub fn positions(&self) -> Vec<[f32; 2]> {
+        self.particles().iter().map(|p| p.position).collect()
+    }
+
+    pub fn velocities(&self) -> Vec<[f32; 2]> {
+        self.particles().iter().map(|p| p.velocity).collect()
+    }
+
+    pub fn stats(&self) -> FlockStats {
+        stats::measure(&self.particles())
+    }
+}
+
+fn scatter(particle_count: u32, seed: u64) -> Vec<Particle> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..particle_count)
+        .map(|_| Particle::random(&mut rng))
+        .collect()
+}
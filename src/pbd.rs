@@ -0,0 +1,293 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::constraints::ConstraintBuffers;
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match the shader
+const CELL_CAPACITY: u32 = 16;
+const MAX_GRID_SIZE: u32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PbdConfig {
+    // Solver passes per frame, more converge stiffer
+    pub iterations: u32,
+    // Collision radius in domain units
+    pub radius: f32,
+}
+
+impl Default for PbdConfig {
+    fn default() -> Self {
+        PbdConfig {
+            iterations: 4,
+            radius: 0.005,
+        }
+    }
+}
+
+impl PbdConfig {
+    fn grid_size(&self) -> u32 {
+        // Cells at least a contact distance wide, so the 3x3 block around a particle covers it
+        ((1.0 / self.radius.max(f32::EPSILON)) as u32).clamp(1, MAX_GRID_SIZE)
+    }
+}
+
+// Must match `PbdParams` in pbd_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PbdParams {
+    particle_count: u32,
+    grid_size: u32,
+    radius: f32,
+    constraint_count: u32,
+}
+
+/// Position-based dynamics after the force stage: takes the positions it produced as the
+/// prediction, corrects them over a few iterations so particles don't overlap and network
+/// distances hold, then derives the velocities from the corrected positions.
+///
+/// Corrections are averaged Jacobi style through fixed-point atomics, so the solve is order
+/// independent. Collisions use a uniform grid rebuilt every iteration.
+pub struct PbdSolver {
+    pub config: PbdConfig,
+    begin: wgpu::ComputePipeline,
+    clear_cells: wgpu::ComputePipeline,
+    bin: wgpu::ComputePipeline,
+    collide: wgpu::ComputePipeline,
+    distance: wgpu::ComputePipeline,
+    apply: wgpu::ComputePipeline,
+    finish: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    constraint_bind_group: Option<(wgpu::BindGroup, u32)>,
+}
+
+impl PbdSolver {
+    /// `network` adds its distance constraints and pinned particles, otherwise every
+    /// particle has the same mass and only collides.
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: PbdConfig,
+        network: Option<&ConstraintBuffers>,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "pbd_shader",
+            include_str!("./shaders/pbd_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .storage_ro(4)
+            .storage_rw(5)
+            .storage_rw(6)
+            .build(device, "PBD");
+        let constraint_bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .build(device, "PBD Constraints");
+        let params_buffer = resources.uniform::<PbdParams>(device, "PBD Params Buffer");
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &config,
+            network,
+        );
+        let constraint_bind_group = network.map(|network| {
+            let bind_group =
+                constraint_bindings.bind_group(device, &[network.constraints.as_entire_binding()]);
+            (bind_group, network.constraint_count)
+        });
+
+        let layout = |label: &str, layouts: &[&wgpu::BindGroupLayout]| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            })
+        };
+        let particle_layout = layout("PBD Pipeline Layout", &[bindings.layout()]);
+        let constraint_layout = layout(
+            "PBD Constraint Pipeline Layout",
+            &[bindings.layout(), constraint_bindings.layout()],
+        );
+        let pipeline = |entry_point: &str, layout: &wgpu::PipelineLayout| {
+            diagnostics::checked(device, &format!("PBD Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("PBD Pipeline"),
+                    layout: Some(layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+
+        PbdSolver {
+            config,
+            begin: pipeline("begin", &particle_layout),
+            clear_cells: pipeline("clear_cells", &particle_layout),
+            bin: pipeline("bin", &particle_layout),
+            collide: pipeline("collide", &particle_layout),
+            distance: pipeline("distance", &constraint_layout),
+            apply: pipeline("apply", &particle_layout),
+            finish: pipeline("finish", &particle_layout),
+            bindings,
+            params_buffer,
+            bind_groups,
+            constraint_bind_group,
+        }
+    }
+
+    /// Resize for and rebind to a new particle buffer. Networks have a fixed size, so this
+    /// only happens without one.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.config,
+            None,
+        );
+    }
+
+    /// Encode the solve for the first `particle_count` particles. Run after the step that
+    /// integrates forces.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        particle_count: u32,
+    ) {
+        let grid_size = self.config.grid_size();
+        let params = PbdParams {
+            particle_count,
+            grid_size,
+            radius: self.config.radius,
+            constraint_count: self.constraint_bind_group.as_ref().map_or(0, |(_, n)| *n),
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let particle_workgroups = particle_count.div_ceil(WORKGROUP_SIZE);
+        let cell_workgroups = (grid_size * grid_size).div_ceil(WORKGROUP_SIZE);
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("PBD Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.set_pipeline(&self.begin);
+        compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+        for _ in 0..self.config.iterations {
+            compute_pass.set_pipeline(&self.clear_cells);
+            compute_pass.dispatch_workgroups(cell_workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.bin);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.collide);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+            if let Some((bind_group, constraint_count)) = &self.constraint_bind_group {
+                compute_pass.set_bind_group(1, bind_group, &[]);
+                compute_pass.set_pipeline(&self.distance);
+                compute_pass.dispatch_workgroups(constraint_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+            compute_pass.set_pipeline(&self.apply);
+            compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+        }
+        compute_pass.set_pipeline(&self.finish);
+        compute_pass.dispatch_workgroups(particle_workgroups, 1, 1);
+    }
+}
+
+impl Stage for PbdSolver {
+    fn kind(&self) -> StageKind {
+        StageKind::Collisions
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        PbdSolver::encode(self, frame.queue, encoder, resources, frame.particle_count);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        PbdSolver::rebind(self, device, resources);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &wgpu::Buffer,
+    config: &PbdConfig,
+    network: Option<&ConstraintBuffers>,
+) -> [wgpu::BindGroup; 2] {
+    let capacity = resources.capacity() as wgpu::BufferAddress;
+    let f32_size = std::mem::size_of::<f32>() as wgpu::BufferAddress;
+    let predicted = resources.buffer(
+        device,
+        "PBD Predicted Positions Buffer",
+        capacity * 2 * f32_size,
+        BufferUsages::STORAGE,
+    );
+    let deltas = resources.buffer(
+        device,
+        "PBD Corrections Buffer",
+        capacity * 3 * f32_size,
+        BufferUsages::STORAGE,
+    );
+    let cells = (config.grid_size() * config.grid_size()) as wgpu::BufferAddress;
+    let cell_counts = resources.buffer(
+        device,
+        "PBD Cell Counts Buffer",
+        cells * f32_size,
+        BufferUsages::STORAGE,
+    );
+    let cell_particles = resources.buffer(
+        device,
+        "PBD Cell Particles Buffer",
+        cells * CELL_CAPACITY as wgpu::BufferAddress * f32_size,
+        BufferUsages::STORAGE,
+    );
+    // Without a network every particle weighs the same
+    let unit_mass;
+    let inverse_mass = match network {
+        Some(network) => &network.inverse_mass,
+        None => {
+            unit_mass = resources.buffer_init(
+                device,
+                "PBD Inverse Mass Buffer",
+                bytemuck::cast_slice(&vec![1.0f32; capacity as usize]),
+                BufferUsages::STORAGE,
+            );
+            &unit_mass
+        }
+    };
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.as_entire_binding(),
+                predicted.as_entire_binding(),
+                deltas.as_entire_binding(),
+                inverse_mass.as_entire_binding(),
+                cell_counts.as_entire_binding(),
+                cell_particles.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,53 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// Pen pressures are held to this much of a normal press at the lightest
+const LIGHTEST: f32 = 0.1;
+
+/// What a graphics tablet's pen pressure scales when stirring and setting off fireworks with
+/// it, so pressing harder paints a bigger or stronger stroke. A normal press, half the pen's
+/// range, leaves them as they'd be with the mouse, and pressing all the way doubles them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PenPressure {
+    /// The radius and the strength
+    #[default]
+    Both,
+    /// How hard the particles are pushed
+    Strength,
+    /// How far the stroke reaches, and how far the sparks fly
+    Radius,
+    /// Neither, the pen acts as a mouse
+    Off,
+}
+
+impl PenPressure {
+    /// What to multiply the strength and radius by at `pressure`, from 0 to 1, or 1s
+    /// without a pen on the tablet.
+    pub fn scales(self, pressure: Option<f32>) -> (f32, f32) {
+        let Some(pressure) = pressure else {
+            return (1.0, 1.0);
+        };
+        let scale = (pressure * 2.0).clamp(LIGHTEST, 2.0);
+        match self {
+            PenPressure::Both => (scale, scale),
+            PenPressure::Strength => (scale, 1.0),
+            PenPressure::Radius => (1.0, scale),
+            PenPressure::Off => (1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_from_a_normal_press() {
+        assert_eq!(PenPressure::Both.scales(None), (1.0, 1.0));
+        assert_eq!(PenPressure::Both.scales(Some(0.5)), (1.0, 1.0));
+        assert_eq!(PenPressure::Strength.scales(Some(1.0)), (2.0, 1.0));
+        assert_eq!(PenPressure::Radius.scales(Some(0.0)), (1.0, LIGHTEST));
+        assert_eq!(PenPressure::Off.scales(Some(1.0)), (1.0, 1.0));
+    }
+}
@@ -0,0 +1,196 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::trail::{Trail, TrailConfig};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Slime mould agents, each sensing the trail ahead of it, to its left and to its right,
+/// turning towards the strongest and moving on at a fixed speed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysarumConfig {
+    // Radians either side of the heading of the side sensors
+    pub sensor_angle: f32,
+    // How far ahead the sensors are, in domain units
+    pub sensor_distance: f32,
+    // Radians turned per frame towards the stronger side
+    pub turn: f32,
+    // Domain units moved per frame
+    pub speed: f32,
+}
+
+impl Default for PhysarumConfig {
+    fn default() -> Self {
+        PhysarumConfig {
+            sensor_angle: 0.4,
+            sensor_distance: 0.02,
+            turn: 0.3,
+            speed: 0.002,
+        }
+    }
+}
+
+impl PhysarumConfig {
+    /// The trail the agents leave when one isn't configured: fine enough for thin veins, and
+    /// without the slope following that the agents' sensing replaces.
+    pub fn trail(&self) -> TrailConfig {
+        TrailConfig {
+            size: 512,
+            deposit: 1.0,
+            diffusion: 0.3,
+            decay: 0.1,
+            follow: 0.0,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `PhysarumParams` in physarum_shader.wgsl
+    struct PhysarumParams {
+        particle_count: u32,
+        trail_size: u32,
+        frame: u32,
+        sensor_angle: f32,
+        sensor_distance: f32,
+        turn: f32,
+        speed: f32,
+    }
+}
+
+/// A physarum step in place of the boids, moving the agents in place along their velocity
+/// through the periodic domain, then depositing into and spreading a `Trail` of its own.
+pub struct Physarum {
+    pub config: PhysarumConfig,
+    trail: Trail,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<PhysarumParams>,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Physarum {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: PhysarumConfig,
+        trail: TrailConfig,
+    ) -> Self {
+        let trail = Trail::new(device, resources, trail);
+        let shader = diagnostics::shader(
+            device,
+            "physarum_shader",
+            include_str!("./shaders/physarum_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_ro(2)
+            .build(device, "Physarum");
+        let params_buffer = UniformBuffer::new(device, resources, "Physarum Params Buffer");
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            trail.trail_buffer(),
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Physarum Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Physarum Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Physarum Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "step",
+            })
+        });
+
+        Physarum {
+            config,
+            trail,
+            pipeline,
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+
+    pub fn trail(&self) -> &Trail {
+        &self.trail
+    }
+}
+
+impl Stage for Physarum {
+    fn kind(&self) -> StageKind {
+        StageKind::Physarum
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = PhysarumParams {
+            particle_count: frame.particle_count,
+            trail_size: self.trail.config.size.max(1),
+            frame: frame.frame,
+            sensor_angle: self.config.sensor_angle,
+            sensor_distance: self.config.sensor_distance,
+            turn: self.config.turn,
+            speed: self.config.speed,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Physarum Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        drop(compute_pass);
+        self.trail.encode(frame, encoder, resources);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.trail.rebind(device, resources);
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            self.trail.trail_buffer(),
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<PhysarumParams>,
+    trail_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                trail_buffer.as_entire_binding(),
+            ],
+        )
+    })
+}
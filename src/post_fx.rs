@@ -0,0 +1,468 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+wgsl_struct! {
+    // Must match `PostParams` in post_fx_shader.wgsl
+    struct PostParams {
+        values: [f32; 4],
+        time: f32,
+        aspect: f32,
+    }
+}
+
+/// One pass of the `PostFx` chain, over the whole rendered frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+// Tagged inside, as TOML has no enum variants with fields
+#[serde(tag = "effect", rename_all = "kebab-case")]
+pub enum Effect {
+    // The frame folded into `segments` mirrored wedges about its centre, turning at `speed`
+    // radians a second
+    Kaleidoscope {
+        segments: u32,
+        speed: f32,
+    },
+    // The frame over what this effect left last frame, scaled by `zoom` and turned by
+    // `rotation` radians about the centre, its hue turned by `hue_shift` radians and dimmed
+    // by `decay`, for music-visualizer trails. Brighter of the two wins
+    Feedback {
+        decay: f32,
+        zoom: f32,
+        rotation: f32,
+        hue_shift: f32,
+    },
+    // Red and blue pulled apart from green towards the edges, by `strength` of the frame's
+    // height at the corners
+    ChromaticAberration {
+        strength: f32,
+    },
+    // Corners darkened, to black at `strength` 1
+    Vignette {
+        strength: f32,
+    },
+}
+
+impl Effect {
+    fn entry_point(self) -> &'static str {
+        match self {
+            Effect::Kaleidoscope { .. } => "fs_kaleidoscope",
+            Effect::Feedback { .. } => "fs_feedback",
+            Effect::ChromaticAberration { .. } => "fs_chromatic_aberration",
+            Effect::Vignette { .. } => "fs_vignette",
+        }
+    }
+
+    // Samples its own output from the frame before
+    fn feeds_back(self) -> bool {
+        matches!(self, Effect::Feedback { .. })
+    }
+
+    // `values` in post_fx_shader.wgsl
+    fn values(self) -> [f32; 4] {
+        match self {
+            Effect::Kaleidoscope { segments, speed } => [segments as f32, speed, 0.0, 0.0],
+            Effect::Feedback {
+                decay,
+                zoom,
+                rotation,
+                hue_shift,
+            } => [decay, zoom, rotation, hue_shift],
+            Effect::ChromaticAberration { strength } | Effect::Vignette { strength } => {
+                [strength, 0.0, 0.0, 0.0]
+            }
+        }
+    }
+}
+
+/// Parse effects like `kaleidoscope:6,0.1`, `feedback:0.9,1.01` or `vignette`, separated by
+/// `;` and run in that order. Values after the first can be left out, as can the strengths.
+pub fn parse_effects(s: &str) -> Result<Vec<Effect>, String> {
+    s.split(';')
+        .map(|effect| {
+            let (name, values) = effect.trim().split_once(':').unwrap_or((effect.trim(), ""));
+            let values = values
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid effect `{effect}`"))?;
+            match name {
+                "kaleidoscope" => {
+                    let usage = "kaleidoscope:segments[,speed]";
+                    match fields(&values, [None, Some(0.0)]) {
+                        Some([segments, speed]) if segments >= 1.0 => Ok(Effect::Kaleidoscope {
+                            segments: segments as u32,
+                            speed,
+                        }),
+                        _ => Err(format!("expected {usage}, got `{effect}`")),
+                    }
+                }
+                "feedback" => {
+                    let usage = "feedback:decay[,zoom[,rotation[,hue-shift]]]";
+                    match fields(&values, [None, Some(1.0), Some(0.0), Some(0.0)]) {
+                        Some([decay, zoom, rotation, hue_shift])
+                            if (0.0..=1.0).contains(&decay) && zoom > 0.0 =>
+                        {
+                            Ok(Effect::Feedback {
+                                decay,
+                                zoom,
+                                rotation,
+                                hue_shift,
+                            })
+                        }
+                        _ => Err(format!("expected {usage}, got `{effect}`")),
+                    }
+                }
+                "aberration" => match fields(&values, [Some(0.005)]) {
+                    Some([strength]) => Ok(Effect::ChromaticAberration { strength }),
+                    _ => Err(format!("expected aberration[:strength], got `{effect}`")),
+                },
+                "vignette" => match fields(&values, [Some(0.5)]) {
+                    Some([strength]) if strength >= 0.0 => Ok(Effect::Vignette { strength }),
+                    _ => Err(format!("expected vignette[:strength], got `{effect}`")),
+                },
+                _ => Err(format!(
+                    "unknown effect `{name}`, expected kaleidoscope, feedback, aberration or \
+                     vignette"
+                )),
+            }
+        })
+        .collect()
+}
+
+// `values` filled out with `defaults`, none if there are too many or one without a default
+// is missing
+fn fields<const N: usize>(values: &[f32], defaults: [Option<f32>; N]) -> Option<[f32; N]> {
+    if values.len() > N {
+        return None;
+    }
+    let mut fields = [0.0; N];
+    for (index, field) in fields.iter_mut().enumerate() {
+        *field = values.get(index).copied().or(defaults[index])?;
+    }
+    Some(fields)
+}
+
+// What a window's frame is drawn into while the effects run, sized to it
+struct Targets {
+    size: [u32; 2],
+    // The scene is drawn into this when multisampled, resolved into the first of `views`
+    multisampled: Option<wgpu::TextureView>,
+    // Ping-ponged between by the effects, starting with the scene in the first
+    textures: [wgpu::Texture; 2],
+    views: [wgpu::TextureView; 2],
+    // Sampling each of `views`
+    sources: [wgpu::BindGroup; 2],
+    // Indexed like `PostFx::effects`, what those that feed back drew last frame, and the
+    // group sampling it
+    history: Vec<Option<(wgpu::Texture, wgpu::BindGroup)>>,
+}
+
+/// Effects run over the rendered scene before it reaches the window, in order, each
+/// sampling the one before. The scene is drawn into a texture of its own so they can, then
+/// copied onto the frame, so frames shared or captured show the effects too. With none the
+/// scene is drawn straight into the frame as before.
+///
+/// The offline --render tiles are drawn without them, as the effects need the whole frame.
+pub struct PostFx {
+    effects: Vec<Effect>,
+    sample_count: u32,
+    sampler: wgpu::Sampler,
+    source_bindings: BindingLayout,
+    history_bindings: BindingLayout,
+    // Indexed like `effects`
+    pipelines: Vec<wgpu::RenderPipeline>,
+    params_buffers: Vec<UniformBuffer<PostParams>>,
+    params: Vec<wgpu::BindGroup>,
+    blit: wgpu::RenderPipeline,
+    // Made on a window's first frame and whenever its size changes, indexed by `is_output`.
+    // Not tracked, as they're made while drawing
+    targets: [RefCell<Option<Targets>>; 2],
+}
+
+impl PostFx {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        effects: Vec<Effect>,
+        sample_count: u32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "post_fx_shader",
+            include_str!("./shaders/post_fx_shader.wgsl"),
+        );
+        let sampler = wgpu::SamplerBuilder::new()
+            .address_mode(wgpu::AddressMode::ClampToEdge)
+            .label(Some("Post Fx Sampler"))
+            .build(device);
+        let source_bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .texture(0)
+            .sampler(1)
+            .build(device, "Post Fx Source");
+        let params_bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .uniform(0)
+            .build(device, "Post Fx Params");
+        let history_bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .texture(0)
+            .build(device, "Post Fx History");
+
+        let effect_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Fx Pipeline Layout"),
+            bind_group_layouts: &[source_bindings.layout(), params_bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let feedback_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Fx Feedback Pipeline Layout"),
+            bind_group_layouts: &[
+                source_bindings.layout(),
+                params_bindings.layout(),
+                history_bindings.layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+        // Effects draw into the single-sampled targets, only the copy onto the frame is
+        // multisampled
+        let pipelines = effects
+            .iter()
+            .map(|effect| {
+                let layout = if effect.feeds_back() {
+                    &feedback_layout
+                } else {
+                    &effect_layout
+                };
+                pipeline(device, &shader, layout, effect.entry_point(), 1)
+            })
+            .collect();
+        let params_buffers = effects
+            .iter()
+            .map(|_| UniformBuffer::new(device, resources, "Post Fx Params Buffer"))
+            .collect::<Vec<_>>();
+        let params = params_buffers
+            .iter()
+            .map(|buffer| params_bindings.bind_group(device, &[buffer.binding()]))
+            .collect();
+
+        let blit_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Fx Blit Pipeline Layout"),
+            bind_group_layouts: &[source_bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let blit = pipeline(device, &shader, &blit_layout, "fs_blit", sample_count);
+
+        PostFx {
+            effects,
+            sample_count,
+            sampler,
+            source_bindings,
+            history_bindings,
+            pipelines,
+            params_buffers,
+            params,
+            blit,
+            targets: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Draw the scene through the effects into `output`, `size` pixels across. `draw` encodes
+    /// the scene into the given attachment, cleared to `clear`. Each window keeps targets of
+    /// its own, told apart by `is_output` as in `render_scene`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        size: [u32; 2],
+        output: wgpu::RenderPassColorAttachment,
+        is_output: bool,
+        time: f32,
+        clear: wgpu::Color,
+        draw: impl FnOnce(&mut wgpu::CommandEncoder, wgpu::RenderPassColorAttachment),
+    ) {
+        let mut targets = self.targets[is_output as usize].borrow_mut();
+        if !matches!(&*targets, Some(targets) if targets.size == size) {
+            *targets = Some(self.targets(device, size));
+        }
+        let targets = targets.as_ref().unwrap();
+
+        let (view, resolve_target) = match &targets.multisampled {
+            Some(multisampled) => (multisampled, Some(&*targets.views[0])),
+            None => (&targets.views[0], None),
+        };
+        draw(
+            encoder,
+            wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: true,
+                },
+            },
+        );
+
+        let aspect = size[0] as f32 / size[1] as f32;
+        for (index, effect) in self.effects.iter().enumerate() {
+            self.params_buffers[index].write(
+                queue,
+                &PostParams {
+                    values: effect.values(),
+                    time,
+                    aspect,
+                },
+            );
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Fx Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.views[(index + 1) % 2],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipelines[index]);
+            render_pass.set_bind_group(0, &targets.sources[index % 2], &[]);
+            render_pass.set_bind_group(1, &self.params[index], &[]);
+            if let Some((_, history)) = &targets.history[index] {
+                render_pass.set_bind_group(2, history, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+            drop(render_pass);
+
+            // Kept for the next frame
+            if let Some((history, _)) = &targets.history[index] {
+                encoder.copy_texture_to_texture(
+                    targets.textures[(index + 1) % 2].as_image_copy(),
+                    history.as_image_copy(),
+                    wgpu::Extent3d {
+                        width: size[0],
+                        height: size[1],
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Fx Blit Pass"),
+            color_attachments: &[Some(output)],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.blit);
+        render_pass.set_bind_group(0, &targets.sources[self.effects.len() % 2], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn targets(&self, device: &wgpu::Device, size: [u32; 2]) -> Targets {
+        let target = |samples: u32, usage: wgpu::TextureUsages| {
+            wgpu::TextureBuilder::new()
+                .size(size)
+                .format(Frame::TEXTURE_FORMAT)
+                .sample_count(samples)
+                .usage(usage)
+                .build(device)
+        };
+        let multisampled = (self.sample_count > 1).then(|| {
+            target(self.sample_count, wgpu::TextureUsages::RENDER_ATTACHMENT)
+                .view()
+                .build()
+        });
+        let textures = [(); 2].map(|_| {
+            target(
+                1,
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            )
+        });
+        let views = textures.each_ref().map(|texture| texture.view().build());
+        let sources = views.each_ref().map(|view| {
+            self.source_bindings.bind_group(
+                device,
+                &[
+                    wgpu::BindingResource::TextureView(view),
+                    wgpu::BindingResource::Sampler(&self.sampler),
+                ],
+            )
+        });
+        // Black to start with
+        let history = self
+            .effects
+            .iter()
+            .map(|effect| {
+                effect.feeds_back().then(|| {
+                    let texture = target(
+                        1,
+                        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    );
+                    let view = texture.view().build();
+                    let bind_group = self
+                        .history_bindings
+                        .bind_group(device, &[wgpu::BindingResource::TextureView(&view)]);
+                    (texture, bind_group)
+                })
+            })
+            .collect();
+        Targets {
+            size,
+            multisampled,
+            textures,
+            views,
+            sources,
+            history,
+        }
+    }
+}
+
+fn pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    entry_point: &str,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    diagnostics::checked(device, "Post Fx Pipeline", || {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Fx Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: Frame::TEXTURE_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    })
+}
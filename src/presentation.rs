@@ -0,0 +1,106 @@
+use nannou::prelude::*;
+use nannou::winit::window::Fullscreen;
+use tracing::{info, warn};
+
+/// Borderless fullscreen "gallery" mode, toggled with F11.
+///
+/// The window either goes fullscreen on a chosen monitor, or becomes an undecorated window
+/// covering the bounding box of every monitor when spanning. Overlays should check `active`
+/// and hide themselves while presenting.
+pub struct Presentation {
+    pub active: bool,
+    monitor: Option<usize>,
+    span: bool,
+    // Window placement to restore after a spanned presentation
+    restore: Option<((i32, i32), (u32, u32))>,
+}
+
+impl Presentation {
+    pub fn new(monitor: Option<usize>, span: bool) -> Self {
+        Presentation {
+            active: false,
+            monitor,
+            span,
+            restore: None,
+        }
+    }
+
+    pub fn toggle(&mut self, app: &App, window: &Window) {
+        if self.active {
+            self.exit(window);
+        } else {
+            self.enter(app, window);
+        }
+    }
+
+    pub fn enter(&mut self, app: &App, window: &Window) {
+        let monitors = app.available_monitors();
+        if self.span && monitors.len() > 1 {
+            let (min_x, min_y, max_x, max_y) = monitors.iter().fold(
+                (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+                |(min_x, min_y, max_x, max_y), monitor| {
+                    let position = monitor.position();
+                    let size = monitor.size();
+                    (
+                        min_x.min(position.x),
+                        min_y.min(position.y),
+                        max_x.max(position.x + size.width as i32),
+                        max_y.max(position.y + size.height as i32),
+                    )
+                },
+            );
+            let position = window.outer_position_pixels().unwrap_or((0, 0));
+            self.restore = Some((position, window.inner_size_pixels()));
+            window.set_decorations(false);
+            window.set_outer_position_pixels(min_x, min_y);
+            window.set_inner_size_pixels((max_x - min_x) as u32, (max_y - min_y) as u32);
+        } else {
+            let monitor = match self.monitor {
+                Some(index) => {
+                    let monitor = monitors.get(index).cloned();
+                    if monitor.is_none() {
+                        warn!(
+                            "Monitor {} not found ({} available), using the current monitor",
+                            index,
+                            monitors.len()
+                        );
+                    }
+                    monitor.or_else(|| window.current_monitor())
+                }
+                None => window.current_monitor(),
+            };
+            window.set_fullscreen_with(Some(Fullscreen::Borderless(monitor)));
+        }
+        window.set_cursor_visible(false);
+        self.active = true;
+    }
+
+    pub fn exit(&mut self, window: &Window) {
+        if let Some(((x, y), (width, height))) = self.restore.take() {
+            window.set_decorations(true);
+            window.set_inner_size_pixels(width, height);
+            window.set_outer_position_pixels(x, y);
+        } else {
+            window.set_fullscreen_with(None);
+        }
+        window.set_cursor_visible(true);
+        self.active = false;
+    }
+}
+
+/// Print the monitors winit reports, so `--monitor` indices can be looked up.
+pub fn print_monitors(app: &App) {
+    for (index, monitor) in app.available_monitors().iter().enumerate() {
+        let size = monitor.size();
+        let position = monitor.position();
+        info!(
+            "{}: {} {}x{} at ({}, {})",
+            index,
+            monitor.name().unwrap_or_else(|| "unknown".to_string()),
+            size.width,
+            size.height,
+            position.x,
+            position.y
+        );
+    }
+}
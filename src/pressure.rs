@@ -0,0 +1,161 @@
+use nannou::prelude::*;
+use nannou::wgpu::{self, BufferUsages};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::resources::GpuResources;
+use crate::theme::Hud;
+
+// Must match IMPULSE_SCALE in compute_shader.wgsl
+const IMPULSE_SCALE: f32 = 1_000_000.0;
+// Frames of impulses added up per reading
+const INTERVAL: u32 = 30;
+// Readings kept for the pressure-volume plot
+const HISTORY: usize = 200;
+pub const MIN_PISTON: f32 = -0.8;
+pub const MAX_PISTON: f32 = 1.0;
+const PLOT_SIZE: f32 = 160.0;
+
+/// One pressure reading, for a 2D gas: force per unit wall length against the box's area.
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    volume: f32,
+    pressure: f32,
+}
+
+/// Reads back the wall impulses the piston variant adds up, and shows pressure against
+/// volume so the ideal gas law can be seen at work.
+///
+/// The counter is copied out and cleared every `INTERVAL` frames and mapped asynchronously,
+/// so readings arrive a frame or two late but never stall rendering.
+pub struct PressureGauge {
+    staging: wgpu::Buffer,
+    // Frames since the last reading was taken
+    frames: u32,
+    // Piston position and frame count for the reading in flight
+    pending: Option<(f32, u32)>,
+    mapped: Arc<AtomicBool>,
+    readings: VecDeque<Reading>,
+}
+
+impl PressureGauge {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources) -> Self {
+        let staging = resources.buffer(
+            device,
+            "Wall Impulse Readback Buffer",
+            std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        );
+        PressureGauge {
+            staging,
+            frames: 0,
+            pending: None,
+            mapped: Arc::default(),
+            readings: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Count a simulated frame, and every `INTERVAL` frames copy out and reset the counter.
+    /// Returns whether a copy was encoded, in which case call `map` after submitting.
+    pub fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        piston: f32,
+    ) -> bool {
+        self.frames += 1;
+        if self.frames < INTERVAL || self.pending.is_some() {
+            return false;
+        }
+        encoder.copy_buffer_to_buffer(&resources.wall_impulse, 0, &self.staging, 0, 4);
+        encoder.clear_buffer(&resources.wall_impulse, 0, None);
+        self.pending = Some((piston, self.frames));
+        self.frames = 0;
+        true
+    }
+
+    pub fn map(&self) {
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("Failed to map wall impulses: {}", err),
+            });
+    }
+
+    /// Pick up the reading in flight if it has arrived.
+    pub fn poll(&mut self) {
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let Some((piston, frames)) = self.pending.take() else {
+            return;
+        };
+        let impulse = {
+            let data = self.staging.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned::<u32>(&data) as f32 / IMPULSE_SCALE
+        };
+        self.staging.unmap();
+
+        // The left wall is at -1 and the box spans -1..1 vertically
+        let width = piston + 1.0;
+        let height = 2.0;
+        let perimeter = 2.0 * (width + height);
+        if self.readings.len() == HISTORY {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(Reading {
+            volume: width * height,
+            pressure: impulse / frames as f32 / perimeter,
+        });
+    }
+
+    /// Pressure-volume plot in the bottom left corner, with the latest reading.
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud) {
+        let Some(latest) = self.readings.back() else {
+            return;
+        };
+        let area = Rect::from_w_h(PLOT_SIZE, PLOT_SIZE).bottom_left_of(window.pad(10.0));
+        draw.rect().xy(area.xy()).wh(area.wh()).color(hud.panel);
+
+        // Axes run from zero to the largest volume and pressure seen
+        let max_volume = 2.0 * (MAX_PISTON + 1.0);
+        let max_pressure = self
+            .readings
+            .iter()
+            .map(|reading| reading.pressure)
+            .fold(f32::EPSILON, f32::max);
+        let point = |reading: &Reading| {
+            pt2(
+                area.left() + reading.volume / max_volume * area.w(),
+                area.bottom() + reading.pressure / max_pressure * area.h(),
+            )
+        };
+        for reading in &self.readings {
+            draw.ellipse()
+                .xy(point(reading))
+                .w_h(3.0, 3.0)
+                .color(ORANGE);
+        }
+        draw.ellipse()
+            .xy(point(latest))
+            .w_h(6.0, 6.0)
+            .color(hud.text);
+
+        let label = Rect::from_w_h(area.w(), 14.0).above(area);
+        draw.text(&format!(
+            "P {:.2e}  V {:.2}  PV {:.2e}",
+            latest.pressure,
+            latest.volume,
+            latest.pressure * latest.volume
+        ))
+        .xy(label.xy())
+        .wh(label.wh())
+        .left_justify()
+        .font_size(12)
+        .color(hud.text);
+    }
+}
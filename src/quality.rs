@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+// Each downgrade keeps this fraction of the particles
+const PARTICLE_STEP: f32 = 0.75;
+const MIN_PARTICLE_FRACTION: f32 = 0.1;
+// Smoothing factor for the frame time moving average
+const SMOOTHING: f32 = 0.05;
+// Frames must run this far over budget before quality drops
+const OVER_BUDGET: f32 = 1.2;
+const MIN_PROBE_DELAY: f32 = 2.0;
+const MAX_PROBE_DELAY: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    pub particle_fraction: f32,
+    pub substeps: u32,
+    pub post_fx: bool,
+}
+
+/// Scales the simulation workload to keep frame times inside a budget.
+///
+/// When the smoothed frame time goes over budget, substeps are dropped first, then the post-fx
+/// chain and then the particle count. Headroom can't be measured reliably under vsync, so after running within
+/// budget for a while the governor probes one level back up; probes that immediately go over
+/// budget again make it wait longer before the next one.
+pub struct QualityGovernor {
+    budget: f32,
+    max_substeps: u32,
+    // Whether there is a post-fx chain to drop
+    post_fx: bool,
+    quality: Quality,
+    smoothed: f32,
+    // Seconds spent within budget since the last change
+    settled: f32,
+    probe_delay: f32,
+    just_probed: bool,
+}
+
+impl QualityGovernor {
+    pub fn new(budget: Duration, max_substeps: u32, post_fx: bool) -> Self {
+        let budget = budget.as_secs_f32();
+        QualityGovernor {
+            budget,
+            max_substeps,
+            post_fx,
+            quality: Quality {
+                particle_fraction: 1.0,
+                substeps: max_substeps,
+                post_fx,
+            },
+            smoothed: budget,
+            settled: 0.0,
+            probe_delay: MIN_PROBE_DELAY,
+            just_probed: false,
+        }
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Feed in the last frame time, returns the new quality if it changed.
+    pub fn update(&mut self, frame_time: Duration) -> Option<Quality> {
+        let frame_time = frame_time.as_secs_f32();
+        self.smoothed += (frame_time - self.smoothed) * SMOOTHING;
+
+        if self.smoothed > self.budget * OVER_BUDGET {
+            if self.just_probed {
+                self.probe_delay = (self.probe_delay * 2.0).min(MAX_PROBE_DELAY);
+            }
+            self.just_probed = false;
+            return self.change(Self::downgrade);
+        }
+
+        self.settled += frame_time;
+        if self.settled >= self.probe_delay {
+            if self.just_probed {
+                // The last probe held up, so try the next one sooner
+                self.probe_delay = (self.probe_delay * 0.5).max(MIN_PROBE_DELAY);
+            }
+            let changed = self.change(Self::upgrade);
+            self.just_probed = changed.is_some();
+            return changed;
+        }
+        None
+    }
+
+    fn change(&mut self, f: fn(&Self) -> Quality) -> Option<Quality> {
+        let next = f(self);
+        self.settled = 0.0;
+        // Give the moving average a fresh start at the new level
+        self.smoothed = self.budget;
+        if next == self.quality {
+            return None;
+        }
+        self.quality = next;
+        Some(next)
+    }
+
+    fn downgrade(&self) -> Quality {
+        let quality = self.quality;
+        if quality.substeps > 1 {
+            Quality {
+                substeps: quality.substeps - 1,
+                ..quality
+            }
+        } else if quality.post_fx {
+            Quality {
+                post_fx: false,
+                ..quality
+            }
+        } else {
+            Quality {
+                particle_fraction: (quality.particle_fraction * PARTICLE_STEP)
+                    .max(MIN_PARTICLE_FRACTION),
+                ..quality
+            }
+        }
+    }
+
+    fn upgrade(&self) -> Quality {
+        let quality = self.quality;
+        if quality.particle_fraction < 1.0 {
+            Quality {
+                particle_fraction: (quality.particle_fraction / PARTICLE_STEP).min(1.0),
+                ..quality
+            }
+        } else if self.post_fx && !quality.post_fx {
+            Quality {
+                post_fx: true,
+                ..quality
+            }
+        } else {
+            Quality {
+                substeps: (quality.substeps + 1).min(self.max_substeps),
+                ..quality
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: Duration = Duration::from_millis(16);
+
+    // Feeds in frames of the given length for a while, collecting the changes
+    fn run(governor: &mut QualityGovernor, frame_time: Duration, seconds: f32) -> Vec<Quality> {
+        let frames = (seconds / frame_time.as_secs_f32()) as usize;
+        (0..frames)
+            .filter_map(|_| governor.update(frame_time))
+            .collect()
+    }
+
+    #[test]
+    fn drops_substeps_then_post_fx_then_particles_on_slow_frames() {
+        let mut governor = QualityGovernor::new(BUDGET, 2, true);
+        let changes = run(&mut governor, BUDGET * 2, 1.0);
+        assert_eq!(
+            changes[..3],
+            [
+                Quality {
+                    particle_fraction: 1.0,
+                    substeps: 1,
+                    post_fx: true
+                },
+                Quality {
+                    particle_fraction: 1.0,
+                    substeps: 1,
+                    post_fx: false
+                },
+                Quality {
+                    particle_fraction: PARTICLE_STEP,
+                    substeps: 1,
+                    post_fx: false
+                },
+            ]
+        );
+        // Sustained slow frames bottom out at the smallest fraction
+        run(&mut governor, BUDGET * 2, 10.0);
+        assert_eq!(governor.quality().particle_fraction, MIN_PARTICLE_FRACTION);
+        assert_eq!(governor.quality().substeps, 1);
+    }
+
+    #[test]
+    fn restores_quality_on_fast_frames() {
+        let mut governor = QualityGovernor::new(BUDGET, 2, true);
+        run(&mut governor, BUDGET * 2, 1.0);
+        assert!(governor.quality().particle_fraction < 1.0);
+        // A single short stretch isn't enough to probe back up
+        assert!(run(&mut governor, BUDGET / 2, MIN_PROBE_DELAY * 0.5).is_empty());
+        let changes = run(&mut governor, BUDGET / 2, 60.0);
+        // Post-fx comes back once the particles are all back, before the substeps
+        let post_fx = changes.iter().position(|quality| quality.post_fx).unwrap();
+        assert_eq!(changes[post_fx - 1].particle_fraction, 1.0);
+        assert_eq!(changes[post_fx].substeps, 1);
+        assert_eq!(
+            governor.quality(),
+            Quality {
+                particle_fraction: 1.0,
+                substeps: 2,
+                post_fx: true
+            }
+        );
+    }
+
+    #[test]
+    fn skips_post_fx_without_a_chain() {
+        let mut governor = QualityGovernor::new(BUDGET, 1, false);
+        let changes = run(&mut governor, BUDGET * 2, 1.0);
+        assert_eq!(
+            changes[0],
+            Quality {
+                particle_fraction: PARTICLE_STEP,
+                substeps: 1,
+                post_fx: false
+            }
+        );
+        run(&mut governor, BUDGET / 2, 60.0);
+        assert!(!governor.quality().post_fx);
+    }
+
+    #[test]
+    fn holds_frames_slightly_over_budget() {
+        let mut governor = QualityGovernor::new(BUDGET, 2, true);
+        // Inside the margin over budget, including single spikes past it
+        let changes: Vec<_> = (0..2000)
+            .filter_map(|i| {
+                let frame_time = if i % 10 == 0 { BUDGET * 2 } else { BUDGET };
+                governor.update(frame_time)
+            })
+            .collect();
+        assert!(changes.is_empty(), "{changes:?}");
+    }
+
+    #[test]
+    fn backs_off_probes_that_go_over_budget() {
+        // Over budget at full quality, well within it one level down
+        let mut governor = QualityGovernor::new(BUDGET, 1, false);
+        let mut changes = 0;
+        let mut elapsed = 0.0;
+        while elapsed < 600.0 {
+            let frame_time = if governor.quality().particle_fraction < 1.0 {
+                BUDGET / 2
+            } else {
+                BUDGET * 2
+            };
+            elapsed += frame_time.as_secs_f32();
+            changes += governor.update(frame_time).is_some() as u32;
+        }
+        // Probing every MIN_PROBE_DELAY would switch hundreds of times, the doubling delay
+        // settles on a probe each MAX_PROBE_DELAY
+        let bound = 2 * (600.0 / MAX_PROBE_DELAY) as u32 + 12;
+        assert!(changes <= bound, "{changes} changes");
+    }
+}
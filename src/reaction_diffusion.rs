@@ -0,0 +1,243 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match the workgroup size of `forward` and `backward` in reaction_diffusion_shader.wgsl
+const CELL_WORKGROUP: u32 = 16;
+
+/// Gray-Scott reaction-diffusion between two chemicals on a grid over the domain: A is fed
+/// in and B eats it, growing spots, stripes and mazes. Particles seed B where they pass.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReactionDiffusionConfig {
+    // Cells along each side of the grid
+    pub size: u32,
+    // Rate A is replenished, and B removed at `feed + kill`. Small changes give quite
+    // different patterns, these grow coral-like mazes
+    pub feed: f32,
+    pub kill: f32,
+    // How fast each chemical spreads, B should be slower than A
+    pub diffusion_a: f32,
+    pub diffusion_b: f32,
+    // Pairs of reaction steps per frame, the patterns grow faster with more
+    pub steps: u32,
+    // B at least this high in every particle's cell, 0 to leave the grid to itself
+    pub seed: f32,
+    // Velocity change per frame for each unit of B per domain unit uphill, negative to
+    // avoid it instead
+    pub follow: f32,
+}
+
+impl Default for ReactionDiffusionConfig {
+    fn default() -> Self {
+        ReactionDiffusionConfig {
+            size: 256,
+            feed: 0.055,
+            kill: 0.062,
+            diffusion_a: 1.0,
+            diffusion_b: 0.5,
+            steps: 4,
+            seed: 0.5,
+            follow: 0.0,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `ReactionParams` in reaction_diffusion_shader.wgsl
+    struct ReactionParams {
+        particle_count: u32,
+        size: u32,
+        feed: f32,
+        kill: f32,
+        diffusion_a: f32,
+        diffusion_b: f32,
+        seed: f32,
+        follow: f32,
+    }
+}
+
+/// Particles seed the grid, it reacts and diffuses a few steps, then particles steer on
+/// what it's grown into.
+///
+/// The chemicals are kept in two grids of (A, B) pairs stepped back and forth between, so
+/// `chemicals` always ends up holding the latest. Every particle in a cell writes the same
+/// value there when seeding, so the result doesn't depend on the order they run in.
+pub struct ReactionDiffusion {
+    pub config: ReactionDiffusionConfig,
+    seed: wgpu::ComputePipeline,
+    forward: wgpu::ComputePipeline,
+    backward: wgpu::ComputePipeline,
+    respond: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ReactionParams>,
+    chemicals: wgpu::Buffer,
+    stepped: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl ReactionDiffusion {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: ReactionDiffusionConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "reaction_diffusion_shader",
+            include_str!("./shaders/reaction_diffusion_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .build(device, "Reaction-Diffusion");
+        let params_buffer =
+            UniformBuffer::new(device, resources, "Reaction-Diffusion Params Buffer");
+        // All A and no B, so nothing happens until seeded
+        let size = config.size.max(1) as usize;
+        let empty = vec![[1.0f32, 0.0]; size * size];
+        let chemicals = resources.buffer_init(
+            device,
+            "Reaction-Diffusion Buffer",
+            bytemuck::cast_slice(&empty),
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        );
+        let stepped = resources.buffer_init(
+            device,
+            "Reaction-Diffusion Stepped Buffer",
+            bytemuck::cast_slice(&empty),
+            BufferUsages::STORAGE,
+        );
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            [&chemicals, &stepped],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reaction-Diffusion Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Reaction-Diffusion Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Reaction-Diffusion Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        ReactionDiffusion {
+            config,
+            seed: pipeline("seed"),
+            forward: pipeline("forward"),
+            backward: pipeline("backward"),
+            respond: pipeline("respond"),
+            bindings,
+            params_buffer,
+            chemicals,
+            stepped,
+            bind_groups,
+        }
+    }
+
+    /// The `size` by `size` (A, B) pairs of f32s, row by row from the bottom of the domain.
+    pub fn chemicals_buffer(&self) -> &wgpu::Buffer {
+        &self.chemicals
+    }
+}
+
+impl Stage for ReactionDiffusion {
+    fn kind(&self) -> StageKind {
+        StageKind::ReactionDiffusion
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = ReactionParams {
+            particle_count: frame.particle_count,
+            size: self.config.size.max(1),
+            feed: self.config.feed,
+            kill: self.config.kill,
+            diffusion_a: self.config.diffusion_a,
+            diffusion_b: self.config.diffusion_b,
+            seed: self.config.seed.clamp(0.0, 1.0),
+            follow: self.config.follow,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        let cell_workgroups = params.size.div_ceil(CELL_WORKGROUP);
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Reaction-Diffusion Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        if params.seed > 0.0 {
+            compute_pass.set_pipeline(&self.seed);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        for _ in 0..self.config.steps {
+            compute_pass.set_pipeline(&self.forward);
+            compute_pass.dispatch_workgroups(cell_workgroups, cell_workgroups, 1);
+            compute_pass.set_pipeline(&self.backward);
+            compute_pass.dispatch_workgroups(cell_workgroups, cell_workgroups, 1);
+        }
+        if params.follow != 0.0 {
+            compute_pass.set_pipeline(&self.respond);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            [&self.chemicals, &self.stepped],
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ReactionParams>,
+    [chemicals, stepped]: [&wgpu::Buffer; 2],
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                chemicals.as_entire_binding(),
+                stepped.as_entire_binding(),
+            ],
+        )
+    })
+}
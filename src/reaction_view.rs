@@ -0,0 +1,86 @@
+use nannou::wgpu::{self, ShaderStages};
+
+use crate::bindings::Bindings;
+use crate::diagnostics;
+use crate::reaction_diffusion::ReactionDiffusion;
+use crate::resources::GpuResources;
+
+/// Draws the B chemical of a `ReactionDiffusion` over the background through the camera, so
+/// its patterns make a background of their own.
+pub struct ReactionView {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ReactionView {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &GpuResources,
+        reaction_diffusion: &ReactionDiffusion,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "reaction_view_shader",
+            include_str!("./shaders/reaction_view_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .storage_ro(0)
+            .uniform(1)
+            .build(device, "Reaction View");
+        let bind_group = bindings.bind_group(
+            device,
+            &[
+                reaction_diffusion.chemicals_buffer().as_entire_binding(),
+                resources.camera.as_entire_binding(),
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reaction View Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Reaction View Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Reaction View Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        ReactionView {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    /// Shade the chemicals over the whole frame.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
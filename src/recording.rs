@@ -0,0 +1,448 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+use crate::camera::CameraState;
+use crate::canvas::CanvasConfig;
+use crate::compaction::CompactionConfig;
+use crate::constraints::NetworkConfig;
+use crate::contagion::ContagionConfig;
+use crate::drag::DragConfig;
+use crate::emitters::EmittersConfig;
+use crate::fireworks::FireworksConfig;
+use crate::forces::{Attractor, NoiseConfig, WanderConfig};
+use crate::freeze::{FreezeConfig, Region};
+use crate::goal::GoalConfig;
+use crate::impulses::Impulse;
+use crate::inflow::InflowConfig;
+use crate::lennard_jones::LennardJonesConfig;
+use crate::lifetime::LifetimeConfig;
+use crate::mass::MassConfig;
+use crate::modulation::Modulator;
+use crate::orientation::OrientationConfig;
+use crate::parameters::Parameter;
+use crate::particle_sort::SortConfig;
+use crate::pbd::PbdConfig;
+use crate::physarum::PhysarumConfig;
+use crate::reaction_diffusion::ReactionDiffusionConfig;
+use crate::replay::{self, Replay};
+use crate::settings::Settings;
+use crate::sim_variant::SimVariant;
+use crate::simulation::{LeaderConfig, RegionOfInterest, RuleRadii, RuleWeights, SpeedLimits};
+use crate::sleep::SleepConfig;
+use crate::stages::StageKind;
+use crate::stamina::StaminaConfig;
+use crate::stir::Stir;
+use crate::territory::TerritoryConfig;
+use crate::text_targets::TextConfig;
+use crate::thermostat::ThermostatConfig;
+use crate::trail::TrailConfig;
+use crate::vector_field::VectorFieldConfig;
+use crate::vorticity::VorticityConfig;
+
+/// Something that changes the simulation or view, applied before simulating a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    ToggleAlignment,
+    ToggleCohesion,
+    ToggleSeparation,
+    CycleNeighborhood,
+    // Plain or shared memory neighbour loop, same result either way
+    ToggleTiled,
+    CycleBackground,
+    // Density field shaded as lit liquid
+    ToggleFluidShading,
+    // Density field exposed from its histogram
+    ToggleAutoExposure,
+    // Velocity or crowding
+    CycleColorMode,
+    SetParticles(u32),
+    // Chosen by the adaptive quality governor, recorded so playback doesn't depend on timing
+    SetQuality {
+        particle_count: u32,
+        substeps: u32,
+        // Left out of recordings from before post-fx was one of its levels
+        #[serde(default)]
+        drop_post_fx: bool,
+    },
+    // Right wall position in the piston variant
+    SetPiston(f32),
+    // Lennard-Jones temperature, in reduced units
+    SetTemperature(f32),
+    SetDrag {
+        linear: f32,
+        quadratic: f32,
+    },
+    SetRuleWeights(RuleWeights),
+    SetRuleRadii(RuleRadii),
+    SetSpeedLimits(SpeedLimits),
+    // Any of `parameters::PARAMETERS` by name, e.g. from a remote client
+    SetParameter {
+        parameter: &'static Parameter,
+        value: f32,
+    },
+    ToggleStage(StageKind),
+    // A wall drawn with the mouse, end points in domain units
+    AddObstacle {
+        a: [f32; 2],
+        b: [f32; 2],
+    },
+    ClearObstacles,
+    // Particles frozen where they are with the mouse, or thawed when not `frozen`
+    Freeze {
+        region: Region,
+        frozen: bool,
+    },
+    // A firework set off with the mouse, in domain units, with its sparks' speed scaled by
+    // the pen's pressure when set off with a tablet
+    Burst {
+        position: [f32; 2],
+        #[serde(default)]
+        scale: Option<f32>,
+    },
+    // A one-shot push out from a point, set off with I or the middle mouse button
+    Impulse(Impulse),
+    // Momentum handed out around the cursor as it's dragged with Shift held
+    Stir(Stir),
+    // The modulators replaced, by a preset
+    ClearModulators,
+    AddModulator(Modulator),
+    // A finger down or moved on a touchscreen, in domain units, strength scaled by pressure
+    Touch {
+        id: u64,
+        position: [f32; 2],
+        strength: f32,
+    },
+    EndTouch {
+        id: u64,
+    },
+    // Where the gamepad's attractor has moved to, and how hard it pulls
+    SetGamepadAttractor(Attractor),
+    Camera(CameraState),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub frame: u64,
+    pub action: Action,
+}
+
+/// Everything needed to re-run a session: the starting state and each action with the frame
+/// it happened on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    // Version of the program that made the recording
+    pub version: String,
+    pub seed: u64,
+    pub particles: u32,
+    pub substeps: u32,
+    pub simulation: SimVariant,
+    #[serde(default)]
+    pub speed_limits: SpeedLimits,
+    #[serde(default)]
+    pub rule_weights: RuleWeights,
+    #[serde(default)]
+    pub rule_radii: RuleRadii,
+    #[serde(default)]
+    pub view_angle: Option<f32>,
+    #[serde(default)]
+    pub thermostat: Option<ThermostatConfig>,
+    #[serde(default)]
+    pub lennard_jones: Option<LennardJonesConfig>,
+    #[serde(default)]
+    pub physarum: Option<PhysarumConfig>,
+    #[serde(default)]
+    pub fireworks: Option<FireworksConfig>,
+    // Replaces the seeded random particles when set
+    #[serde(default)]
+    pub network: Option<NetworkConfig>,
+    #[serde(default)]
+    pub pbd: Option<PbdConfig>,
+    #[serde(default)]
+    pub noise: Option<NoiseConfig>,
+    #[serde(default)]
+    pub wander: Option<WanderConfig>,
+    #[serde(default)]
+    pub mass: Option<MassConfig>,
+    #[serde(default)]
+    pub roi: Option<RegionOfInterest>,
+    // Read again on playback, like the level
+    #[serde(default)]
+    pub vector_field: Option<VectorFieldConfig>,
+    #[serde(default)]
+    pub vorticity: Option<VorticityConfig>,
+    #[serde(default)]
+    pub drag: Option<DragConfig>,
+    #[serde(default)]
+    pub trail: Option<TrailConfig>,
+    #[serde(default)]
+    pub reaction_diffusion: Option<ReactionDiffusionConfig>,
+    #[serde(default)]
+    pub lifetime: Option<LifetimeConfig>,
+    #[serde(default)]
+    pub orientation: Option<OrientationConfig>,
+    #[serde(default)]
+    pub inflow: Option<InflowConfig>,
+    #[serde(default)]
+    pub stamina: Option<StaminaConfig>,
+    #[serde(default)]
+    pub sleep: Option<SleepConfig>,
+    #[serde(default)]
+    pub canvas: Option<CanvasConfig>,
+    #[serde(default)]
+    pub compaction: Option<CompactionConfig>,
+    // Its route file is read again on playback
+    #[serde(default)]
+    pub goal: Option<GoalConfig>,
+    #[serde(default)]
+    pub leaders: Option<LeaderConfig>,
+    // So is its font
+    #[serde(default)]
+    pub text: Option<TextConfig>,
+    #[serde(default)]
+    pub emitters: Option<EmittersConfig>,
+    #[serde(default)]
+    pub contagion: Option<ContagionConfig>,
+    #[serde(default)]
+    pub territory: Option<TerritoryConfig>,
+    #[serde(default)]
+    pub sort: Option<SortConfig>,
+    #[serde(default)]
+    pub freeze: Option<FreezeConfig>,
+    #[serde(default)]
+    pub attractors: Vec<Attractor>,
+    #[serde(default)]
+    pub modulators: Vec<Modulator>,
+    // Read again on playback, so it has to still be there
+    #[serde(default)]
+    pub level: Option<PathBuf>,
+    // Drawn before the first frame, from a scene
+    #[serde(default)]
+    pub walls: Vec<[f32; 4]>,
+    // Frames simulated in total
+    pub frames: u64,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    /// A recording starting from the simulation state in `settings`.
+    pub fn new(seed: u64, settings: &Settings) -> Self {
+        Recording {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            seed,
+            particles: settings.particles,
+            substeps: settings.substeps,
+            simulation: settings.simulation,
+            speed_limits: settings.speed_limits,
+            rule_weights: settings.rule_weights,
+            rule_radii: settings.rule_radii,
+            view_angle: settings.view_angle,
+            thermostat: settings.thermostat,
+            lennard_jones: settings.lennard_jones,
+            physarum: settings.physarum,
+            fireworks: settings.fireworks,
+            network: settings.network,
+            pbd: settings.pbd,
+            noise: settings.noise,
+            wander: settings.wander,
+            mass: settings.mass,
+            roi: settings.roi,
+            vector_field: settings.vector_field.clone(),
+            vorticity: settings.vorticity,
+            drag: settings.drag,
+            trail: settings.trail,
+            reaction_diffusion: settings.reaction_diffusion,
+            lifetime: settings.lifetime,
+            orientation: settings.orientation,
+            inflow: settings.inflow,
+            stamina: settings.stamina,
+            sleep: settings.sleep,
+            canvas: settings.canvas,
+            compaction: settings.compaction,
+            goal: settings.goal.clone(),
+            leaders: settings.leaders,
+            text: settings.text.clone(),
+            emitters: settings.emitters.clone(),
+            contagion: settings.contagion,
+            territory: settings.territory.clone(),
+            sort: settings.sort,
+            freeze: settings.freeze.clone(),
+            attractors: settings.attractors.clone(),
+            modulators: settings.modulators.clone(),
+            level: settings.level.clone(),
+            walls: Vec::new(),
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Whether the boids step is all that runs, which half precision particles need.
+    pub fn boids_only(&self) -> bool {
+        self.other_stages().is_empty()
+    }
+
+    /// The options running stages besides the boids step, to name in warnings.
+    pub fn other_stages(&self) -> Vec<&'static str> {
+        [
+            ("--thermostat", self.thermostat.is_none()),
+            ("--lennard-jones", self.lennard_jones.is_none()),
+            ("--physarum", self.physarum.is_none()),
+            ("--fireworks", self.fireworks.is_none()),
+            ("--cloth or --rope", self.network.is_none()),
+            ("--pbd", self.pbd.is_none()),
+            ("--noise", self.noise.is_none()),
+            ("--vector-field", self.vector_field.is_none()),
+            ("--vorticity", self.vorticity.is_none()),
+            ("--drag", self.drag.is_none()),
+            ("--trail", self.trail.is_none()),
+            ("--reaction-diffusion", self.reaction_diffusion.is_none()),
+            ("--lifetime", self.lifetime.is_none()),
+            ("--attractor", self.attractors.is_empty()),
+            ("--orient", self.orientation.is_none()),
+            ("--inflow", self.inflow.is_none()),
+            ("--stamina", self.stamina.is_none()),
+            ("--sleep", self.sleep.is_none()),
+            ("--canvas", self.canvas.is_none()),
+            ("--compact", self.compaction.is_none()),
+            ("--goal", self.goal.is_none()),
+            ("--leaders", self.leaders.is_none()),
+            ("--text", self.text.is_none()),
+            ("--emitters", self.emitters.is_none()),
+            ("--contagion", self.contagion.is_none()),
+            ("--bases", self.territory.is_none()),
+            ("--sort", self.sort.is_none()),
+            ("--freeze", self.freeze.is_none()),
+            ("--level", self.level.is_none()),
+        ]
+        .into_iter()
+        .filter(|(_, off)| !off)
+        .map(|(option, _)| option)
+        .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        nannou::io::load_from_json(path)
+            .map_err(|err| format!("Failed to load recording {}: {}", path.display(), err))
+    }
+}
+
+/// Collects actions while running and writes the recording on exit, as a replay with the
+/// settings launched with when `path` is a `.replay` file.
+pub struct Recorder {
+    path: PathBuf,
+    recording: Recording,
+    // Bundled into the replay
+    settings: Settings,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, recording: Recording, settings: Settings) -> Self {
+        Recorder {
+            path,
+            recording,
+            settings,
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, action: Action) {
+        self.recording.events.push(Event { frame, action });
+    }
+
+    pub fn finish(mut self, frames: u64) {
+        self.recording.frames = frames;
+        let events = self.recording.events.len();
+        let saved = if replay::is_replay(&self.path) {
+            Replay::new(self.settings, self.recording).save(&self.path)
+        } else {
+            nannou::io::save_to_json(&self.path, &self.recording)
+                .map_err(|err| format!("Failed to save recording {}: {}", self.path.display(), err))
+        };
+        match saved {
+            Ok(()) => info!(
+                "Saved {} frames and {} events to {}",
+                frames,
+                events,
+                self.path.display()
+            ),
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+
+/// Feeds a recording's actions back in on the frames they happened.
+pub struct Player {
+    recording: Recording,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        Player { recording, next: 0 }
+    }
+
+    /// Actions to apply before simulating `frame`.
+    pub fn due(&mut self, frame: u64) -> Vec<Action> {
+        let events = &self.recording.events[self.next..];
+        let count = events
+            .iter()
+            .take_while(|event| event.frame <= frame)
+            .count();
+        self.next += count;
+        events[..count].iter().map(|event| event.action).collect()
+    }
+
+    /// Whether every recorded frame has been played.
+    pub fn finished(&self, frame: u64) -> bool {
+        frame >= self.recording.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        let settings = Settings {
+            view_angle: Some(90.0),
+            stamina: Some(Default::default()),
+            contagion: Some(Default::default()),
+            ..Settings::default()
+        };
+        let mut recording = Recording::new(7, &settings);
+        recording.frames = 120;
+        recording.events = vec![
+            Event {
+                frame: 3,
+                action: Action::SetParticles(5000),
+            },
+            Event {
+                frame: 10,
+                action: Action::SetDrag {
+                    linear: 0.1,
+                    quadratic: 0.2,
+                },
+            },
+            Event {
+                frame: 50,
+                action: Action::ToggleStage(StageKind::Noise),
+            },
+        ];
+        let json = serde_json::to_string(&recording).unwrap();
+        let loaded: Recording = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert_eq!(loaded.events[1].action, recording.events[1].action);
+    }
+
+    #[test]
+    fn boids_only_without_other_stages() {
+        assert!(Recording::new(1, &Settings::default()).boids_only());
+        let settings = Settings {
+            stamina: Some(Default::default()),
+            ..Settings::default()
+        };
+        assert!(!Recording::new(1, &settings).boids_only());
+        assert_eq!(Recording::new(1, &settings).other_stages(), ["--stamina"]);
+    }
+}
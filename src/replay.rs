@@ -0,0 +1,146 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::recording::Recording;
+use crate::settings::Settings;
+
+// Bumped whenever a replay's layout changes in a way older versions can't read
+const FORMAT: u32 = 1;
+
+/// A recording bundled with every setting it was made with, to hand to someone else to play
+/// the exact session on their machine: a `.replay` file, compressed JSON. The record command
+/// writes one in place of a plain recording when given that extension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub format: u32,
+    // Version of the program that made it, shaders change between releases so the same
+    // actions only play out the same on a compatible one, see `compatible`
+    pub version: String,
+    pub settings: Settings,
+    pub recording: Recording,
+}
+
+/// Whether `path` is saved and played as a replay, rather than a plain JSON recording.
+pub fn is_replay(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "replay")
+}
+
+// Releases that play each other's replays the same, the same major version, or minor before 1.0
+fn release(version: &str) -> Option<(u64, u64)> {
+    let mut numbers = version.split('.').map(|number| number.parse::<u64>().ok());
+    let major = numbers.next()??;
+    let minor = numbers.next()??;
+    Some(if major == 0 { (0, minor) } else { (major, 0) })
+}
+
+/// Whether a replay made by `version` reproduces on this one.
+pub fn compatible(version: &str) -> bool {
+    let current = release(env!("CARGO_PKG_VERSION"));
+    current.is_some() && release(version) == current
+}
+
+impl Replay {
+    pub fn new(settings: Settings, recording: Recording) -> Self {
+        Replay {
+            format: FORMAT,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            settings,
+            recording,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let failed =
+            |err: &dyn Display| format!("Failed to save replay {}: {}", path.display(), err);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        serde_json::to_writer(&mut encoder, self).map_err(|err| failed(&err))?;
+        let bytes = encoder.finish().map_err(|err| failed(&err))?;
+        nannou::io::safe_file_save(path, &bytes).map_err(|err| failed(&err))
+    }
+
+    /// Load the replay at `path`, refusing one the simulation wouldn't reproduce from.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_value(read(path)?)
+            .map_err(|err| format!("Failed to load replay {}: {}", path.display(), err))
+    }
+
+    /// The replay in `value`, checked for a readable format and a compatible version first,
+    /// so a newer file says so rather than failing on fields it doesn't know.
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        let format = value.get("format").and_then(Value::as_u64).unwrap_or(0);
+        let version = value
+            .get("version")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown");
+        if format > u64::from(FORMAT) {
+            return Err(format!(
+                "made by version {version} in a newer format, {format}, this version {} reads up to {FORMAT}",
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        if !compatible(version) {
+            return Err(format!(
+                "made by version {version}, which doesn't play the same as this version {}",
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+}
+
+/// The JSON in the replay at `path`, decompressed, for checking its keys.
+pub fn read(path: &Path) -> Result<Value, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Failed to open replay {}: {}", path.display(), err))?;
+    serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+        .map_err(|err| format!("Failed to read replay {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{Action, Event};
+    use crate::theme::Theme;
+
+    #[test]
+    fn round_trips_compressed_and_refuses_incompatible_versions() {
+        let settings = Settings {
+            theme: Theme::Paper,
+            ..Settings::default()
+        };
+        let mut recording = Recording::new(7, &settings);
+        recording.frames = 60;
+        recording.events.push(Event {
+            frame: 3,
+            action: Action::SetParticles(5000),
+        });
+        let replay = Replay::new(settings, recording);
+        let path = std::env::temp_dir().join(format!(
+            "particle-nannou-{}-session.replay",
+            std::process::id()
+        ));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let json = |replay: &Replay| serde_json::to_value(replay).unwrap();
+        assert_eq!(json(&loaded.unwrap()), json(&replay));
+
+        assert!(compatible(env!("CARGO_PKG_VERSION")));
+        assert!(!compatible("99.0.0"));
+        assert!(!compatible("unknown"));
+        let mut value = json(&replay);
+        value["version"] = "99.0.0".into();
+        assert!(Replay::from_value(value).is_err());
+        let mut value = json(&replay);
+        value["format"] = (FORMAT + 1).into();
+        assert!(Replay::from_value(value).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use bytemuck::Zeroable;
+use nannou::rand::Rng;
+use nannou::wgpu::{self, util::DeviceExt, BufferUsages};
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::camera::CameraUniforms;
+use crate::diagnostics;
+use crate::gpu;
+use crate::particle_layout::ParticleLayout;
+use crate::{Particle, SimParams};
+
+/// Owns the GPU buffers and textures shared between passes and keeps track of what's
+/// allocated, so the particle buffer can be regrown in one place.
+///
+/// Particles live in a pair of ping-pong buffers: each simulation step reads one and writes
+/// the other. Anything holding bind groups over them has to rebind after `set_capacity`.
+pub struct GpuResources {
+    particles: [wgpu::Buffer; 2],
+    layout: ParticleLayout,
+    // Index of the buffer holding the latest state
+    current: usize,
+    pub params: wgpu::Buffer,
+    pub camera: wgpu::Buffer,
+    // Wall impulse counter written by the simulation in the piston variant
+    pub wall_impulse: wgpu::Buffer,
+    // Neighbours each particle saw in the last boids step, indexed like the particles
+    pub neighbor_counts: wgpu::Buffer,
+    // Each particle's colour and the emitter that last dyed it, see `Emitters`, indexed like
+    // the particles
+    pub dyes: wgpu::Buffer,
+    // Each particle's bits from `flags`, indexed like the particles. Cleared when resized
+    pub flags: wgpu::Buffer,
+    capacity: u32,
+    // Bytes allocated per label. Recreating a resource under the same label replaces it.
+    sizes: BTreeMap<String, u64>,
+}
+
+impl GpuResources {
+    pub fn new(device: &wgpu::Device, particles: &[Particle]) -> Self {
+        GpuResources::with_layout(device, particles, ParticleLayout::default())
+    }
+
+    pub fn with_layout(
+        device: &wgpu::Device,
+        particles: &[Particle],
+        layout: ParticleLayout,
+    ) -> Self {
+        let mut sizes = BTreeMap::new();
+        GpuResources {
+            particles: create_particle_buffers(device, &mut sizes, &layout.encode(particles)),
+            layout,
+            current: 0,
+            params: create_uniform::<SimParams>(device, &mut sizes, "Sim Params Buffer"),
+            camera: create_uniform::<CameraUniforms>(device, &mut sizes, "Camera Buffer"),
+            wall_impulse: create_buffer(
+                device,
+                &mut sizes,
+                "Wall Impulse Buffer",
+                mem::size_of::<u32>() as wgpu::BufferAddress,
+                BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            ),
+            neighbor_counts: create_neighbor_counts(device, &mut sizes, particles.len() as u32),
+            dyes: create_dyes(device, &mut sizes, particles.len() as u32),
+            flags: create_flags(device, &mut sizes, particles.len() as u32),
+            capacity: particles.len() as u32,
+            sizes,
+        }
+    }
+
+    /// The buffer holding the latest particle state.
+    pub fn particles(&self) -> &wgpu::Buffer {
+        &self.particles[self.current]
+    }
+
+    pub fn particle_buffers(&self) -> &[wgpu::Buffer; 2] {
+        &self.particles
+    }
+
+    pub fn layout(&self) -> ParticleLayout {
+        self.layout
+    }
+
+    /// Bind the particles to draw as instance vertex buffers, laid out as
+    /// `ParticleLayout::vertex_buffer_layouts` says.
+    pub fn set_vertex_buffers<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let particles = self.particles();
+        if self.layout.separate_arrays {
+            let velocities = self.capacity as wgpu::BufferAddress * self.layout.component_size();
+            render_pass.set_vertex_buffer(0, particles.slice(..velocities));
+            render_pass.set_vertex_buffer(1, particles.slice(velocities..));
+        } else {
+            render_pass.set_vertex_buffer(0, particles.slice(..));
+        }
+    }
+
+    /// Index into `particle_buffers` of the latest state.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Flip the ping-pong buffers after a simulation step has written the other one.
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+    }
+
+    /// Number of particles each particle buffer holds.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Total bytes allocated through this manager.
+    pub fn total_bytes(&self) -> u64 {
+        self.sizes.values().sum()
+    }
+
+    /// Bytes allocated per label, in label order.
+    pub fn allocations(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.sizes
+            .iter()
+            .map(|(label, &bytes)| (label.as_str(), bytes))
+    }
+
+    pub fn buffer(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::BufferAddress,
+        usage: BufferUsages,
+    ) -> wgpu::Buffer {
+        create_buffer(device, &mut self.sizes, label, size, usage)
+    }
+
+    /// A buffer created with `contents`, e.g. precomputed constraints.
+    pub fn buffer_init(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        contents: &[u8],
+        usage: BufferUsages,
+    ) -> wgpu::Buffer {
+        self.sizes.insert(label.to_owned(), contents.len() as u64);
+        diagnostics::checked(device, label, || {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            })
+        })
+    }
+
+    pub fn uniform<T>(&mut self, device: &wgpu::Device, label: &str) -> wgpu::Buffer {
+        create_uniform::<T>(device, &mut self.sizes, label)
+    }
+
+    pub fn texture(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        size: [u32; 2],
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        let texture = wgpu::TextureBuilder::new()
+            .size(size)
+            .format(format)
+            .usage(usage)
+            .build(device);
+        self.track(label, texture.size_bytes() as u64);
+        texture
+    }
+
+    /// Record a resource created elsewhere, e.g. a texture loaded from a file.
+    pub fn track(&mut self, label: &str, bytes: u64) {
+        self.sizes.insert(label.to_owned(), bytes);
+    }
+
+    /// Copy the first `count` particles back from the GPU, blocking until they arrive.
+    pub fn read_particles(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        count: u32,
+    ) -> Vec<Particle> {
+        let count = count.min(self.capacity);
+        let len = self.layout.bytes_needed(self.capacity, count) as usize;
+        let bytes = gpu::read_buffer::<u8>(device, queue, self.particles(), len);
+        self.layout.decode(&bytes, self.capacity, count)
+    }
+
+    /// Put every particle somewhere random again, as when first made, with their flags
+    /// cleared.
+    pub fn scatter(&self, queue: &wgpu::Queue, rng: &mut impl Rng) {
+        let particles = (0..self.capacity)
+            .map(|_| Particle::random(rng))
+            .collect::<Vec<_>>();
+        let bytes = self.layout.encode(&particles);
+        for buffer in &self.particles {
+            queue.write_buffer(buffer, 0, &bytes);
+        }
+        queue.write_buffer(&self.flags, 0, &vec![0; self.flags.size() as usize]);
+    }
+
+    /// Grow or shrink the particle buffers. Existing particles are kept, new ones are
+    /// scattered randomly.
+    pub fn set_capacity(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: u32,
+        rng: &mut impl Rng,
+    ) {
+        let capacity = capacity.max(1);
+        let new_particles = (self.capacity..capacity)
+            .map(|_| Particle::random(rng))
+            .collect::<Vec<_>>();
+        // Zeroed particles are placeholders for the copied ones
+        let mut contents = vec![Particle::zeroed(); capacity.min(self.capacity) as usize];
+        contents.extend(new_particles);
+        let buffers =
+            create_particle_buffers(device, &mut self.sizes, &self.layout.encode(&contents));
+
+        let kept = self
+            .layout
+            .kept_ranges(self.capacity, capacity, capacity.min(self.capacity));
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Resize Encoder"),
+        });
+        for buffer in &buffers {
+            for &(from, to, size) in &kept {
+                encoder.copy_buffer_to_buffer(self.particles(), from, buffer, to, size);
+            }
+        }
+        queue.submit(Some(encoder.finish()));
+
+        self.particles = buffers;
+        self.neighbor_counts = create_neighbor_counts(device, &mut self.sizes, capacity);
+        self.dyes = create_dyes(device, &mut self.sizes, capacity);
+        self.flags = create_flags(device, &mut self.sizes, capacity);
+        self.current = 0;
+        self.capacity = capacity;
+    }
+}
+
+fn create_particle_buffers(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    contents: &[u8],
+) -> [wgpu::Buffer; 2] {
+    ["Particle Buffer A", "Particle Buffer B"].map(|label| {
+        sizes.insert(label.to_owned(), contents.len() as u64);
+        diagnostics::checked(device, label, || {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: BufferUsages::STORAGE
+                    | BufferUsages::VERTEX
+                    | BufferUsages::COPY_DST
+                    | BufferUsages::COPY_SRC,
+            })
+        })
+    })
+}
+
+fn create_neighbor_counts(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    capacity: u32,
+) -> wgpu::Buffer {
+    create_buffer(
+        device,
+        sizes,
+        "Neighbor Counts Buffer",
+        capacity.max(1) as wgpu::BufferAddress * mem::size_of::<u32>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE,
+    )
+}
+
+fn create_flags(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    capacity: u32,
+) -> wgpu::Buffer {
+    create_buffer(
+        device,
+        sizes,
+        "Flags Buffer",
+        capacity.max(1) as wgpu::BufferAddress * mem::size_of::<u32>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+    )
+}
+
+// Must match `Dye` in emitters_shader.wgsl
+const DYE_SIZE: wgpu::BufferAddress = 16;
+
+fn create_dyes(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    capacity: u32,
+) -> wgpu::Buffer {
+    create_buffer(
+        device,
+        sizes,
+        "Dyes Buffer",
+        capacity.max(1) as wgpu::BufferAddress * DYE_SIZE,
+        BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    )
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    label: &str,
+    size: wgpu::BufferAddress,
+    usage: BufferUsages,
+) -> wgpu::Buffer {
+    sizes.insert(label.to_owned(), size);
+    diagnostics::checked(device, label, || {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    })
+}
+
+fn create_uniform<T>(
+    device: &wgpu::Device,
+    sizes: &mut BTreeMap<String, u64>,
+    label: &str,
+) -> wgpu::Buffer {
+    // Bound at the WGSL struct's size, which rounds up to 16 bytes
+    let size = mem::size_of::<T>().next_multiple_of(16) as wgpu::BufferAddress;
+    create_buffer(
+        device,
+        sizes,
+        label,
+        size,
+        BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    )
+}
@@ -0,0 +1,85 @@
+use nannou::wgpu::{self, BufferUsages};
+
+use crate::resources::GpuResources;
+
+// Frames between snapshots. Rewinding restores one snapshot per frame, so it plays back
+// this many times faster than real time.
+const INTERVAL: u64 = 6;
+// Assumed frame rate for turning seconds of history into snapshots
+const FRAME_RATE: f32 = 60.0;
+// Upper bound on the history buffer, fewer seconds are kept for large particle counts
+const MAX_BYTES: u64 = 128 * 1024 * 1024;
+
+/// Ring buffer of recent particle states on the GPU, for scrubbing backwards.
+pub struct History {
+    buffer: wgpu::Buffer,
+    slot_bytes: u64,
+    slots: u64,
+    // Next slot to write
+    head: u64,
+    // Snapshots currently held
+    len: u64,
+    frames: u64,
+}
+
+impl History {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, seconds: f32) -> Self {
+        let slot_bytes = resources.particles().size();
+        let wanted = (seconds * FRAME_RATE / INTERVAL as f32).ceil() as u64;
+        let limit = MAX_BYTES.min(device.limits().max_buffer_size) / slot_bytes;
+        let slots = wanted.min(limit).max(1);
+        let buffer = resources.buffer(
+            device,
+            "Rewind History Buffer",
+            slots * slot_bytes,
+            BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        );
+        History {
+            buffer,
+            slot_bytes,
+            slots,
+            head: 0,
+            len: 0,
+            frames: 0,
+        }
+    }
+
+    /// Snapshot the latest particle state every few frames. Call once per simulated frame.
+    pub fn capture(&mut self, encoder: &mut wgpu::CommandEncoder, resources: &GpuResources) {
+        self.frames += 1;
+        if !self.frames.is_multiple_of(INTERVAL) {
+            return;
+        }
+        encoder.copy_buffer_to_buffer(
+            resources.particles(),
+            0,
+            &self.buffer,
+            self.head * self.slot_bytes,
+            self.slot_bytes,
+        );
+        self.head = (self.head + 1) % self.slots;
+        self.len = (self.len + 1).min(self.slots);
+    }
+
+    /// Seconds of history left to rewind through.
+    pub fn seconds(&self) -> f32 {
+        (self.len * INTERVAL) as f32 / FRAME_RATE
+    }
+
+    /// Restore the most recent snapshot and drop it, so calling this each frame scrubs
+    /// backwards. Does nothing once the history runs out.
+    pub fn rewind(&mut self, encoder: &mut wgpu::CommandEncoder, resources: &GpuResources) {
+        if self.len == 0 {
+            return;
+        }
+        self.head = (self.head + self.slots - 1) % self.slots;
+        self.len -= 1;
+        encoder.copy_buffer_to_buffer(
+            &self.buffer,
+            self.head * self.slot_bytes,
+            resources.particles(),
+            0,
+            self.slot_bytes,
+        );
+    }
+}
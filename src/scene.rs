@@ -0,0 +1,354 @@
+use clap::CommandFactory;
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::cli::Args;
+use crate::locale::{fill, text};
+use crate::settings::Settings;
+use crate::theme::Hud;
+
+const LINE_HEIGHT: f32 = 18.0;
+const WIDTH: f32 = 360.0;
+const MARGIN: f32 = 10.0;
+
+/// A complete setup to share: everything it launches with, modes included, and the walls
+/// drawn before the first frame. Run with --scene, or picked from the --scenes folder with F8.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    // Listed in the browser, the file name when empty
+    pub name: String,
+    // As [ax, ay, bx, by], see `Obstacles::push`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<[f32; 4]>,
+    // Tables have to come after plain values in TOML
+    pub settings: Settings,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        nannou::io::load_from_toml(path)
+            .map_err(|err| format!("Failed to load scene {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        nannou::io::save_to_toml(path, self)
+            .map_err(|err| format!("Failed to save scene {}: {}", path.display(), err))
+    }
+}
+
+/// The scenes in the --scenes folder, listed over the view to pick one to switch to.
+pub struct SceneBrowser {
+    pub open: bool,
+    pub dir: PathBuf,
+    // Paths and names, in order of file name
+    scenes: Vec<(PathBuf, String)>,
+    selected: usize,
+}
+
+impl SceneBrowser {
+    pub fn new(dir: PathBuf) -> Self {
+        let mut browser = SceneBrowser {
+            open: false,
+            dir,
+            scenes: Vec::new(),
+            selected: 0,
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// List the `.toml` files in the folder again, leaving out those that aren't scenes with
+    /// a message.
+    pub fn refresh(&mut self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            self.scenes.clear();
+            return;
+        };
+        let mut paths = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "toml")
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        self.scenes = paths
+            .into_iter()
+            .filter_map(|path| match Scene::load(&path) {
+                Ok(scene) if !scene.name.is_empty() => Some((path, scene.name)),
+                Ok(_) => {
+                    let name = path.file_stem()?.to_string_lossy().into_owned();
+                    Some((path, name))
+                }
+                Err(err) => {
+                    warn!("Leaving out scene: {}", err);
+                    None
+                }
+            })
+            .collect();
+        self.selected = self.selected.min(self.scenes.len().saturating_sub(1));
+    }
+
+    /// Move the selection by `step` rows, wrapping around.
+    pub fn select(&mut self, step: isize) {
+        let count = self.scenes.len() as isize;
+        if count > 0 {
+            self.selected = (self.selected as isize + step).rem_euclid(count) as usize;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.scenes
+            .get(self.selected)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// A path in the folder for a new scene, not yet taken.
+    pub fn new_path(&self) -> PathBuf {
+        (1..)
+            .map(|n| self.dir.join(format!("scene-{n}.toml")))
+            .find(|path| !path.exists())
+            .expect("some scene number is free")
+    }
+
+    /// Draw the list in the top left corner, with `current`, the running scene, marked.
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud, current: Option<&Path>) {
+        let rows = self.scenes.len().max(1) + 1;
+        let panel = Rect::from_w_h(WIDTH, rows as f32 * LINE_HEIGHT + 2.0 * MARGIN)
+            .top_left_of(window.pad(MARGIN));
+        draw.rect().xy(panel.xy()).wh(panel.wh()).color(hud.panel);
+        let text_area = panel.pad(MARGIN);
+        let row = |index: usize| {
+            Rect::from_w_h(text_area.w(), LINE_HEIGHT)
+                .top_left_of(text_area)
+                .shift_y(-LINE_HEIGHT * index as f32)
+        };
+        let dim = Rgba {
+            alpha: hud.text.alpha * 0.6,
+            ..hud.text
+        };
+
+        if self.scenes.is_empty() {
+            let rect = row(0);
+            draw.text(&fill(
+                &text().no_scenes_in,
+                &[("path", &self.dir.display())],
+            ))
+            .xy(rect.xy())
+            .wh(rect.wh())
+            .font_size(12)
+            .left_justify()
+            .no_line_wrap()
+            .color(dim);
+        }
+        for (index, (path, name)) in self.scenes.iter().enumerate() {
+            let marker = match (index == self.selected, current == Some(path.as_path())) {
+                (true, _) => "> ",
+                (false, true) => "* ",
+                (false, false) => "  ",
+            };
+            let rect = row(index);
+            draw.text(&format!("{marker}{name}"))
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .font_size(13)
+                .left_justify()
+                .no_line_wrap()
+                .color(if index == self.selected {
+                    hud.text
+                } else {
+                    dim
+                });
+        }
+        let rect = row(rows - 1);
+        draw.text(&text().scene_browser_help)
+            .xy(rect.xy())
+            .wh(rect.wh())
+            .font_size(11)
+            .left_justify()
+            .no_line_wrap()
+            .color(SKYBLUE);
+    }
+}
+
+/// The options to build the scene at `path` with, from those `given` to this run, and the
+/// options left out of them. Modes only apply when a scene is built, so switching builds the
+/// new one from scratch with the same options. What goes with the run, like the recording,
+/// the stats and logs written and sync, ends with the scene switched away from. Telemetry and
+/// shared frames carry on through the switch, handed over rather than started again.
+pub fn scene_args(
+    given: impl IntoIterator<Item = OsString>,
+    path: &Path,
+) -> (Vec<OsString>, Vec<String>) {
+    // Replaced by the new value
+    const REPLACED: &str = "--scene";
+    // Kept running by the scene switched away from and handed over, see `hand_over`
+    const HANDED_OVER: [&str; 4] = [
+        "--telemetry",
+        "--telemetry-interval",
+        "--share-pipe",
+        "--share-ndi",
+    ];
+    // Played, written to or listened on for the run, ending with the scene switched away
+    // from, with the options that only go with them
+    const LEFT_OUT: [&str; 9] = [
+        "--play",
+        "--stats",
+        "--stats-interval",
+        "--sync-serve",
+        "--sync-followers",
+        "--sync-follow",
+        "--contagion-log",
+        "--territory-log",
+        "--log",
+    ];
+    // Built, so every argument knows how many values it takes
+    let mut command = Args::command();
+    command.build();
+    // Whether the option `long` takes `next` as its value. Those that can go without, like
+    // `--tiled`, only take it when it isn't another option
+    let takes_value = |long: &str, next: Option<&OsString>| {
+        let Some(range) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long))
+            .and_then(|arg| arg.get_num_args())
+        else {
+            return false;
+        };
+        range.min_values() > 0
+            || range.max_values() > 0
+                && next.is_some_and(|next| !next.to_string_lossy().starts_with('-'))
+    };
+    // First, as options after a command would be taken as the command's
+    let mut args = Vec::<OsString>::from([REPLACED.into(), path.into()]);
+    let mut left_out = Vec::new();
+    let mut recording = false;
+    let mut given = given.into_iter().peekable();
+    while let Some(arg) = given.next() {
+        let text = arg.to_string_lossy().into_owned();
+        let Some(long) = text.strip_prefix("--") else {
+            // The command, and the file a recording is saved to after it
+            if text == "record" {
+                recording = true;
+                left_out.push("the recording".to_owned());
+            } else if !recording || text.starts_with('-') {
+                args.push(arg);
+            }
+            continue;
+        };
+        let (name, inline_value) = match long.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (long, false),
+        };
+        let value = (!inline_value && takes_value(name, given.peek()))
+            .then(|| given.next())
+            .flatten();
+        let flag = format!("--{name}");
+        if LEFT_OUT.contains(&flag.as_str()) {
+            left_out.push(flag);
+        } else if flag != REPLACED && !HANDED_OVER.contains(&flag.as_str()) {
+            args.push(arg);
+            args.extend(value);
+        }
+    }
+    (args, left_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn round_trips_through_toml() {
+        let scene = Scene {
+            name: "Two emitters".to_owned(),
+            walls: vec![[-0.5, 0.0, 0.5, 0.0], [0.0, -0.5, 0.0, 0.5]],
+            settings: Settings {
+                emitters: Some(crate::emitters::EmittersConfig {
+                    positions: vec![[-0.5, 0.0], [0.5, 0.0]],
+                    ..Default::default()
+                }),
+                camera: Some(Default::default()),
+                attractors: vec![crate::forces::parse_attractor("0.5,0,0.001").unwrap()],
+                ..Settings::default()
+            },
+        };
+        let path =
+            std::env::temp_dir().join(format!("particle-nannou-{}-scene.toml", std::process::id()));
+        scene.save(&path).unwrap();
+        let loaded = Scene::load(&path);
+        let _ = fs::remove_file(&path);
+        // As JSON, since the settings hold floats and don't compare directly
+        let json = |scene: &Scene| serde_json::to_value(scene).unwrap();
+        assert_eq!(json(&loaded.unwrap()), json(&scene));
+    }
+
+    #[test]
+    fn switches_scenes_without_the_recording_files_or_addresses() {
+        let given = [
+            "--tiled",
+            "--telemetry",
+            "0.0.0.0:9001",
+            "--telemetry-interval=10",
+            "--text",
+            "record",
+            "--scene",
+            "old.toml",
+            "--sync-serve",
+            "0.0.0.0:9100",
+            "--stats=stats.csv",
+            "--play",
+            "old.replay",
+            "--share-pipe",
+            "frames.fifo",
+            "record",
+            "session.replay",
+            "--log",
+            "--separate-arrays",
+            "--scene",
+            "older.toml",
+            "--half-precision",
+            "false",
+            "-v",
+        ];
+        let (args, left_out) = scene_args(given.map(OsString::from), Path::new("new.toml"));
+        assert_eq!(
+            args,
+            [
+                "--scene",
+                "new.toml",
+                "--tiled",
+                "--text",
+                "record",
+                "--separate-arrays",
+                "--half-precision",
+                "false",
+                "-v"
+            ]
+        );
+        assert_eq!(
+            left_out,
+            [
+                "--sync-serve",
+                "--stats",
+                "--play",
+                "the recording",
+                "--log"
+            ]
+        );
+        let parsed =
+            Args::try_parse_from(["particle-nannou".into()].into_iter().chain(args)).unwrap();
+        assert!(parsed.command.is_none());
+        assert_eq!(parsed.scene.as_deref(), Some(Path::new("new.toml")));
+        assert_eq!(parsed.text.as_deref(), Some("record"));
+        assert_eq!(parsed.tiled, Some(true));
+        assert_eq!(parsed.half_precision, Some(false));
+    }
+}
@@ -0,0 +1,596 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::background::{BackgroundConfig, BackgroundKind};
+use crate::beats::BeatConfig;
+use crate::camera::{CameraState, ColorMode};
+use crate::canvas::CanvasConfig;
+use crate::cli::PresentMode;
+use crate::compaction::CompactionConfig;
+use crate::constraints::NetworkConfig;
+use crate::contagion::ContagionConfig;
+use crate::drag::DragConfig;
+use crate::emitters::EmittersConfig;
+use crate::fireworks::FireworksConfig;
+use crate::forces::{Attractor, NoiseConfig, WanderConfig};
+use crate::freeze::FreezeConfig;
+use crate::gamepad::GamepadConfig;
+use crate::goal::GoalConfig;
+use crate::impulses::ImpulseConfig;
+use crate::inflow::InflowConfig;
+use crate::installation::InstallationConfig;
+use crate::lennard_jones::LennardJonesConfig;
+use crate::lifetime::LifetimeConfig;
+use crate::mass::MassConfig;
+use crate::modulation::Modulator;
+use crate::orientation::OrientationConfig;
+use crate::particle_sort::SortConfig;
+use crate::particle_system::BoidsParams;
+use crate::pbd::PbdConfig;
+use crate::pen::PenPressure;
+use crate::physarum::PhysarumConfig;
+use crate::post_fx::Effect;
+use crate::reaction_diffusion::ReactionDiffusionConfig;
+use crate::sim_variant::SimVariant;
+use crate::simulation::{LeaderConfig, RegionOfInterest, RuleRadii, RuleWeights, SpeedLimits};
+use crate::sleep::SleepConfig;
+use crate::species::SpeciesConfig;
+use crate::stamina::StaminaConfig;
+use crate::stir::StirConfig;
+use crate::svg_export::SvgConfig;
+use crate::territory::TerritoryConfig;
+use crate::text_targets::TextConfig;
+use crate::theme::{Cue, Hues, Theme};
+use crate::thermostat::ThermostatConfig;
+use crate::trail::TrailConfig;
+use crate::vector_field::VectorFieldConfig;
+use crate::vorticity::VorticityConfig;
+
+/// Everything that carries over between runs, saved on exit and restored on launch.
+///
+/// Command line flags override the saved values, and are saved in turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window_size: [u32; 2],
+    pub particles: u32,
+    pub substeps: u32,
+    pub adaptive_quality: bool,
+    #[serde(deserialize_with = "checked::positive")]
+    pub frame_budget_ms: Option<f32>,
+    pub present_mode: PresentMode,
+    // Uncapped when 0, as the frame interval would be infinite
+    #[serde(deserialize_with = "checked::nonzero")]
+    pub fps: Option<u32>,
+    // Simulated frames per real second whatever the display's, when set
+    #[serde(deserialize_with = "checked::positive")]
+    pub tick_rate: Option<f32>,
+    // Degrees either side of its heading a boid sees, all round unless set
+    pub view_angle: Option<f32>,
+    // Seconds of history kept for rewinding, 0 to disable
+    pub rewind_seconds: f32,
+    // Start in presentation mode
+    pub present: bool,
+    // Show the frame time graph
+    pub frame_graph: bool,
+    // Draw the copies of a periodic domain around its edges
+    pub periodic_copies: bool,
+    // Show the whole domain in the corner, with the flock's density and the view on it
+    pub minimap: bool,
+    // Show how the particles' speeds are spread, in the corner
+    pub speed_histogram: bool,
+    // Windows across the domain, which the camera starts zoomed in to and is reset to
+    pub world_size: f32,
+    // Shade the density field as a lit liquid surface
+    pub fluid_shading: bool,
+    // Expose the density field from the histogram of its counts rather than a fixed scale
+    pub auto_exposure: bool,
+    // What the particles' colours show
+    pub color_mode: ColorMode,
+    // Overlay colours and the particles' palette, see `Theme`. Its background is kept in
+    // `background`, so colours set by hand survive
+    pub theme: Theme,
+    // Colours picked for what the particles' colours show, see `Hues`
+    pub hues: Hues,
+    // What shows speed and species besides colour, see `Cue`
+    pub cue: Cue,
+    // Language of the overlays and messages, `en`, `es` or the path of a locale file, see
+    // `locale::Messages`
+    pub locale: String,
+    // Pull of each finger on a touchscreen at a firm press, negative pushes particles away
+    pub touch_strength: f32,
+    // What a tablet's pen pressure scales when stirring and setting off fireworks
+    pub pen_pressure: PenPressure,
+    // Logical points across each particle whatever the display, a share of the domain
+    // unless set
+    pub particle_size: Option<f32>,
+    // OBJ mesh drawn for each particle in place of the triangle
+    pub mesh: Option<PathBuf>,
+    // Image or text file whose dark pixels or `#`s are walls, see `Level`
+    pub level: Option<PathBuf>,
+    // Tables have to come after plain values in TOML
+    pub simulation: SimVariant,
+    pub speed_limits: SpeedLimits,
+    // What I and the middle mouse button set off at the cursor
+    pub impulse: ImpulseConfig,
+    // What dragging with Shift held hands the particles
+    pub stir: StirConfig,
+    pub rule_weights: RuleWeights,
+    pub rule_radii: RuleRadii,
+    pub background: BackgroundConfig,
+    pub svg: SvgConfig,
+    // Off unless set
+    pub thermostat: Option<ThermostatConfig>,
+    // Molecular dynamics in place of the boids when set
+    pub lennard_jones: Option<LennardJonesConfig>,
+    // Slime mould agents in place of the boids when set
+    pub physarum: Option<PhysarumConfig>,
+    // Bursting fireworks in place of the boids when set
+    pub fireworks: Option<FireworksConfig>,
+    // Cloth or rope in place of the random particles when set
+    pub network: Option<NetworkConfig>,
+    // Position-based collisions and distance constraints after the step when set
+    pub pbd: Option<PbdConfig>,
+    pub noise: Option<NoiseConfig>,
+    pub wander: Option<WanderConfig>,
+    // Every particle's mass is 1 unless set
+    pub mass: Option<MassConfig>,
+    // Simulated in finer substeps than the rest of the domain when set
+    pub roi: Option<RegionOfInterest>,
+    // Carries the particles along a velocity field loaded from a file when set
+    pub vector_field: Option<VectorFieldConfig>,
+    // Spins up the swirls in the flow when set
+    pub vorticity: Option<VorticityConfig>,
+    // Slows particles down the faster they go when set
+    pub drag: Option<DragConfig>,
+    // A trail the particles leave and follow, like pheromones, when set
+    pub trail: Option<TrailConfig>,
+    // Reaction-diffusion seeded by the particles, drawn as the background, when set
+    pub reaction_diffusion: Option<ReactionDiffusionConfig>,
+    // Respawns particles after a while, fading and shrinking them as they age, when set
+    pub lifetime: Option<LifetimeConfig>,
+    // Gives particles an angle of their own, turned towards their heading, when set
+    pub orientation: Option<OrientationConfig>,
+    // Streams particles in at one edge and out at the opposite one when set
+    pub inflow: Option<InflowConfig>,
+    // Tires particles that go fast and rests them when spent, when set
+    pub stamina: Option<StaminaConfig>,
+    // Stops particles that have stayed slow and skips them until moved, when set
+    pub sleep: Option<SleepConfig>,
+    // Particles painting their paths onto a canvas that never fades, when set
+    pub canvas: Option<CanvasConfig>,
+    // Removes escaped particles after each frame when set
+    pub compaction: Option<CompactionConfig>,
+    // A goal travelling a route, which the flock steers towards, when set
+    pub goal: Option<GoalConfig>,
+    // Some boids lead the rest along the goal's route when set
+    pub leaders: Option<LeaderConfig>,
+    // Particles assembling into a string of text when set
+    pub text: Option<TextConfig>,
+    // Sources dyeing the particles that pass through them when set
+    pub emitters: Option<EmittersConfig>,
+    // An outbreak spreading through the flock, shown by colour, when set
+    pub contagion: Option<ContagionConfig>,
+    // Home bases the particles are divided between and called back to, when set
+    pub territory: Option<TerritoryConfig>,
+    // Reorders the particle buffer every so often when set
+    pub sort: Option<SortConfig>,
+    // Particles held still in regions, frozen and thawed with the mouse, when set
+    pub freeze: Option<FreezeConfig>,
+    // An attractor steered with a gamepad when set
+    pub gamepad: Option<GamepadConfig>,
+    // Impulses set off on the beats of live audio when set
+    pub beats: Option<BeatConfig>,
+    // Suspends while hidden and restarts when frames stay slow, for unattended machines,
+    // when set
+    pub installation: Option<InstallationConfig>,
+    // The view to start in, and for presets to switch to, when set
+    pub camera: Option<CameraState>,
+    // Arrays of tables go last, and are left out when empty, as an empty one is written as a
+    // plain value, which can't come after tables
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attractors: Vec<Attractor>,
+    // Particles drawn their own way, each species its share, all drawn alike when empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub species: Vec<SpeciesConfig>,
+    // Run over the rendered frame in order, see `PostFx`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post_fx: Vec<Effect>,
+    // Parameters swung over time, see `Modulator`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modulators: Vec<Modulator>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            window_size: [1024, 768],
+            particles: 50_000,
+            substeps: 1,
+            adaptive_quality: false,
+            frame_budget_ms: None,
+            present_mode: PresentMode::Fifo,
+            fps: None,
+            tick_rate: None,
+            particle_size: None,
+            view_angle: None,
+            rewind_seconds: 10.0,
+            present: false,
+            frame_graph: false,
+            periodic_copies: false,
+            minimap: false,
+            speed_histogram: false,
+            world_size: 1.0,
+            fluid_shading: false,
+            auto_exposure: false,
+            color_mode: ColorMode::Velocity,
+            theme: Theme::Dark,
+            hues: Hues::Wheel,
+            cue: Cue::None,
+            locale: "en".to_owned(),
+            touch_strength: 0.00005,
+            pen_pressure: PenPressure::default(),
+            mesh: None,
+            level: None,
+            simulation: SimVariant::default(),
+            speed_limits: SpeedLimits::default(),
+            impulse: ImpulseConfig::default(),
+            stir: StirConfig::default(),
+            rule_weights: RuleWeights::default(),
+            rule_radii: RuleRadii::default(),
+            background: BackgroundConfig {
+                kind: BackgroundKind::Solid,
+                color_top: [0x00, 0x00, 0x00],
+                color_bottom: [0x1a, 0x1a, 0x2e],
+                image: None,
+            },
+            svg: SvgConfig::default(),
+            thermostat: None,
+            lennard_jones: None,
+            physarum: None,
+            fireworks: None,
+            network: None,
+            pbd: None,
+            noise: None,
+            wander: None,
+            mass: None,
+            roi: None,
+            vector_field: None,
+            vorticity: None,
+            drag: None,
+            trail: None,
+            reaction_diffusion: None,
+            lifetime: None,
+            orientation: None,
+            inflow: None,
+            stamina: None,
+            sleep: None,
+            canvas: None,
+            compaction: None,
+            goal: None,
+            leaders: None,
+            text: None,
+            emitters: None,
+            contagion: None,
+            territory: None,
+            sort: None,
+            freeze: None,
+            gamepad: None,
+            beats: None,
+            installation: None,
+            camera: None,
+            attractors: Vec::new(),
+            species: Vec::new(),
+            post_fx: Vec::new(),
+            modulators: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .map(|dir| dir.join("particle-nannou"))
+            .unwrap_or_default()
+            .join("settings.toml")
+    }
+
+    /// Load saved settings, falling back to the defaults if there are none or they're unreadable.
+    pub fn load(path: &Path) -> Self {
+        if !path.exists() {
+            return Settings::default();
+        }
+        match nannou::io::load_from_toml(path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("Ignoring unreadable settings {}: {}", path.display(), err);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        // nannou's safe_file_save doesn't create missing parent directories correctly
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if let Err(err) = fs::create_dir_all(dir) {
+                error!(
+                    "Failed to create settings directory {}: {}",
+                    dir.display(),
+                    err
+                );
+                return;
+            }
+        }
+        if let Err(err) = nannou::io::save_to_toml(path, self) {
+            error!("Failed to save settings {}: {}", path.display(), err);
+        }
+    }
+
+    /// The parameters `parameters::PARAMETERS` names, as these start with.
+    pub fn boids_params(&self) -> BoidsParams {
+        BoidsParams {
+            weights: self.rule_weights,
+            radii: self.rule_radii,
+            speed_limits: self.speed_limits,
+            view_angle: self.view_angle,
+            variant: self.simulation,
+        }
+    }
+
+    pub fn set_boids_params(&mut self, params: &BoidsParams) {
+        self.rule_weights = params.weights;
+        self.rule_radii = params.radii;
+        self.speed_limits = params.speed_limits;
+        self.view_angle = params.view_angle;
+        self.simulation = params.variant;
+    }
+
+    pub fn frame_budget(&self) -> Duration {
+        match (self.frame_budget_ms, self.fps) {
+            (Some(ms), _) => Duration::from_secs_f32(ms / 1000.0),
+            (None, Some(fps)) => Duration::from_secs_f64(1.0 / fps as f64),
+            (None, None) => Duration::from_secs_f64(1.0 / 60.0),
+        }
+    }
+}
+
+/// Serialize `[u8; 3]` colours as "#rrggbb" strings so the file stays hand-editable.
+pub mod hex_color {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &[u8; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b] = color;
+        serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        crate::cli::parse_hex_color(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Settings that would fail at startup if hand-edited out of range, left unset instead.
+mod checked {
+    use serde::{Deserialize, Deserializer};
+    use tracing::warn;
+
+    /// A rate or duration, which has to be finite and over 0.
+    pub fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+        let value = Option::<f32>::deserialize(deserializer)?;
+        Ok(value.filter(|&value| {
+            let valid = value.is_finite() && value > 0.0;
+            if !valid {
+                warn!("Ignoring a setting of {value}, expected a positive number");
+            }
+            valid
+        }))
+    }
+
+    /// A cap, where 0 means uncapped.
+    pub fn nonzero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+        Ok(Option::<u32>::deserialize(deserializer)?.filter(|&value| value > 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("particle-nannou-{}-{name}", std::process::id()))
+    }
+
+    // As JSON, since the settings hold floats and don't compare directly
+    fn json(settings: &Settings) -> serde_json::Value {
+        serde_json::to_value(settings).unwrap()
+    }
+
+    // Every optional table set, as TOML needs plain values written before tables
+    fn everything() -> Settings {
+        Settings {
+            frame_budget_ms: Some(12.0),
+            fps: Some(30),
+            tick_rate: Some(120.0),
+            particle_size: Some(6.0),
+            theme: Theme::Paper,
+            hues: Hues::Safe,
+            cue: Cue::Shape,
+            locale: "es".to_owned(),
+            view_angle: Some(120.0),
+            mesh: Some(PathBuf::from("boid.obj")),
+            level: Some(PathBuf::from("level.json")),
+            thermostat: Some(ThermostatConfig::new(0.003)),
+            lennard_jones: Some(Default::default()),
+            physarum: Some(Default::default()),
+            fireworks: Some(Default::default()),
+            network: Some(Default::default()),
+            pbd: Some(Default::default()),
+            noise: Some(Default::default()),
+            wander: Some(Default::default()),
+            mass: Some(Default::default()),
+            roi: Some(Default::default()),
+            vector_field: Some(Default::default()),
+            vorticity: Some(Default::default()),
+            drag: Some(Default::default()),
+            trail: Some(Default::default()),
+            reaction_diffusion: Some(Default::default()),
+            lifetime: Some(Default::default()),
+            orientation: Some(Default::default()),
+            inflow: Some(Default::default()),
+            stamina: Some(Default::default()),
+            sleep: Some(Default::default()),
+            canvas: Some(Default::default()),
+            compaction: Some(Default::default()),
+            goal: Some(Default::default()),
+            leaders: Some(Default::default()),
+            text: Some(Default::default()),
+            emitters: Some(EmittersConfig {
+                positions: vec![[-0.5, 0.0], [0.5, 0.0]],
+                ..Default::default()
+            }),
+            contagion: Some(Default::default()),
+            territory: Some(TerritoryConfig {
+                bases: vec![[0.0, 0.5]],
+                ..Default::default()
+            }),
+            sort: Some(Default::default()),
+            freeze: Some(Default::default()),
+            gamepad: Some(Default::default()),
+            beats: Some(Default::default()),
+            installation: Some(Default::default()),
+            camera: Some(Default::default()),
+            attractors: vec![crate::forces::parse_attractor("0.5,0,0.001").unwrap()],
+            species: vec![SpeciesConfig::default(), SpeciesConfig::default()],
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        for settings in [Settings::default(), everything()] {
+            let path = temp_path("settings.toml");
+            settings.save(&path);
+            let loaded = nannou::io::load_from_toml::<_, Settings>(&path);
+            let _ = fs::remove_file(&path);
+            assert_eq!(json(&loaded.unwrap()), json(&settings));
+        }
+    }
+
+    // Empty lists of tables written after the tables used to fail the whole save, see the
+    // attributes on `Settings::attractors` and the lists after it
+    #[test]
+    fn saves_empty_lists_after_tables() {
+        let settings = Settings {
+            attractors: Vec::new(),
+            species: Vec::new(),
+            post_fx: Vec::new(),
+            modulators: Vec::new(),
+            ..everything()
+        };
+        let path = temp_path("empty-lists.toml");
+        let saved = nannou::io::save_to_toml(&path, &settings);
+        let loaded = nannou::io::load_from_toml::<_, Settings>(&path);
+        let _ = fs::remove_file(&path);
+        assert!(saved.is_ok(), "{:?}", saved.err());
+        assert_eq!(json(&loaded.unwrap()), json(&settings));
+    }
+
+    #[test]
+    fn fills_missing_settings_with_defaults() {
+        let path = temp_path("partial.toml");
+        fs::write(&path, "particles = 500\n\n[speed_limits]\nmax = 0.01\n").unwrap();
+        let loaded = Settings::load(&path);
+        let _ = fs::remove_file(&path);
+        let expected = Settings {
+            particles: 500,
+            speed_limits: SpeedLimits {
+                max: 0.01,
+                ..Default::default()
+            },
+            ..Settings::default()
+        };
+        assert_eq!(json(&loaded), json(&expected));
+    }
+
+    #[test]
+    fn falls_back_to_defaults_when_unreadable() {
+        let path = temp_path("broken.toml");
+        fs::write(&path, "particles = \"many\"").unwrap();
+        let loaded = Settings::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(json(&loaded), json(&Settings::default()));
+        assert_eq!(
+            json(&Settings::load(&temp_path("missing.toml"))),
+            json(&Settings::default())
+        );
+    }
+
+    #[test]
+    fn ignores_budgets_that_would_fail_at_startup() {
+        for budget in ["0.0", "-5.0", "nan", "inf"] {
+            let path = temp_path("budget.toml");
+            fs::write(
+                &path,
+                format!("particles = 500\nframe_budget_ms = {budget}\n"),
+            )
+            .unwrap();
+            let loaded = Settings::load(&path);
+            let _ = fs::remove_file(&path);
+            assert_eq!(loaded.particles, 500, "{budget}");
+            assert_eq!(loaded.frame_budget_ms, None, "{budget}");
+            assert_eq!(loaded.frame_budget(), Duration::from_secs_f64(1.0 / 60.0));
+        }
+        let path = temp_path("budget.toml");
+        fs::write(&path, "frame_budget_ms = 12.5\n").unwrap();
+        let loaded = Settings::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.frame_budget_ms, Some(12.5));
+    }
+
+    #[test]
+    fn runs_uncapped_with_an_fps_of_0() {
+        let path = temp_path("fps.toml");
+        fs::write(&path, "particles = 500\nfps = 0\n").unwrap();
+        let loaded = Settings::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.particles, 500);
+        assert_eq!(loaded.fps, None);
+        assert_eq!(loaded.frame_budget(), Duration::from_secs_f64(1.0 / 60.0));
+    }
+
+    #[test]
+    fn ignores_tick_rates_that_would_fail_at_startup() {
+        for rate in ["0", "-60.0", "nan"] {
+            let path = temp_path("tick-rate.toml");
+            fs::write(&path, format!("particles = 500\ntick_rate = {rate}\n")).unwrap();
+            let loaded = Settings::load(&path);
+            let _ = fs::remove_file(&path);
+            // Only the rate is left out
+            assert_eq!(loaded.particles, 500, "{rate}");
+            assert_eq!(loaded.tick_rate, None, "{rate}");
+        }
+    }
+
+    #[test]
+    fn frame_budget_prefers_the_budget_then_the_fps_cap() {
+        let settings = Settings {
+            frame_budget_ms: Some(20.0),
+            fps: Some(30),
+            ..Settings::default()
+        };
+        assert_eq!(settings.frame_budget(), Duration::from_millis(20));
+        let settings = Settings {
+            frame_budget_ms: None,
+            ..settings
+        };
+        assert_eq!(settings.frame_budget(), Duration::from_secs_f64(1.0 / 30.0));
+        let settings = Settings {
+            fps: None,
+            ..settings
+        };
+        assert_eq!(settings.frame_budget(), Duration::from_secs_f64(1.0 / 60.0));
+    }
+}
@@ -0,0 +1,219 @@
+use nannou::prelude::*;
+
+use crate::locale::text;
+use crate::shader_watch;
+
+const VISIBLE_LINES: usize = 36;
+// Lines moved by Page Up and Page Down
+const PAGE: usize = 20;
+const INDENT: &str = "    ";
+const MARGIN: f32 = 10.0;
+const LINE_HEIGHT: f32 = 16.0;
+// Share of the window's width the panel covers, from the left
+const WIDTH: f32 = 0.6;
+
+/// A plain text editor over the left of the window, toggled with F2, for live coding the
+/// boids shader: it opens on the source being simulated and Ctrl+Enter compiles it. When
+/// that fails the error is shown under the text and the last shader that compiled keeps
+/// running, as with --watch-shader.
+#[derive(Default)]
+pub struct ShaderEditor {
+    pub open: bool,
+    lines: Vec<String>,
+    // Line and character in it
+    cursor: (usize, usize),
+    // First line shown
+    scroll: usize,
+    // Changed since it was opened or last compiled
+    edited: bool,
+    error: Option<String>,
+}
+
+impl ShaderEditor {
+    /// Show the editor, with `source` unless there are edits still to compile.
+    pub fn show(&mut self, source: &str) {
+        self.open = true;
+        if !self.edited {
+            self.lines = source.lines().map(str::to_owned).collect();
+            if self.lines.is_empty() {
+                self.lines.push(String::new());
+            }
+            self.cursor.0 = self.cursor.0.min(self.lines.len() - 1);
+            self.cursor.1 = self.cursor.1.min(self.line_len());
+            self.follow_cursor();
+        }
+    }
+
+    pub fn source(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Record how the source from `source` compiled.
+    pub fn set_result(&mut self, result: Result<(), String>) {
+        self.edited = result.is_err();
+        self.error = result.err();
+    }
+
+    /// Type a character at the cursor. Control characters are left to `key`.
+    pub fn type_char(&mut self, c: char) {
+        if c.is_control() {
+            return;
+        }
+        let (line, column) = self.cursor;
+        let at = byte_index(&self.lines[line], column);
+        self.lines[line].insert(at, c);
+        self.cursor.1 += 1;
+        self.edited = true;
+    }
+
+    /// Move the cursor or edit the text for keys that don't type a character.
+    pub fn key(&mut self, key: Key) {
+        let (line, column) = self.cursor;
+        match key {
+            Key::Left if column > 0 => self.cursor.1 -= 1,
+            Key::Left if line > 0 => self.cursor = (line - 1, self.lines[line - 1].chars().count()),
+            Key::Right if column < self.line_len() => self.cursor.1 += 1,
+            Key::Right if line + 1 < self.lines.len() => self.cursor = (line + 1, 0),
+            Key::Up => self.move_lines(-1),
+            Key::Down => self.move_lines(1),
+            Key::PageUp => self.move_lines(-(PAGE as isize)),
+            Key::PageDown => self.move_lines(PAGE as isize),
+            Key::Home => self.cursor.1 = 0,
+            Key::End => self.cursor.1 = self.line_len(),
+            Key::Back if column > 0 => {
+                let at = byte_index(&self.lines[line], column - 1);
+                self.lines[line].remove(at);
+                self.cursor.1 -= 1;
+                self.edited = true;
+            }
+            // Join onto the line above
+            Key::Back if line > 0 => {
+                let rest = self.lines.remove(line);
+                let above = &mut self.lines[line - 1];
+                self.cursor = (line - 1, above.chars().count());
+                above.push_str(&rest);
+                self.edited = true;
+            }
+            Key::Delete if column < self.line_len() => {
+                let at = byte_index(&self.lines[line], column);
+                self.lines[line].remove(at);
+                self.edited = true;
+            }
+            Key::Delete if line + 1 < self.lines.len() => {
+                let below = self.lines.remove(line + 1);
+                self.lines[line].push_str(&below);
+                self.edited = true;
+            }
+            // Split the line, keeping its indentation
+            Key::Return | Key::NumpadEnter => {
+                let at = byte_index(&self.lines[line], column);
+                let rest = self.lines[line].split_off(at);
+                let indent: String = self.lines[line].chars().take_while(|c| *c == ' ').collect();
+                self.cursor = (line + 1, indent.chars().count());
+                self.lines.insert(line + 1, indent + &rest);
+                self.edited = true;
+            }
+            Key::Tab => {
+                let at = byte_index(&self.lines[line], column);
+                self.lines[line].insert_str(at, INDENT);
+                self.cursor.1 += INDENT.len();
+                self.edited = true;
+            }
+            _ => return,
+        }
+        self.follow_cursor();
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.cursor.0].chars().count()
+    }
+
+    fn move_lines(&mut self, lines: isize) {
+        let last = self.lines.len() - 1;
+        self.cursor.0 = self.cursor.0.saturating_add_signed(lines).min(last);
+        self.cursor.1 = self.cursor.1.min(self.line_len());
+    }
+
+    // Scroll so the cursor's line is shown
+    fn follow_cursor(&mut self) {
+        let line = self.cursor.0;
+        if line < self.scroll {
+            self.scroll = line;
+        } else if line >= self.scroll + VISIBLE_LINES {
+            self.scroll = line + 1 - VISIBLE_LINES;
+        }
+    }
+
+    pub fn draw(&self, draw: &Draw, window: Rect) {
+        let error = self.error.as_deref().map(shader_watch::naga_message);
+        let error_lines = error.map_or(0, |error| error.lines().count());
+        let rows = VISIBLE_LINES + 1 + error_lines;
+        let panel = Rect::from_w_h(
+            window.w() * WIDTH,
+            (rows as f32 * LINE_HEIGHT + 2.0 * MARGIN).min(window.h()),
+        )
+        .top_left_of(window);
+        draw.rect()
+            .xy(panel.xy())
+            .wh(panel.wh())
+            .color(rgba(0.02, 0.02, 0.05, 0.85));
+        let text_area = panel.pad(MARGIN);
+        let row = |index: usize| {
+            Rect::from_w_h(text_area.w(), LINE_HEIGHT)
+                .top_left_of(text_area)
+                .shift_y(-LINE_HEIGHT * index as f32)
+        };
+
+        let end = (self.scroll + VISIBLE_LINES).min(self.lines.len());
+        for (index, number) in (self.scroll..end).enumerate() {
+            let mut line = self.lines[number].clone();
+            // A bar for the cursor, as the font isn't monospaced
+            if number == self.cursor.0 {
+                line.insert(byte_index(&line, self.cursor.1), '|');
+            }
+            let rect = row(index);
+            draw.text(&format!("{:>4}  {}", number + 1, line))
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .font_size(12)
+                .left_justify()
+                .no_line_wrap()
+                .color(if number == self.cursor.0 {
+                    WHITE
+                } else {
+                    LIGHTGRAY
+                });
+        }
+
+        let status = match (error, self.edited) {
+            (Some(_), _) => &text().editor_broken,
+            (None, true) => &text().editor_edited,
+            (None, false) => &text().editor_help,
+        };
+        let rect = row(VISIBLE_LINES);
+        draw.text(status)
+            .xy(rect.xy())
+            .wh(rect.wh())
+            .font_size(12)
+            .left_justify()
+            .no_line_wrap()
+            .color(SKYBLUE);
+        for (index, line) in error.into_iter().flat_map(str::lines).enumerate() {
+            let rect = row(VISIBLE_LINES + 1 + index);
+            draw.text(line)
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .font_size(12)
+                .left_justify()
+                .no_line_wrap()
+                .color(rgb(1.0, 0.45, 0.4));
+        }
+    }
+}
+
+// Where the `column`th character of `line` starts
+fn byte_index(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map_or(line.len(), |(index, _)| index)
+}
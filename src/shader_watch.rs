@@ -0,0 +1,106 @@
+use nannou::prelude::*;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::warn;
+
+// How often the file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MARGIN: f32 = 10.0;
+
+/// The part of a shader error from `diagnostics::try_shader` worth showing. wgpu's own lines
+/// about where it came from say little here, naga's message starts at the shader's name.
+pub fn naga_message(error: &str) -> &str {
+    error
+        .find("Shader '")
+        .map_or(error, |start| &error[start..])
+        .trim_end()
+}
+
+/// A shader file reloaded whenever it's saved, and the error from the last reload that
+/// failed, shown over the window until a fixed copy is saved.
+pub struct ShaderWatch {
+    pub path: PathBuf,
+    // When the loaded copy was saved, None until the first load
+    modified: Option<SystemTime>,
+    polled: Option<Instant>,
+    error: Option<String>,
+}
+
+impl ShaderWatch {
+    pub fn new(path: PathBuf) -> Self {
+        ShaderWatch {
+            path,
+            modified: None,
+            polled: None,
+            error: None,
+        }
+    }
+
+    /// The file's source when it's been saved since last asked, first off straight away.
+    pub fn poll(&mut self) -> Option<String> {
+        if self
+            .polled
+            .is_some_and(|polled| polled.elapsed() < POLL_INTERVAL)
+        {
+            return None;
+        }
+        self.polled = Some(Instant::now());
+        match self.read() {
+            Ok(source) => source,
+            Err(err) => {
+                let error = format!("Failed to read {}: {}", self.path.display(), err);
+                // Some editors briefly remove the file while saving, so only report it once
+                if self.error.as_ref() != Some(&error) {
+                    warn!("{}", error);
+                    self.error = Some(error);
+                }
+                None
+            }
+        }
+    }
+
+    fn read(&mut self) -> io::Result<Option<String>> {
+        let modified = fs::metadata(&self.path)?.modified()?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+        let source = fs::read_to_string(&self.path)?;
+        self.modified = Some(modified);
+        Ok(Some(source))
+    }
+
+    /// Record how the last source from `poll` compiled.
+    pub fn set_result(&mut self, result: Result<(), String>) {
+        self.error = result.err();
+    }
+
+    pub fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The error from the last reload, across the top of the window.
+    pub fn draw(&self, draw: &Draw, window: Rect) {
+        let Some(error) = &self.error else {
+            return;
+        };
+        let text = format!("{}\n{}", self.path.display(), naga_message(error));
+        let lines = text.lines().count() as f32;
+        let area = window.pad(MARGIN);
+        let panel = Rect::from_w_h(area.w(), lines * 16.0 + 2.0 * MARGIN).mid_top_of(area);
+        draw.rect()
+            .xy(panel.xy())
+            .wh(panel.wh())
+            .color(rgba(0.35, 0.02, 0.02, 0.85));
+        draw.text(&text)
+            .xy(panel.xy())
+            .wh(panel.pad(MARGIN).wh())
+            .font_size(12)
+            .line_spacing(2.0)
+            .left_justify()
+            .align_text_top()
+            .no_line_wrap()
+            .color(WHITE);
+    }
+}
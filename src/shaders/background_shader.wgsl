@@ -0,0 +1,81 @@
+#include "noise.wgsl"
+
+struct BackgroundUniforms {
+    color_top: vec4<f32>,
+    color_bottom: vec4<f32>,
+    mode: u32,
+    time: f32,
+    // Screen aspect divided by image aspect, used to "cover" fit the image
+    aspect: f32,
+    // Seconds after which the noise repeats exactly, 0 to drift forever
+    period: f32,
+    // xy offset and zw scale of the visible part in uv, for tiled rendering
+    view: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> bg: BackgroundUniforms;
+@group(0) @binding(1) var bg_texture: texture_2d<f32>;
+@group(0) @binding(2) var bg_sampler: sampler;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    // uv.y = 0 at the bottom of the screen, 1 at the top
+    output.uv = uv;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = bg.view.xy + input.uv * bg.view.zw;
+
+    switch (bg.mode) {
+        // Vertical gradient
+        case 1u: {
+            return mix(bg.color_bottom, bg.color_top, uv.y);
+        }
+        // Image
+        case 2u: {
+            // Scale around the centre so the image covers the screen without stretching
+            var scale = vec2<f32>(1.0, 1.0);
+            if bg.aspect > 1.0 {
+                scale.y = 1.0 / bg.aspect;
+            } else {
+                scale.x = bg.aspect;
+            }
+            let image_uv = (vec2<f32>(uv.x, 1.0 - uv.y) - 0.5) * scale + 0.5;
+            return textureSample(bg_texture, bg_sampler, image_uv);
+        }
+        // Noise field
+        case 3u: {
+            // Domain-warped fBm drifting slowly over time
+            let p = uv * 3.0;
+            var drift = vec2<f32>(bg.time * 0.04, bg.time * 0.05);
+            if bg.period > 0.0 {
+                // Drift around a circle instead, at about the same speed, to come back to the start
+                let tau = 6.2831853;
+                let angle = tau * bg.time / bg.period;
+                drift = vec2<f32>(cos(angle), sin(angle)) * bg.period * 0.05 / tau;
+            }
+            let warp = vec2<f32>(
+                fbm(p + vec2<f32>(0.0, drift.y), 5u),
+                fbm(p + vec2<f32>(5.2, 1.3) - vec2<f32>(drift.x, 0.0), 5u)
+            );
+            let n = fbm(p + warp * 2.0, 5u);
+            return mix(bg.color_bottom, bg.color_top, n);
+        }
+        // Solid colour
+        default: {
+            return bg.color_top;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+#include "common.wgsl"
+
+struct CanvasParams {
+    particle_count: u32,
+    // Fraction of a cell each particle covers
+    opacity: f32,
+    // Cells along each side
+    size: u32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: CanvasParams;
+// Red, green and blue summed in fixed point, then the count, of the particles in each cell
+// this frame, row by row from the bottom of the domain. Emptied again by `blend`
+@group(0) @binding(2) var<storage, read_write> deposits: array<atomic<u32>>;
+// Linear colour and how much of the cell it covers
+@group(0) @binding(3) var<storage, read_write> paint: array<vec4<f32>>;
+
+// Colours are summed as integers, so up to 4 million particles of white fit in a cell
+const FIXED_POINT: f32 = 1024.0;
+
+// As `velocity_color` in vertex_shader.wgsl, so the paint matches the velocity colour mode
+fn velocity_color(velocity: vec2<f32>) -> vec3<f32> {
+    let speed = length(velocity);
+    let direction = select(vec2<f32>(1.0, 0.0), velocity / speed, speed > 0.00001);
+    return vec3<f32>(abs(direction.x), abs(direction.y), speed * 100.0);
+}
+
+@compute @workgroup_size(256)
+fn deposit(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let p = particles[index];
+    let cell = vec2<i32>(floor((p.position * 0.5 + 0.5) * f32(params.size)));
+    if any(cell < vec2<i32>(0)) || any(cell >= vec2<i32>(i32(params.size))) {
+        return;
+    }
+    let sums = (u32(cell.y) * params.size + u32(cell.x)) * 4u;
+    let color = clamp(velocity_color(p.velocity), vec3<f32>(0.0), vec3<f32>(1.0));
+    let amounts = vec3<u32>(color * FIXED_POINT);
+    atomicAdd(&deposits[sums], amounts.r);
+    atomicAdd(&deposits[sums + 1u], amounts.g);
+    atomicAdd(&deposits[sums + 2u], amounts.b);
+    atomicAdd(&deposits[sums + 3u], 1u);
+}
+
+// Lay this frame's average colour over each cell's paint, covering more of it the more
+// particles passed, then empty the sums for the next frame
+@compute @workgroup_size(256)
+fn blend(@builtin(global_invocation_id) id: vec3<u32>) {
+    let cell = id.x;
+    if cell >= params.size * params.size {
+        return;
+    }
+    let sums = cell * 4u;
+    let count = atomicExchange(&deposits[sums + 3u], 0u);
+    let sum = vec3<f32>(
+        f32(atomicExchange(&deposits[sums], 0u)),
+        f32(atomicExchange(&deposits[sums + 1u], 0u)),
+        f32(atomicExchange(&deposits[sums + 2u], 0u)),
+    );
+    if count == 0u {
+        return;
+    }
+    let color = sum / (FIXED_POINT * f32(count));
+    let cover = 1.0 - pow(1.0 - params.opacity, f32(count));
+    let old = paint[cell];
+    let alpha = old.a + (1.0 - old.a) * cover;
+    let rgb = (old.rgb * old.a * (1.0 - cover) + color * cover) / max(alpha, 1e-6);
+    paint[cell] = vec4<f32>(rgb, alpha);
+}
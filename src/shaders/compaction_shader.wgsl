@@ -0,0 +1,83 @@
+#include "common.wgsl"
+
+struct CompactionParams {
+    bounds: f32,
+};
+
+// Draw and dispatch arguments for the survivors, in the layouts draw_indirect and
+// dispatch_workgroups_indirect read
+struct Counts {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+    workgroups_x: u32,
+    workgroups_y: u32,
+    workgroups_z: u32,
+    alive: u32,
+};
+
+@group(0) @binding(0) var<storage, read> particles_in: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+// 1 per surviving particle, then scanned in place into each survivor's new index
+@group(0) @binding(2) var<storage, read_write> offsets: array<u32>;
+@group(0) @binding(3) var<storage, read_write> particles_out: array<Particle>;
+@group(0) @binding(4) var<storage, read_write> counts: Counts;
+@group(0) @binding(5) var<uniform> compaction: CompactionParams;
+
+// Must match the Rust side
+const WORKGROUP_SIZE: u32 = 256u;
+const VERTEX_COUNT: u32 = 3u;
+
+// All exponent bits set means infinite or NaN. Checked on the bits, since comparisons
+// with NaN can't be relied on across drivers
+fn finite(x: f32) -> bool {
+    return (bitcast<u32>(x) & 0x7f800000u) != 0x7f800000u;
+}
+
+// Particles that blew up are removed too
+fn alive(index: u32) -> bool {
+    let position = particles_in[index].position;
+    return finite(position.x) && finite(position.y)
+        && abs(position.x) <= compaction.bounds && abs(position.y) <= compaction.bounds;
+}
+
+@compute @workgroup_size(256)
+fn flag(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    offsets[index] = select(0u, 1u, alive(index));
+}
+
+@compute @workgroup_size(256)
+fn scatter(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    let count = params.particle_count;
+    if index >= count {
+        return;
+    }
+    let last = count - 1u;
+    let total = offsets[last] + select(0u, 1u, alive(last));
+
+    // Survivors move to the front in order, the removed ones after them, so nothing is lost
+    // if the count grows again
+    let offset = offsets[index];
+    if alive(index) {
+        particles_out[offset] = particles_in[index];
+    } else {
+        particles_out[total + index - offset] = particles_in[index];
+    }
+
+    if index == last {
+        counts.vertex_count = VERTEX_COUNT;
+        counts.instance_count = total;
+        counts.first_vertex = 0u;
+        counts.first_instance = 0u;
+        counts.workgroups_x = (total + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+        counts.workgroups_y = 1u;
+        counts.workgroups_z = 1u;
+        counts.alive = total;
+    }
+}
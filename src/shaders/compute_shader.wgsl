@@ -1,75 +1,256 @@
-struct Particle {
-    position: vec2<f32>,
-    velocity: vec2<f32>,
-};
+#include "common.wgsl"
+#include "flags.wgsl"
+#include "mass.wgsl"
+#include "leaders.wgsl"
+
+// How particles are stored, see `ParticleLayout`. Both blocks are replaced by
+// sim_variant.rs, so keep them exactly as they are.
+// Precision of each position and velocity
+alias Component = vec2<f32>;
+fn unpack(component: Component) -> vec2<f32> { return component; }
+fn pack(v: vec2<f32>) -> Component { return v; }
+// Interleaved or in separate arrays
+struct StoredParticle { position: Component, velocity: Component };
+fn load(index: u32) -> Particle {
+    let stored = particles_in[index];
+    return Particle(unpack(stored.position), unpack(stored.velocity));
+}
+fn store(index: u32, p: Particle) {
+    particles_out[index] = StoredParticle(pack(p.position), pack(p.velocity));
+}
 
+// Ping-pong buffers: every particle reads the same previous state regardless of dispatch
+// order, which keeps steps deterministic
+@group(0) @binding(0) var<storage, read> particles_in: array<StoredParticle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read_write> particles_out: array<StoredParticle>;
+// Momentum transferred to the walls, in units of 1 / IMPULSE_SCALE, when PISTON is on
+@group(0) @binding(3) var<storage, read_write> wall_impulse: atomic<u32>;
+// Neighbours each particle saw this step, for colouring by crowding
+@group(0) @binding(4) var<storage, read_write> neighbor_counts: array<u32>;
+// Asleep particles are left where they are, see `Sleep`
+@group(0) @binding(5) var<storage, read> flags: array<u32>;
 
-@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+// Specialization constants, rewritten per pipeline variant by sim_variant.rs along with the
+// @workgroup_size below. Keep each on its own line in exactly this form.
+// 0 = circular neighbourhood, 1 = square (cheaper, no sqrt for the range test)
+const NEIGHBORHOOD: u32 = 0u;
+const ALIGNMENT: bool = true;
+const COHESION: bool = true;
+const SEPARATION: bool = true;
+// Closed box with a movable right wall, measuring wall impulses for the pressure gauge
+const PISTON: bool = false;
+// Stage neighbours through shared memory in tiles of TILE_SIZE, the workgroup size
+const TILED: bool = false;
+const TILE_SIZE: u32 = 256u;
 
-const MAX_SPEED: f32 = 0.005;
 const BOUNDARY_LIMIT: f32 = 1.0;
-const PERCEPTION_RADIUS: f32 = 0.09;
+// Must match IMPULSE_SCALE in pressure.rs
+const IMPULSE_SCALE: f32 = 1000000.0;
 
-@compute @workgroup_size(256)
-fn simulate_boids(@builtin(global_invocation_id) id: vec3<u32>) {
-    let index: u32 = id.x;
-    if index >= arrayLength(&particles) {
-        return;
+// Advanced per substep, finer inside the region of interest
+var<private> dt: f32;
+
+// See `RegionOfInterest`
+fn in_region(position: vec2<f32>) -> bool {
+    return params.roi != 0u && all(position >= params.roi_min) && all(position <= params.roi_max);
+}
+
+// Whatever is over the top speed or under the cruising speed dies away by `speed_decay` each
+// frame, all at once at 1, so steering can push a little past the limits instead of
+// piling every boid up exactly at them
+fn limit_speed(speed: f32) -> f32 {
+    let keep = pow(1.0 - clamp(params.speed_decay, 0.0, 1.0), dt);
+    if speed > params.max_speed {
+        return params.max_speed + (speed - params.max_speed) * keep;
     }
+    if speed < params.min_speed {
+        return params.min_speed - (params.min_speed - speed) * keep;
+    }
+    return speed;
+}
 
-    var p = particles[index]; // Current boid
+// Sums over the neighbours seen so far
+struct Flock {
+    alignment: vec2<f32>,
+    cohesion: vec2<f32>,
+    separation: vec2<f32>,
+    // Within any rule's radius
+    total: u32,
+    // Within each rule's own: alignment, cohesion, separation
+    counts: vec3<u32>,
+    // What `cohesion` is weighted by in all, leaders counting for more
+    cohesion_weight: f32,
+};
 
-    var alignment: vec2<f32> = vec2<f32>(0.0, 0.0);
-    var cohesion: vec2<f32> = vec2<f32>(0.0, 0.0);
-    var separation: vec2<f32> = vec2<f32>(0.0, 0.0);
+// Whether `offset` is within `radius`, in the shape NEIGHBORHOOD picks
+fn within(offset: vec2<f32>, radius: f32) -> bool {
+    if NEIGHBORHOOD == 1u {
+        return abs(offset.x) < radius && abs(offset.y) < radius;
+    }
+    return dot(offset, offset) < radius * radius;
+}
+
+// Whether a neighbour at `offset` from `p` is outside the cone it sees, see `view_cos`
+fn behind(p: Particle, offset: vec2<f32>) -> bool {
+    if params.view_cos <= -1.0 {
+        return false;
+    }
+    // The way to the neighbour is -offset
+    return -dot(offset, p.velocity) < params.view_cos * length(offset) * length(p.velocity);
+}
 
-    var total: u32 = 0u;
+fn visit(flock: ptr<function, Flock>, p: Particle, neighbor: Particle, neighbor_index: u32) {
+    let offset = p.position - neighbor.position;
+    let reach = max(params.alignment_radius, max(params.cohesion_radius, params.separation_radius));
+    if !within(offset, reach) || behind(p, offset) {
+        return;
+    }
+    if ALIGNMENT && within(offset, params.alignment_radius) {
+        (*flock).alignment += neighbor.velocity;
+        (*flock).counts.x += 1u;
+    }
 
-    for (var k: u32 = 0u; k < arrayLength(&particles); k = k + 1u) {
-        if k == index {
-            continue;
+    if SEPARATION && within(offset, params.separation_radius) {
+        let distance = length(offset);
+        if distance > 0.01 {
+            (*flock).separation += offset / (distance * distance); // Inverse square falloff
         }
+        (*flock).counts.z += 1u;
+    }
 
-        let neighbor = particles[k];
-        let distance = length(p.position - neighbor.position);
-        if abs(distance) < PERCEPTION_RADIUS {
-            alignment += neighbor.velocity;
+    if COHESION && within(offset, params.cohesion_radius) {
+        let weight = select(1.0, params.leader_weight, is_leader(neighbor_index, params));
+        (*flock).cohesion += neighbor.position * weight;
+        (*flock).cohesion_weight += weight;
+        (*flock).counts.y += 1u;
+    }
+    (*flock).total += 1u;
+}
 
-            if distance > 0.01 && distance < PERCEPTION_RADIUS {
-                separation += (p.position - neighbor.position) / (distance * distance); // Inverse square falloff
-            }
+// Neighbours staged through shared memory when TILED is on, a tile per workgroup's worth
+var<workgroup> tile: array<Particle, TILE_SIZE>;
 
-            cohesion += neighbor.position;
-            total += 1u;
+@compute @workgroup_size(256)
+fn simulate_boids(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let index: u32 = id.x;
+    let in_count = index < params.particle_count;
+    if !TILED && !in_count {
+        return;
+    }
+
+    var p: Particle; // Current boid
+    if in_count {
+        p = load(index);
+    }
+    let refined = in_region(p.position);
+    dt = select(params.dt, params.roi_dt, refined);
+    // The region of interest's extra substeps leave everything else where it was, and
+    // sleepers stay put without looking at their neighbours
+    let asleep = in_count && (flags[index] & FLAG_ASLEEP) != 0u;
+    let skip = (params.roi_only != 0u && !refined) || asleep;
+    var flock = Flock(vec2<f32>(0.0), vec2<f32>(0.0), vec2<f32>(0.0), 0u, vec3<u32>(0u), 0.0);
+
+    if TILED {
+        // Every invocation loads one particle of the tile and then reads all of them from
+        // shared memory, so each workgroup reads the buffer once per tile rather than once
+        // per invocation. All invocations take part in the loads, even those past the count.
+        for (var start = 0u; start < params.particle_count; start += TILE_SIZE) {
+            if start + local < params.particle_count {
+                tile[local] = load(start + local);
+            }
+            workgroupBarrier();
+            if in_count && !skip {
+                let end = min(TILE_SIZE, params.particle_count - start);
+                for (var j = 0u; j < end; j++) {
+                    if start + j != index {
+                        visit(&flock, p, tile[j], start + j);
+                    }
+                }
+            }
+            workgroupBarrier();
+        }
+        if !in_count {
+            return;
+        }
+    } else if !skip {
+        for (var k: u32 = 0u; k < params.particle_count; k = k + 1u) {
+            if k == index {
+                continue;
+            }
+            visit(&flock, p, load(k), k);
         }
     }
+    if skip {
+        store(index, p);
+        return;
+    }
+    neighbor_counts[index] = flock.total;
 
-    if total > 0u {
-        let total_f32: f32 = f32(total);
-        alignment /= total_f32;
-        cohesion /= total_f32;
-        separation /= total_f32;
-        p.velocity += normalize(alignment) * 0.001;
-        p.velocity += normalize(cohesion - p.position) * 0.002;
-        p.velocity += normalize(separation) * 0.0023;
+    if flock.total > 0u {
+        // Each rule averages over the neighbours in its own radius
+        let counts = vec3<f32>(max(flock.counts, vec3<u32>(1u)));
+        let alignment = flock.alignment / counts.x;
+        let cohesion = flock.cohesion / max(flock.cohesion_weight, 1e-6);
+        let separation = flock.separation / counts.z;
+        // Steering is a force, so heavy particles answer it slowly
+        let mass = particle_mass(index, params);
+        if ALIGNMENT && flock.counts.x > 0u {
+            p.velocity += normalize(alignment) * 0.001 * params.alignment_weight * dt / mass;
+        }
+        if COHESION && flock.cohesion_weight > 0.0 {
+            p.velocity +=
+                normalize(cohesion - p.position) * 0.002 * params.cohesion_weight * dt / mass;
+        }
+        if SEPARATION && flock.counts.z > 0u {
+            p.velocity +=
+                normalize(separation) * 0.0023 * params.separation_weight * dt / mass;
+        }
     }
 
     let speed = length(p.velocity);
     if speed > 0.0001 {
-        p.velocity = normalize(p.velocity) * min(speed, MAX_SPEED);
+        p.velocity = normalize(p.velocity) * limit_speed(speed);
     } else {
         p.velocity = vec2<f32>(0.001, 0.001); // Ensures the particle keeps moving
     }
 
-    p.position += p.velocity;
+    p.position += p.velocity * dt;
 
-    if abs(p.position.x) > BOUNDARY_LIMIT || abs(p.position.y) > BOUNDARY_LIMIT {
-        p.velocity = -p.velocity;
-    }
-    if abs(p.position.x) > BOUNDARY_LIMIT {
-        p.position.x = sign(p.position.x) * BOUNDARY_LIMIT;
+    if PISTON {
+        // Reflect off each wall separately, adding up the momentum each bounce transfers
+        var impulse = 0.0;
+        if p.position.x < -BOUNDARY_LIMIT {
+            p.position.x = -BOUNDARY_LIMIT;
+            impulse += 2.0 * abs(p.velocity.x);
+            p.velocity.x = abs(p.velocity.x);
+        }
+        if p.position.x > params.piston {
+            p.position.x = params.piston;
+            impulse += 2.0 * abs(p.velocity.x);
+            p.velocity.x = -abs(p.velocity.x);
+        }
+        if abs(p.position.y) > BOUNDARY_LIMIT {
+            p.position.y = sign(p.position.y) * BOUNDARY_LIMIT;
+            impulse += 2.0 * abs(p.velocity.y);
+            p.velocity.y = -sign(p.position.y) * abs(p.velocity.y);
+        }
+        if impulse > 0.0 {
+            impulse *= particle_mass(index, params);
+            atomicAdd(&wall_impulse, u32(impulse * IMPULSE_SCALE));
+        }
+    } else {
+        if abs(p.position.x) > BOUNDARY_LIMIT || abs(p.position.y) > BOUNDARY_LIMIT {
+            p.velocity = -p.velocity;
+        }
+        if abs(p.position.x) > BOUNDARY_LIMIT {
+            p.position.x = sign(p.position.x) * BOUNDARY_LIMIT;
+        }
     }
 
 
-    particles[index] = p;
+    store(index, p);
 }
@@ -0,0 +1,105 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct ContagionParams {
+    particle_count: u32,
+    frame: u32,
+    // Chance each frame of catching it from each infected particle in range
+    probability: f32,
+    radius: f32,
+    // Frames until an infected particle recovers
+    recovery_frames: u32,
+    // Fraction infected at the start
+    initial: f32,
+};
+
+// Must match `HEALTH_SIZE` in contagion.rs
+struct Health {
+    // SUSCEPTIBLE, INFECTED or RECOVERED
+    state: u32,
+    // Frames since it was infected
+    frames: u32,
+};
+
+// Must match `Dye` in vertex_shader.wgsl
+struct Dye {
+    color: vec3<f32>,
+    emitter: u32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ContagionParams;
+@group(0) @binding(2) var<storage, read_write> health: array<Health>;
+@group(0) @binding(3) var<storage, read_write> next_health: array<Health>;
+@group(0) @binding(4) var<storage, read_write> dyes: array<Dye>;
+// Particles in each state this frame, in the order of the states
+@group(0) @binding(5) var<storage, read_write> counts: array<atomic<u32>, 3>;
+
+const SUSCEPTIBLE: u32 = 0u;
+const INFECTED: u32 = 1u;
+const RECOVERED: u32 = 2u;
+
+// Seeds for `random_seed`
+const INIT_STREAM: u32 = 12u;
+const CATCH_STREAM: u32 = 13u;
+
+fn dye_of(state: u32) -> Dye {
+    var colors = array<vec3<f32>, 3>(
+        vec3<f32>(0.35, 0.55, 0.95),
+        vec3<f32>(1.0, 0.25, 0.2),
+        vec3<f32>(0.45, 0.8, 0.45),
+    );
+    return Dye(colors[state], state + 1u);
+}
+
+// Patient zeros picked at random, the rest susceptible
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&health) {
+        return;
+    }
+    var rng = random_seed(index, 0u, INIT_STREAM);
+    let state = select(SUSCEPTIBLE, INFECTED, random_f32(&rng) < params.initial);
+    health[index] = Health(state, 0u);
+    dyes[index] = dye_of(state);
+}
+
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var own = health[index];
+
+    if own.state == SUSCEPTIBLE {
+        // Each infected neighbour is another chance to catch it
+        let position = particles[index].position;
+        var exposures = 0u;
+        for (var k = 0u; k < params.particle_count; k++) {
+            if k != index && health[k].state == INFECTED {
+                let offset = particles[k].position - position;
+                if dot(offset, offset) < params.radius * params.radius {
+                    exposures += 1u;
+                }
+            }
+        }
+        if exposures > 0u {
+            var rng = random_seed(index, params.frame, CATCH_STREAM);
+            let escape = pow(1.0 - params.probability, f32(exposures));
+            if random_f32(&rng) >= escape {
+                own = Health(INFECTED, 0u);
+            }
+        }
+    } else if own.state == INFECTED {
+        own.frames += 1u;
+        if own.frames >= params.recovery_frames {
+            own.state = RECOVERED;
+        }
+    }
+
+    next_health[index] = own;
+    dyes[index] = dye_of(own.state);
+    atomicAdd(&counts[own.state], 1u);
+}
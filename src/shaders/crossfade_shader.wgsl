@@ -0,0 +1,34 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Texture coordinates, y down
+    @location(0) uv: vec2<f32>,
+};
+
+// Must match `CrossfadeParams` in crossfade.rs
+struct CrossfadeParams {
+    // 1 shows the scene switched away from fully, 0 not at all
+    opacity: f32,
+};
+
+// The scene switched away from, drawn on its own
+@group(0) @binding(0) var outgoing: texture_2d<f32>;
+@group(0) @binding(1) var outgoing_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: CrossfadeParams;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return output;
+}
+
+// Over the scene taking over, blended by how much of the crossfade is left
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(outgoing, outgoing_sampler, input.uv);
+    return vec4<f32>(color.rgb, params.opacity);
+}
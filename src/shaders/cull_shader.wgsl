@@ -0,0 +1,51 @@
+#include "common.wgsl"
+#include "flags.wgsl"
+
+struct Camera {
+    center: vec2<f32>,
+    zoom: f32,
+    // The domain's plane onto clip space, perspective divide last, see `Camera::view`
+    view: mat3x3<f32>,
+    // Clip space back onto the plane
+    inverse: mat3x3<f32>,
+};
+
+// Layout of the arguments read by draw_indirect
+struct DrawIndirectArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> visible: array<Particle>;
+@group(0) @binding(2) var<storage, read_write> draw_args: DrawIndirectArgs;
+@group(0) @binding(3) var<uniform> params: SimParams;
+@group(0) @binding(4) var<uniform> camera: Camera;
+@group(0) @binding(5) var<storage, read> flags: array<u32>;
+
+// Slightly more than the boid size, so boids straddling the edge stay visible
+const MARGIN: f32 = 0.01;
+
+@compute @workgroup_size(256)
+fn cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index: u32 = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+
+    if (flags[index] & FLAG_HIDDEN) != 0u {
+        return;
+    }
+
+    let p = particles[index];
+    let clip = (p.position - camera.center) * camera.zoom;
+    let extent = 1.0 + MARGIN * camera.zoom;
+    if abs(clip.x) > extent || abs(clip.y) > extent {
+        return;
+    }
+
+    let slot = atomicAdd(&draw_args.instance_count, 1u);
+    visible[slot] = p;
+}
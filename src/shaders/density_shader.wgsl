@@ -0,0 +1,107 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Must match `ShadingParams` in density.rs
+struct ShadingParams {
+    // Over the field in the same -1..1 space as the screen, z out of the screen
+    light: vec3<f32>,
+    fluid: u32,
+    // Map the counts through `curve` rather than up to FULL_DENSITY
+    auto_exposure: u32,
+};
+
+// Must match GRID_SIZE in density.rs and splat_shader.wgsl
+const GRID_SIZE: u32 = 512u;
+// Cell count that maps to full brightness
+const FULL_DENSITY: f32 = 64.0;
+// How steep the fluid surface looks for a given change in density
+const BUMP: f32 = 12.0;
+const SHININESS: f32 = 48.0;
+// Must match EXPOSURE_BINS in density.rs and exposure_shader.wgsl
+const BINS: u32 = 64u;
+// Must match LOG_RANGE in density.rs and exposure_shader.wgsl
+const LOG_RANGE: f32 = 16.0;
+
+@group(0) @binding(0) var<storage, read> density: array<u32>;
+@group(0) @binding(1) var<uniform> shading: ShadingParams;
+// Brightness for counts at the bottom of each bin of log2(1 + count) over LOG_RANGE, eased
+// towards the screen's equalized histogram by exposure_shader.wgsl
+@group(0) @binding(2) var<storage, read> curve: array<f32>;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    // uv.y = 0 at the bottom of the screen, 1 at the top, matching the splat
+    output.uv = uv;
+    return output;
+}
+
+// Log scale so sparse regions stay visible next to dense flocks
+fn intensity_at(cell: vec2<i32>) -> f32 {
+    let clamped = vec2<u32>(clamp(cell, vec2<i32>(0), vec2<i32>(i32(GRID_SIZE) - 1)));
+    let count = f32(density[clamped.y * GRID_SIZE + clamped.x]);
+    if shading.auto_exposure == 0u {
+        return clamp(log2(1.0 + count) / log2(1.0 + FULL_DENSITY), 0.0, 1.0);
+    }
+    if count == 0.0 {
+        return 0.0;
+    }
+    let level = min(log2(1.0 + count) / LOG_RANGE * f32(BINS), f32(BINS) - 1.0);
+    let bin = u32(level);
+    return mix(curve[bin], curve[min(bin + 1u, BINS - 1u)], fract(level));
+}
+
+// The intensity blurred over the neighbouring cells, read as the height of a liquid surface
+fn height_at(cell: vec2<i32>) -> f32 {
+    var sum = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            sum += intensity_at(cell + vec2<i32>(x, y));
+        }
+    }
+    return sum / 9.0;
+}
+
+// Blinn-Phong lit liquid, with normals from the slope of the height field
+fn fluid(cell: vec2<i32>, uv: vec2<f32>) -> vec4<f32> {
+    let height = height_at(cell);
+    let slope = vec2<f32>(
+        height_at(cell + vec2<i32>(1, 0)) - height_at(cell - vec2<i32>(1, 0)),
+        height_at(cell + vec2<i32>(0, 1)) - height_at(cell - vec2<i32>(0, 1)),
+    ) * 0.5;
+    let normal = normalize(vec3<f32>(-slope * BUMP, 1.0));
+
+    let position = vec3<f32>(uv * 2.0 - 1.0, 0.0);
+    let to_light = normalize(shading.light - position);
+    let half_vector = normalize(to_light + vec3<f32>(0.0, 0.0, 1.0));
+    let diffuse = max(dot(normal, to_light), 0.0);
+    let specular = pow(max(dot(normal, half_vector), 0.0), SHININESS);
+
+    let base = mix(vec3<f32>(0.02, 0.15, 0.4), vec3<f32>(0.1, 0.55, 0.9), height);
+    let color = base * (0.25 + 0.75 * diffuse) + vec3<f32>(specular);
+    // A sharp edge where the surface meets the background, like a meniscus
+    let alpha = smoothstep(0.05, 0.15, height);
+    return vec4<f32>(color, alpha);
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = min(vec2<u32>(input.uv * f32(GRID_SIZE)), vec2<u32>(GRID_SIZE - 1u));
+    if shading.fluid != 0u {
+        return fluid(vec2<i32>(cell), input.uv);
+    }
+    let intensity = intensity_at(vec2<i32>(cell));
+    // Dark blue through cyan to white
+    let color = mix(
+        mix(vec3<f32>(0.05, 0.1, 0.5), vec3<f32>(0.1, 0.8, 1.0), min(intensity * 2.0, 1.0)),
+        vec3<f32>(1.0, 1.0, 1.0),
+        max(intensity * 2.0 - 1.0, 0.0)
+    );
+    return vec4<f32>(color, intensity);
+}
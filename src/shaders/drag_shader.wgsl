@@ -0,0 +1,26 @@
+#include "common.wgsl"
+#include "mass.wgsl"
+
+struct DragParams {
+    // Fraction of the velocity lost each frame
+    linear: f32,
+    // Fraction lost each frame per unit of speed
+    quadratic: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> drag: DragParams;
+
+// Never more than the whole velocity, so strong drag stops particles rather than turning
+// them round
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let velocity = particles[index].velocity;
+    let loss = (drag.linear + drag.quadratic * length(velocity)) / particle_mass(index, params);
+    particles[index].velocity = velocity * max(1.0 - loss, 0.0);
+}
@@ -0,0 +1,131 @@
+#include "common.wgsl"
+#include "palette.wgsl"
+
+struct EmittersParams {
+    particle_count: u32,
+    emitters: u32,
+    // Distance from an emitter inside which particles take its colour
+    radius: f32,
+    // Fraction of the colour swapped for the average of the cell's each frame
+    mixing: f32,
+    // Cells along each side of the grid
+    grid: u32,
+};
+
+// Must match `Dye` in vertex_shader.wgsl. `emitter` is 1 more than the index of the emitter
+// that last dyed the particle, 0 until one has
+struct Dye {
+    color: vec3<f32>,
+    emitter: u32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: EmittersParams;
+// x, y and hue of each emitter, the last component is unused
+@group(0) @binding(2) var<uniform> emitters: array<vec4<f32>, 8>;
+@group(0) @binding(3) var<storage, read_write> dyes: array<Dye>;
+// Red, green and blue summed in fixed point, then the count, of the dyed particles in each
+// cell, row by row from the bottom of the domain. Cleared again by `clear`
+@group(0) @binding(4) var<storage, read_write> cells: array<atomic<u32>>;
+
+// Colours are summed as integers, so up to 4 million particles of white fit in a cell
+const FIXED_POINT: f32 = 1024.0;
+
+fn dye_of(emitter: u32) -> Dye {
+    return Dye(palette(emitters[emitter].z), emitter + 1u);
+}
+
+// The emitter nearest `position`, and how far away it is
+fn nearest(position: vec2<f32>) -> vec2<f32> {
+    var best = vec2<f32>(0.0, 1e9);
+    for (var i = 0u; i < params.emitters; i++) {
+        let distance = length(position - emitters[i].xy);
+        if distance < best.y {
+            best = vec2<f32>(f32(i), distance);
+        }
+    }
+    return best;
+}
+
+// -1 outside the domain
+fn cell_of(position: vec2<f32>) -> i32 {
+    let cell = vec2<i32>(floor((position * 0.5 + 0.5) * f32(params.grid)));
+    if any(cell < vec2<i32>(0)) || any(cell >= vec2<i32>(i32(params.grid))) {
+        return -1;
+    }
+    return cell.y * i32(params.grid) + cell.x;
+}
+
+// Every particle dyed by its nearest emitter, so the flock starts divided between them
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&dyes) || index >= arrayLength(&particles) {
+        return;
+    }
+    if params.emitters == 0u {
+        dyes[index] = Dye(vec3<f32>(0.0), 0u);
+        return;
+    }
+    dyes[index] = dye_of(u32(nearest(particles[index].position).x));
+}
+
+@compute @workgroup_size(256)
+fn deposit(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let dye = dyes[index];
+    let cell = cell_of(particles[index].position);
+    if dye.emitter == 0u || cell < 0 {
+        return;
+    }
+    let sums = u32(cell) * 4u;
+    let amounts = vec3<u32>(clamp(dye.color, vec3<f32>(0.0), vec3<f32>(1.0)) * FIXED_POINT);
+    atomicAdd(&cells[sums], amounts.r);
+    atomicAdd(&cells[sums + 1u], amounts.g);
+    atomicAdd(&cells[sums + 2u], amounts.b);
+    atomicAdd(&cells[sums + 3u], 1u);
+}
+
+// Redye the particles at an emitter, and mix the rest with their cell's average
+@compute @workgroup_size(256)
+fn blend(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    let emitter = nearest(position);
+    if params.emitters > 0u && emitter.y < params.radius {
+        dyes[index] = dye_of(u32(emitter.x));
+        return;
+    }
+    let cell = cell_of(position);
+    if params.mixing <= 0.0 || dyes[index].emitter == 0u || cell < 0 {
+        return;
+    }
+    let sums = u32(cell) * 4u;
+    let count = atomicLoad(&cells[sums + 3u]);
+    if count == 0u {
+        return;
+    }
+    let average = vec3<f32>(
+        f32(atomicLoad(&cells[sums])),
+        f32(atomicLoad(&cells[sums + 1u])),
+        f32(atomicLoad(&cells[sums + 2u])),
+    ) / (FIXED_POINT * f32(count));
+    dyes[index].color = mix(dyes[index].color, average, params.mixing);
+}
+
+@compute @workgroup_size(16, 16)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.grid || id.y >= params.grid {
+        return;
+    }
+    let sums = (id.y * params.grid + id.x) * 4u;
+    for (var i = 0u; i < 4u; i++) {
+        atomicStore(&cells[sums + i], 0u);
+    }
+}
@@ -0,0 +1,63 @@
+// Must match GRID_SIZE in density.rs and splat_shader.wgsl
+const GRID_SIZE: u32 = 512u;
+// Must match EXPOSURE_BINS in density.rs and density_shader.wgsl
+const BINS: u32 = 64u;
+// Must match LOG_RANGE in density.rs and density_shader.wgsl
+const LOG_RANGE: f32 = 16.0;
+// Fraction of the way to this frame's curve the shown one moves each frame, so a flock
+// bunching up or spreading out dims and brightens smoothly rather than flickering
+const ADAPT: f32 = 0.05;
+
+@group(0) @binding(0) var<storage, read> density: array<u32>;
+// Occupied cells in each bin of log2(1 + count), emptied by `adapt`
+@group(0) @binding(1) var<storage, read_write> bins: array<atomic<u32>>;
+// Brightness for counts at the bottom of each bin, see `curve` in density_shader.wgsl
+@group(0) @binding(2) var<storage, read_write> curve: array<f32>;
+
+var<workgroup> local_bins: array<atomic<u32>, BINS>;
+
+// Bin the occupied cells, in shared memory first as most of them land in the same few bins
+@compute @workgroup_size(256)
+fn count(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    if local < BINS {
+        atomicStore(&local_bins[local], 0u);
+    }
+    workgroupBarrier();
+    if id.x < GRID_SIZE * GRID_SIZE && density[id.x] > 0u {
+        let level = log2(1.0 + f32(density[id.x])) / LOG_RANGE;
+        atomicAdd(&local_bins[min(u32(level * f32(BINS)), BINS - 1u)], 1u);
+    }
+    workgroupBarrier();
+    if local < BINS {
+        let binned = atomicLoad(&local_bins[local]);
+        if binned > 0u {
+            atomicAdd(&bins[local], binned);
+        }
+    }
+}
+
+// Ease each bin's brightness towards the fraction of occupied cells in it or below, which
+// spreads the cells evenly over the brightness range whatever the densities
+@compute @workgroup_size(64)
+fn adapt(@builtin(local_invocation_index) bin: u32) {
+    var below = 0u;
+    var total = 0u;
+    for (var other = 0u; other < BINS; other++) {
+        let binned = atomicLoad(&bins[other]);
+        total += binned;
+        if other <= bin {
+            below += binned;
+        }
+    }
+    workgroupBarrier();
+    atomicStore(&bins[bin], 0u);
+    // Nothing on screen, so nothing to expose for
+    if total == 0u {
+        return;
+    }
+    let target_level = f32(below) / f32(total);
+    curve[bin] = mix(curve[bin], target_level, ADAPT);
+}
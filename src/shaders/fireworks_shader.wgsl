@@ -0,0 +1,79 @@
+#include "common.wgsl"
+#include "palette.wgsl"
+#include "random.wgsl"
+
+struct FireworksParams {
+    particle_count: u32,
+    frame: u32,
+    // Domain units per frame, taken off the vertical velocity
+    gravity: f32,
+    // Fraction of the velocity lost each frame
+    drag: f32,
+    // Fastest spark, in domain units per frame
+    speed: f32,
+    // Sparks per burst
+    sparks: u32,
+    bursts: u32,
+    // First particle the first burst takes, the rest follow on, wrapping around
+    next: u32,
+    // Simulated seconds a spark lives, and up to what fraction longer or shorter
+    lifetime: f32,
+    spread: f32,
+};
+
+// Must match `Life` in lifetime_shader.wgsl
+struct Life {
+    age: f32,
+    lifetime: f32,
+    alpha: f32,
+    size: f32,
+    tint: vec4<f32>,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: FireworksParams;
+@group(0) @binding(2) var<storage, read_write> lives: array<Life>;
+// x, y and hue of each burst, then how fast its sparks fly as a multiple of `speed`
+@group(0) @binding(3) var<uniform> bursts: array<vec4<f32>, 16>;
+
+const SPARK_STREAM: u32 = 7u;
+
+// Out of the domain, so the trail skips them
+const PARKED: vec2<f32> = vec2<f32>(8.0, 8.0);
+
+// Gravity and drag on the live sparks, moving them in place
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let life = lives[index];
+    if life.age >= life.lifetime {
+        particles[index] = Particle(PARKED, vec2<f32>(0.0, 0.0));
+        return;
+    }
+    var p = particles[index];
+    p.velocity.y -= params.gravity;
+    p.velocity *= 1.0 - params.drag;
+    p.position += p.velocity;
+    particles[index] = p;
+}
+
+// One invocation per spark of every burst this frame, each flying off in a random direction
+// at up to the burst's top speed, evenly over the disc
+@compute @workgroup_size(256)
+fn burst(@builtin(global_invocation_id) id: vec3<u32>) {
+    let burst = id.x / max(params.sparks, 1u);
+    if burst >= params.bursts {
+        return;
+    }
+    let index = (params.next + id.x) % params.particle_count;
+    var rng = random_seed(index, params.frame, SPARK_STREAM);
+    let speed = params.speed * bursts[burst].w;
+    let velocity = random_direction(&rng) * sqrt(random_f32(&rng)) * speed;
+    particles[index] = Particle(bursts[burst].xy, velocity);
+    let lifetime = params.lifetime * (1.0 + params.spread * random_signed(&rng));
+    let tint = vec4<f32>(palette(bursts[burst].z), 1.0);
+    lives[index] = Life(0.0, lifetime, 1.0, 1.0, tint);
+}
@@ -0,0 +1,77 @@
+#include "common.wgsl"
+#include "random.wgsl"
+#include "noise.wgsl"
+#include "mass.wgsl"
+
+struct ForceParams {
+    particle_count: u32,
+    attractor_count: u32,
+    // Simulated seconds, so the noise field drifts
+    time: f32,
+    noise_strength: f32,
+    noise_scale: f32,
+    // Random kick each frame on top of the field
+    noise_jitter: f32,
+    frame: u32,
+    // Most the heading turns per frame in radians, and how fast that changes
+    wander_strength: f32,
+    wander_frequency: f32,
+};
+
+const JITTER_STREAM: u32 = 2u;
+const WANDER_STREAM: u32 = 9u;
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ForceParams;
+// xy position and strength (negative repels), the last component is unused
+@group(0) @binding(2) var<uniform> attractors: array<vec4<f32>, 16>;
+// Only for `particle_mass`
+@group(0) @binding(3) var<uniform> sim: SimParams;
+
+// Keeps the pull finite right at an attractor
+const SOFTENING: f32 = 0.01;
+
+@compute @workgroup_size(256)
+fn noise(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let p = particles[index].position * params.noise_scale + vec2<f32>(params.time * 0.1, 0.0);
+    var rng = random_seed(index, params.frame, JITTER_STREAM);
+    let kick = vec2<f32>(random_gaussian(&rng), random_gaussian(&rng)) * params.noise_jitter;
+    let force = curl(p) * params.noise_strength + kick;
+    particles[index].velocity += force / particle_mass(index, sim);
+}
+
+@compute @workgroup_size(256)
+fn wander(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    // Noise along time on a row of each particle's own, so neighbours don't turn in step
+    var rng = random_seed(index, 0u, WANDER_STREAM);
+    let row = random_f32(&rng) * 1000.0;
+    let along = vec2<f32>(params.time * params.wander_frequency, row);
+    let turn = simplex_noise(along) * params.wander_strength / particle_mass(index, sim);
+    let c = cos(turn);
+    let s = sin(turn);
+    let v = particles[index].velocity;
+    particles[index].velocity = vec2<f32>(c * v.x - s * v.y, s * v.x + c * v.y);
+}
+
+@compute @workgroup_size(256)
+fn attract(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    var pull = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < params.attractor_count; i++) {
+        let offset = attractors[i].xy - position;
+        pull += offset * attractors[i].z / (dot(offset, offset) + SOFTENING);
+    }
+    particles[index].velocity += pull / particle_mass(index, sim);
+}
@@ -1,4 +1,16 @@
+// Takes every output of the vertex shader, as wgpu wants, though only the colour and
+// position across a dot are used. The rest are for user shaders, see user_fragment.wgsl
 @fragment
-fn fs_main(@location(0) color: vec4<f32>) -> @location(0) vec4<f32> {
+fn fs_main(
+    @location(0) color: vec4<f32>,
+    @location(1) local: vec2<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) velocity: vec2<f32>,
+    @location(4) age_time: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    // Dots are cut out of their square
+    if dot(local, local) > 1.0 {
+        discard;
+    }
     return color; // Use the color from the vertex shader
 }
@@ -0,0 +1,62 @@
+#include "common.wgsl"
+#include "flags.wgsl"
+
+struct FreezeParams {
+    particle_count: u32,
+    // Regions frozen or thawed this frame
+    edits: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: FreezeParams;
+// Two for each edit: its bounds, min and max corners or centre and radius, then its shape,
+// 0 for a rectangle and 1 for a circle, and 1 to freeze or 0 to thaw
+@group(0) @binding(2) var<uniform> edits: array<vec4<f32>, 16>;
+// Where each frozen particle is held
+@group(0) @binding(3) var<storage, read_write> holds: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> flags: array<u32>;
+
+fn inside(position: vec2<f32>, bounds: vec4<f32>, shape: f32) -> bool {
+    if shape > 0.5 {
+        return distance(position, bounds.xy) <= bounds.z;
+    }
+    return all(position >= bounds.xy) && all(position <= bounds.zw);
+}
+
+// Each edit in turn, so a later one wins where they overlap
+@compute @workgroup_size(256)
+fn mark(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    for (var i = 0u; i < params.edits; i++) {
+        let kind = edits[i * 2u + 1u];
+        if !inside(position, edits[i * 2u], kind.x) {
+            continue;
+        }
+        if kind.y > 0.5 {
+            // Already frozen ones stay where they were
+            if (flags[index] & FLAG_FROZEN) == 0u {
+                holds[index] = position;
+                flags[index] |= FLAG_FROZEN;
+            }
+        } else {
+            flags[index] &= ~FLAG_FROZEN;
+        }
+    }
+}
+
+@compute @workgroup_size(256)
+fn hold(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    if (flags[index] & FLAG_FROZEN) == 0u {
+        return;
+    }
+    particles[index].position = holds[index];
+    particles[index].velocity = vec2<f32>(0.0);
+}
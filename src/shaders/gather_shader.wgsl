@@ -0,0 +1,26 @@
+// Moves whole elements of `words` u32s each from the slot `order` names to their place in
+// order, one word per invocation.
+
+struct GatherParams {
+    count: u32,
+    // u32s per element
+    words: u32,
+    _pad0: u32,
+    _pad1: u32,
+};
+
+@group(0) @binding(0) var<storage, read> source: array<u32>;
+// Which element of the source goes in each slot of the destination
+@group(0) @binding(1) var<storage, read> order: array<u32>;
+@group(0) @binding(2) var<storage, read_write> destination: array<u32>;
+@group(0) @binding(3) var<uniform> params: GatherParams;
+
+@compute @workgroup_size(256)
+fn gather(@builtin(global_invocation_id) id: vec3<u32>) {
+    let element = id.x / params.words;
+    if element >= params.count {
+        return;
+    }
+    let word = id.x % params.words;
+    destination[id.x] = source[order[element] * params.words + word];
+}
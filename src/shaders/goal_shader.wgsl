@@ -0,0 +1,35 @@
+#include "common.wgsl"
+#include "leaders.wgsl"
+
+struct GoalParams {
+    // Where the goal is this frame, in domain units
+    goal: vec2<f32>,
+    // Velocity change per frame towards the goal
+    strength: f32,
+    // Distance inside which the pull eases off
+    arrival: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> goal: GoalParams;
+
+// The same pull however far away, so the whole flock sets off after the goal together
+@compute @workgroup_size(256)
+fn seek(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    // With leaders only they seek it, and the rest follow them
+    if params.leader_fraction > 0.0 && !is_leader(index, params) {
+        return;
+    }
+    let offset = goal.goal - particles[index].position;
+    let distance = length(offset);
+    if distance <= 0.0 {
+        return;
+    }
+    let ease = min(distance / goal.arrival, 1.0);
+    particles[index].velocity += offset / distance * goal.strength * ease;
+}
@@ -0,0 +1,39 @@
+#include "common.wgsl"
+
+// Must match `BINS` in histogram.rs
+const BINS: u32 = 64u;
+
+// Must match `HistogramParams` in histogram.rs
+struct HistogramParams {
+    // Speed at the top of the last bin, which also takes anything faster
+    top: f32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> bins: array<atomic<u32>, BINS>;
+@group(0) @binding(2) var<uniform> params: SimParams;
+@group(0) @binding(3) var<uniform> histogram: HistogramParams;
+
+// Counted per workgroup first, so each bin takes one global add per workgroup
+var<workgroup> local_bins: array<atomic<u32>, BINS>;
+
+@compute @workgroup_size(256)
+fn count(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let index = id.x;
+    if index < params.particle_count {
+        let speed = length(particles[index].velocity);
+        let bin = min(u32(speed / histogram.top * f32(BINS)), BINS - 1u);
+        atomicAdd(&local_bins[bin], 1u);
+    }
+    workgroupBarrier();
+
+    if local_index < BINS {
+        let local_count = atomicLoad(&local_bins[local_index]);
+        if local_count != 0u {
+            atomicAdd(&bins[local_index], local_count);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+#include "common.wgsl"
+#include "mass.wgsl"
+
+struct ImpulseParams {
+    particle_count: u32,
+    impulse_count: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ImpulseParams;
+// xy position, strength (negative pulls in) and radius
+@group(0) @binding(2) var<uniform> impulses: array<vec4<f32>, 16>;
+// Only for `particle_mass`
+@group(0) @binding(3) var<uniform> sim: SimParams;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    var kick = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < params.impulse_count; i++) {
+        let offset = position - impulses[i].xy;
+        let distance = length(offset);
+        let radius = impulses[i].w;
+        if distance < radius && distance > 0.0 {
+            kick += offset / distance * impulses[i].z * (1.0 - distance / radius);
+        }
+    }
+    particles[index].velocity += kick / particle_mass(index, sim);
+}
@@ -0,0 +1,44 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct InflowParams {
+    // Into the domain from the inflow edge
+    inward: vec2<f32>,
+    speed: f32,
+    drive: f32,
+    frame: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> inflow: InflowParams;
+
+// Seeds for `random_seed`
+const INFLOW_STREAM: u32 = 14u;
+// How close to the outflow edge a particle leaves from, as the boids step holds it inside
+const OUTFLOW_DEPTH: f32 = 0.005;
+// Sideways spread of the speed particles come in at
+const JITTER: f32 = 0.1;
+
+@compute @workgroup_size(256)
+fn stream(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var p = particles[index];
+    let flow = inflow.inward * inflow.speed;
+    // Across the stream, and how far along it, from -1 at the inflow edge to 1 at the outflow
+    let across = vec2<f32>(-inflow.inward.y, inflow.inward.x);
+    let along = dot(p.position, inflow.inward);
+
+    if along >= 1.0 - OUTFLOW_DEPTH {
+        var rng = random_seed(index, inflow.frame, INFLOW_STREAM);
+        let offset = random_signed(&rng);
+        p.position = -inflow.inward * (1.0 - OUTFLOW_DEPTH) + across * offset;
+        p.velocity = flow + across * inflow.speed * JITTER * random_signed(&rng);
+    } else {
+        p.velocity = mix(p.velocity, flow, inflow.drive);
+    }
+    particles[index] = p;
+}
@@ -0,0 +1,26 @@
+// The interface for user compute kernels. Each .wgsl file in the --kernels folder defines
+//
+//     @compute @workgroup_size(256)
+//     fn main(@builtin(global_invocation_id) id: vec3<u32>)
+//
+// which is run over the particles once a frame, changing them in place, and is appended to
+// this file. A line `// after: <stage>`, e.g. `// after: drag`, runs it after that stage,
+// named as `StageKind` is in kebab-case, and without one it runs after the forces. It can
+// include the shared modules, e.g. `#include "noise.wgsl"`.
+#include "common.wgsl"
+#include "flags.wgsl"
+
+struct KernelParams {
+    particle_count: u32,
+    // Frames simulated so far, for seeding random.wgsl
+    frame: u32,
+    // Simulated seconds
+    time: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: KernelParams;
+// Each particle's bits from flags.rs, e.g. to leave the frozen ones be
+@group(0) @binding(2) var<storage, read_write> flags: array<u32>;
+// The boids step's parameters, e.g. the speed limits, or for `particle_mass` in mass.wgsl
+@group(0) @binding(3) var<uniform> sim: SimParams;
@@ -0,0 +1,14 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+const LEADER_STREAM: u32 = 10u;
+
+// Whether the particle at `index` leads, the same every frame so nothing needs storing. About
+// `leader_fraction` of them do, none when it's 0.
+fn is_leader(index: u32, sim: SimParams) -> bool {
+    if sim.leader_fraction <= 0.0 {
+        return false;
+    }
+    var rng = random_seed(index, 0u, LEADER_STREAM);
+    return random_f32(&rng) < sim.leader_fraction;
+}
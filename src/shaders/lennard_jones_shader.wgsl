@@ -0,0 +1,104 @@
+#include "common.wgsl"
+#include "mass.wgsl"
+
+struct LennardJonesParams {
+    // Cells per side of the neighbour grid, at least 3
+    grid_size: u32,
+    // Particle diameter in domain units, the length unit below
+    sigma: f32,
+    // Reduced time units per frame
+    time_step: f32,
+};
+
+@group(0) @binding(0) var<storage, read> particles_in: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read_write> particles_out: array<Particle>;
+@group(0) @binding(3) var<uniform> lj: LennardJonesParams;
+@group(0) @binding(4) var<storage, read_write> cell_counts: array<atomic<u32>>;
+// CELL_CAPACITY particle indices per cell
+@group(0) @binding(5) var<storage, read_write> cell_particles: array<u32>;
+
+// Must match CELL_CAPACITY in lennard_jones.rs
+const CELL_CAPACITY: u32 = 32u;
+// In units of sigma
+const CUTOFF: f32 = 2.5;
+// Closer pairs feel the force at this distance, so overlapping random starts don't explode
+const MIN_DISTANCE: f32 = 0.8;
+// The periodic domain is -1..1 on both axes
+const DOMAIN: f32 = 2.0;
+
+fn wrap(position: vec2<f32>) -> vec2<f32> {
+    return position - DOMAIN * floor((position + 1.0) / DOMAIN);
+}
+
+fn cell_of(position: vec2<f32>) -> vec2<u32> {
+    let cell = vec2<u32>((position + 1.0) / DOMAIN * f32(lj.grid_size));
+    return min(cell, vec2<u32>(lj.grid_size - 1u));
+}
+
+@compute @workgroup_size(256)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < lj.grid_size * lj.grid_size {
+        atomicStore(&cell_counts[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(256)
+fn bin(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(wrap(particles_in[index].position));
+    let cell_index = cell.y * lj.grid_size + cell.x;
+    let slot = atomicAdd(&cell_counts[cell_index], 1u);
+    // A full cell drops the particle from the grid, so others don't see it this substep
+    if slot < CELL_CAPACITY {
+        cell_particles[cell_index * CELL_CAPACITY + slot] = index;
+    }
+}
+
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+
+    var p = particles_in[index];
+    p.position = wrap(p.position);
+    let cell = vec2<i32>(cell_of(p.position));
+    let grid_size = i32(lj.grid_size);
+
+    // Sum of pair forces in reduced units, with epsilon 1
+    var force = vec2<f32>(0.0, 0.0);
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let neighbor_cell = (cell + vec2<i32>(dx, dy) + grid_size) % grid_size;
+            let cell_index = u32(neighbor_cell.y * grid_size + neighbor_cell.x);
+            let count = min(atomicLoad(&cell_counts[cell_index]), CELL_CAPACITY);
+            for (var slot = 0u; slot < count; slot++) {
+                let k = cell_particles[cell_index * CELL_CAPACITY + slot];
+                if k == index {
+                    continue;
+                }
+                // Minimum image: the nearest copy of the neighbour across the periodic edges
+                var offset = p.position - wrap(particles_in[k].position);
+                offset -= DOMAIN * round(offset / DOMAIN);
+                let r = offset / lj.sigma;
+                let r2 = dot(r, r);
+                if r2 < CUTOFF * CUTOFF {
+                    let inv2 = 1.0 / max(r2, MIN_DISTANCE * MIN_DISTANCE);
+                    let inv6 = inv2 * inv2 * inv2;
+                    force += r * 24.0 * inv2 * inv6 * (2.0 * inv6 - 1.0);
+                }
+            }
+        }
+    }
+
+    // Back to domain units per frame squared
+    let acceleration = force / particle_mass(index, params) * lj.sigma * lj.time_step * lj.time_step;
+    p.velocity += acceleration * params.dt;
+    p.position = wrap(p.position + p.velocity * params.dt);
+    particles_out[index] = p;
+}
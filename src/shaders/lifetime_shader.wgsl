@@ -0,0 +1,106 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct LifetimeParams {
+    seconds: f32,
+    spread: f32,
+    // `Easing::shader_index`
+    fade: u32,
+    shrink: u32,
+    // Simulated seconds since the last frame
+    dt: f32,
+    frame: u32,
+    // Otherwise the dead stay dead, see `LifetimeConfig::respawn`
+    respawn: u32,
+};
+
+// Seconds lived and to live, then the alpha and size the vertex shader draws with, and a
+// colour it draws in instead of the velocity's when its alpha isn't 0
+struct Life {
+    age: f32,
+    lifetime: f32,
+    alpha: f32,
+    size: f32,
+    tint: vec4<f32>,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read_write> lives: array<Life>;
+@group(0) @binding(3) var<uniform> lifetime: LifetimeParams;
+
+// Seeds for `random_seed`
+const INIT_STREAM: u32 = 5u;
+const RESPAWN_STREAM: u32 = 6u;
+
+fn draw_lifetime(rng: ptr<function, u32>) -> f32 {
+    return lifetime.seconds * (1.0 + lifetime.spread * random_signed(rng));
+}
+
+// How far along `easing` is when a fraction `t` of the life is gone
+fn ease(easing: u32, t: f32) -> f32 {
+    switch easing {
+        case 1u: {
+            return t;
+        }
+        case 2u: {
+            return t * t;
+        }
+        case 3u: {
+            return 1.0 - (1.0 - t) * (1.0 - t);
+        }
+        case 4u: {
+            return smoothstep(0.0, 1.0, t);
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
+
+// The dead are hidden whatever the easing
+fn with_look(life: Life) -> Life {
+    let t = clamp(life.age / max(life.lifetime, 1e-6), 0.0, 1.0);
+    let alive = select(0.0, 1.0, life.age < life.lifetime);
+    return Life(
+        life.age,
+        life.lifetime,
+        (1.0 - ease(lifetime.fade, t)) * alive,
+        (1.0 - ease(lifetime.shrink, t)) * alive,
+        life.tint,
+    );
+}
+
+// Spread out over a lifetime, so particles don't all die at once, or all dead when they
+// don't respawn
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&lives) {
+        return;
+    }
+    var rng = random_seed(index, 0u, INIT_STREAM);
+    let span = draw_lifetime(&rng);
+    let age = select(span, random_f32(&rng) * span, lifetime.respawn != 0u);
+    lives[index] = with_look(Life(age, span, 1.0, 1.0, vec4<f32>(0.0)));
+}
+
+// Age by a frame, respawning anywhere in the domain, like `Particle::random`, at the end
+// when respawning
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var life = lives[index];
+    life.age += lifetime.dt;
+    if life.age >= life.lifetime && lifetime.respawn != 0u {
+        var rng = random_seed(index, lifetime.frame, RESPAWN_STREAM);
+        let position = vec2<f32>(random_signed(&rng), random_signed(&rng));
+        let velocity = vec2<f32>(random_signed(&rng), random_signed(&rng)) * 0.001;
+        particles[index] = Particle(position, velocity);
+        life = Life(0.0, draw_lifetime(&rng), 1.0, 1.0, life.tint);
+    }
+    lives[index] = with_look(life);
+}
@@ -0,0 +1,18 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+const MASS_STREAM: u32 = 4u;
+
+// The same every frame for a given index, so nothing needs storing. 0 in
+// `mass_distribution` is no masses, 1 uniform between min and max, 2 uniform in log space.
+fn particle_mass(index: u32, sim: SimParams) -> f32 {
+    if sim.mass_distribution == 0u {
+        return 1.0;
+    }
+    var rng = random_seed(index, 0u, MASS_STREAM);
+    let t = random_f32(&rng);
+    if sim.mass_distribution == 2u {
+        return sim.mass_min * pow(sim.mass_max / sim.mass_min, t);
+    }
+    return mix(sim.mass_min, sim.mass_max, t);
+}
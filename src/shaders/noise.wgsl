@@ -0,0 +1,88 @@
+// Coherent noise, included with `#include "noise.wgsl"`.
+//
+// Value noise is cheap and blocky, in 0..1. Simplex noise is smoother with fewer grid
+// artifacts, in -1..1. Both are 2D and tile nowhere.
+
+fn noise_hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f); // Smoothstep interpolation
+
+    let a = noise_hash(i);
+    let b = noise_hash(i + vec2<f32>(1.0, 0.0));
+    let c = noise_hash(i + vec2<f32>(0.0, 1.0));
+    let d = noise_hash(i + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn mod289(x: vec3<f32>) -> vec3<f32> {
+    return x - floor(x / 289.0) * 289.0;
+}
+
+fn permute(x: vec3<f32>) -> vec3<f32> {
+    return mod289((x * 34.0 + 1.0) * x);
+}
+
+// 2D simplex noise, after Ashima Arts and Stefan Gustavson's webgl-noise (MIT)
+fn simplex_noise(v: vec2<f32>) -> f32 {
+    // (3 - sqrt(3)) / 6, (sqrt(3) - 1) / 2, -1 + 2 * (3 - sqrt(3)) / 6, 1 / 41
+    let C = vec4<f32>(0.211324865405187, 0.366025403784439, -0.577350269189626, 0.024390243902439);
+
+    // The corner of the containing triangle nearest the origin, then the other two
+    var i = floor(v + dot(v, C.yy));
+    let x0 = v - i + dot(i, C.xx);
+    let i1 = select(vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), x0.x > x0.y);
+    let x1 = x0 + C.xx - i1;
+    let x2 = x0 + C.zz;
+
+    i = i - floor(i / 289.0) * 289.0;
+    let p = permute(permute(i.y + vec3<f32>(0.0, i1.y, 1.0)) + i.x + vec3<f32>(0.0, i1.x, 1.0));
+
+    var m = max(0.5 - vec3<f32>(dot(x0, x0), dot(x1, x1), dot(x2, x2)), vec3<f32>(0.0));
+    m = m * m;
+    m = m * m;
+
+    // Gradients from 41 points on a line, mapped onto a diamond
+    let x = 2.0 * fract(p * C.www) - 1.0;
+    let h = abs(x) - 0.5;
+    let ox = floor(x + 0.5);
+    let a0 = x - ox;
+    // Normalise the gradients implicitly by scaling m
+    m *= 1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h);
+
+    let g = vec3<f32>(a0.x * x0.x + h.x * x0.y, a0.y * x1.x + h.y * x1.y, a0.z * x2.x + h.z * x2.y);
+    return 130.0 * dot(m, g);
+}
+
+// Octaves of value noise, each at twice the frequency and half the amplitude, in 0..1
+fn fbm(p: vec2<f32>, octaves: u32) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var octave = 0u; octave < octaves; octave++) {
+        value += amplitude * value_noise(q);
+        q = q * 2.0;
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// Curl of value noise: swirls without sources or sinks, so particles don't bunch up
+fn curl(p: vec2<f32>) -> vec2<f32> {
+    let e = 0.01;
+    let dx = value_noise(p + vec2<f32>(e, 0.0)) - value_noise(p - vec2<f32>(e, 0.0));
+    let dy = value_noise(p + vec2<f32>(0.0, e)) - value_noise(p - vec2<f32>(0.0, e));
+    return vec2<f32>(dy, -dx) / (2.0 * e);
+}
+
+// Curl of simplex noise, smoother than `curl` and about twice as fast-changing
+fn simplex_curl(p: vec2<f32>) -> vec2<f32> {
+    let e = 0.01;
+    let dx = simplex_noise(p + vec2<f32>(e, 0.0)) - simplex_noise(p - vec2<f32>(e, 0.0));
+    let dy = simplex_noise(p + vec2<f32>(0.0, e)) - simplex_noise(p - vec2<f32>(0.0, e));
+    return vec2<f32>(dy, -dx) / (2.0 * e);
+}
@@ -0,0 +1,91 @@
+#include "common.wgsl"
+
+struct ObstacleParams {
+    particle_count: u32,
+    segment_count: u32,
+    // Particles are kept at least this far from each wall's centre line
+    radius: f32,
+    // Fraction of the speed into a wall kept on the way out
+    bounce: f32,
+    // Cells in the level's distance field, zero without a level
+    level_width: u32,
+    level_height: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ObstacleParams;
+// Each wall's end points as xy and zw
+@group(0) @binding(2) var<storage, read> segments: array<vec4<f32>>;
+// Signed distance to the level's walls at each cell's centre, row by row from the top
+@group(0) @binding(3) var<storage, read> level: array<f32>;
+
+fn level_cell(cell: vec2<i32>) -> f32 {
+    let size = vec2<i32>(i32(params.level_width), i32(params.level_height));
+    let clamped = clamp(cell, vec2<i32>(0), size - 1);
+    return level[clamped.y * size.x + clamped.x];
+}
+
+// Bilinear between cell centres, so the walls' edges are smooth rather than blocky
+fn level_distance(position: vec2<f32>) -> f32 {
+    let size = vec2<f32>(f32(params.level_width), f32(params.level_height));
+    let cell = vec2<f32>(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5) * size - 0.5;
+    let base = floor(cell);
+    let f = cell - base;
+    let corner = vec2<i32>(base);
+    let top = mix(level_cell(corner), level_cell(corner + vec2<i32>(1, 0)), f.x);
+    let bottom = mix(level_cell(corner + vec2<i32>(0, 1)), level_cell(corner + vec2<i32>(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+}
+
+fn level_normal(position: vec2<f32>) -> vec2<f32> {
+    let step = 1.0 / vec2<f32>(f32(params.level_width), f32(params.level_height));
+    let gradient = vec2<f32>(
+        level_distance(position + vec2<f32>(step.x, 0.0)) - level_distance(position - vec2<f32>(step.x, 0.0)),
+        level_distance(position + vec2<f32>(0.0, step.y)) - level_distance(position - vec2<f32>(0.0, step.y)),
+    );
+    return gradient / max(length(gradient), 1e-12);
+}
+
+@compute @workgroup_size(256)
+fn collide(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var particle = particles[index];
+    for (var i = 0u; i < params.segment_count; i++) {
+        let a = segments[i].xy;
+        let ab = segments[i].zw - a;
+        let t = clamp(dot(particle.position - a, ab) / max(dot(ab, ab), 1e-12), 0.0, 1.0);
+        let closest = a + ab * t;
+        let offset = particle.position - closest;
+        let distance = length(offset);
+        if distance >= params.radius {
+            continue;
+        }
+        // Right on the line, push out to the side it's heading away from
+        var normal = vec2<f32>(-ab.y, ab.x) / max(length(ab), 1e-6);
+        if distance > 1e-6 {
+            normal = offset / distance;
+        } else if dot(particle.velocity, normal) > 0.0 {
+            normal = -normal;
+        }
+        particle.position = closest + normal * params.radius;
+        let into = dot(particle.velocity, normal);
+        if into < 0.0 {
+            particle.velocity -= (1.0 + params.bounce) * into * normal;
+        }
+    }
+    if params.level_width > 0u {
+        let distance = level_distance(particle.position);
+        if distance < params.radius {
+            let normal = level_normal(particle.position);
+            particle.position += normal * (params.radius - distance);
+            let into = dot(particle.velocity, normal);
+            if into < 0.0 {
+                particle.velocity -= (1.0 + params.bounce) * into * normal;
+            }
+        }
+    }
+    particles[index] = particle;
+}
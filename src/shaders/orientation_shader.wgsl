@@ -0,0 +1,56 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct OrientationParams {
+    align: f32,
+    damping: f32,
+    spin: f32,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+// Angle in radians from +x, then angular velocity in radians per frame
+@group(0) @binding(2) var<storage, read_write> spins: array<vec2<f32>>;
+@group(0) @binding(3) var<uniform> orientation: OrientationParams;
+
+const PI: f32 = 3.14159265;
+
+// Into -PI..PI
+fn wrap(angle: f32) -> f32 {
+    return angle - 2.0 * PI * floor((angle + PI) / (2.0 * PI));
+}
+
+// Seeds for `random_seed`
+const INIT_STREAM: u32 = 1u;
+
+// Start facing along the velocity, each spinning a little differently, without an upload
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&spins) {
+        return;
+    }
+    let velocity = particles[index].velocity;
+    var rng = random_seed(index, 0u, INIT_STREAM);
+    let spin = random_signed(&rng) * orientation.spin;
+    spins[index] = vec2<f32>(atan2(velocity.y, velocity.x), spin);
+}
+
+// Torque towards the velocity's heading against damping, then one step of rotation. Still
+// particles have no heading, so they keep spinning down
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let velocity = particles[index].velocity;
+    var spin = spins[index];
+    let heading = atan2(velocity.y, velocity.x);
+    let moving = select(0.0, 1.0, length(velocity) > 0.00001);
+    let torque = orientation.align * moving * wrap(heading - spin.x)
+        - orientation.damping * spin.y;
+    spin.y += torque;
+    spin.x = wrap(spin.x + spin.y);
+    spins[index] = spin;
+}
@@ -0,0 +1,56 @@
+// Colours for telling things apart and showing amounts, round the colour wheel or safe for
+// colour blindness, included with `#include "palette.wgsl"`.
+
+// Fully saturated hues are too dark in blue and too harsh in green, so lighten them a little
+fn palette(hue: f32) -> vec3<f32> {
+    let rgb = clamp(abs(fract(hue + vec3<f32>(0.0, 2.0, 1.0) / 3.0) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    return mix(vec3<f32>(1.0), rgb, 0.75);
+}
+
+// Okabe and Ito's colours as linear RGB, which stay apart with red-green or blue-yellow
+// colour blindness. Their black is left out, to show on dark backgrounds
+const SAFE: array<vec3<f32>, 7> = array<vec3<f32>, 7>(
+    vec3<f32>(0.791, 0.347, 0.0),    // Orange
+    vec3<f32>(0.093, 0.456, 0.815),  // Sky blue
+    vec3<f32>(0.0, 0.342, 0.171),    // Bluish green
+    vec3<f32>(0.871, 0.776, 0.054),  // Yellow
+    vec3<f32>(0.0, 0.168, 0.445),    // Blue
+    vec3<f32>(0.665, 0.112, 0.0),    // Vermillion
+    vec3<f32>(0.604, 0.191, 0.386),  // Reddish purple
+);
+
+fn safe_color(index: u32) -> vec3<f32> {
+    var colors = SAFE;
+    return colors[index % 7u];
+}
+
+// From dark purple through teal to yellow, lighter all the way, as linear RGB, from a
+// polynomial fitted to viridis. Must match `viridis` in theme.rs
+fn viridis(t: f32) -> vec3<f32> {
+    let c0 = vec3<f32>(0.2777273, 0.0054073, 0.3340998);
+    let c1 = vec3<f32>(0.105093, 1.4046135, 1.3845902);
+    let c2 = vec3<f32>(-0.3308618, 0.2148476, 0.0950952);
+    let c3 = vec3<f32>(-4.6342305, -5.799101, -19.332441);
+    let c4 = vec3<f32>(6.22827, 14.179933, 56.690553);
+    let c5 = vec3<f32>(4.776385, -13.745145, -65.35303);
+    let c6 = vec3<f32>(-5.435456, 4.6458526, 26.312435);
+    let x = clamp(t, 0.0, 1.0);
+    let srgb = c0 + x * (c1 + x * (c2 + x * (c3 + x * (c4 + x * (c5 + x * c6)))));
+    return pow(clamp(srgb, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+// From nearly black to white. Must match `grey` in theme.rs
+fn grey(t: f32) -> f32 {
+    return 0.03 + 0.97 * clamp(t, 0.0, 1.0);
+}
+
+// Four shades of grey far enough apart to tell, repeating
+fn grey_level(index: u32) -> f32 {
+    return grey(0.25 + 0.25 * f32(index % 4u));
+}
+
+// Where a colour from `palette` is round the wheel, from 0 to 1
+fn wheel_hue(color: vec3<f32>) -> f32 {
+    let angle = atan2(sqrt(3.0) * (color.g - color.b), 2.0 * color.r - color.g - color.b);
+    return fract(angle / 6.28318530718);
+}
@@ -0,0 +1,175 @@
+#include "common.wgsl"
+
+struct PbdParams {
+    particle_count: u32,
+    // Cells per side of the collision grid, at least 1
+    grid_size: u32,
+    // Particles closer than twice this collide
+    radius: f32,
+    constraint_count: u32,
+};
+
+struct Constraint {
+    a: u32,
+    b: u32,
+    rest_length: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: PbdParams;
+// Positions being solved, the particles hold the start of the frame meanwhile
+@group(0) @binding(2) var<storage, read_write> predicted: array<vec2<f32>>;
+// Summed corrections per particle this iteration: x and y in units of 1 / DELTA_SCALE, then
+// the number of constraints that contributed
+@group(0) @binding(3) var<storage, read_write> deltas: array<atomic<i32>>;
+// 0 pins a particle in place
+@group(0) @binding(4) var<storage, read> inverse_mass: array<f32>;
+@group(0) @binding(5) var<storage, read_write> cell_counts: array<atomic<u32>>;
+// CELL_CAPACITY particle indices per cell
+@group(0) @binding(6) var<storage, read_write> cell_particles: array<u32>;
+@group(1) @binding(0) var<storage, read> constraints: array<Constraint>;
+
+// Must match CELL_CAPACITY in pbd.rs
+const CELL_CAPACITY: u32 = 16u;
+const DELTA_SCALE: f32 = 100000000.0;
+// Over-relaxation of the averaged Jacobi corrections, between 1 and 2
+const RELAXATION: f32 = 1.5;
+const BOUNDARY_LIMIT: f32 = 1.0;
+
+fn add_delta(index: u32, delta: vec2<f32>) {
+    atomicAdd(&deltas[3u * index], i32(round(delta.x * DELTA_SCALE)));
+    atomicAdd(&deltas[3u * index + 1u], i32(round(delta.y * DELTA_SCALE)));
+    atomicAdd(&deltas[3u * index + 2u], 1);
+}
+
+fn cell_of(position: vec2<f32>) -> vec2<u32> {
+    let cell = vec2<u32>(clamp((position + 1.0) * 0.5, vec2<f32>(0.0), vec2<f32>(1.0)) * f32(params.grid_size));
+    return min(cell, vec2<u32>(params.grid_size - 1u));
+}
+
+// Predict from wherever the force stage moved the particles, and keep the start of the
+// frame, recovered from the velocity, to take the corrected velocity from at the end
+@compute @workgroup_size(256)
+fn begin(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let p = particles[index];
+    predicted[index] = p.position;
+    particles[index].position = p.position - p.velocity;
+}
+
+@compute @workgroup_size(256)
+fn clear_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < params.grid_size * params.grid_size {
+        atomicStore(&cell_counts[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(256)
+fn bin(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(predicted[index]);
+    let cell_index = cell.y * params.grid_size + cell.x;
+    let slot = atomicAdd(&cell_counts[cell_index], 1u);
+    // A full cell leaves the particle out of collisions this iteration
+    if slot < CELL_CAPACITY {
+        cell_particles[cell_index * CELL_CAPACITY + slot] = index;
+    }
+}
+
+// Push overlapping particles apart, each particle working out its own share
+@compute @workgroup_size(256)
+fn collide(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let w = inverse_mass[index];
+    if w == 0.0 {
+        return;
+    }
+    let position = predicted[index];
+    let cell = vec2<i32>(cell_of(position));
+    let grid_size = i32(params.grid_size);
+    let contact = 2.0 * params.radius;
+
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let neighbor_cell = cell + vec2<i32>(dx, dy);
+            if any(neighbor_cell < vec2<i32>(0)) || any(neighbor_cell >= vec2<i32>(grid_size)) {
+                continue;
+            }
+            let cell_index = u32(neighbor_cell.y * grid_size + neighbor_cell.x);
+            let count = min(atomicLoad(&cell_counts[cell_index]), CELL_CAPACITY);
+            for (var slot = 0u; slot < count; slot++) {
+                let k = cell_particles[cell_index * CELL_CAPACITY + slot];
+                if k == index {
+                    continue;
+                }
+                let offset = position - predicted[k];
+                let distance = length(offset);
+                if distance < contact && distance > 0.000001 {
+                    let share = w / (w + inverse_mass[k]);
+                    add_delta(index, offset / distance * (contact - distance) * share);
+                }
+            }
+        }
+    }
+}
+
+@compute @workgroup_size(256)
+fn distance(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.constraint_count {
+        return;
+    }
+    let constraint = constraints[id.x];
+    let wa = inverse_mass[constraint.a];
+    let wb = inverse_mass[constraint.b];
+    if wa + wb == 0.0 {
+        return;
+    }
+    let offset = predicted[constraint.b] - predicted[constraint.a];
+    let distance = length(offset);
+    if distance < 0.000001 {
+        return;
+    }
+    let correction = offset / distance * (distance - constraint.rest_length) / (wa + wb);
+    add_delta(constraint.a, correction * wa);
+    add_delta(constraint.b, -correction * wb);
+}
+
+// Move by the average correction and clear the sums for the next iteration
+@compute @workgroup_size(256)
+fn apply(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let delta = vec2<f32>(
+        f32(atomicExchange(&deltas[3u * index], 0)),
+        f32(atomicExchange(&deltas[3u * index + 1u], 0)),
+    ) / DELTA_SCALE;
+    let count = atomicExchange(&deltas[3u * index + 2u], 0);
+    var position = predicted[index];
+    if count > 0 {
+        position += delta / f32(count) * RELAXATION;
+    }
+    let limit = vec2<f32>(BOUNDARY_LIMIT - params.radius);
+    predicted[index] = clamp(position, -limit, limit);
+}
+
+@compute @workgroup_size(256)
+fn finish(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = predicted[index];
+    let start = particles[index].position;
+    particles[index] = Particle(position, position - start);
+}
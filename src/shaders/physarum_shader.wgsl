@@ -0,0 +1,66 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct PhysarumParams {
+    particle_count: u32,
+    // Cells along each side of the trail
+    trail_size: u32,
+    frame: u32,
+    // Radians either side of the heading of the side sensors
+    sensor_angle: f32,
+    // Domain units ahead of the agent
+    sensor_distance: f32,
+    // Radians per frame
+    turn: f32,
+    // Domain units per frame
+    speed: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: PhysarumParams;
+// Row by row from the bottom of the domain, see trail_shader.wgsl
+@group(0) @binding(2) var<storage, read> trail: array<f32>;
+
+const TURN_STREAM: u32 = 3u;
+
+fn wrap(position: vec2<f32>) -> vec2<f32> {
+    return position - 2.0 * floor(position * 0.5 + 0.5);
+}
+
+fn sense(position: vec2<f32>, angle: f32) -> f32 {
+    let sensor = wrap(position + vec2<f32>(cos(angle), sin(angle)) * params.sensor_distance);
+    let size = params.trail_size;
+    let cell = min(vec2<u32>((sensor * 0.5 + 0.5) * f32(size)), vec2<u32>(size - 1u));
+    return trail[cell.y * size + cell.x];
+}
+
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var particle = particles[index];
+    var rng = random_seed(index, params.frame, TURN_STREAM);
+    // Agents that have stopped set off somewhere at random
+    var heading = random_f32(&rng) * 6.2831853;
+    if dot(particle.velocity, particle.velocity) > 0.0 {
+        heading = atan2(particle.velocity.y, particle.velocity.x);
+    }
+
+    let ahead = sense(particle.position, heading);
+    let left = sense(particle.position, heading + params.sensor_angle);
+    let right = sense(particle.position, heading - params.sensor_angle);
+    if ahead < left && ahead < right {
+        // Both sides stronger, pick one
+        heading += select(-params.turn, params.turn, random_f32(&rng) < 0.5);
+    } else if left > ahead {
+        heading += params.turn;
+    } else if right > ahead {
+        heading -= params.turn;
+    }
+
+    particle.velocity = vec2<f32>(cos(heading), sin(heading)) * params.speed;
+    particle.position = wrap(particle.position + particle.velocity);
+    particles[index] = particle;
+}
@@ -0,0 +1,102 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Texture coordinates, y down
+    @location(0) uv: vec2<f32>,
+};
+
+// Must match `PostParams` in post_fx.rs
+struct PostParams {
+    // The effect's own, see `Effect::values`
+    values: vec4<f32>,
+    // Seconds, on the background's clock
+    time: f32,
+    // Width over height of the frame
+    aspect: f32,
+};
+
+const TAU: f32 = 6.28318530718;
+
+// The frame so far: the scene, or the effect before
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(1) @binding(0) var<uniform> params: PostParams;
+// What the effect drew last frame, for those that feed back
+@group(2) @binding(0) var history: texture_2d<f32>;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return output;
+}
+
+// Onto the frame, once every effect has run
+@fragment
+fn fs_blit(input: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, input.uv);
+}
+
+// values: segments, turns in radians a second
+@fragment
+fn fs_kaleidoscope(input: VertexOutput) -> @location(0) vec4<f32> {
+    let wedge = TAU / max(params.values.x, 1.0);
+    // About the centre, in square units so the wedges aren't stretched
+    let scale = vec2<f32>(params.aspect, 1.0);
+    let p = (input.uv - 0.5) * scale;
+    var angle = atan2(p.y, p.x) - params.values.y * params.time;
+    angle -= wedge * floor(angle / wedge);
+    // Every other half wedge mirrored, so neighbours meet without a seam
+    angle = min(angle, wedge - angle);
+    let folded = vec2<f32>(cos(angle), sin(angle)) * length(p);
+    return textureSample(source, source_sampler, folded / scale + 0.5);
+}
+
+// `color` turned about the grey axis by `angle` radians
+fn turn_hue(color: vec3<f32>, angle: f32) -> vec3<f32> {
+    let axis = vec3<f32>(0.57735027);
+    let c = cos(angle);
+    return color * c + cross(axis, color) * sin(angle) + axis * dot(axis, color) * (1.0 - c);
+}
+
+// values: decay, zoom, rotation and hue shift in radians, each frame
+@fragment
+fn fs_feedback(input: VertexOutput) -> @location(0) vec4<f32> {
+    let scale = vec2<f32>(params.aspect, 1.0);
+    // Where last frame's pixel came from, so it grows and turns outwards
+    let p = (input.uv - 0.5) * scale / params.values.y;
+    let c = cos(params.values.z);
+    let s = sin(params.values.z);
+    let origin = vec2<f32>(c * p.x + s * p.y, c * p.y - s * p.x) / scale + 0.5;
+    let previous = textureSample(history, source_sampler, origin).rgb;
+    let trail = max(turn_hue(previous, params.values.w), vec3<f32>(0.0)) * params.values.x;
+    let color = textureSample(source, source_sampler, input.uv);
+    return vec4<f32>(max(color.rgb, trail), color.a);
+}
+
+// values: strength
+@fragment
+fn fs_chromatic_aberration(input: VertexOutput) -> @location(0) vec4<f32> {
+    // Along the way out from the centre, in uv units, so it grows towards the edges
+    let scale = vec2<f32>(params.aspect, 1.0);
+    let p = (input.uv - 0.5) * scale;
+    let offset = p * 2.0 * params.values.x / scale;
+    let color = textureSample(source, source_sampler, input.uv);
+    let red = textureSample(source, source_sampler, input.uv + offset).r;
+    let blue = textureSample(source, source_sampler, input.uv - offset).b;
+    return vec4<f32>(red, color.g, blue, color.a);
+}
+
+// values: strength
+@fragment
+fn fs_vignette(input: VertexOutput) -> @location(0) vec4<f32> {
+    let scale = vec2<f32>(params.aspect, 1.0);
+    // 1 at the corners
+    let distance = length((input.uv - 0.5) * scale) / length(scale * 0.5);
+    let shade = 1.0 - params.values.x * smoothstep(0.3, 1.0, distance);
+    let color = textureSample(source, source_sampler, input.uv);
+    return vec4<f32>(color.rgb * max(shade, 0.0), color.a);
+}
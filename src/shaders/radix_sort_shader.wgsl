@@ -0,0 +1,78 @@
+// One pass of a least significant digit radix sort over 8 bit digits. Four passes sort
+// 32 bit keys, ping-ponging between the caller's buffers and scratch ones.
+
+struct SortParams {
+    count: u32,
+    // Bit offset of this pass's digit
+    shift: u32,
+    // Workgroups of BLOCK_SIZE elements
+    block_count: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var<storage, read> keys_in: array<u32>;
+@group(0) @binding(1) var<storage, read> values_in: array<u32>;
+@group(0) @binding(2) var<storage, read_write> keys_out: array<u32>;
+@group(0) @binding(3) var<storage, read_write> values_out: array<u32>;
+// Digit-major counts per block, digit * block_count + block, which an exclusive scan in
+// between the passes turns into the first output index of each digit in each block
+@group(0) @binding(4) var<storage, read_write> histograms: array<u32>;
+@group(0) @binding(5) var<uniform> params: SortParams;
+
+// Must match BLOCK_SIZE in gpu/sort.rs
+const BLOCK_SIZE: u32 = 256u;
+const RADIX: u32 = 256u;
+// Marks the slots past the end in the last block
+const NO_DIGIT: u32 = 0xffffffffu;
+
+var<workgroup> counts: array<atomic<u32>, RADIX>;
+var<workgroup> digits: array<u32, BLOCK_SIZE>;
+
+fn digit(key: u32) -> u32 {
+    return (key >> params.shift) & (RADIX - 1u);
+}
+
+@compute @workgroup_size(256)
+fn histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) block: vec3<u32>,
+) {
+    atomicStore(&counts[local], 0u);
+    workgroupBarrier();
+    if id.x < params.count {
+        atomicAdd(&counts[digit(keys_in[id.x])], 1u);
+    }
+    workgroupBarrier();
+    histograms[local * params.block_count + block.x] = atomicLoad(&counts[local]);
+}
+
+@compute @workgroup_size(256)
+fn scatter(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) block: vec3<u32>,
+) {
+    var key = 0u;
+    var d = NO_DIGIT;
+    if id.x < params.count {
+        key = keys_in[id.x];
+        d = digit(key);
+    }
+    digits[local] = d;
+    workgroupBarrier();
+    if d == NO_DIGIT {
+        return;
+    }
+
+    // Earlier elements of the block with the same digit go first, which keeps the sort stable
+    var rank = 0u;
+    for (var i = 0u; i < local; i++) {
+        if digits[i] == d {
+            rank++;
+        }
+    }
+    let index = histograms[d * params.block_count + block.x] + rank;
+    keys_out[index] = key;
+    values_out[index] = values_in[id.x];
+}
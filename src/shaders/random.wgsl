@@ -0,0 +1,46 @@
+// Hash-based random numbers, included with `#include "random.wgsl"`.
+//
+// Each invocation seeds its own state from its particle index and the frame with
+// `random_seed`, then draws from it in turn, so nothing has to be uploaded and playback
+// draws the same numbers as the run it recorded.
+
+// PCG hash, from "Hash Functions for GPU Rendering" (Jarzynski and Olano)
+fn pcg(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// A different stream for every particle and frame. `stream` tells apart uses in the same
+// frame that shouldn't match, e.g. the noise and orientation stages
+fn random_seed(index: u32, frame: u32, stream: u32) -> u32 {
+    return pcg(index ^ pcg(frame ^ pcg(stream)));
+}
+
+fn random_u32(state: ptr<function, u32>) -> u32 {
+    *state = pcg(*state);
+    return *state;
+}
+
+// Uniform in 0..1, from the top 24 bits so every value is exact
+fn random_f32(state: ptr<function, u32>) -> f32 {
+    return f32(random_u32(state) >> 8u) / 16777216.0;
+}
+
+// Uniform in -1..1
+fn random_signed(state: ptr<function, u32>) -> f32 {
+    return random_f32(state) * 2.0 - 1.0;
+}
+
+// A random direction
+fn random_direction(state: ptr<function, u32>) -> vec2<f32> {
+    let angle = random_f32(state) * 6.28318531;
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+// Standard normal, by Box-Muller
+fn random_gaussian(state: ptr<function, u32>) -> f32 {
+    let u = max(random_f32(state), 1e-7);
+    let v = random_f32(state);
+    return sqrt(-2.0 * log(u)) * cos(6.28318531 * v);
+}
@@ -0,0 +1,113 @@
+#include "common.wgsl"
+
+struct ReactionParams {
+    particle_count: u32,
+    // Cells along each side of the grids
+    size: u32,
+    feed: f32,
+    kill: f32,
+    diffusion_a: f32,
+    diffusion_b: f32,
+    // B at least this high where particles are
+    seed: f32,
+    // Velocity change per unit of B's slope
+    follow: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ReactionParams;
+// (A, B) per cell, row by row from the bottom of the domain. `forward` steps `chemicals`
+// into `stepped` and `backward` steps it back
+@group(0) @binding(2) var<storage, read_write> chemicals: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read_write> stepped: array<vec2<f32>>;
+
+fn cell_of(position: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(floor((position * 0.5 + 0.5) * f32(params.size)));
+}
+
+// Wraps around, like the domain
+fn index_of(cell: vec2<i32>) -> u32 {
+    let size = i32(params.size);
+    let wrapped = (cell % size + size) % size;
+    return u32(wrapped.y * size + wrapped.x);
+}
+
+// Weights of each neighbour in the Laplacian, the centre's is -1
+fn weight(offset: vec2<i32>) -> f32 {
+    return select(0.2, 0.05, offset.x != 0 && offset.y != 0);
+}
+
+fn react(here: vec2<f32>, laplacian: vec2<f32>) -> vec2<f32> {
+    let reaction = here.x * here.y * here.y;
+    let a = here.x + params.diffusion_a * laplacian.x - reaction + params.feed * (1.0 - here.x);
+    let b = here.y + params.diffusion_b * laplacian.y + reaction - (params.kill + params.feed) * here.y;
+    return clamp(vec2<f32>(a, b), vec2<f32>(0.0), vec2<f32>(1.0));
+}
+
+@compute @workgroup_size(256)
+fn seed(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    if any(abs(position) >= vec2<f32>(1.0)) {
+        return;
+    }
+    // Every particle here writes the same thing, so races don't matter
+    let cell = index_of(cell_of(position));
+    chemicals[cell] = vec2<f32>(chemicals[cell].x, max(chemicals[cell].y, params.seed));
+}
+
+@compute @workgroup_size(16, 16)
+fn forward(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size || id.y >= params.size {
+        return;
+    }
+    let cell = vec2<i32>(id.xy);
+    let here = chemicals[index_of(cell)];
+    var laplacian = -here;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let offset = vec2<i32>(dx, dy);
+            if dx != 0 || dy != 0 {
+                laplacian += chemicals[index_of(cell + offset)] * weight(offset);
+            }
+        }
+    }
+    stepped[index_of(cell)] = react(here, laplacian);
+}
+
+@compute @workgroup_size(16, 16)
+fn backward(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size || id.y >= params.size {
+        return;
+    }
+    let cell = vec2<i32>(id.xy);
+    let here = stepped[index_of(cell)];
+    var laplacian = -here;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let offset = vec2<i32>(dx, dy);
+            if dx != 0 || dy != 0 {
+                laplacian += stepped[index_of(cell + offset)] * weight(offset);
+            }
+        }
+    }
+    chemicals[index_of(cell)] = react(here, laplacian);
+}
+
+@compute @workgroup_size(256)
+fn respond(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(particles[index].position);
+    // Central differences over two cells, each 2 / size domain units wide
+    let slope = vec2<f32>(
+        chemicals[index_of(cell + vec2<i32>(1, 0))].y - chemicals[index_of(cell - vec2<i32>(1, 0))].y,
+        chemicals[index_of(cell + vec2<i32>(0, 1))].y - chemicals[index_of(cell - vec2<i32>(0, 1))].y,
+    ) * f32(params.size) / 4.0;
+    particles[index].velocity += slope * params.follow;
+}
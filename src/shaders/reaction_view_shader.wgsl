@@ -0,0 +1,49 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+};
+
+struct Camera {
+    center: vec2<f32>,
+    zoom: f32,
+    // The domain's plane onto clip space, perspective divide last, see `Camera::view`
+    view: mat3x3<f32>,
+    // Clip space back onto the plane
+    inverse: mat3x3<f32>,
+};
+
+// Square grid of (A, B), row by row from the bottom of the domain
+@group(0) @binding(0) var<storage, read> chemicals: array<vec2<f32>>;
+@group(0) @binding(1) var<uniform> camera: Camera;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.clip = uv * 2.0 - 1.0;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let plane = camera.inverse * vec3<f32>(input.clip, 1.0);
+    let world = plane.xy / plane.z;
+    // Off the domain, or above a perspective camera's horizon
+    if plane.z <= 0.0 || any(abs(world) > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0);
+    }
+    let size = u32(sqrt(f32(arrayLength(&chemicals))));
+    let cell = min(vec2<u32>((world * 0.5 + 0.5) * f32(size)), vec2<u32>(size - 1u));
+    // B rarely goes much past 0.4, so that's full strength
+    let b = clamp(chemicals[cell.y * size + cell.x].y / 0.4, 0.0, 1.0);
+    // Deep teal through sea green to pale yellow
+    let color = mix(
+        mix(vec3<f32>(0.0, 0.15, 0.2), vec3<f32>(0.1, 0.6, 0.5), min(b * 2.0, 1.0)),
+        vec3<f32>(1.0, 0.95, 0.7),
+        max(b * 2.0 - 1.0, 0.0)
+    );
+    return vec4<f32>(color, smoothstep(0.0, 0.3, b));
+}
@@ -0,0 +1,90 @@
+// Work-efficient (Blelloch) exclusive prefix sum. Each workgroup scans a block of
+// BLOCK_SIZE elements in shared memory and writes the block's total out, the totals are
+// scanned the same way, then added back onto their blocks.
+
+struct ScanParams {
+    count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+// One total per block, scanned by the next level before `add_block_offsets` reads it back
+@group(0) @binding(1) var<storage, read_write> block_sums: array<u32>;
+@group(0) @binding(2) var<uniform> params: ScanParams;
+
+// Two elements per invocation. Must match BLOCK_SIZE in gpu/scan.rs
+const WORKGROUP_SIZE: u32 = 256u;
+const BLOCK_SIZE: u32 = 512u;
+
+var<workgroup> tree: array<u32, BLOCK_SIZE>;
+
+fn load(index: u32) -> u32 {
+    if index < params.count {
+        return data[index];
+    }
+    return 0u;
+}
+
+fn store(index: u32, value: u32) {
+    if index < params.count {
+        data[index] = value;
+    }
+}
+
+@compute @workgroup_size(256)
+fn scan_blocks(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) block: vec3<u32>,
+) {
+    let base = block.x * BLOCK_SIZE;
+    tree[local] = load(base + local);
+    tree[local + WORKGROUP_SIZE] = load(base + local + WORKGROUP_SIZE);
+
+    // Up-sweep: build partial sums in place, each level halving the invocations at work
+    var stride = 1u;
+    for (var width = WORKGROUP_SIZE; width > 0u; width = width >> 1u) {
+        workgroupBarrier();
+        if local < width {
+            let right = stride * (2u * local + 2u) - 1u;
+            tree[right] += tree[right - stride];
+        }
+        stride = stride << 1u;
+    }
+
+    // The root holds the block's total, swap in zero to make the scan exclusive
+    workgroupBarrier();
+    if local == 0u {
+        block_sums[block.x] = tree[BLOCK_SIZE - 1u];
+        tree[BLOCK_SIZE - 1u] = 0u;
+    }
+
+    // Down-sweep: push the sums of everything to the left back down the tree
+    for (var width = 1u; width <= WORKGROUP_SIZE; width = width << 1u) {
+        stride = stride >> 1u;
+        workgroupBarrier();
+        if local < width {
+            let right = stride * (2u * local + 2u) - 1u;
+            let left = right - stride;
+            let sum = tree[left];
+            tree[left] = tree[right];
+            tree[right] += sum;
+        }
+    }
+
+    workgroupBarrier();
+    store(base + local, tree[local]);
+    store(base + local + WORKGROUP_SIZE, tree[local + WORKGROUP_SIZE]);
+}
+
+@compute @workgroup_size(256)
+fn add_block_offsets(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) block: vec3<u32>,
+) {
+    let base = block.x * BLOCK_SIZE;
+    let offset = block_sums[block.x];
+    store(base + local, load(base + local) + offset);
+    store(base + local + WORKGROUP_SIZE, load(base + local + WORKGROUP_SIZE) + offset);
+}
@@ -0,0 +1,36 @@
+#include "common.wgsl"
+#include "flags.wgsl"
+
+struct SleepParams {
+    speed: f32,
+    frames: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+// Frames in a row each particle has stayed under the speed, up to the frames to sleep
+@group(0) @binding(2) var<storage, read_write> stills: array<u32>;
+@group(0) @binding(3) var<uniform> sleep: SleepParams;
+@group(0) @binding(4) var<storage, read_write> flags: array<u32>;
+
+// Count the frames a particle stays slow, and once there are enough stop it where it is and
+// mark it asleep. Whatever speeds it up again restarts the count, waking it
+@compute @workgroup_size(256)
+fn settle(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let speed = length(particles[index].velocity);
+    var still = 0u;
+    if speed <= sleep.speed * params.max_speed {
+        still = min(stills[index] + 1u, sleep.frames);
+    }
+    stills[index] = still;
+    if still >= sleep.frames {
+        particles[index].velocity = vec2<f32>(0.0);
+        flags[index] |= FLAG_ASLEEP;
+    } else {
+        flags[index] &= ~FLAG_ASLEEP;
+    }
+}
@@ -0,0 +1,73 @@
+#include "common.wgsl"
+
+struct SortKeyParams {
+    particle_count: u32,
+    // `SortKey::shader_index`
+    key: u32,
+    // Species, each ending where `ends` says
+    species: u32,
+};
+
+// Must match `Life` in lifetime_shader.wgsl
+struct Life {
+    age: f32,
+    lifetime: f32,
+    alpha: f32,
+    size: f32,
+    tint: vec4<f32>,
+};
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> lives: array<Life>;
+// One past the last particle of each species, in order
+@group(0) @binding(2) var<storage, read> ends: array<u32>;
+@group(0) @binding(3) var<storage, read_write> keys_out: array<u32>;
+@group(0) @binding(4) var<storage, read_write> values_out: array<u32>;
+@group(0) @binding(5) var<uniform> params: SortKeyParams;
+
+// The species goes in the top bits, so each stays in its own range, the key in the rest
+const KEY_BITS: u32 = 20u;
+const KEY_MASK: u32 = 0xfffffu;
+
+// Spread the low 10 bits of `v` out to the even bits
+fn part_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 8u)) & 0x00ff00ffu;
+    x = (x | (x << 4u)) & 0x0f0f0f0fu;
+    x = (x | (x << 2u)) & 0x33333333u;
+    x = (x | (x << 1u)) & 0x55555555u;
+    return x;
+}
+
+// Z-order index of the particle's cell in a 1024 by 1024 grid over the domain, those
+// outside it clamped to the edge
+fn cell_key(position: vec2<f32>) -> u32 {
+    let cell = vec2<u32>(clamp((position * 0.5 + 0.5) * 1024.0, vec2<f32>(0.0), vec2<f32>(1023.0)));
+    return part_bits(cell.x) | (part_bits(cell.y) << 1u);
+}
+
+// Fraction of its life lived, the dead last
+fn age_key(life: Life) -> u32 {
+    let lived = clamp(life.age / max(life.lifetime, 1e-6), 0.0, 1.0);
+    return min(u32(lived * f32(KEY_MASK)), KEY_MASK);
+}
+
+@compute @workgroup_size(256)
+fn keys(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var species = 0u;
+    while species + 1u < params.species && index >= ends[species] {
+        species++;
+    }
+    var key: u32;
+    if params.key == 1u {
+        key = age_key(lives[index]);
+    } else {
+        key = cell_key(particles[index].position);
+    }
+    keys_out[index] = (species << KEY_BITS) | (key & KEY_MASK);
+    values_out[index] = index;
+}
@@ -0,0 +1,35 @@
+#include "common.wgsl"
+
+struct Camera {
+    center: vec2<f32>,
+    zoom: f32,
+    // The domain's plane onto clip space, perspective divide last, see `Camera::view`
+    view: mat3x3<f32>,
+    // Clip space back onto the plane
+    inverse: mat3x3<f32>,
+};
+
+// Must match GRID_SIZE in density.rs and density_shader.wgsl
+const GRID_SIZE: u32 = 512u;
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> density: array<atomic<u32>>;
+@group(0) @binding(2) var<uniform> params: SimParams;
+@group(0) @binding(3) var<uniform> camera: Camera;
+
+// Count the particles landing in each screen-space cell
+@compute @workgroup_size(256)
+fn splat(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index: u32 = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+
+    let clip = (particles[index].position - camera.center) * camera.zoom;
+    if abs(clip.x) >= 1.0 || abs(clip.y) >= 1.0 {
+        return;
+    }
+
+    let cell = vec2<u32>((clip * 0.5 + 0.5) * f32(GRID_SIZE));
+    atomicAdd(&density[cell.y * GRID_SIZE + cell.x], 1u);
+}
@@ -0,0 +1,86 @@
+#include "common.wgsl"
+
+struct SpringParams {
+    constraint_count: u32,
+    stiffness: f32,
+    damping: f32,
+    gravity: f32,
+};
+
+struct Constraint {
+    a: u32,
+    b: u32,
+    rest_length: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> spring: SpringParams;
+@group(0) @binding(3) var<storage, read> constraints: array<Constraint>;
+// 0 pins a particle in place
+@group(0) @binding(4) var<storage, read> inverse_mass: array<f32>;
+// Force on each particle this substep, x then y, in units of 1 / FORCE_SCALE
+@group(0) @binding(5) var<storage, read_write> forces: array<atomic<i32>>;
+
+// Stiff springs stretched across the whole domain stay well inside the i32 range
+const FORCE_SCALE: f32 = 100000000.0;
+const BOUNDARY_LIMIT: f32 = 1.0;
+
+fn add_force(index: u32, force: vec2<f32>) {
+    atomicAdd(&forces[2u * index], i32(round(force.x * FORCE_SCALE)));
+    atomicAdd(&forces[2u * index + 1u], i32(round(force.y * FORCE_SCALE)));
+}
+
+@compute @workgroup_size(256)
+fn springs(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= spring.constraint_count {
+        return;
+    }
+    let constraint = constraints[id.x];
+    let a = particles[constraint.a];
+    let b = particles[constraint.b];
+    let offset = b.position - a.position;
+    let distance = length(offset);
+    if distance < 0.000001 {
+        return;
+    }
+    let direction = offset / distance;
+    // Hooke's law, damped along the spring so it doesn't ring forever
+    let stretch = distance - constraint.rest_length;
+    let closing_speed = dot(b.velocity - a.velocity, direction);
+    let force = direction * (spring.stiffness * stretch + spring.damping * closing_speed);
+    add_force(constraint.a, force);
+    add_force(constraint.b, -force);
+}
+
+@compute @workgroup_size(256)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    // Read and clear for the next substep in one go
+    let force = vec2<f32>(
+        f32(atomicExchange(&forces[2u * index], 0)),
+        f32(atomicExchange(&forces[2u * index + 1u], 0)),
+    ) / FORCE_SCALE;
+
+    var p = particles[index];
+    let w = inverse_mass[index];
+    if w == 0.0 {
+        return;
+    }
+    p.velocity += (force * w - vec2<f32>(0.0, spring.gravity)) * params.dt;
+    p.position += p.velocity * params.dt;
+
+    // Stop at the edges of the domain rather than bouncing
+    let clamped = clamp(p.position, vec2<f32>(-BOUNDARY_LIMIT), vec2<f32>(BOUNDARY_LIMIT));
+    if clamped.x != p.position.x {
+        p.velocity.x = 0.0;
+    }
+    if clamped.y != p.position.y {
+        p.velocity.y = 0.0;
+    }
+    p.position = clamped;
+    particles[index] = p;
+}
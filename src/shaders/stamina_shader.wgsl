@@ -0,0 +1,55 @@
+#include "common.wgsl"
+#include "random.wgsl"
+
+struct StaminaParams {
+    drain: f32,
+    recovery: f32,
+    rest: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+// Energy from 0 to 1, then 1 while resting until full and 0 otherwise
+@group(0) @binding(2) var<storage, read_write> energies: array<vec2<f32>>;
+@group(0) @binding(3) var<uniform> stamina: StaminaParams;
+
+// Seeds for `random_seed`
+const INIT_STREAM: u32 = 11u;
+
+// Anywhere from half to full, so the particles tire at different times
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&energies) {
+        return;
+    }
+    var rng = random_seed(index, 0u, INIT_STREAM);
+    energies[index] = vec2<f32>(0.5 + 0.5 * random_f32(&rng), 0.0);
+}
+
+// Spend energy by how near the top speed the particle goes and regain it by how far below,
+// then hold it to the top speed its energy leaves it
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var velocity = particles[index].velocity;
+    var energy = energies[index];
+    let speed = length(velocity);
+    let effort = clamp(speed / max(params.max_speed, 1e-6), 0.0, 1.0);
+    energy.x = clamp(energy.x + stamina.recovery * (1.0 - effort) - stamina.drain * effort, 0.0, 1.0);
+    if energy.x <= 0.0 {
+        energy.y = 1.0;
+    } else if energy.x >= 1.0 {
+        energy.y = 0.0;
+    }
+
+    let fraction = select(mix(stamina.rest, 1.0, energy.x), stamina.rest, energy.y > 0.0);
+    let top = params.max_speed * fraction;
+    if speed > top {
+        particles[index].velocity = velocity * (top / speed);
+    }
+    energies[index] = energy;
+}
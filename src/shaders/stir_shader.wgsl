@@ -0,0 +1,32 @@
+#include "common.wgsl"
+#include "mass.wgsl"
+
+struct StirParams {
+    particle_count: u32,
+    stir_count: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: StirParams;
+// Two for each stir: xy position and radius, then the velocity handed out at its centre
+@group(0) @binding(2) var<uniform> stirs: array<vec4<f32>, 32>;
+// Only for `particle_mass`
+@group(0) @binding(3) var<uniform> sim: SimParams;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let position = particles[index].position;
+    var push = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < params.stir_count; i++) {
+        let around = stirs[i * 2u];
+        let distance = length(position - around.xy);
+        if distance < around.z {
+            push += stirs[i * 2u + 1u].xy * (1.0 - distance / around.z);
+        }
+    }
+    particles[index].velocity += push / particle_mass(index, sim);
+}
@@ -0,0 +1,87 @@
+#include "common.wgsl"
+#include "palette.wgsl"
+
+struct TerritoryParams {
+    particle_count: u32,
+    bases: u32,
+    // Simulated seconds
+    time: f32,
+    // Seconds between each base's calls home
+    period: f32,
+    // Fraction of the period spent heading home
+    homing: f32,
+    // Velocity change per frame towards home
+    strength: f32,
+    // Distance inside which the pull eases off
+    radius: f32,
+};
+
+// Must match `Dye` in vertex_shader.wgsl. `emitter` is 1 more than the index of the base the
+// particle belongs to
+struct Dye {
+    color: vec3<f32>,
+    emitter: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: TerritoryParams;
+// x, y and hue of each base, then the point of its period it calls home at
+@group(0) @binding(2) var<uniform> bases: array<vec4<f32>, 8>;
+@group(0) @binding(3) var<storage, read_write> dyes: array<Dye>;
+// Particles by the base they belong to, then the base they're nearest, 8 by 8
+@group(0) @binding(4) var<storage, read_write> counts: array<atomic<u32>>;
+
+// Must match `MAX_BASES` in territory.rs
+const MAX_BASES: u32 = 8u;
+
+fn nearest(position: vec2<f32>) -> u32 {
+    var best = 0u;
+    var best_distance = 1e9;
+    for (var i = 0u; i < params.bases; i++) {
+        let distance = length(position - bases[i].xy);
+        if distance < best_distance {
+            best = i;
+            best_distance = distance;
+        }
+    }
+    return best;
+}
+
+// Every particle given to its nearest base, so the flock starts divided between them
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&dyes) || index >= arrayLength(&particles) {
+        return;
+    }
+    if params.bases == 0u {
+        dyes[index] = Dye(vec3<f32>(0.0), 0u);
+        return;
+    }
+    let base = nearest(particles[index].position);
+    dyes[index] = Dye(palette(bases[base].z), base + 1u);
+}
+
+@compute @workgroup_size(256)
+fn step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count || params.bases == 0u {
+        return;
+    }
+    let base = min(max(dyes[index].emitter, 1u) - 1u, params.bases - 1u);
+    let position = particles[index].position;
+    atomicAdd(&counts[base * MAX_BASES + nearest(position)], 1u);
+
+    // Called home for the first part of each period, from its base's turn on
+    let phase = fract(params.time / params.period - bases[base].w);
+    if phase >= params.homing {
+        return;
+    }
+    let offset = bases[base].xy - position;
+    let distance = length(offset);
+    if distance <= 0.0 {
+        return;
+    }
+    let ease = min(distance / params.radius, 1.0);
+    particles[index].velocity += offset / distance * params.strength * ease;
+}
@@ -0,0 +1,33 @@
+#include "common.wgsl"
+
+struct TextParams {
+    // Velocity change per frame towards the spot
+    strength: f32,
+    // Distance inside which the pull eases off
+    arrival: f32,
+    // Fraction of the velocity lost each frame at the spot
+    damping: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> text: TextParams;
+@group(0) @binding(3) var<storage, read> targets: array<vec2<f32>>;
+
+// Towards the particle's own spot, braking as it gets there so the glyphs fill in rather
+// than shimmer
+@compute @workgroup_size(256)
+fn seek(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let offset = targets[index] - particles[index].position;
+    let distance = length(offset);
+    let ease = min(distance / text.arrival, 1.0);
+    var velocity = particles[index].velocity * (1.0 - text.damping * (1.0 - ease));
+    if distance > 0.0 {
+        velocity += offset / distance * text.strength * ease;
+    }
+    particles[index].velocity = velocity;
+}
@@ -0,0 +1,77 @@
+#include "common.wgsl"
+
+struct ThermostatParams {
+    particle_count: u32,
+    // Mean squared speed to hold the particles at, i.e. the temperature
+    target_speed_sq: f32,
+    // Fraction of the way to the target covered each frame
+    rate: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: ThermostatParams;
+// Sum of squared speeds per `measure` workgroup
+@group(0) @binding(2) var<storage, read_write> partial_sums: array<f32>;
+// Velocity scale worked out by `total`
+@group(0) @binding(3) var<storage, read_write> scale: f32;
+
+const WORKGROUP_SIZE: u32 = 256u;
+
+var<workgroup> sums: array<f32, WORKGROUP_SIZE>;
+
+// Tree reduction of `sums` into sums[0]
+fn reduce(local: u32) {
+    workgroupBarrier();
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+        if local < stride {
+            sums[local] += sums[local + stride];
+        }
+        workgroupBarrier();
+    }
+}
+
+@compute @workgroup_size(256)
+fn measure(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+) {
+    var speed_sq = 0.0;
+    if id.x < params.particle_count {
+        let velocity = particles[id.x].velocity;
+        speed_sq = dot(velocity, velocity);
+    }
+    sums[local] = speed_sq;
+    reduce(local);
+    if local == 0u {
+        partial_sums[workgroup.x] = sums[0];
+    }
+}
+
+// Run as a single workgroup
+@compute @workgroup_size(256)
+fn total(@builtin(local_invocation_index) local: u32) {
+    let groups = (params.particle_count + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+    var sum = 0.0;
+    for (var i = local; i < groups; i = i + WORKGROUP_SIZE) {
+        sum += partial_sums[i];
+    }
+    sums[local] = sum;
+    reduce(local);
+    if local == 0u {
+        let mean_sq = sums[0] / f32(max(params.particle_count, 1u));
+        // Berendsen-style: rescale part of the way towards the target temperature
+        var s = 1.0;
+        if mean_sq > 0.0 {
+            s = sqrt(max(1.0 + params.rate * (params.target_speed_sq / mean_sq - 1.0), 0.0));
+        }
+        scale = s;
+    }
+}
+
+@compute @workgroup_size(256)
+fn rescale(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < params.particle_count {
+        particles[id.x].velocity *= scale;
+    }
+}
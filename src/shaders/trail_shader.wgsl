@@ -0,0 +1,82 @@
+#include "common.wgsl"
+
+struct TrailParams {
+    particle_count: u32,
+    // Cells along each side of the grid
+    size: u32,
+    // Left by each particle each frame
+    deposit: f32,
+    // Fraction of each cell swapped for its neighbours' average each frame
+    diffusion: f32,
+    // Fraction lost each frame
+    decay: f32,
+    // Velocity change per unit of slope, negative avoids the trail
+    follow: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: TrailParams;
+// Row by row from the bottom of the domain
+@group(0) @binding(2) var<storage, read> trail: array<f32>;
+// This frame's deposits in fixed point, cleared again by `diffuse`
+@group(0) @binding(3) var<storage, read_write> deposits: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read_write> diffused: array<f32>;
+
+// Deposits are summed as integers, so up to 65536 particles of a deposit of 1 fit in a cell
+const FIXED_POINT: f32 = 65536.0;
+
+fn cell_of(position: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(floor((position * 0.5 + 0.5) * f32(params.size)));
+}
+
+fn trail_at(cell: vec2<i32>) -> f32 {
+    let clamped = clamp(cell, vec2<i32>(0), vec2<i32>(i32(params.size) - 1));
+    return trail[u32(clamped.y) * params.size + u32(clamped.x)];
+}
+
+@compute @workgroup_size(256)
+fn sense(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(particles[index].position);
+    // Central differences over two cells, each 2 / size domain units wide
+    let slope = vec2<f32>(
+        trail_at(cell + vec2<i32>(1, 0)) - trail_at(cell - vec2<i32>(1, 0)),
+        trail_at(cell + vec2<i32>(0, 1)) - trail_at(cell - vec2<i32>(0, 1)),
+    ) * f32(params.size) / 4.0;
+    particles[index].velocity += slope * params.follow;
+}
+
+@compute @workgroup_size(256)
+fn deposit(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(particles[index].position);
+    if any(cell < vec2<i32>(0)) || any(cell >= vec2<i32>(i32(params.size))) {
+        return;
+    }
+    let amount = u32(max(params.deposit, 0.0) * FIXED_POINT);
+    atomicAdd(&deposits[u32(cell.y) * params.size + u32(cell.x)], amount);
+}
+
+@compute @workgroup_size(16, 16)
+fn diffuse(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size || id.y >= params.size {
+        return;
+    }
+    let cell = vec2<i32>(id.xy);
+    var sum = 0.0;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            sum += trail_at(cell + vec2<i32>(dx, dy));
+        }
+    }
+    let index = id.y * params.size + id.x;
+    let added = f32(atomicExchange(&deposits[index], 0u)) / FIXED_POINT;
+    let spread = mix(trail[index], sum / 9.0, params.diffusion);
+    diffused[index] = (spread + added) * (1.0 - params.decay);
+}
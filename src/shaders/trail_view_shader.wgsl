@@ -0,0 +1,53 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) clip: vec2<f32>,
+};
+
+struct Camera {
+    center: vec2<f32>,
+    zoom: f32,
+    // The domain's plane onto clip space, perspective divide last, see `Camera::view`
+    view: mat3x3<f32>,
+    // Clip space back onto the plane
+    inverse: mat3x3<f32>,
+};
+
+// Trail that maps to full brightness
+const FULL_TRAIL: f32 = 32.0;
+
+// Square, row by row from the bottom of the domain
+@group(0) @binding(0) var<storage, read> trail: array<f32>;
+@group(0) @binding(1) var<uniform> camera: Camera;
+
+// Full-screen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.clip = uv * 2.0 - 1.0;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let plane = camera.inverse * vec3<f32>(input.clip, 1.0);
+    let world = plane.xy / plane.z;
+    // Off the domain, or above a perspective camera's horizon
+    if plane.z <= 0.0 || any(abs(world) > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0);
+    }
+    let size = u32(sqrt(f32(arrayLength(&trail))));
+    let cell = min(vec2<u32>((world * 0.5 + 0.5) * f32(size)), vec2<u32>(size - 1u));
+    let value = max(trail[cell.y * size + cell.x], 0.0);
+    // Log scale, so faint trails show next to the main veins
+    let intensity = clamp(log2(1.0 + value) / log2(1.0 + FULL_TRAIL), 0.0, 1.0);
+    // Dark amber through yellow to white
+    let color = mix(
+        mix(vec3<f32>(0.3, 0.1, 0.0), vec3<f32>(1.0, 0.8, 0.2), min(intensity * 2.0, 1.0)),
+        vec3<f32>(1.0, 1.0, 1.0),
+        max(intensity * 2.0 - 1.0, 0.0)
+    );
+    return vec4<f32>(color, intensity);
+}
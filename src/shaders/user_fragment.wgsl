@@ -0,0 +1,34 @@
+// The interface for user fragment shaders. Each .wgsl file in the --user-shaders folder
+// defines
+//
+//     fn shade(input: ShadeInput) -> vec4<f32>
+//
+// which returns the colour to draw the fragment with, blended like the built-in shader's, and
+// is appended to this file. It can include the shared modules, e.g. `#include "noise.wgsl"`.
+// U cycles through them.
+
+struct ShadeInput {
+    // Across the particle's shape from 0 to 1, x from tail to tip. Dots are the circle in
+    // their square, fragments outside it aren't discarded
+    uv: vec2<f32>,
+    // In domain units per frame
+    velocity: vec2<f32>,
+    // From 0 when born to 1 at the end of its lifetime, always 0 without --lifetime
+    age: f32,
+    // Seconds, looping with --loop-frames like the background
+    time: f32,
+    // What the built-in shader draws
+    color: vec4<f32>,
+};
+
+// wgpu wants every output of the vertex shader taken, `local` included
+@fragment
+fn fs_main(
+    @location(0) color: vec4<f32>,
+    @location(1) local: vec2<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) velocity: vec2<f32>,
+    @location(4) age_time: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    return shade(ShadeInput(uv, velocity, age_time.x, age_time.y, color));
+}
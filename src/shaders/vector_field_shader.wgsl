@@ -0,0 +1,48 @@
+#include "common.wgsl"
+
+struct FieldParams {
+    particle_count: u32,
+    // Vectors in the field, stretched over the domain
+    width: u32,
+    height: u32,
+    // Domain units per frame for each unit of the field
+    scale: f32,
+    // Fraction of the difference from the field's velocity closed each frame
+    coupling: f32,
+    // How far from the first frame in `field` to the second
+    blend: f32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: FieldParams;
+// Two frames one after the other, each row by row from the bottom
+@group(0) @binding(2) var<storage, read> field: array<vec2<f32>>;
+
+fn field_cell(cell: vec2<i32>) -> vec2<f32> {
+    let size = vec2<i32>(i32(params.width), i32(params.height));
+    let clamped = clamp(cell, vec2<i32>(0), size - 1);
+    let index = clamped.y * size.x + clamped.x;
+    return mix(field[index], field[index + size.x * size.y], params.blend);
+}
+
+// Bilinear between the vectors, each at the centre of its cell, clamped at the edges
+fn sample_field(position: vec2<f32>) -> vec2<f32> {
+    let size = vec2<f32>(f32(params.width), f32(params.height));
+    let cell = (position * 0.5 + 0.5) * size - 0.5;
+    let base = floor(cell);
+    let f = cell - base;
+    let corner = vec2<i32>(base);
+    let bottom = mix(field_cell(corner), field_cell(corner + vec2<i32>(1, 0)), f.x);
+    let top = mix(field_cell(corner + vec2<i32>(0, 1)), field_cell(corner + vec2<i32>(1, 1)), f.x);
+    return mix(bottom, top, f.y);
+}
+
+@compute @workgroup_size(256)
+fn advect(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let flow = sample_field(particles[index].position) * params.scale;
+    particles[index].velocity += (flow - particles[index].velocity) * params.coupling;
+}
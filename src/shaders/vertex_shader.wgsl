@@ -1,3 +1,7 @@
+#include "random.wgsl"
+#include "flags.wgsl"
+#include "palette.wgsl"
+
 struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) velocity: vec2<f32>,
@@ -8,78 +12,411 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    // Across a dot from -1 to 1, 0 for every other shape
+    @location(1) local: vec2<f32>,
+    // What user fragment shaders get besides the colour, see user_fragment.wgsl. Across
+    // the shape from 0 to 1, x from tail to tip
+    @location(2) uv: vec2<f32>,
+    @location(3) velocity: vec2<f32>,
+    // Age from 0 to 1, 0 without a lifetime, then the time
+    @location(4) age_time: vec2<f32>,
 };
 
-@vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
-    // Get position and velocity for this instance
-    let position = input.position;
-    let velocity = input.velocity;
-    
-    // Normalize the velocity to get the direction vector
+struct Camera {
+    center: vec2<f32>,
+    zoom: f32,
+    // The domain's plane onto clip space, perspective divide last, see `Camera::view`
+    view: mat3x3<f32>,
+    // Clip space back onto the plane
+    inverse: mat3x3<f32>,
+};
+
+struct RenderParams {
+    // `ColorMode::shader_index`
+    color_mode: u32,
+    // Neighbour count drawn brightest when colouring by crowding
+    crowded: f32,
+    // 1 when instances are indexed like the particles, 0 when culled into a list of their own
+    flagged: u32,
+    // Seconds, on the background's clock
+    time: f32,
+    // 9 to draw each particle again in the copies of a periodic domain around it, 1 otherwise
+    copies: u32,
+    // `Palette::shader_index`
+    palette: u32,
+    // Ticks the drawn frame is behind the simulation, to draw the particles that far back
+    // along their velocity, see `FramePacer::lag`
+    lag: f32,
+    // World size of a particle before its species' scale, see `particle_size` in main.rs
+    size: f32,
+    // `Hues::shader_index`
+    hues: u32,
+    // `Cue::shader_index`
+    cue: u32,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> render: RenderParams;
+// Neighbours each particle counted in the last boids step, indexed like the particles
+@group(0) @binding(2) var<storage, read> neighbor_counts: array<u32>;
+
+// Must match `Dye` in emitters_shader.wgsl
+struct Dye {
+    color: vec3<f32>,
+    emitter: u32,
+};
+
+@group(0) @binding(3) var<storage, read> dyes: array<Dye>;
+// Each particle's bits from flags.rs, indexed like the particles
+@group(0) @binding(4) var<storage, read> flags: array<u32>;
+
+// Must match `SpeciesLook` in species.rs
+struct Look {
+    // `Shape::shader_index`
+    shape: u32,
+    // Relative to the usual boid
+    size_min: f32,
+    size_max: f32,
+    // How bright it's drawn under the brightness cue
+    shade: f32,
+};
+
+// Of the species being drawn, see `SpeciesConfig`
+@group(1) @binding(0) var<uniform> species: Look;
+
+const SIZE_STREAM: u32 = 8u;
+
+// Two triangles, as the vertex indices of a square or dot go
+const QUAD: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
+
+// Along the velocity, or +x when still
+fn velocity_direction(velocity: vec2<f32>) -> vec2<f32> {
     let speed = length(velocity);
     // Prevent division by zero
-    let direction = select(
+    return select(
         vec2<f32>(1.0, 0.0),  // Default direction if velocity is zero
         velocity / speed,      // Normalized velocity
         speed > 0.00001        // Condition: only normalize if speed is non-zero
     );
-    
-    // Size of the triangle
-    let boid_size: f32 = 0.009;
-    
-    // Define triangle vertices explicitly in local space
-    // Tip of triangle pointing toward positive X
-    var local_pos: vec2<f32>;
-    switch (input.vertex_index) {
-        case 0u: { local_pos = vec2<f32>(1.0, 0.0); }  // Tip
-        case 1u: { local_pos = vec2<f32>(-0.5, 0.5); } // Back left
-        case 2u: { local_pos = vec2<f32>(-0.5, -0.5); } // Back right
-        default: { local_pos = vec2<f32>(0.0, 0.0); }  // Fallback
-    }
-    
-    // Manual rotation calculation - rotate to align with velocity
-    // This is clearer than using a rotation matrix for debugging
-    var rotated_pos: vec2<f32>;
-    if speed > 0.00001 {
-        // If we have a non-zero velocity, align with it
-        if input.vertex_index == 0u {
-            // Tip of triangle - place in direction of velocity
-            rotated_pos = direction * boid_size;
-        } else if input.vertex_index == 1u {
-            // Back left - perpendicular to velocity, plus backward
-            rotated_pos = vec2<f32>(
-                -direction.x * 0.5 - direction.y * 0.5,
-                -direction.y * 0.5 + direction.x * 0.5
-            ) * boid_size;
-        } else {
-            // Back right - perpendicular to velocity, minus backward
-            rotated_pos = vec2<f32>(
-                -direction.x * 0.5 + direction.y * 0.5,
-                -direction.y * 0.5 - direction.x * 0.5
-            ) * boid_size;
-        }
-    } else {
-        // Fallback for zero velocity - just scale the local position
-        rotated_pos = local_pos * boid_size;
-    }
-    
-    // Apply the final position
-    let world_pos = position + rotated_pos;
+}
 
-    var output: VertexOutput;
-    output.clip_position = vec4<f32>(world_pos, 0.0, 1.0);
-    
+// Per-particle angle and angular velocity, see `Orientation`
+fn spin_direction(spin: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(cos(spin.x), sin(spin.x));
+}
+
+// Coloured by the velocity whichever way the particle faces
+fn velocity_color(velocity: vec2<f32>) -> vec3<f32> {
     // Debug coloring to see velocity direction:
     // - Red component shows x velocity
     // - Green component shows y velocity
     // - Blue shows overall speed
-    output.color = vec4<f32>(
+    let direction = velocity_direction(velocity);
+    return vec3<f32>(
         abs(direction.x),
         abs(direction.y),
-        speed * 100.0,  // Scale speed for visibility
-        1.0
+        length(velocity) * 100.0  // Scale speed for visibility
     );
+}
+
+// From still to the speed `velocity_color` shows brightest, 0 to 1
+fn speed_level(velocity: vec2<f32>) -> f32 {
+    return clamp(length(velocity) * 100.0, 0.0, 1.0);
+}
+
+// From alone to crowded, 0 to 1. Counts go on a log scale, so sparse flocks still show their
+// structure next to dense ones
+fn crowding_level(neighbors: u32) -> f32 {
+    return clamp(log2(1.0 + f32(neighbors)) / log2(1.0 + render.crowded), 0.0, 1.0);
+}
+
+// From dark blue when alone through magenta to pale yellow when crowded
+fn crowding_color(t: f32) -> vec3<f32> {
+    let low = mix(vec3<f32>(0.05, 0.05, 0.3), vec3<f32>(0.85, 0.2, 0.55), clamp(t * 2.0, 0.0, 1.0));
+    return mix(low, vec3<f32>(1.0, 0.95, 0.6), clamp(t * 2.0 - 1.0, 0.0, 1.0));
+}
+
+// The colour of the `index`th of something told apart, `wheel` unless `render.hues` picks
+// safe colours or greys. Must match `Hues::shader_index`
+fn category_color(wheel: vec3<f32>, index: u32) -> vec3<f32> {
+    if render.hues == 1u {
+        return safe_color(index);
+    }
+    if render.hues == 2u {
+        return vec3<f32>(grey_level(index));
+    }
+    return wheel;
+}
+
+// The colour of an amount from 0 to 1, `wheel` unless `render.hues` picks viridis or greys
+fn amount_color(wheel: vec3<f32>, t: f32) -> vec3<f32> {
+    if render.hues == 1u {
+        return viridis(t);
+    }
+    if render.hues == 2u {
+        return vec3<f32>(grey(t));
+    }
+    return wheel;
+}
+
+fn particle_color(input: VertexInput) -> vec3<f32> {
+    if render.color_mode == 1u {
+        let t = crowding_level(neighbor_counts[input.instance_index]);
+        return amount_color(crowding_color(t), t);
+    }
+    // Grey until an emitter has dyed it. The emitter, outbreak state or base that dyed it
+    // picks the colour under safe hues, as mixed dyes can't be told apart there anyway
+    if render.color_mode == 2u {
+        let dye = dyes[input.instance_index];
+        return select(vec3<f32>(0.3), category_color(dye.color, dye.emitter - 1u), dye.emitter != 0u);
+    }
+    return amount_color(velocity_color(input.velocity), speed_level(input.velocity));
+}
+
+// Darker the slower it goes and the later its species under the brightness cue, see `Cue`
+fn cued(color: vec3<f32>, velocity: vec2<f32>) -> vec3<f32> {
+    if render.cue != 1u {
+        return color;
+    }
+    return color * mix(0.35, 1.0, speed_level(velocity)) * species.shade;
+}
+
+// How much longer than usual along its heading it's drawn, longer the faster under the shape
+// cue
+fn stretch(velocity: vec2<f32>) -> f32 {
+    return select(1.0, mix(0.6, 1.8, speed_level(velocity)), render.cue == 2u);
+}
+
+// Darkened by how light it is under the ink palette, so pale colours still show on a light
+// background. Must match `Palette::paint`
+fn painted(color: vec3<f32>) -> vec3<f32> {
+    if render.palette == 0u {
+        return color;
+    }
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return color * (1.0 - 0.7 * min(luma, 1.0));
+}
+
+// A point on the domain's plane in clip space, through the camera's projection
+fn project(world: vec2<f32>) -> vec4<f32> {
+    let clip = camera.view * vec3<f32>(world, 1.0);
+    return vec4<f32>(clip.xy, 0.0, clip.z);
+}
+
+// How far out from the domain's edges its periodic copies are drawn
+const COPY_MARGIN: f32 = 0.25;
+
+// The particle where it was `render.lag` ticks ago, between its last two steps
+fn paced(input: VertexInput) -> VertexInput {
+    var moved = input;
+    moved.position -= input.velocity * render.lag;
+    return moved;
+}
 
+// The particle in one of the domain's copies, picked by whole shapes' worth of vertices: the
+// middle copy is the domain itself
+fn tiled(input: VertexInput) -> VertexInput {
+    let corners = select(6u, 3u, species.shape == 0u);
+    let copy = select(4u, input.vertex_index / corners, render.copies > 1u);
+    var moved = paced(input);
+    moved.vertex_index = input.vertex_index % corners;
+    moved.position += (vec2<f32>(f32(copy % 3u), f32(copy / 3u)) - 1.0) * 2.0;
+    return moved;
+}
+
+// Hidden particles moved off screen, selected ones drawn paler. Culled ones were only
+// kept when not hidden, and their instances no longer line up with their flags
+fn flagged(output: VertexOutput, input: VertexInput) -> VertexOutput {
+    var marked = output;
+    // Copies too far from the edges to join up with what's crossing them
+    if render.copies > 1u && any(abs(input.position) > vec2<f32>(1.0 + COPY_MARGIN)) {
+        marked.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    }
+    if render.flagged == 0u {
+        return marked;
+    }
+    let bits = flags[input.instance_index];
+    if (bits & FLAG_HIDDEN) != 0u {
+        marked.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    }
+    if (bits & FLAG_SELECTED) != 0u {
+        marked.color = vec4<f32>(mix(output.color.rgb, vec3<f32>(1.0), 0.6), output.color.a);
+    }
+    return marked;
+}
+
+// The shape for one particle, a triangle's tip pointing along `direction`
+fn boid(input: VertexInput, direction: vec2<f32>) -> VertexOutput {
+    // Size of the triangle, the same for the same particle every frame
+    var rng = random_seed(input.instance_index, 0u, SIZE_STREAM);
+    let boid_size: f32 = render.size * mix(species.size_min, species.size_max, random_f32(&rng));
+
+    var output: VertexOutput;
+    output.color = vec4<f32>(painted(cued(particle_color(input), input.velocity)), 1.0);
+    output.local = vec2<f32>(0.0);
+    output.velocity = input.velocity;
+    output.age_time = vec2<f32>(0.0, render.time);
+    let stretched = stretch(input.velocity);
+    if species.shape != 0u {
+        // Half as wide as the triangle is long
+        var quad = QUAD;
+        let local = quad[input.vertex_index % 6u];
+        let rotate = mat2x2<f32>(direction, vec2<f32>(-direction.y, direction.x));
+        let world_pos = input.position + rotate * (local * vec2<f32>(stretched, 1.0)) * boid_size * 0.5;
+        output.clip_position = project(world_pos);
+        output.uv = local * 0.5 + 0.5;
+        if species.shape == 2u {
+            output.local = local;
+        }
+        return output;
+    }
+
+    // Manual rotation calculation - rotate to align with the direction
+    // This is clearer than using a rotation matrix for debugging
+    var rotated_pos: vec2<f32>;
+    if input.vertex_index == 0u {
+        // Tip of triangle - place in the direction
+        rotated_pos = direction * boid_size * stretched;
+        output.uv = vec2<f32>(1.0, 0.5);
+    } else if input.vertex_index == 1u {
+        // Back left - perpendicular to the direction, plus backward
+        rotated_pos = vec2<f32>(
+            -direction.x * 0.5 - direction.y * 0.5,
+            -direction.y * 0.5 + direction.x * 0.5
+        ) * boid_size;
+        output.uv = vec2<f32>(0.0, 1.0);
+    } else {
+        // Back right - perpendicular to the direction, minus backward
+        rotated_pos = vec2<f32>(
+            -direction.x * 0.5 + direction.y * 0.5,
+            -direction.y * 0.5 - direction.x * 0.5
+        ) * boid_size;
+        output.uv = vec2<f32>(0.0, 0.0);
+    }
+
+    // Apply the final position
+    let world_pos = input.position + rotated_pos;
+
+    output.clip_position = project(world_pos);
     return output;
 }
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    let copy = tiled(input);
+    return flagged(boid(copy, velocity_direction(copy.velocity)), copy);
+}
+
+@vertex
+fn vs_oriented(input: VertexInput, @location(4) spin: vec2<f32>) -> VertexOutput {
+    let copy = tiled(input);
+    return flagged(boid(copy, spin_direction(spin)), copy);
+}
+
+// Per-particle alpha and size, and a tint used in place of the colour unless its alpha is 0,
+// see `Lifetime`
+struct Life {
+    @location(5) look: vec2<f32>,
+    @location(6) tint: vec4<f32>,
+    // The age and lifetime in seconds
+    @location(7) age: vec2<f32>,
+};
+
+// Shrunk towards the particle's position and faded by its `Life`
+fn aged(output: VertexOutput, input: VertexInput, life: Life) -> VertexOutput {
+    var faded = output;
+    // Before the perspective divide, which keeps it on the plane
+    faded.clip_position = mix(project(input.position), output.clip_position, life.look.y);
+    // Tints, like the bursts' colours, go to the safe colour nearest their hue
+    if life.tint.a > 0.0 {
+        let index = u32(round(wheel_hue(life.tint.rgb) * 7.0));
+        faded.color = vec4<f32>(category_color(life.tint.rgb, index), faded.color.a);
+    }
+    faded.color.a *= life.look.x;
+    faded.age_time.x = clamp(life.age.x / max(life.age.y, 1e-6), 0.0, 1.0);
+    return faded;
+}
+
+@vertex
+fn vs_aged(input: VertexInput, life: Life) -> VertexOutput {
+    let copy = tiled(input);
+    return flagged(aged(boid(copy, velocity_direction(copy.velocity)), copy, life), copy);
+}
+
+@vertex
+fn vs_oriented_aged(input: VertexInput, @location(4) spin: vec2<f32>, life: Life) -> VertexOutput {
+    let copy = tiled(input);
+    return flagged(aged(boid(copy, spin_direction(spin)), copy, life), copy);
+}
+
+// Must match `MeshVertex` in mesh.rs
+struct MeshVertex {
+    @location(2) position: vec3<f32>,
+    @location(3) normal: vec3<f32>,
+};
+
+// Lights meshes from the upper left, towards the screen
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(-0.4, 0.5, 0.77);
+
+// A loaded mesh in place of the triangle, turned about the screen's normal to face along
+// `direction` and flattened onto the screen
+fn mesh(input: VertexInput, vertex: MeshVertex, direction: vec2<f32>) -> VertexOutput {
+    let rotate = mat2x2<f32>(direction, vec2<f32>(-direction.y, direction.x));
+
+    let boid_size: f32 = render.size;
+    let stretched = vec2<f32>(stretch(input.velocity), 1.0);
+    let world_pos = input.position + rotate * (vertex.position.xy * stretched) * boid_size;
+    let normal = vec3<f32>(rotate * vertex.normal.xy, vertex.normal.z);
+    let light = 0.35 + 0.65 * max(dot(normal, LIGHT_DIRECTION), 0.0);
+
+    var output: VertexOutput;
+    output.clip_position = project(world_pos);
+    // Coloured like the triangles, shaded by the light
+    output.color = vec4<f32>(painted(cued(particle_color(input), input.velocity)) * light, 1.0);
+    output.local = vec2<f32>(0.0);
+    output.uv = vertex.position.xy * 0.5 + 0.5;
+    output.velocity = input.velocity;
+    output.age_time = vec2<f32>(0.0, render.time);
+    return output;
+}
+
+@vertex
+fn vs_mesh(given: VertexInput, vertex: MeshVertex) -> VertexOutput {
+    let input = paced(given);
+    return flagged(mesh(input, vertex, velocity_direction(input.velocity)), input);
+}
+
+@vertex
+fn vs_mesh_oriented(
+    given: VertexInput,
+    vertex: MeshVertex,
+    @location(4) spin: vec2<f32>,
+) -> VertexOutput {
+    let input = paced(given);
+    return flagged(mesh(input, vertex, spin_direction(spin)), input);
+}
+
+@vertex
+fn vs_mesh_aged(given: VertexInput, vertex: MeshVertex, life: Life) -> VertexOutput {
+    let input = paced(given);
+    let output = mesh(input, vertex, velocity_direction(input.velocity));
+    return flagged(aged(output, input, life), input);
+}
+
+@vertex
+fn vs_mesh_oriented_aged(
+    given: VertexInput,
+    vertex: MeshVertex,
+    @location(4) spin: vec2<f32>,
+    life: Life,
+) -> VertexOutput {
+    let input = paced(given);
+    return flagged(aged(mesh(input, vertex, spin_direction(spin)), input, life), input);
+}
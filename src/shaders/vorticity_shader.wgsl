@@ -0,0 +1,115 @@
+#include "common.wgsl"
+
+struct VorticityParams {
+    strength: f32,
+    // Cells along each side of the grid
+    grid: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<uniform> vorticity: VorticityParams;
+// x and y velocity summed in fixed point, then the count, of the particles in each cell, row
+// by row from the bottom of the domain
+@group(0) @binding(3) var<storage, read_write> cells: array<atomic<i32>>;
+// Curl of each cell's average velocity, anticlockwise positive
+@group(0) @binding(4) var<storage, read_write> curls: array<f32>;
+
+// Velocities are summed as integers, so a few million particles at the top speed fit in a
+// cell
+const VELOCITY_SCALE: f32 = 100000.0;
+
+// -1 outside the domain
+fn cell_of(position: vec2<f32>) -> vec2<i32> {
+    let cell = vec2<i32>(floor((position * 0.5 + 0.5) * f32(vorticity.grid)));
+    if any(cell < vec2<i32>(0)) || any(cell >= vec2<i32>(i32(vorticity.grid))) {
+        return vec2<i32>(-1);
+    }
+    return cell;
+}
+
+// Held to the grid, so the edges take one-sided differences
+fn cell_index(cell: vec2<i32>) -> u32 {
+    let held = clamp(cell, vec2<i32>(0), vec2<i32>(i32(vorticity.grid) - 1));
+    return u32(held.y) * vorticity.grid + u32(held.x);
+}
+
+fn average_velocity(cell: vec2<i32>) -> vec2<f32> {
+    let sums = cell_index(cell) * 3u;
+    let count = atomicLoad(&cells[sums + 2u]);
+    if count == 0 {
+        return vec2<f32>(0.0);
+    }
+    let sum = vec2<f32>(f32(atomicLoad(&cells[sums])), f32(atomicLoad(&cells[sums + 1u])));
+    return sum / (VELOCITY_SCALE * f32(count));
+}
+
+@compute @workgroup_size(256)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vorticity.grid * vorticity.grid {
+        return;
+    }
+    for (var i = 0u; i < 3u; i++) {
+        atomicStore(&cells[id.x * 3u + i], 0);
+    }
+}
+
+@compute @workgroup_size(256)
+fn deposit(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let p = particles[index];
+    let cell = cell_of(p.position);
+    if cell.x < 0 {
+        return;
+    }
+    let sums = cell_index(cell) * 3u;
+    let amounts = vec2<i32>(round(p.velocity * VELOCITY_SCALE));
+    atomicAdd(&cells[sums], amounts.x);
+    atomicAdd(&cells[sums + 1u], amounts.y);
+    atomicAdd(&cells[sums + 2u], 1);
+}
+
+// Central differences of the average velocities either side, in cells. Only ever compared
+// and used as a direction, so the cell size is left out
+@compute @workgroup_size(256)
+fn curl(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vorticity.grid * vorticity.grid {
+        return;
+    }
+    let cell = vec2<i32>(i32(id.x % vorticity.grid), i32(id.x / vorticity.grid));
+    let right = average_velocity(cell + vec2<i32>(1, 0));
+    let left = average_velocity(cell - vec2<i32>(1, 0));
+    let up = average_velocity(cell + vec2<i32>(0, 1));
+    let down = average_velocity(cell - vec2<i32>(0, 1));
+    curls[id.x] = 0.5 * ((right.y - left.y) - (up.x - down.x));
+}
+
+// Push each particle across the gradient of the curl's size, round its swirl and towards its
+// centre, by the curl there
+@compute @workgroup_size(256)
+fn confine(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.particle_count {
+        return;
+    }
+    let cell = cell_of(particles[index].position);
+    if cell.x < 0 {
+        return;
+    }
+    let gradient = 0.5 * vec2<f32>(
+        abs(curls[cell_index(cell + vec2<i32>(1, 0))])
+            - abs(curls[cell_index(cell - vec2<i32>(1, 0))]),
+        abs(curls[cell_index(cell + vec2<i32>(0, 1))])
+            - abs(curls[cell_index(cell - vec2<i32>(0, 1))]),
+    );
+    let size = length(gradient);
+    if size < 1e-9 {
+        return;
+    }
+    let towards = gradient / size;
+    let curl = curls[cell_index(cell)];
+    particles[index].velocity += vorticity.strength * curl * vec2<f32>(towards.y, -towards.x);
+}
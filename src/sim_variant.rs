@@ -0,0 +1,313 @@
+use clap::ValueEnum;
+use nannou::wgpu;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::diagnostics;
+use crate::particle_layout::ParticleLayout;
+
+const SHADER_SOURCE: &str = include_str!("./shaders/compute_shader.wgsl");
+// Lines in the shader source rewritten for each variant
+const WORKGROUP_SIZE_LINE: &str = "@compute @workgroup_size(256)";
+const NEIGHBORHOOD_LINE: &str = "const NEIGHBORHOOD: u32 = 0u;";
+const ALIGNMENT_LINE: &str = "const ALIGNMENT: bool = true;";
+const COHESION_LINE: &str = "const COHESION: bool = true;";
+const SEPARATION_LINE: &str = "const SEPARATION: bool = true;";
+const PISTON_LINE: &str = "const PISTON: bool = false;";
+const TILED_LINE: &str = "const TILED: bool = false;";
+const TILE_SIZE_LINE: &str = "const TILE_SIZE: u32 = 256u;";
+// The storage blocks in the shader, and what replaces them
+const FULL_PRECISION: &str = "alias Component = vec2<f32>;
+fn unpack(component: Component) -> vec2<f32> { return component; }
+fn pack(v: vec2<f32>) -> Component { return v; }";
+// Halves packed in pairs, since naga can't parse the f16 type yet. The packing builtins
+// need no device feature either.
+const HALF_PRECISION: &str = "alias Component = u32;
+fn unpack(component: Component) -> vec2<f32> { return unpack2x16float(component); }
+fn pack(v: vec2<f32>) -> Component { return pack2x16float(v); }";
+const INTERLEAVED: &str = "struct StoredParticle { position: Component, velocity: Component };
+fn load(index: u32) -> Particle {
+    let stored = particles_in[index];
+    return Particle(unpack(stored.position), unpack(stored.velocity));
+}
+fn store(index: u32, p: Particle) {
+    particles_out[index] = StoredParticle(pack(p.position), pack(p.velocity));
+}";
+const SEPARATE_ARRAYS: &str = "alias StoredParticle = Component;
+fn load(index: u32) -> Particle {
+    return Particle(unpack(particles_in[index]), unpack(particles_in[params.capacity + index]));
+}
+fn store(index: u32, p: Particle) {
+    particles_out[index] = pack(p.position);
+    particles_out[params.capacity + index] = pack(p.velocity);
+}";
+
+/// Which nearby particles count as neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Neighborhood {
+    Circle,
+    /// Cheaper range test, flocks look slightly boxier
+    Square,
+}
+
+/// Choices baked into the compute shader at pipeline creation, so the hot loop doesn't
+/// branch on uniforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimVariant {
+    pub workgroup_size: u32,
+    pub neighborhood: Neighborhood,
+    pub alignment: bool,
+    pub cohesion: bool,
+    pub separation: bool,
+    // Pressure demo: closed box with a movable wall, see `PressureGauge`
+    pub piston: bool,
+    // Stage neighbours through workgroup shared memory, see `simulate_boids`
+    pub tiled: bool,
+    // How the particle buffers are laid out, see `ParticleLayout`
+    pub half_precision: bool,
+    pub separate_arrays: bool,
+}
+
+impl Default for SimVariant {
+    fn default() -> Self {
+        SimVariant {
+            workgroup_size: 256,
+            neighborhood: Neighborhood::Circle,
+            alignment: true,
+            cohesion: true,
+            separation: true,
+            piston: false,
+            tiled: false,
+            half_precision: false,
+            separate_arrays: false,
+        }
+    }
+}
+
+impl SimVariant {
+    /// The compute shader source with this variant's constants substituted.
+    pub fn shader_source(&self) -> String {
+        self.substitute(SHADER_SOURCE)
+    }
+
+    // `source` with this variant's constants substituted, for compute_shader.wgsl or an
+    // edited copy that keeps the lines rewritten
+    fn substitute(&self, source: &str) -> String {
+        let neighborhood = match self.neighborhood {
+            Neighborhood::Circle => 0,
+            Neighborhood::Square => 1,
+        };
+        let precision = if self.half_precision {
+            HALF_PRECISION
+        } else {
+            FULL_PRECISION
+        };
+        let arrangement = if self.separate_arrays {
+            SEPARATE_ARRAYS
+        } else {
+            INTERLEAVED
+        };
+        source
+            .replace(FULL_PRECISION, precision)
+            .replace(INTERLEAVED, arrangement)
+            .replace(
+                WORKGROUP_SIZE_LINE,
+                &format!("@compute @workgroup_size({})", self.workgroup_size),
+            )
+            .replace(
+                NEIGHBORHOOD_LINE,
+                &format!("const NEIGHBORHOOD: u32 = {}u;", neighborhood),
+            )
+            .replace(
+                ALIGNMENT_LINE,
+                &format!("const ALIGNMENT: bool = {};", self.alignment),
+            )
+            .replace(
+                COHESION_LINE,
+                &format!("const COHESION: bool = {};", self.cohesion),
+            )
+            .replace(
+                SEPARATION_LINE,
+                &format!("const SEPARATION: bool = {};", self.separation),
+            )
+            .replace(
+                PISTON_LINE,
+                &format!("const PISTON: bool = {};", self.piston),
+            )
+            .replace(TILED_LINE, &format!("const TILED: bool = {};", self.tiled))
+            .replace(
+                TILE_SIZE_LINE,
+                &format!("const TILE_SIZE: u32 = {}u;", self.workgroup_size),
+            )
+    }
+
+    /// Identifies the variant in diagnostics, e.g. `compute_shader_wg256_circle_acs`, with
+    /// `_tiled` on the end for the shared memory kernel, `_half` for half precision and `_soa`
+    /// for separate arrays.
+    pub fn name(&self) -> String {
+        let neighborhood = match self.neighborhood {
+            Neighborhood::Circle => "circle",
+            Neighborhood::Square => "square",
+        };
+        let rules: String = [
+            (self.alignment, 'a'),
+            (self.cohesion, 'c'),
+            (self.separation, 's'),
+            (self.piston, 'p'),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, letter)| letter)
+        .collect();
+        format!(
+            "compute_shader_wg{}_{}_{}{}{}{}",
+            self.workgroup_size,
+            neighborhood,
+            rules,
+            if self.tiled { "_tiled" } else { "" },
+            if self.half_precision { "_half" } else { "" },
+            if self.separate_arrays { "_soa" } else { "" }
+        )
+    }
+
+    pub fn layout(&self) -> ParticleLayout {
+        ParticleLayout {
+            half_precision: self.half_precision,
+            separate_arrays: self.separate_arrays,
+        }
+    }
+
+    pub fn workgroups(&self, particle_count: u32) -> u32 {
+        particle_count.div_ceil(self.workgroup_size)
+    }
+}
+
+pub fn parse_workgroup_size(s: &str) -> Result<u32, String> {
+    let size: u32 = s
+        .parse()
+        .map_err(|_| format!("invalid workgroup size `{s}`"))?;
+    // 256 is the most invocations per workgroup wgpu guarantees
+    if !size.is_power_of_two() || !(32..=256).contains(&size) {
+        return Err(format!(
+            "workgroup size must be 32, 64, 128 or 256, got {size}"
+        ));
+    }
+    Ok(size)
+}
+
+/// Compiles simulation pipelines on first use and keeps them, so switching back to a
+/// variant is free.
+pub struct SimPipelines {
+    layout: wgpu::PipelineLayout,
+    pipelines: HashMap<SimVariant, wgpu::ComputePipeline>,
+    // Compiled in place of compute_shader.wgsl once reloaded
+    source: Option<String>,
+}
+
+impl SimPipelines {
+    pub fn new(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Simulate Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        SimPipelines {
+            layout,
+            pipelines: HashMap::new(),
+            source: None,
+        }
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, variant: SimVariant) -> &wgpu::ComputePipeline {
+        let (layout, source) = (&self.layout, self.source.as_deref());
+        self.pipelines.entry(variant).or_insert_with(|| {
+            // A reloaded shader only had to compile for the variant it was reloaded with
+            let reloaded = source.and_then(|source| {
+                compile(device, layout, variant, source)
+                    .map_err(|err| warn!("{err}\nUsing compute_shader.wgsl instead"))
+                    .ok()
+            });
+            reloaded.unwrap_or_else(|| {
+                compile(device, layout, variant, SHADER_SOURCE)
+                    .unwrap_or_else(|err| panic!("{err}"))
+            })
+        })
+    }
+
+    /// The source compiled, before each variant's constants are substituted: the last one
+    /// reloaded or compute_shader.wgsl.
+    pub fn source(&self) -> &str {
+        self.source.as_deref().unwrap_or(SHADER_SOURCE)
+    }
+
+    /// Compile `source` in place of compute_shader.wgsl, for live editing. When it doesn't
+    /// compile for `variant`, returns the error, naga's for bad WGSL, and keeps the pipelines
+    /// already compiled.
+    pub fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        variant: SimVariant,
+        source: String,
+    ) -> Result<(), String> {
+        let pipeline = compile(device, &self.layout, variant, &source)?;
+        self.pipelines.clear();
+        self.pipelines.insert(variant, pipeline);
+        self.source = Some(source);
+        Ok(())
+    }
+}
+
+fn compile(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    variant: SimVariant,
+    source: &str,
+) -> Result<wgpu::ComputePipeline, String> {
+    let name = variant.name();
+    let shader = diagnostics::try_shader(device, &name, &variant.substitute(source))?;
+    diagnostics::try_checked(device, &format!("Simulate Pipeline ({name})"), || {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Simulate Pipeline"),
+            layout: Some(layout),
+            module: &shader,
+            entry_point: "simulate_boids",
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workgroups_cover_every_particle() {
+        for workgroup_size in [32, 64, 128, 256] {
+            let variant = SimVariant {
+                workgroup_size,
+                ..SimVariant::default()
+            };
+            assert_eq!(variant.workgroups(0), 0);
+            assert_eq!(variant.workgroups(1), 1);
+            assert_eq!(variant.workgroups(workgroup_size), 1);
+            assert_eq!(variant.workgroups(workgroup_size + 1), 2);
+            for count in [1000, 4096, 100_001, crate::MAX_PARTICLES] {
+                let workgroups = variant.workgroups(count);
+                // Enough invocations, and less than a workgroup of them to spare
+                assert!(workgroups * workgroup_size >= count);
+                assert!((workgroups - 1) * workgroup_size < count);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_workgroup_sizes() {
+        assert_eq!(parse_workgroup_size("64"), Ok(64));
+        assert_eq!(parse_workgroup_size("256"), Ok(256));
+        for size in ["16", "96", "512", "0", "big"] {
+            assert!(parse_workgroup_size(size).is_err(), "{size}");
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use nannou::wgpu::{self, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::mass::{self, MassConfig};
+use crate::modulation::{self, Modulator, Target};
+use crate::particle_system::BoidsParams;
+use crate::resources::GpuResources;
+use crate::sim_variant::{SimPipelines, SimVariant};
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::SimParams;
+
+/// How near neighbours are for every rule unless set apart, in domain units.
+pub const PERCEPTION_RADIUS: f32 = 0.09;
+
+/// Neighbours within `radius` each of `particle_count` particles would count if they were
+/// spread evenly over the domain, which spans -1..1 on both axes.
+pub fn even_neighbors(particle_count: u32, radius: f32) -> f32 {
+    particle_count as f32 * std::f32::consts::PI * radius * radius / 4.0
+}
+
+/// How fast boids may go, as soft limits: speed outside them dies away over a few frames.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedLimits {
+    // Top speed, in domain units per frame
+    pub max: f32,
+    // Cruising speed boids speed back up to when slower
+    pub min: f32,
+    // Fraction of the speed over `max` or under `min` lost each frame, 1 for hard limits
+    pub decay: f32,
+}
+
+impl Default for SpeedLimits {
+    fn default() -> Self {
+        SpeedLimits {
+            max: 0.005,
+            min: 0.001,
+            decay: 0.5,
+        }
+    }
+}
+
+/// How strongly each boids rule steers, as multiples of its usual strength.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleWeights {
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+}
+
+impl Default for RuleWeights {
+    fn default() -> Self {
+        RuleWeights {
+            alignment: 1.0,
+            cohesion: 1.0,
+            separation: 1.0,
+        }
+    }
+}
+
+/// How near neighbours have to be for each boids rule to count them, in domain units. Classic
+/// boids keeps separation short and lets alignment and cohesion reach further.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleRadii {
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub separation: f32,
+}
+
+impl RuleRadii {
+    /// The furthest any rule looks, which bounds the neighbours counted.
+    pub fn reach(&self) -> f32 {
+        self.alignment.max(self.cohesion).max(self.separation)
+    }
+}
+
+impl Default for RuleRadii {
+    fn default() -> Self {
+        RuleRadii {
+            alignment: PERCEPTION_RADIUS,
+            cohesion: PERCEPTION_RADIUS,
+            separation: PERCEPTION_RADIUS,
+        }
+    }
+}
+
+/// Some of the boids lead: they follow the goal's route, or a meandering one without a goal,
+/// and the rest weigh them more in cohesion, so the flock migrates the way they go instead of
+/// milling about. Which ones lead is hashed from their index, see leaders.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeaderConfig {
+    // Fraction of the particles that lead
+    pub fraction: f32,
+    // How many followers a leader counts as in cohesion
+    pub weight: f32,
+}
+
+impl Default for LeaderConfig {
+    fn default() -> Self {
+        LeaderConfig {
+            fraction: 0.05,
+            weight: 10.0,
+        }
+    }
+}
+
+/// Parse `fraction[,weight]`, e.g. `0.05,10`.
+pub fn parse_leaders(s: &str) -> Result<LeaderConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid leaders `{s}`"))?;
+    let weight = match values[..] {
+        [fraction] if (0.0..=1.0).contains(&fraction) => LeaderConfig::default().weight,
+        [fraction, weight] if (0.0..=1.0).contains(&fraction) && weight >= 0.0 => weight,
+        _ => return Err(format!("expected leaders like 0.05,10, got `{s}`")),
+    };
+    Ok(LeaderConfig {
+        fraction: values[0],
+        weight,
+    })
+}
+
+/// A rectangle of the domain the boids step takes `substeps` times as many substeps in as
+/// everywhere else, e.g. around an attractor the user is playing with, so a large domain can
+/// run coarsely where accuracy matters less. Particles outside are left where they are for
+/// the extra substeps, so those skip the neighbour loop.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionOfInterest {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    // Substeps inside for each one outside
+    pub substeps: u32,
+}
+
+impl Default for RegionOfInterest {
+    fn default() -> Self {
+        RegionOfInterest {
+            min: [-0.25, -0.25],
+            max: [0.25, 0.25],
+            substeps: 4,
+        }
+    }
+}
+
+/// Parse a rectangle `x0,y0,x1,y1` in domain units, e.g. `-0.5,-0.5,0.5,0.5`, corners either
+/// way round.
+pub fn parse_rect(s: &str) -> Result<[[f32; 2]; 2], String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid rectangle `{s}`"))?;
+    match values[..] {
+        [x0, y0, x1, y1] => Ok([[x0.min(x1), y0.min(y1)], [x0.max(x1), y0.max(y1)]]),
+        _ => Err(format!("expected a rectangle like x0,y0,x1,y1, got `{s}`")),
+    }
+}
+
+/// The boids step: one compute dispatch per substep, ping-ponging between the particle
+/// buffers, and more in the `RegionOfInterest` when set.
+pub struct Simulation {
+    bindings: BindingLayout,
+    // Indexed by the particle buffer read from, see `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // `SimParams` for the extra substeps in the region of interest, with bind groups over it
+    // indexed like `bind_groups`
+    roi_params: UniformBuffer<SimParams>,
+    roi_bind_groups: [wgpu::BindGroup; 2],
+    pipelines: SimPipelines,
+    // x of the right wall, which the piston variant lets move
+    pub piston: f32,
+    // How particle masses are spread, or all 1 without
+    pub mass: Option<MassConfig>,
+    pub speed_limits: SpeedLimits,
+    pub weights: RuleWeights,
+    pub radii: RuleRadii,
+    pub leaders: Option<LeaderConfig>,
+    // Degrees either side of its heading a boid sees neighbours within, all round unless set
+    pub view_angle: Option<f32>,
+    pub roi: Option<RegionOfInterest>,
+    // Swing the weights and speed limits over time, see `Modulator`
+    pub modulators: Vec<Modulator>,
+}
+
+impl Simulation {
+    /// Also builds the pipeline for `variant` up front, so the first frame doesn't stall.
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, variant: SimVariant) -> Self {
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_ro(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .storage_ro(5)
+            .build(device, "Simulate");
+        let roi_params = UniformBuffer::new(device, resources, "Region Of Interest Params Buffer");
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            resources.params.as_entire_binding(),
+        );
+        let roi_bind_groups = bind(device, resources, &bindings, roi_params.binding());
+        let mut pipelines = SimPipelines::new(device, bindings.layout());
+        pipelines.get(device, variant);
+        Simulation {
+            bindings,
+            bind_groups,
+            roi_params,
+            roi_bind_groups,
+            pipelines,
+            piston: 1.0,
+            mass: None,
+            speed_limits: SpeedLimits::default(),
+            weights: RuleWeights::default(),
+            radii: RuleRadii::default(),
+            leaders: None,
+            view_angle: None,
+            roi: None,
+            modulators: Vec::new(),
+        }
+    }
+
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &GpuResources) {
+        let params = resources.params.as_entire_binding();
+        self.bind_groups = bind(device, resources, &self.bindings, params);
+        let roi_params = self.roi_params.binding();
+        self.roi_bind_groups = bind(device, resources, &self.bindings, roi_params);
+    }
+
+    /// The parameters `parameters::PARAMETERS` names, as run with `variant`.
+    pub fn boids_params(&self, variant: SimVariant) -> BoidsParams {
+        BoidsParams {
+            weights: self.weights,
+            radii: self.radii,
+            speed_limits: self.speed_limits,
+            view_angle: self.view_angle,
+            variant,
+        }
+    }
+
+    /// Take all but the variant from `params`, which callers switch themselves.
+    pub fn set_boids_params(&mut self, params: &BoidsParams) {
+        self.weights = params.weights;
+        self.radii = params.radii;
+        self.speed_limits = params.speed_limits;
+        self.view_angle = params.view_angle;
+    }
+
+    pub fn shader_source(&self) -> &str {
+        self.pipelines.source()
+    }
+
+    /// Simulate with `source` in place of compute_shader.wgsl from now on, see
+    /// `SimPipelines::reload`.
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        variant: SimVariant,
+        source: String,
+    ) -> Result<(), String> {
+        self.pipelines.reload(device, variant, source)
+    }
+
+    /// Write the `SimParams` for the next steps, modulated as at `time` simulated seconds.
+    /// Without a `particle_count` the one already on the GPU is kept, for counts the GPU
+    /// changes itself, see `Compactor`.
+    pub fn write_params(
+        &self,
+        queue: &wgpu::Queue,
+        resources: &GpuResources,
+        particle_count: Option<u32>,
+        substeps: u32,
+        time: f32,
+    ) {
+        let roi = self.roi.filter(|roi| roi.substeps > 1);
+        let modulated = |target| modulation::factor(&self.modulators, target, time);
+        let max_speed = self.speed_limits.max * modulated(Target::MaxSpeed);
+        let min_speed = self.speed_limits.min * modulated(Target::MinSpeed);
+        let mut params = SimParams {
+            particle_count: particle_count.unwrap_or_default(),
+            dt: 1.0 / substeps as f32,
+            piston: self.piston,
+            capacity: resources.capacity(),
+            mass_distribution: mass::shader_distribution(self.mass.as_ref()),
+            mass_min: self.mass.map_or(1.0, |mass| mass.min),
+            mass_max: self.mass.map_or(1.0, |mass| mass.max),
+            max_speed,
+            min_speed: min_speed.min(max_speed),
+            speed_decay: self.speed_limits.decay,
+            alignment_weight: self.weights.alignment * modulated(Target::Alignment),
+            cohesion_weight: self.weights.cohesion * modulated(Target::Cohesion),
+            separation_weight: self.weights.separation * modulated(Target::Separation),
+            roi: roi.is_some() as u32,
+            roi_only: 0,
+            roi_dt: 1.0 / (substeps * roi.map_or(1, |roi| roi.substeps)) as f32,
+            roi_min: roi.map_or([0.0; 2], |roi| roi.min),
+            roi_max: roi.map_or([0.0; 2], |roi| roi.max),
+            view_cos: self
+                .view_angle
+                .filter(|&angle| angle < 180.0)
+                .map_or(-1.0, |angle| angle.to_radians().cos()),
+            alignment_radius: self.radii.alignment,
+            cohesion_radius: self.radii.cohesion,
+            separation_radius: self.radii.separation,
+            leader_fraction: self.leaders.map_or(0.0, |leaders| leaders.fraction),
+            leader_weight: self.leaders.map_or(1.0, |leaders| leaders.weight),
+        };
+        // `particle_count` comes first
+        let skipped = match particle_count {
+            Some(_) => 0,
+            None => std::mem::size_of::<u32>(),
+        };
+        queue.write_buffer(
+            &resources.params,
+            skipped as wgpu::BufferAddress,
+            &bytemuck::bytes_of(&params)[skipped..],
+        );
+        if roi.is_some() {
+            params.roi_only = 1;
+            self.roi_params.write(queue, &params);
+        }
+    }
+
+    /// Encode `substeps` steps for the first `particle_count` particles, leaving the result
+    /// in `resources.particles()`.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+        variant: SimVariant,
+        particle_count: u32,
+        substeps: u32,
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass"),
+        });
+        compute_pass.set_pipeline(self.pipelines.get(device, variant));
+        let workgroups_x = variant.workgroups(particle_count);
+
+        // Each dispatch reads the previous substep's output. The region of interest takes its
+        // first substep along with everything else, then the rest on its own
+        let roi_substeps = self.roi.map_or(1, |roi| roi.substeps.max(1));
+        for _ in 0..substeps {
+            for roi_substep in 0..roi_substeps {
+                let bind_groups = if roi_substep == 0 {
+                    &self.bind_groups
+                } else {
+                    &self.roi_bind_groups
+                };
+                compute_pass.set_bind_group(0, &bind_groups[resources.current()], &[]);
+                compute_pass.dispatch_workgroups(workgroups_x, 1, 1);
+                resources.swap();
+            }
+        }
+    }
+}
+
+impl Stage for Simulation {
+    fn kind(&self) -> StageKind {
+        StageKind::Boids
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        Simulation::encode(
+            self,
+            frame.device,
+            encoder,
+            resources,
+            frame.variant,
+            frame.particle_count,
+            frame.substeps,
+        );
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        Simulation::rebind(self, device, resources);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params: wgpu::BindingResource,
+) -> [wgpu::BindGroup; 2] {
+    let [a, b] = resources.particle_buffers();
+    [(a, b), (b, a)].map(|(src, dst)| {
+        bindings.bind_group(
+            device,
+            &[
+                src.as_entire_binding(),
+                params.clone(),
+                dst.as_entire_binding(),
+                resources.wall_impulse.as_entire_binding(),
+                resources.neighbor_counts.as_entire_binding(),
+                resources.flags.as_entire_binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rectangles_corners_either_way_round() {
+        let expected = [[-0.5, -0.25], [0.5, 0.75]];
+        assert_eq!(parse_rect("-0.5,-0.25,0.5,0.75"), Ok(expected));
+        assert_eq!(parse_rect("0.5,0.75,-0.5,-0.25"), Ok(expected));
+        assert_eq!(parse_rect("0.5, -0.25, -0.5, 0.75"), Ok(expected));
+        assert!(parse_rect("0,0,1").is_err());
+        assert!(parse_rect("a,b,c,d").is_err());
+    }
+
+    #[test]
+    fn parses_leaders_within_range() {
+        assert_eq!(
+            parse_leaders("0.1"),
+            Ok(LeaderConfig {
+                fraction: 0.1,
+                ..LeaderConfig::default()
+            })
+        );
+        assert_eq!(
+            parse_leaders("0.2,5"),
+            Ok(LeaderConfig {
+                fraction: 0.2,
+                weight: 5.0
+            })
+        );
+        for leaders in ["1.5", "-0.1", "0.1,-1", "0.1,2,3", ""] {
+            assert!(parse_leaders(leaders).is_err(), "{leaders}");
+        }
+    }
+
+    #[test]
+    fn reach_is_the_furthest_radius() {
+        let radii = RuleRadii {
+            alignment: 0.1,
+            cohesion: 0.2,
+            separation: 0.03,
+        };
+        assert_eq!(radii.reach(), 0.2);
+        assert_eq!(RuleRadii::default().reach(), PERCEPTION_RADIUS);
+    }
+
+    #[test]
+    fn even_neighbors_scale_with_the_area() {
+        // The whole domain's area is 4, so a radius covering it counts everyone
+        let radius = (4.0 / std::f32::consts::PI).sqrt();
+        assert!((even_neighbors(1000, radius) - 1000.0).abs() < 1e-2);
+        let ratio = even_neighbors(1000, 0.2) / even_neighbors(1000, 0.1);
+        assert!((ratio - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn configs_fill_missing_fields_with_defaults() {
+        let limits: SpeedLimits = serde_json::from_str(r#"{"max":0.01}"#).unwrap();
+        assert_eq!(
+            limits,
+            SpeedLimits {
+                max: 0.01,
+                ..SpeedLimits::default()
+            }
+        );
+        let weights: RuleWeights = serde_json::from_str("{}").unwrap();
+        assert_eq!(weights, RuleWeights::default());
+        let roi = RegionOfInterest {
+            substeps: 8,
+            ..RegionOfInterest::default()
+        };
+        let json = serde_json::to_string(&roi).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RegionOfInterest>(&json).unwrap(),
+            roi
+        );
+    }
+}
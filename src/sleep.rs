@@ -0,0 +1,268 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SleepConfig {
+    // Fraction of the top speed a particle has to stay under to fall asleep
+    pub speed: f32,
+    // Frames in a row it has to stay under it
+    pub frames: u32,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        SleepConfig {
+            speed: 0.02,
+            frames: 60,
+        }
+    }
+}
+
+/// Parse `speed[,frames]`, e.g. `0.02,60`.
+pub fn parse_sleep(s: &str) -> Result<SleepConfig, String> {
+    let (speed, frames) = match s.split_once(',') {
+        Some((speed, frames)) => (speed, Some(frames)),
+        None => (s, None),
+    };
+    let speed = speed
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid sleep speed `{speed}`"))?;
+    let frames = match frames {
+        Some(frames) => frames
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid sleep frames `{frames}`"))?,
+        None => SleepConfig::default().frames,
+    };
+    if !(0.0..=1.0).contains(&speed) || frames == 0 {
+        return Err(format!(
+            "expected a speed fraction of 0 to 1 and at least 1 frame, got `{s}`"
+        ));
+    }
+    Ok(SleepConfig { speed, frames })
+}
+
+wgsl_struct! {
+    // Must match `SleepParams` in sleep_shader.wgsl
+    struct SleepParams {
+        speed: f32,
+        frames: u32,
+    }
+}
+
+/// Puts particles that have barely moved for a while to sleep, stopping them and marking
+/// them `flags::ASLEEP`, which the boids step skips, neighbours and all. Settled granular
+/// scenes, piled up under gravity behind walls, then cost little more than the particles
+/// still moving. Anything that moves a sleeper again, a collision, an impulse or a force,
+/// wakes it on the next frame, though the boids around it see it as standing still.
+///
+/// The frames each particle has been still are kept in a buffer of their own, indexed like
+/// the particles, so like `Stamina` it doesn't go with compaction, and every particle starts
+/// awake again when the buffers are resized.
+pub struct Sleep {
+    pub config: SleepConfig,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<SleepParams>,
+    stills: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Sleep {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: SleepConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "sleep_shader",
+            include_str!("./shaders/sleep_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .storage_rw(4)
+            .build(device, "Sleep");
+        let params_buffer = UniformBuffer::new(device, resources, "Sleep Params Buffer");
+        let (stills, bind_groups) = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sleep Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Sleep Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Sleep Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "settle",
+            })
+        });
+
+        Sleep {
+            config,
+            pipeline,
+            bindings,
+            params_buffer,
+            stills,
+            bind_groups,
+        }
+    }
+
+    /// Frames each particle has been still, for moving them along with the particles.
+    pub fn stills_buffer(&self) -> &wgpu::Buffer {
+        &self.stills
+    }
+}
+
+impl Stage for Sleep {
+    fn kind(&self) -> StageKind {
+        StageKind::Sleep
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = SleepParams {
+            speed: self.config.speed.clamp(0.0, 1.0),
+            frames: self.config.frames.max(1),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Sleep Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.stills, self.bind_groups) =
+            bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<SleepParams>,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let stills = resources.buffer(
+        device,
+        "Sleep Stills Buffer",
+        resources.capacity() as wgpu::BufferAddress
+            * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                stills.as_entire_binding(),
+                params_buffer.binding(),
+                resources.flags.as_entire_binding(),
+            ],
+        )
+    });
+    (stills, bind_groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_variant::SimVariant;
+    use crate::{flags, gpu, headless, Particle, SimParams};
+
+    fn step(sleep: &mut Sleep, resources: &mut GpuResources, frame: &FrameContext) {
+        let mut encoder = frame
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Sleep Test Encoder"),
+            });
+        sleep.encode(frame, &mut encoder, resources);
+        frame.queue.submit(Some(encoder.finish()));
+    }
+
+    #[test]
+    fn sleeps_below_the_speed_and_wakes_when_moved() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // Still, slow and over a twentieth of the top speed of 0.01
+        let speeds = [0.0, 0.0004, 0.002];
+        let particles = speeds.map(|speed| Particle {
+            position: [0.0; 2],
+            velocity: [speed, 0.0],
+        });
+        let mut resources = GpuResources::new(&device, &particles);
+        let config = SleepConfig {
+            speed: 0.05,
+            frames: 3,
+        };
+        let mut sleep = Sleep::new(&device, &mut resources, config);
+        let params = SimParams {
+            particle_count: 3,
+            max_speed: 0.01,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        queue.write_buffer(&resources.params, 0, bytemuck::bytes_of(&params));
+        let frame = FrameContext {
+            device: &device,
+            queue: &queue,
+            variant: SimVariant::default(),
+            particle_count: 3,
+            substeps: 1,
+            time: 0.0,
+            frame: 0,
+        };
+        let asleep = |resources: &GpuResources| {
+            gpu::read_buffer::<u32>(&device, &queue, &resources.flags, 3)
+                .into_iter()
+                .map(|bits| bits & flags::ASLEEP != 0)
+                .collect::<Vec<_>>()
+        };
+
+        // Not until they've been slow for long enough
+        for _ in 0..2 {
+            step(&mut sleep, &mut resources, &frame);
+        }
+        assert_eq!(asleep(&resources), [false; 3]);
+        step(&mut sleep, &mut resources, &frame);
+        assert_eq!(asleep(&resources), [true, true, false]);
+        let settled = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 3);
+        assert_eq!(settled[0].velocity, [0.0; 2]);
+        assert_eq!(settled[1].velocity, [0.0; 2]);
+        assert_eq!(settled[2].velocity, [0.002, 0.0]);
+
+        // Knocked by a neighbour, the first wakes on the next frame and the second sleeps on
+        let knocked = Particle {
+            position: [0.0; 2],
+            velocity: [0.0, 0.003],
+        };
+        queue.write_buffer(resources.particles(), 0, bytemuck::bytes_of(&knocked));
+        step(&mut sleep, &mut resources, &frame);
+        assert_eq!(asleep(&resources), [false, true, false]);
+        let woken = gpu::read_buffer::<Particle>(&device, &queue, resources.particles(), 1);
+        assert_eq!(woken[0].velocity, [0.0, 0.003]);
+    }
+}
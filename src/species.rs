@@ -0,0 +1,183 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::wgsl::wgsl_struct;
+
+/// What each particle of a species is drawn as, facing along its heading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    /// The usual boid
+    Triangle,
+    Square,
+    /// A disc the size of the square
+    Dot,
+}
+
+impl Shape {
+    // Must match `boid` in vertex_shader.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            Shape::Triangle => 0,
+            Shape::Square => 1,
+            Shape::Dot => 2,
+        }
+    }
+
+    /// Vertices drawn per particle.
+    pub fn vertices(self) -> u32 {
+        match self {
+            Shape::Triangle => 3,
+            Shape::Square | Shape::Dot => 6,
+        }
+    }
+}
+
+/// How a species is drawn over what's behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeciesBlend {
+    Opaque,
+    /// Faded by the particles' age, when they age
+    Alpha,
+    /// Glowing where particles overlap
+    Additive,
+}
+
+/// A group of particles drawn their own way. Species take their shares of the particle
+/// buffer in order, so each is drawn as one range of instances.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeciesConfig {
+    // Of the particles, relative to the other species' shares
+    pub share: f32,
+    pub shape: Shape,
+    // Smallest and largest particle, relative to the usual boid, each particle's size
+    // between them hashed from its index
+    pub size: [f32; 2],
+    // Blended however the rest would be when not set
+    pub blend: Option<SpeciesBlend>,
+}
+
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        SpeciesConfig {
+            share: 1.0,
+            shape: Shape::Triangle,
+            size: [1.0, 1.0],
+            blend: None,
+        }
+    }
+}
+
+impl SpeciesConfig {
+    pub fn look(&self) -> SpeciesLook {
+        SpeciesLook {
+            shape: self.shape.shader_index(),
+            size_min: self.size[0].max(0.0),
+            size_max: self.size[1].max(0.0),
+            shade: 1.0,
+        }
+    }
+}
+
+/// Give each species a shape no earlier one has, while there are shapes left, so they can be
+/// told apart by shape alone.
+pub fn distinct_shapes(species: &mut [SpeciesConfig]) {
+    let mut taken = Vec::new();
+    for config in species {
+        if taken.contains(&config.shape) {
+            if let Some(&shape) = Shape::value_variants()
+                .iter()
+                .find(|shape| !taken.contains(*shape))
+            {
+                config.shape = shape;
+            }
+        }
+        taken.push(config.shape);
+    }
+}
+
+/// How bright the `index`th of `count` species is drawn under the brightness cue, the first
+/// brightest.
+pub fn shade(index: usize, count: usize) -> f32 {
+    1.0 - 0.5 * index as f32 / count.max(1) as f32
+}
+
+wgsl_struct! {
+    // Must match `Look` in vertex_shader.wgsl
+    pub struct SpeciesLook {
+        pub shape: u32,
+        pub size_min: f32,
+        pub size_max: f32,
+        pub shade: f32,
+    }
+}
+
+impl Default for SpeciesLook {
+    fn default() -> Self {
+        SpeciesConfig::default().look()
+    }
+}
+
+/// Parse species like `shape[,blend[,size[,share]]];...`, with the size a scale or a range
+/// like `0.5-2`, e.g. `triangle;dot,additive,0.5-2,0.25`.
+pub fn parse_species(s: &str) -> Result<Vec<SpeciesConfig>, String> {
+    s.split(';')
+        .map(|species| {
+            let fields = species.split(',').map(str::trim).collect::<Vec<_>>();
+            if fields.len() > 4 {
+                return Err(format!(
+                    "expected a species like dot,additive,0.5-2,0.25, got `{species}`"
+                ));
+            }
+            let mut config = SpeciesConfig {
+                shape: Shape::from_str(fields[0], true)?,
+                ..Default::default()
+            };
+            if let Some(blend) = fields.get(1) {
+                config.blend = Some(SpeciesBlend::from_str(blend, true)?);
+            }
+            if let Some(size) = fields.get(2) {
+                let (min, max) = size.split_once('-').unwrap_or((size, size));
+                let parse = |n: &str| {
+                    n.trim()
+                        .parse::<f32>()
+                        .map_err(|_| format!("invalid species size `{size}`"))
+                };
+                config.size = [parse(min)?, parse(max)?];
+            }
+            if let Some(share) = fields.get(3) {
+                config.share = share
+                    .parse()
+                    .map_err(|_| format!("invalid species share `{share}`"))?;
+            }
+            Ok(config)
+        })
+        .collect()
+}
+
+/// The instances each species draws when `count` particles are drawn, by share.
+pub fn ranges(species: &[SpeciesConfig], count: u32) -> Vec<Range<u32>> {
+    let total = species
+        .iter()
+        .map(|species| species.share.max(0.0))
+        .sum::<f32>();
+    let mut shared = 0.0;
+    let mut start = 0;
+    species
+        .iter()
+        .map(|species| {
+            shared += species.share.max(0.0);
+            let end = if total > 0.0 {
+                ((shared / total) as f64 * count as f64).round() as u32
+            } else {
+                count
+            };
+            let range = start..end.clamp(start, count);
+            start = range.end;
+            range
+        })
+        .collect()
+}
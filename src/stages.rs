@@ -0,0 +1,199 @@
+use clap::ValueEnum;
+use nannou::wgpu;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+
+use crate::resources::GpuResources;
+use crate::sim_variant::SimVariant;
+
+/// Identifies a stage, for toggling it and in recordings, and in kebab-case for placing
+/// kernels, see `Kernel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+pub enum StageKind {
+    Boids,
+    LennardJones,
+    Physarum,
+    Fireworks,
+    Springs,
+    Noise,
+    Wander,
+    Attractors,
+    Touches,
+    Impulses,
+    Stirring,
+    Goal,
+    Territory,
+    Text,
+    VectorField,
+    Vorticity,
+    Drag,
+    Trail,
+    ReactionDiffusion,
+    Lifetime,
+    Collisions,
+    Inflow,
+    Obstacles,
+    Thermostat,
+    Stamina,
+    Freeze,
+    Sleep,
+    Orientation,
+    Canvas,
+    Emitters,
+    Contagion,
+    Compaction,
+    Kernel,
+}
+
+/// What a stage gets to know about the frame being encoded.
+pub struct FrameContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub variant: SimVariant,
+    pub particle_count: u32,
+    pub substeps: u32,
+    // Simulated seconds, from the frame count so playback matches
+    pub time: f32,
+    // Frames simulated so far, for seeding random numbers, see random.wgsl
+    pub frame: u32,
+}
+
+/// One step of the per-frame simulation, with its own pipelines and bind groups over the
+/// particle buffers. Stages that integrate step the particles with ping-pong buffers or in
+/// place, the rest change `resources.particles()` in place.
+pub trait Stage: Any {
+    fn kind(&self) -> StageKind;
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    );
+
+    /// Rebind to the particle buffers after `GpuResources::set_capacity`.
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources);
+}
+
+struct Slot {
+    stage: Box<dyn Stage>,
+    enabled: bool,
+}
+
+/// The simulation as an ordered list of stages, each encoded in turn when enabled.
+#[derive(Default)]
+pub struct Stages {
+    slots: Vec<Slot>,
+}
+
+impl Stages {
+    pub fn push(&mut self, stage: impl Stage, enabled: bool) {
+        self.slots.push(Slot {
+            stage: Box::new(stage),
+            enabled,
+        });
+    }
+
+    /// Insert a stage right after the first of `kind`, or give it back if there's none.
+    pub fn insert_after<S: Stage>(
+        &mut self,
+        kind: StageKind,
+        stage: S,
+        enabled: bool,
+    ) -> Result<(), S> {
+        let Some(index) = self.slots.iter().position(|slot| slot.stage.kind() == kind) else {
+            return Err(stage);
+        };
+        self.slots.insert(
+            index + 1,
+            Slot {
+                stage: Box::new(stage),
+                enabled,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        self.encode_each(frame, encoder, resources, |_, encoder, encode| {
+            encode(encoder)
+        });
+    }
+
+    /// Like `encode`, but handing each enabled stage's encoding to `around` with its kind,
+    /// e.g. to time each one.
+    pub fn encode_each(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+        mut around: impl FnMut(
+            StageKind,
+            &mut wgpu::CommandEncoder,
+            &mut dyn FnMut(&mut wgpu::CommandEncoder),
+        ),
+    ) {
+        for slot in self.slots.iter_mut().filter(|slot| slot.enabled) {
+            let kind = slot.stage.kind();
+            let _span = tracing::trace_span!("stage", ?kind).entered();
+            around(kind, encoder, &mut |encoder| {
+                slot.stage.encode(frame, encoder, resources)
+            });
+        }
+    }
+
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        for slot in &mut self.slots {
+            slot.stage.rebind(device, resources);
+        }
+    }
+
+    pub fn get<T: Stage>(&self) -> Option<&T> {
+        self.slots
+            .iter()
+            .find_map(|slot| (&*slot.stage as &dyn Any).downcast_ref())
+    }
+
+    pub fn get_mut<T: Stage>(&mut self) -> Option<&mut T> {
+        self.slots
+            .iter_mut()
+            .find_map(|slot| (&mut *slot.stage as &mut dyn Any).downcast_mut())
+    }
+
+    /// Like `get_mut`, for a type with more than one stage, e.g. `ForceStage`.
+    pub fn get_kind_mut<T: Stage>(&mut self, kind: StageKind) -> Option<&mut T> {
+        self.slots
+            .iter_mut()
+            .filter(|slot| slot.stage.kind() == kind)
+            .find_map(|slot| (&mut *slot.stage as &mut dyn Any).downcast_mut())
+    }
+
+    /// Whether there's an enabled stage of this kind.
+    pub fn enabled(&self, kind: StageKind) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.enabled && slot.stage.kind() == kind)
+    }
+
+    /// Flip a stage on or off, returning its new state, or `None` if there's no such stage.
+    pub fn toggle(&mut self, kind: StageKind) -> Option<bool> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.stage.kind() == kind)?;
+        slot.enabled = !slot.enabled;
+        Some(slot.enabled)
+    }
+
+    /// Stages in order, with whether each is enabled.
+    pub fn list(&self) -> impl Iterator<Item = (StageKind, bool)> + '_ {
+        self.slots
+            .iter()
+            .map(|slot| (slot.stage.kind(), slot.enabled))
+    }
+}
@@ -0,0 +1,202 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaminaConfig {
+    // Energy spent per frame at top speed, out of a full 1
+    pub drain: f32,
+    // Energy regained per frame at a standstill
+    pub recovery: f32,
+    // Fraction of the top speed left with no energy, which a spent particle rests at
+    pub rest: f32,
+}
+
+impl Default for StaminaConfig {
+    fn default() -> Self {
+        StaminaConfig {
+            drain: 0.004,
+            recovery: 0.01,
+            rest: 0.2,
+        }
+    }
+}
+
+/// Parse `drain[,recovery[,rest]]`, e.g. `0.004,0.01,0.2`.
+pub fn parse_stamina(s: &str) -> Result<StaminaConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid stamina `{s}`"))?;
+    let default = StaminaConfig::default();
+    let (drain, recovery, rest) = match values[..] {
+        [drain] => (drain, default.recovery, default.rest),
+        [drain, recovery] => (drain, recovery, default.rest),
+        [drain, recovery, rest] => (drain, recovery, rest),
+        _ => return Err(format!("expected stamina like 0.004,0.01,0.2, got `{s}`")),
+    };
+    if drain < 0.0 || recovery < 0.0 || !(0.0..=1.0).contains(&rest) {
+        return Err(format!(
+            "expected a drain and recovery of 0 or more and a rest fraction of 0 to 1, got `{s}`"
+        ));
+    }
+    Ok(StaminaConfig {
+        drain,
+        recovery,
+        rest,
+    })
+}
+
+wgsl_struct! {
+    // Must match `StaminaParams` in stamina_shader.wgsl
+    struct StaminaParams {
+        drain: f32,
+        recovery: f32,
+        rest: f32,
+    }
+}
+
+/// Energy per particle, spent by going fast and regained going slow, with the top speed
+/// shrinking as it runs down. A particle that runs out rests at the slowest until it's full
+/// again, so flocks surge and settle by turns.
+///
+/// Kept in a buffer of its own, indexed like the particles, and started at random levels so
+/// the particles don't all tire at once. Like `Orientation` it doesn't go with compaction,
+/// and starts over when the buffers are resized.
+pub struct Stamina {
+    pub config: StaminaConfig,
+    init: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<StaminaParams>,
+    energies: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the energies have been started
+    needs_init: bool,
+}
+
+impl Stamina {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: StaminaConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "stamina_shader",
+            include_str!("./shaders/stamina_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .uniform(3)
+            .build(device, "Stamina");
+        let params_buffer = UniformBuffer::new(device, resources, "Stamina Params Buffer");
+        let (energies, bind_groups) = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stamina Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Stamina Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Stamina Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+
+        Stamina {
+            config,
+            init: pipeline("init"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            energies,
+            bind_groups,
+            needs_init: true,
+        }
+    }
+
+    /// Each particle's energy and whether it's resting, for moving them along with the
+    /// particles.
+    pub fn energies_buffer(&self) -> &wgpu::Buffer {
+        &self.energies
+    }
+}
+
+impl Stage for Stamina {
+    fn kind(&self) -> StageKind {
+        StageKind::Stamina
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = StaminaParams {
+            drain: self.config.drain,
+            recovery: self.config.recovery,
+            rest: self.config.rest.clamp(0.0, 1.0),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Stamina Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        if std::mem::take(&mut self.needs_init) {
+            compute_pass.set_pipeline(&self.init);
+            compute_pass.dispatch_workgroups(resources.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        compute_pass.set_pipeline(&self.step);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.energies, self.bind_groups) =
+            bind(device, resources, &self.bindings, &self.params_buffer);
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<StaminaParams>,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let energies = resources.buffer(
+        device,
+        "Stamina Energies Buffer",
+        resources.capacity() as wgpu::BufferAddress
+            * std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+        BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                energies.as_entire_binding(),
+                params_buffer.binding(),
+            ],
+        )
+    });
+    (energies, bind_groups)
+}
@@ -0,0 +1,362 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::Particle;
+
+/// Aggregates over the whole flock, for studying how the parameters affect its order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FlockStats {
+    // Mean speed, in domain units per frame
+    pub mean_speed: f32,
+    // Length of the mean heading, 1 when every particle flies the same way and near 0 when
+    // they head every which way
+    pub polarization: f32,
+    // Mean distance from each particle to the one nearest it
+    pub nearest_neighbor: f32,
+    // Separate groups of particles, see `count_clusters`. A float so sweeps can average it
+    pub clusters: f32,
+}
+
+impl FlockStats {
+    /// Column names for `csv_fields`.
+    pub const CSV_HEADER: &'static str = "mean_speed,polarization,nearest_neighbor,clusters";
+
+    /// Each statistic averaged over `samples`, or all 0 without any.
+    pub fn mean(samples: &[FlockStats]) -> FlockStats {
+        let count = samples.len().max(1) as f32;
+        let sum = |field: fn(&FlockStats) -> f32| samples.iter().map(field).sum::<f32>() / count;
+        FlockStats {
+            mean_speed: sum(|stats| stats.mean_speed),
+            polarization: sum(|stats| stats.polarization),
+            nearest_neighbor: sum(|stats| stats.nearest_neighbor),
+            clusters: sum(|stats| stats.clusters),
+        }
+    }
+
+    pub fn csv_fields(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.mean_speed, self.polarization, self.nearest_neighbor, self.clusters
+        )
+    }
+}
+
+/// Measure `particles` read back from the GPU. Particles that turned into NaN are left out.
+///
+/// Done on the CPU, as it's only wanted every few frames, from a read back that's made for the
+/// speeds anyway, and tracing the clusters needs the whole grid in one place.
+pub fn measure(particles: &[Particle]) -> FlockStats {
+    let particles = particles
+        .iter()
+        .filter(|p| p.position.iter().chain(&p.velocity).all(|x| x.is_finite()))
+        .collect::<Vec<_>>();
+    if particles.is_empty() {
+        return FlockStats::default();
+    }
+
+    let mut speed = 0.0f64;
+    let mut heading = [0.0f64; 2];
+    for p in &particles {
+        let [x, y] = p.velocity.map(f64::from);
+        let length = x.hypot(y);
+        speed += length;
+        if length > 0.0 {
+            heading[0] += x / length;
+            heading[1] += y / length;
+        }
+    }
+    let count = particles.len() as f64;
+    let positions = particles.iter().map(|p| p.position).collect::<Vec<_>>();
+    let grid = Grid::new(&positions);
+    let nearest = (0..positions.len())
+        .filter_map(|i| grid.nearest_distance(&positions, i))
+        .map(f64::from)
+        .collect::<Vec<_>>();
+
+    let nearest_neighbor = if nearest.is_empty() {
+        0.0
+    } else {
+        (nearest.iter().sum::<f64>() / nearest.len() as f64) as f32
+    };
+
+    FlockStats {
+        mean_speed: (speed / count) as f32,
+        polarization: (heading[0].hypot(heading[1]) / count) as f32,
+        nearest_neighbor,
+        clusters: count_clusters(&positions, nearest_neighbor * CLUSTER_REACH) as f32,
+    }
+}
+
+// Side of the cells clusters are traced over, in mean nearest neighbour distances
+const CLUSTER_REACH: f32 = 3.0;
+
+/// Groups of particles apart from each other: the connected components of the cells of side
+/// `cell` that hold any, with cells touching at an edge or corner joined. Measured against the
+/// mean spacing, a flock stays one cluster however dense it is, and lone stragglers count as
+/// their own.
+fn count_clusters(positions: &[[f32; 2]], cell: f32) -> usize {
+    if positions.is_empty() {
+        return 0;
+    }
+    if cell <= 0.0 {
+        // Every particle in one place
+        return 1;
+    }
+    let cell_of = |[x, y]: [f32; 2]| ((x / cell).floor() as i64, (y / cell).floor() as i64);
+    // Occupied cells, each with whether it's been reached yet
+    let mut occupied = positions
+        .iter()
+        .map(|&position| (cell_of(position), false))
+        .collect::<HashMap<_, _>>();
+    let cells = occupied.keys().copied().collect::<Vec<_>>();
+
+    let mut clusters = 0;
+    let mut stack = Vec::new();
+    for start in cells {
+        if occupied.insert(start, true) == Some(true) {
+            continue;
+        }
+        clusters += 1;
+        stack.push(start);
+        while let Some((x, y)) = stack.pop() {
+            for neighbor in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy))) {
+                if let Some(reached) = occupied.get_mut(&neighbor) {
+                    if !*reached {
+                        *reached = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+    }
+    clusters
+}
+
+// Most cells per side, however many particles there are
+const MAX_GRID_SIDE: usize = 1024;
+
+/// Positions bucketed into square cells over their bounding box, about two to a cell, so
+/// nearest neighbours are found by searching outwards ring by ring instead of over all pairs.
+struct Grid {
+    min: [f32; 2],
+    cell: f32,
+    side: usize,
+    // Indices into the positions, sorted by cell, with each cell's range in `starts`
+    indices: Vec<u32>,
+    starts: Vec<u32>,
+}
+
+impl Grid {
+    fn new(positions: &[[f32; 2]]) -> Self {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for position in positions {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+        let side = ((positions.len() as f32 / 2.0).sqrt().ceil() as usize).clamp(1, MAX_GRID_SIDE);
+        let mut grid = Grid {
+            min,
+            cell: extent / side as f32,
+            side,
+            indices: Vec::with_capacity(positions.len()),
+            starts: vec![0; side * side + 1],
+        };
+
+        // Counting sort by cell
+        let cells = positions
+            .iter()
+            .map(|&position| grid.cell_index(grid.cell_of(position)))
+            .collect::<Vec<_>>();
+        for &cell in &cells {
+            grid.starts[cell + 1] += 1;
+        }
+        for cell in 0..side * side {
+            grid.starts[cell + 1] += grid.starts[cell];
+        }
+        let mut next = grid.starts.clone();
+        grid.indices.resize(positions.len(), 0);
+        for (i, &cell) in cells.iter().enumerate() {
+            grid.indices[next[cell] as usize] = i as u32;
+            next[cell] += 1;
+        }
+        grid
+    }
+
+    fn cell_of(&self, position: [f32; 2]) -> [usize; 2] {
+        [0, 1].map(|axis| {
+            let cell = ((position[axis] - self.min[axis]) / self.cell) as usize;
+            cell.min(self.side - 1)
+        })
+    }
+
+    fn cell_index(&self, [x, y]: [usize; 2]) -> usize {
+        y * self.side + x
+    }
+
+    /// Distance from particle `i` to its nearest neighbour, or `None` if it's the only one.
+    fn nearest_distance(&self, positions: &[[f32; 2]], i: usize) -> Option<f32> {
+        let position = positions[i];
+        let [cx, cy] = self.cell_of(position).map(|c| c as isize);
+        let side = self.side as isize;
+        let mut best = f32::INFINITY;
+        for ring in 0..side {
+            // Everything from this ring out is at least this far away
+            let reach = (ring - 1).max(0) as f32 * self.cell;
+            if best <= reach * reach {
+                break;
+            }
+            for y in cy - ring..=cy + ring {
+                for x in cx - ring..=cx + ring {
+                    let on_ring = (x - cx).abs() == ring || (y - cy).abs() == ring;
+                    if !on_ring || x < 0 || y < 0 || x >= side || y >= side {
+                        continue;
+                    }
+                    let cell = self.cell_index([x as usize, y as usize]);
+                    let range = self.starts[cell] as usize..self.starts[cell + 1] as usize;
+                    for &j in &self.indices[range] {
+                        if j as usize == i {
+                            continue;
+                        }
+                        let other = positions[j as usize];
+                        let dx = other[0] - position[0];
+                        let dy = other[1] - position[1];
+                        best = best.min(dx * dx + dy * dy);
+                    }
+                }
+            }
+        }
+        best.is_finite().then(|| best.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::rand::{rngs::StdRng, SeedableRng};
+
+    // A `columns` by `rows` lattice `spacing` apart from `corner`, all flying at `velocity`
+    fn lattice(corner: [f32; 2], columns: usize, rows: usize, spacing: f32) -> Vec<Particle> {
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| Particle {
+                    position: [
+                        corner[0] + column as f32 * spacing,
+                        corner[1] + row as f32 * spacing,
+                    ],
+                    velocity: [0.001, 0.0],
+                })
+            })
+            .collect()
+    }
+
+    fn positions(particles: &[Particle]) -> Vec<[f32; 2]> {
+        particles.iter().map(|p| p.position).collect()
+    }
+
+    #[test]
+    fn finds_the_spacing_of_a_lattice() {
+        let stats = measure(&lattice([-0.5, -0.5], 20, 10, 0.05));
+        assert!((stats.nearest_neighbor - 0.05).abs() < 1e-5, "{stats:?}");
+        assert_eq!(stats.clusters, 1.0);
+    }
+
+    #[test]
+    fn counts_separated_clumps() {
+        let mut particles = lattice([-0.9, -0.9], 8, 8, 0.01);
+        particles.extend(lattice([0.5, 0.6], 8, 8, 0.01));
+        let stats = measure(&particles);
+        assert!((stats.nearest_neighbor - 0.01).abs() < 1e-5, "{stats:?}");
+        assert_eq!(stats.clusters, 2.0);
+
+        // Joined into one by a line of particles the lattice's spacing apart
+        particles.extend((1..140).map(|i| Particle {
+            position: [-0.83 + i as f32 * 0.01, -0.83 + i as f32 * 0.01],
+            velocity: [0.001, 0.0],
+        }));
+        assert_eq!(measure(&particles).clusters, 1.0);
+    }
+
+    #[test]
+    fn counts_clusters_in_cells_touching_at_a_corner_as_one() {
+        assert_eq!(count_clusters(&[], 1.0), 0);
+        assert_eq!(count_clusters(&[[0.5, 0.5], [1.5, 1.5]], 1.0), 1);
+        assert_eq!(count_clusters(&[[0.5, 0.5], [2.5, 0.5]], 1.0), 2);
+        assert_eq!(count_clusters(&[[0.5, 0.5], [2.5, 0.5]], 0.0), 1);
+    }
+
+    #[test]
+    fn finds_the_same_nearest_neighbours_as_searching_every_pair() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut particles = (0..500)
+            .map(|_| Particle::random(&mut rng))
+            .collect::<Vec<_>>();
+        // A far straggler, so most of the grid is empty, and a pair on top of each other
+        particles.push(Particle {
+            position: [40.0, -30.0],
+            velocity: [0.0; 2],
+        });
+        particles.push(particles[0]);
+        let positions = positions(&particles);
+        let grid = Grid::new(&positions);
+        for (i, position) in positions.iter().enumerate() {
+            let expected = positions
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, other)| (other[0] - position[0]).hypot(other[1] - position[1]))
+                .fold(f32::INFINITY, f32::min);
+            let found = grid.nearest_distance(&positions, i).unwrap();
+            assert!(
+                (found - expected).abs() < 1e-6,
+                "{i}: {found} != {expected}"
+            );
+        }
+        assert_eq!(grid.nearest_distance(&positions, 0), Some(0.0));
+    }
+
+    #[test]
+    fn has_no_nearest_neighbour_for_a_lone_particle() {
+        let positions = [[0.25, 0.75]];
+        assert_eq!(Grid::new(&positions).nearest_distance(&positions, 0), None);
+    }
+
+    #[test]
+    fn measures_speed_and_polarization() {
+        let aligned = lattice([0.0, 0.0], 4, 4, 0.1);
+        let stats = measure(&aligned);
+        assert!((stats.mean_speed - 0.001).abs() < 1e-7, "{stats:?}");
+        assert!((stats.polarization - 1.0).abs() < 1e-5, "{stats:?}");
+
+        // Half of them turned around, at twice the speed
+        let mut opposed = aligned;
+        for p in opposed.iter_mut().step_by(2) {
+            p.velocity = [-0.002, 0.0];
+        }
+        let stats = measure(&opposed);
+        assert!((stats.mean_speed - 0.0015).abs() < 1e-7, "{stats:?}");
+        assert!(stats.polarization.abs() < 1e-5, "{stats:?}");
+    }
+
+    #[test]
+    fn leaves_out_particles_that_turned_into_nan() {
+        let mut particles = lattice([0.0, 0.0], 4, 4, 0.1);
+        let stats = measure(&particles);
+        particles.push(Particle {
+            position: [f32::NAN, 0.0],
+            velocity: [0.0; 2],
+        });
+        particles.push(Particle {
+            position: [0.05, 0.05],
+            velocity: [f32::INFINITY, 0.0],
+        });
+        assert_eq!(measure(&particles), stats);
+
+        let broken = &particles[particles.len() - 2..];
+        assert_eq!(measure(broken), FlockStats::default());
+        assert_eq!(measure(&[]), FlockStats::default());
+    }
+}
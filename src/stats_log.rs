@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+use crate::stats::FlockStats;
+
+/// Writes `FlockStats` every `interval` frames to a CSV file, a row per reading with the
+/// frame, simulated time and particle count.
+///
+/// Each reading copies the particles back and waits for them, so long intervals keep the
+/// frame rate up with many particles.
+pub struct StatsLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    interval: u64,
+}
+
+impl StatsLog {
+    pub fn create(path: &Path, interval: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "frame,time,particles,{}", FlockStats::CSV_HEADER)?;
+        info!(
+            "Logging statistics to {} every {} frames",
+            path.display(),
+            interval
+        );
+        Ok(StatsLog {
+            path: path.to_owned(),
+            writer,
+            interval: interval.max(1),
+        })
+    }
+
+    /// Whether `frame` is due a reading.
+    pub fn due(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    pub fn log(&mut self, frame: u64, particles: u32, stats: &FlockStats) {
+        let time = frame as f32 / 60.0;
+        let row = format!("{frame},{time},{particles},{}", stats.csv_fields());
+        if let Err(err) = writeln!(self.writer, "{row}") {
+            error!("Failed to write to {}: {}", self.path.display(), err);
+        }
+    }
+
+    pub fn finish(mut self) {
+        match self.writer.flush() {
+            Ok(()) => info!("Saved statistics to {}", self.path.display()),
+            Err(err) => error!("Failed to save {}: {}", self.path.display(), err),
+        }
+    }
+}
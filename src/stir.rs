@@ -0,0 +1,221 @@
+use nannou::wgpu::{self, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most stirs applied in one frame, half the size of the array in stir_shader.wgsl. Any
+/// more wait for the next frame.
+pub const MAX_STIRS: usize = 16;
+
+/// Momentum handed to the particles around a point, in the direction the cursor moved,
+/// hardest at the centre and fading to nothing at `radius`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stir {
+    pub position: [f32; 2],
+    // Velocity added at the centre, in domain units per frame
+    pub velocity: [f32; 2],
+    pub radius: f32,
+}
+
+/// How dragging with Shift held stirs the particles.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StirConfig {
+    // Fraction of the cursor's movement given to the particles under it
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl Default for StirConfig {
+    fn default() -> Self {
+        StirConfig {
+            strength: 0.2,
+            radius: 0.15,
+        }
+    }
+}
+
+impl StirConfig {
+    /// The stir for the cursor moving by `motion` to `position`, both in domain units.
+    pub fn at(&self, position: [f32; 2], motion: [f32; 2]) -> Stir {
+        Stir {
+            position,
+            velocity: motion.map(|m| m * self.strength),
+            radius: self.radius,
+        }
+    }
+}
+
+impl Stir {
+    /// This stir pushing `strength` times as hard and reaching `radius` times as far.
+    pub fn scaled(self, strength: f32, radius: f32) -> Stir {
+        Stir {
+            velocity: self.velocity.map(|v| v * strength),
+            radius: self.radius * radius,
+            ..self
+        }
+    }
+}
+
+/// Parse `strength[,radius]`, e.g. `0.2,0.15`.
+pub fn parse_stir(s: &str) -> Result<StirConfig, String> {
+    let values = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid stir `{s}`"))?;
+    let radius = match values[..] {
+        [_] => StirConfig::default().radius,
+        [_, radius] if radius > 0.0 => radius,
+        _ => return Err(format!("expected a stir like 0.2,0.15, got `{s}`")),
+    };
+    Ok(StirConfig {
+        strength: values[0],
+        radius,
+    })
+}
+
+wgsl_struct! {
+    // Must match `StirParams` in stir_shader.wgsl
+    struct StirParams {
+        particle_count: u32,
+        stir_count: u32,
+    }
+}
+
+/// Stirs queued with `push`, each applied to the particles' velocities once on the next
+/// frame, so the cursor drags the particles along like a spoon through a fluid rather than
+/// only pulling or pushing them. Skipped on frames without any.
+pub struct Stirring {
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<StirParams>,
+    // x, y and radius, then the velocity, of each stir this frame
+    stir_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    pending: Vec<Stir>,
+}
+
+impl Stirring {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "stir_shader",
+            include_str!("./shaders/stir_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .uniform(3)
+            .build(device, "Stir");
+        let params_buffer = UniformBuffer::new(device, resources, "Stir Params Buffer");
+        let stir_buffer = resources.uniform::<[[f32; 4]; 2 * MAX_STIRS]>(device, "Stir Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer, &stir_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stir Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Stir Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Stir Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "main",
+            })
+        });
+
+        Stirring {
+            pipeline,
+            bindings,
+            params_buffer,
+            stir_buffer,
+            bind_groups,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Apply `stir` on the next frame.
+    pub fn push(&mut self, stir: Stir) {
+        self.pending.push(stir);
+    }
+}
+
+impl Stage for Stirring {
+    fn kind(&self) -> StageKind {
+        StageKind::Stirring
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let count = self.pending.len().min(MAX_STIRS);
+        let mut packed = [[0.0f32; 4]; 2 * MAX_STIRS];
+        for (slots, stir) in packed.chunks_exact_mut(2).zip(self.pending.drain(..count)) {
+            let ([x, y], [vx, vy]) = (stir.position, stir.velocity);
+            slots[0] = [x, y, stir.radius, 0.0];
+            slots[1] = [vx, vy, 0.0, 0.0];
+        }
+        let params = StirParams {
+            particle_count: frame.particle_count,
+            stir_count: count as u32,
+        };
+        self.params_buffer.write(frame.queue, &params);
+        frame
+            .queue
+            .write_buffer(&self.stir_buffer, 0, bytemuck::cast_slice(&packed));
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Stir Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.stir_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<StirParams>,
+    stir_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                stir_buffer.as_entire_binding(),
+                resources.params.as_entire_binding(),
+            ],
+        )
+    })
+}
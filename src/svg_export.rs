@@ -0,0 +1,145 @@
+use nannou::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::background::{BackgroundConfig, BackgroundKind};
+use crate::camera::Camera;
+use crate::theme::{Cue, Hues, Palette};
+use crate::Particle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SvgStyle {
+    /// A dot per particle
+    Circles,
+    /// A short line along each particle's velocity
+    Strokes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgConfig {
+    pub style: SvgStyle,
+    // In pixels of the exported image, which is the window's size in logical points
+    pub stroke_width: f32,
+}
+
+impl Default for SvgConfig {
+    fn default() -> Self {
+        SvgConfig {
+            style: SvgStyle::Strokes,
+            stroke_width: 1.5,
+        }
+    }
+}
+
+/// Write the particles as seen through `camera` to an SVG the size of the window, coloured
+/// the same way as on screen, each `boid_size` across in world units. Returns how many
+/// particles were drawn.
+#[allow(clippy::too_many_arguments)]
+pub fn export(
+    path: &Path,
+    particles: &[Particle],
+    camera: &Camera,
+    [width, height]: [u32; 2],
+    boid_size: f32,
+    background_kind: BackgroundKind,
+    background: &BackgroundConfig,
+    palette: Palette,
+    hues: Hues,
+    cue: Cue,
+    config: &SvgConfig,
+) -> io::Result<usize> {
+    let (w, h) = (width as f32, height as f32);
+    // World to image pixels, the same transform as the vertex shader followed by the viewport
+    let to_image = |world: Vec2| {
+        let clip = camera.world_to_clip(world);
+        vec2((clip.x + 1.0) * 0.5 * w, (1.0 - clip.y) * 0.5 * h)
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    write_background(&mut svg, background_kind, background);
+
+    let radius = boid_size * camera.zoom * 0.5 * w.min(h) * 0.5;
+    let mut drawn = 0;
+    for particle in particles {
+        let position = Vec2::from(particle.position);
+        let velocity = Vec2::from(particle.velocity);
+        let head = to_image(position);
+        if head.x < -radius || head.x > w + radius || head.y < -radius || head.y > h + radius {
+            continue;
+        }
+        let color = hex(palette.paint(hues.velocity_color(velocity, cue)));
+        match config.style {
+            SvgStyle::Circles => {
+                let _ = writeln!(
+                    svg,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="{color}"/>"#,
+                    head.x,
+                    head.y,
+                    radius.max(config.stroke_width * 0.5)
+                );
+            }
+            SvgStyle::Strokes => {
+                let direction = velocity.try_normalize().unwrap_or(Vec2::X);
+                let tail = to_image(position - direction * boid_size * 1.5);
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{color}" stroke-width="{}" stroke-linecap="round"/>"#,
+                    tail.x, tail.y, head.x, head.y, config.stroke_width
+                );
+            }
+        }
+        drawn += 1;
+    }
+    svg.push_str("</svg>\n");
+
+    fs::write(path, svg)?;
+    Ok(drawn)
+}
+
+fn write_background(svg: &mut String, kind: BackgroundKind, config: &BackgroundConfig) {
+    let top = srgb_hex(config.color_top);
+    match kind {
+        BackgroundKind::Solid => {
+            let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{top}"/>"#);
+        }
+        BackgroundKind::Gradient => {
+            let bottom = srgb_hex(config.color_bottom);
+            let _ = writeln!(
+                svg,
+                r#"<defs><linearGradient id="background" x1="0" y1="0" x2="0" y2="1"><stop offset="0" stop-color="{top}"/><stop offset="1" stop-color="{bottom}"/></linearGradient></defs>"#
+            );
+            let _ = writeln!(
+                svg,
+                r#"<rect width="100%" height="100%" fill="url(#background)"/>"#
+            );
+        }
+        // Images and noise don't translate to vectors, leave them out for compositing later
+        BackgroundKind::Image | BackgroundKind::Noise => {}
+    }
+}
+
+// Linear colour from vertex_shader.wgsl
+fn hex(linear: [f32; 3]) -> String {
+    srgb_hex(linear.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        let srgb = if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    }))
+}
+
+fn srgb_hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
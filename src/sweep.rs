@@ -0,0 +1,156 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::cli::SweepArgs;
+use crate::parameters::{self, Parameter};
+use crate::particle_system::{BoidsParams, ParticleSystem};
+use crate::stats::FlockStats;
+
+/// One axis of the grid: a parameter and the values it takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepAxis {
+    pub parameter: &'static Parameter,
+    pub values: Vec<f32>,
+}
+
+/// Parse `NAME=VALUES`, where the values are either listed, e.g. `cohesion=0,0.5,1`, or
+/// spread evenly from the first to the last, e.g. `cohesion=0:2:5` for five values.
+pub fn parse_axis(s: &str) -> Result<SweepAxis, String> {
+    let (name, values) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUES, got `{s}`"))?;
+    let parameter = parameters::find(name)?;
+    let number = |s: &str| {
+        s.trim()
+            .parse::<f32>()
+            .map_err(|_| format!("invalid number `{s}`"))
+    };
+
+    let values = match values.split(':').collect::<Vec<_>>()[..] {
+        [start, end, steps] => {
+            let (start, end) = (number(start)?, number(end)?);
+            let steps = steps
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&steps| steps > 0)
+                .ok_or_else(|| format!("invalid number of steps `{steps}`"))?;
+            (0..steps)
+                .map(|i| match steps {
+                    1 => start,
+                    _ => start + (end - start) * i as f32 / (steps - 1) as f32,
+                })
+                .collect()
+        }
+        [list] => list.split(',').map(number).collect::<Result<Vec<_>, _>>()?,
+        _ => {
+            return Err(format!(
+                "expected a list or START:END:STEPS, got `{values}`"
+            ))
+        }
+    };
+    if let Some(value) = values
+        .iter()
+        .find(|value| !(parameter.min..=parameter.max).contains(*value))
+    {
+        return Err(format!(
+            "expected {} from {} to {}, got {value}",
+            parameter.name, parameter.min, parameter.max
+        ));
+    }
+    Ok(SweepAxis { parameter, values })
+}
+
+/// Run the plain boids step headlessly at every point in the grid the axes span, each
+/// parameter left out at its default, and write each run's statistics, averaged over the
+/// measured frames, to a CSV table.
+pub fn run(args: &SweepArgs) -> Result<(), String> {
+    let mut system = ParticleSystem::new(args.particles, 0).ok_or("no GPU adapter available")?;
+    let points = grid(&args.params);
+    let output =
+        |err: std::io::Error| format!("failed to write {}: {}", args.output.display(), err);
+    let mut writer = BufWriter::new(File::create(&args.output).map_err(output)?);
+
+    let names = args
+        .params
+        .iter()
+        .map(|axis| axis.parameter.name)
+        .collect::<Vec<_>>();
+    writeln!(
+        writer,
+        "{},seed,{}",
+        names.join(","),
+        FlockStats::CSV_HEADER
+    )
+    .map_err(output)?;
+
+    let runs = points.len() as u64 * args.seeds;
+    println!(
+        "Sweeping {} points x {} seeds with {} particles, {} frames each",
+        points.len(),
+        args.seeds,
+        args.particles,
+        args.warmup + args.frames
+    );
+    for (i, point) in points.iter().enumerate() {
+        for seed in 0..args.seeds {
+            let stats = simulate(&mut system, args, point, seed);
+            let values = point.iter().map(f32::to_string).collect::<Vec<_>>();
+            let run = i as u64 * args.seeds + seed + 1;
+            println!(
+                "[{run}/{runs}] {} seed {seed}: polarization {:.3}",
+                names
+                    .iter()
+                    .zip(&values)
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                stats.polarization
+            );
+            writeln!(writer, "{},{seed},{}", values.join(","), stats.csv_fields())
+                .map_err(output)?;
+        }
+    }
+    writer.flush().map_err(output)?;
+    println!("Saved {} runs to {}", runs, args.output.display());
+    Ok(())
+}
+
+// Every combination of the axes' values, the last axis varying fastest
+fn grid(axes: &[SweepAxis]) -> Vec<Vec<f32>> {
+    axes.iter().fold(vec![Vec::new()], |points, axis| {
+        points
+            .iter()
+            .flat_map(|point| {
+                axis.values.iter().map(move |&value| {
+                    let mut point = point.clone();
+                    point.push(value);
+                    point
+                })
+            })
+            .collect()
+    })
+}
+
+// Statistics averaged over every `sample_interval`th frame after the warmup
+fn simulate(system: &mut ParticleSystem, args: &SweepArgs, point: &[f32], seed: u64) -> FlockStats {
+    let mut params = BoidsParams::default();
+    for (axis, &value) in args.params.iter().zip(point) {
+        axis.parameter.set(&mut params, value);
+    }
+    system.set_params(params);
+    system.reset(args.particles, seed);
+
+    let mut samples = Vec::new();
+    system.step(args.warmup as u32);
+    samples.push(system.stats());
+    // Up to the end, however the frames divide into samples
+    let mut remaining = args.frames;
+    while remaining > 0 {
+        let frames = remaining.min(args.sample_interval);
+        system.step(frames as u32);
+        samples.push(system.stats());
+        remaining -= frames;
+    }
+    FlockStats::mean(&samples)
+}
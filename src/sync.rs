@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::recording::{Action, Recording};
+
+// Between attempts to reach an authority that isn't up yet, and checks for followers joining
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What the authority sends its followers, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SyncMessage {
+    // The starting state, first thing on joining
+    Start(Box<Recording>),
+    // The actions applied before simulating `frame`, sent as it's simulated
+    Frame { frame: u64, actions: Vec<Action> },
+}
+
+#[derive(Default)]
+struct Followers {
+    streams: Vec<TcpStream>,
+    // Set by the first frame, after which joining followers couldn't catch up
+    started: bool,
+}
+
+/// The instance the others follow: it sends its starting state to each follower as it joins,
+/// then every frame's actions as it simulates the frame, so the followers run the same
+/// simulation, like playing back a recording while it's made.
+pub struct SyncAuthority {
+    followers: Arc<Mutex<Followers>>,
+    // Applied since the last frame was sent
+    pending: Vec<Action>,
+}
+
+impl SyncAuthority {
+    pub fn start(address: &str, recording: Recording) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        info!("Sync authority on {}", listener.local_addr()?);
+        let start = line(&SyncMessage::Start(Box::new(recording)))?;
+        let followers = Arc::new(Mutex::new(Followers::default()));
+        let joining = followers.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| "?".to_owned(), |peer| peer.to_string());
+                let mut followers = joining.lock().unwrap();
+                if followers.started {
+                    // Dropping the connection tells the follower why
+                    warn!("Turned away follower {}, the simulation has started", peer);
+                    continue;
+                }
+                match stream
+                    .set_nodelay(true)
+                    .and_then(|()| stream.write_all(start.as_bytes()))
+                {
+                    Ok(()) => {
+                        info!("Follower {} joined", peer);
+                        followers.streams.push(stream);
+                    }
+                    Err(err) => error!("Follower {} failed to join: {}", peer, err),
+                }
+            }
+        });
+        Ok(SyncAuthority {
+            followers,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Wait until `count` followers have joined, before simulating anything.
+    pub fn wait_for(&self, count: usize) {
+        let joined = || self.followers.lock().unwrap().streams.len();
+        if joined() < count {
+            info!("Waiting for {} followers", count);
+        }
+        while joined() < count {
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+
+    /// Send `action` with the next frame. Camera moves aren't sent, so each follower can show
+    /// its own part of the flock, e.g. its panel of a wall.
+    pub fn push(&mut self, action: Action) {
+        if !matches!(action, Action::Camera(_)) {
+            self.pending.push(action);
+        }
+    }
+
+    /// Send `frame` with the actions pushed before it, as it's simulated. Followers that have
+    /// gone away are dropped; one that stops reading holds the authority up once the
+    /// connection's buffers fill.
+    pub fn send_frame(&mut self, frame: u64) {
+        let actions = std::mem::take(&mut self.pending);
+        let Ok(message) = line(&SyncMessage::Frame { frame, actions }) else {
+            return;
+        };
+        let mut followers = self.followers.lock().unwrap();
+        followers.started = true;
+        followers
+            .streams
+            .retain_mut(|stream| match stream.write_all(message.as_bytes()) {
+                Ok(()) => true,
+                Err(err) => {
+                    error!("Follower dropped: {}", err);
+                    false
+                }
+            });
+    }
+}
+
+/// An instance reproducing an authority's simulation: it starts from the authority's state and
+/// simulates each frame only once the authority has, with the same actions.
+pub struct SyncFollower {
+    frames: Receiver<io::Result<(u64, Vec<Action>)>>,
+}
+
+impl SyncFollower {
+    /// Join the authority at `address`, waiting for it to come up, and read its starting
+    /// state.
+    pub fn connect(address: &str) -> io::Result<(Self, Recording)> {
+        let mut waiting = false;
+        let stream = loop {
+            match TcpStream::connect(address) {
+                Ok(stream) => break stream,
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    if !waiting {
+                        info!("Waiting for the sync authority on {}", address);
+                        waiting = true;
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream);
+        let recording = match read_message(&mut reader)? {
+            Some(SyncMessage::Start(recording)) => *recording,
+            Some(SyncMessage::Frame { .. }) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected the starting state",
+                ))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "turned away, the authority's simulation has already started",
+                ))
+            }
+        };
+        info!("Following the sync authority on {}", address);
+
+        let (sender, frames) = mpsc::channel();
+        thread::spawn(move || loop {
+            let frame = match read_message(&mut reader) {
+                Ok(Some(SyncMessage::Frame { frame, actions })) => Ok((frame, actions)),
+                Ok(Some(SyncMessage::Start(_))) => continue,
+                Ok(None) => Err(io::ErrorKind::UnexpectedEof.into()),
+                Err(err) => Err(err),
+            };
+            let failed = frame.is_err();
+            if sender.send(frame).is_err() || failed {
+                return;
+            }
+        });
+        Ok((SyncFollower { frames }, recording))
+    }
+
+    /// The actions to apply before simulating `frame`, or `None` while the authority hasn't
+    /// got to it. An error once the authority has gone or the two are out of step.
+    pub fn due(&self, frame: u64) -> io::Result<Option<Vec<Action>>> {
+        match self.frames.try_recv() {
+            Ok(Ok((sent, actions))) if sent == frame => Ok(Some(actions)),
+            Ok(Ok((sent, _))) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("out of step, got frame {} for frame {}", sent, frame),
+            )),
+            Ok(Err(err)) => Err(err),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+fn line(message: &SyncMessage) -> io::Result<String> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    Ok(line)
+}
+
+// The next message, or `None` once the connection's closed
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<SyncMessage>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
@@ -0,0 +1,431 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{error, info};
+
+use crate::parameters::PARAMETERS;
+use crate::particle_system::BoidsParams;
+use crate::recording::Action;
+use crate::sim_variant::SimVariant;
+use crate::stages::StageKind;
+use crate::stats::FlockStats;
+
+// Appended to the client's key before hashing, from RFC 6455
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Larger messages than any action close the connection
+const MAX_MESSAGE: u64 = 64 * 1024;
+// Messages waiting to go out to a client before broadcasts to it are dropped, so a slow
+// connection misses updates rather than holding up a frame
+const QUEUED_MESSAGES: usize = 4;
+
+// A frame to write to a client, its opcode and payload
+type Outgoing = (u8, Vec<u8>);
+
+/// What's broadcast every `interval` frames, as JSON.
+#[derive(Debug, Serialize)]
+pub struct Telemetry {
+    pub frame: u64,
+    pub fps: f32,
+    pub particles: u32,
+    pub substeps: u32,
+    pub simulation: SimVariant,
+    // Whether each stage in the frame is on
+    pub stages: BTreeMap<String, bool>,
+    // Each of `parameters::PARAMETERS` by name, which `Action::SetParameter` sets
+    pub parameters: BTreeMap<String, f32>,
+    pub stats: FlockStats,
+}
+
+impl Telemetry {
+    pub fn stages(stages: impl Iterator<Item = (StageKind, bool)>) -> BTreeMap<String, bool> {
+        stages
+            .map(|(kind, enabled)| (format!("{kind:?}"), enabled))
+            .collect()
+    }
+
+    pub fn parameters(params: &BoidsParams) -> BTreeMap<String, f32> {
+        PARAMETERS
+            .iter()
+            .map(|parameter| (parameter.name.to_owned(), parameter.value(params)))
+            .collect()
+    }
+}
+
+/// A WebSocket server for watching and steering the app from a browser on another machine.
+///
+/// Every connected client gets a `Telemetry` message every `interval` frames. Clients send
+/// `Action`s as JSON, e.g. `"toggle-alignment"` or `{"set-particles": 20000}`, or set any of
+/// the parameters the message lists, e.g. `{"set-parameter": {"parameter": "cohesion",
+/// "value": 0.5}}`, which are applied like key presses, so they're recorded too. Each
+/// connection is read and written on threads of its own, so it never holds up a frame.
+pub struct TelemetryServer {
+    // Where to queue each client's messages, for the thread writing them
+    clients: Arc<Mutex<Vec<SyncSender<Outgoing>>>>,
+    actions: Receiver<Action>,
+    interval: u64,
+}
+
+impl TelemetryServer {
+    pub fn start(address: &str, interval: u64) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        info!(
+            "Telemetry on ws://{} every {} frames",
+            listener.local_addr()?,
+            interval
+        );
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (sender, actions) = mpsc::channel();
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let clients = accepted.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, &clients, &sender) {
+                        error!("Telemetry client dropped: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(TelemetryServer {
+            clients,
+            actions,
+            interval: interval.max(1),
+        })
+    }
+
+    /// Actions received since the last call.
+    pub fn poll(&self) -> Vec<Action> {
+        self.actions.try_iter().collect()
+    }
+
+    /// Whether `frame` is due a broadcast, and anyone is listening.
+    pub fn due(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval) && !self.clients.lock().unwrap().is_empty()
+    }
+
+    /// Queue `telemetry` for every client, skipping those still sending the last ones and
+    /// dropping those that have gone away.
+    pub fn broadcast(&self, telemetry: &Telemetry) {
+        let Ok(message) = serde_json::to_string(telemetry) else {
+            return;
+        };
+        self.clients.lock().unwrap().retain(|client| {
+            !matches!(
+                client.try_send((OPCODE_TEXT, message.clone().into_bytes())),
+                Err(TrySendError::Disconnected(_))
+            )
+        });
+    }
+}
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+// Upgrade the connection, then read actions from it until it closes
+fn serve(
+    stream: TcpStream,
+    clients: &Mutex<Vec<SyncSender<Outgoing>>>,
+    sender: &Sender<Action>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_owned());
+            }
+        }
+    }
+    let Some(key) = key else {
+        writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a WebSocket request",
+        ));
+    };
+    let accept = base64(&sha1(format!("{key}{HANDSHAKE_GUID}").as_bytes()));
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+    )?;
+
+    // Everything after the handshake is written here, replies and broadcasts alike, so
+    // they never interleave
+    let (outgoing, queued) = mpsc::sync_channel::<Outgoing>(QUEUED_MESSAGES);
+    thread::spawn(move || {
+        for (opcode, payload) in queued {
+            if write_frame(&mut writer, opcode, &payload).is_err() || opcode == OPCODE_CLOSE {
+                break;
+            }
+        }
+    });
+    clients.lock().unwrap().push(outgoing.clone());
+    let result = read_actions(&mut reader, &outgoing, sender);
+    if result.is_err() {
+        // Stops the writer too, as the client went away without closing
+        let _ = reader.get_ref().shutdown(Shutdown::Both);
+    }
+    result
+}
+
+// Actions from the client until it closes, replying to pings and messages that aren't
+// actions. Replies wait for room in the queue rather than being dropped.
+fn read_actions(
+    reader: &mut impl Read,
+    outgoing: &SyncSender<Outgoing>,
+    sender: &Sender<Action>,
+) -> io::Result<()> {
+    let reply = |opcode, payload| {
+        outgoing
+            .send((opcode, payload))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    };
+    loop {
+        let (opcode, payload) = read_frame(reader)?;
+        match opcode {
+            OPCODE_TEXT => match serde_json::from_slice::<Action>(&payload) {
+                Ok(action) => {
+                    if sender.send(action).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(err) => {
+                    let error = serde_json::json!({ "error": err.to_string() }).to_string();
+                    reply(OPCODE_TEXT, error.into_bytes())?;
+                }
+            },
+            OPCODE_PING => reply(OPCODE_PONG, payload)?,
+            OPCODE_CLOSE => {
+                // Echoing the close lets the client finish cleanly, the writer stops after it
+                reply(OPCODE_CLOSE, payload)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
+
+// One whole message from a client, whose frames are always masked. Fragments are joined.
+fn read_frame(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut message = Vec::new();
+    let mut first_opcode = None;
+    loop {
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let mut length = (header[1] & 0x7f) as u64;
+        if length == 126 {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes)?;
+            length = u16::from_be_bytes(bytes) as u64;
+        } else if length == 127 {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            length = u64::from_be_bytes(bytes);
+        }
+        if message.len() as u64 + length > MAX_MESSAGE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        let mut mask = [0u8; 4];
+        if header[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask)?;
+        }
+        let start = message.len();
+        message.resize(start + length as usize, 0);
+        reader.read_exact(&mut message[start..])?;
+        for (i, byte) in message[start..].iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        // Continuation frames carry opcode 0
+        let opcode = *first_opcode.get_or_insert(opcode);
+        if last {
+            return Ok((opcode, message));
+        }
+    }
+}
+
+// An unmasked, unfragmented frame, as servers send
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+// Only for the handshake, which the protocol fixes to SHA-1
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, state) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&state.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sample handshake from RFC 6455, section 1.3
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+    const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+    #[test]
+    fn accepts_the_rfc_handshake() {
+        assert_eq!(
+            base64(&sha1(format!("{KEY}{HANDSHAKE_GUID}").as_bytes())),
+            ACCEPT
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn broadcasts_past_a_client_that_stops_reading() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (sender, actions) = mpsc::channel();
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = serve(stream, &accepted, &sender);
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        write!(
+            client,
+            "GET / HTTP/1.1\r\nUpgrade: websocket\r\nSec-WebSocket-Key: {KEY}\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = BufReader::new(client.try_clone().unwrap());
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            response.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            lines.push(line.trim_end().to_owned());
+        }
+        assert!(lines.contains(&format!("Sec-WebSocket-Accept: {ACCEPT}")));
+        while clients.lock().unwrap().is_empty() {
+            thread::yield_now();
+        }
+
+        // Far more than the socket buffers hold, which the client never reads
+        let server = TelemetryServer {
+            clients,
+            actions,
+            interval: 1,
+        };
+        let telemetry = Telemetry {
+            frame: 0,
+            fps: 60.0,
+            particles: 1,
+            substeps: 1,
+            simulation: SimVariant::default(),
+            stages: (0..2000)
+                .map(|i| (format!("stage {i:032}"), true))
+                .collect(),
+            parameters: BTreeMap::new(),
+            stats: FlockStats::default(),
+        };
+        for _ in 0..200 {
+            server.broadcast(&telemetry);
+        }
+        assert_eq!(server.clients.lock().unwrap().len(), 1);
+    }
+}
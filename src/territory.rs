@@ -0,0 +1,266 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Most home bases there can be, the size of the array in territory_shader.wgsl.
+pub const MAX_BASES: usize = 8;
+
+/// Home bases the particles are divided between, each particle heading back to its own every
+/// so often and roaming the rest of the time, the bases taking turns so their flocks pull
+/// apart and run through each other by turns. Drawn with `ColorMode::Emitter`, by base.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerritoryConfig {
+    // Where each base is, in domain units. Particles start at home in the nearest
+    pub bases: Vec<[f32; 2]>,
+    // Simulated seconds between each base's calls home
+    pub period: f32,
+    // Fraction of the period its particles spend heading home
+    pub homing: f32,
+    // Velocity change per frame towards home while heading there
+    pub strength: f32,
+    // Distance from a base inside which the pull eases off
+    pub radius: f32,
+}
+
+impl Default for TerritoryConfig {
+    fn default() -> Self {
+        TerritoryConfig {
+            bases: Vec::new(),
+            period: 20.0,
+            homing: 0.3,
+            strength: 0.0005,
+            radius: 0.15,
+        }
+    }
+}
+
+/// How mixed the particles of each base were on a frame: for base `i`, `members[i]` belong
+/// to it and `at_home[i]` of them are nearer it than any other base, while `visitors[i]`
+/// belong elsewhere but are nearer it. Read from `Territory::counts_buffer`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mixing {
+    pub members: Vec<u32>,
+    pub at_home: Vec<u32>,
+    pub visitors: Vec<u32>,
+}
+
+impl Mixing {
+    /// From the counts buffer's contents: `MAX_BASES` rows, one for the base particles belong
+    /// to, of `MAX_BASES` counts, one for the base they're nearest.
+    pub fn from_counts(counts: &[u32], bases: usize) -> Self {
+        let bases = bases.min(MAX_BASES);
+        let count = |home: usize, nearest: usize| counts[home * MAX_BASES + nearest];
+        Mixing {
+            members: (0..bases)
+                .map(|home| (0..bases).map(|nearest| count(home, nearest)).sum())
+                .collect(),
+            at_home: (0..bases).map(|base| count(base, base)).collect(),
+            visitors: (0..bases)
+                .map(|nearest| {
+                    (0..bases)
+                        .filter(|&home| home != nearest)
+                        .map(|home| count(home, nearest))
+                        .sum()
+                })
+                .collect(),
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `TerritoryParams` in territory_shader.wgsl
+    struct TerritoryParams {
+        particle_count: u32,
+        bases: u32,
+        time: f32,
+        period: f32,
+        homing: f32,
+        strength: f32,
+        radius: f32,
+    }
+}
+
+/// Keeps each particle's base in `GpuResources::dyes`, as its emitter, so it's coloured by
+/// base and moved along with it when sorted, and steers it home when its base calls. Like
+/// `Emitters` compaction is left out with it, and particles are divided again when the
+/// buffers are resized.
+///
+/// The frame's `Mixing` is added up into `counts_buffer` for logging.
+pub struct Territory {
+    pub config: TerritoryConfig,
+    init: wgpu::ComputePipeline,
+    step: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<TerritoryParams>,
+    // x, y and hue of each base, then the point of its period it calls home at
+    base_buffer: wgpu::Buffer,
+    counts: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+    // Set until the particles have been divided between the bases
+    needs_init: bool,
+}
+
+impl Territory {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: TerritoryConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "territory_shader",
+            include_str!("./shaders/territory_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .build(device, "Territory");
+        let params_buffer = UniformBuffer::new(device, resources, "Territory Params Buffer");
+        let base_buffer =
+            resources.uniform::<[[f32; 4]; MAX_BASES]>(device, "Territory Bases Buffer");
+        let counts = resources.buffer(
+            device,
+            "Territory Counts Buffer",
+            (MAX_BASES * MAX_BASES * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        );
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            &base_buffer,
+            &counts,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Territory Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Territory Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Territory Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Territory {
+            config,
+            init: pipeline("init"),
+            step: pipeline("step"),
+            bindings,
+            params_buffer,
+            base_buffer,
+            counts,
+            bind_groups,
+            needs_init: true,
+        }
+    }
+
+    /// The last frame's `Mixing`, as `MAX_BASES` by `MAX_BASES` `u32`s, for copying out.
+    pub fn counts_buffer(&self) -> &wgpu::Buffer {
+        &self.counts
+    }
+}
+
+impl Stage for Territory {
+    fn kind(&self) -> StageKind {
+        StageKind::Territory
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let positions = &self.config.bases[..self.config.bases.len().min(MAX_BASES)];
+        // Evenly round the palette and the period, so each base calls home in its turn
+        let mut bases = [[0.0; 4]; MAX_BASES];
+        for (i, position) in positions.iter().enumerate() {
+            let turn = i as f32 / positions.len() as f32;
+            bases[i] = [position[0], position[1], turn, turn];
+        }
+        frame
+            .queue
+            .write_buffer(&self.base_buffer, 0, bytemuck::cast_slice(&bases));
+        let params = TerritoryParams {
+            particle_count: frame.particle_count,
+            bases: positions.len() as u32,
+            time: frame.time,
+            period: self.config.period.max(f32::EPSILON),
+            homing: self.config.homing.clamp(0.0, 1.0),
+            strength: self.config.strength,
+            radius: self.config.radius.max(f32::EPSILON),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        encoder.clear_buffer(&self.counts, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Territory Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        if std::mem::take(&mut self.needs_init) {
+            compute_pass.set_pipeline(&self.init);
+            compute_pass.dispatch_workgroups(resources.capacity().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        compute_pass.set_pipeline(&self.step);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.base_buffer,
+            &self.counts,
+        );
+        self.needs_init = true;
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<TerritoryParams>,
+    base_buffer: &wgpu::Buffer,
+    counts: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                base_buffer.as_entire_binding(),
+                resources.dyes.as_entire_binding(),
+                counts.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,117 @@
+use nannou::wgpu::{self, BufferUsages};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::resources::GpuResources;
+use crate::territory::{Mixing, Territory, MAX_BASES};
+
+// Frames between readings
+const INTERVAL: u64 = 30;
+
+/// Writes the `Territory`'s `Mixing` to a CSV file as the run goes, a row per base per
+/// reading every `INTERVAL` frames, with the frame and simulated time.
+///
+/// The counts are copied out and mapped asynchronously like the outbreak's, so rows arrive a
+/// frame or two late but never stall rendering.
+pub struct TerritoryLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    staging: wgpu::Buffer,
+    // Frame and bases of the counts in flight
+    pending: Option<(u64, usize)>,
+    mapped: Arc<AtomicBool>,
+}
+
+impl TerritoryLog {
+    pub fn create(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        path: &Path,
+    ) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "frame,time,base,members,at_home,visitors")?;
+        info!(
+            "Logging the bases' mixing to {} every {} frames",
+            path.display(),
+            INTERVAL
+        );
+        let staging = resources.buffer(
+            device,
+            "Territory Counts Readback Buffer",
+            (MAX_BASES * MAX_BASES * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        );
+        Ok(TerritoryLog {
+            path: path.to_owned(),
+            writer,
+            staging,
+            pending: None,
+            mapped: Arc::default(),
+        })
+    }
+
+    /// Copy out the counts of the frame just encoded when it's due a reading. Returns whether
+    /// a copy was encoded, in which case call `map` after submitting.
+    pub fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        territory: &Territory,
+        frame: u64,
+    ) -> bool {
+        if !frame.is_multiple_of(INTERVAL) || self.pending.is_some() {
+            return false;
+        }
+        let counts = territory.counts_buffer();
+        encoder.copy_buffer_to_buffer(counts, 0, &self.staging, 0, counts.size());
+        self.pending = Some((frame, territory.config.bases.len()));
+        true
+    }
+
+    pub fn map(&self) {
+        let mapped = self.mapped.clone();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(()) => mapped.store(true, Ordering::Release),
+                Err(err) => error!("Failed to map the bases' counts: {}", err),
+            });
+    }
+
+    /// Write the reading in flight if its counts have arrived.
+    pub fn poll(&mut self) {
+        if !self.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let Some((frame, bases)) = self.pending.take() else {
+            return;
+        };
+        let mixing = {
+            let data = self.staging.slice(..).get_mapped_range();
+            Mixing::from_counts(bytemuck::cast_slice(&data), bases)
+        };
+        self.staging.unmap();
+
+        let time = frame as f32 / 60.0;
+        for base in 0..mixing.members.len() {
+            let row = format!(
+                "{frame},{time},{base},{},{},{}",
+                mixing.members[base], mixing.at_home[base], mixing.visitors[base]
+            );
+            if let Err(err) = writeln!(self.writer, "{row}") {
+                error!("Failed to write to {}: {}", self.path.display(), err);
+                return;
+            }
+        }
+    }
+
+    pub fn finish(mut self) {
+        match self.writer.flush() {
+            Ok(()) => info!("Saved the bases' mixing to {}", self.path.display()),
+            Err(err) => error!("Failed to save {}: {}", self.path.display(), err),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use nannou::rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use nannou::text::{self, rt::point, Scale};
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Pixels per line the text is rasterised at, finer than any flock can show
+const RASTER_HEIGHT: f32 = 128.0;
+// Pixels at least this covered by a glyph become targets
+const COVERAGE: f32 = 0.5;
+
+/// Particles assembling into a string of text: each gets a spot inside the glyphs to seek,
+/// as for a title or an intro.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextConfig {
+    // What the particles spell. Line breaks start new lines
+    pub text: String,
+    // TrueType or OpenType font file, Noto Sans when not set
+    pub font: Option<PathBuf>,
+    // Widest the text is fitted into, as a fraction of the domain
+    pub width: f32,
+    // Velocity change per frame towards each particle's spot, in domain units. It has to
+    // beat the flocking for the text to be legible
+    pub strength: f32,
+    // Distance from the spot inside which the pull eases off
+    pub arrival: f32,
+    // Fraction of the velocity lost each frame at the spot, so particles settle there
+    pub damping: f32,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        TextConfig {
+            text: String::new(),
+            font: None,
+            width: 0.9,
+            strength: 0.0002,
+            arrival: 0.05,
+            damping: 0.1,
+        }
+    }
+}
+
+/// The spots inside the glyphs of `config`'s text, a pixel apart, in a random but fixed
+/// order so any number of them covers the whole text evenly. Also returns the size of a
+/// pixel in domain units.
+pub fn rasterize(config: &TextConfig) -> Result<(Vec<[f32; 2]>, f32), String> {
+    let font = match &config.font {
+        Some(path) => text::font::from_file(path)
+            .map_err(|err| format!("Failed to load font {}: {}", path.display(), err))?,
+        None => text::font::default_notosans(),
+    };
+    let scale = Scale::uniform(RASTER_HEIGHT);
+    let metrics = font.v_metrics(scale);
+    let line_height = metrics.ascent - metrics.descent + metrics.line_gap;
+
+    // In pixels, y down
+    let mut pixels = Vec::new();
+    for (row, line) in config.text.lines().enumerate() {
+        let baseline = metrics.ascent + row as f32 * line_height;
+        for glyph in font.layout(line, scale, point(0.0, baseline)) {
+            let Some(bounds) = glyph.pixel_bounding_box() else {
+                continue;
+            };
+            glyph.draw(|x, y, coverage| {
+                if coverage >= COVERAGE {
+                    pixels.push([bounds.min.x + x as i32, bounds.min.y + y as i32]);
+                }
+            });
+        }
+    }
+    if pixels.is_empty() {
+        return Err(format!("`{}` draws nothing to assemble into", config.text));
+    }
+
+    let mut min = [i32::MAX; 2];
+    let mut max = [i32::MIN; 2];
+    for pixel in &pixels {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(pixel[axis]);
+            max[axis] = max[axis].max(pixel[axis] + 1);
+        }
+    }
+    let extent = (max[0] - min[0]).max(max[1] - min[1]) as f32;
+    let cell = 2.0 * config.width / extent;
+    let centre = [0, 1].map(|axis| (min[axis] + max[axis]) as f32 / 2.0);
+    let mut spots = pixels
+        .iter()
+        .map(|&[x, y]| {
+            [
+                (x as f32 + 0.5 - centre[0]) * cell,
+                (centre[1] - y as f32 - 0.5) * cell,
+            ]
+        })
+        .collect::<Vec<_>>();
+    spots.shuffle(&mut StdRng::seed_from_u64(0));
+    Ok((spots, cell))
+}
+
+wgsl_struct! {
+    // Must match `TextParams` in text_shader.wgsl
+    struct TextParams {
+        strength: f32,
+        arrival: f32,
+        damping: f32,
+    }
+}
+
+/// Pulls each particle towards its own spot in the text in place, after the integrating
+/// stage. Spots follow the buffer index, so particles keep theirs from frame to frame.
+pub struct TextTargets {
+    pub config: TextConfig,
+    spots: Vec<[f32; 2]>,
+    cell: f32,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<TextParams>,
+    // Each particle's spot, indexed like the particles
+    targets: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl TextTargets {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: TextConfig,
+        spots: Vec<[f32; 2]>,
+        cell: f32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "text_shader",
+            include_str!("./shaders/text_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_ro(3)
+            .build(device, "Text");
+        let params_buffer = UniformBuffer::new(device, resources, "Text Params Buffer");
+        let (targets, bind_groups) =
+            bind(device, resources, &bindings, &params_buffer, &spots, cell);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Text Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Text Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "seek",
+            })
+        });
+
+        TextTargets {
+            config,
+            spots,
+            cell,
+            pipeline,
+            bindings,
+            params_buffer,
+            targets,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for TextTargets {
+    fn kind(&self) -> StageKind {
+        StageKind::Text
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = TextParams {
+            strength: self.config.strength,
+            arrival: self.config.arrival.max(f32::EPSILON),
+            damping: self.config.damping.clamp(0.0, 1.0),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Text Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        (self.targets, self.bind_groups) = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.spots,
+            self.cell,
+        );
+    }
+}
+
+// Spots are shared out in order, and shared again with a random offset inside the pixel
+// when there are more particles than spots
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<TextParams>,
+    spots: &[[f32; 2]],
+    cell: f32,
+) -> (wgpu::Buffer, [wgpu::BindGroup; 2]) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let targets = (0..resources.capacity() as usize)
+        .map(|i| {
+            let [x, y] = spots[i % spots.len()];
+            if i < spots.len() {
+                [x, y]
+            } else {
+                let half = cell / 2.0;
+                [
+                    x + rng.gen_range(-half..half),
+                    y + rng.gen_range(-half..half),
+                ]
+            }
+        })
+        .collect::<Vec<_>>();
+    let targets = resources.buffer_init(
+        device,
+        "Text Targets Buffer",
+        bytemuck::cast_slice(&targets),
+        BufferUsages::STORAGE,
+    );
+    let bind_groups = resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+                targets.as_entire_binding(),
+            ],
+        )
+    });
+    (targets, bind_groups)
+}
@@ -0,0 +1,223 @@
+use clap::ValueEnum;
+use nannou::color::{rgba, Rgba};
+use nannou::glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+// How much the ink palette darkens the palest colours
+const INK: f32 = 0.7;
+// Speed shown brightest, the scale `velocity_color` in vertex_shader.wgsl shows speed at
+const FULL_SPEED: f32 = 0.01;
+// How dark the brightness cue draws still particles
+const STILL_BRIGHTNESS: f32 = 0.35;
+
+/// The background, particle palette and overlay colours, switched together so a run can go
+/// on a light background for figures without anything turning illegible. Picked with
+/// --theme and kept in presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Bright particles on black
+    #[default]
+    Dark,
+    /// Inked particles on white
+    Light,
+    /// Inked particles on warm off-white
+    Paper,
+}
+
+/// How the particles' colours are adjusted to show on the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// As the colour mode gives them
+    Bright,
+    /// Darkened by how light they are, so pale colours still show on a light background
+    Ink,
+}
+
+/// The colours picked for what the particles' colours show, so demos stay readable for
+/// colour blind audiences. Set with --hues and kept in presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hues {
+    /// Round the colour wheel, with the heading shown by hue
+    #[default]
+    Wheel,
+    /// Okabe and Ito's colours for emitters, outbreaks, bases and bursts, and viridis for
+    /// speed and crowding, which stay apart with red-green or blue-yellow colour blindness
+    Safe,
+    /// Shades of grey alone, for no colour vision at all
+    Grey,
+}
+
+/// What shows each particle's speed and species besides its colour, so they can be told
+/// apart without telling hues apart. Set with --cue and kept in presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cue {
+    /// Colour alone
+    #[default]
+    None,
+    /// Slower particles and later species drawn darker
+    Brightness,
+    /// Faster particles drawn longer along their heading, and each species a shape of its
+    /// own while there are shapes to go round
+    Shape,
+}
+
+/// Colours the overlays are drawn in.
+#[derive(Debug, Clone, Copy)]
+pub struct Hud {
+    // Behind each overlay's graph or text
+    pub panel: Rgba,
+    pub text: Rgba,
+}
+
+impl Theme {
+    /// The solid colour, also the top of gradients, and the bottom of gradients, as sRGB.
+    pub fn background(self) -> ([u8; 3], [u8; 3]) {
+        match self {
+            Theme::Dark => ([0x00, 0x00, 0x00], [0x1a, 0x1a, 0x2e]),
+            Theme::Light => ([0xff, 0xff, 0xff], [0xe4, 0xe8, 0xf0]),
+            Theme::Paper => ([0xf4, 0xef, 0xe4], [0xe2, 0xd9, 0xc6]),
+        }
+    }
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette::Bright,
+            Theme::Light | Theme::Paper => Palette::Ink,
+        }
+    }
+
+    pub fn hud(self) -> Hud {
+        match self {
+            Theme::Dark => Hud {
+                panel: rgba(0.0, 0.0, 0.0, 0.6),
+                text: rgba(1.0, 1.0, 1.0, 1.0),
+            },
+            Theme::Light => Hud {
+                panel: rgba(1.0, 1.0, 1.0, 0.75),
+                text: rgba(0.1, 0.1, 0.12, 1.0),
+            },
+            Theme::Paper => Hud {
+                panel: rgba(0.96, 0.94, 0.89, 0.8),
+                text: rgba(0.23, 0.2, 0.15, 1.0),
+            },
+        }
+    }
+}
+
+impl Hues {
+    // Must match `category_color` and `amount_color` in vertex_shader.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            Hues::Wheel => 0,
+            Hues::Safe => 1,
+            Hues::Grey => 2,
+        }
+    }
+
+    /// A linear colour for a particle moving at `velocity`, as the vertex shader gives it
+    /// before the palette.
+    pub fn velocity_color(self, velocity: Vec2, cue: Cue) -> [f32; 3] {
+        let speed = velocity.length();
+        let level = (speed / FULL_SPEED).min(1.0);
+        let color = match self {
+            Hues::Wheel => {
+                let direction = if speed > 0.00001 {
+                    velocity / speed
+                } else {
+                    Vec2::X
+                };
+                Vec3::new(direction.x.abs(), direction.y.abs(), speed / FULL_SPEED)
+            }
+            Hues::Safe => viridis(level),
+            Hues::Grey => Vec3::splat(grey(level)),
+        };
+        let brightness = match cue {
+            Cue::Brightness => STILL_BRIGHTNESS + (1.0 - STILL_BRIGHTNESS) * level,
+            Cue::None | Cue::Shape => 1.0,
+        };
+        (color * brightness).to_array()
+    }
+}
+
+impl Cue {
+    // Must match `cued` in vertex_shader.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            Cue::None => 0,
+            Cue::Brightness => 1,
+            Cue::Shape => 2,
+        }
+    }
+}
+
+// Must match `viridis` in palette.wgsl
+fn viridis(t: f32) -> Vec3 {
+    const FIT: [[f32; 3]; 7] = [
+        [0.277_727_3, 0.005_407_3, 0.334_099_8],
+        [0.105_093, 1.404_613_5, 1.384_590_2],
+        [-0.330_861_8, 0.214_847_6, 0.095_095_2],
+        [-4.634_230_5, -5.799_101, -19.332_441],
+        [6.228_27, 14.179_933, 56.690_55],
+        [4.776_385, -13.745_145, -65.353_03],
+        [-5.435_456, 4.645_852_6, 26.312_435],
+    ];
+    let t = t.clamp(0.0, 1.0);
+    let srgb = FIT
+        .iter()
+        .rev()
+        .fold(Vec3::ZERO, |sum, &c| sum * t + Vec3::from(c));
+    srgb.clamp(Vec3::ZERO, Vec3::ONE).powf(2.2)
+}
+
+// Must match `grey` in palette.wgsl
+fn grey(t: f32) -> f32 {
+    0.03 + 0.97 * t.clamp(0.0, 1.0)
+}
+
+impl Palette {
+    // Must match `painted` in vertex_shader.wgsl
+    pub fn shader_index(self) -> u32 {
+        match self {
+            Palette::Bright => 0,
+            Palette::Ink => 1,
+        }
+    }
+
+    /// A linear colour as the vertex shader paints it with this palette.
+    pub fn paint(self, color: [f32; 3]) -> [f32; 3] {
+        match self {
+            Palette::Bright => color,
+            Palette::Ink => {
+                let [r, g, b] = color;
+                let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                color.map(|c| c * (1.0 - INK * luma.min(1.0)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_and_grey_hues_lighten_with_speed() {
+        let luma = |[r, g, b]: [f32; 3]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        for hues in [Hues::Safe, Hues::Grey] {
+            let lumas = (0..=10)
+                .map(|i| luma(hues.velocity_color(Vec2::new(i as f32 * 0.001, 0.0), Cue::None)))
+                .collect::<Vec<_>>();
+            assert!(
+                lumas.windows(2).all(|pair| pair[0] < pair[1]),
+                "{hues:?}: {lumas:?}"
+            );
+        }
+        // Heading along x is all red on the wheel, dimmed when slow under the brightness cue
+        let red = |speed| Hues::Wheel.velocity_color(Vec2::new(speed, 0.0), Cue::Brightness)[0];
+        assert!(red(0.001) < 0.5);
+        assert_eq!(red(0.01), 1.0);
+    }
+}
@@ -0,0 +1,191 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermostatConfig {
+    // RMS particle speed to hold, in domain units per frame. Boids top out around 0.005,
+    // see `SpeedLimits`.
+    pub target_speed: f32,
+    // Fraction of the difference corrected each frame, 1 rescales straight to the target
+    pub rate: f32,
+}
+
+impl ThermostatConfig {
+    pub fn new(target_speed: f32) -> Self {
+        ThermostatConfig {
+            target_speed,
+            rate: 0.1,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `ThermostatParams` in thermostat_shader.wgsl
+    struct ThermostatParams {
+        particle_count: u32,
+        target_speed_sq: f32,
+        rate: f32,
+    }
+}
+
+/// Rescales velocities after each step so the total kinetic energy (the temperature) stays
+/// near a target, for gas-like demos.
+///
+/// Three passes: per-workgroup sums of squared speeds, a single workgroup turning those into
+/// a scale factor, then the rescale itself. Nothing is read back to the CPU.
+pub struct Thermostat {
+    pub config: ThermostatConfig,
+    measure: wgpu::ComputePipeline,
+    total: wgpu::ComputePipeline,
+    rescale: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<ThermostatParams>,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Thermostat {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: ThermostatConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "thermostat_shader",
+            include_str!("./shaders/thermostat_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_rw(2)
+            .storage_rw(3)
+            .build(device, "Thermostat");
+        let params_buffer = UniformBuffer::new(device, resources, "Thermostat Params Buffer");
+        let bind_groups = bind(device, resources, &bindings, &params_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Thermostat Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Thermostat Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Thermostat Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Thermostat {
+            config,
+            measure: pipeline("measure"),
+            total: pipeline("total"),
+            rescale: pipeline("rescale"),
+            bindings,
+            params_buffer,
+            bind_groups,
+        }
+    }
+
+    /// Resize for and rebind to a new particle buffer.
+    pub fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(device, resources, &self.bindings, &self.params_buffer);
+    }
+
+    /// Encode the rescale of the latest particle state. Run after the simulation step.
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &GpuResources,
+        count: u32,
+    ) {
+        let params = ThermostatParams {
+            particle_count: count,
+            target_speed_sq: self.config.target_speed * self.config.target_speed,
+            rate: self.config.rate.clamp(0.0, 1.0),
+        };
+        self.params_buffer.write(queue, &params);
+
+        let workgroups = count.div_ceil(WORKGROUP_SIZE);
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Thermostat Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.set_pipeline(&self.measure);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        compute_pass.set_pipeline(&self.total);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+        compute_pass.set_pipeline(&self.rescale);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+}
+
+impl Stage for Thermostat {
+    fn kind(&self) -> StageKind {
+        StageKind::Thermostat
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        Thermostat::encode(self, frame.queue, encoder, resources, frame.particle_count);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        Thermostat::rebind(self, device, resources);
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &mut GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<ThermostatParams>,
+) -> [wgpu::BindGroup; 2] {
+    let f32_size = std::mem::size_of::<f32>() as wgpu::BufferAddress;
+    let workgroups = resources.capacity().div_ceil(WORKGROUP_SIZE) as wgpu::BufferAddress;
+    let partial_sums = resources.buffer(
+        device,
+        "Thermostat Partial Sums Buffer",
+        workgroups * f32_size,
+        BufferUsages::STORAGE,
+    );
+    let scale = resources.buffer(
+        device,
+        "Thermostat Scale Buffer",
+        f32_size,
+        BufferUsages::STORAGE,
+    );
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                partial_sums.as_entire_binding(),
+                scale.as_entire_binding(),
+            ],
+        )
+    })
+}
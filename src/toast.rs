@@ -0,0 +1,107 @@
+use nannou::prelude::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::theme::Hud;
+
+// How long each message stays up, fading out over the last part. Errors stay longer
+const SHOWN: Duration = Duration::from_millis(2500);
+const ERROR_SHOWN: Duration = Duration::from_millis(5000);
+const FADE: Duration = Duration::from_millis(700);
+// Older messages make way past this many
+const MAX_MESSAGES: usize = 5;
+const MARGIN: f32 = 10.0;
+const LINE_HEIGHT: f32 = 22.0;
+
+struct Toast {
+    message: String,
+    error: bool,
+    shown: Instant,
+}
+
+impl Toast {
+    fn left(&self, now: Instant) -> Duration {
+        let shown = if self.error { ERROR_SHOWN } else { SHOWN };
+        shown.saturating_sub(now.duration_since(self.shown))
+    }
+}
+
+/// Short messages shown at the bottom of the control window for a moment, fading out, so
+/// feedback like a saved export or a lost connection doesn't rely on reading the terminal.
+/// They're logged too.
+#[derive(Default)]
+pub struct Toasts {
+    // Oldest first
+    toasts: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn info(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        info!("{}", message);
+        self.push(message, false);
+    }
+
+    /// Shown in red, for longer, and logged as an error.
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        error!("{}", message);
+        self.push(message, true);
+    }
+
+    fn push(&mut self, message: String, error: bool) {
+        if self.toasts.len() == MAX_MESSAGES {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            message,
+            error,
+            shown: Instant::now(),
+        });
+    }
+
+    /// Drop the messages that have faded out.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.toasts.retain(|toast| !toast.left(now).is_zero());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toasts.is_empty()
+    }
+
+    pub fn draw(&self, draw: &Draw, window: Rect, hud: Hud) {
+        let now = Instant::now();
+        let area = window.pad(MARGIN);
+        // Newest at the bottom
+        for (row, toast) in self.toasts.iter().rev().enumerate() {
+            let alpha = (toast.left(now).as_secs_f32() / FADE.as_secs_f32()).min(1.0);
+            let line = Rect::from_w_h(area.w(), LINE_HEIGHT)
+                .mid_bottom_of(area)
+                .shift_y(LINE_HEIGHT * row as f32);
+            // Roughly as wide as the text at this size
+            let width = toast.message.chars().count() as f32 * 8.0 + 2.0 * MARGIN;
+            let (backdrop, text) = if toast.error {
+                (rgba(0.5, 0.05, 0.05, 0.75), rgba(1.0, 1.0, 1.0, 1.0))
+            } else {
+                (hud.panel, hud.text)
+            };
+            draw.rect()
+                .xy(line.xy())
+                .w_h(width.min(area.w()), LINE_HEIGHT - 2.0)
+                .color(Rgba {
+                    alpha: backdrop.alpha * alpha,
+                    ..backdrop
+                });
+            draw.text(&toast.message)
+                .xy(line.xy())
+                .wh(line.wh())
+                .font_size(14)
+                .color(Rgba {
+                    alpha: text.alpha * alpha,
+                    ..text
+                });
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Must match the workgroup size of `diffuse` in trail_shader.wgsl
+const CELL_WORKGROUP: u32 = 16;
+
+/// A grid over the domain that particles leave a trail in, like heat or pheromone, which
+/// spreads and fades and pulls them up its slope.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrailConfig {
+    // Cells along each side of the grid
+    pub size: u32,
+    // Left in a particle's cell by each particle each frame
+    pub deposit: f32,
+    // Fraction of each cell swapped for the average of its neighbours each frame
+    pub diffusion: f32,
+    // Fraction of the trail lost each frame
+    pub decay: f32,
+    // Velocity change per frame for each unit of trail per domain unit uphill, negative to
+    // avoid the trail instead
+    pub follow: f32,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        TrailConfig {
+            size: 256,
+            deposit: 1.0,
+            diffusion: 0.5,
+            decay: 0.05,
+            follow: 0.000001,
+        }
+    }
+}
+
+wgsl_struct! {
+    // Must match `TrailParams` in trail_shader.wgsl
+    struct TrailParams {
+        particle_count: u32,
+        size: u32,
+        deposit: f32,
+        diffusion: f32,
+        decay: f32,
+        follow: f32,
+    }
+}
+
+/// Three passes each frame: particles sense the trail and steer, then deposit into it, then
+/// the grid diffuses and decays.
+///
+/// Deposits are summed with atomics in fixed point, since many particles share a cell, and
+/// folded into the trail by the diffusion pass, which writes a new grid that's copied back
+/// over the old one. Other stages can read the trail through `trail_buffer`.
+pub struct Trail {
+    pub config: TrailConfig,
+    sense: wgpu::ComputePipeline,
+    deposit: wgpu::ComputePipeline,
+    diffuse: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<TrailParams>,
+    trail: wgpu::Buffer,
+    deposits: wgpu::Buffer,
+    diffused: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Trail {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, config: TrailConfig) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "trail_shader",
+            include_str!("./shaders/trail_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_ro(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .build(device, "Trail");
+        let params_buffer = UniformBuffer::new(device, resources, "Trail Params Buffer");
+        let cells = config.size.max(1) as wgpu::BufferAddress;
+        let mut grid = |label: &str, usage: BufferUsages| {
+            resources.buffer(
+                device,
+                label,
+                cells * cells * std::mem::size_of::<f32>() as wgpu::BufferAddress,
+                BufferUsages::STORAGE | usage,
+            )
+        };
+        let trail = grid(
+            "Trail Buffer",
+            BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        );
+        let deposits = grid("Trail Deposit Buffer", BufferUsages::empty());
+        let diffused = grid("Trail Diffused Buffer", BufferUsages::COPY_SRC);
+        let bind_groups = bind(
+            device,
+            resources,
+            &bindings,
+            &params_buffer,
+            [&trail, &deposits, &diffused],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(device, &format!("Trail Pipeline ({entry_point})"), || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Trail Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+        };
+
+        Trail {
+            config,
+            sense: pipeline("sense"),
+            deposit: pipeline("deposit"),
+            diffuse: pipeline("diffuse"),
+            bindings,
+            params_buffer,
+            trail,
+            deposits,
+            diffused,
+            bind_groups,
+        }
+    }
+
+    /// The trail's `size` by `size` f32s, row by row from the bottom of the domain.
+    pub fn trail_buffer(&self) -> &wgpu::Buffer {
+        &self.trail
+    }
+}
+
+impl Stage for Trail {
+    fn kind(&self) -> StageKind {
+        StageKind::Trail
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let params = TrailParams {
+            particle_count: frame.particle_count,
+            size: self.config.size.max(1),
+            deposit: self.config.deposit,
+            diffusion: self.config.diffusion.clamp(0.0, 1.0),
+            decay: self.config.decay.clamp(0.0, 1.0),
+            follow: self.config.follow,
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        let cell_workgroups = params.size.div_ceil(CELL_WORKGROUP);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Trail Pass"),
+            });
+            compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+            compute_pass.set_pipeline(&self.sense);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.deposit);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.diffuse);
+            compute_pass.dispatch_workgroups(cell_workgroups, cell_workgroups, 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.diffused, 0, &self.trail, 0, self.trail.size());
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            [&self.trail, &self.deposits, &self.diffused],
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<TrailParams>,
+    [trail, deposits, diffused]: [&wgpu::Buffer; 3],
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                trail.as_entire_binding(),
+                deposits.as_entire_binding(),
+                diffused.as_entire_binding(),
+            ],
+        )
+    })
+}
@@ -0,0 +1,86 @@
+use nannou::wgpu::{self, ShaderStages};
+
+use crate::bindings::Bindings;
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::trail::Trail;
+
+/// Draws a `Trail` over the background through the camera, brighter where it's stronger,
+/// like a physarum trail map.
+pub struct TrailView {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TrailView {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &GpuResources,
+        trail: &Trail,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "trail_view_shader",
+            include_str!("./shaders/trail_view_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::FRAGMENT)
+            .storage_ro(0)
+            .uniform(1)
+            .build(device, "Trail View");
+        let bind_group = bindings.bind_group(
+            device,
+            &[
+                trail.trail_buffer().as_entire_binding(),
+                resources.camera.as_entire_binding(),
+            ],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail View Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Trail View Pipeline", || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Trail View Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        });
+
+        TrailView {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    /// Shade the trail over the whole frame.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
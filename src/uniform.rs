@@ -0,0 +1,41 @@
+use nannou::wgpu::{self, BufferUsages};
+use std::marker::PhantomData;
+
+use crate::resources::GpuResources;
+use crate::wgsl::WgslType;
+
+/// A uniform buffer holding one `T`, declared with `wgsl_struct!`, written in the layout
+/// WGSL reads it in whatever the Rust layout, so params structs need no hand padding.
+///
+/// Only scalars and vectors are supported as fields, so the stricter rules for arrays and
+/// nested structs in uniform buffers never come up.
+pub struct UniformBuffer<T> {
+    buffer: wgpu::Buffer,
+    _value: PhantomData<T>,
+}
+
+impl<T: WgslType> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, resources: &mut GpuResources, label: &str) -> Self {
+        // Rounded up so the shader can keep padding at the end of its struct
+        let size = T::SIZE.next_multiple_of(16) as wgpu::BufferAddress;
+        UniformBuffer {
+            buffer: resources.buffer(
+                device,
+                label,
+                size,
+                BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            ),
+            _value: PhantomData,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        let mut bytes = Vec::with_capacity(T::SIZE);
+        value.write(&mut bytes);
+        queue.write_buffer(&self.buffer, 0, &bytes);
+    }
+
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
@@ -0,0 +1,64 @@
+use nannou::wgpu;
+use std::fs;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+use crate::diagnostics;
+
+// What each user shader is appended to, documenting what it has to define
+const INTERFACE: &str = include_str!("./shaders/user_fragment.wgsl");
+
+/// A fragment shader from the --user-shaders folder, drawn with in place of the built-in one
+/// to change how the particles look without touching the Rust code. See
+/// shaders/user_fragment.wgsl for what it's given.
+pub struct UserShader {
+    // Its file name
+    pub name: String,
+    pub module: wgpu::ShaderModule,
+}
+
+/// Compile the `.wgsl` files in `dir`, in order of name. Those that don't compile are left out
+/// with a message, and a missing folder has none.
+pub fn load(device: &wgpu::Device, dir: &Path) -> Vec<UserShader> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wgsl")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let shaders = paths
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let source = match fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(err) => {
+                    error!("Failed to read user shader {}: {}", path.display(), err);
+                    return None;
+                }
+            };
+            let source = format!("{INTERFACE}\n{source}");
+            match diagnostics::try_shader(device, &format!("user_{name}"), &source) {
+                Ok(module) => Some(UserShader { name, module }),
+                Err(err) => {
+                    warn!("Leaving out user shader {}: {}", path.display(), err);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    if !shaders.is_empty() {
+        info!(
+            "Loaded {} user shaders from {}, U cycles through them",
+            shaders.len(),
+            dir.display()
+        );
+    }
+    shaders
+}
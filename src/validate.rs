@@ -0,0 +1,127 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+use crate::cli::ValidateArgs;
+use crate::recording::Recording;
+use crate::replay::{self, Replay};
+use crate::scene::Scene;
+use crate::settings::Settings;
+
+/// Check each file loads the way the app would load it, and list the keys in it that nothing
+/// reads, usually misspelt, since loading skips those silently. Fails if any file does.
+pub fn run(args: &ValidateArgs) -> Result<(), String> {
+    let mut failed = 0;
+    for path in &args.files {
+        match check(path) {
+            Ok((kind, unknown)) if unknown.is_empty() => {
+                println!("{}: ok, {}", path.display(), kind);
+            }
+            Ok((kind, unknown)) => {
+                failed += 1;
+                println!("{}: {} with keys nothing reads:", path.display(), kind);
+                for key in unknown {
+                    println!("    {key}");
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{}: {}", path.display(), err);
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} files have problems",
+            failed,
+            args.files.len()
+        )),
+    }
+}
+
+// What the file holds, and the keys in it that aren't read, as dotted paths
+fn check(path: &Path) -> Result<(&'static str, Vec<String>), String> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    match extension {
+        Some("toml") => {
+            let value: Value =
+                nannou::io::load_from_toml(path).map_err(|err| format!("unreadable: {err}"))?;
+            // Scenes keep their settings in a table of their own
+            if value.get("settings").is_some_and(Value::is_object) {
+                let scene = Scene::load(path)?;
+                Ok(("scene", unknown_keys(&value, &scene)))
+            } else {
+                let settings: Settings = nannou::io::load_from_toml(path)
+                    .map_err(|err| format!("invalid settings: {err}"))?;
+                Ok(("settings", unknown_keys(&value, &settings)))
+            }
+        }
+        Some("json") => {
+            let value: Value =
+                nannou::io::load_from_json(path).map_err(|err| format!("unreadable: {err}"))?;
+            let recording = Recording::load(path)?;
+            Ok(("recording", unknown_keys(&value, &recording)))
+        }
+        Some("replay") => {
+            let value = replay::read(path)?;
+            let replay = Replay::from_value(value.clone())?;
+            Ok(("replay", unknown_keys(&value, &replay)))
+        }
+        _ => Err("unknown kind of file, expected .toml, .json or .replay".to_owned()),
+    }
+}
+
+// Keys in `given` that don't survive loading it as `loaded` and saving it again
+fn unknown_keys(given: &Value, loaded: &impl Serialize) -> Vec<String> {
+    let saved = serde_json::to_value(loaded).unwrap_or_default();
+    let mut unknown = Vec::new();
+    compare(given, &saved, "", &mut unknown);
+    unknown
+}
+
+fn compare(given: &Value, saved: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (given, saved) {
+        (Value::Object(given), Value::Object(saved)) => {
+            for (key, value) in given {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{path}.{key}"),
+                };
+                match saved.get(key) {
+                    Some(saved) => compare(value, saved, &path, unknown),
+                    // Empty lists are left out on saving, but were still read
+                    None if value.as_array().is_some_and(Vec::is_empty) => {}
+                    None => unknown.push(path),
+                }
+            }
+        }
+        (Value::Array(given), Value::Array(saved)) if given.len() == saved.len() => {
+            for (index, (given, saved)) in given.iter().zip(saved).enumerate() {
+                compare(given, saved, &format!("{path}[{index}]"), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finds_keys_nothing_reads() {
+        let given = json!({
+            "particles": 1000,
+            "partciles": 2000,
+            "attractors": [],
+            "camera": { "zoom": 2.0, "zom": 3.0 },
+        });
+        let settings: Settings = serde_json::from_value(given.clone()).unwrap();
+        assert_eq!(
+            unknown_keys(&given, &settings),
+            vec!["camera.zom".to_owned(), "partciles".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use clap::ValueEnum;
+use nannou::image::{self, imageops::FilterType, DynamicImage, GenericImageView};
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Two frames are on the GPU at once, and storage buffers are only guaranteed up to 128 MiB
+pub const MAX_FIELD_CELLS: usize = 1 << 22;
+// Images are shrunk to fit on each side, which also smooths away the gradients of noise
+pub const MAX_IMAGE_SIZE: u32 = 256;
+// Loaded as images rather than numbers
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "bmp", "gif", "tif", "tiff"];
+
+/// Which way a field loaded from an image carries the particles, from its brightness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageFollow {
+    /// Along the edges, around the bright and dark shapes, tracing their outlines
+    #[default]
+    Contours,
+    /// Up the gradient, gathering in the bright parts
+    Light,
+    /// Down the gradient, gathering in the dark parts
+    Dark,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VectorFieldConfig {
+    // A .npy array shaped (rows, columns, 2) or (frames, rows, columns, 2), a .csv with a row
+    // of u,v pairs per line, or a directory of either taken as frames in name order
+    pub path: PathBuf,
+    // Domain units per frame for each unit of the field's vectors
+    pub scale: f32,
+    // Fraction of the difference from the field's velocity closed each frame, like drag in a
+    // flowing fluid. 1 carries the particles along with the field exactly
+    pub coupling: f32,
+    // Simulated seconds between frames of a sequence, blended from one to the next and
+    // looping at the end
+    pub frame_seconds: f32,
+    // How the brightness of an image steers, for fields loaded from images
+    pub follow: ImageFollow,
+}
+
+impl Default for VectorFieldConfig {
+    fn default() -> Self {
+        VectorFieldConfig {
+            path: PathBuf::new(),
+            scale: 0.001,
+            coupling: 0.1,
+            frame_seconds: 1.0,
+            follow: ImageFollow::default(),
+        }
+    }
+}
+
+impl VectorFieldConfig {
+    pub fn new(path: PathBuf) -> Self {
+        VectorFieldConfig {
+            path,
+            ..Default::default()
+        }
+    }
+}
+
+/// Grids of 2D vectors stretched over the whole -1..1 domain, row by row from the bottom, so
+/// the first index is y like numpy's (rows, columns) and plotting with `quiver`. More than
+/// one frame makes a sequence played over time.
+///
+/// Images become the gradient of their brightness, turned by an `ImageFollow` and scaled so
+/// the sharpest edge is 1, for trajectories that trace a photo's structure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorField {
+    pub width: u32,
+    pub height: u32,
+    // The same size each
+    pub frames: Vec<Vec<[f32; 2]>>,
+}
+
+impl VectorField {
+    /// Load a `.npy` file as a numpy array, an image as the gradient of its brightness
+    /// followed as `follow` says, a directory as a frame per such file in it, sorted by name,
+    /// and anything else as CSV.
+    pub fn load(path: &Path, follow: ImageFollow) -> Result<Self, String> {
+        let error =
+            |err: String| format!("Failed to load vector field {}: {}", path.display(), err);
+        let field = if path.is_dir() {
+            let mut files = fs::read_dir(path)
+                .map_err(|err| error(err.to_string()))?
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|file| {
+                    file.extension()
+                        .is_some_and(|extension| extension == "npy" || extension == "csv")
+                        || is_image(file)
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            let mut fields = files
+                .iter()
+                .map(|file| VectorField::load_file(file, follow));
+            let mut field = fields
+                .next()
+                .ok_or_else(|| error("no .npy, .csv or image files".to_string()))??;
+            for next in fields {
+                let next = next?;
+                if [next.width, next.height] != [field.width, field.height] {
+                    return Err(error("the frames differ in size".to_string()));
+                }
+                field.frames.extend(next.frames);
+            }
+            field
+        } else {
+            VectorField::load_file(path, follow)?
+        };
+        let cells = (field.width * field.height) as usize;
+        if cells > MAX_FIELD_CELLS {
+            return Err(error(format!(
+                "more than {MAX_FIELD_CELLS} vectors a frame"
+            )));
+        }
+        Ok(field)
+    }
+
+    fn load_file(path: &Path, follow: ImageFollow) -> Result<Self, String> {
+        let error =
+            |err: String| format!("Failed to load vector field {}: {}", path.display(), err);
+        let field = if is_image(path) {
+            let image = image::open(path).map_err(|err| error(err.to_string()))?;
+            VectorField::from_image(image, follow)
+        } else if path.extension().is_some_and(|extension| extension == "npy") {
+            let bytes = fs::read(path).map_err(|err| error(err.to_string()))?;
+            VectorField::parse_npy(&bytes)
+        } else {
+            let text = fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+            VectorField::parse_csv(&text)
+        };
+        field.map_err(error)
+    }
+
+    // Little-endian float32 or float64 in C order, as `numpy.save` writes by default
+    fn parse_npy(bytes: &[u8]) -> Result<Self, String> {
+        let rest = bytes.strip_prefix(b"\x93NUMPY").ok_or("not a .npy file")?;
+        let (header_len, header_start) = match rest {
+            [1, _, a, b, ..] => (u16::from_le_bytes([*a, *b]) as usize, 4),
+            [2 | 3, _, a, b, c, d, ..] => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, 6),
+            _ => return Err("unsupported .npy version".to_string()),
+        };
+        let header = rest
+            .get(header_start..header_start + header_len)
+            .ok_or("truncated header")?;
+        let header = std::str::from_utf8(header).map_err(|err| err.to_string())?;
+        let data = &rest[header_start + header_len..];
+
+        let value = |key: &str| {
+            let start = header
+                .find(&format!("'{key}':"))
+                .ok_or(format!("no {key}"))?;
+            Ok::<_, String>(header[start + key.len() + 3..].trim_start())
+        };
+        if value("fortran_order")?.starts_with("True") {
+            return Err("Fortran ordered arrays aren't supported".to_string());
+        }
+        let descr = value("descr")?;
+        let size = if descr.starts_with("'<f4'") {
+            4
+        } else if descr.starts_with("'<f8'") {
+            8
+        } else {
+            return Err(format!(
+                "expected float32 or float64, got {}",
+                descr.split(',').next().unwrap_or_default()
+            ));
+        };
+        let shape = value("shape")?;
+        let shape = shape
+            .strip_prefix('(')
+            .and_then(|shape| shape.split(')').next())
+            .ok_or("invalid shape")?
+            .split(',')
+            .map(str::trim)
+            .filter(|dimension| !dimension.is_empty())
+            .map(str::parse::<usize>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?;
+        let (count, height, width) = match shape[..] {
+            [height, width, 2] => (1, height, width),
+            [count, height, width, 2] => (count, height, width),
+            _ => {
+                return Err(format!(
+                    "expected a shape of (rows, columns, 2) or (frames, rows, columns, 2), got \
+                     {shape:?}"
+                ))
+            }
+        };
+
+        // The shape comes from the file, and may be anything
+        let too_large = || format!("shape {shape:?} is too large");
+        let cells = width.checked_mul(height).ok_or_else(too_large)?;
+        let components = count
+            .checked_mul(cells)
+            .and_then(|values| values.checked_mul(2))
+            .ok_or_else(too_large)?;
+        if data.len() < components.checked_mul(size).ok_or_else(too_large)? {
+            return Err("truncated data".to_string());
+        }
+        let component = |index: usize| {
+            let bytes = &data[index * size..(index + 1) * size];
+            if size == 4 {
+                f32::from_le_bytes(bytes.try_into().unwrap())
+            } else {
+                f64::from_le_bytes(bytes.try_into().unwrap()) as f32
+            }
+        };
+        let frames = (0..count)
+            .map(|frame| {
+                (frame * cells..(frame + 1) * cells)
+                    .map(|index| [component(2 * index), component(2 * index + 1)])
+                    .collect()
+            })
+            .collect();
+        VectorField::new(width, height, frames)
+    }
+
+    // Blank lines and ones starting with # are skipped
+    fn parse_csv(text: &str) -> Result<Self, String> {
+        let mut width = None;
+        let mut vectors = Vec::new();
+        let rows = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+        for (number, line) in rows {
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("line {}: {}", number + 1, err))?;
+            if values.len() % 2 != 0 {
+                return Err(format!("line {}: expected u,v pairs", number + 1));
+            }
+            if *width.get_or_insert(values.len() / 2) != values.len() / 2 {
+                return Err(format!("line {}: rows differ in length", number + 1));
+            }
+            vectors.extend(values.chunks_exact(2).map(|pair| [pair[0], pair[1]]));
+        }
+        let width = width.unwrap_or(0);
+        let height = vectors.len().checked_div(width).unwrap_or(0);
+        VectorField::new(width, height, vec![vectors])
+    }
+
+    // Central differences of the luma, clamped at the edges
+    fn from_image(image: DynamicImage, follow: ImageFollow) -> Result<Self, String> {
+        let (width, height) = image.dimensions();
+        let image = if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+            let (width, height) = (width.min(MAX_IMAGE_SIZE), height.min(MAX_IMAGE_SIZE));
+            image.resize_exact(width, height, FilterType::Triangle)
+        } else {
+            image
+        };
+        let luma = image.to_luma8();
+        let (width, height) = (luma.width() as i64, luma.height() as i64);
+        let brightness = |x: i64, y: i64| {
+            let pixel = luma.get_pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+            pixel.0[0] as f32 / 255.0
+        };
+        // Images go from the top, fields from the bottom
+        let gradients = (0..height)
+            .rev()
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                [
+                    (brightness(x + 1, y) - brightness(x - 1, y)) * 0.5,
+                    (brightness(x, y - 1) - brightness(x, y + 1)) * 0.5,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let sharpest = gradients
+            .iter()
+            .map(|[x, y]| x.hypot(*y))
+            .fold(0.0, f32::max);
+        let scale = if sharpest > 0.0 { 1.0 / sharpest } else { 0.0 };
+        let vectors = gradients
+            .into_iter()
+            .map(|[x, y]| match follow {
+                ImageFollow::Contours => [-y * scale, x * scale],
+                ImageFollow::Light => [x * scale, y * scale],
+                ImageFollow::Dark => [-x * scale, -y * scale],
+            })
+            .collect();
+        VectorField::new(width as usize, height as usize, vec![vectors])
+    }
+
+    fn new(width: usize, height: usize, frames: Vec<Vec<[f32; 2]>>) -> Result<Self, String> {
+        if width == 0 || height == 0 || frames.is_empty() {
+            return Err("the field is empty".to_string());
+        }
+        Ok(VectorField {
+            width: width as u32,
+            height: height as u32,
+            frames,
+        })
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+wgsl_struct! {
+    // Must match `FieldParams` in vector_field_shader.wgsl
+    struct FieldParams {
+        particle_count: u32,
+        width: u32,
+        height: u32,
+        scale: f32,
+        coupling: f32,
+        blend: f32,
+    }
+}
+
+/// Eases every particle's velocity towards a `VectorField`'s, sampled bilinearly where it
+/// is, after the integrating stage.
+///
+/// Sequences are streamed to the GPU a frame at a time: the buffer holds the frame before
+/// the current time and the one after, blended between in the shader, and moves on to the
+/// next pair as time passes.
+pub struct VectorFieldStage {
+    pub config: VectorFieldConfig,
+    field: VectorField,
+    // Frame whose pair is in `field_buffer`
+    uploaded: Option<usize>,
+    pipeline: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<FieldParams>,
+    field_buffer: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl VectorFieldStage {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: VectorFieldConfig,
+        field: VectorField,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "vector_field_shader",
+            include_str!("./shaders/vector_field_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .storage_ro(2)
+            .build(device, "Vector Field");
+        let params_buffer = UniformBuffer::new(device, resources, "Vector Field Params Buffer");
+        let field_buffer = resources.buffer(
+            device,
+            "Vector Field Buffer",
+            2 * field.frames[0].len() as wgpu::BufferAddress
+                * std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        );
+        let bind_groups = bind(device, resources, &bindings, &params_buffer, &field_buffer);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Vector Field Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = diagnostics::checked(device, "Vector Field Pipeline", || {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Vector Field Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "advect",
+            })
+        });
+
+        VectorFieldStage {
+            config,
+            field,
+            uploaded: None,
+            pipeline,
+            bindings,
+            params_buffer,
+            field_buffer,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for VectorFieldStage {
+    fn kind(&self) -> StageKind {
+        StageKind::VectorField
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        let frames = &self.field.frames;
+        let position = frame.time / self.config.frame_seconds.max(f32::EPSILON);
+        let current = position.floor() as usize % frames.len();
+        if self.uploaded != Some(current) {
+            let next = (current + 1) % frames.len();
+            let bytes = [&frames[current][..], &frames[next][..]].concat();
+            frame
+                .queue
+                .write_buffer(&self.field_buffer, 0, bytemuck::cast_slice(&bytes));
+            self.uploaded = Some(current);
+        }
+        let params = FieldParams {
+            particle_count: frame.particle_count,
+            width: self.field.width,
+            height: self.field.height,
+            scale: self.config.scale,
+            coupling: self.config.coupling.clamp(0.0, 1.0),
+            blend: if frames.len() > 1 {
+                position.fract()
+            } else {
+                0.0
+            },
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Vector Field Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        compute_pass.dispatch_workgroups(frame.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.field_buffer,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<FieldParams>,
+    field_buffer: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                params_buffer.binding(),
+                field_buffer.as_entire_binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::image::GrayImage;
+
+    #[test]
+    fn follows_an_images_brightness() {
+        // Brighter to the right, so the gradient points along +x everywhere
+        let ramp = DynamicImage::ImageLuma8(GrayImage::from_fn(8, 4, |x, _| [x as u8 * 30].into()));
+        let near = |[x, y]: [f32; 2], [ex, ey]: [f32; 2]| (x - ex).abs() + (y - ey).abs() < 1e-5;
+        let field = |follow| {
+            VectorField::from_image(ramp.clone(), follow)
+                .unwrap()
+                .frames[0][9]
+        };
+        assert!(near(field(ImageFollow::Light), [1.0, 0.0]));
+        assert!(near(field(ImageFollow::Dark), [-1.0, 0.0]));
+        assert!(near(field(ImageFollow::Contours), [0.0, 1.0]));
+
+        // Brighter at the top of the image, which is the top of the domain
+        let ramp =
+            DynamicImage::ImageLuma8(GrayImage::from_fn(4, 8, |_, y| [255 - y as u8 * 30].into()));
+        let field = VectorField::from_image(ramp, ImageFollow::Light).unwrap();
+        assert!(near(field.frames[0][9], [0.0, 1.0]));
+    }
+
+    #[test]
+    fn refuses_npy_shapes_too_large_to_hold() {
+        let npy = |shape: &str, data: &[f32]| {
+            let header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend((header.len() as u16).to_le_bytes());
+            bytes.extend(header.bytes());
+            bytes.extend(data.iter().flat_map(|value| value.to_le_bytes()));
+            bytes
+        };
+        let field = VectorField::parse_npy(&npy("(1, 2, 2)", &[1.0, 0.0, 0.0, 1.0])).unwrap();
+        assert_eq!(field.frames[0], vec![[1.0, 0.0], [0.0, 1.0]]);
+
+        let huge = format!("({}, {}, 2)", usize::MAX / 2, 3);
+        let err = VectorField::parse_npy(&npy(&huge, &[])).err().unwrap();
+        assert!(err.contains("too large"), "{err}");
+    }
+}
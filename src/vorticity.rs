@@ -0,0 +1,327 @@
+use nannou::wgpu::{self, BufferUsages, ComputePassDescriptor, ShaderStages};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{BindingLayout, Bindings};
+use crate::diagnostics;
+use crate::resources::GpuResources;
+use crate::stages::{FrameContext, Stage, StageKind};
+use crate::uniform::UniformBuffer;
+use crate::wgsl::wgsl_struct;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+// x and y velocity summed in fixed point, then the count, see `cells` in
+// vorticity_shader.wgsl
+const CELL_SIZE: wgpu::BufferAddress = 12;
+// Curl of each cell's average velocity, see `curls` in vorticity_shader.wgsl
+const CURL_SIZE: wgpu::BufferAddress = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VorticityConfig {
+    // How hard swirls are spun up, as a fraction of the velocity difference across a cell
+    // added each frame
+    pub strength: f32,
+    // Cells along each side of the grid velocities are averaged over
+    pub grid: u32,
+}
+
+impl Default for VorticityConfig {
+    fn default() -> Self {
+        VorticityConfig {
+            strength: 0.2,
+            grid: 32,
+        }
+    }
+}
+
+/// Parse `strength[,grid]`, e.g. `0.2,32`.
+pub fn parse_vorticity(s: &str) -> Result<VorticityConfig, String> {
+    let (strength, grid) = match s.split_once(',') {
+        Some((strength, grid)) => (strength, Some(grid)),
+        None => (s, None),
+    };
+    let strength = strength
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid vorticity strength `{strength}`"))?;
+    let grid = match grid {
+        Some(grid) => grid
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid vorticity grid `{grid}`"))?,
+        None => VorticityConfig::default().grid,
+    };
+    if strength < 0.0 || !(3..=256).contains(&grid) {
+        return Err(format!(
+            "expected a strength of 0 or more and a grid of 3 to 256 cells, got `{s}`"
+        ));
+    }
+    Ok(VorticityConfig { strength, grid })
+}
+
+wgsl_struct! {
+    // Must match `VorticityParams` in vorticity_shader.wgsl
+    struct VorticityParams {
+        strength: f32,
+        grid: u32,
+    }
+}
+
+/// Vorticity confinement, from smoke simulation: the particles' velocities are binned into
+/// a coarse grid, the curl of the averaged flow is taken per cell, and each particle is
+/// pushed round the swirl it's in, towards where the curl is strongest. Small eddies that
+/// alignment would otherwise smooth out get spun up instead, so flocks and fluids churn.
+///
+/// The grid is cleared and filled again every frame, so nothing is kept per particle.
+pub struct Vorticity {
+    pub config: VorticityConfig,
+    clear: wgpu::ComputePipeline,
+    deposit: wgpu::ComputePipeline,
+    curl: wgpu::ComputePipeline,
+    confine: wgpu::ComputePipeline,
+    bindings: BindingLayout,
+    params_buffer: UniformBuffer<VorticityParams>,
+    cells: wgpu::Buffer,
+    curls: wgpu::Buffer,
+    // Indexed like `GpuResources::particle_buffers`
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Vorticity {
+    pub fn new(
+        device: &wgpu::Device,
+        resources: &mut GpuResources,
+        config: VorticityConfig,
+    ) -> Self {
+        let shader = diagnostics::shader(
+            device,
+            "vorticity_shader",
+            include_str!("./shaders/vorticity_shader.wgsl"),
+        );
+        let bindings = Bindings::new(ShaderStages::COMPUTE)
+            .storage_rw(0)
+            .uniform(1)
+            .uniform(2)
+            .storage_rw(3)
+            .storage_rw(4)
+            .build(device, "Vorticity");
+        let params_buffer = UniformBuffer::new(device, resources, "Vorticity Params Buffer");
+        let cell_count = cell_count(config.grid);
+        let cells = resources.buffer(
+            device,
+            "Vorticity Cells Buffer",
+            cell_count * CELL_SIZE,
+            BufferUsages::STORAGE,
+        );
+        let curls = resources.buffer(
+            device,
+            "Vorticity Curls Buffer",
+            cell_count * CURL_SIZE,
+            BufferUsages::STORAGE,
+        );
+        let bind_groups = bind(device, resources, &bindings, &params_buffer, &cells, &curls);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Vorticity Pipeline Layout"),
+            bind_group_layouts: &[bindings.layout()],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            diagnostics::checked(
+                device,
+                &format!("Vorticity Pipeline ({entry_point})"),
+                || {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Vorticity Pipeline"),
+                        layout: Some(&pipeline_layout),
+                        module: &shader,
+                        entry_point,
+                    })
+                },
+            )
+        };
+
+        Vorticity {
+            config,
+            clear: pipeline("clear"),
+            deposit: pipeline("deposit"),
+            curl: pipeline("curl"),
+            confine: pipeline("confine"),
+            bindings,
+            params_buffer,
+            cells,
+            curls,
+            bind_groups,
+        }
+    }
+}
+
+impl Stage for Vorticity {
+    fn kind(&self) -> StageKind {
+        StageKind::Vorticity
+    }
+
+    fn encode(
+        &mut self,
+        frame: &FrameContext,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut GpuResources,
+    ) {
+        // The buffers are sized for the grid it started with
+        let params = VorticityParams {
+            strength: self.config.strength.max(0.0),
+            grid: self.config.grid.clamp(3, 256),
+        };
+        self.params_buffer.write(frame.queue, &params);
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Vorticity Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_groups[resources.current()], &[]);
+        let cell_workgroups = (params.grid * params.grid).div_ceil(WORKGROUP_SIZE);
+        let particle_workgroups = frame.particle_count.div_ceil(WORKGROUP_SIZE);
+        for (pipeline, workgroups) in [
+            (&self.clear, cell_workgroups),
+            (&self.deposit, particle_workgroups),
+            (&self.curl, cell_workgroups),
+            (&self.confine, particle_workgroups),
+        ] {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, resources: &mut GpuResources) {
+        self.bind_groups = bind(
+            device,
+            resources,
+            &self.bindings,
+            &self.params_buffer,
+            &self.cells,
+            &self.curls,
+        );
+    }
+}
+
+fn cell_count(grid: u32) -> wgpu::BufferAddress {
+    let grid = grid.clamp(3, 256) as wgpu::BufferAddress;
+    grid * grid
+}
+
+fn bind(
+    device: &wgpu::Device,
+    resources: &GpuResources,
+    bindings: &BindingLayout,
+    params_buffer: &UniformBuffer<VorticityParams>,
+    cells: &wgpu::Buffer,
+    curls: &wgpu::Buffer,
+) -> [wgpu::BindGroup; 2] {
+    resources.particle_buffers().each_ref().map(|particles| {
+        bindings.bind_group(
+            device,
+            &[
+                particles.as_entire_binding(),
+                resources.params.as_entire_binding(),
+                params_buffer.binding(),
+                cells.as_entire_binding(),
+                curls.as_entire_binding(),
+            ],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim_variant::SimVariant;
+    use crate::{gpu, headless, Particle, SimParams};
+
+    // Particles on a 32 by 32 lattice over the domain, swirling round the centre at `spin`
+    // times their distance from it, dying away outside a third of the domain
+    fn swirl(spin: f32) -> Vec<Particle> {
+        (0..32 * 32)
+            .map(|i| {
+                let [x, y] = [i % 32, i / 32].map(|n| (n as f32 + 0.5) / 16.0 - 1.0);
+                let falloff = (-(x * x + y * y) / 0.1).exp();
+                Particle {
+                    position: [x, y],
+                    velocity: [-y * spin * falloff, x * spin * falloff],
+                }
+            })
+            .collect()
+    }
+
+    // How much faster each particle goes round the centre anticlockwise after a frame
+    fn spun_up(device: &wgpu::Device, queue: &wgpu::Queue, particles: &[Particle]) -> Vec<f32> {
+        let mut resources = GpuResources::new(device, particles);
+        let config = VorticityConfig {
+            strength: 0.5,
+            grid: 16,
+        };
+        let mut vorticity = Vorticity::new(device, &mut resources, config);
+        let count = particles.len() as u32;
+        let params = SimParams {
+            particle_count: count,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        queue.write_buffer(&resources.params, 0, bytemuck::bytes_of(&params));
+        let frame = FrameContext {
+            device,
+            queue,
+            variant: SimVariant::default(),
+            particle_count: count,
+            substeps: 1,
+            time: 0.0,
+            frame: 0,
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Vorticity Test Encoder"),
+        });
+        vorticity.encode(&frame, &mut encoder, &mut resources);
+        queue.submit(Some(encoder.finish()));
+        let after =
+            gpu::read_buffer::<Particle>(device, queue, resources.particles(), count as usize);
+        particles
+            .iter()
+            .zip(after)
+            .map(|(before, after)| {
+                let [x, y] = before.position;
+                let tangent = [-y, x].map(|n| n / x.hypot(y));
+                (0..2)
+                    .map(|axis| (after.velocity[axis] - before.velocity[axis]) * tangent[axis])
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spins_swirls_up_the_way_they_turn() {
+        let Some((device, queue)) = headless::device() else {
+            eprintln!("No GPU adapter available, skipping");
+            return;
+        };
+        // Round the swirl, leaving out the cells at its centre and the still flow outside it.
+        // The grid is coarse, so some on the diagonals go the other way, but only a little
+        let check = |spin: f32| {
+            let particles = swirl(spin);
+            let spun = spun_up(&device, &queue, &particles)
+                .into_iter()
+                .zip(&particles)
+                .filter(|(_, p)| (0.15..0.45).contains(&p.position[0].hypot(p.position[1])))
+                .map(|(spun, _)| spun * spin.signum())
+                .collect::<Vec<_>>();
+            let along = spun.iter().filter(|&&s| s > 0.0).count();
+            assert!(along * 4 >= spun.len() * 3, "{along} of {}", spun.len());
+            let against = spun.iter().filter(|&&s| s < 0.0).sum::<f32>();
+            let total = spun.iter().sum::<f32>();
+            assert!(total > 0.0 && -against < total / 10.0, "{against} {total}");
+        };
+        check(0.01);
+        check(-0.01);
+
+        // Without any swirl nothing changes
+        let still = swirl(0.0);
+        assert!(spun_up(&device, &queue, &still).iter().all(|&s| s == 0.0));
+    }
+}
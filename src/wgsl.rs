@@ -0,0 +1,205 @@
+//! WGSL shared between shaders. A line `#include "name.wgsl"` in a shader is replaced by
+//! that module when it's compiled through `diagnostics::shader`, once per shader however
+//! many times it's asked for, so definitions can live in one place.
+
+use crate::{Particle, SimParams};
+
+/// The source of the module `#include "name"` stands for.
+fn module(name: &str) -> Option<String> {
+    Some(match name {
+        // Particle and SimParams, the structs every particle shader needs, generated from
+        // lib.rs
+        "common.wgsl" => [Particle::wgsl(), SimParams::wgsl()].join("\n"),
+        // The bits of each particle's flags, generated from flags.rs
+        "flags.wgsl" => crate::flags::wgsl(),
+        // Hash-based random numbers seeded per particle and frame
+        "random.wgsl" => include_str!("./shaders/random.wgsl").to_owned(),
+        // Value and simplex noise, fBm and curl
+        "noise.wgsl" => include_str!("./shaders/noise.wgsl").to_owned(),
+        // Each particle's mass, hashed from its index
+        "mass.wgsl" => include_str!("./shaders/mass.wgsl").to_owned(),
+        // Which particles lead, hashed from their index
+        "leaders.wgsl" => include_str!("./shaders/leaders.wgsl").to_owned(),
+        // Distinct colours, round the hue wheel or safe for colour blindness, and ramps for
+        // amounts
+        "palette.wgsl" => include_str!("./shaders/palette.wgsl").to_owned(),
+        _ => return None,
+    })
+}
+
+const INCLUDE: &str = "#include";
+
+/// Resolve the includes in `source`, including those in the modules it includes.
+pub fn compose(source: &str) -> Result<String, String> {
+    let mut included = Vec::new();
+    let mut composed = String::with_capacity(source.len());
+    resolve(source, &mut included, &mut composed)?;
+    Ok(composed)
+}
+
+fn resolve(source: &str, included: &mut Vec<String>, composed: &mut String) -> Result<(), String> {
+    for (number, line) in source.lines().enumerate() {
+        let Some(rest) = line.trim().strip_prefix(INCLUDE) else {
+            composed.push_str(line);
+            composed.push('\n');
+            continue;
+        };
+        let name = rest
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| format!("line {}: expected {INCLUDE} \"name.wgsl\"", number + 1))?;
+        if included.iter().any(|included| included == name) {
+            continue;
+        }
+        let module =
+            module(name).ok_or_else(|| format!("line {}: no module {name}", number + 1))?;
+        included.push(name.to_owned());
+        resolve(&module, included, composed).map_err(|err| format!("{name} {err}"))?;
+    }
+    Ok(())
+}
+
+/// A Rust type with a WGSL counterpart, and that counterpart's size and alignment in
+/// storage and uniform buffers.
+pub trait WgslType {
+    const NAME: &'static str;
+    const SIZE: usize;
+    const ALIGN: usize;
+
+    /// Append the `SIZE` bytes WGSL would read this as, padding included.
+    fn write(&self, bytes: &mut Vec<u8>);
+}
+
+macro_rules! wgsl_type {
+    ($ty:ty, $name:literal, $size:literal, $align:literal) => {
+        impl WgslType for $ty {
+            const NAME: &'static str = $name;
+            const SIZE: usize = $size;
+            const ALIGN: usize = $align;
+
+            fn write(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(bytemuck::bytes_of(self));
+            }
+        }
+    };
+}
+
+wgsl_type!(u32, "u32", 4, 4);
+wgsl_type!(i32, "i32", 4, 4);
+wgsl_type!(f32, "f32", 4, 4);
+wgsl_type!([u32; 2], "vec2<u32>", 8, 8);
+wgsl_type!([f32; 2], "vec2<f32>", 8, 8);
+// Aligned like a vec4, so a scalar fits in after it
+wgsl_type!([f32; 3], "vec3<f32>", 12, 16);
+wgsl_type!([f32; 4], "vec4<f32>", 16, 16);
+
+/// A struct whose WGSL definition is generated from it, see `wgsl_struct!`.
+pub trait WgslStruct: WgslType {
+    fn wgsl() -> String;
+}
+
+/// Declare a struct along with its WGSL layout and definition. Values are written with
+/// the padding WGSL expects between fields, see `UniformBuffer`, so there are no `_pad`
+/// fields to keep in step by hand.
+///
+/// Starting with `#[repr(C)]` also fails to compile if the Rust layout differs from the
+/// WGSL one, for structs that are uploaded as they are, e.g. a `[f32; 2]` after a single
+/// `f32`, which WGSL would align to 8 bytes.
+#[macro_export]
+macro_rules! wgsl_struct {
+    (
+        #[repr(C)]
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $($(#[$field_attr:meta])* pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        #[repr(C)]
+        $(#[$attr])*
+        pub struct $name {
+            $($(#[$field_attr])* pub $field: $ty,)*
+        }
+
+        $crate::wgsl::wgsl_struct!(@impl $name { $($field: $ty,)* });
+
+        const _: () = {
+            let mut offset: usize = 0;
+            $(
+                offset = offset.next_multiple_of(<$ty as $crate::wgsl::WgslType>::ALIGN);
+                assert!(
+                    std::mem::offset_of!($name, $field) == offset,
+                    concat!(stringify!($name), ".", stringify!($field), " isn't where WGSL puts it"),
+                );
+                offset += <$ty as $crate::wgsl::WgslType>::SIZE;
+            )*
+            assert!(
+                std::mem::size_of::<$name>() == <$name as $crate::wgsl::WgslType>::SIZE,
+                concat!(stringify!($name), " isn't the size WGSL makes it"),
+            );
+        };
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $ty,)*
+        }
+
+        $crate::wgsl::wgsl_struct!(@impl $name { $($field: $ty,)* });
+    };
+    (@impl $name:ident { $($field:ident: $ty:ty,)* }) => {
+        impl $crate::wgsl::WgslType for $name {
+            const NAME: &'static str = stringify!($name);
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(
+                    if <$ty as $crate::wgsl::WgslType>::ALIGN > align {
+                        align = <$ty as $crate::wgsl::WgslType>::ALIGN;
+                    }
+                )*
+                align
+            };
+            const SIZE: usize = {
+                let mut offset: usize = 0;
+                $(
+                    offset = offset.next_multiple_of(<$ty as $crate::wgsl::WgslType>::ALIGN)
+                        + <$ty as $crate::wgsl::WgslType>::SIZE;
+                )*
+                offset.next_multiple_of(<Self as $crate::wgsl::WgslType>::ALIGN)
+            };
+
+            fn write(&self, bytes: &mut Vec<u8>) {
+                let start = bytes.len();
+                $(
+                    let offset = (bytes.len() - start)
+                        .next_multiple_of(<$ty as $crate::wgsl::WgslType>::ALIGN);
+                    bytes.resize(start + offset, 0);
+                    $crate::wgsl::WgslType::write(&self.$field, bytes);
+                )*
+                bytes.resize(start + <Self as $crate::wgsl::WgslType>::SIZE, 0);
+            }
+        }
+
+        impl $crate::wgsl::WgslStruct for $name {
+            fn wgsl() -> String {
+                let mut wgsl = format!("struct {} {{\n", stringify!($name));
+                $(
+                    wgsl.push_str(&format!(
+                        "    {}: {},\n",
+                        stringify!($field),
+                        <$ty as $crate::wgsl::WgslType>::NAME,
+                    ));
+                )*
+                wgsl.push_str("};\n");
+                wgsl
+            }
+        }
+    };
+}
+
+pub use wgsl_struct;